@@ -1,13 +1,16 @@
 import { useState } from 'react';
 import Graph from './components/Graph';
 import NodeDetails from './components/NodeDetails';
+import ForwardingControl from './components/ForwardingControl';
+import SessionBanner from './components/SessionBanner';
 import { useWebSocket } from './hooks/useWebSocket';
-import type { McpLog } from './types';
 
 function App() {
   const [selectedNode, setSelectedNode] = useState<string | null>(null);
 
-  const events: McpLog[] = useWebSocket('ws://localhost:3000/ws');
+  const { events, stats } = useWebSocket('ws://localhost:3000/ws');
+
+  const handshakes = events.filter((e) => e.method === 'sentinel/session_info').length;
 
   const selectedEvent =
     selectedNode != null
@@ -18,6 +21,29 @@ function App() {
     <div style={{ display: 'flex', height: '100vh', width: '100vw' }}>
       {/* Main graph area */}
       <div style={{ flex: 1, position: 'relative' }}>
+        {stats && stats.tap_dropped_total > 0 && (
+          <div
+            style={{
+              position: 'absolute',
+              top: 12,
+              left: 12,
+              zIndex: 10,
+              padding: '6px 10px',
+              borderRadius: 6,
+              background: '#3d1d00',
+              border: '1px solid #d29922',
+              color: '#f0c674',
+              fontSize: 12,
+            }}
+            title={Object.entries(stats.tap_dropped)
+              .map(([direction, n]) => `${direction}: ${n}`)
+              .join('\n')}
+          >
+            {stats.tap_dropped_total} message(s) not audited: audit pipeline overflowed
+          </div>
+        )}
+        <ForwardingControl status={stats?.forwarding ?? null} />
+        <SessionBanner handshakes={handshakes} />
         <Graph
           events={events}
           onNodeClick={setSelectedNode}
@@ -0,0 +1,76 @@
+import { useEffect, useState } from 'react';
+import type { ForwardingStatus } from '../types';
+
+const CONTROL_URL = 'http://localhost:3000/api/control';
+
+// Same token as the WebSocket, taken from the page URL (?token=...)
+function controlUrl(action: 'pause' | 'resume'): string {
+  const token = new URLSearchParams(window.location.search).get('token');
+  const url = `${CONTROL_URL}/${action}`;
+  return token ? `${url}?token=${encodeURIComponent(token)}` : url;
+}
+
+export default function ForwardingControl({ status }: { status: ForwardingStatus | null }) {
+  // The reply to our own request shows at once; stats frames take over after.
+  const [current, setCurrent] = useState<ForwardingStatus | null>(status);
+  const [error, setError] = useState<string | null>(null);
+  const [busy, setBusy] = useState(false);
+
+  useEffect(() => {
+    if (status) setCurrent(status);
+  }, [status]);
+
+  const paused = current?.paused ?? false;
+
+  const toggle = async () => {
+    setBusy(true);
+    setError(null);
+    try {
+      const res = await fetch(controlUrl(paused ? 'resume' : 'pause'), { method: 'POST' });
+      if (!res.ok) throw new Error(`HTTP ${res.status}`);
+      setCurrent((await res.json()) as ForwardingStatus);
+    } catch (e) {
+      setError(e instanceof Error ? e.message : String(e));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  return (
+    <div
+      style={{
+        position: 'absolute',
+        top: 12,
+        right: 12,
+        zIndex: 10,
+        display: 'flex',
+        alignItems: 'center',
+        gap: 8,
+        fontSize: 12,
+        color: '#c9d1d9',
+      }}
+    >
+      {paused && current && (
+        <span style={{ color: '#f0c674' }}>
+          Paused: {current.held_messages} message(s) held ({current.held_bytes} / {current.max_held_bytes} bytes)
+        </span>
+      )}
+      {error && <span style={{ color: '#f85149' }}>{error}</span>}
+      <button
+        onClick={toggle}
+        disabled={busy}
+        style={{
+          padding: '6px 10px',
+          borderRadius: 6,
+          border: `1px solid ${paused ? '#3fb950' : '#d29922'}`,
+          background: '#161b22',
+          color: paused ? '#3fb950' : '#d29922',
+          cursor: busy ? 'wait' : 'pointer',
+        }}
+        title="Hold client -> server messages (server replies still flow)"
+      >
+        {paused ? 'Resume forwarding' : 'Pause forwarding'}
+      </button>
+    </div>
+  );
+}
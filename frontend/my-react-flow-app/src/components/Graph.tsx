@@ -14,8 +14,8 @@ import {
   BackgroundVariant,
 } from '@xyflow/react';
 import '@xyflow/react/dist/style.css';
-import type { McpLog } from '../types';
-import { StreamDirection } from '../types';
+import type { McpLog, RequestId } from '../types';
+import { isRequestLog, isResponseLog } from '../types';
 
 interface GraphProps {
   events: McpLog[];
@@ -27,7 +27,7 @@ type CustomNodeData = {
   label: string;
   method?: string;
   status: 'pending' | 'success' | 'error';
-  requestId?: number;
+  requestId?: RequestId;
   selectedId?: string | null;
   calls?: number;
   outbound?: number;
@@ -57,7 +57,7 @@ type ToolStats = {
   outbound: number;
   inbound: number;
   errors: number;
-  lastRequestId?: number;
+  lastRequestId?: RequestId;
   totalLatency: number;
   maxLatency: number;
 };
@@ -455,12 +455,12 @@ export default function Graph({ events, onNodeClick, selectedNode }: GraphProps)
   const [didFit, setDidFit] = useState(false);
 
   const requestMethodById = useMemo(() => {
-    const map = new Map<number, string>();
+    const map = new Map<RequestId, string>();
 
     for (const e of events) {
       if (
-        e.direction === StreamDirection.Outbound &&
-        typeof e.request_id === 'number' &&
+        isRequestLog(e) &&
+        e.request_id != null &&
         typeof e.method === 'string' &&
         e.method.length > 0
       ) {
@@ -509,9 +509,9 @@ export default function Graph({ events, onNodeClick, selectedNode }: GraphProps)
       stats.total += 1;
       stats.lastRequestId = e.request_id;
 
-      if (e.direction === StreamDirection.Outbound) {
+      if (isRequestLog(e)) {
         stats.outbound += 1;
-      } else if (e.direction === StreamDirection.Inbound) {
+      } else if (isResponseLog(e)) {
         stats.inbound += 1;
 
         if (typeof e.latency_ms === 'number') {
@@ -519,8 +519,9 @@ export default function Graph({ events, onNodeClick, selectedNode }: GraphProps)
           stats.maxLatency = Math.max(stats.maxLatency, e.latency_ms);
         }
 
-        // IMPORTANT: Sentinel puts JSON-RPC errors at payload.error (not payload.result.error)
-        if ((e.payload as any)?.error) {
+        // IMPORTANT: Sentinel puts JSON-RPC errors at payload.error (not payload.result.error);
+        // a failed tool call answers with a result instead, flagged tool_is_error
+        if ((e.payload as any)?.error || e.tool_is_error) {
           stats.errors += 1;
         }
       }
@@ -682,13 +683,13 @@ export default function Graph({ events, onNodeClick, selectedNode }: GraphProps)
 
     for (const method of toolMethods) {
       const lastInboundForMethod = [...events].reverse().find((e) => {
-        if (e.direction !== StreamDirection.Inbound) return false;
+        if (!isResponseLog(e)) return false;
         if (e.request_id == null) return false;
         const m = typeof e.method === 'string' && e.method.length > 0 ? e.method : requestMethodById.get(e.request_id);
         return m === method;
       });
 
-      const hasError = Boolean((lastInboundForMethod?.payload as any)?.error);
+      const hasError = Boolean((lastInboundForMethod?.payload as any)?.error || lastInboundForMethod?.tool_is_error);
       hasErrorByMethod.set(method, hasError);
     }
 
@@ -20,7 +20,7 @@ const COLORS = {
 };
 
 export default function NodeDetails({ event, onClose }: NodeDetailsProps) {
-  const isError = (event.payload as any)?.error;
+  const isError = event.is_error ?? Boolean((event.payload as any)?.error);
   const accentColor = isError ? COLORS.neonRed : COLORS.neonGreen;
 
   return (
@@ -92,6 +92,26 @@ export default function NodeDetails({ event, onClose }: NodeDetailsProps) {
         </div>
       </div>
 
+      {/* Server */}
+      {event.server_name && (
+        <div style={{ marginBottom: '16px' }}>
+          <div
+            style={{
+              fontSize: '11px',
+              color: COLORS.textSecondary,
+              marginBottom: '4px',
+              textTransform: 'uppercase',
+              letterSpacing: '0.5px',
+            }}
+          >
+            Server
+          </div>
+          <div style={{ fontSize: '14px', fontFamily: 'monospace', color: COLORS.textPrimary }}>
+            {event.server_name}
+          </div>
+        </div>
+      )}
+
       {/* Method */}
       {event.method && (
         <div style={{ marginBottom: '16px' }}>
@@ -145,6 +165,93 @@ export default function NodeDetails({ event, onClose }: NodeDetailsProps) {
         </div>
       )}
 
+      {/* Tool / resource / prompt */}
+      {(event.tool_name ?? event.resource_uri ?? event.prompt_name) !== undefined && (
+        <div style={{ marginBottom: '16px' }}>
+          <div
+            style={{
+              fontSize: '11px',
+              color: COLORS.textSecondary,
+              marginBottom: '4px',
+              textTransform: 'uppercase',
+              letterSpacing: '0.5px',
+            }}
+          >
+            {event.tool_name !== undefined ? 'Tool' : event.resource_uri !== undefined ? 'Resource' : 'Prompt'}
+          </div>
+          <div
+            style={{
+              fontSize: '14px',
+              color: COLORS.textPrimary,
+              wordBreak: 'break-all',
+            }}
+            title={event.params_digest && `params blake3 ${event.params_digest}`}
+          >
+            {event.tool_name ?? event.resource_uri ?? event.prompt_name}
+          </div>
+        </div>
+      )}
+
+      {/* Error */}
+      {event.is_error && (
+        <div style={{ marginBottom: '16px' }}>
+          <div
+            style={{
+              fontSize: '11px',
+              color: COLORS.textSecondary,
+              marginBottom: '4px',
+              textTransform: 'uppercase',
+              letterSpacing: '0.5px',
+            }}
+          >
+            Error
+          </div>
+          <div
+            style={{
+              fontSize: '14px',
+              color: COLORS.neonRed,
+              wordBreak: 'break-word',
+            }}
+          >
+            {event.error_code !== undefined && `${event.error_code}: `}
+            {event.error_message}
+          </div>
+        </div>
+      )}
+
+      {/* Redactions */}
+      {(event.redactions || event.redaction_truncated_scan) && (
+        <div style={{ marginBottom: '16px' }}>
+          <div
+            style={{
+              fontSize: '11px',
+              color: COLORS.textSecondary,
+              marginBottom: '4px',
+              textTransform: 'uppercase',
+              letterSpacing: '0.5px',
+            }}
+          >
+            Redacted
+          </div>
+          <div
+            style={{
+              fontSize: '14px',
+              color: COLORS.neonPurple,
+              wordBreak: 'break-word',
+            }}
+          >
+            {Object.entries(event.redactions ?? {})
+              .map(([rule, count]) => `${rule} ×${count}`)
+              .join(', ')}
+            {event.redaction_truncated_scan && (
+              <div style={{ fontSize: '12px', color: COLORS.textSecondary }}>
+                Scanned only up to max_scan_bytes
+              </div>
+            )}
+          </div>
+        </div>
+      )}
+
       {/* Direction */}
       <div style={{ marginBottom: '16px' }}>
         <div
@@ -167,7 +274,7 @@ export default function NodeDetails({ event, onClose }: NodeDetailsProps) {
                 : COLORS.neonGreen,
           }}
         >
-          {event.direction}
+          {event.role ? `${event.direction} · ${event.role}` : event.direction}
         </div>
       </div>
 
@@ -0,0 +1,62 @@
+import { useEffect, useState } from 'react';
+import type { SessionSummary } from '../types';
+
+const SESSION_URL = 'http://localhost:3000/api/session';
+
+// Same token as the WebSocket, taken from the page URL (?token=...)
+function sessionUrl(): string {
+  const token = new URLSearchParams(window.location.search).get('token');
+  return token ? `${SESSION_URL}?token=${encodeURIComponent(token)}` : SESSION_URL;
+}
+
+function label(name?: string, version?: string): string {
+  if (!name) return 'unknown';
+  return version ? `${name} ${version}` : name;
+}
+
+// Which client/server pair is being audited. Refetched whenever a
+// sentinel/session_info event arrives (`handshakes` counts them).
+export default function SessionBanner({ handshakes }: { handshakes: number }) {
+  const [sessions, setSessions] = useState<SessionSummary[]>([]);
+
+  useEffect(() => {
+    fetch(sessionUrl())
+      .then((res) => (res.ok ? res.json() : Promise.reject(new Error(`HTTP ${res.status}`))))
+      .then((body: { sessions: SessionSummary[] }) => setSessions(body.sessions))
+      .catch(() => setSessions([]));
+  }, [handshakes]);
+
+  const known = sessions.filter((s) => s.info);
+  if (known.length === 0) return null;
+
+  return (
+    <div
+      style={{
+        position: 'absolute',
+        bottom: 12,
+        left: 12,
+        zIndex: 10,
+        display: 'flex',
+        flexDirection: 'column',
+        gap: 4,
+        fontSize: 12,
+        color: '#c9d1d9',
+      }}
+    >
+      {known.map(({ session_id, server, info }) => (
+        <div
+          key={session_id}
+          title={[
+            `client capabilities: ${info!.client_capabilities.join(', ') || 'none'}`,
+            `server capabilities: ${info!.server_capabilities.join(', ') || 'none'}`,
+          ].join('\n')}
+        >
+          {server && <strong>{server}: </strong>}
+          {label(info!.client_name, info!.client_version)} ⇄ {label(info!.server_name, info!.server_version)}
+          {info!.protocol_version && ` · MCP ${info!.protocol_version}`}
+          {info!.phase !== 'operating' && ` · ${info!.phase}`}
+        </div>
+      ))}
+    </div>
+  );
+}
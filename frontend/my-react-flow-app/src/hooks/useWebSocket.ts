@@ -1,8 +1,9 @@
 import { useState, useEffect, useRef } from 'react';
-import type { McpLog } from '../types';
+import type { McpLog, PipelineStats } from '../types';
 
-export function useWebSocket(url: string): McpLog[] {
+export function useWebSocket(url: string): { events: McpLog[]; stats: PipelineStats | null } {
   const [events, setEvents] = useState<McpLog[]>([]);
+  const [stats, setStats] = useState<PipelineStats | null>(null);
   const wsRef = useRef<WebSocket | null>(null);
   const reconnectTimeoutRef = useRef<number | null>(null);
 
@@ -28,8 +29,14 @@ export function useWebSocket(url: string): McpLog[] {
 
         ws.onmessage = (event) => {
           try {
-            const data: McpLog = JSON.parse(event.data);
-            setEvents((prev) => [...prev, data].slice(-1000)); // Keep last 1000 events
+            const data = JSON.parse(event.data);
+            // Control frames (watermark, stats, gap) carry an `op` or `type` instead of being events
+            if (data.op === 'stats') {
+              setStats(data as PipelineStats);
+              return;
+            }
+            if (data.op || data.type) return;
+            setEvents((prev) => [...prev, data as McpLog].slice(-1000)); // Keep last 1000 events
           } catch (e) {
             console.error('Failed to parse WebSocket message:', e);
           }
@@ -70,6 +77,6 @@ export function useWebSocket(url: string): McpLog[] {
     };
   }, [url]);
 
-  return events;
+  return { events, stats };
 }
 
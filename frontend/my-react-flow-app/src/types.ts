@@ -1,16 +1,32 @@
 export enum StreamDirection {
   Inbound = 'Inbound',
   Outbound = 'Outbound',
+  Sentinel = 'Sentinel',
+  Stderr = 'Stderr',
 }
 
+// Who initiated the exchange; `direction` only records the transport pipe.
+export enum MessageRole {
+  ClientRequest = 'ClientRequest',
+  ServerResponse = 'ServerResponse',
+  ServerRequest = 'ServerRequest',
+  ClientResponse = 'ClientResponse',
+  ClientNotification = 'ClientNotification',
+  ServerNotification = 'ServerNotification',
+}
+
+// JSON-RPC ids may be numbers or strings (`7` and `"7"` are different ids)
+export type RequestId = number | string;
+
 export interface McpLog {
   event_id: number;
   run_id: string;
 
   timestamp: number;
   direction: StreamDirection;
+  role?: MessageRole;
   method?: string;
-  request_id?: number;
+  request_id?: RequestId;
   latency_ms?: number;
   payload: any;
 
@@ -18,5 +34,76 @@ export interface McpLog {
   trace_id: string;
   span_id: string;
   parent_span_id?: string;
+
+  // Set when one Sentinel wraps several servers (`run --servers`)
+  server_name?: string;
+  // Position within a JSON-RPC batch line
+  batch?: { index: number; size: number };
+  // JSON-RPC error responses (older records: look at payload.error)
+  is_error?: boolean;
+  error_code?: number;
+  error_message?: string;
+  // What a tools/call, resources/read or prompts/get request acts on
+  tool_name?: string;
+  resource_uri?: string;
+  prompt_name?: string;
+  params_digest?: string;
+  // Responses to tools/call: result.isError and the size of result.content
+  tool_is_error?: boolean;
+  result_bytes?: number;
+  // Connection epoch within the run; responses say whether they found their request
+  session_epoch?: number;
+  pairing?: 'matched' | 'unmatched' | 'late_response';
+  // A notifications/cancelled that closed its request; latency_ms is time to cancel
+  cancelled?: boolean;
+  // Redaction rules that rewrote the event and how often each did
+  redactions?: Record<string, number>;
+  // Redaction scanned the payload only up to max_scan_bytes
+  redaction_truncated_scan?: boolean;
+}
+
+// Periodic `{"op": "stats"}` frame on the WebSocket stream.
+export interface PipelineStats {
+  tap_overflow: 'block' | 'drop';
+  tap_dropped: Record<StreamDirection, number>;
+  tap_dropped_total: number;
+  forwarding?: ForwardingStatus;
+}
+
+// Client -> server forwarding, paused from the dashboard (`/api/control`)
+export interface ForwardingStatus {
+  paused: boolean;
+  paused_ms: number | null;
+  held_messages: number;
+  held_bytes: number;
+  max_held_bytes: number;
 }
 
+// Older (schema v1) logs carry no role; fall back to the transport direction.
+export function isRequestLog(e: McpLog): boolean {
+  if (e.role) return e.role === MessageRole.ClientRequest || e.role === MessageRole.ServerRequest;
+  return e.direction === StreamDirection.Outbound;
+}
+
+export function isResponseLog(e: McpLog): boolean {
+  if (e.role) return e.role === MessageRole.ServerResponse || e.role === MessageRole.ClientResponse;
+  return e.direction === StreamDirection.Inbound;
+}
+
+// `initialize` handshake of an audited session, from GET /api/session.
+export interface SessionInfo {
+  phase: 'initializing' | 'initialized' | 'operating';
+  protocol_version?: string;
+  client_name?: string;
+  client_version?: string;
+  server_name?: string;
+  server_version?: string;
+  client_capabilities: string[];
+  server_capabilities: string[];
+}
+
+export interface SessionSummary {
+  session_id: string;
+  server?: string;
+  info: SessionInfo | null;
+}
@@ -0,0 +1,164 @@
+//! `sentinel export-attestation` / `verify-attestation`: a detached summary of a
+//! verified log (its key, runs, counts, final chain hash and every signed
+//! checkpoint) that an auditor can check with nothing but the bundle.
+
+use crate::audit::{self, AuditRecord, EventMark, Provenance, VerifyOptions};
+use crate::audit_crypto;
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// `format` of a bundle written by `sentinel export-attestation`.
+pub const FORMAT_ATTESTATION: &str = "sentinel-attestation-v1";
+
+/// Only `checkpoints` are signed. The other fields are what the exporter found
+/// when it verified the log; `verify-attestation` checks that they agree with
+/// the signed checkpoints.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Attestation {
+    pub format: String,
+    pub created_ts_ms: u64,
+    /// Version of the Sentinel that exported the bundle
+    pub exported_by: String,
+    /// File name of the log, and blake3 (hex) of its bytes as stored
+    pub log_file: String,
+    pub log_blake3: String,
+    pub pubkey_b64: String,
+    pub key_id: String,
+    pub run_ids: Vec<String>,
+    pub provenance: Vec<Provenance>,
+    pub records: u64,
+    pub events: u64,
+    pub first_event: Option<EventMark>,
+    pub last_event: Option<EventMark>,
+    pub final_entry_hash_b64: String,
+    /// Every checkpoint of the log as written, signatures included
+    pub checkpoints: Vec<AuditRecord>,
+}
+
+/// Verify the (possibly encrypted) log at `log_path` and describe it.
+pub fn export(log_path: &str, pubkey: &str, decrypt_privkey_path: Option<&str>) -> Result<Attestation, String> {
+    let vk = audit::load_verify_key_b64(pubkey)?;
+    let log = audit_crypto::open_plaintext(log_path, decrypt_privkey_path)?;
+    let options = VerifyOptions {
+        check_offsets: !log.offsets_checked,
+        collect_checkpoints: true,
+        ..VerifyOptions::default()
+    };
+    let summary = audit::verify_log(log.reader, pubkey, &options).map_err(|e| format!("log does not verify: {}", e))?;
+
+    let mut hasher = blake3::Hasher::new();
+    let mut f = std::fs::File::open(log_path).map_err(|e| format!("open {}: {}", log_path, e))?;
+    std::io::copy(&mut f, &mut hasher).map_err(|e| format!("read {}: {}", log_path, e))?;
+
+    Ok(Attestation {
+        format: FORMAT_ATTESTATION.to_string(),
+        created_ts_ms: crate::events::current_timestamp_ms(),
+        exported_by: env!("CARGO_PKG_VERSION").to_string(),
+        log_file: Path::new(log_path).file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        log_blake3: hasher.finalize().to_hex().to_string(),
+        pubkey_b64: B64.encode(vk.to_bytes()),
+        key_id: audit::key_id_from_pubkey(&vk),
+        run_ids: summary.run_ids,
+        provenance: summary.provenance,
+        records: summary.records_seen,
+        events: summary.events_verified,
+        first_event: summary.first_event,
+        last_event: summary.last_event,
+        final_entry_hash_b64: summary.final_entry_hash_b64,
+        checkpoints: summary.checkpoints,
+    })
+}
+
+/// What [`verify`] established.
+pub struct AttestationCheck {
+    pub checkpoints: usize,
+    /// Events up to this id are covered by a valid checkpoint signature
+    pub signed_through: u64,
+    pub warnings: Vec<String>,
+}
+
+/// Check every checkpoint signature in `att` against its public key (which must
+/// be `pinned_pubkey`, when given) and that the unsigned fields agree with them.
+pub fn verify(att: &Attestation, pinned_pubkey: Option<&str>) -> Result<AttestationCheck, String> {
+    if att.format != FORMAT_ATTESTATION {
+        return Err(format!("unrecognized attestation format {:?}", att.format));
+    }
+    let pk = B64
+        .decode(att.pubkey_b64.trim())
+        .ok()
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .ok_or("bad pubkey_b64")?;
+    let vk = VerifyingKey::from_bytes(&pk).map_err(|e| format!("invalid pubkey_b64: {}", e))?;
+    if audit::key_id_from_pubkey(&vk) != att.key_id {
+        return Err(format!("key_id {} does not match pubkey_b64", att.key_id));
+    }
+    if let Some(pinned) = pinned_pubkey {
+        let pinned = audit::load_verify_key_b64(pinned)?;
+        if pinned != vk {
+            return Err(format!(
+                "bundle is for key_id {}, not the given public key (key_id {})",
+                att.key_id,
+                audit::key_id_from_pubkey(&pinned)
+            ));
+        }
+    }
+
+    let Some(last_checkpoint) = att.checkpoints.last() else {
+        return Err("attestation holds no checkpoints".to_string());
+    };
+    let mut previous_event_id = 0u64;
+    for (i, cp) in att.checkpoints.iter().enumerate() {
+        let (last_event_id, _) = audit::verify_checkpoint_record(cp, vk).map_err(|e| format!("checkpoint {}: {}", i + 1, e))?;
+        if let AuditRecord::Checkpoint { run_id, .. } = cp {
+            if !att.run_ids.contains(run_id) {
+                return Err(format!("checkpoint {}: run_id {} is not among run_ids", i + 1, run_id));
+            }
+        }
+        if last_event_id < previous_event_id {
+            return Err(format!(
+                "checkpoint {}: last_event_id {} goes back from {}",
+                i + 1,
+                last_event_id,
+                previous_event_id
+            ));
+        }
+        previous_event_id = last_event_id;
+    }
+
+    // Event ids are contiguous in a log that verifies
+    if let (Some(first), Some(last)) = (att.first_event, att.last_event) {
+        if last.event_id < first.event_id || last.event_id - first.event_id + 1 != att.events {
+            return Err(format!(
+                "{} events do not span event_id {} to {}",
+                att.events, first.event_id, last.event_id
+            ));
+        }
+    }
+
+    let (signed_through, signed_tip) = audit::verify_checkpoint_record(last_checkpoint, vk)?;
+    let last_event_id = att.last_event.map_or(0, |e| e.event_id);
+    let mut warnings = Vec::new();
+    if signed_through > last_event_id {
+        return Err(format!(
+            "a checkpoint covers event_id {}, after the last event {}",
+            signed_through, last_event_id
+        ));
+    }
+    if signed_through < last_event_id {
+        warnings.push(format!(
+            "events {} to {} are not covered by a checkpoint; final_entry_hash_b64 is not signed",
+            signed_through + 1,
+            last_event_id
+        ));
+    } else if B64.encode(signed_tip) != att.final_entry_hash_b64 {
+        return Err("final_entry_hash_b64 does not match the last checkpoint".to_string());
+    }
+
+    Ok(AttestationCheck {
+        checkpoints: att.checkpoints.len(),
+        signed_through,
+        warnings,
+    })
+}
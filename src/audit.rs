@@ -1,20 +1,89 @@
-use crate::events::McpLog;
+use crate::events::{McpLog, StreamDirection};
+use crate::merkle::MerkleAccumulator;
+use crate::protocol::RequestId;
+use crate::redaction::{RedactionPolicy, RedactionReport};
+use crate::timestamp::TrustRoots;
 use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
-use blake3;
 use ed25519_dalek::{Signature, SigningKey, Signer, VerifyingKey};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub const HASH_ALG: &str = "blake3";
 pub const SIG_ALG: &str = "ed25519";
 
+/// Event record schema version written by this build.
+///
+/// - v1: hashes the fixed `SignableMcpLog` subset
+/// - v2: hashes every `McpLog` field (adds `role`); absent optional fields are omitted
+/// - v3: v2 plus the record's byte offset in the file (`write_offset`)
+/// - v4: inbound events carry `detections`, the advisory rules their payload
+///   matched; hashed like v3
+/// - v5: the child's stderr is logged with the `Stderr` direction; hashed like v3
+/// - v6: events of a multiplexed run carry `server_name`; hashed like v3
+/// - v7: `request_id` may be a string; hashed like v3, the version tells older
+///   readers (which expect a number) that they cannot verify the record
+/// - v8: a JSON-RPC batch line is logged as one record per element, in array
+///   order, with consecutive event ids, the line's `observed_ts_ms` and a
+///   `batch` position; hashed like v3
+/// - v9: error responses carry `is_error`, `error_code` and `error_message`;
+///   hashed like v3 (the fields are omitted when unset, so v1-v8 records still
+///   hash as written)
+/// - v10: `tools/call`, `resources/read` and `prompts/get` requests carry
+///   `tool_name` / `resource_uri` / `prompt_name` and `params_digest`, taken
+///   over the params as redacted; hashed like v3
+/// - v11: responses to `tools/call` carry `tool_is_error` and `result_bytes`;
+///   hashed like v3
+/// - v12: events carry `session_epoch`, responses `pairing`; hashed like v3
+/// - v13: cancellations carry `cancelled`, late responses `pairing: late_response`;
+///   hashed like v3
+/// - v14: `RunBoundary` records where an appended run takes over the chain
+/// - v15: `SegmentHeader` records open each rotated segment after the first
+/// - v16: events may carry `signature_b64` (`run --sign-every-event`); not
+///   hashed, so events hash like v3
+/// - v17: checkpoints carry the Merkle root and leaf count of the events since
+///   the previous one (checkpoint version 2)
+/// - v18: `Migration` records close a log rewritten by `sentinel migrate-log`
+/// - v19: `TimestampProof` records hold a checkpoint's RFC 3161 token
+/// - v20: `WitnessAck` records hold a remote witness's receipt for a checkpoint
+/// - v21: checkpoints carry the provenance of the run that signed them
+///   (checkpoint version 3)
+/// - v22: `GapMarker` records skip the event ids of messages the audit pipeline
+///   dropped; hashed as a link in the chain, see [`make_gap_marker`]
+/// - v23: events redaction rewrote carry `redactions`, the count per rule;
+///   hashed like v3
+/// - v24: events scanned only up to `max_scan_bytes` carry
+///   `redaction_truncated_scan`; hashed like v3
+pub const SCHEMA_VERSION: u32 = 24;
+
+/// `format` written in the FileHeader of a plaintext audit log.
+/// v3 files have a strict physical layout: one record per `\n`-terminated line,
+/// nothing in between.
+pub const FORMAT_AUDIT: &str = "sentinel-audit-v3";
+/// `format` written in the FileHeader of an encrypted audit log.
+pub const FORMAT_AUDIT_ENCRYPTED: &str = "sentinel-audit-encrypted-v3";
+/// Formats written before record offsets were pinned; verified with lenient layout.
+pub const LEGACY_FORMAT_AUDIT: &str = "sentinel-audit-v2";
+pub const LEGACY_FORMAT_AUDIT_ENCRYPTED: &str = "sentinel-audit-encrypted-v2";
+
 /// Wrapper record written to JSONL.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Nearly every record is an Event; boxing it would only add an allocation.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "record_type")]
 pub enum AuditRecord {
+    /// First line of every file Sentinel writes; identifies the format and producer.
+    /// Not part of the hash chain. Legacy files have no header.
+    FileHeader {
+        format: String,
+        crate_version: String,
+        created_ts_ms: u64,
+    },
     Event {
         log: McpLog,
         integrity: IntegrityFields,
@@ -29,19 +98,141 @@ pub enum AuditRecord {
         hash_alg: String,
         sig_alg: String,
         version: u32,
+        /// Merkle root over the entry hashes of the events (and gap markers) since
+        /// the previous checkpoint (version 2+, signed with the rest)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        merkle_root_b64: Option<String>,
+        /// Number of leaves under `merkle_root_b64`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        merkle_leaves: Option<u64>,
+        /// Version of the Sentinel that wrote the checkpoint (version 3+, signed
+        /// with the rest)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sentinel_version: Option<String>,
+        /// blake3 (hex) of the wrapped command line, see [`Provenance`]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command_digest: Option<String>,
+        /// Host the run was on
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hostname: Option<String>,
+    },
+    /// Where a run appended to an existing log (`run --append`) takes over the
+    /// chain: signs the previous run's chain tip for the new `run_id`. The only
+    /// place `run_id` may change. Not part of the hash chain.
+    RunBoundary {
+        previous_run_id: String,
+        run_id: String,
+        created_ts_ms: u64,
+        last_event_id: u64,
+        last_entry_hash_b64: String,
+        signature_b64: String,
+        key_id: String,
+        hash_alg: String,
+        sig_alg: String,
+        version: u32,
+    },
+    /// First record of every rotated segment after the first (`sentinel_audit.<n>.jsonl`):
+    /// signs the chain tip the previous segment closed with, and names that file.
+    /// Not part of the hash chain.
+    SegmentHeader {
+        run_id: String,
+        segment: u32,
+        previous_segment: String,
+        created_ts_ms: u64,
+        last_event_id: u64,
+        last_entry_hash_b64: String,
+        signature_b64: String,
+        key_id: String,
+        hash_alg: String,
+        sig_alg: String,
+        version: u32,
+    },
+    /// Written by `sentinel migrate-log` after the records of a log it rewrote to
+    /// the current schema: signs the rewritten chain tip together with the chain
+    /// tip and file digest of the original. Not part of the hash chain.
+    Migration {
+        run_id: String,
+        created_ts_ms: u64,
+        last_event_id: u64,
+        last_entry_hash_b64: String,
+        /// blake3 of the original file, as stored
+        source_file_blake3_b64: String,
+        /// Final entry hash of the original log
+        source_last_entry_hash_b64: String,
+        /// Event record versions found in the original
+        source_versions: Vec<u32>,
+        signature_b64: String,
+        key_id: String,
+        hash_alg: String,
+        sig_alg: String,
+        version: u32,
+    },
+    /// RFC 3161 timestamp token for the checkpoint ending at
+    /// `checkpoint_last_event_id`, from the TSA at `tsa_url` (`run --timestamp-url`).
+    /// Written whenever the token arrives, so other records may come between the
+    /// checkpoint and its proof. Not part of the hash chain.
+    TimestampProof {
+        run_id: String,
+        checkpoint_last_event_id: u64,
+        /// SHA-256 of the checkpoint's signing preimage: the digest the TSA signed
+        imprint_b64: String,
+        tsa_url: String,
+        /// DER TimeStampToken (CMS SignedData over a TSTInfo)
+        token_b64: String,
+        /// The token's genTime, ms since the epoch
+        gen_time_ms: u64,
+        version: u32,
+    },
+    /// Receipt from the remote witness (`run --witness-url`) for the checkpoint
+    /// ending at `checkpoint_last_event_id`. Written whenever the receipt arrives.
+    /// Informational: `verify --witness-url` asks the witness itself. Not part of
+    /// the hash chain.
+    WitnessAck {
+        run_id: String,
+        checkpoint_last_event_id: u64,
+        last_entry_hash_b64: String,
+        witness_url: String,
+        receipt_id: String,
+        acked_ts_ms: u64,
+        version: u32,
+    },
+    /// Messages the audit pipeline had no room for (`run --tap-overflow drop`).
+    /// Event ids `first_event_id..=last_event_id` are skipped, one per dropped
+    /// message, and the next event continues after them. A link in the hash chain
+    /// like an event, and a leaf of the next checkpoint's Merkle tree; `verify`
+    /// accepts a gap in event ids only where one of these accounts for it.
+    GapMarker {
+        run_id: String,
+        created_ts_ms: u64,
+        first_event_id: u64,
+        last_event_id: u64,
+        /// Tap sequence numbers the dropped messages had (see `run --raw-capture`)
+        first_seq: u64,
+        last_seq: u64,
+        /// Dropped messages per direction
+        dropped: BTreeMap<String, u64>,
+        integrity: IntegrityFields,
     },
 }
 
 /// Integrity metadata attached to each event record.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct IntegrityFields {
     pub prev_hash_b64: String,
     pub entry_hash_b64: String,
     pub hash_alg: String,
     pub version: u32,
+    /// Byte offset of this record's line in the file as written (v3+). For
+    /// encrypted logs this is the offset of the outer `Encrypted` record.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_offset: Option<u64>,
+    /// Signature over the entry hash, written by `run --sign-every-event`. Not
+    /// covered by the hash, so records hash the same with or without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_b64: Option<String>,
 }
 
-/// Deterministic subset of McpLog used for hashing.
+/// Deterministic subset of McpLog used for hashing v1 records.
 /// payload is recursively canonicalized to sort object keys.
 #[derive(Debug, Clone, Serialize)]
 struct SignableMcpLog<'a> {
@@ -51,7 +242,8 @@ struct SignableMcpLog<'a> {
     timestamp: u64,
     direction: crate::events::StreamDirection,
     method: &'a Option<String>,
-    request_id: &'a Option<u64>,
+    /// Always a number in v1 records, so it encodes as it always has
+    request_id: &'a Option<RequestId>,
     latency_ms: &'a Option<u64>,
     payload: Value,
     session_id: &'a str,
@@ -60,7 +252,7 @@ struct SignableMcpLog<'a> {
     parent_span_id: &'a Option<String>,
 }
 
-fn canonicalize_value(v: &Value) -> Value {
+pub fn canonicalize_value(v: &Value) -> Value {
     match v {
         Value::Object(map) => {
             let mut keys: Vec<_> = map.keys().cloned().collect();
@@ -78,7 +270,20 @@ fn canonicalize_value(v: &Value) -> Value {
     }
 }
 
-fn signable_bytes(log: &McpLog) -> Result<Vec<u8>, String> {
+fn signable_bytes(log: &McpLog, version: u32) -> Result<Vec<u8>, String> {
+    match version {
+        1 => signable_bytes_v1(log),
+        2..=SCHEMA_VERSION => {
+            let value = serde_json::to_value(log)
+                .map_err(|e| format!("failed to serialize signable log: {e}"))?;
+            serde_json::to_vec(&canonicalize_value(&value))
+                .map_err(|e| format!("failed to serialize signable log: {e}"))
+        }
+        v => Err(format!("unsupported record version {v}")),
+    }
+}
+
+fn signable_bytes_v1(log: &McpLog) -> Result<Vec<u8>, String> {
     let signable = SignableMcpLog {
         run_id: &log.run_id,
         event_id: log.event_id,
@@ -97,7 +302,70 @@ fn signable_bytes(log: &McpLog) -> Result<Vec<u8>, String> {
     serde_json::to_vec(&signable).map_err(|e| format!("failed to serialize signable log: {e}"))
 }
 
-fn decode_b64_32(s: &str) -> Result<[u8; 32], String> {
+/// Build the header record written as the first line of a new file.
+pub fn make_file_header(format: &str) -> AuditRecord {
+    AuditRecord::FileHeader {
+        format: format.to_string(),
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_ts_ms: crate::events::current_timestamp_ms(),
+    }
+}
+
+/// Inspect the first line of a Sentinel file.
+/// - `Ok(Some(format))` for a valid FileHeader
+/// - `Ok(None)` for a header-less legacy file
+/// - `Err` for a line that claims to be a header but is corrupt
+pub fn parse_file_header(line: &str) -> Result<Option<String>, String> {
+    let value: Value = match serde_json::from_str(normalize_line(line)) {
+        Ok(v) => v,
+        Err(_) => return Ok(None),
+    };
+    if value.get("record_type").and_then(|v| v.as_str()) != Some("FileHeader") {
+        return Ok(None);
+    }
+
+    match serde_json::from_value::<AuditRecord>(value) {
+        Ok(AuditRecord::FileHeader { format, .. }) if format.starts_with("sentinel-") => Ok(Some(format)),
+        Ok(AuditRecord::FileHeader { format, .. }) => Err(format!("unrecognized file format {format:?}")),
+        Ok(_) => Err("corrupt FileHeader record".to_string()),
+        Err(e) => Err(format!("corrupt FileHeader record: {e}")),
+    }
+}
+
+/// Check that a decrypted record was written at `offset` in the encrypted file.
+pub fn check_record_offset(record_json: &str, offset: u64) -> Result<(), String> {
+    if let Ok(AuditRecord::Event { integrity, .. }) = serde_json::from_str::<AuditRecord>(record_json) {
+        if let Some(recorded) = integrity.write_offset.filter(|r| *r != offset) {
+            return Err(format!(
+                "record written at byte {recorded} but found at byte {offset} (bytes inserted or removed)"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Layout rule for strict (v3) files: a record line is exactly one JSON object
+/// followed by a single `\n` - no BOM, CR, blank lines or surrounding whitespace.
+pub fn check_strict_line(raw: &str) -> Result<(), String> {
+    let Some(body) = raw.strip_suffix('\n') else {
+        return Err("record is not terminated by a newline".to_string());
+    };
+    if body.is_empty() {
+        return Err("unexpected blank line between records".to_string());
+    }
+    if !body.starts_with('{') || !body.ends_with('}') || body.contains('\r') {
+        return Err("unexpected bytes around record".to_string());
+    }
+    Ok(())
+}
+
+/// Strip a leading UTF-8 BOM and surrounding whitespace (including the `\r` of a
+/// CRLF line ending) from a JSONL line, as produced by Windows tooling.
+pub fn normalize_line(line: &str) -> &str {
+    line.trim_start_matches('\u{feff}').trim()
+}
+
+pub fn decode_b64_32(s: &str) -> Result<[u8; 32], String> {
     let bytes = B64
         .decode(s)
         .map_err(|e| format!("base64 decode failed: {e}"))?;
@@ -123,6 +391,247 @@ fn checkpoint_preimage(run_id: &str, last_event_id: u64, last_entry_hash: &[u8;
     *hasher.finalize().as_bytes()
 }
 
+fn checkpoint_preimage_v2(
+    run_id: &str,
+    last_event_id: u64,
+    last_entry_hash: &[u8; 32],
+    merkle_root: &[u8; 32],
+    merkle_leaves: u64,
+) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"sentinel/checkpoint/v2");
+    hasher.update(&(run_id.len() as u64).to_le_bytes());
+    hasher.update(run_id.as_bytes());
+    hasher.update(&last_event_id.to_le_bytes());
+    hasher.update(last_entry_hash);
+    hasher.update(&merkle_leaves.to_le_bytes());
+    hasher.update(merkle_root);
+    *hasher.finalize().as_bytes()
+}
+
+/// Preimage of named fields under a domain tag, for record versions from
+/// checkpoint v3 on. Each field is written as its length-prefixed name and
+/// length-prefixed value, so a field added later changes the domain tag's
+/// version instead of shifting the bytes of the fields after it.
+struct Preimage(blake3::Hasher);
+
+impl Preimage {
+    fn new(domain: &str) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(domain.as_bytes());
+        Self(hasher)
+    }
+
+    fn bytes(mut self, name: &str, value: &[u8]) -> Self {
+        self.0.update(&(name.len() as u64).to_le_bytes());
+        self.0.update(name.as_bytes());
+        self.0.update(&(value.len() as u64).to_le_bytes());
+        self.0.update(value);
+        self
+    }
+
+    fn u64(self, name: &str, value: u64) -> Self {
+        self.bytes(name, &value.to_le_bytes())
+    }
+
+    fn finish(self) -> [u8; 32] {
+        *self.0.finalize().as_bytes()
+    }
+}
+
+/// Which Sentinel wrote a log, wrapping what, where. Signed into version 3
+/// checkpoints.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Provenance {
+    pub sentinel_version: String,
+    /// blake3 (hex) of the wrapped command's argv, NUL-joined
+    pub command_digest: String,
+    pub hostname: String,
+}
+
+impl Provenance {
+    /// Provenance of this process wrapping `argv`.
+    pub fn current(argv: &[String]) -> Self {
+        Self {
+            sentinel_version: env!("CARGO_PKG_VERSION").to_string(),
+            command_digest: blake3::hash(argv.join("\0").as_bytes()).to_hex().to_string(),
+            hostname: hostname(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer outlives the call and its length is passed along.
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if rc != 0 {
+        return String::new();
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// The signed content of a checkpoint, whichever version it is written as.
+struct CheckpointBody<'a> {
+    run_id: &'a str,
+    last_event_id: u64,
+    last_entry_hash: [u8; 32],
+    /// (root, leaf count); version 2+
+    merkle: Option<([u8; 32], u64)>,
+    /// Version 3+
+    provenance: Option<Provenance>,
+}
+
+impl<'a> CheckpointBody<'a> {
+    /// The body of a checkpoint record and the version it was signed as; `None`
+    /// for any other record.
+    fn from_record(rec: &'a AuditRecord) -> Result<Option<(u32, Self)>, String> {
+        let AuditRecord::Checkpoint {
+            run_id,
+            last_event_id,
+            last_entry_hash_b64,
+            version,
+            merkle_root_b64,
+            merkle_leaves,
+            sentinel_version,
+            command_digest,
+            hostname,
+            ..
+        } = rec
+        else {
+            return Ok(None);
+        };
+        let last_entry_hash =
+            decode_b64_32(last_entry_hash_b64).map_err(|e| format!("bad checkpoint last_entry_hash_b64: {e}"))?;
+        let merkle = match (merkle_root_b64, merkle_leaves) {
+            (Some(root), Some(leaves)) => Some((
+                decode_b64_32(root).map_err(|e| format!("bad checkpoint merkle_root_b64: {e}"))?,
+                *leaves,
+            )),
+            _ => None,
+        };
+        let provenance = match (sentinel_version, command_digest, hostname) {
+            (Some(v), Some(d), Some(h)) => Some(Provenance {
+                sentinel_version: v.clone(),
+                command_digest: d.clone(),
+                hostname: h.clone(),
+            }),
+            _ => None,
+        };
+        let body = Self {
+            run_id,
+            last_event_id: *last_event_id,
+            last_entry_hash,
+            merkle,
+            provenance,
+        };
+        Ok(Some((*version, body)))
+    }
+
+    /// The newest version that signs every field present.
+    fn version(&self) -> u32 {
+        match (&self.merkle, &self.provenance) {
+            (Some(_), Some(_)) => 3,
+            (Some(_), None) => 2,
+            (None, _) => 1,
+        }
+    }
+
+    /// The preimage signed by a checkpoint of `version`. Fields newer than the
+    /// version are not covered, so a record must not carry them.
+    fn preimage(&self, version: u32) -> Result<[u8; 32], String> {
+        let (run_id, id, hash) = (self.run_id, self.last_event_id, &self.last_entry_hash);
+        match (version, &self.merkle, &self.provenance) {
+            (1, None, None) => Ok(checkpoint_preimage(run_id, id, hash)),
+            (2, Some((root, leaves)), None) => Ok(checkpoint_preimage_v2(run_id, id, hash, root, *leaves)),
+            (3, Some((root, leaves)), Some(p)) => Ok(Preimage::new("sentinel/checkpoint/v3")
+                .bytes("run_id", run_id.as_bytes())
+                .u64("last_event_id", id)
+                .bytes("last_entry_hash", hash)
+                .u64("merkle_leaves", *leaves)
+                .bytes("merkle_root", root)
+                .bytes("sentinel_version", p.sentinel_version.as_bytes())
+                .bytes("command_digest", p.command_digest.as_bytes())
+                .bytes("hostname", p.hostname.as_bytes())
+                .finish()),
+            (1..=3, _, _) => Err(format!(
+                "version {version} checkpoint with {} fields",
+                if self.version() > version { "unsigned newer" } else { "missing" }
+            )),
+            (v, _, _) => Err(format!("unsupported checkpoint version {v}")),
+        }
+    }
+}
+
+fn run_boundary_preimage(previous_run_id: &str, run_id: &str, last_event_id: u64, last_entry_hash: &[u8; 32]) -> [u8; 32] {
+    // Domain-separated so a boundary signature can never pass as a checkpoint's.
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"sentinel/run-boundary/v1");
+    for id in [previous_run_id, run_id] {
+        hasher.update(&(id.len() as u64).to_le_bytes());
+        hasher.update(id.as_bytes());
+    }
+    hasher.update(&last_event_id.to_le_bytes());
+    hasher.update(last_entry_hash);
+    *hasher.finalize().as_bytes()
+}
+
+fn segment_preimage(
+    run_id: &str,
+    segment: u32,
+    previous_segment: &str,
+    last_event_id: u64,
+    last_entry_hash: &[u8; 32],
+) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"sentinel/segment/v1");
+    for s in [run_id, previous_segment] {
+        hasher.update(&(s.len() as u64).to_le_bytes());
+        hasher.update(s.as_bytes());
+    }
+    hasher.update(&segment.to_le_bytes());
+    hasher.update(&last_event_id.to_le_bytes());
+    hasher.update(last_entry_hash);
+    *hasher.finalize().as_bytes()
+}
+
+fn migration_preimage(
+    run_id: &str,
+    last_event_id: u64,
+    last_entry_hash: &[u8; 32],
+    source_file_blake3: &[u8; 32],
+    source_last_entry_hash: &[u8; 32],
+    source_versions: &[u32],
+) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"sentinel/migration/v1");
+    hasher.update(&(run_id.len() as u64).to_le_bytes());
+    hasher.update(run_id.as_bytes());
+    hasher.update(&last_event_id.to_le_bytes());
+    hasher.update(last_entry_hash);
+    hasher.update(source_file_blake3);
+    hasher.update(source_last_entry_hash);
+    hasher.update(&(source_versions.len() as u64).to_le_bytes());
+    for v in source_versions {
+        hasher.update(&v.to_le_bytes());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+fn event_signature_preimage(entry_hash: &[u8; 32]) -> [u8; 32] {
+    // The entry hash already binds run, event id, content and chain position.
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"sentinel/event/v1");
+    hasher.update(entry_hash);
+    *hasher.finalize().as_bytes()
+}
+
 pub fn key_id_from_pubkey(pubkey: &VerifyingKey) -> String {
     // Short, stable identifier auditors can refer to.
     let bytes = pubkey.to_bytes();
@@ -131,77 +640,195 @@ pub fn key_id_from_pubkey(pubkey: &VerifyingKey) -> String {
     hex::encode(&fp.as_bytes()[0..6])
 }
 
-pub fn load_signing_key_b64(path: impl AsRef<Path>) -> Result<SigningKey, String> {
-    // File contains base64 of 32-byte Ed25519 seed.
-    let s = fs::read_to_string(path).map_err(|e| format!("failed to read key file: {e}"))?;
-    let s = s.trim();
-    let seed = B64
-        .decode(s)
-        .map_err(|e| format!("failed to base64-decode seed: {e}"))?;
-    if seed.len() != 32 {
-        return Err(format!("expected 32-byte seed, got {}", seed.len()));
-    }
-    let mut seed32 = [0u8; 32];
-    seed32.copy_from_slice(&seed);
-    Ok(SigningKey::from_bytes(&seed32))
+/// Load the Ed25519 signing seed from a key spec (file path, `cmd:` or `env:`;
+/// see [`crate::keys::KeySpec`]).
+pub fn load_signing_key_b64(spec: &str) -> Result<SigningKey, String> {
+    let seed = crate::keys::load_key32(spec, "signing key")?;
+    Ok(SigningKey::from_bytes(&seed))
 }
 
-pub fn load_verify_key_b64(path: impl AsRef<Path>) -> Result<VerifyingKey, String> {
-    // File contains base64 of 32-byte Ed25519 public key.
-    let s = fs::read_to_string(path).map_err(|e| format!("failed to read pubkey file: {e}"))?;
-    let s = s.trim();
-    let pk = B64
-        .decode(s)
-        .map_err(|e| format!("failed to base64-decode pubkey: {e}"))?;
-    if pk.len() != 32 {
-        return Err(format!("expected 32-byte public key, got {}", pk.len()));
+/// Load an Ed25519 public key from a key spec.
+pub fn load_verify_key_b64(spec: &str) -> Result<VerifyingKey, String> {
+    let pk = crate::keys::load_key32(spec, "verifying key")?;
+    VerifyingKey::from_bytes(&pk).map_err(|e| format!("invalid verifying key: {e}"))
+}
+
+/// Signs checkpoints. Implemented for in-memory keys; a provider that signs
+/// remotely (KMS, HSM) implements it without ever exposing the private key.
+pub trait CheckpointSigner: Send + Sync {
+    fn verifying_key(&self) -> VerifyingKey;
+
+    fn sign_checkpoint(&self, preimage: &[u8]) -> Result<Signature, String>;
+}
+
+impl CheckpointSigner for SigningKey {
+    fn verifying_key(&self) -> VerifyingKey {
+        SigningKey::verifying_key(self)
+    }
+
+    fn sign_checkpoint(&self, preimage: &[u8]) -> Result<Signature, String> {
+        Ok(self.sign(preimage))
     }
-    let mut pk32 = [0u8; 32];
-    pk32.copy_from_slice(&pk);
-    VerifyingKey::from_bytes(&pk32).map_err(|e| format!("invalid verifying key: {e}"))
 }
 
-/// Compute entry hash = blake3(prev_hash || signable_bytes)
-pub fn compute_entry_hash(prev_hash: &[u8; 32], log: &McpLog) -> Result<[u8; 32], String> {
-    let bytes = signable_bytes(log)?;
+/// Compute entry hash = blake3(prev_hash || signable_bytes [|| write_offset (v3+)])
+pub fn compute_entry_hash(
+    prev_hash: &[u8; 32],
+    log: &McpLog,
+    version: u32,
+    write_offset: Option<u64>,
+) -> Result<[u8; 32], String> {
+    let bytes = signable_bytes(log, version)?;
     let mut hasher = blake3::Hasher::new();
     hasher.update(prev_hash);
     hasher.update(&bytes);
+    if version >= 3 {
+        let offset = write_offset.ok_or_else(|| format!("v{version} record is missing write_offset"))?;
+        hasher.update(&offset.to_le_bytes());
+    }
     Ok(*hasher.finalize().as_bytes())
 }
 
-/// Build an event record + updated prev hash.
+/// Build an event record + updated prev hash. `write_offset` is the byte position
+/// in the file where the record's line will start.
 pub fn make_event_record(
     prev_hash: &[u8; 32],
     log: McpLog,
+    write_offset: u64,
 ) -> Result<(AuditRecord, [u8; 32]), String> {
-    let entry_hash = compute_entry_hash(prev_hash, &log)?;
+    let entry_hash = compute_entry_hash(prev_hash, &log, SCHEMA_VERSION, Some(write_offset))?;
     let rec = AuditRecord::Event {
         log,
         integrity: IntegrityFields {
             prev_hash_b64: encode_b64_32(prev_hash),
             entry_hash_b64: encode_b64_32(&entry_hash),
             hash_alg: HASH_ALG.to_string(),
-            version: 1,
+            version: SCHEMA_VERSION,
+            write_offset: Some(write_offset),
+            signature_b64: None,
+        },
+    };
+    Ok((rec, entry_hash))
+}
+
+/// Whether `log` is the tap sequencer's notice of dropped taps, which the chain
+/// records as a GapMarker.
+pub fn is_gap_notice(log: &McpLog) -> bool {
+    log.direction == StreamDirection::Sentinel && log.method.as_deref() == Some(crate::tap::TAP_GAP_METHOD)
+}
+
+/// Taps dropped before they were sequenced, as the tap sequencer's
+/// `sentinel/tap_gap` notice names them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TapGap {
+    pub first_seq: u64,
+    pub last_seq: u64,
+    /// Per direction
+    pub dropped: BTreeMap<String, u64>,
+}
+
+impl TapGap {
+    /// Messages dropped, and so event ids skipped.
+    pub fn missed(&self) -> u64 {
+        self.last_seq.saturating_sub(self.first_seq) + 1
+    }
+}
+
+/// Entry hash of a GapMarker: every field but `integrity`, canonicalized,
+/// chained to `prev_hash` and bound to the record's offset like a v3 event.
+fn gap_entry_hash(prev_hash: &[u8; 32], marker: &AuditRecord, write_offset: Option<u64>) -> Result<[u8; 32], String> {
+    let mut value = serde_json::to_value(marker).map_err(|e| format!("failed to serialize gap marker: {e}"))?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("integrity");
+    }
+    let bytes = serde_json::to_vec(&canonicalize_value(&value))
+        .map_err(|e| format!("failed to serialize gap marker: {e}"))?;
+    let offset = write_offset.ok_or("gap marker is missing write_offset")?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(prev_hash);
+    hasher.update(&bytes);
+    hasher.update(&offset.to_le_bytes());
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// Build the GapMarker for `gap`, skipping the event ids after `last_event_id`,
+/// and its entry hash. `write_offset` is where the record's line will start.
+pub fn make_gap_marker(
+    prev_hash: &[u8; 32],
+    run_id: &str,
+    created_ts_ms: u64,
+    last_event_id: u64,
+    gap: TapGap,
+    write_offset: u64,
+) -> Result<(AuditRecord, [u8; 32]), String> {
+    let mut rec = AuditRecord::GapMarker {
+        run_id: run_id.to_string(),
+        created_ts_ms,
+        first_event_id: last_event_id + 1,
+        last_event_id: last_event_id + gap.missed(),
+        first_seq: gap.first_seq,
+        last_seq: gap.last_seq,
+        dropped: gap.dropped,
+        integrity: IntegrityFields {
+            prev_hash_b64: encode_b64_32(prev_hash),
+            entry_hash_b64: String::new(),
+            hash_alg: HASH_ALG.to_string(),
+            version: SCHEMA_VERSION,
+            write_offset: Some(write_offset),
+            signature_b64: None,
         },
     };
+    let entry_hash = gap_entry_hash(prev_hash, &rec, Some(write_offset))?;
+    if let AuditRecord::GapMarker { integrity, .. } = &mut rec {
+        integrity.entry_hash_b64 = encode_b64_32(&entry_hash);
+    }
     Ok((rec, entry_hash))
 }
 
-/// Build a signed checkpoint record for the current chain tip.
+/// Sign an event's entry hash for `IntegrityFields::signature_b64`.
+pub fn sign_entry_hash(signer: &dyn CheckpointSigner, entry_hash: &[u8; 32]) -> Result<String, String> {
+    let pre = event_signature_preimage(entry_hash);
+    let sig = signer
+        .sign_checkpoint(&pre)
+        .map_err(|e| format!("event signing failed (key_id {}): {e}", key_id_from_pubkey(&signer.verifying_key())))?;
+    Ok(B64.encode(sig.to_bytes()))
+}
+
+/// Attach an event signature made by [`sign_entry_hash`] to an event record.
+pub fn set_event_signature(rec: &mut AuditRecord, signature_b64: String) {
+    if let AuditRecord::Event { integrity, .. } = rec {
+        integrity.signature_b64 = Some(signature_b64);
+    }
+}
+
+/// Build a signed checkpoint record for the current chain tip. With `merkle`
+/// (root, leaf count) it is a version 2 checkpoint that also signs the root, and
+/// with `provenance` as well a version 3 one that also signs who wrote it.
 pub fn make_checkpoint_record(
-    signing_key: &SigningKey,
+    signer: &dyn CheckpointSigner,
     run_id: &str,
     created_ts_ms: u64,
     last_event_id: u64,
     last_entry_hash: &[u8; 32],
-) -> AuditRecord {
-    let pubkey = signing_key.verifying_key();
-    let key_id = key_id_from_pubkey(&pubkey);
+    merkle: Option<([u8; 32], u64)>,
+    provenance: Option<&Provenance>,
+) -> Result<AuditRecord, String> {
+    let key_id = key_id_from_pubkey(&signer.verifying_key());
 
-    let pre = checkpoint_preimage(run_id, last_event_id, last_entry_hash);
-    let sig: Signature = signing_key.sign(&pre);
-    AuditRecord::Checkpoint {
+    let body = CheckpointBody {
+        run_id,
+        last_event_id,
+        last_entry_hash: *last_entry_hash,
+        merkle,
+        provenance: provenance.filter(|_| merkle.is_some()).cloned(),
+    };
+    let version = body.version();
+    let pre = body.preimage(version)?;
+    let sig = signer
+        .sign_checkpoint(&pre)
+        .map_err(|e| format!("checkpoint signing failed (key_id {key_id}): {e}"))?;
+    let provenance = body.provenance;
+    Ok(AuditRecord::Checkpoint {
         run_id: run_id.to_string(),
         created_ts_ms,
         last_event_id,
@@ -210,79 +837,777 @@ pub fn make_checkpoint_record(
         key_id,
         hash_alg: HASH_ALG.to_string(),
         sig_alg: SIG_ALG.to_string(),
-        version: 1,
-    }
+        version,
+        merkle_root_b64: merkle.map(|(root, _)| encode_b64_32(&root)),
+        merkle_leaves: merkle.map(|(_, leaves)| leaves),
+        sentinel_version: provenance.as_ref().map(|p| p.sentinel_version.clone()),
+        command_digest: provenance.as_ref().map(|p| p.command_digest.clone()),
+        hostname: provenance.map(|p| p.hostname),
+    })
 }
 
-/// Verify an audit JSONL file.
-/// - Validates the hash chain across all Event records
-/// - Validates signatures on Checkpoint records
-pub fn verify_audit_log_file(
-    log_path: impl AsRef<Path>,
-    pubkey_path: impl AsRef<Path>,
-) -> Result<(), String> {
-    let vk = load_verify_key_b64(pubkey_path)?;
-    let expected_key_id = key_id_from_pubkey(&vk);
-
-    let f = fs::File::open(log_path.as_ref())
-        .map_err(|e| format!("failed to open log file {:?}: {e}", log_path.as_ref()))?;
-    let reader = BufReader::new(f);
-
-    let mut prev_hash = [0u8; 32];
-    let mut last_event_id: u64 = 0;
-    let mut run_id_seen: Option<String> = None;
-
-    let mut checkpoints_verified = 0u64;
-    let mut events_verified = 0u64;
-
-    for (idx, line_res) in reader.lines().enumerate() {
-        let line_no = idx + 1;
-        let line = line_res.map_err(|e| format!("line {line_no}: read error: {e}"))?;
-        if line.trim().is_empty() {
-            continue;
-        }
-
-        let rec: AuditRecord =
-            serde_json::from_str(&line).map_err(|e| format!("line {line_no}: JSON parse error: {e}"))?;
+/// The run, last_event_id and signing preimage of a checkpoint record; `None`
+/// for any other record, or one whose fields do not fit its version.
+pub fn checkpoint_signing_preimage(rec: &AuditRecord) -> Option<(String, u64, [u8; 32])> {
+    let (version, body) = CheckpointBody::from_record(rec).ok()??;
+    let pre = body.preimage(version).ok()?;
+    Some((body.run_id.to_string(), body.last_event_id, pre))
+}
 
-        match rec {
-            AuditRecord::Event { log, integrity } => {
-                // Run-id consistency
-                if let Some(rid) = &run_id_seen {
-                    if &log.run_id != rid {
-                        return Err(format!(
-                            "line {line_no}: run_id changed ({} -> {})",
-                            rid, log.run_id
-                        ));
-                    }
-                } else {
-                    run_id_seen = Some(log.run_id.clone());
-                }
+/// The provenance signed into a version 3 checkpoint record.
+pub fn checkpoint_provenance(rec: &AuditRecord) -> Option<Provenance> {
+    match CheckpointBody::from_record(rec) {
+        Ok(Some((3, body))) => body.provenance,
+        _ => None,
+    }
+}
 
-                // Check prev_hash matches file chain
-                let prev_b = decode_b64_32(&integrity.prev_hash_b64)
-                    .map_err(|e| format!("line {line_no}: bad prev_hash_b64: {e}"))?;
-                if prev_b != prev_hash {
-                    return Err(format!(
-                        "line {line_no}: prev_hash mismatch (expected {}, got {})",
-                        encode_b64_32(&prev_hash),
-                        integrity.prev_hash_b64
-                    ));
-                }
+/// Build the signed record that hands the chain tip of `previous_run_id` over
+/// to `run_id`.
+pub fn make_run_boundary_record(
+    signer: &dyn CheckpointSigner,
+    previous_run_id: &str,
+    run_id: &str,
+    created_ts_ms: u64,
+    last_event_id: u64,
+    last_entry_hash: &[u8; 32],
+) -> Result<AuditRecord, String> {
+    let key_id = key_id_from_pubkey(&signer.verifying_key());
 
-                // Check monotonic event_id (optional but very useful)
-                if last_event_id != 0 && log.event_id != last_event_id + 1 {
-                    return Err(format!(
-                        "line {line_no}: event_id not contiguous (prev {}, got {})",
-                        last_event_id, log.event_id
-                    ));
-                }
+    let pre = run_boundary_preimage(previous_run_id, run_id, last_event_id, last_entry_hash);
+    let sig = signer
+        .sign_checkpoint(&pre)
+        .map_err(|e| format!("run boundary signing failed (key_id {key_id}): {e}"))?;
+    Ok(AuditRecord::RunBoundary {
+        previous_run_id: previous_run_id.to_string(),
+        run_id: run_id.to_string(),
+        created_ts_ms,
+        last_event_id,
+        last_entry_hash_b64: encode_b64_32(last_entry_hash),
+        signature_b64: B64.encode(sig.to_bytes()),
+        key_id,
+        hash_alg: HASH_ALG.to_string(),
+        sig_alg: SIG_ALG.to_string(),
+        version: 1,
+    })
+}
 
-                // Recompute entry hash
-                let computed = compute_entry_hash(&prev_hash, &log)
-                    .map_err(|e| format!("line {line_no}: compute_entry_hash failed: {e}"))?;
-                let entry_b = decode_b64_32(&integrity.entry_hash_b64)
-                    .map_err(|e| format!("line {line_no}: bad entry_hash_b64: {e}"))?;
+/// Build the signed header that opens rotated segment `segment`, continuing the
+/// chain tip `previous_segment` (a file name) closed with.
+pub fn make_segment_header_record(
+    signer: &dyn CheckpointSigner,
+    run_id: &str,
+    segment: u32,
+    previous_segment: &str,
+    created_ts_ms: u64,
+    last_event_id: u64,
+    last_entry_hash: &[u8; 32],
+) -> Result<AuditRecord, String> {
+    let key_id = key_id_from_pubkey(&signer.verifying_key());
+
+    let pre = segment_preimage(run_id, segment, previous_segment, last_event_id, last_entry_hash);
+    let sig = signer
+        .sign_checkpoint(&pre)
+        .map_err(|e| format!("segment header signing failed (key_id {key_id}): {e}"))?;
+    Ok(AuditRecord::SegmentHeader {
+        run_id: run_id.to_string(),
+        segment,
+        previous_segment: previous_segment.to_string(),
+        created_ts_ms,
+        last_event_id,
+        last_entry_hash_b64: encode_b64_32(last_entry_hash),
+        signature_b64: B64.encode(sig.to_bytes()),
+        key_id,
+        hash_alg: HASH_ALG.to_string(),
+        sig_alg: SIG_ALG.to_string(),
+        version: 1,
+    })
+}
+
+/// What a migrated log was rewritten from (see [`AuditRecord::Migration`]).
+#[derive(Debug, Clone)]
+pub struct MigrationSource {
+    pub file_blake3: [u8; 32],
+    pub last_entry_hash: [u8; 32],
+    pub versions: Vec<u32>,
+}
+
+/// Build the signed record that ties a migrated log's chain tip to its source.
+pub fn make_migration_record(
+    signer: &dyn CheckpointSigner,
+    run_id: &str,
+    created_ts_ms: u64,
+    last_event_id: u64,
+    last_entry_hash: &[u8; 32],
+    source: &MigrationSource,
+) -> Result<AuditRecord, String> {
+    let key_id = key_id_from_pubkey(&signer.verifying_key());
+
+    let pre = migration_preimage(
+        run_id,
+        last_event_id,
+        last_entry_hash,
+        &source.file_blake3,
+        &source.last_entry_hash,
+        &source.versions,
+    );
+    let sig = signer
+        .sign_checkpoint(&pre)
+        .map_err(|e| format!("migration signing failed (key_id {key_id}): {e}"))?;
+    Ok(AuditRecord::Migration {
+        run_id: run_id.to_string(),
+        created_ts_ms,
+        last_event_id,
+        last_entry_hash_b64: encode_b64_32(last_entry_hash),
+        source_file_blake3_b64: encode_b64_32(&source.file_blake3),
+        source_last_entry_hash_b64: encode_b64_32(&source.last_entry_hash),
+        source_versions: source.versions.clone(),
+        signature_b64: B64.encode(sig.to_bytes()),
+        key_id,
+        hash_alg: HASH_ALG.to_string(),
+        sig_alg: SIG_ALG.to_string(),
+        version: 1,
+    })
+}
+
+/// The deterministic core of the audit writer: numbers events, redacts them and
+/// links them into the hash chain. No I/O and no clock, so `rederive` can replay
+/// it exactly.
+pub struct AuditChain {
+    /// How events are redacted; `None` leaves them as they are
+    redaction: Option<Arc<RedactionPolicy>>,
+    /// Payloads larger than this are replaced by a stub (`--max-payload-bytes`)
+    max_payload_bytes: Option<usize>,
+    prev_hash: [u8; 32],
+    last_event_id: u64,
+    since_last_checkpoint: u64,
+    /// Entry hashes since the last checkpoint; `None` writes version 1 checkpoints
+    merkle: Option<MerkleAccumulator>,
+    /// Signed into every checkpoint; `None` writes version 2 checkpoints
+    provenance: Option<Provenance>,
+}
+
+impl AuditChain {
+    pub fn new(redaction: Option<Arc<RedactionPolicy>>) -> Self {
+        Self {
+            redaction,
+            max_payload_bytes: None,
+            prev_hash: [0u8; 32],
+            last_event_id: 0,
+            since_last_checkpoint: 0,
+            merkle: Some(MerkleAccumulator::default()),
+            provenance: None,
+        }
+    }
+
+    /// Move payloads larger than `max` bytes out of the records (see
+    /// [`crate::blobs::offload_payload`]).
+    pub fn with_max_payload_bytes(mut self, max: Option<usize>) -> Self {
+        self.max_payload_bytes = max;
+        self
+    }
+
+    /// Sign `provenance` into the checkpoints (version 3).
+    pub fn with_provenance(mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Write version 1 checkpoints without a Merkle root, as logs from before
+    /// Merkle checkpoints have them.
+    pub fn without_merkle(mut self) -> Self {
+        self.merkle = None;
+        self
+    }
+
+    /// Continue the chain of an existing log from its verified tip.
+    pub fn resume(redaction: Option<Arc<RedactionPolicy>>, tip: &ChainTip) -> Self {
+        Self {
+            prev_hash: tip.last_entry_hash,
+            last_event_id: tip.last_event_id,
+            ..Self::new(redaction)
+        }
+    }
+
+    /// Assign the next event id, redact, offload an oversized payload and build the
+    /// record whose line will start at `write_offset`. The chain only advances once
+    /// the caller reports the write with [`AuditChain::commit`]. A notice of
+    /// dropped taps becomes a GapMarker, and `log.event_id` the last id it skips.
+    pub fn prepare(&self, log: &mut McpLog, write_offset: u64) -> Result<PreparedEvent, String> {
+        if is_gap_notice(log) {
+            let gap: TapGap = serde_json::from_value(log.payload.clone())
+                .map_err(|e| format!("bad {} notice: {e}", crate::tap::TAP_GAP_METHOD))?;
+            log.event_id = self.last_event_id + gap.missed();
+            return self.prepare_gap(&log.run_id, log.observed_ts_ms, gap, write_offset);
+        }
+
+        // Event IDs are assigned here so that events synthesized anywhere in the
+        // pipeline still form a contiguous sequence in the hash chain.
+        log.event_id = self.last_event_id + 1;
+
+        let redactions = match &self.redaction {
+            Some(policy) => crate::redaction::redact_log(log, policy),
+            None => RedactionReport::default(),
+        };
+        redactions.mark(log);
+        // After redaction, so offloaded payloads never hold what redaction removes.
+        let blob = self
+            .max_payload_bytes
+            .and_then(|max| crate::blobs::offload_payload(log, max));
+
+        let (record, entry_hash) = make_event_record(&self.prev_hash, log.clone(), write_offset)
+            .map_err(|e| format!("failed to create event record: {e}"))?;
+        Ok(PreparedEvent { record, entry_hash, blob, redactions })
+    }
+
+    /// Build the GapMarker for `gap` (see [`AuditChain::prepare`]); committed with
+    /// the last event id it skips.
+    pub fn prepare_gap(
+        &self,
+        run_id: &str,
+        created_ts_ms: u64,
+        gap: TapGap,
+        write_offset: u64,
+    ) -> Result<PreparedEvent, String> {
+        let (record, entry_hash) =
+            make_gap_marker(&self.prev_hash, run_id, created_ts_ms, self.last_event_id, gap, write_offset)?;
+        Ok(PreparedEvent { record, entry_hash, blob: None, redactions: RedactionReport::default() })
+    }
+
+    /// Advance the chain past a record built by [`AuditChain::prepare`] and written.
+    pub fn commit(&mut self, event_id: u64, entry_hash: [u8; 32]) {
+        self.prev_hash = entry_hash;
+        self.last_event_id = event_id;
+        self.since_last_checkpoint += 1;
+        if let Some(merkle) = &mut self.merkle {
+            merkle.push(&entry_hash);
+        }
+    }
+
+    pub fn last_event_id(&self) -> u64 {
+        self.last_event_id
+    }
+
+    pub fn last_entry_hash_b64(&self) -> String {
+        encode_b64_32(&self.prev_hash)
+    }
+
+    /// Events appended since the last checkpoint.
+    pub fn pending(&self) -> u64 {
+        self.since_last_checkpoint
+    }
+
+    /// Sign the current chain tip and reset the checkpoint counter.
+    pub fn checkpoint(
+        &mut self,
+        signer: &dyn CheckpointSigner,
+        run_id: &str,
+        created_ts_ms: u64,
+    ) -> Result<AuditRecord, String> {
+        self.since_last_checkpoint = 0;
+        let merkle = self.merkle.take().map(|m| (m.root(), m.leaves()));
+        if merkle.is_some() {
+            self.merkle = Some(MerkleAccumulator::default());
+        }
+        make_checkpoint_record(
+            signer,
+            run_id,
+            created_ts_ms,
+            self.last_event_id,
+            &self.prev_hash,
+            merkle,
+            self.provenance.as_ref(),
+        )
+    }
+
+    /// Sign the current chain tip over from `previous_run_id` to `run_id`. Like a
+    /// checkpoint, the boundary starts a new Merkle tree.
+    pub fn run_boundary(
+        &mut self,
+        signer: &dyn CheckpointSigner,
+        previous_run_id: &str,
+        run_id: &str,
+        created_ts_ms: u64,
+    ) -> Result<AuditRecord, String> {
+        if self.merkle.is_some() {
+            self.merkle = Some(MerkleAccumulator::default());
+        }
+        make_run_boundary_record(signer, previous_run_id, run_id, created_ts_ms, self.last_event_id, &self.prev_hash)
+    }
+
+    /// Sign the current chain tip together with the log it was migrated from.
+    pub fn migration(
+        &self,
+        signer: &dyn CheckpointSigner,
+        run_id: &str,
+        created_ts_ms: u64,
+        source: &MigrationSource,
+    ) -> Result<AuditRecord, String> {
+        make_migration_record(signer, run_id, created_ts_ms, self.last_event_id, &self.prev_hash, source)
+    }
+
+    /// Sign the current chain tip into the header of rotated segment `segment`.
+    pub fn segment_header(
+        &self,
+        signer: &dyn CheckpointSigner,
+        run_id: &str,
+        segment: u32,
+        previous_segment: &str,
+        created_ts_ms: u64,
+    ) -> Result<AuditRecord, String> {
+        make_segment_header_record(
+            signer,
+            run_id,
+            segment,
+            previous_segment,
+            created_ts_ms,
+            self.last_event_id,
+            &self.prev_hash,
+        )
+    }
+}
+
+/// An event record built by [`AuditChain::prepare`], not yet written.
+pub struct PreparedEvent {
+    pub record: AuditRecord,
+    pub entry_hash: [u8; 32],
+    /// Payload moved out of the record by `--max-payload-bytes`, serialized
+    pub blob: Option<Vec<u8>>,
+    /// What redaction replaced in the event, also recorded on it
+    pub redactions: RedactionReport,
+}
+
+/// End of a verified log, where an appended run picks up the chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTip {
+    pub run_id: String,
+    pub last_event_id: u64,
+    pub last_entry_hash: [u8; 32],
+    /// File length; the appended run's first record starts here
+    pub end_offset: u64,
+}
+
+/// First bytes of a zstd frame. A plaintext log written with `run --compress zstd`
+/// is a zstd stream and starts with them.
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Open a log for reading, decompressing it if it is a zstd stream.
+pub fn open_log(log_path: &Path) -> Result<Box<dyn Read>, String> {
+    let f = fs::File::open(log_path).map_err(|e| format!("failed to open log file {:?}: {e}", log_path))?;
+    decompressed(f).map_err(|e| format!("failed to read log file {:?}: {e}", log_path))
+}
+
+/// `log` itself, or its decompressed contents if it starts with [`ZSTD_MAGIC`].
+/// An age stream is refused: it has to be opened with an identity
+/// ([`crate::audit_crypto::open_plaintext`]).
+pub fn decompressed<'a>(log: impl Read + 'a) -> std::io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(log);
+    let start = reader.fill_buf()?;
+    if start.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+    } else if start.starts_with(crate::audit_crypto::AGE_MAGIC) {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the log is age-encrypted; it can only be read with an age identity",
+        ))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Verify the whole log at `log_path` and return its chain tip. Only plaintext
+/// logs with a strict (`sentinel-audit-v3`) layout can be continued.
+pub fn chain_tip(log_path: impl AsRef<Path>, vk: VerifyingKey) -> Result<ChainTip, String> {
+    let log_path = log_path.as_ref();
+    let f = open_log(log_path)?;
+    let mut verifier = ChainVerifier::new(vk);
+    let end_offset = walk_lines(&mut verifier, f, &VerifyOptions::default())?;
+    if !verifier.strict_layout {
+        return Err(format!("only {FORMAT_AUDIT} logs can be appended to"));
+    }
+    if verifier.segment.is_some() {
+        return Err("the log is a rotated segment".to_string());
+    }
+    Ok(verifier.tip(end_offset))
+}
+
+/// Path of rotated segment `n` (1-based) of the log at `first`:
+/// `sentinel_audit.jsonl` continues in `sentinel_audit.1.jsonl`, `sentinel_audit.2.jsonl`, ...
+pub fn segment_path(first: &Path, n: u32) -> PathBuf {
+    let name = match (first.file_stem(), first.extension()) {
+        (Some(stem), Some(ext)) => format!("{}.{n}.{}", stem.to_string_lossy(), ext.to_string_lossy()),
+        _ => format!("{}.{n}", first.file_name().unwrap_or_default().to_string_lossy()),
+    };
+    first.with_file_name(name)
+}
+
+/// `n` when `name` is the file name of rotated segment `n` of `first`.
+fn segment_number(first: &Path, name: &str) -> Option<u32> {
+    let middle = match (first.file_stem(), first.extension()) {
+        (Some(stem), Some(ext)) => name
+            .strip_prefix(&format!("{}.", stem.to_string_lossy()))?
+            .strip_suffix(&format!(".{}", ext.to_string_lossy()))?,
+        _ => name.strip_prefix(&format!("{}.", first.file_name()?.to_string_lossy()))?,
+    };
+    middle.parse().ok().filter(|n| *n > 0)
+}
+
+/// The log at `first` followed by its rotated segments, in order. Fails when a
+/// segment before the last one found is missing.
+pub fn segment_paths(first: &Path) -> Result<Vec<PathBuf>, String> {
+    let dir = match first.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let entries = fs::read_dir(dir).map_err(|e| format!("failed to list {:?}: {e}", dir))?;
+    let mut numbers: Vec<u32> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| segment_number(first, &e.file_name().to_string_lossy()))
+        .collect();
+    numbers.sort_unstable();
+
+    let mut paths = vec![first.to_path_buf()];
+    for (expected, n) in (1..).zip(numbers) {
+        if n != expected {
+            return Err(format!("segment {:?} is missing", segment_path(first, expected)));
+        }
+        paths.push(segment_path(first, n));
+    }
+    Ok(paths)
+}
+
+/// Where a rotated segment continues the chain, as its SegmentHeader states.
+#[derive(Debug, Clone)]
+pub struct SegmentLink {
+    pub run_id: String,
+    pub segment: u32,
+    pub previous_segment: String,
+    pub last_event_id: u64,
+    pub last_entry_hash: [u8; 32],
+}
+
+impl SegmentLink {
+    /// Check that this is segment `n` and continues `previous` (the file name of
+    /// segment `n - 1`), which ended at `tip`.
+    pub fn check_continues(&self, n: u32, previous: &str, tip: &ChainTip) -> Result<(), String> {
+        if self.segment != n {
+            return Err(format!("is segment {}, expected segment {n} (a segment is missing)", self.segment));
+        }
+        if self.previous_segment != previous {
+            return Err(format!("continues {:?}, expected {:?}", self.previous_segment, previous));
+        }
+        if self.run_id != tip.run_id || self.last_event_id != tip.last_event_id || self.last_entry_hash != tip.last_entry_hash
+        {
+            return Err(format!("does not continue the chain tip of {} (segment missing or replaced)", previous));
+        }
+        Ok(())
+    }
+}
+
+/// Position in the hash chain, persisted so verification can resume on new data.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifierCursor {
+    pub prev_hash_b64: String,
+    pub last_event_id: u64,
+    pub run_id: Option<String>,
+    pub records_seen: u64,
+    pub events_verified: u64,
+    pub checkpoints_verified: u64,
+    /// last_event_id covered by the most recent valid checkpoint signature
+    pub last_signed_event_id: u64,
+    /// File declared a strict (v3) layout in its header
+    #[serde(default)]
+    pub strict_layout: bool,
+    /// Entry hashes since the last checkpoint, for its Merkle root
+    #[serde(default)]
+    pub merkle: MerkleAccumulator,
+    #[serde(default)]
+    pub gaps: u64,
+    #[serde(default)]
+    pub missed_events: u64,
+}
+
+/// Incremental verifier for a plaintext audit log, fed one line at a time.
+pub struct ChainVerifier {
+    vk: VerifyingKey,
+    expected_key_id: String,
+    prev_hash: [u8; 32],
+    last_event_id: u64,
+    run_id_seen: Option<String>,
+    records_seen: u64,
+    events_verified: u64,
+    checkpoints_verified: u64,
+    /// GapMarker records, and the event ids they skip
+    gaps: u64,
+    missed_events: u64,
+    last_signed_event_id: u64,
+    strict_layout: bool,
+    segment: Option<SegmentLink>,
+    merkle: MerkleAccumulator,
+    /// CA bundle timestamp tokens must chain to (`verify --tsa-ca-bundle`)
+    tsa_roots: Option<Arc<TrustRoots>>,
+    stamps: TimestampTally,
+    /// Distinct provenance of the version 3 checkpoints, in order of appearance
+    provenance: Vec<Provenance>,
+    first_event: Option<EventMark>,
+    last_event: Option<EventMark>,
+    /// `created_ts_ms` of the last checkpoint
+    last_checkpoint_ts_ms: Option<u64>,
+    /// Every run_id the chain went through, in order
+    run_ids: Vec<String>,
+    /// Every checkpoint record, when collecting them ([`VerifyOptions::collect_checkpoints`])
+    checkpoints: Option<Vec<AuditRecord>>,
+}
+
+/// Checkpoints and the timestamp proofs seen for them. Not part of the cursor:
+/// `watch-verify` checks each proof but does not track missing ones.
+#[derive(Debug, Default)]
+struct TimestampTally {
+    /// Imprints of checkpoints without a proof so far, with their last_event_id
+    unstamped: Vec<([u8; 32], u64)>,
+    /// Imprints of proofs whose checkpoint was not seen before them
+    orphans: Vec<[u8; 32]>,
+    proofs: u64,
+}
+
+impl TimestampTally {
+    /// Continue with the tally of the segment after this one, whose proofs may
+    /// cover this segment's last checkpoints.
+    fn absorb(&mut self, next: TimestampTally) {
+        for imprint in next.orphans {
+            match self.unstamped.iter().position(|(i, _)| *i == imprint) {
+                Some(at) => {
+                    self.unstamped.remove(at);
+                }
+                None => self.orphans.push(imprint),
+            }
+        }
+        self.unstamped.extend(next.unstamped);
+        self.proofs += next.proofs;
+    }
+
+    /// Warnings for checkpoints without a proof and proofs without a checkpoint.
+    /// Missing proofs only count once timestamps are in use: some proof was
+    /// written, or the options ask for them.
+    fn warnings(&self, options: &VerifyOptions) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+        if let Some((_, event_id)) = self.unstamped.first() {
+            let missing = format!(
+                "{} checkpoint(s) have no timestamp proof (the first at event_id {})",
+                self.unstamped.len(),
+                event_id
+            );
+            if options.require_timestamps {
+                return Err(missing);
+            }
+            if self.proofs > 0 || options.tsa_roots.is_some() {
+                warnings.push(missing);
+            }
+        }
+        if !self.orphans.is_empty() {
+            warnings.push(format!(
+                "{} timestamp proof(s) cover a checkpoint that is not in this log (verify rotated logs with --segments)",
+                self.orphans.len()
+            ));
+        }
+        Ok(warnings)
+    }
+}
+
+impl ChainVerifier {
+    pub fn new(vk: VerifyingKey) -> Self {
+        Self {
+            expected_key_id: key_id_from_pubkey(&vk),
+            vk,
+            prev_hash: [0u8; 32],
+            last_event_id: 0,
+            run_id_seen: None,
+            records_seen: 0,
+            events_verified: 0,
+            checkpoints_verified: 0,
+            gaps: 0,
+            missed_events: 0,
+            last_signed_event_id: 0,
+            strict_layout: false,
+            segment: None,
+            merkle: MerkleAccumulator::default(),
+            tsa_roots: None,
+            stamps: TimestampTally::default(),
+            provenance: Vec::new(),
+            first_event: None,
+            last_event: None,
+            last_checkpoint_ts_ms: None,
+            run_ids: Vec::new(),
+            checkpoints: None,
+        }
+    }
+
+    /// Enter `run_id` as the current run.
+    fn enter_run(&mut self, run_id: String) {
+        if self.run_ids.last() != Some(&run_id) {
+            self.run_ids.push(run_id.clone());
+        }
+        self.run_id_seen = Some(run_id);
+    }
+
+    /// What the lines verified so far establish about the log.
+    pub fn summary(&self) -> VerifySummary {
+        VerifySummary {
+            warnings: Vec::new(),
+            provenance: self.provenance.clone(),
+            records_seen: self.records_seen,
+            events_verified: self.events_verified,
+            checkpoints_verified: self.checkpoints_verified,
+            gaps: self.gaps,
+            missed_events: self.missed_events,
+            first_event: self.first_event,
+            last_event: self.last_event,
+            last_checkpoint_ts_ms: self.last_checkpoint_ts_ms,
+            final_entry_hash_b64: encode_b64_32(&self.prev_hash),
+            run_ids: self.run_ids.clone(),
+            checkpoints: self.checkpoints.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn resume(vk: VerifyingKey, cursor: &VerifierCursor) -> Result<Self, String> {
+        let mut v = Self::new(vk);
+        if cursor.records_seen > 0 {
+            v.prev_hash = decode_b64_32(&cursor.prev_hash_b64)
+                .map_err(|e| format!("bad cursor prev_hash_b64: {e}"))?;
+        }
+        v.last_event_id = cursor.last_event_id;
+        v.run_id_seen = cursor.run_id.clone();
+        v.records_seen = cursor.records_seen;
+        v.events_verified = cursor.events_verified;
+        v.checkpoints_verified = cursor.checkpoints_verified;
+        v.gaps = cursor.gaps;
+        v.missed_events = cursor.missed_events;
+        v.last_signed_event_id = cursor.last_signed_event_id;
+        v.strict_layout = cursor.strict_layout;
+        v.merkle = cursor.merkle.clone();
+        Ok(v)
+    }
+
+    /// The chain tip of what was verified so far, which ends at `end_offset`.
+    pub fn tip(&self, end_offset: u64) -> ChainTip {
+        ChainTip {
+            run_id: self.run_id_seen.clone().unwrap_or_default(),
+            last_event_id: self.last_event_id,
+            last_entry_hash: self.prev_hash,
+            end_offset,
+        }
+    }
+
+    /// The SegmentHeader the file started with, if it is a rotated segment.
+    pub fn segment_link(&self) -> Option<&SegmentLink> {
+        self.segment.as_ref()
+    }
+
+    pub fn cursor(&self) -> VerifierCursor {
+        VerifierCursor {
+            prev_hash_b64: encode_b64_32(&self.prev_hash),
+            last_event_id: self.last_event_id,
+            run_id: self.run_id_seen.clone(),
+            records_seen: self.records_seen,
+            events_verified: self.events_verified,
+            checkpoints_verified: self.checkpoints_verified,
+            last_signed_event_id: self.last_signed_event_id,
+            strict_layout: self.strict_layout,
+            merkle: self.merkle.clone(),
+            gaps: self.gaps,
+            missed_events: self.missed_events,
+        }
+    }
+
+    /// Verify the next line of the file, as read including its line terminator.
+    /// `offset` is where the line starts in the file, when known; v3 events must
+    /// record exactly that offset. Errors name `line_no`.
+    pub fn verify_line(&mut self, line_no: usize, offset: Option<u64>, raw: &str) -> Result<(), String> {
+        if self.strict_layout {
+            check_strict_line(raw).map_err(|e| format!("line {line_no}: {e}"))?;
+        }
+        let line = normalize_line(raw);
+        if line.is_empty() {
+            return Ok(());
+        }
+        self.records_seen += 1;
+
+        if self.records_seen == 1 {
+            match parse_file_header(line).map_err(|e| format!("line {line_no}: {e}"))? {
+                Some(format) if format == FORMAT_AUDIT => {
+                    check_strict_line(raw).map_err(|e| format!("line {line_no}: {e}"))?;
+                    self.strict_layout = true;
+                    return Ok(());
+                }
+                Some(format) if format == LEGACY_FORMAT_AUDIT => return Ok(()),
+                Some(format) => {
+                    return Err(format!(
+                        "line {line_no}: file format is {format}, expected {FORMAT_AUDIT} (decrypt encrypted logs first)"
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        let rec: AuditRecord =
+            serde_json::from_str(line).map_err(|e| format!("line {line_no}: JSON parse error: {e}"))?;
+
+        match rec {
+            AuditRecord::FileHeader { .. } => {
+                return Err(format!("line {line_no}: FileHeader is only allowed on the first line"));
+            }
+
+            AuditRecord::Event { log, integrity } => {
+                // Run-id consistency
+                if let Some(rid) = &self.run_id_seen {
+                    if &log.run_id != rid {
+                        return Err(format!(
+                            "line {line_no}: run_id changed ({} -> {})",
+                            rid, log.run_id
+                        ));
+                    }
+                } else {
+                    self.enter_run(log.run_id.clone());
+                }
+
+                // Check prev_hash matches file chain
+                let prev_b = decode_b64_32(&integrity.prev_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad prev_hash_b64: {e}"))?;
+                if prev_b != self.prev_hash {
+                    return Err(format!(
+                        "line {line_no}: prev_hash mismatch (expected {}, got {})",
+                        encode_b64_32(&self.prev_hash),
+                        integrity.prev_hash_b64
+                    ));
+                }
+
+                // Check monotonic event_id (optional but very useful)
+                if self.last_event_id != 0 && log.event_id != self.last_event_id + 1 {
+                    return Err(format!(
+                        "line {line_no}: event_id not contiguous (prev {}, got {})",
+                        self.last_event_id, log.event_id
+                    ));
+                }
+
+                if self.strict_layout && integrity.version < 3 {
+                    return Err(format!(
+                        "line {line_no}: v{} record in a {FORMAT_AUDIT} file",
+                        integrity.version
+                    ));
+                }
+                if let (Some(recorded), Some(actual)) = (integrity.write_offset, offset) {
+                    if recorded != actual {
+                        return Err(format!(
+                            "line {line_no}: record written at byte {recorded} but found at byte {actual} (bytes inserted or removed)"
+                        ));
+                    }
+                }
+
+                // Recompute entry hash
+                let computed = compute_entry_hash(&self.prev_hash, &log, integrity.version, integrity.write_offset)
+                    .map_err(|e| format!("line {line_no}: compute_entry_hash failed: {e}"))?;
+                let entry_b = decode_b64_32(&integrity.entry_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad entry_hash_b64: {e}"))?;
 
                 if computed != entry_b {
                     return Err(format!(
@@ -292,83 +1617,1180 @@ pub fn verify_audit_log_file(
                     ));
                 }
 
+                if let Some(sig) = &integrity.signature_b64 {
+                    let pre = event_signature_preimage(&computed);
+                    self.check_signature(line_no, "event", &self.expected_key_id, sig, &pre)?;
+                    self.last_signed_event_id = log.event_id;
+                }
+
                 // Advance chain tip
-                prev_hash = computed;
-                last_event_id = log.event_id;
-                events_verified += 1;
+                self.prev_hash = computed;
+                self.last_event_id = log.event_id;
+                self.events_verified += 1;
+                self.merkle.push(&computed);
+                let mark = EventMark { event_id: log.event_id, observed_ts_ms: log.observed_ts_ms };
+                self.first_event.get_or_insert(mark);
+                self.last_event = Some(mark);
             }
 
             AuditRecord::Checkpoint {
-                run_id,
-                last_event_id: cp_last_event_id,
-                last_entry_hash_b64,
-                signature_b64,
-                key_id,
-                hash_alg: _,
-                sig_alg: _,
-                version: _,
-                created_ts_ms: _,
+                ref run_id,
+                created_ts_ms,
+                ref signature_b64,
+                ref key_id,
+                ..
             } => {
+                let Some((version, body)) =
+                    CheckpointBody::from_record(&rec).map_err(|e| format!("line {line_no}: {e}"))?
+                else {
+                    unreachable!("a Checkpoint record has a checkpoint body")
+                };
+                let cp_last_event_id = body.last_event_id;
+
                 // Bind checkpoint to same run
-                if let Some(rid) = &run_id_seen {
-                    if &run_id != rid {
+                if let Some(rid) = &self.run_id_seen {
+                    if run_id != rid {
                         return Err(format!(
                             "line {line_no}: checkpoint run_id mismatch (expected {}, got {})",
                             rid, run_id
                         ));
                     }
                 } else {
-                    run_id_seen = Some(run_id.clone());
+                    self.enter_run(run_id.clone());
                 }
 
                 // Must match current chain tip
-                let cp_hash = decode_b64_32(&last_entry_hash_b64)
-                    .map_err(|e| format!("line {line_no}: bad checkpoint last_entry_hash_b64: {e}"))?;
-                if cp_hash != prev_hash {
+                if body.last_entry_hash != self.prev_hash {
                     return Err(format!(
                         "line {line_no}: checkpoint hash does not match current chain tip"
                     ));
                 }
 
-                if cp_last_event_id != last_event_id {
+                if cp_last_event_id != self.last_event_id {
                     return Err(format!(
                         "line {line_no}: checkpoint last_event_id {} does not match stream last_event_id {}",
-                        cp_last_event_id, last_event_id
+                        cp_last_event_id, self.last_event_id
+                    ));
+                }
+
+                if let (2.., Some((root, leaves))) = (version, body.merkle) {
+                    if leaves != self.merkle.leaves() || root != self.merkle.root() {
+                        return Err(format!(
+                            "line {line_no}: checkpoint merkle root does not cover the {} events since the previous checkpoint",
+                            self.merkle.leaves()
+                        ));
+                    }
+                }
+                let pre = body.preimage(version).map_err(|e| format!("line {line_no}: {e}"))?;
+                self.check_signature(line_no, "checkpoint", key_id, signature_b64, &pre)?;
+                if let Some(p) = body.provenance.filter(|_| version >= 3) {
+                    if !self.provenance.contains(&p) {
+                        self.provenance.push(p);
+                    }
+                }
+                self.stamps.unstamped.push((crate::timestamp::imprint(&pre), cp_last_event_id));
+
+                self.checkpoints_verified += 1;
+                self.last_signed_event_id = cp_last_event_id;
+                self.last_checkpoint_ts_ms = Some(created_ts_ms);
+                self.merkle = MerkleAccumulator::default();
+                if let Some(checkpoints) = &mut self.checkpoints {
+                    checkpoints.push(rec.clone());
+                }
+            }
+
+            AuditRecord::RunBoundary {
+                previous_run_id,
+                run_id,
+                last_event_id: rb_last_event_id,
+                last_entry_hash_b64,
+                signature_b64,
+                key_id,
+                hash_alg: _,
+                sig_alg: _,
+                version: _,
+                created_ts_ms: _,
+            } => {
+                // Hands over a run that is already in the file
+                match &self.run_id_seen {
+                    Some(rid) if *rid == previous_run_id => {}
+                    Some(rid) => {
+                        return Err(format!(
+                            "line {line_no}: run boundary previous_run_id mismatch (expected {}, got {})",
+                            rid, previous_run_id
+                        ))
+                    }
+                    None => return Err(format!("line {line_no}: run boundary before any run")),
+                }
+
+                let rb_hash = decode_b64_32(&last_entry_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad run boundary last_entry_hash_b64: {e}"))?;
+                if rb_hash != self.prev_hash {
+                    return Err(format!("line {line_no}: run boundary hash does not match current chain tip"));
+                }
+                if rb_last_event_id != self.last_event_id {
+                    return Err(format!(
+                        "line {line_no}: run boundary last_event_id {} does not match stream last_event_id {}",
+                        rb_last_event_id, self.last_event_id
+                    ));
+                }
+
+                let pre = run_boundary_preimage(&previous_run_id, &run_id, rb_last_event_id, &rb_hash);
+                self.check_signature(line_no, "run boundary", &key_id, &signature_b64, &pre)?;
+
+                self.enter_run(run_id);
+                self.last_signed_event_id = rb_last_event_id;
+                self.merkle = MerkleAccumulator::default();
+            }
+
+            AuditRecord::SegmentHeader {
+                run_id,
+                segment,
+                previous_segment,
+                last_event_id: sh_last_event_id,
+                last_entry_hash_b64,
+                signature_b64,
+                key_id,
+                hash_alg: _,
+                sig_alg: _,
+                version: _,
+                created_ts_ms: _,
+            } => {
+                // Opens the file: the chain picks up from the signed tip of the previous segment
+                if self.run_id_seen.is_some() || self.events_verified > 0 {
+                    return Err(format!("line {line_no}: SegmentHeader must be the first record"));
+                }
+
+                let sh_hash = decode_b64_32(&last_entry_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad segment header last_entry_hash_b64: {e}"))?;
+                let pre = segment_preimage(&run_id, segment, &previous_segment, sh_last_event_id, &sh_hash);
+                self.check_signature(line_no, "segment header", &key_id, &signature_b64, &pre)?;
+
+                self.prev_hash = sh_hash;
+                self.last_event_id = sh_last_event_id;
+                self.last_signed_event_id = sh_last_event_id;
+                self.enter_run(run_id.clone());
+                self.segment = Some(SegmentLink {
+                    run_id,
+                    segment,
+                    previous_segment,
+                    last_event_id: sh_last_event_id,
+                    last_entry_hash: sh_hash,
+                });
+            }
+
+            AuditRecord::Migration {
+                run_id,
+                last_event_id: m_last_event_id,
+                last_entry_hash_b64,
+                source_file_blake3_b64,
+                source_last_entry_hash_b64,
+                source_versions,
+                signature_b64,
+                key_id,
+                hash_alg: _,
+                sig_alg: _,
+                version: _,
+                created_ts_ms: _,
+            } => {
+                if self.run_id_seen.as_ref() != Some(&run_id) {
+                    return Err(format!("line {line_no}: migration run_id mismatch (got {run_id})"));
+                }
+                let m_hash = decode_b64_32(&last_entry_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad migration last_entry_hash_b64: {e}"))?;
+                if m_hash != self.prev_hash || m_last_event_id != self.last_event_id {
+                    return Err(format!("line {line_no}: migration record does not match current chain tip"));
+                }
+                let source = MigrationSource {
+                    file_blake3: decode_b64_32(&source_file_blake3_b64)
+                        .map_err(|e| format!("line {line_no}: bad source_file_blake3_b64: {e}"))?,
+                    last_entry_hash: decode_b64_32(&source_last_entry_hash_b64)
+                        .map_err(|e| format!("line {line_no}: bad source_last_entry_hash_b64: {e}"))?,
+                    versions: source_versions,
+                };
+
+                let pre = migration_preimage(
+                    &run_id,
+                    m_last_event_id,
+                    &m_hash,
+                    &source.file_blake3,
+                    &source.last_entry_hash,
+                    &source.versions,
+                );
+                self.check_signature(line_no, "migration", &key_id, &signature_b64, &pre)?;
+                self.last_signed_event_id = m_last_event_id;
+            }
+
+            AuditRecord::TimestampProof {
+                run_id: _,
+                checkpoint_last_event_id,
+                imprint_b64,
+                tsa_url: _,
+                token_b64,
+                gen_time_ms,
+                version: _,
+            } => {
+                // The imprint binds the proof to its checkpoint; the token must stamp
+                // exactly that imprint at the time the record claims.
+                let imprint = decode_b64_32(&imprint_b64)
+                    .map_err(|e| format!("line {line_no}: bad timestamp imprint_b64: {e}"))?;
+                let token = B64
+                    .decode(&token_b64)
+                    .map_err(|e| format!("line {line_no}: bad timestamp token_b64: {e}"))?;
+                let stamped = crate::timestamp::verify_token(&token, &imprint, self.tsa_roots.as_deref())
+                    .map_err(|e| format!("line {line_no}: timestamp proof: {e}"))?;
+                if stamped != gen_time_ms {
+                    return Err(format!(
+                        "line {line_no}: timestamp proof gen_time_ms {gen_time_ms} does not match its token ({stamped})"
+                    ));
+                }
+
+                match self.stamps.unstamped.iter().position(|(i, _)| *i == imprint) {
+                    Some(at) => {
+                        let (_, event_id) = self.stamps.unstamped.remove(at);
+                        if event_id != checkpoint_last_event_id {
+                            return Err(format!(
+                                "line {line_no}: timestamp proof names event_id {checkpoint_last_event_id}, but stamps the checkpoint at event_id {event_id}"
+                            ));
+                        }
+                    }
+                    None => self.stamps.orphans.push(imprint),
+                }
+                self.stamps.proofs += 1;
+            }
+
+            AuditRecord::WitnessAck { .. } => {}
+
+            AuditRecord::GapMarker { ref run_id, first_event_id, last_event_id: gap_last_event_id, ref integrity, .. } => {
+                match &self.run_id_seen {
+                    Some(rid) if rid != run_id => {
+                        return Err(format!("line {line_no}: run_id changed ({} -> {})", rid, run_id));
+                    }
+                    Some(_) => {}
+                    None => self.enter_run(run_id.clone()),
+                }
+
+                let prev_b = decode_b64_32(&integrity.prev_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad prev_hash_b64: {e}"))?;
+                if prev_b != self.prev_hash {
+                    return Err(format!(
+                        "line {line_no}: prev_hash mismatch (expected {}, got {})",
+                        encode_b64_32(&self.prev_hash),
+                        integrity.prev_hash_b64
                     ));
                 }
 
-                if key_id != expected_key_id {
+                // Accounts for the ids right after the chain tip, and for at least one
+                if (self.last_event_id != 0 && first_event_id != self.last_event_id + 1)
+                    || gap_last_event_id < first_event_id
+                {
                     return Err(format!(
-                        "line {line_no}: checkpoint key_id mismatch (expected {}, got {})",
-                        expected_key_id, key_id
+                        "line {line_no}: gap marker for event_id {first_event_id}..={gap_last_event_id} does not follow event_id {}",
+                        self.last_event_id
                     ));
                 }
 
-                let sig_bytes = B64
-                    .decode(signature_b64)
-                    .map_err(|e| format!("line {line_no}: bad signature_b64: {e}"))?;
-                if sig_bytes.len() != 64 {
-                    return Err(format!("line {line_no}: signature length {} != 64", sig_bytes.len()));
+                if let (Some(recorded), Some(actual)) = (integrity.write_offset, offset) {
+                    if recorded != actual {
+                        return Err(format!(
+                            "line {line_no}: record written at byte {recorded} but found at byte {actual} (bytes inserted or removed)"
+                        ));
+                    }
                 }
-                let mut sig64 = [0u8; 64];
-                sig64.copy_from_slice(&sig_bytes);
-                let sig = Signature::from_bytes(&sig64);
 
-                let pre = checkpoint_preimage(&run_id, cp_last_event_id, &cp_hash);
-                vk.verify_strict(&pre, &sig)
-                    .map_err(|e| format!("line {line_no}: signature verify failed: {e}"))?;
+                let computed = gap_entry_hash(&self.prev_hash, &rec, integrity.write_offset)
+                    .map_err(|e| format!("line {line_no}: {e}"))?;
+                let entry_b = decode_b64_32(&integrity.entry_hash_b64)
+                    .map_err(|e| format!("line {line_no}: bad entry_hash_b64: {e}"))?;
+                if computed != entry_b {
+                    return Err(format!(
+                        "line {line_no}: entry_hash mismatch (expected {}, got {})",
+                        encode_b64_32(&computed),
+                        integrity.entry_hash_b64
+                    ));
+                }
 
-                checkpoints_verified += 1;
+                self.prev_hash = computed;
+                self.last_event_id = gap_last_event_id;
+                self.merkle.push(&computed);
+                self.gaps += 1;
+                self.missed_events += gap_last_event_id - first_event_id + 1;
             }
         }
+
+        Ok(())
     }
 
-    if events_verified == 0 {
-        return Err("no Event records found".to_string());
+    /// Check a signed record's key and signature over `preimage`.
+    fn check_signature(
+        &self,
+        line_no: usize,
+        what: &str,
+        key_id: &str,
+        signature_b64: &str,
+        preimage: &[u8],
+    ) -> Result<(), String> {
+        if key_id != self.expected_key_id {
+            return Err(format!(
+                "line {line_no}: {what} key_id mismatch (expected {}, got {})",
+                self.expected_key_id, key_id
+            ));
+        }
+
+        let sig_bytes = B64
+            .decode(signature_b64)
+            .map_err(|e| format!("line {line_no}: bad signature_b64: {e}"))?;
+        if sig_bytes.len() != 64 {
+            return Err(format!("line {line_no}: signature length {} != 64", sig_bytes.len()));
+        }
+        let mut sig64 = [0u8; 64];
+        sig64.copy_from_slice(&sig_bytes);
+        let sig = Signature::from_bytes(&sig64);
+
+        self.vk
+            .verify_strict(preimage, &sig)
+            .map_err(|e| format!("line {line_no}: signature verify failed: {e}"))
     }
-    if checkpoints_verified == 0 {
-        return Err("no Checkpoint records found (did you set checkpoint interval too high?)".to_string());
+
+    /// Whole-file requirements, checked once the end of the log is reached.
+    pub fn finish(&self) -> Result<(), String> {
+        // A run without events is only complete with its start and end checkpoints;
+        // both are bound to the chain tip like any other checkpoint.
+        if self.events_verified == 0 && self.checkpoints_verified < 2 {
+            return Err("no Event records found (and no start and end checkpoints)".to_string());
+        }
+        if self.checkpoints_verified == 0 {
+            return Err("no Checkpoint records found (did you set checkpoint interval too high?)".to_string());
+        }
+        Ok(())
     }
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// `format` of an inclusion proof written by `sentinel prove`.
+pub const FORMAT_INCLUSION_PROOF: &str = "sentinel-inclusion-proof-v1";
+
+/// Proof that one event is in a log, checkable with only the public key: the
+/// event record, its audit path to the Merkle root of the checkpoint that
+/// covers it, and that (signed) checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub format: String,
+    pub event: AuditRecord,
+    /// Position of the event among the checkpoint's leaves
+    pub leaf_index: u64,
+    /// Sibling hashes from the leaf up to the root
+    pub path_b64: Vec<String>,
+    pub checkpoint: AuditRecord,
+}
+
+/// Build an inclusion proof for `event_id` from a plaintext log. The event must
+/// be covered by a version 2 (Merkle) checkpoint.
+pub fn make_inclusion_proof(log: impl Read, event_id: u64) -> Result<InclusionProof, String> {
+
+    // Entry hashes since the last checkpoint, and the wanted event once seen
+    let mut leaves: Vec<[u8; 32]> = Vec::new();
+    let mut found: Option<(AuditRecord, usize)> = None;
+
+    for (i, line) in BufReader::new(log).lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: read error: {e}", i + 1))?;
+        let line = normalize_line(&line);
+        if line.is_empty() || (i == 0 && parse_file_header(line)?.is_some()) {
+            continue;
+        }
+        let rec: AuditRecord =
+            serde_json::from_str(line).map_err(|e| format!("line {}: JSON parse error: {e}", i + 1))?;
+        match &rec {
+            AuditRecord::Event { log, integrity } => {
+                let hash = decode_b64_32(&integrity.entry_hash_b64)
+                    .map_err(|e| format!("line {}: bad entry_hash_b64: {e}", i + 1))?;
+                if log.event_id == event_id {
+                    found = Some((rec.clone(), leaves.len()));
+                }
+                leaves.push(hash);
+            }
+            AuditRecord::Checkpoint { version, .. } => {
+                if let Some((event, index)) = found.take() {
+                    if *version < 2 {
+                        return Err(format!(
+                            "event {event_id} is covered by a version {version} checkpoint, which has no Merkle root"
+                        ));
+                    }
+                    let path = crate::merkle::inclusion_path(&leaves, index);
+                    return Ok(InclusionProof {
+                        format: FORMAT_INCLUSION_PROOF.to_string(),
+                        event,
+                        leaf_index: index as u64,
+                        path_b64: path.iter().map(encode_b64_32).collect(),
+                        checkpoint: rec,
+                    });
+                }
+                leaves.clear();
+            }
+            AuditRecord::GapMarker { integrity, .. } => {
+                leaves.push(
+                    decode_b64_32(&integrity.entry_hash_b64)
+                        .map_err(|e| format!("line {}: bad entry_hash_b64: {e}", i + 1))?,
+                );
+            }
+            AuditRecord::RunBoundary { .. } | AuditRecord::SegmentHeader { .. } => leaves.clear(),
+            AuditRecord::FileHeader { .. }
+            | AuditRecord::Migration { .. }
+            | AuditRecord::TimestampProof { .. }
+            | AuditRecord::WitnessAck { .. } => {}
+        }
+    }
+
+    match found {
+        Some(_) => Err(format!("event {event_id} is not covered by a checkpoint yet")),
+        None => Err(format!("event {event_id} not found in the log")),
+    }
+}
+
+/// Check an inclusion proof against the public key alone: the event's entry
+/// hash, its audit path to the checkpoint's Merkle root, and the checkpoint
+/// signature. Returns the proven event id.
+pub fn verify_inclusion_proof(proof: &InclusionProof, pubkey: &str) -> Result<u64, String> {
+    if proof.format != FORMAT_INCLUSION_PROOF {
+        return Err(format!("unrecognized proof format {:?}", proof.format));
+    }
+    let vk = load_verify_key_b64(pubkey)?;
+    let verifier = ChainVerifier::new(vk);
+
+    let AuditRecord::Event { log, integrity } = &proof.event else {
+        return Err("proof event is not an Event record".to_string());
+    };
+    let AuditRecord::Checkpoint {
+        run_id,
+        last_event_id,
+        signature_b64,
+        key_id,
+        merkle_root_b64: Some(root_b64),
+        merkle_leaves: Some(leaves),
+        ..
+    } = &proof.checkpoint
+    else {
+        return Err("proof checkpoint is not a Checkpoint record with a Merkle root".to_string());
+    };
+    let Some((version, body)) = CheckpointBody::from_record(&proof.checkpoint)? else {
+        unreachable!("a Checkpoint record has a checkpoint body")
+    };
+
+    let prev = decode_b64_32(&integrity.prev_hash_b64).map_err(|e| format!("bad prev_hash_b64: {e}"))?;
+    let entry_hash = compute_entry_hash(&prev, log, integrity.version, integrity.write_offset)?;
+    if encode_b64_32(&entry_hash) != integrity.entry_hash_b64 {
+        return Err("event entry_hash does not match its content".to_string());
+    }
+    if log.run_id != *run_id {
+        return Err(format!("event run_id {} does not match checkpoint run_id {}", log.run_id, run_id));
+    }
+    // Every leaf after it takes at least one event id (a gap marker takes several).
+    if proof.leaf_index >= *leaves || log.event_id + (leaves - proof.leaf_index) > last_event_id + 1 {
+        return Err(format!(
+            "event {} is not leaf {} of the {} events up to event {}",
+            log.event_id, proof.leaf_index, leaves, last_event_id
+        ));
+    }
+
+    let path = proof
+        .path_b64
+        .iter()
+        .map(|p| decode_b64_32(p).map_err(|e| format!("bad path hash: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let root = crate::merkle::root_from_path(&entry_hash, proof.leaf_index, *leaves, &path)?;
+    if encode_b64_32(&root) != *root_b64 {
+        return Err("audit path does not lead to the checkpoint's Merkle root".to_string());
+    }
+
+    let pre = body.preimage(version)?;
+    verifier.check_signature(0, "checkpoint", key_id, signature_b64, &pre)?;
+    if let Some(sig) = &integrity.signature_b64 {
+        verifier.check_signature(0, "event", &verifier.expected_key_id, sig, &event_signature_preimage(&entry_hash))?;
+    }
+    Ok(log.event_id)
+}
+
+/// Check a Checkpoint record's key and signature on their own, without the chain
+/// it closes. Returns the record's last_event_id and the chain tip it signs.
+pub fn verify_checkpoint_record(rec: &AuditRecord, vk: VerifyingKey) -> Result<(u64, [u8; 32]), String> {
+    let AuditRecord::Checkpoint { key_id, signature_b64, .. } = rec else {
+        return Err("not a Checkpoint record".to_string());
+    };
+    let Some((version, body)) = CheckpointBody::from_record(rec)? else {
+        unreachable!("a Checkpoint record has a checkpoint body")
+    };
+    let pre = body.preimage(version)?;
+    ChainVerifier::new(vk)
+        .check_signature(0, "checkpoint", key_id, signature_b64, &pre)
+        .map_err(|e| e.trim_start_matches("line 0: ").to_string())?;
+    Ok((body.last_event_id, body.last_entry_hash))
+}
+
+/// Options for [`verify_bytes`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyOptions {
+    /// Check each v3 record's `write_offset` against its position in the input.
+    /// Off for decrypted copies, whose offsets refer to the encrypted file.
+    pub check_offsets: bool,
+    /// Fail when a checkpoint has no timestamp proof, instead of warning
+    #[serde(default)]
+    pub require_timestamps: bool,
+    /// Also check that timestamp tokens are signed by a TSA chaining to these CAs
+    #[serde(skip)]
+    pub tsa_roots: Option<Arc<TrustRoots>>,
+    /// Keep every checkpoint record for [`VerifySummary::checkpoints`]
+    #[serde(skip)]
+    pub collect_checkpoints: bool,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            check_offsets: true,
+            require_timestamps: false,
+            tsa_roots: None,
+            collect_checkpoints: false,
+        }
+    }
+}
+
+/// Result of a one-shot verification. Serializes to the JSON handed across the
+/// FFI boundary, so field names are part of the stable interface.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub ok: bool,
+    pub error: Option<String>,
+    pub records_seen: u64,
+    pub events_verified: u64,
+    pub checkpoints_verified: u64,
+    pub last_event_id: u64,
+    pub last_signed_event_id: u64,
+    /// Problems that do not fail verification, such as missing timestamp proofs
+    pub warnings: Vec<String>,
+    /// Who wrote the log, from its version 3 checkpoints
+    pub provenance: Vec<Provenance>,
+}
+
+/// An event's id and when it was observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventMark {
+    pub event_id: u64,
+    pub observed_ts_ms: u64,
+}
+
+/// What a successful [`verify_log_file`] or [`verify_segments`] found besides
+/// the log being intact.
+#[derive(Debug, Default)]
+pub struct VerifySummary {
+    pub warnings: Vec<String>,
+    pub provenance: Vec<Provenance>,
+    pub records_seen: u64,
+    pub events_verified: u64,
+    pub checkpoints_verified: u64,
+    /// GapMarker records: where messages the audit pipeline dropped were left out
+    pub gaps: u64,
+    /// Event ids the gap markers skip, one per dropped message
+    pub missed_events: u64,
+    pub first_event: Option<EventMark>,
+    pub last_event: Option<EventMark>,
+    /// When the last checkpoint was made
+    pub last_checkpoint_ts_ms: Option<u64>,
+    /// Chain tip at the end of the log
+    pub final_entry_hash_b64: String,
+    /// Runs in the log, in order (more than one after `run --append`)
+    pub run_ids: Vec<String>,
+    /// Every checkpoint record, in order, with [`VerifyOptions::collect_checkpoints`]
+    pub checkpoints: Vec<AuditRecord>,
+}
+
+impl VerifySummary {
+    /// Continue with the summary of the segment after this one.
+    fn absorb(&mut self, next: VerifySummary) {
+        for p in next.provenance {
+            if !self.provenance.contains(&p) {
+                self.provenance.push(p);
+            }
+        }
+        self.records_seen += next.records_seen;
+        self.events_verified += next.events_verified;
+        self.checkpoints_verified += next.checkpoints_verified;
+        self.gaps += next.gaps;
+        self.missed_events += next.missed_events;
+        self.first_event = self.first_event.or(next.first_event);
+        self.last_event = next.last_event.or(self.last_event);
+        self.last_checkpoint_ts_ms = next.last_checkpoint_ts_ms.or(self.last_checkpoint_ts_ms);
+        self.final_entry_hash_b64 = next.final_entry_hash_b64;
+        for run_id in next.run_ids {
+            if self.run_ids.last() != Some(&run_id) {
+                self.run_ids.push(run_id);
+            }
+        }
+        self.checkpoints.extend(next.checkpoints);
+    }
+}
+
+impl VerifyReport {
+    pub fn failed(error: String) -> Self {
+        Self {
+            ok: false,
+            error: Some(error),
+            records_seen: 0,
+            events_verified: 0,
+            checkpoints_verified: 0,
+            last_event_id: 0,
+            last_signed_event_id: 0,
+            warnings: Vec::new(),
+            provenance: Vec::new(),
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), String> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Verify a complete audit log read from `log`. Synchronous and self-contained
+/// (no runtime, no files besides the reader), for scripting and FFI callers.
+/// `pubkey_b64` is the base64 Ed25519 public key itself, not a path. A zstd
+/// stream is decompressed first.
+#[cfg_attr(not(feature = "ffi"), allow(dead_code))]
+pub fn verify_bytes(log: impl Read, pubkey_b64: &str, options: &VerifyOptions) -> VerifyReport {
+    let vk = match decode_b64_32(pubkey_b64.trim())
+        .and_then(|pk| VerifyingKey::from_bytes(&pk).map_err(|e| format!("invalid verifying key: {e}")))
+    {
+        Ok(vk) => vk,
+        Err(e) => return VerifyReport::failed(format!("verifying key: {e}")),
+    };
+    match decompressed(log) {
+        Ok(log) => verify_reader(&mut ChainVerifier::new(vk), log, options),
+        Err(e) => VerifyReport::failed(format!("failed to read log: {e}")),
+    }
+}
+
+/// Verify an audit JSONL file.
+/// - Validates the hash chain across all Event records
+/// - Validates signatures on Checkpoint and RunBoundary records, and on events
+///   that carry one
+/// - Allows `run_id` to change only at a RunBoundary (appended runs)
+/// - Accepts a rotated segment on its own, trusting its signed SegmentHeader for
+///   the chain tip it starts from (see [`verify_segments`] for the whole set)
+pub fn verify_audit_log_file(log_path: impl AsRef<Path>, pubkey: &str) -> Result<VerifySummary, String> {
+    verify_log_file(log_path.as_ref(), pubkey, &VerifyOptions::default())
+}
+
+
+/// Verify a rotated log: every segment on its own, then that each one continues
+/// the chain tip of the segment before it. `segments` pairs each segment's path
+/// (as from [`segment_paths`]) with its records, decrypted on the fly if the
+/// segment is encrypted.
+/// Timestamp proofs may land in the segment after their checkpoint; the
+/// warnings are for the set as a whole.
+pub fn verify_segments(
+    segments: Vec<(PathBuf, crate::audit_crypto::PlaintextLog)>,
+    vk: VerifyingKey,
+    options: &VerifyOptions,
+) -> Result<VerifySummary, String> {
+    let mut previous: Option<(String, ChainTip)> = None;
+    let mut stamps = TimestampTally::default();
+    let mut summary = VerifySummary::default();
+
+    for (n, (path, plaintext)) in segments.into_iter().enumerate() {
+        let segment_options = VerifyOptions {
+            check_offsets: !plaintext.offsets_checked,
+            ..options.clone()
+        };
+        let mut verifier = ChainVerifier::new(vk);
+        let end_offset = walk_lines(&mut verifier, plaintext.reader, &segment_options)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        stamps.absorb(std::mem::take(&mut verifier.stamps));
+        summary.absorb(verifier.summary());
+
+        match (&previous, &verifier.segment) {
+            (None, None) => {}
+            (None, Some(link)) => {
+                return Err(format!(
+                    "{}: is segment {} of a rotated log; verify from its first file",
+                    path.display(),
+                    link.segment
+                ))
+            }
+            (Some(_), None) => return Err(format!("{}: has no SegmentHeader", path.display())),
+            (Some((prev_name, prev_tip)), Some(link)) => link
+                .check_continues(n as u32, prev_name, prev_tip)
+                .map_err(|e| format!("{}: {e}", path.display()))?,
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        previous = Some((name, verifier.tip(end_offset)));
+    }
+    summary.warnings = stamps.warnings(options)?;
+    Ok(summary)
+}
+
+/// Verify the log at `log_path` with `options`.
+pub fn verify_log_file(log_path: &Path, pubkey: &str, options: &VerifyOptions) -> Result<VerifySummary, String> {
+    verify_log(open_log(log_path)?, pubkey, options)
+}
+
+/// [`verify_log_file`] on the lines of `log`, e.g. an encrypted log decrypted as
+/// it is read.
+pub fn verify_log(log: impl Read, pubkey: &str, options: &VerifyOptions) -> Result<VerifySummary, String> {
+    let vk = load_verify_key_b64(pubkey)?;
+    let mut verifier = ChainVerifier::new(vk);
+    let report = verify_reader(&mut verifier, log, options);
+    let warnings = report.warnings.clone();
+    report.into_result()?;
+    Ok(VerifySummary { warnings, ..verifier.summary() })
+}
+
+fn verify_reader(verifier: &mut ChainVerifier, log: impl Read, options: &VerifyOptions) -> VerifyReport {
+    let result = walk_lines(verifier, log, options).and_then(|_| verifier.stamps.warnings(options));
+    let (error, warnings) = match result {
+        Ok(warnings) => (None, warnings),
+        Err(e) => (Some(e), Vec::new()),
+    };
+
+    let cursor = verifier.cursor();
+    VerifyReport {
+        ok: error.is_none(),
+        error,
+        records_seen: cursor.records_seen,
+        events_verified: cursor.events_verified,
+        checkpoints_verified: cursor.checkpoints_verified,
+        last_event_id: cursor.last_event_id,
+        last_signed_event_id: cursor.last_signed_event_id,
+        warnings,
+        provenance: verifier.provenance.clone(),
+    }
+}
+
+/// Feed every line of `log` to `verifier` and check the whole-file
+/// requirements. Returns the number of bytes read.
+fn walk_lines(verifier: &mut ChainVerifier, log: impl Read, options: &VerifyOptions) -> Result<u64, String> {
+    verifier.tsa_roots = options.tsa_roots.clone();
+    if options.collect_checkpoints {
+        verifier.checkpoints.get_or_insert_with(Vec::new);
+    }
+    let mut reader = BufReader::new(log);
+    let mut offset = 0u64;
+    let mut buf = Vec::new();
+    for line_no in 1.. {
+        buf.clear();
+        let n = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| format!("line {line_no}: read error: {e}"))?;
+        if n == 0 {
+            break;
+        }
+        let line = std::str::from_utf8(&buf).map_err(|_| format!("line {line_no}: not valid UTF-8"))?;
+        verifier.verify_line(line_no, options.check_offsets.then_some(offset), line)?;
+        offset += n as u64;
+    }
+    verifier.finish()?;
+    Ok(offset)
+}
+
+/// Writes plaintext audit logs record by record, the way `run` does, for tests
+/// that need a log to read while it grows.
+#[cfg(test)]
+pub mod test_log {
+    use super::*;
+    use std::io::Write;
+
+    pub const RUN_ID: &str = "test-run";
+
+    pub struct LogWriter {
+        /// The log itself; rotated segments derive from it
+        pub first: PathBuf,
+        out: fs::File,
+        offset: u64,
+        segment: u32,
+        chain: AuditChain,
+        key: SigningKey,
+    }
+
+    impl LogWriter {
+        /// A new log with its header and the start checkpoint.
+        pub fn create(first: &Path) -> Self {
+            let header = serde_json::to_string(&make_file_header(FORMAT_AUDIT)).unwrap();
+            Self::with_header(first, Some(&header))
+        }
+
+        /// A new log whose first line is `header` as given (none for a legacy
+        /// log), then the start checkpoint.
+        pub fn with_header(first: &Path, header: Option<&str>) -> Self {
+            let mut writer = Self {
+                first: first.to_path_buf(),
+                out: fs::File::create(first).unwrap(),
+                offset: 0,
+                segment: 0,
+                chain: AuditChain::new(None),
+                key: SigningKey::from_bytes(&[7; 32]),
+            };
+            if let Some(header) = header {
+                writer.line(header);
+            }
+            writer.checkpoint();
+            writer
+        }
+
+        /// The public key, as a file `verify` and `watch-verify` take.
+        pub fn write_pubkey(&self, path: &Path) {
+            fs::write(path, B64.encode(self.key.verifying_key().to_bytes())).unwrap();
+        }
+
+        pub fn line(&mut self, line: &str) {
+            self.out.write_all(line.as_bytes()).unwrap();
+            self.out.write_all(b"\n").unwrap();
+            self.out.flush().unwrap();
+            self.offset += line.len() as u64 + 1;
+        }
+
+        fn record(&mut self, rec: &AuditRecord) {
+            self.line(&serde_json::to_string(rec).unwrap());
+        }
+
+        fn prepare(&self, method: &str) -> PreparedEvent {
+            let mut log = McpLog::synthetic(
+                RUN_ID.to_string(),
+                method,
+                serde_json::json!({"n": self.chain.last_event_id() + 1}),
+                "session",
+                "trace",
+                1_000 + self.chain.last_event_id(),
+                "span".to_string(),
+            );
+            self.chain.prepare(&mut log, self.offset).unwrap()
+        }
+
+        /// Append the next event of the chain.
+        pub fn event(&mut self, method: &str) {
+            let prepared = self.prepare(method);
+            self.record(&prepared.record);
+            if let AuditRecord::Event { log, .. } = &prepared.record {
+                self.chain.commit(log.event_id, prepared.entry_hash);
+            }
+        }
+
+        /// Append `log` as the next event of the chain.
+        pub fn log(&mut self, mut log: McpLog) {
+            let prepared = self.chain.prepare(&mut log, self.offset).unwrap();
+            self.record(&prepared.record);
+            self.chain.commit(log.event_id, prepared.entry_hash);
+        }
+
+        /// Append the next event with its payload changed after it was hashed.
+        pub fn tampered_event(&mut self, method: &str) {
+            let mut prepared = self.prepare(method);
+            if let AuditRecord::Event { log, .. } = &mut prepared.record {
+                log.payload = serde_json::json!({"n": "changed"});
+            }
+            self.record(&prepared.record);
+        }
+
+        /// Append the GapMarker for `missed` dropped inbound messages.
+        pub fn gap(&mut self, missed: u64) {
+            let mut notice = McpLog::synthetic(
+                RUN_ID.to_string(),
+                crate::tap::TAP_GAP_METHOD,
+                serde_json::json!({"first_seq": 10, "last_seq": 9 + missed, "dropped": {"Inbound": missed}}),
+                "session",
+                "trace",
+                1_000 + self.chain.last_event_id(),
+                String::new(),
+            );
+            let prepared = self.chain.prepare(&mut notice, self.offset).unwrap();
+            self.record(&prepared.record);
+            self.chain.commit(notice.event_id, prepared.entry_hash);
+        }
+
+        /// Skip `n` event ids without a record of it.
+        pub fn skip(&mut self, n: u64) {
+            self.chain.last_event_id += n;
+        }
+
+        pub fn checkpoint(&mut self) {
+            let cp = self.chain.checkpoint(&self.key, RUN_ID, 2_000).unwrap();
+            self.record(&cp);
+        }
+
+        /// Close the current segment with a checkpoint and continue the chain
+        /// in the next one.
+        pub fn rotate(&mut self) {
+            self.checkpoint();
+            let previous = match self.segment {
+                0 => self.first.clone(),
+                n => segment_path(&self.first, n),
+            };
+            let previous = previous.file_name().unwrap().to_string_lossy().into_owned();
+            self.segment += 1;
+            self.out = fs::File::create(segment_path(&self.first, self.segment)).unwrap();
+            self.offset = 0;
+            self.record(&make_file_header(FORMAT_AUDIT));
+            let header = self.chain.segment_header(&self.key, RUN_ID, self.segment, &previous, 3_000).unwrap();
+            self.record(&header);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_log::LogWriter;
+    use super::*;
+
+    /// A log written by the first release: no header, v1 events and checkpoints.
+    const V1_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/v1");
+    /// The v1 log as Windows tooling leaves it: a BOM and CRLF line endings.
+    const CRLF_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/crlf");
+    /// A v3 log (`tests/fixtures/rederive`) with bytes injected between records.
+    const PADDING_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/padding");
+
+    fn header(format: &str) -> String {
+        serde_json::to_string(&make_file_header(format)).unwrap()
+    }
+
+    /// A log of two events and a closing checkpoint under `first_line`, or
+    /// with `first_line` added after the start checkpoint when `later`.
+    fn verify_with(first_line: Option<&str>, later: Option<&str>) -> Result<VerifySummary, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let mut writer = LogWriter::with_header(&path, first_line);
+        if let Some(line) = later {
+            writer.line(line);
+        }
+        writer.event("tools/list");
+        writer.event("tools/call");
+        writer.checkpoint();
+        let pubkey = dir.path().join("pub.b64");
+        writer.write_pubkey(&pubkey);
+        verify_log_file(&path, pubkey.to_str().unwrap(), &VerifyOptions::default())
+    }
+
+    #[test]
+    fn current_and_legacy_logs_verify() {
+        let current = header(FORMAT_AUDIT);
+        let legacy = header(LEGACY_FORMAT_AUDIT);
+        for (name, first_line) in [("current", Some(current.as_str())), ("v2 header", Some(&legacy)), ("no header", None)] {
+            let summary = verify_with(first_line, None).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!((summary.events_verified, summary.checkpoints_verified), (2, 2), "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_corrupt_or_foreign_header_is_refused() {
+        let fixtures = [
+            (r#"{"record_type":"FileHeader","format":"sentinel-audit-v3"}"#, "line 1: corrupt FileHeader record"),
+            (
+                r#"{"record_type":"FileHeader","format":"sentinel-audit-v3","crate_version":"0.1.0","created_ts_ms":"soon"}"#,
+                "line 1: corrupt FileHeader record",
+            ),
+            (
+                r#"{"record_type":"FileHeader","format":"other-tool-v1","crate_version":"0.1.0","created_ts_ms":1}"#,
+                "line 1: unrecognized file format \"other-tool-v1\"",
+            ),
+            (
+                r#"{"record_type":"FileHeader","format":"sentinel-audit-encrypted-v3","crate_version":"0.1.0","created_ts_ms":1}"#,
+                "line 1: file format is sentinel-audit-encrypted-v3, expected sentinel-audit-v3 (decrypt encrypted logs first)",
+            ),
+            (
+                r#"{"record_type":"FileHeader","format":"sentinel-capture-v1","crate_version":"0.1.0","created_ts_ms":1}"#,
+                "line 1: file format is sentinel-capture-v1",
+            ),
+            (r#"{"record_type":"FileHeader","format":"sentin"#, "line 1: JSON parse error"),
+        ];
+        for (first_line, expected) in fixtures {
+            let err = verify_with(Some(first_line), None).unwrap_err();
+            assert!(err.starts_with(expected), "{}: {}", first_line, err);
+        }
+    }
+
+    #[test]
+    fn a_header_after_the_first_line_is_refused() {
+        let err = verify_with(Some(&header(FORMAT_AUDIT)), Some(&header(FORMAT_AUDIT))).unwrap_err();
+        assert!(err.starts_with("line 3: FileHeader is only allowed on the first line"), "{}", err);
+    }
+
+    #[test]
+    fn only_a_header_record_is_read_as_one() {
+        assert_eq!(parse_file_header(&header(FORMAT_AUDIT)), Ok(Some(FORMAT_AUDIT.to_string())));
+        assert_eq!(parse_file_header(&format!("{}\r\n", header(FORMAT_AUDIT))), Ok(Some(FORMAT_AUDIT.to_string())));
+        // A legacy log starts right away with a record, or with anything else.
+        assert_eq!(parse_file_header(r#"{"record_type":"Checkpoint","run_id":"r"}"#), Ok(None));
+        assert_eq!(parse_file_header("not json"), Ok(None));
+        assert_eq!(parse_file_header(""), Ok(None));
+    }
+
+    #[test]
+    fn a_gap_in_event_ids_verifies_only_with_its_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let pubkey = dir.path().join("pub.b64");
+        let write = |name: &str, gap: &dyn Fn(&mut LogWriter)| {
+            let path = dir.path().join(name);
+            let mut writer = LogWriter::create(&path);
+            writer.event("tools/list");
+            gap(&mut writer);
+            writer.event("tools/call");
+            writer.checkpoint();
+            writer.write_pubkey(&pubkey);
+            path
+        };
+        let verify = |path: &Path| verify_log_file(path, pubkey.to_str().unwrap(), &VerifyOptions::default());
+
+        let marked = write("marked.jsonl", &|w| w.gap(3));
+        let summary = verify(&marked).unwrap();
+        assert_eq!((summary.events_verified, summary.gaps, summary.missed_events), (2, 1, 3));
+        assert_eq!(summary.last_event.map(|e| e.event_id), Some(5));
+        // The marker is a leaf of the checkpoint's tree, so the events around it still prove.
+        for event_id in [1, 5] {
+            let proof = make_inclusion_proof(fs::File::open(&marked).unwrap(), event_id).unwrap();
+            assert_eq!(verify_inclusion_proof(&proof, pubkey.to_str().unwrap()), Ok(event_id));
+        }
+
+        let unmarked = write("unmarked.jsonl", &|w| w.skip(3));
+        let err = verify(&unmarked).unwrap_err();
+        assert!(err.starts_with("line 4: event_id not contiguous (prev 1, got 5)"), "{}", err);
+
+        // The marker is hashed like an event: its counts can't be changed.
+        let text = fs::read_to_string(&marked).unwrap();
+        assert!(text.contains(r#""dropped":{"Inbound":3}"#));
+        fs::write(&marked, text.replace(r#""dropped":{"Inbound":3}"#, r#""dropped":{"Inbound":1}"#)).unwrap();
+        let err = verify(&marked).unwrap_err();
+        assert!(err.starts_with("line 4: entry_hash mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn a_v1_log_verifies() {
+        let dir = Path::new(V1_FIXTURE);
+        let text = fs::read_to_string(dir.join("audit.jsonl")).unwrap();
+        for line in text.lines() {
+            match serde_json::from_str(line).unwrap() {
+                AuditRecord::Event { integrity, .. } => assert_eq!((integrity.version, integrity.write_offset), (1, None)),
+                AuditRecord::Checkpoint { version, .. } => assert_eq!(version, 1),
+                other => panic!("not in a v1 log: {:?}", other),
+            }
+        }
+
+        let pubkey = dir.join("pubkey.b64");
+        let summary =
+            verify_log_file(&dir.join("audit.jsonl"), pubkey.to_str().unwrap(), &VerifyOptions::default()).unwrap();
+        assert_eq!(summary.events_verified, 9);
+        assert_eq!(summary.checkpoints_verified, 3);
+    }
+
+    #[test]
+    fn an_edited_v1_event_fails_its_hash() {
+        let text = fs::read_to_string(Path::new(V1_FIXTURE).join("audit.jsonl")).unwrap();
+        assert!(text.contains("chain of custody"));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        fs::write(&path, text.replacen("chain of custody", "chain of command", 1)).unwrap();
+
+        let pubkey = Path::new(V1_FIXTURE).join("pubkey.b64");
+        let err = verify_log_file(&path, pubkey.to_str().unwrap(), &VerifyOptions::default()).unwrap_err();
+        assert!(err.starts_with("line 6: entry_hash mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn a_v1_log_with_a_bom_and_crlf_line_endings_verifies() {
+        let dir = Path::new(CRLF_FIXTURE);
+        let bytes = fs::read(dir.join("audit.jsonl")).unwrap();
+        assert!(bytes.starts_with(b"\xEF\xBB\xBF") && bytes.ends_with(b"\r\n"));
+
+        let pubkey = dir.join("pubkey.b64");
+        let summary =
+            verify_log_file(&dir.join("audit.jsonl"), pubkey.to_str().unwrap(), &VerifyOptions::default()).unwrap();
+        assert_eq!(summary.events_verified, 9);
+        assert_eq!(summary.checkpoints_verified, 3);
+    }
+
+    /// A v3 log pins its layout: padding between records fails verification
+    /// at the padded line, whether or not offsets are checked.
+    #[test]
+    fn padding_between_v3_records_is_refused() {
+        let pubkey = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rederive/pubkey.b64");
+        let untouched = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rederive/audit.jsonl");
+        verify_log_file(Path::new(untouched), pubkey, &VerifyOptions::default()).unwrap();
+
+        for (fixture, expected) in [
+            ("blank_line.jsonl", "line 4: unexpected blank line between records"),
+            ("crlf.jsonl", "line 3: unexpected bytes around record"),
+            ("leading_space.jsonl", "line 4: unexpected bytes around record"),
+            ("trailing_space.jsonl", "line 4: unexpected bytes around record"),
+            ("duplicate_line.jsonl", "line 5: prev_hash mismatch"),
+        ] {
+            let path = Path::new(PADDING_FIXTURE).join(fixture);
+            for check_offsets in [true, false] {
+                let options = VerifyOptions { check_offsets, ..VerifyOptions::default() };
+                let err = verify_log_file(&path, pubkey, &options).unwrap_err();
+                assert!(err.starts_with(expected), "{} (check_offsets {}): {}", fixture, check_offsets, err);
+            }
+        }
+
+        // Whitespace inside a record keeps its hash and layout but moves every
+        // record after it, which only the recorded offsets show.
+        let path = Path::new(PADDING_FIXTURE).join("inner_whitespace.jsonl");
+        let err = verify_log_file(&path, pubkey, &VerifyOptions::default()).unwrap_err();
+        assert!(err.starts_with("line 4: record written at byte 1869 but found at byte 1870"), "{}", err);
+        let options = VerifyOptions { check_offsets: false, ..VerifyOptions::default() };
+        verify_log_file(&path, pubkey, &options).unwrap();
+    }
+
+    /// A sign-only signer, like a KMS key: it signs what it is given and never
+    /// hands out its private key.
+    struct RemoteSigner {
+        key: SigningKey,
+        signed: std::sync::atomic::AtomicUsize,
+        reachable: bool,
+    }
+
+    impl CheckpointSigner for RemoteSigner {
+        fn verifying_key(&self) -> VerifyingKey {
+            self.key.verifying_key()
+        }
+
+        fn sign_checkpoint(&self, preimage: &[u8]) -> Result<Signature, String> {
+            if !self.reachable {
+                return Err("signing service unreachable".to_string());
+            }
+            self.signed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(self.key.sign(preimage))
+        }
+    }
+
+    #[test]
+    fn checkpoints_signed_through_a_sign_only_signer_verify() {
+        let signer = RemoteSigner { key: SigningKey::from_bytes(&[9; 32]), signed: Default::default(), reachable: true };
+        let mut chain = AuditChain::new(None);
+        let mut text = header(FORMAT_AUDIT) + "\n";
+        let line = |text: &mut String, record: &AuditRecord| {
+            *text += &serde_json::to_string(record).unwrap();
+            text.push('\n');
+        };
+        line(&mut text, &chain.checkpoint(&signer, "run", 1_000).unwrap());
+        for n in 0..3 {
+            let payload = serde_json::json!({ "n": n });
+            let mut log = McpLog::synthetic("run".into(), "tools/list", payload, "session", "trace", 1_000, "span".into());
+            let prepared = chain.prepare(&mut log, text.len() as u64).unwrap();
+            line(&mut text, &prepared.record);
+            chain.commit(log.event_id, prepared.entry_hash);
+        }
+        line(&mut text, &chain.checkpoint(&signer, "run", 2_000).unwrap());
+        assert_eq!(signer.signed.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (path, pubkey) = (dir.path().join("audit.jsonl"), dir.path().join("pub.b64"));
+        fs::write(&path, text).unwrap();
+        fs::write(&pubkey, B64.encode(signer.verifying_key().to_bytes())).unwrap();
+        let summary = verify_log_file(&path, pubkey.to_str().unwrap(), &VerifyOptions::default()).unwrap();
+        assert_eq!((summary.events_verified, summary.checkpoints_verified), (3, 2));
+
+        let unreachable = RemoteSigner { reachable: false, ..signer };
+        let key_id = key_id_from_pubkey(&unreachable.verifying_key());
+        assert_eq!(
+            chain.checkpoint(&unreachable, "run", 3_000).unwrap_err(),
+            format!("checkpoint signing failed (key_id {}): signing service unreachable", key_id)
+        );
+    }
+}
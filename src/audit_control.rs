@@ -0,0 +1,192 @@
+use crate::audit::{self, VerifyOptions, VerifySummary};
+use crate::audit_crypto;
+
+use ed25519_dalek::VerifyingKey;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use zeroize::Zeroizing;
+
+/// What the server asks of the audit writer, answered between two records.
+pub enum AuditRequest {
+    /// Flush what is written so far and say where the log ends.
+    Flush(oneshot::Sender<Result<FlushedLog, String>>),
+    /// Record that a checkpoint was asked for, sign the chain tip if the log is
+    /// signed, and sync.
+    Checkpoint(oneshot::Sender<Result<ForcedCheckpoint, String>>),
+}
+
+/// Least time between two checkpoints asked for through the API.
+const FORCED_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The chain tip a forced checkpoint ended on, synced to disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct ForcedCheckpoint {
+    pub last_event_id: u64,
+    pub last_entry_hash_b64: String,
+    /// A Checkpoint record signs the tip (false when the log is not signed)
+    pub signed: bool,
+}
+
+/// The audit log as far as it was flushed: the segment being written and how
+/// many of its bytes hold whole records.
+#[derive(Debug, Clone)]
+pub struct FlushedLog {
+    /// 0 is `--audit-log` itself
+    pub segment: u32,
+    pub path: PathBuf,
+    pub len: u64,
+}
+
+#[derive(Debug)]
+pub enum AuditControlError {
+    /// No audit log is written (`replay`), or no such segment
+    NotFound(String),
+    /// Why the log can't be verified while it is written
+    Unverifiable(String),
+    /// A checkpoint was forced too recently; try again after this long
+    RateLimited(Duration),
+    Failed(String),
+}
+
+impl From<String> for AuditControlError {
+    fn from(e: String) -> Self {
+        Self::Failed(e)
+    }
+}
+
+/// The server's handle on the audit log being written, for `/api/audit/*`.
+#[derive(Default)]
+pub struct AuditControl {
+    requests: Option<mpsc::Sender<AuditRequest>>,
+    /// `--audit-log`, which rotated segments are named after
+    base: PathBuf,
+    /// Checks the checkpoints; `None` when the log is not signed
+    verifying_key: Option<VerifyingKey>,
+    /// Recipient private key of an envelope-encrypted log
+    recipient_sk: Option<Zeroizing<[u8; 32]>>,
+    unverifiable: Option<String>,
+    /// When the last checkpoint was forced
+    last_forced: Mutex<Option<Instant>>,
+}
+
+impl AuditControl {
+    /// A handle on the log written at `base`, and the requests for its writer.
+    pub fn new(base: PathBuf) -> (Self, mpsc::Receiver<AuditRequest>) {
+        let (tx, rx) = mpsc::channel(16);
+        (Self { requests: Some(tx), base, ..Self::default() }, rx)
+    }
+
+    pub fn with_verifying_key(mut self, vk: Option<VerifyingKey>) -> Self {
+        self.verifying_key = vk;
+        self
+    }
+
+    pub fn with_recipient_key(mut self, sk: Option<Zeroizing<[u8; 32]>>) -> Self {
+        self.recipient_sk = sk;
+        self
+    }
+
+    /// The log can't be read back while it is written, for `reason`.
+    pub fn with_unreadable_log(mut self, reason: String) -> Self {
+        self.unverifiable = Some(reason);
+        self
+    }
+
+    /// Have the writer flush the log; where it ends then.
+    pub async fn flush(&self) -> Result<FlushedLog, AuditControlError> {
+        let requests = self.requests()?;
+        let (tx, rx) = oneshot::channel();
+        let stopped = || AuditControlError::Failed("the audit writer has stopped".to_string());
+        requests.send(AuditRequest::Flush(tx)).await.map_err(|_| stopped())?;
+        Ok(rx.await.map_err(|_| stopped())??)
+    }
+
+    /// Have the writer checkpoint and sync the log now, at most once every
+    /// [`FORCED_CHECKPOINT_INTERVAL`].
+    pub async fn checkpoint(&self) -> Result<ForcedCheckpoint, AuditControlError> {
+        let requests = self.requests()?;
+        {
+            let mut last = self.last_forced.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(wait) = last.and_then(|at| FORCED_CHECKPOINT_INTERVAL.checked_sub(at.elapsed())) {
+                return Err(AuditControlError::RateLimited(wait));
+            }
+            *last = Some(Instant::now());
+        }
+        let (tx, rx) = oneshot::channel();
+        let unwritten = || AuditControlError::Failed("the audit writer did not write the checkpoint".to_string());
+        requests.send(AuditRequest::Checkpoint(tx)).await.map_err(|_| unwritten())?;
+        Ok(rx.await.map_err(|_| unwritten())??)
+    }
+
+    /// The file of `segment` (`None` for the one being written) and its length:
+    /// all of a rotated segment, what is flushed of the current one.
+    pub async fn segment(&self, segment: Option<u32>) -> Result<FlushedLog, AuditControlError> {
+        let current = self.flush().await?;
+        match segment {
+            None => Ok(current),
+            Some(n) if n == current.segment => Ok(current),
+            Some(n) if n < current.segment => {
+                let path = segment_file(&self.base, n);
+                let len = tokio::fs::metadata(&path)
+                    .await
+                    .map_err(|e| format!("audit log {}: {}", path.display(), e))?
+                    .len();
+                Ok(FlushedLog { segment: n, path, len })
+            }
+            Some(n) => Err(AuditControlError::NotFound(format!(
+                "no segment {} (the log is at segment {})",
+                n, current.segment
+            ))),
+        }
+    }
+
+    /// Verify the log as far as it is flushed, every segment of it.
+    pub async fn verify(&self) -> Result<(FlushedLog, VerifySummary), AuditControlError> {
+        self.requests()?;
+        if let Some(reason) = &self.unverifiable {
+            return Err(AuditControlError::Unverifiable(reason.clone()));
+        }
+        let vk = self
+            .verifying_key
+            .ok_or_else(|| AuditControlError::Unverifiable("the audit log is not signed".to_string()))?;
+        let flushed = self.flush().await?;
+        let base = self.base.clone();
+        let recipient_sk = self.recipient_sk.clone();
+
+        let tip = flushed.clone();
+        let summary = tokio::task::spawn_blocking(move || -> Result<VerifySummary, String> {
+            let mut segments = Vec::new();
+            for n in 0..=tip.segment {
+                let path = segment_file(&base, n);
+                let len = match n == tip.segment {
+                    true => tip.len,
+                    false => std::fs::metadata(&path).map_err(|e| format!("{}: {}", path.display(), e))?.len(),
+                };
+                let log = audit_crypto::open_plaintext_prefix(&path.to_string_lossy(), len, recipient_sk.as_deref())
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                segments.push((path, log));
+            }
+            audit::verify_segments(segments, vk, &VerifyOptions::default())
+        })
+        .await
+        .map_err(|e| format!("verify: {}", e))?;
+        Ok((flushed, summary?))
+    }
+
+    fn requests(&self) -> Result<&mpsc::Sender<AuditRequest>, AuditControlError> {
+        self.requests
+            .as_ref()
+            .ok_or_else(|| AuditControlError::NotFound("no audit log is written".to_string()))
+    }
+}
+
+/// The file of segment `n` of the log at `base`: `base` itself for segment 0.
+fn segment_file(base: &Path, n: u32) -> PathBuf {
+    match n {
+        0 => base.to_path_buf(),
+        n => audit::segment_path(base, n),
+    }
+}
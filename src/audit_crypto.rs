@@ -8,17 +8,23 @@ use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::fmt::Write as _;
 use std::path::{Path, PathBuf};
-use tempfile::NamedTempFile;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use x25519_dalek::{x25519, PublicKey, X25519_BASEPOINT_BYTES};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
 
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
-/// ===== Key generation =====
-pub fn keygen_recipient(out_dir: impl AsRef<Path>) -> Result<(), String> {
+use crate::audit::{
+    check_record_offset, check_strict_line, decompressed, make_file_header, normalize_line, open_log, parse_file_header,
+    FORMAT_AUDIT, FORMAT_AUDIT_ENCRYPTED, LEGACY_FORMAT_AUDIT_ENCRYPTED,
+};
+
+// ===== Key generation =====
+pub fn keygen_recipient(out_dir: impl AsRef<Path>, passphrase: Option<&str>) -> Result<(), String> {
     let out_dir = out_dir.as_ref();
     fs::create_dir_all(out_dir)
         .map_err(|e| format!("failed to create {:?}: {}", out_dir, e))?;
@@ -29,7 +35,12 @@ pub fn keygen_recipient(out_dir: impl AsRef<Path>) -> Result<(), String> {
     let pk_bytes = x25519(sk, X25519_BASEPOINT_BYTES);
     let pk = PublicKey::from(pk_bytes);
 
-    fs::write(out_dir.join("recipient_priv.b64"), format!("{}\n", B64.encode(sk)))
+    let priv_file = match passphrase {
+        Some(passphrase) => crate::keys::seal_keystore(&sk, passphrase)?,
+        None => format!("{}\n", B64.encode(sk)),
+    };
+    sk.zeroize();
+    fs::write(out_dir.join("recipient_priv.b64"), priv_file)
         .map_err(|e| format!("write recipient_priv.b64: {}", e))?;
     fs::write(
         out_dir.join("recipient_pub.b64"),
@@ -39,36 +50,37 @@ pub fn keygen_recipient(out_dir: impl AsRef<Path>) -> Result<(), String> {
 
     println!("Generated recipient encryption keys (X25519)");
     println!("  Private (KEEP SECRET): {:?}", out_dir.join("recipient_priv.b64"));
+    if passphrase.is_some() {
+        println!("  (passphrase-protected keystore)");
+    }
     println!("  Public  (DISTRIBUTE):  {:?}", out_dir.join("recipient_pub.b64"));
+    println!("  Key id: {}", recipient_key_id(pk.as_bytes()));
     Ok(())
 }
 
-/// ===== Internal helpers =====
+/// Id of an X25519 recipient public key, as named in each KeyEnvelope
+/// (`recipient_key_id`).
+pub fn recipient_key_id(pk: &[u8; 32]) -> String {
+    let h = Sha256::digest(pk);
+    hex::encode(&h[..6])
+}
 
-fn read_b64_32(path: &Path) -> Result<[u8; 32], String> {
-    let s = fs::read_to_string(path).map_err(|e| format!("read {:?}: {}", path, e))?;
-    let bytes = B64
-        .decode(s.trim())
-        .map_err(|e| format!("base64 decode {:?}: {}", path, e))?;
-    if bytes.len() != 32 {
-        return Err(format!(
-            "expected 32 bytes in {:?}, got {}",
-            path,
-            bytes.len()
-        ));
+/// Whether 32 bytes can be an X25519 public key worth encrypting to. Every
+/// string of 32 bytes is a u-coordinate, but the all-zero key, the identity and
+/// the other low-order points give a shared secret anyone can compute.
+pub fn check_recipient_pubkey(pk: &[u8; 32]) -> Result<(), String> {
+    // A clamped scalar is a multiple of the cofactor, so it sends every
+    // low-order point to zero.
+    if x25519([0x42; 32], *pk) == [0u8; 32] {
+        return Err("it is a low-order point (all-zero or identity), not a real X25519 public key".to_string());
     }
-    let mut out = [0u8; 32];
-    out.copy_from_slice(&bytes);
-    Ok(out)
+    Ok(())
 }
 
-fn key_id(pk: &[u8; 32]) -> String {
-    let h = Sha256::digest(pk);
-    hex::encode(&h[..6])
-}
+// ===== Internal helpers =====
 
 #[derive(Clone)]
-struct DataKey([u8; 32]);
+pub struct DataKey([u8; 32]);
 
 impl Drop for DataKey {
     fn drop(&mut self) {
@@ -77,14 +89,39 @@ impl Drop for DataKey {
 }
 
 impl DataKey {
-    fn random() -> Self {
+    pub fn random() -> Self {
         let mut dk = [0u8; 32];
         OsRng.fill_bytes(&mut dk);
         Self(dk)
     }
+
+    /// Encrypt `msg` under a fresh nonce; the output is `nonce || ciphertext`.
+    pub fn seal(&self, aad: &[u8], msg: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.0));
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let ct = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg, aad })
+            .map_err(|_| "encrypt failed".to_string())?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&ct);
+        Ok(out)
+    }
+
+    /// Reverse of [`DataKey::seal`].
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < 12 {
+            return Err("sealed data too short".to_string());
+        }
+        let (nonce, ct) = sealed.split_at(12);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.0));
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ct, aad })
+            .map_err(|_| "decrypt failed (bad key or tampered ciphertext)".to_string())
+    }
 }
 
-/// ===== Data structures =====
+// ===== Data structures =====
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct KeyEnvelope {
@@ -95,29 +132,144 @@ pub struct KeyEnvelope {
 
     pub recipient_key_id: String,
 
-    pub ephemeral_pubkey_b64: String,
+    #[serde(flatten)]
+    pub wrap: DekWrap,
 
-    pub wrap_nonce_b64: String,
-    pub wrapped_dek_b64: String,
+    /// The same DEK wrapped for further keys, such as the `run --escrow-pubkey-path`
+    /// escrow key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_recipients: Vec<ExtraRecipient>,
 
     pub kex_alg: String,
     pub kdf_alg: String,
     pub aead_alg: String,
+
+    /// 0 for the run's first data key, then one more per rotation; the DEK of
+    /// generation `n` seals the records that carry `key_generation: n`
+    #[serde(default, skip_serializing_if = "is_first_generation")]
+    pub key_generation: u32,
+}
+
+/// A DEK wrapped for one X25519 key under an ephemeral key of its own.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DekWrap {
+    pub ephemeral_pubkey_b64: String,
+    pub wrap_nonce_b64: String,
+    pub wrapped_dek_b64: String,
 }
 
+/// An extra entry of a KeyEnvelope.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtraRecipient {
+    /// What the key is for ("escrow")
+    pub role: String,
+    pub recipient_key_id: String,
+    #[serde(flatten)]
+    pub wrap: DekWrap,
+}
+
+/// Role of the `--escrow-pubkey-path` entry.
+const ROLE_ESCROW: &str = "escrow";
+
+/// Version of the `Encrypted` records written now. Version 2 added `seq`;
+/// version 3 builds the nonce from a prefix chosen with the sink and the seq as
+/// a counter ([`counter_nonce`]) instead of drawing it at random. Version 1 and
+/// 2 records are still decrypted, with their AAD and random nonces.
+const ENCRYPTED_RECORD_VERSION: u32 = 3;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct EncryptedRecord {
     record_type: String, // "Encrypted"
     version: u32,
     run_id: String,
     inner_type: String,
+    /// How the plaintext was compressed before sealing ("zstd"); absent when it was not
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<String>,
+    /// Which KeyEnvelope's DEK sealed the record
+    #[serde(default, skip_serializing_if = "is_first_generation")]
+    key_generation: u32,
+    /// Position among the records of the sink (version 2); strictly increasing.
+    /// From version 3 it is also the counter in the nonce.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
     nonce_b64: String,
     ciphertext_b64: String,
 }
 
-/// ===== Envelope logic =====
+/// Nonce of a version 3 record: the sink's 4-byte prefix, then the record's
+/// seq as a 64-bit little-endian counter. A sink never repeats a seq, so it never
+/// repeats a nonce under any of its data keys.
+fn counter_nonce(prefix: [u8; 4], seq: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&prefix);
+    nonce[4..].copy_from_slice(&seq.to_le_bytes());
+    nonce
+}
+
+fn is_first_generation(generation: &u32) -> bool {
+    *generation == 0
+}
 
-fn build_envelope(run_id: &str, recipient_pub: &PublicKey, dek: &DataKey) -> Result<KeyEnvelope, String> {
+/// AAD of an `Encrypted` record. It binds the record's seq (version 2), its
+/// compression and, after a DEK rotation, its key generation, so none of them
+/// can be stripped or changed without failing authentication. Version 1 records
+/// have no seq and keep their shorter AAD.
+fn record_aad(
+    run_id: &str,
+    inner_type: &str,
+    seq: Option<u64>,
+    compression: Option<&str>,
+    key_generation: u32,
+) -> String {
+    match (seq, compression, key_generation) {
+        (Some(seq), c, g) => format!("{}|{}|{}|{}|{}", run_id, inner_type, seq, c.unwrap_or(""), g),
+        (None, Some(c), 0) => format!("{}|{}|{}", run_id, inner_type, c),
+        (None, None, 0) => format!("{}|{}", run_id, inner_type),
+        (None, c, g) => format!("{}|{}|{}|{}", run_id, inner_type, c.unwrap_or(""), g),
+    }
+}
+
+/// AAD of a wrapped DEK: the run, and the key generation after a rotation.
+fn wrap_aad(run_id: &str, key_generation: u32) -> String {
+    match key_generation {
+        0 => run_id.to_string(),
+        g => format!("{}|{}", run_id, g),
+    }
+}
+
+// ===== Envelope logic =====
+
+fn build_envelope(
+    run_id: &str,
+    recipient_pub: &PublicKey,
+    escrow_pub: Option<&PublicKey>,
+    dek: &DataKey,
+    key_generation: u32,
+) -> Result<KeyEnvelope, String> {
+    let extra_recipients = match escrow_pub {
+        Some(escrow_pub) => vec![ExtraRecipient {
+            role: ROLE_ESCROW.into(),
+            recipient_key_id: recipient_key_id(escrow_pub.as_bytes()),
+            wrap: wrap_dek(run_id, escrow_pub, dek, key_generation)?,
+        }],
+        None => Vec::new(),
+    };
+    Ok(KeyEnvelope {
+        record_type: "KeyEnvelope".into(),
+        version: 1,
+        run_id: run_id.into(),
+        recipient_key_id: recipient_key_id(recipient_pub.as_bytes()),
+        wrap: wrap_dek(run_id, recipient_pub, dek, key_generation)?,
+        extra_recipients,
+        kex_alg: "x25519".into(),
+        kdf_alg: "hkdf-sha256".into(),
+        aead_alg: "chacha20poly1305".into(),
+        key_generation,
+    })
+}
+
+fn wrap_dek(run_id: &str, recipient_pub: &PublicKey, dek: &DataKey, key_generation: u32) -> Result<DekWrap, String> {
     let mut eph_sk = [0u8; 32];
     OsRng.fill_bytes(&mut eph_sk);
 
@@ -141,28 +293,44 @@ fn build_envelope(run_id: &str, recipient_pub: &PublicKey, dek: &DataKey) -> Res
             Nonce::from_slice(&nonce),
             Payload {
                 msg: &dek.0,
-                aad: run_id.as_bytes(),
+                aad: wrap_aad(run_id, key_generation).as_bytes(),
             },
         )
         .map_err(|_| "wrap encrypt failed".to_string())?;
 
-    Ok(KeyEnvelope {
-        record_type: "KeyEnvelope".into(),
-        version: 1,
-        run_id: run_id.into(),
-        recipient_key_id: key_id(recipient_pub.as_bytes()),
+    Ok(DekWrap {
         ephemeral_pubkey_b64: B64.encode(eph_pk.as_bytes()),
         wrap_nonce_b64: B64.encode(nonce),
         wrapped_dek_b64: B64.encode(wrapped),
-        kex_alg: "x25519".into(),
-        kdf_alg: "hkdf-sha256".into(),
-        aead_alg: "chacha20poly1305".into(),
     })
 }
 
+/// Unwrap the DEK of `env` with the private key of its recipient or of one of
+/// its extra recipients (the entry whose key id matches).
 fn unwrap_envelope(env: &KeyEnvelope, recipient_sk: &[u8; 32]) -> Result<DataKey, String> {
+    let own_key_id = recipient_key_id(&x25519(*recipient_sk, X25519_BASEPOINT_BYTES));
+    let wrap = env
+        .extra_recipients
+        .iter()
+        .find(|extra| extra.recipient_key_id == own_key_id)
+        .map_or(&env.wrap, |extra| &extra.wrap);
+    unwrap_dek(wrap, &env.run_id, env.key_generation, recipient_sk)
+}
+
+/// The DEK of the KeyEnvelope escrow entry for `escrow_sk`.
+fn unwrap_escrow(env: &KeyEnvelope, escrow_sk: &[u8; 32]) -> Result<DataKey, String> {
+    let key_id = recipient_key_id(&x25519(*escrow_sk, X25519_BASEPOINT_BYTES));
+    let extra = env
+        .extra_recipients
+        .iter()
+        .find(|extra| extra.role == ROLE_ESCROW && extra.recipient_key_id == key_id)
+        .ok_or_else(|| format!("KeyEnvelope has no escrow entry for key id {}", key_id))?;
+    unwrap_dek(&extra.wrap, &env.run_id, env.key_generation, escrow_sk)
+}
+
+fn unwrap_dek(wrap: &DekWrap, run_id: &str, key_generation: u32, recipient_sk: &[u8; 32]) -> Result<DataKey, String> {
     let eph_pk_bytes = B64
-        .decode(env.ephemeral_pubkey_b64.trim())
+        .decode(wrap.ephemeral_pubkey_b64.trim())
         .map_err(|e| format!("decode ephemeral_pubkey_b64: {}", e))?;
     if eph_pk_bytes.len() != 32 {
         return Err("bad ephemeral pubkey length".to_string());
@@ -180,7 +348,7 @@ fn unwrap_envelope(env: &KeyEnvelope, recipient_sk: &[u8; 32]) -> Result<DataKey
     let cipher = ChaCha20Poly1305::new(Key::from_slice(&wrap_key));
 
     let nonce_bytes = B64
-        .decode(env.wrap_nonce_b64.trim())
+        .decode(wrap.wrap_nonce_b64.trim())
         .map_err(|e| format!("decode wrap_nonce_b64: {}", e))?;
     if nonce_bytes.len() != 12 {
         return Err("bad wrap nonce length".to_string());
@@ -189,7 +357,7 @@ fn unwrap_envelope(env: &KeyEnvelope, recipient_sk: &[u8; 32]) -> Result<DataKey
     nonce.copy_from_slice(&nonce_bytes);
 
     let wrapped = B64
-        .decode(env.wrapped_dek_b64.trim())
+        .decode(wrap.wrapped_dek_b64.trim())
         .map_err(|e| format!("decode wrapped_dek_b64: {}", e))?;
 
     let dek_bytes = cipher
@@ -197,7 +365,7 @@ fn unwrap_envelope(env: &KeyEnvelope, recipient_sk: &[u8; 32]) -> Result<DataKey
             Nonce::from_slice(&nonce),
             Payload {
                 msg: &wrapped,
-                aad: env.run_id.as_bytes(),
+                aad: wrap_aad(run_id, key_generation).as_bytes(),
             },
         )
         .map_err(|_| "failed to unwrap DEK (bad key or tampered envelope)".to_string())?;
@@ -210,63 +378,810 @@ fn unwrap_envelope(env: &KeyEnvelope, recipient_sk: &[u8; 32]) -> Result<DataKey
     Ok(DataKey(dk))
 }
 
-/// ===== AuditSink (PLAINTEXT or ENCRYPTED) =====
+// ===== Vaulted values =====
+
+const VAULT_INFO: &[u8] = b"sentinel/vault/v1";
+
+/// The key an ephemeral X25519 exchange with the recipient gives a vaulted value.
+fn vault_key(shared: &[u8; 32]) -> Result<DataKey, String> {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared)
+        .expand(VAULT_INFO, &mut key)
+        .map_err(|_| "hkdf expand failed".to_string())?;
+    Ok(DataKey(key))
+}
+
+/// Encrypt one value redaction vaults to `recipient_pub`, on its own (a fresh
+/// ephemeral key each time): `ephemeral pubkey || nonce || ciphertext`.
+pub fn vault_seal(recipient_pub: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let mut eph_sk = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(eph_sk.as_mut());
+    let eph_pk = x25519(*eph_sk, X25519_BASEPOINT_BYTES);
+    let shared = Zeroizing::new(x25519(*eph_sk, *recipient_pub));
+    let mut out = eph_pk.to_vec();
+    out.extend(vault_key(&shared)?.seal(&eph_pk, plaintext)?);
+    Ok(out)
+}
+
+/// Reverse of [`vault_seal`] with the recipient's private key.
+pub fn vault_open(recipient_sk: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < 32 {
+        return Err("vaulted value too short".to_string());
+    }
+    let (eph_pk, rest) = sealed.split_at(32);
+    let eph_pk: [u8; 32] = eph_pk.try_into().expect("32 bytes");
+    let shared = Zeroizing::new(x25519(*recipient_sk, eph_pk));
+    vault_key(&shared)?.open(&eph_pk, rest)
+}
+
+// ===== AuditSink (PLAINTEXT or ENCRYPTED) =====
+
+/// When the audit writer forces written records to disk (`run --fsync`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Leave it to the OS (records are still flushed at shutdown)
+    Never,
+    /// At most this long after a record is written
+    Interval(std::time::Duration),
+    /// After every checkpoint
+    EveryCheckpoint,
+    /// After every event; events already queued are synced together
+    EveryEvent,
+}
+
+impl FsyncPolicy {
+    /// Parse `never`, `interval:<ms>`, `every-checkpoint` or `every-event`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "never" => Ok(Self::Never),
+            "every-checkpoint" => Ok(Self::EveryCheckpoint),
+            "every-event" => Ok(Self::EveryEvent),
+            other => match other.strip_prefix("interval:").map(str::parse::<u64>) {
+                Some(Ok(ms)) if ms > 0 => Ok(Self::Interval(std::time::Duration::from_millis(ms))),
+                Some(_) => Err(format!("invalid fsync interval {:?} (expected interval:<ms>, ms > 0)", other)),
+                None => Err(format!(
+                    "invalid fsync policy {:?} (use never, interval:<ms>, every-checkpoint or every-event)",
+                    other
+                )),
+            },
+        }
+    }
+}
+
+/// Applies an [`FsyncPolicy`] to an [`AuditSink`]: the audit loop reports what
+/// it wrote, and this syncs when the policy says so.
+///
+/// Unless the policy is `never`, a file's header and KeyEnvelope (and the run
+/// boundary and start checkpoint after them) are synced with [`Durability::barrier`]
+/// before any event is written, so a log never holds events its decryptor or
+/// verifier cannot reach after a crash. `every-event` syncs once for all the
+/// events that were already queued when it got to them.
+#[derive(Debug)]
+pub struct Durability {
+    policy: FsyncPolicy,
+    /// Records written since the last sync
+    unsynced: bool,
+}
+
+impl Durability {
+    pub fn new(policy: FsyncPolicy) -> Self {
+        Self { policy, unsynced: false }
+    }
+
+    /// The timer of `interval:<ms>`; its ticks are for [`Durability::sync`].
+    pub fn ticker(&self) -> Option<tokio::time::Interval> {
+        match self.policy {
+            FsyncPolicy::Interval(period) => Some(tokio::time::interval_at(tokio::time::Instant::now() + period, period)),
+            _ => None,
+        }
+    }
+
+    /// Records were written since the last sync.
+    pub fn unsynced(&self) -> bool {
+        self.unsynced
+    }
+
+    /// A record was written.
+    pub fn wrote(&mut self) {
+        self.unsynced = true;
+    }
+
+    /// Sync unless the policy is `never`: what was written so far, such as the
+    /// start of a file or a segment being closed, is durable before what depends on it.
+    pub async fn barrier<W: AsyncWrite + LogOutput + Unpin>(&mut self, sink: &mut AuditSink<W>) -> Result<(), String> {
+        match self.policy {
+            FsyncPolicy::Never => Ok(()),
+            _ => self.sync(sink).await,
+        }
+    }
+
+    /// A checkpoint was written; synced under `every-checkpoint`.
+    pub async fn checkpointed<W: AsyncWrite + LogOutput + Unpin>(&mut self, sink: &mut AuditSink<W>) -> Result<(), String> {
+        self.wrote();
+        match self.policy {
+            FsyncPolicy::EveryCheckpoint => self.sync(sink).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// An event was written and `more_queued` others wait behind it. Under
+    /// `every-event` the last one of a batch syncs them all.
+    pub async fn event_written<W: AsyncWrite + LogOutput + Unpin>(
+        &mut self,
+        sink: &mut AuditSink<W>,
+        more_queued: bool,
+    ) -> Result<(), String> {
+        self.wrote();
+        match self.policy {
+            FsyncPolicy::EveryEvent if !more_queued => self.sync(sink).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Sync now, whatever the policy.
+    pub async fn sync<W: AsyncWrite + LogOutput + Unpin>(&mut self, sink: &mut AuditSink<W>) -> Result<(), String> {
+        sink.sync_data().await?;
+        self.unsynced = false;
+        Ok(())
+    }
+}
+
+/// How audit records are compressed (`run --compress`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Compression {
+    /// Records are written as they are
+    #[default]
+    None,
+    /// A plaintext log becomes one zstd stream; an encrypted log compresses each
+    /// record before sealing it
+    Zstd,
+}
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// How an encrypted audit log is written (`run --encrypt-format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum EncryptFormat {
+    /// Each record sealed under a data key wrapped in a KeyEnvelope for
+    /// `--encrypt-recipient-pubkey-b64-path`
+    #[default]
+    Envelope,
+    /// The whole log as one age stream to the `--encrypt-recipient` age1... recipients
+    Age,
+}
+
+/// How `run` encrypts its audit log (and each rotated segment).
+#[derive(Clone)]
+pub enum Encryption {
+    None,
+    /// Key specs of the recipient public key and of the escrow public key
+    /// every data key is also wrapped for
+    Envelope { recipient: String, escrow: Option<String> },
+    Age(Vec<age::x25519::Recipient>),
+}
+
+impl Encryption {
+    /// Parse `age1...` recipient strings.
+    pub fn age(recipients: &[String]) -> Result<Self, String> {
+        if recipients.is_empty() {
+            return Err("--encrypt-format age needs at least one --encrypt-recipient age1...".to_string());
+        }
+        let parsed = recipients
+            .iter()
+            .map(|r| r.trim().parse::<age::x25519::Recipient>().map_err(|e| format!("age recipient {:?}: {}", r, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::Age(parsed))
+    }
+}
+
+/// First bytes of an age file. A log written with `run --encrypt-format age` is
+/// an age stream and starts with them.
+pub const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+/// Whether the log at `log_path` is an age stream.
+pub fn is_age_log(log_path: &str) -> Result<bool, String> {
+    let mut f = fs::File::open(log_path).map_err(|e| format!("failed to open log file {:?}: {}", log_path, e))?;
+    let mut start = [0u8; AGE_MAGIC.len()];
+    let mut n = 0;
+    while n < start.len() {
+        match f.read(&mut start[n..]).map_err(|e| format!("failed to read log file {:?}: {}", log_path, e))? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(start[..n] == *AGE_MAGIC)
+}
+
+/// When an encrypted log switches to a fresh data key (`run --rotate-dek-max-records`,
+/// `--rotate-dek-max-minutes`). Each new key gets its own KeyEnvelope mid-stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DekRotation {
+    pub max_records: Option<u64>,
+    pub max_age: Option<std::time::Duration>,
+}
+
+impl DekRotation {
+    fn due(&self, records: u64, age: std::time::Duration) -> bool {
+        self.max_records.is_some_and(|max| records >= max) || self.max_age.is_some_and(|max| age >= max)
+    }
+}
+
+/// How many of an audit log's outputs must take a record for it to count as
+/// written (`run --audit-log-quorum`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Quorum {
+    /// Every output; a failed write is cut back out of every output and tried
+    /// again for the next record. If that fails the outputs are broken and the run stops
+    All,
+    /// At least one; a failed output is dropped and the others carry on
+    #[default]
+    Any,
+}
+
+/// What an audit log output needs besides writing: syncing to disk for
+/// `--fsync`, and cutting its end back so that under [`Quorum::All`] a record
+/// that did not reach every output is taken out of the ones it did.
+pub trait LogOutput {
+    /// Wait until what was written is on disk.
+    fn sync_data(&mut self) -> impl std::future::Future<Output = std::io::Result<()>> + Send;
+    /// Length of what was written so far.
+    fn end(&mut self) -> impl std::future::Future<Output = std::io::Result<u64>> + Send;
+    /// Cut back to `len` bytes; the next write continues from there.
+    fn truncate(&mut self, len: u64) -> impl std::future::Future<Output = std::io::Result<()>> + Send;
+}
+
+impl LogOutput for tokio::fs::File {
+    async fn sync_data(&mut self) -> std::io::Result<()> {
+        tokio::fs::File::sync_data(self).await
+    }
+
+    async fn end(&mut self) -> std::io::Result<u64> {
+        self.flush().await?;
+        self.seek(std::io::SeekFrom::End(0)).await
+    }
+
+    async fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+        self.flush().await?;
+        self.set_len(len).await?;
+        // A file not opened for appending would otherwise go on writing past a hole.
+        self.seek(std::io::SeekFrom::Start(len)).await.map(|_| ())
+    }
+}
+
+/// Error counters of each audit log output (`--audit-log`, then the mirrors), for
+/// the stats frame and `/metrics`.
+#[derive(Debug)]
+pub struct SinkStats {
+    sinks: Vec<SinkHealth>,
+}
+
+#[derive(Debug)]
+struct SinkHealth {
+    path: String,
+    errors: AtomicU64,
+    /// Dropped from the set under `--audit-log-quorum any`
+    failed: AtomicBool,
+}
+
+impl SinkStats {
+    pub fn new(paths: &[String]) -> Self {
+        let sinks = paths
+            .iter()
+            .map(|path| SinkHealth {
+                path: path.clone(),
+                errors: AtomicU64::new(0),
+                failed: AtomicBool::new(false),
+            })
+            .collect();
+        Self { sinks }
+    }
+
+    /// `[{"path": .., "errors": n, "failed": bool}, ...]`
+    pub fn snapshot(&self) -> serde_json::Value {
+        self.sinks
+            .iter()
+            .map(|s| {
+                serde_json::json!({
+                    "path": s.path,
+                    "errors": s.errors.load(Ordering::Relaxed),
+                    "failed": s.failed.load(Ordering::Relaxed),
+                })
+            })
+            .collect()
+    }
+
+    /// One line per output that saw errors, for the end of the run.
+    pub fn failures(&self) -> Vec<String> {
+        self.sinks
+            .iter()
+            .filter(|s| s.errors.load(Ordering::Relaxed) > 0)
+            .map(|s| {
+                format!(
+                    "{}: {} error(s){}",
+                    s.path,
+                    s.errors.load(Ordering::Relaxed),
+                    if s.failed.load(Ordering::Relaxed) { ", dropped" } else { "" }
+                )
+            })
+            .collect()
+    }
+
+    pub fn render_openmetrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE sentinel_audit_sink_errors counter");
+        let _ = writeln!(out, "# HELP sentinel_audit_sink_errors Failed writes, flushes and syncs per audit log output");
+        for s in &self.sinks {
+            let _ = writeln!(
+                out,
+                "sentinel_audit_sink_errors_total{{path={:?}}} {}",
+                s.path,
+                s.errors.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+/// The files one audit log is written to: `--audit-log` and its mirrors, kept
+/// byte-identical. Each operation goes to every live output, then the quorum
+/// decides whether it succeeded. Under [`Quorum::Any`] a failed output is
+/// dropped, since its copy is incomplete from there on; the last one never is.
+/// Under [`Quorum::All`] a failed write is rewound on every output, and if one
+/// cannot be the outputs are broken: every later operation fails.
+pub struct Outputs<W> {
+    outputs: Vec<Output<W>>,
+    quorum: Quorum,
+    stats: Arc<SinkStats>,
+    /// Why the outputs no longer hold the same records
+    broken: Option<String>,
+}
+
+struct Output<W> {
+    /// What `--audit-log` / `--audit-log-mirror` named; rotated segments derive from it
+    base: PathBuf,
+    /// `None` once dropped
+    out: Option<W>,
+    /// Its length, tracked under [`Quorum::All`] from the first write on; `Some(None)`
+    /// when it has none (a pipe), so a failed write cannot be taken back out of it
+    len: Option<Option<u64>>,
+}
+
+impl<W: AsyncWrite + LogOutput + Unpin> Outputs<W> {
+    /// `outs` pairs each configured path with its open file, in the order of `stats`.
+    pub fn new(outs: Vec<(PathBuf, W)>, quorum: Quorum, stats: Arc<SinkStats>) -> Self {
+        let outputs = outs
+            .into_iter()
+            .map(|(base, out)| Output { base, out: Some(out), len: None })
+            .collect();
+        Self { outputs, quorum, stats, broken: None }
+    }
+
+    /// Why the outputs stopped taking records, if they did. A run cannot go on
+    /// writing its audit log after that.
+    pub fn broken(&self) -> Option<&str> {
+        self.broken.as_deref()
+    }
+
+    /// Give up on keeping the outputs identical, e.g. after a failed write that
+    /// cannot be taken back.
+    fn mark_broken(&mut self, why: String) {
+        if self.broken.is_none() {
+            eprintln!("❌ Audit log outputs no longer match: {}", why);
+            self.broken = Some(why);
+        }
+    }
+
+    fn check_broken(&self) -> std::io::Result<()> {
+        match &self.broken {
+            Some(why) => Err(std::io::Error::other(format!("audit log outputs broken ({})", why))),
+            None => Ok(()),
+        }
+    }
+
+    async fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.check_broken()?;
+        if self.quorum == Quorum::All {
+            self.learn_lengths().await;
+        }
+        let mut results = Vec::new();
+        for (i, o) in self.outputs.iter_mut().enumerate() {
+            if let Some(out) = o.out.as_mut() {
+                results.push((i, out.write_all(bytes).await));
+            }
+        }
+        let all_written = results.iter().all(|(_, r)| r.is_ok());
+        let settled = self.settle("write", results);
+        if self.quorum == Quorum::All {
+            if all_written {
+                for o in &mut self.outputs {
+                    o.len = o.len.map(|len| len.map(|len| len + bytes.len() as u64));
+                }
+            } else {
+                self.rewind().await;
+            }
+        }
+        settled
+    }
+
+    /// Under [`Quorum::All`], find out where each output ends before the first write.
+    async fn learn_lengths(&mut self) {
+        for o in &mut self.outputs {
+            if let (Some(out), None) = (o.out.as_mut(), o.len) {
+                o.len = Some(out.end().await.ok());
+            }
+        }
+    }
+
+    /// Cut every output back to where it ended before a write that failed on
+    /// some of them; the outputs are broken if one cannot be.
+    async fn rewind(&mut self) {
+        let mut failed = Vec::new();
+        for o in &mut self.outputs {
+            let Some(out) = o.out.as_mut() else { continue };
+            let cut = match o.len.flatten() {
+                Some(len) => out.truncate(len).await,
+                None => Err(std::io::Error::other("its length is unknown")),
+            };
+            if let Err(e) = cut {
+                failed.push(format!("{}: {}", o.base.display(), e));
+            }
+        }
+        if !failed.is_empty() {
+            self.mark_broken(format!("a failed write could not be taken back from {}", failed.join(", ")));
+        }
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.check_broken()?;
+        let mut results = Vec::new();
+        for (i, o) in self.outputs.iter_mut().enumerate() {
+            if let Some(out) = o.out.as_mut() {
+                results.push((i, out.flush().await));
+            }
+        }
+        self.settle("flush", results)
+    }
+
+    async fn sync_data(&mut self) -> std::io::Result<()> {
+        self.check_broken()?;
+        let mut results = Vec::new();
+        for (i, o) in self.outputs.iter_mut().enumerate() {
+            if let Some(out) = o.out.as_mut() {
+                results.push((i, out.sync_data().await));
+            }
+        }
+        self.settle("fsync", results)
+    }
+
+    /// Count the failures among `results` (output index, outcome) and apply the quorum.
+    fn settle(&mut self, what: &str, results: Vec<(usize, std::io::Result<()>)>) -> std::io::Result<()> {
+        let mut first_error = None;
+        for (i, result) in results {
+            let Err(e) = result else { continue };
+            let health = &self.stats.sinks[i];
+            health.errors.fetch_add(1, Ordering::Relaxed);
+            let others = self
+                .outputs
+                .iter()
+                .enumerate()
+                .filter(|(j, o)| *j != i && o.out.is_some())
+                .count();
+            if self.quorum == Quorum::Any && others > 0 {
+                eprintln!(
+                    "⚠️  Audit log output {} failed ({}: {}); continuing with the {} other(s)",
+                    health.path, what, e, others
+                );
+                health.failed.store(true, Ordering::Relaxed);
+                self.outputs[i].out = None;
+            } else {
+                first_error.get_or_insert(std::io::Error::new(e.kind(), format!("{}: {}", health.path, e)));
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+impl Outputs<tokio::fs::File> {
+    /// Create segment `n` of every live output. Creating one counts like a write.
+    pub async fn create_segment(&self, n: u32) -> Result<Self, String> {
+        let mut next = Self {
+            outputs: Vec::new(),
+            quorum: self.quorum,
+            stats: self.stats.clone(),
+            broken: None,
+        };
+        let mut results = Vec::new();
+        for (i, o) in self.outputs.iter().enumerate() {
+            let mut out = None;
+            if o.out.is_some() {
+                let path = crate::audit::segment_path(&o.base, n);
+                match tokio::fs::File::create(&path).await {
+                    Ok(file) => out = Some(file),
+                    Err(e) => results.push((
+                        i,
+                        Err(std::io::Error::new(e.kind(), format!("create {}: {}", path.display(), e))),
+                    )),
+                }
+            }
+            next.outputs.push(Output { base: o.base.clone(), out, len: Some(Some(0)) });
+        }
+        next.settle("create segment", results).map_err(|e| e.to_string())?;
+        Ok(next)
+    }
+}
+
+/// Where a [`LogStream`] leaves its output until it is written to the files.
+#[derive(Clone, Default)]
+struct Spool(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Spool {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+type ZstdEncoder<W> = zstd::stream::write::Encoder<'static, W>;
+type AgeWriter = age::stream::StreamWriter<Spool>;
+
+enum StreamStages {
+    Zstd(ZstdEncoder<Spool>),
+    Age(AgeWriter),
+    /// Compressed, then encrypted
+    ZstdAge(ZstdEncoder<AgeWriter>),
+}
 
-pub enum AuditSink<'a, W: AsyncWrite + Unpin> {
-    Plain { out: &'a mut W },
+/// A plaintext log written as one stream: zstd-compressed, age-encrypted, or
+/// both. The stream's bytes collect in `spool` until they are drained to the files.
+pub struct LogStream {
+    stages: StreamStages,
+    spool: Spool,
+}
+
+impl LogStream {
+    fn new(compression: Compression, age_recipients: Option<&[age::x25519::Recipient]>) -> Result<Option<Self>, String> {
+        let spool = Spool::default();
+        let age_writer = |spool: Spool| -> Result<AgeWriter, String> {
+            let recipients = age_recipients.unwrap_or_default();
+            age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+                .map_err(|e| format!("age recipients: {}", e))?
+                .wrap_output(spool)
+                .map_err(|e| format!("start age stream: {}", e))
+        };
+        let zstd = |e: std::io::Error| format!("start zstd stream: {}", e);
+        let stages = match (compression, age_recipients.is_some()) {
+            (Compression::None, false) => return Ok(None),
+            (Compression::Zstd, false) => StreamStages::Zstd(ZstdEncoder::new(spool.clone(), ZSTD_LEVEL).map_err(zstd)?),
+            (Compression::None, true) => StreamStages::Age(age_writer(spool.clone())?),
+            (Compression::Zstd, true) => {
+                StreamStages::ZstdAge(ZstdEncoder::new(age_writer(spool.clone())?, ZSTD_LEVEL).map_err(zstd)?)
+            }
+        };
+        Ok(Some(Self { stages, spool }))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match &mut self.stages {
+            StreamStages::Zstd(w) => w.write_all(buf),
+            StreamStages::Age(w) => w.write_all(buf),
+            StreamStages::ZstdAge(w) => w.write_all(buf),
+        }
+    }
+
+    /// End the current zstd block. age only writes whole 64 KiB chunks (and the
+    /// last one when the stream finishes), so an age stream keeps its partial chunk.
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.stages {
+            StreamStages::Zstd(w) => w.flush(),
+            StreamStages::Age(w) => w.flush(),
+            StreamStages::ZstdAge(w) => w.flush(),
+        }
+    }
+
+    /// End the stream; its last bytes are left in the spool.
+    fn finish(self) -> std::io::Result<Spool> {
+        match self.stages {
+            StreamStages::Zstd(w) => w.finish()?,
+            StreamStages::Age(w) => w.finish()?,
+            StreamStages::ZstdAge(w) => w.finish()?.finish()?,
+        };
+        Ok(self.spool)
+    }
+}
+
+/// `position` is the number of bytes written so far, i.e. the offset at which the
+/// next record's line starts. For a zstd or age stream it counts the plaintext
+/// bytes, so offsets match the log the verifier reads.
+pub enum AuditSink<W: AsyncWrite + Unpin> {
+    Plain {
+        out: Outputs<W>,
+        position: u64,
+        stream: Option<LogStream>,
+    },
     Encrypted {
-        out: &'a mut W,
+        out: Outputs<W>,
+        position: u64,
         run_id: String,
+        recipient_pub: PublicKey,
+        escrow_pub: Option<PublicKey>,
         dek: DataKey,
+        key_generation: u32,
+        /// Records sealed with `dek`, and when it was made
+        sealed: u64,
+        /// Seq of the next record
+        seq: u64,
+        /// First bytes of every record nonce, chosen with the sink
+        nonce_prefix: [u8; 4],
+        dek_created: std::time::Instant,
+        rotation: DekRotation,
+        compress: bool,
     },
 }
 
-impl<'a, W: AsyncWrite + Unpin> AuditSink<'a, W> {
+impl<W: AsyncWrite + LogOutput + Unpin> AuditSink<W> {
     pub async fn new(
-        out: &'a mut W,
+        out: Outputs<W>,
         run_id: &str,
-        recipient_pub_path: Option<&str>,
+        encryption: &Encryption,
+        compression: Compression,
     ) -> Result<Self, String> {
-        if let Some(path) = recipient_pub_path {
-            let pub_bytes = read_b64_32(Path::new(path))?;
-            let recipient_pub = PublicKey::from(pub_bytes);
+        let compress = compression == Compression::Zstd;
+        // The header stays plaintext in both modes so tools can identify the file
+        // (inside the zstd or age stream when the whole log is one). An age stream
+        // holds exactly what a plaintext log would.
+        let format = if let Encryption::Envelope { .. } = encryption { FORMAT_AUDIT_ENCRYPTED } else { FORMAT_AUDIT };
+        let header = serde_json::to_string(&make_file_header(format))
+            .map_err(|e| format!("serialize header: {}", e))?;
+
+        let (path, escrow_path) = match encryption {
+            Encryption::Envelope { recipient, escrow } => (recipient, escrow),
+            Encryption::None | Encryption::Age(_) => {
+                let age_recipients = match encryption {
+                    Encryption::Age(recipients) => Some(recipients.as_slice()),
+                    _ => None,
+                };
+                let stream = LogStream::new(compression, age_recipients)?;
+                let mut sink = Self::Plain { out, position: 0, stream };
+                sink.write_line(&header).await.map_err(|e| format!("write FileHeader: {}", e))?;
+                return Ok(sink);
+            }
+        };
+
+        let pub_bytes = crate::keys::load_key32(path, "recipient public key")?;
+        let recipient_pub = PublicKey::from(pub_bytes);
+        let escrow_pub = match escrow_path {
+            Some(path) => Some(PublicKey::from(crate::keys::load_key32(path, "escrow public key")?)),
+            None => None,
+        };
+
+        let dek = DataKey::random();
+        let env = build_envelope(run_id, &recipient_pub, escrow_pub.as_ref(), &dek, 0)?;
+        let env_line = serde_json::to_string(&env).map_err(|e| format!("serialize env: {}", e))?;
+
+        let mut sink = Self::Encrypted {
+            out,
+            position: 0,
+            run_id: run_id.into(),
+            recipient_pub,
+            escrow_pub,
+            dek,
+            key_generation: 0,
+            sealed: 0,
+            seq: 0,
+            nonce_prefix: {
+                let mut prefix = [0u8; 4];
+                OsRng.fill_bytes(&mut prefix);
+                prefix
+            },
+            dek_created: std::time::Instant::now(),
+            rotation: DekRotation::default(),
+            compress,
+        };
+        sink.write_line(&header).await.map_err(|e| format!("write FileHeader: {}", e))?;
+        sink.write_line(&env_line).await.map_err(|e| format!("write KeyEnvelope: {}", e))?;
+        Ok(sink)
+    }
 
-            let dek = DataKey::random();
-            let env = build_envelope(run_id, &recipient_pub, &dek)?;
+    /// Continue a plaintext log whose records end at `position` (`run --append`);
+    /// the file already has its header.
+    pub fn append(out: Outputs<W>, position: u64) -> Self {
+        Self::Plain { out, position, stream: None }
+    }
 
-            let line = serde_json::to_string(&env).map_err(|e| format!("serialize env: {}", e))?;
-            out.write_all(format!("{}\n", line).as_bytes())
-                .await
-                .map_err(|e| format!("write KeyEnvelope: {}", e))?;
+    /// Rotate the data key of an encrypted log by `rotation`; no effect on a plaintext one.
+    pub fn with_dek_rotation(mut self, dek_rotation: DekRotation) -> Self {
+        if let Self::Encrypted { rotation, .. } = &mut self {
+            *rotation = dek_rotation;
+        }
+        self
+    }
 
-            Ok(Self::Encrypted {
-                out,
-                run_id: run_id.into(),
-                dek,
-            })
-        } else {
-            Ok(Self::Plain { out })
+    /// Switch to a fresh data key, announced by a new KeyEnvelope, once the
+    /// current one is due for rotation. Called between records, before the next
+    /// event's offset is taken, so the envelope never lands inside a record.
+    /// Returns the new key generation if the key was rotated.
+    pub async fn rotate_dek_if_due(&mut self) -> Result<Option<u32>, String> {
+        let Self::Encrypted { run_id, recipient_pub, escrow_pub, key_generation, sealed, dek_created, rotation, .. } = self
+        else {
+            return Ok(None);
+        };
+        if !rotation.due(*sealed, dek_created.elapsed()) {
+            return Ok(None);
         }
+        let next_generation = *key_generation + 1;
+        let dek = DataKey::random();
+        let env = build_envelope(run_id, recipient_pub, escrow_pub.as_ref(), &dek, next_generation)?;
+        let env_line = serde_json::to_string(&env).map_err(|e| format!("serialize env: {}", e))?;
+        self.write_line(&env_line).await.map_err(|e| format!("write KeyEnvelope: {}", e))?;
+
+        if let Self::Encrypted { dek: current, key_generation, sealed, dek_created, .. } = self {
+            *current = dek;
+            *key_generation = next_generation;
+            *sealed = 0;
+            *dek_created = std::time::Instant::now();
+        }
+        Ok(Some(next_generation))
     }
 
-    pub async fn write_record(&mut self, inner: &str, json: &str) -> Result<(), String> {
+    /// The data key records are currently sealed with, when they are encrypted.
+    pub fn data_key(&self) -> Option<&DataKey> {
+        match self {
+            Self::Plain { .. } => None,
+            Self::Encrypted { dek, .. } => Some(dek),
+        }
+    }
+
+    /// The files the records go to.
+    pub fn outputs(&self) -> &Outputs<W> {
+        match self {
+            Self::Plain { out, .. } | Self::Encrypted { out, .. } => out,
+        }
+    }
+
+    /// Offset at which the next record will be written.
+    pub fn position(&self) -> u64 {
         match self {
-            Self::Plain { out } => {
-                out.write_all(format!("{}\n", json).as_bytes())
-                    .await
-                    .map_err(|e| format!("write plaintext: {}", e))?;
+            Self::Plain { position, .. } | Self::Encrypted { position, .. } => *position,
+        }
+    }
+
+    pub async fn write_record(&mut self, inner: &str, json: &str) -> Result<(), String> {
+        let line = match self {
+            Self::Plain { .. } => {
+                return self.write_line(json).await.map_err(|e| format!("write plaintext: {}", e));
             }
-            Self::Encrypted { out, run_id, dek } => {
+            Self::Encrypted { run_id, dek, key_generation, sealed, seq, nonce_prefix, compress, .. } => {
+                // The counter must not wrap back onto a nonce already used.
+                if *seq == u64::MAX {
+                    return Err("record counter exhausted; refusing to reuse a nonce".to_string());
+                }
                 let cipher = ChaCha20Poly1305::new(Key::from_slice(&dek.0));
-                let mut nonce = [0u8; 12];
-                OsRng.fill_bytes(&mut nonce);
+                let nonce = counter_nonce(*nonce_prefix, *seq);
+
+                let (msg, compression) = match compress {
+                    true => (
+                        zstd::bulk::compress(json.as_bytes(), ZSTD_LEVEL).map_err(|e| format!("compress: {}", e))?,
+                        Some("zstd".to_string()),
+                    ),
+                    false => (json.as_bytes().to_vec(), None),
+                };
 
-                let aad = format!("{}|{}", run_id, inner);
+                let aad = record_aad(run_id, inner, Some(*seq), compression.as_deref(), *key_generation);
                 let ct = cipher
                     .encrypt(
                         Nonce::from_slice(&nonce),
                         Payload {
-                            msg: json.as_bytes(),
+                            msg: &msg,
                             aad: aad.as_bytes(),
                         },
                     )
@@ -274,92 +1189,498 @@ impl<'a, W: AsyncWrite + Unpin> AuditSink<'a, W> {
 
                 let rec = EncryptedRecord {
                     record_type: "Encrypted".into(),
-                    version: 1,
+                    version: ENCRYPTED_RECORD_VERSION,
                     run_id: run_id.clone(),
                     inner_type: inner.into(),
+                    compression,
+                    key_generation: *key_generation,
+                    seq: Some(*seq),
                     nonce_b64: B64.encode(nonce),
                     ciphertext_b64: B64.encode(ct),
                 };
+                *sealed += 1;
+                // Used even if the write fails, so a seq never repeats
+                *seq += 1;
 
-                let line =
-                    serde_json::to_string(&rec).map_err(|e| format!("serialize enc: {}", e))?;
-                out.write_all(format!("{}\n", line).as_bytes())
-                    .await
-                    .map_err(|e| format!("write encrypted: {}", e))?;
+                serde_json::to_string(&rec).map_err(|e| format!("serialize enc: {}", e))?
+            }
+        };
+        self.write_line(&line).await.map_err(|e| format!("write encrypted: {}", e))
+    }
+
+    /// Write `line` and its newline, through the zstd or age stream if there is one.
+    async fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let line = format!("{}\n", line);
+        match self {
+            Self::Plain { out, position, stream: Some(stream) } => {
+                stream.write_all(line.as_bytes())?;
+                // The stream has taken the line, so it cannot be written again.
+                if let Err(e) = drain(out, &stream.spool).await {
+                    out.mark_broken(format!("the log stream lost a record: {}", e));
+                    return Err(e);
+                }
+                *position += line.len() as u64;
+            }
+            Self::Plain { out, position, stream: None } | Self::Encrypted { out, position, .. } => {
+                out.write_all(line.as_bytes()).await?;
+                *position += line.len() as u64;
             }
         }
         Ok(())
     }
 
+    /// Write out buffered records. A zstd stream ends its current block, so
+    /// everything written so far can be decompressed; an age stream holds back
+    /// its last partial chunk until it is closed.
     pub async fn flush(&mut self) -> Result<(), String> {
         match self {
-            Self::Plain { out } => out.flush().await.map_err(|e| format!("flush: {}", e))?,
-            Self::Encrypted { out, .. } => out.flush().await.map_err(|e| format!("flush: {}", e))?,
+            Self::Plain { out, stream: Some(stream), .. } => {
+                stream.flush().map_err(|e| format!("flush: {}", e))?;
+                drain(out, &stream.spool).await.map_err(|e| format!("flush: {}", e))?;
+                out.flush().await.map_err(|e| format!("flush: {}", e))?
+            }
+            Self::Plain { out, .. } | Self::Encrypted { out, .. } => {
+                out.flush().await.map_err(|e| format!("flush: {}", e))?
+            }
         }
         Ok(())
     }
+
+    /// Flush, ending a zstd stream's frame or an age stream's last chunk.
+    /// Nothing may be written afterwards.
+    pub async fn close(&mut self) -> Result<(), String> {
+        if let Self::Plain { out, stream, .. } = self {
+            if let Some(stream) = stream.take() {
+                let spool = stream.finish().map_err(|e| format!("finish log stream: {}", e))?;
+                drain(out, &spool).await.map_err(|e| format!("finish log stream: {}", e))?;
+            }
+        }
+        self.flush().await
+    }
+
+    /// Flush buffered records and wait until the file's data is on disk.
+    pub async fn sync_data(&mut self) -> Result<(), String> {
+        self.flush().await?;
+        match self {
+            Self::Plain { out, .. } | Self::Encrypted { out, .. } => {
+                out.sync_data().await.map_err(|e| format!("fsync: {}", e))
+            }
+        }
+    }
 }
 
-pub fn maybe_decrypt_to_temp_plaintext(
-    log_path: &str,
-    recipient_privkey_b64_path: Option<&str>,
-) -> Result<PathBuf, String> {
-    let file = File::open(log_path).map_err(|e| format!("open audit log: {}", e))?;
-    let mut reader = BufReader::new(file);
+/// Move the stream bytes produced so far to `out`.
+async fn drain<W: AsyncWrite + LogOutput + Unpin>(out: &mut Outputs<W>, spool: &Spool) -> std::io::Result<()> {
+    let bytes = spool.take();
+    if !bytes.is_empty() {
+        out.write_all(&bytes).await?;
+    }
+    Ok(())
+}
+
+
+/// Where the records of an encrypted log start: its first KeyEnvelope, how many
+/// leading lines (header and envelope) precede the records, and whether the file
+/// must follow the strict line layout of the current format.
+struct EncryptedLayout {
+    env: KeyEnvelope,
+    skip: usize,
+    strict: bool,
+}
 
-    let mut first_line = String::new();
-    reader
-        .read_line(&mut first_line)
-        .map_err(|e| format!("read first line: {}", e))?;
+/// The layout of `log_path` if it is encrypted, `None` for a plaintext log.
+fn encrypted_layout(log_path: &str) -> Result<Option<EncryptedLayout>, String> {
+    let reader = BufReader::new(open_log(Path::new(log_path))?);
 
-    if first_line.trim().is_empty() {
+    // Header (if any) and KeyEnvelope (if encrypted) are the first two records.
+    let mut leading = Vec::with_capacity(2);
+    for line_res in reader.lines() {
+        let line = line_res.map_err(|e| format!("read line: {}", e))?;
+        if normalize_line(&line).is_empty() {
+            continue;
+        }
+        leading.push(normalize_line(&line).to_string());
+        if leading.len() == 2 {
+            break;
+        }
+    }
+
+    let Some(first_line) = leading.first() else {
         return Err("audit log is empty".to_string());
+    };
+
+    let mut strict = false;
+    let (envelope_line, skip) = match parse_file_header(first_line)? {
+        Some(format) if format == FORMAT_AUDIT_ENCRYPTED => {
+            strict = true;
+            (leading.get(1), 2)
+        }
+        Some(format) if format == LEGACY_FORMAT_AUDIT_ENCRYPTED => (leading.get(1), 2),
+        Some(_) => return Ok(None),
+        // Legacy header-less file: sniff for a KeyEnvelope.
+        None => (Some(first_line), 1),
+    };
+
+    match envelope_line.and_then(|l| RecordDecryptor::parse_envelope(l)) {
+        Some(env) => Ok(Some(EncryptedLayout { env, skip, strict })),
+        _ if skip == 2 => Err("encrypted audit log is missing its KeyEnvelope".to_string()),
+        _ => Ok(None),
     }
+}
 
-    let env_parse = serde_json::from_str::<KeyEnvelope>(first_line.trim());
-    if env_parse.is_err() {
-        return Ok(PathBuf::from(log_path));
+/// The data keys of an encrypted log, by key generation, unwrapped from the
+/// escrow entries of its KeyEnvelopes (`sentinel reveal-dek`), base64.
+pub fn reveal_deks(log_path: &str, escrow_privkey_spec: &str) -> Result<Vec<(u32, Zeroizing<String>)>, String> {
+    if is_age_log(log_path)? || encrypted_layout(log_path)?.is_none() {
+        return Err("audit log is not envelope-encrypted; it has no data keys".to_string());
     }
-    let env = env_parse.unwrap();
-    if env.record_type != "KeyEnvelope" {
-        return Ok(PathBuf::from(log_path));
+    let escrow_sk = Zeroizing::new(crate::keys::load_key32(escrow_privkey_spec, "escrow private key")?);
+    let reader = BufReader::new(open_log(Path::new(log_path))?);
+    let mut deks = Vec::new();
+    for (i, line_res) in reader.lines().enumerate() {
+        let line = line_res.map_err(|e| format!("read line: {}", e))?;
+        if !line.contains("\"KeyEnvelope\"") {
+            continue;
+        }
+        let Some(env) = RecordDecryptor::parse_envelope(&line) else {
+            continue;
+        };
+        let dek = unwrap_escrow(&env, &escrow_sk).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        deks.push((env.key_generation, Zeroizing::new(B64.encode(dek.0))));
     }
+    Ok(deks)
+}
+
+/// The records of a log as plaintext JSONL, read as the log is.
+pub struct PlaintextLog {
+    pub reader: Box<dyn Read>,
+    /// The records are sealed one by one (the envelope format) and decrypted as
+    /// they are read. Their `write_offset`s refer to the encrypted file and were
+    /// checked while decrypting. An age stream decrypts to a plaintext log whose
+    /// offsets are checked as usual.
+    pub offsets_checked: bool,
+}
 
+/// Open `log_path` for reading its records, decrypting them on the fly when the
+/// log is encrypted. Nothing decrypted is written to disk. For an age log,
+/// `recipient_privkey_b64_path` is an age identity file instead.
+pub fn open_plaintext(log_path: &str, recipient_privkey_b64_path: Option<&str>) -> Result<PlaintextLog, String> {
+    if is_age_log(log_path)? {
+        let identity_path = recipient_privkey_b64_path.ok_or("age-encrypted audit log requires an age identity file")?;
+        return Ok(PlaintextLog {
+            reader: open_age_log(log_path, identity_path)?,
+            offsets_checked: false,
+        });
+    }
+    let Some(layout) = encrypted_layout(log_path)? else {
+        return Ok(PlaintextLog {
+            reader: open_log(Path::new(log_path))?,
+            offsets_checked: false,
+        });
+    };
     let priv_path = recipient_privkey_b64_path
         .ok_or("encrypted audit log requires recipient private key for verification")?;
-    let recipient_sk = read_b64_32(Path::new(priv_path))?;
+    let lines = DecryptedLines::open(log_path, layout, priv_path)?;
+    Ok(PlaintextLog {
+        reader: Box::new(DecryptingReader { lines, pending: Vec::new(), pos: 0 }),
+        offsets_checked: true,
+    })
+}
 
-    let dek = unwrap_envelope(&env, &recipient_sk)?;
+/// [`open_plaintext`] for the first `len` bytes of `log_path`, e.g. as far as a
+/// log still being written is flushed. Only for logs whose records are lines of
+/// the file (not a zstd or age stream); an encrypted one is decrypted with
+/// `recipient_sk` itself.
+pub fn open_plaintext_prefix(log_path: &str, len: u64, recipient_sk: Option<&[u8; 32]>) -> Result<PlaintextLog, String> {
+    let prefix = || -> Result<Box<dyn Read>, String> {
+        let f = fs::File::open(log_path).map_err(|e| format!("failed to open log file {:?}: {}", log_path, e))?;
+        Ok(Box::new(f.take(len)))
+    };
+    let Some(layout) = encrypted_layout(log_path)? else {
+        return Ok(PlaintextLog { reader: prefix()?, offsets_checked: false });
+    };
+    let sk = recipient_sk.ok_or("encrypted audit log requires recipient private key for verification")?;
+    let decryptor = RecordDecryptor::with_secret(&layout.env, *sk)?;
+    let lines = DecryptedLines::new(prefix()?, layout, decryptor);
+    Ok(PlaintextLog {
+        reader: Box::new(DecryptingReader { lines, pending: Vec::new(), pos: 0 }),
+        offsets_checked: true,
+    })
+}
 
-    let file = File::open(log_path).map_err(|e| format!("re-open audit log: {}", e))?;
-    let reader = BufReader::new(file);
+/// [`open_plaintext`] with the recipient private key itself; not for an age log.
+pub fn open_plaintext_with_secret(log_path: &str, recipient_sk: &[u8; 32]) -> Result<PlaintextLog, String> {
+    if is_age_log(log_path)? {
+        return Err("an age-encrypted log can't be opened with an X25519 recipient key".to_string());
+    }
+    let Some(layout) = encrypted_layout(log_path)? else {
+        return Ok(PlaintextLog {
+            reader: open_log(Path::new(log_path))?,
+            offsets_checked: false,
+        });
+    };
+    let decryptor = RecordDecryptor::with_secret(&layout.env, *recipient_sk)?;
+    let lines = DecryptedLines::new(open_log(Path::new(log_path))?, layout, decryptor);
+    Ok(PlaintextLog {
+        reader: Box::new(DecryptingReader { lines, pending: Vec::new(), pos: 0 }),
+        offsets_checked: true,
+    })
+}
 
-    let mut tmp =
-        NamedTempFile::new().map_err(|e| format!("create temp file: {}", e))?;
+/// Decrypt an age log with the identities in the file at `identity_path`,
+/// decompressing it too if it was compressed before encryption.
+fn open_age_log(log_path: &str, identity_path: &str) -> Result<Box<dyn Read>, String> {
+    let text = crate::keys::load_text(identity_path, "age identity")?;
+    let identities = age::IdentityFile::from_buffer(text.as_bytes())
+        .and_then(|file| file.into_identities().map_err(std::io::Error::other))
+        .map_err(|e| format!("age identity file: {}", e))?;
+    let f = fs::File::open(log_path).map_err(|e| format!("failed to open log file {:?}: {}", log_path, e))?;
+    let decryptor = age::Decryptor::new_buffered(BufReader::new(f)).map_err(|e| format!("age header: {}", e))?;
+    let reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref() as &dyn age::Identity))
+        .map_err(|e| format!("age decryption: {}", e))?;
+    // A chunk that fails authentication surfaces as a read error of the stream.
+    decompressed(reader).map_err(|e| format!("failed to read log file {:?}: {}", log_path, e))
+}
 
-    let cipher = ChaCha20Poly1305::new(Key::from_slice(&dek.0));
-    let mut saw_first = false;
+/// Decrypt an encrypted log one line at a time, handing each plaintext record to
+/// `each` with its line number in the encrypted file. Each record's offset is
+/// checked against the encrypted file. Errors name the line and the record's
+/// `inner_type`. Returns the number of records.
+pub fn decrypt_records(
+    log_path: &str,
+    recipient_privkey_b64_path: &str,
+    mut each: impl FnMut(usize, &str) -> Result<(), String>,
+) -> Result<u64, String> {
+    let layout = encrypted_layout(log_path)?.ok_or("audit log is not encrypted")?;
+    let mut lines = DecryptedLines::open(log_path, layout, recipient_privkey_b64_path)?;
+    let mut records = 0u64;
+    while let Some((line_no, inner, pt_str)) = lines.next_record()? {
+        // Errors of `each` (the verifier's) already name the line
+        each(line_no, &pt_str).map_err(|e| format!("{} record at {}", inner, e))?;
+        records += 1;
+    }
+    Ok(records)
+}
 
-    for line_res in reader.lines() {
-        let line = line_res.map_err(|e| format!("read line: {}", e))?;
-        let s = line.trim();
-        if s.is_empty() {
-            continue;
+/// The records of an encrypted log, decrypted one line at a time.
+struct DecryptedLines {
+    reader: BufReader<Box<dyn Read>>,
+    decryptor: RecordDecryptor,
+    /// Leading lines (header, first envelope) still to skip
+    skip: usize,
+    strict: bool,
+    line_no: usize,
+    offset: u64,
+    buf: Vec<u8>,
+}
+
+impl DecryptedLines {
+    fn open(log_path: &str, layout: EncryptedLayout, priv_path: &str) -> Result<Self, String> {
+        let decryptor = RecordDecryptor::new(&layout.env, priv_path)?;
+        Ok(Self::new(open_log(Path::new(log_path))?, layout, decryptor))
+    }
+
+    fn new(reader: Box<dyn Read>, layout: EncryptedLayout, decryptor: RecordDecryptor) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            decryptor,
+            skip: layout.skip,
+            strict: layout.strict,
+            line_no: 0,
+            offset: 0,
+            buf: Vec::new(),
         }
+    }
 
-        if !saw_first {
-            saw_first = true;
-            continue;
+    /// The next record: its line number in the encrypted file, its `inner_type`
+    /// and its plaintext. `None` at the end of the file.
+    fn next_record(&mut self) -> Result<Option<(usize, String, String)>, String> {
+        loop {
+            self.buf.clear();
+            let n = self
+                .reader
+                .read_until(b'\n', &mut self.buf)
+                .map_err(|e| format!("read line: {}", e))?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.line_no += 1;
+            let line_no = self.line_no;
+            let line_offset = self.offset;
+            self.offset += n as u64;
+
+            let raw = std::str::from_utf8(&self.buf).map_err(|_| format!("line {}: not valid UTF-8", line_no))?;
+            if self.strict {
+                check_strict_line(raw).map_err(|e| format!("line {}: {}", line_no, e))?;
+            }
+            let s = normalize_line(raw);
+            if s.is_empty() {
+                continue;
+            }
+
+            if self.skip > 0 {
+                self.skip -= 1;
+                continue;
+            }
+            // A rotated data key; its envelope is not part of the chain
+            if let Some(env) = RecordDecryptor::parse_envelope(s) {
+                self.decryptor.add_envelope(&env).map_err(|e| format!("line {}: {}", line_no, e))?;
+                continue;
+            }
+
+            let (inner, pt_str) = self
+                .decryptor
+                .decrypt_record(s)
+                .map_err(|e| format!("line {}: {}", line_no, e))?;
+            check_record_offset(&pt_str, line_offset)
+                .map_err(|e| format!("line {}: {} record: {}", line_no, inner, e))?;
+            return Ok(Some((line_no, inner, pt_str)));
         }
+    }
+}
+
+/// [`DecryptedLines`] as JSONL, for the readers of plaintext logs. A decryption
+/// failure is a read error (`InvalidData`) naming the line of the encrypted file.
+struct DecryptingReader {
+    lines: DecryptedLines,
+    /// The current record and its newline, sent from `pos` on
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for DecryptingReader {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.pending.len() {
+            match self.lines.next_record() {
+                Ok(Some((_, _, pt_str))) => {
+                    self.pending.clear();
+                    self.pending.extend_from_slice(pt_str.as_bytes());
+                    self.pending.push(b'\n');
+                    self.pos = 0;
+                }
+                Ok(None) => return Ok(0),
+                Err(e) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("decryption: {}", e),
+                    ))
+                }
+            }
+        }
+        let n = out.len().min(self.pending.len() - self.pos);
+        out[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// ===== Record decryption =====
+
+/// Decrypts the `Encrypted` records of one run, given its first KeyEnvelope and
+/// then each one written when the data key was rotated.
+pub struct RecordDecryptor {
+    run_id: String,
+    recipient_sk: [u8; 32],
+    /// Indexed by key generation
+    deks: Vec<DataKey>,
+    /// Seq of the last record decrypted
+    last_seq: Option<u64>,
+    /// Nonce prefix of the run's version 3 records, from the first one
+    nonce_prefix: Option<[u8; 4]>,
+}
+
+impl Drop for RecordDecryptor {
+    fn drop(&mut self) {
+        self.recipient_sk.zeroize();
+    }
+}
+
+impl RecordDecryptor {
+    pub fn new(env: &KeyEnvelope, recipient_privkey_b64_path: &str) -> Result<Self, String> {
+        let recipient_sk = crate::keys::load_key32(recipient_privkey_b64_path, "recipient private key")?;
+        Self::with_secret(env, recipient_sk)
+    }
+
+    /// [`RecordDecryptor::new`] with a recipient private key already loaded.
+    pub fn with_secret(env: &KeyEnvelope, recipient_sk: [u8; 32]) -> Result<Self, String> {
+        let mut decryptor = Self {
+            run_id: env.run_id.clone(),
+            recipient_sk,
+            deks: Vec::new(),
+            last_seq: None,
+            nonce_prefix: None,
+        };
+        decryptor.add_envelope(env)?;
+        Ok(decryptor)
+    }
+
+    /// Unwrap the data key of a later KeyEnvelope. Generations must follow each
+    /// other, so an envelope that was dropped or moved is noticed here.
+    pub fn add_envelope(&mut self, env: &KeyEnvelope) -> Result<(), String> {
+        if env.run_id != self.run_id {
+            return Err("KeyEnvelope run_id mismatch (possible splicing)".to_string());
+        }
+        if env.key_generation as usize != self.deks.len() {
+            return Err(format!(
+                "KeyEnvelope for key generation {} where generation {} was expected",
+                env.key_generation,
+                self.deks.len()
+            ));
+        }
+        self.deks.push(unwrap_envelope(env, &self.recipient_sk)?);
+        Ok(())
+    }
+
+    /// Parse a KeyEnvelope line, or `None` if the line is some other record.
+    pub fn parse_envelope(line: &str) -> Option<KeyEnvelope> {
+        serde_json::from_str::<KeyEnvelope>(normalize_line(line))
+            .ok()
+            .filter(|env| env.record_type == "KeyEnvelope")
+    }
+
+    /// Decrypt one `Encrypted` line back to the plaintext record JSON. Lines
+    /// must be given in file order: a version 2 record whose seq is not after the
+    /// previous one's was reordered or duplicated.
+    pub fn decrypt_line(&mut self, line: &str) -> Result<String, String> {
+        self.decrypt_record(line).map(|(_, plaintext)| plaintext)
+    }
 
-        let rec: EncryptedRecord =
-            serde_json::from_str(s).map_err(|e| format!("parse EncryptedRecord: {}", e))?;
+    /// [`RecordDecryptor::decrypt_line`], also returning the record's `inner_type`.
+    pub fn decrypt_record(&mut self, line: &str) -> Result<(String, String), String> {
+        let rec: EncryptedRecord = serde_json::from_str(normalize_line(line))
+            .map_err(|e| format!("parse EncryptedRecord: {}", e))?;
         if rec.record_type != "Encrypted" {
             return Err(format!("unexpected record_type {}", rec.record_type));
         }
-        if rec.run_id != env.run_id {
+        let plaintext = self.open_record(&rec).map_err(|e| format!("{} record: {}", rec.inner_type, e))?;
+        Ok((rec.inner_type, plaintext))
+    }
+
+    /// A version 3 nonce must be the run's prefix followed by the record's seq.
+    fn check_counter_nonce(&self, nonce: &[u8; 12], seq: u64) -> Result<(), String> {
+        let prefix = [nonce[0], nonce[1], nonce[2], nonce[3]];
+        if *nonce != counter_nonce(prefix, seq) {
+            return Err(format!("nonce counter does not match record seq {}", seq));
+        }
+        match self.nonce_prefix {
+            Some(expected) if expected != prefix => {
+                Err("nonce prefix differs from the run's earlier records (records spliced from another sink)".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn open_record(&mut self, rec: &EncryptedRecord) -> Result<String, String> {
+        if rec.run_id != self.run_id {
             return Err("run_id mismatch (possible splicing)".to_string());
         }
+        match (rec.version, rec.seq) {
+            (1, None) | (2 | 3, Some(_)) => {}
+            (1, Some(_)) => return Err("version 1 record with a seq".to_string()),
+            (v @ (2 | 3), None) => return Err(format!("version {} record without a seq", v)),
+            (v, _) => return Err(format!("unsupported Encrypted record version {}", v)),
+        }
 
         let nonce_bytes = B64
             .decode(rec.nonce_b64.trim())
@@ -369,13 +1690,27 @@ pub fn maybe_decrypt_to_temp_plaintext(
         }
         let mut nonce = [0u8; 12];
         nonce.copy_from_slice(&nonce_bytes);
+        if let (3, Some(seq)) = (rec.version, rec.seq) {
+            self.check_counter_nonce(&nonce, seq)?;
+        }
 
         let ct = B64
             .decode(rec.ciphertext_b64.trim())
             .map_err(|e| format!("decode ciphertext: {}", e))?;
 
-        let aad = format!("{}|{}", env.run_id, rec.inner_type);
-
+        let aad = record_aad(
+            &self.run_id,
+            &rec.inner_type,
+            rec.seq,
+            rec.compression.as_deref(),
+            rec.key_generation,
+        );
+
+        let dek = self
+            .deks
+            .get(rec.key_generation as usize)
+            .ok_or_else(|| format!("no KeyEnvelope for key generation {}", rec.key_generation))?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&dek.0));
         let pt = cipher
             .decrypt(
                 Nonce::from_slice(&nonce),
@@ -385,16 +1720,399 @@ pub fn maybe_decrypt_to_temp_plaintext(
                 },
             )
             .map_err(|_| "decrypt failed (bad key or tampered ciphertext)".to_string())?;
+        if let Some(seq) = rec.seq {
+            if let Some(last) = self.last_seq.filter(|last| seq <= *last) {
+                return Err(format!(
+                    "record seq {} is out of order after seq {} (records reordered or duplicated)",
+                    seq, last
+                ));
+            }
+            self.last_seq = Some(seq);
+        }
+        if let (3, None) = (rec.version, self.nonce_prefix) {
+            self.nonce_prefix = Some([nonce[0], nonce[1], nonce[2], nonce[3]]);
+        }
+        let pt = match rec.compression.as_deref() {
+            None => pt,
+            Some("zstd") => zstd::stream::decode_all(pt.as_slice()).map_err(|e| format!("decompress: {}", e))?,
+            Some(other) => return Err(format!("unsupported compression {:?}", other)),
+        };
 
-        let pt_str =
-            String::from_utf8(pt).map_err(|_| "decrypted payload not utf8".to_string())?;
+        String::from_utf8(pt).map_err(|_| "decrypted payload not utf8".to_string())
+    }
+}
 
-        writeln!(tmp, "{}", pt_str).map_err(|e| format!("write decrypted: {}", e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+    use std::task::{Context, Poll};
+
+    /// An in-memory output that takes `budget` more bytes, then fails every write.
+    #[derive(Clone)]
+    struct MemOutput {
+        data: Arc<Mutex<Vec<u8>>>,
+        budget: Arc<AtomicUsize>,
+        can_truncate: bool,
+        /// Its length at each sync
+        syncs: Arc<Mutex<Vec<usize>>>,
     }
 
-    let (_file, path) = tmp
-        .keep()
-        .map_err(|e| format!("persist temp file: {}", e))?;
+    impl MemOutput {
+        fn new() -> Self {
+            Self {
+                data: Arc::default(),
+                budget: Arc::new(AtomicUsize::new(usize::MAX)),
+                can_truncate: true,
+                syncs: Arc::default(),
+            }
+        }
 
-    Ok(path)
-}
\ No newline at end of file
+        fn syncs(&self) -> Vec<usize> {
+            self.syncs.lock().unwrap().clone()
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            self.data.lock().unwrap().clone()
+        }
+    }
+
+    impl AsyncWrite for MemOutput {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+            let n = buf.len().min(self.budget.load(Ordering::SeqCst));
+            if n == 0 {
+                return Poll::Ready(Err(std::io::Error::other("disk gone")));
+            }
+            self.budget.fetch_sub(n, Ordering::SeqCst);
+            self.data.lock().unwrap().extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl LogOutput for MemOutput {
+        async fn sync_data(&mut self) -> std::io::Result<()> {
+            let len = self.data.lock().unwrap().len();
+            self.syncs.lock().unwrap().push(len);
+            Ok(())
+        }
+
+        async fn end(&mut self) -> std::io::Result<u64> {
+            Ok(self.data.lock().unwrap().len() as u64)
+        }
+
+        async fn truncate(&mut self, len: u64) -> std::io::Result<()> {
+            if !self.can_truncate {
+                return Err(std::io::Error::other("not seekable"));
+            }
+            self.data.lock().unwrap().truncate(len as usize);
+            Ok(())
+        }
+    }
+
+    async fn mirrored(primary: &MemOutput, mirror: &MemOutput) -> (AuditSink<MemOutput>, Arc<SinkStats>) {
+        let stats = Arc::new(SinkStats::new(&["primary".to_string(), "mirror".to_string()]));
+        let outputs = Outputs::new(
+            vec![(PathBuf::from("primary"), primary.clone()), (PathBuf::from("mirror"), mirror.clone())],
+            Quorum::All,
+            stats.clone(),
+        );
+        let sink = AuditSink::new(outputs, "run", &Encryption::None, Compression::None).await.unwrap();
+        (sink, stats)
+    }
+
+    #[tokio::test]
+    async fn quorum_all_takes_a_failed_record_back_out_of_every_output() {
+        let (primary, mirror) = (MemOutput::new(), MemOutput::new());
+        let (mut sink, stats) = mirrored(&primary, &mirror).await;
+        sink.write_record("Event", r#"{"n":1}"#).await.unwrap();
+        let before = (primary.bytes(), sink.position());
+
+        // The mirror takes part of the record, then fails.
+        mirror.budget.store(4, Ordering::SeqCst);
+        assert!(sink.write_record("Event", r#"{"n":2}"#).await.is_err());
+        assert_eq!((primary.bytes(), sink.position()), before);
+        assert_eq!(mirror.bytes(), before.0);
+        assert!(sink.outputs().broken().is_none());
+
+        // The record that follows lands at the same offset in both.
+        mirror.budget.store(usize::MAX, Ordering::SeqCst);
+        sink.write_record("Event", r#"{"n":3}"#).await.unwrap();
+        assert_eq!(primary.bytes(), mirror.bytes());
+        assert!(String::from_utf8(primary.bytes()).unwrap().ends_with("}\n{\"n\":1}\n{\"n\":3}\n"));
+        assert_eq!(stats.snapshot()[1]["errors"], 1);
+        assert_eq!(stats.snapshot()[0]["errors"], 0);
+    }
+
+    #[tokio::test]
+    async fn quorum_all_breaks_when_a_failed_record_cannot_be_taken_back() {
+        let primary = MemOutput::new();
+        let mirror = MemOutput { can_truncate: false, ..MemOutput::new() };
+        let (mut sink, _) = mirrored(&primary, &mirror).await;
+        let header = primary.bytes();
+
+        mirror.budget.store(0, Ordering::SeqCst);
+        assert!(sink.write_record("Event", r#"{"n":1}"#).await.is_err());
+        assert!(sink.outputs().broken().is_some());
+        // The primary got the record back out even so, and takes nothing more.
+        assert_eq!(primary.bytes(), header);
+        mirror.budget.store(usize::MAX, Ordering::SeqCst);
+        assert!(sink.write_record("Event", r#"{"n":2}"#).await.is_err());
+        assert!(sink.flush().await.is_err());
+        assert_eq!(primary.bytes(), header);
+    }
+
+    #[tokio::test]
+    async fn a_log_whose_data_key_rotated_decrypts_and_verifies() {
+        let dir = tempfile::tempdir().unwrap();
+        keygen_recipient(dir.path(), None).unwrap();
+        let recipient = dir.path().join("recipient_pub.b64").display().to_string();
+        let recipient_priv = dir.path().join("recipient_priv.b64").display().to_string();
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let pubkey = dir.path().join("pub.b64");
+        fs::write(&pubkey, B64.encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        // The audit loop's order: rotate if due, then the event at the offset after it
+        let log_path = dir.path().join("audit.jsonl");
+        let file = tokio::fs::File::create(&log_path).await.unwrap();
+        let outputs = Outputs::new(vec![(log_path.clone(), file)], Quorum::Any, Arc::new(SinkStats::new(&[])));
+        let encryption = Encryption::Envelope { recipient, escrow: None };
+        let mut sink = AuditSink::new(outputs, "run", &encryption, Compression::None)
+            .await
+            .unwrap()
+            .with_dek_rotation(DekRotation { max_records: Some(2), max_age: None });
+        let mut chain = crate::audit::AuditChain::new(None);
+        let mut generations = Vec::new();
+        for n in 0..6 {
+            if let Some(generation) = sink.rotate_dek_if_due().await.unwrap() {
+                generations.push(generation);
+            }
+            let mut log = crate::events::McpLog::synthetic(
+                "run".into(),
+                "test/event",
+                serde_json::json!({ "n": n }),
+                "session",
+                "trace",
+                1_000,
+                "span".into(),
+            );
+            let prepared = chain.prepare(&mut log, sink.position()).unwrap();
+            sink.write_record("Event", &serde_json::to_string(&prepared.record).unwrap()).await.unwrap();
+            chain.commit(log.event_id, prepared.entry_hash);
+        }
+        let cp = chain.checkpoint(&signing_key, "run", 2_000).unwrap();
+        sink.write_record("Checkpoint", &serde_json::to_string(&cp).unwrap()).await.unwrap();
+        sink.close().await.unwrap();
+        assert_eq!(generations, [1, 2]);
+
+        let log_path = log_path.display().to_string();
+        let plaintext = open_plaintext(&log_path, Some(&recipient_priv)).unwrap();
+        assert!(plaintext.offsets_checked);
+        let options = crate::audit::VerifyOptions { check_offsets: false, ..Default::default() };
+        let summary = crate::audit::verify_log(plaintext.reader, &pubkey.display().to_string(), &options).unwrap();
+        assert_eq!((summary.events_verified, summary.checkpoints_verified), (6, 1));
+
+        // `sentinel decrypt` reads the same map of key generations
+        let mut records = Vec::new();
+        decrypt_records(&log_path, &recipient_priv, |_, plaintext| {
+            records.push(plaintext.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(records.len(), 7);
+        assert!(records[5].contains(r#""n":5"#), "{}", records[5]);
+    }
+
+    /// An encrypted sink on `out` and the start the audit loop writes before
+    /// any event, made durable as `policy` says.
+    async fn started(out: &MemOutput, policy: FsyncPolicy) -> (AuditSink<MemOutput>, Durability) {
+        let dir = tempfile::tempdir().unwrap();
+        keygen_recipient(dir.path(), None).unwrap();
+        let encryption = Encryption::Envelope {
+            recipient: dir.path().join("recipient_pub.b64").display().to_string(),
+            escrow: None,
+        };
+        let outputs = Outputs::new(vec![(PathBuf::from("audit"), out.clone())], Quorum::Any, Arc::new(SinkStats::new(&[])));
+        let mut sink = AuditSink::new(outputs, "run", &encryption, Compression::None).await.unwrap();
+        sink.write_record("Checkpoint", r#"{"start":true}"#).await.unwrap();
+        let mut durability = Durability::new(policy);
+        durability.barrier(&mut sink).await.unwrap();
+        (sink, durability)
+    }
+
+    /// The lines `out` holds.
+    fn lines(out: &MemOutput) -> Vec<String> {
+        String::from_utf8(out.bytes()).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn the_start_of_an_encrypted_log_is_durable_before_any_event() {
+        for policy in [
+            FsyncPolicy::Interval(std::time::Duration::from_millis(10)),
+            FsyncPolicy::EveryCheckpoint,
+            FsyncPolicy::EveryEvent,
+        ] {
+            let out = MemOutput::new();
+            let (_, durability) = started(&out, policy).await;
+            // FileHeader, KeyEnvelope and the start checkpoint, all synced
+            let lines = lines(&out);
+            assert_eq!(lines.len(), 3);
+            assert!(RecordDecryptor::parse_envelope(&lines[1]).is_some());
+            assert_eq!(out.syncs(), [out.bytes().len()], "{:?}", policy);
+            assert!(!durability.unsynced());
+        }
+
+        let out = MemOutput::new();
+        started(&out, FsyncPolicy::Never).await;
+        assert!(out.syncs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn every_event_syncs_once_per_batch_of_queued_events() {
+        let out = MemOutput::new();
+        let (mut sink, mut durability) = started(&out, FsyncPolicy::EveryEvent).await;
+        for more_queued in [true, true, false] {
+            sink.write_record("Event", "{}").await.unwrap();
+            durability.event_written(&mut sink, more_queued).await.unwrap();
+        }
+        // The start, then the three events together
+        assert_eq!(out.syncs().len(), 2);
+        assert_eq!(out.syncs()[1], out.bytes().len());
+
+        sink.write_record("Event", "{}").await.unwrap();
+        durability.event_written(&mut sink, false).await.unwrap();
+        assert_eq!(out.syncs().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn every_checkpoint_syncs_only_at_checkpoints() {
+        let out = MemOutput::new();
+        let (mut sink, mut durability) = started(&out, FsyncPolicy::EveryCheckpoint).await;
+        sink.write_record("Event", "{}").await.unwrap();
+        durability.event_written(&mut sink, false).await.unwrap();
+        assert_eq!(out.syncs().len(), 1);
+        assert!(durability.unsynced());
+
+        sink.write_record("Checkpoint", "{}").await.unwrap();
+        durability.checkpointed(&mut sink).await.unwrap();
+        assert_eq!(out.syncs().len(), 2);
+        assert_eq!(out.syncs()[1], out.bytes().len());
+    }
+
+    #[tokio::test]
+    async fn interval_and_never_leave_events_and_checkpoints_to_the_timer() {
+        let interval = FsyncPolicy::Interval(std::time::Duration::from_millis(10));
+        for policy in [interval, FsyncPolicy::Never] {
+            let out = MemOutput::new();
+            let (mut sink, mut durability) = started(&out, policy).await;
+            let syncs = out.syncs().len();
+            sink.write_record("Event", "{}").await.unwrap();
+            durability.event_written(&mut sink, false).await.unwrap();
+            sink.write_record("Checkpoint", "{}").await.unwrap();
+            durability.checkpointed(&mut sink).await.unwrap();
+            assert_eq!(out.syncs().len(), syncs, "{:?}", policy);
+            assert!(durability.unsynced());
+            assert_eq!(durability.ticker().is_some(), policy == interval);
+        }
+
+        // What a tick of the timer does
+        let out = MemOutput::new();
+        let (mut sink, mut durability) = started(&out, interval).await;
+        sink.write_record("Event", "{}").await.unwrap();
+        durability.event_written(&mut sink, false).await.unwrap();
+        durability.sync(&mut sink).await.unwrap();
+        assert_eq!(out.syncs(), [out.syncs()[0], out.bytes().len()]);
+        assert!(!durability.unsynced());
+    }
+
+    /// A verbose agent session: a tool listing with its schemas every tenth
+    /// event, and tool results in between that differ only in their values.
+    fn verbose_session() -> Vec<crate::events::McpLog> {
+        let tools: Vec<serde_json::Value> = (0..20)
+            .map(|i| {
+                serde_json::json!({
+                    "name": format!("tool_{}", i),
+                    "description": "Look up records in the inventory service and return matching rows as text.",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "query": { "type": "string", "description": "Free-text filter" },
+                            "limit": { "type": "integer", "minimum": 1, "maximum": 500 },
+                            "region": { "type": "string", "enum": ["eu-west-1", "us-east-1", "ap-south-1"] }
+                        },
+                        "required": ["query"]
+                    }
+                })
+            })
+            .collect();
+        (0..400u64)
+            .map(|n| {
+                let (method, payload) = if n % 10 == 0 {
+                    ("tools/list", serde_json::json!({ "result": { "tools": tools } }))
+                } else {
+                    let text = format!("row {}: status=ok region=eu-west-1 latency_ms={} owner=inventory", n, n * 7 % 300);
+                    ("tools/call", serde_json::json!({ "result": { "content": [{ "type": "text", "text": text }] } }))
+                };
+                crate::events::McpLog::synthetic("run".into(), method, payload, "session", "trace", 1_000 + n, format!("span-{}", n))
+            })
+            .collect()
+    }
+
+    /// The size of `verbose_session` logged to `name` as `encryption` and
+    /// `compression` say, once the log is checked to still verify.
+    async fn logged_size(dir: &Path, name: &str, encryption: &Encryption, compression: Compression) -> u64 {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let pubkey = dir.join("pub.b64");
+        fs::write(&pubkey, B64.encode(signing_key.verifying_key().to_bytes())).unwrap();
+        let log_path = dir.join(name);
+        let file = tokio::fs::File::create(&log_path).await.unwrap();
+        let outputs = Outputs::new(vec![(log_path.clone(), file)], Quorum::Any, Arc::new(SinkStats::new(&[])));
+        let mut sink = AuditSink::new(outputs, "run", encryption, compression).await.unwrap();
+        let mut chain = crate::audit::AuditChain::new(None);
+        for mut log in verbose_session() {
+            let prepared = chain.prepare(&mut log, sink.position()).unwrap();
+            sink.write_record("Event", &serde_json::to_string(&prepared.record).unwrap()).await.unwrap();
+            chain.commit(log.event_id, prepared.entry_hash);
+        }
+        let cp = chain.checkpoint(&signing_key, "run", 2_000).unwrap();
+        sink.write_record("Checkpoint", &serde_json::to_string(&cp).unwrap()).await.unwrap();
+        sink.close().await.unwrap();
+
+        let recipient_priv = dir.join("recipient_priv.b64").display().to_string();
+        let plaintext = open_plaintext(&log_path.display().to_string(), Some(&recipient_priv)).unwrap();
+        let options = crate::audit::VerifyOptions { check_offsets: false, ..Default::default() };
+        let summary = crate::audit::verify_log(plaintext.reader, &pubkey.display().to_string(), &options).unwrap();
+        assert_eq!((summary.events_verified, summary.checkpoints_verified), (400, 1));
+        fs::metadata(&log_path).unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn zstd_shrinks_a_verbose_session() {
+        let dir = tempfile::tempdir().unwrap();
+        keygen_recipient(dir.path(), None).unwrap();
+        let envelope = Encryption::Envelope {
+            recipient: dir.path().join("recipient_pub.b64").display().to_string(),
+            escrow: None,
+        };
+        let plain = logged_size(dir.path(), "plain.jsonl", &Encryption::None, Compression::None).await;
+        let plain_zstd = logged_size(dir.path(), "plain.jsonl.zst", &Encryption::None, Compression::Zstd).await;
+        let sealed = logged_size(dir.path(), "sealed.jsonl", &envelope, Compression::None).await;
+        let sealed_zstd = logged_size(dir.path(), "sealed_zstd.jsonl", &envelope, Compression::Zstd).await;
+        println!("plaintext: {} -> {} bytes ({:.1}x)", plain, plain_zstd, plain as f64 / plain_zstd as f64);
+        println!("encrypted: {} -> {} bytes ({:.1}x)", sealed, sealed_zstd, sealed as f64 / sealed_zstd as f64);
+
+        // One stream shares its window across records; a sealed record is
+        // compressed alone, so only what repeats within it shrinks.
+        assert!(plain_zstd * 10 <= plain, "{} -> {}", plain, plain_zstd);
+        assert!(sealed_zstd * 2 <= sealed, "{} -> {}", sealed, sealed_zstd);
+    }
+}
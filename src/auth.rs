@@ -0,0 +1,130 @@
+use crate::keys;
+
+use serde::Serialize;
+
+/// What a token lets its holder do. Ordered: an admin can do what a viewer can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read the stream, the history and the run's status
+    Viewer,
+    /// Also pause and resume traffic, force checkpoints and download the audit log
+    Admin,
+}
+
+impl Role {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "viewer" => Some(Role::Viewer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// The tokens the dashboard and API accept, and the role of each. Without any,
+/// every request is let in as an admin.
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    /// Hashes of the tokens: `blake3::Hash` compares in constant time, and the
+    /// tokens themselves are not kept
+    tokens: Vec<(blake3::Hash, Role)>,
+}
+
+impl AuthConfig {
+    /// Tokens from `--ws-token` values (`viewer:<token>`, `admin:<token>`, or a
+    /// bare token without a ':', which is an admin's) and from the lines of a tokens file, in
+    /// the same form; blank lines and `#` comments are skipped.
+    pub fn from_args(tokens: &[String], tokens_file: Option<&str>) -> Result<Self, String> {
+        let mut auth = Self::default();
+        for spec in tokens {
+            auth.add(spec).map_err(|e| format!("--ws-token: {}", e))?;
+        }
+        if let Some(file) = tokens_file {
+            let text = keys::load_text(file, "WebSocket tokens file")?;
+            for (i, line) in text.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                auth.add(line).map_err(|e| format!("{} line {}: {}", file, i + 1, e))?;
+            }
+        }
+        Ok(auth)
+    }
+
+    /// A spec is a bare token only without a ':'; anything before one must
+    /// name a role, so a mistyped `<role>:` never yields an admin token.
+    fn add(&mut self, spec: &str) -> Result<(), String> {
+        let (role, token) = match spec.split_once(':') {
+            Some((role, token)) => match Role::parse(role) {
+                Some(role) => (role, token),
+                // Not echoed: it may be part of a secret.
+                None => return Err("unknown role before ':' (expected viewer or admin)".to_string()),
+            },
+            None => (Role::Admin, spec),
+        };
+        if token.is_empty() {
+            return Err(format!("empty {} token", role));
+        }
+        let hash = blake3::hash(token.as_bytes());
+        match self.tokens.iter().find(|(h, _)| *h == hash) {
+            Some((_, other)) if *other != role => Err(format!("the same token is given as {} and {}", other, role)),
+            Some(_) => Ok(()),
+            None => {
+                self.tokens.push((hash, role));
+                Ok(())
+            }
+        }
+    }
+
+    /// Requests need a token.
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// The role of `token`, if it is one. Every configured token is compared,
+    /// in constant time, whichever matches.
+    pub fn role_of(&self, token: &str) -> Option<Role> {
+        let hash = blake3::hash(token.as_bytes());
+        self.tokens
+            .iter()
+            .fold(None, |found, (h, role)| if *h == hash { Some(*role) } else { found })
+    }
+
+    /// How many tokens there are of `role`.
+    pub fn count(&self, role: Role) -> usize {
+        self.tokens.iter().filter(|(_, r)| *r == role).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_spec_is_a_role_and_token_or_a_bare_token() {
+        let auth = AuthConfig::from_args(&["viewer:v".into(), "admin:a:b".into(), "bare".into()], None).unwrap();
+        assert_eq!(auth.role_of("v"), Some(Role::Viewer));
+        assert_eq!(auth.role_of("a:b"), Some(Role::Admin));
+        assert_eq!(auth.role_of("bare"), Some(Role::Admin));
+    }
+
+    #[test]
+    fn an_unknown_role_prefix_is_refused() {
+        for spec in ["veiwer:token", "Viewer:token", "read:token", ":token"] {
+            let err = AuthConfig::from_args(&[spec.to_string()], None).unwrap_err();
+            assert!(err.contains("unknown role"), "{}: {}", spec, err);
+            assert!(!err.contains(spec), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::events::McpLog;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+/// Replace a payload larger than `max_bytes` (as serialized JSON) with a stub
+/// naming its blake3 hash and size. Returns the serialized original, for the
+/// blob store; the hash chain covers the stub.
+pub fn offload_payload(log: &mut McpLog, max_bytes: usize) -> Option<Vec<u8>> {
+    let bytes = serde_json::to_vec(&log.payload).ok()?;
+    if bytes.len() <= max_bytes {
+        return None;
+    }
+    log.payload = json!({
+        "sentinel_truncated": true,
+        "blake3": blake3::hash(&bytes).to_hex().to_string(),
+        "original_bytes": bytes.len(),
+    });
+    Some(bytes)
+}
+
+/// Directory of offloaded payloads, one file per content hash (`--blob-dir`).
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn create(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| format!("create blob dir {}: {}", dir.display(), e))?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    /// Store `bytes` under their hash. Content already present is not rewritten.
+    pub async fn put(&self, bytes: &[u8]) -> Result<String, String> {
+        let hash = blake3::hash(bytes).to_hex().to_string();
+        let path = self.dir.join(&hash);
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(hash);
+        }
+        // Written under a temporary name first, so a blob is either whole or absent.
+        let tmp = self.dir.join(format!(".{}.tmp", hash));
+        tokio::fs::write(&tmp, bytes)
+            .await
+            .map_err(|e| format!("write blob {}: {}", tmp.display(), e))?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("store blob {}: {}", path.display(), e))?;
+        Ok(hash)
+    }
+}
+
+/// Read the blob for `hash` from `dir`, checking it still matches the hash.
+pub fn fetch(dir: impl AsRef<Path>, hash: &str) -> Result<Vec<u8>, String> {
+    let hash = hash.trim().to_ascii_lowercase();
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} is not a blake3 hash (64 hex characters)", hash));
+    }
+    let path = dir.as_ref().join(&hash);
+    let bytes = std::fs::read(&path).map_err(|e| format!("read blob {}: {}", path.display(), e))?;
+    if blake3::hash(&bytes).to_hex().as_str() != hash {
+        return Err(format!("blob {} does not match its hash", path.display()));
+    }
+    Ok(bytes)
+}
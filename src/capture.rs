@@ -0,0 +1,82 @@
+use crate::events::{StreamDirection, TapEvent};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+/// One sequenced tap as written to a raw capture (`--raw-capture`), one JSON
+/// object per line. Bytes are stored exactly as observed, before redaction.
+#[derive(Debug, Serialize, Deserialize)]
+struct CaptureRecord {
+    seq: u64,
+    direction: StreamDirection,
+    observed_ts_ms: u64,
+    bytes_b64: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+}
+
+/// Appends every tap event the parser sees, in sequence order.
+pub struct CaptureWriter {
+    out: BufWriter<tokio::fs::File>,
+}
+
+impl CaptureWriter {
+    pub async fn create(path: &str) -> Result<Self, String> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| format!("create raw capture {}: {}", path, e))?;
+        Ok(Self { out: BufWriter::new(file) })
+    }
+
+    pub async fn write(&mut self, evt: &TapEvent) -> Result<(), String> {
+        let rec = CaptureRecord {
+            seq: evt.seq,
+            direction: evt.direction,
+            observed_ts_ms: evt.observed_ts_ms,
+            bytes_b64: B64.encode(&evt.bytes),
+            server: evt.server.as_deref().map(str::to_string),
+        };
+        let mut line = serde_json::to_vec(&rec).map_err(|e| format!("serialize capture record: {}", e))?;
+        line.push(b'\n');
+        self.out
+            .write_all(&line)
+            .await
+            .map_err(|e| format!("write raw capture: {}", e))
+    }
+
+    pub async fn flush(&mut self) -> Result<(), String> {
+        self.out.flush().await.map_err(|e| format!("flush raw capture: {}", e))
+    }
+}
+
+/// Load a raw capture back into tap events.
+pub fn read_capture(path: &Path) -> Result<Vec<TapEvent>, String> {
+    let file = File::open(path).map_err(|e| format!("open {}: {}", path.display(), e))?;
+    let mut events = Vec::new();
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("read {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let rec: CaptureRecord =
+            serde_json::from_str(&line).map_err(|e| format!("capture line {}: {}", i + 1, e))?;
+        let bytes = B64
+            .decode(&rec.bytes_b64)
+            .map_err(|e| format!("capture line {}: bad bytes_b64: {}", i + 1, e))?;
+        events.push(TapEvent {
+            seq: rec.seq,
+            direction: rec.direction,
+            bytes: Bytes::from(bytes),
+            observed_ts_ms: rec.observed_ts_ms,
+            server: rec.server.map(Arc::from),
+        });
+    }
+
+    Ok(events)
+}
@@ -0,0 +1,130 @@
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Environment and working directory the child is spawned with.
+///
+/// Values may be secrets: they reach the child only, never the audit log or
+/// `Debug` output.
+#[derive(Clone, Default)]
+pub struct ChildEnv {
+    /// Set on top of the inherited environment (or instead of it), in order
+    pub vars: Vec<(String, String)>,
+    /// Start from an empty environment instead of Sentinel's
+    pub clear: bool,
+    pub cwd: Option<PathBuf>,
+}
+
+impl fmt::Debug for ChildEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChildEnv")
+            .field("vars", &self.vars.iter().map(|(k, _)| k).collect::<Vec<_>>())
+            .field("clear", &self.clear)
+            .field("cwd", &self.cwd)
+            .finish()
+    }
+}
+
+impl ChildEnv {
+    pub fn apply(&self, command: &mut Command) {
+        if self.clear {
+            command.env_clear();
+        }
+        command.envs(self.vars.iter().map(|(k, v)| (k, v)));
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+    }
+
+    /// Names of the variables the child will see, sorted.
+    pub fn effective_keys(&self) -> BTreeSet<String> {
+        let mut keys: BTreeSet<String> = if self.clear {
+            BTreeSet::new()
+        } else {
+            std::env::vars_os().map(|(k, _)| k.to_string_lossy().into_owned()).collect()
+        };
+        keys.extend(self.vars.iter().map(|(k, _)| k.clone()));
+        keys
+    }
+
+    /// What the child runs with, for the run-start audit event: key names only.
+    pub fn describe(&self, program: &str) -> Value {
+        let cwd = match &self.cwd {
+            Some(cwd) => Some(cwd.clone()),
+            None => std::env::current_dir().ok(),
+        };
+        json!({
+            "transport": "stdio",
+            "program": program,
+            "cwd": cwd.map(|p| p.display().to_string()),
+            "env_cleared": self.clear,
+            "env_set": self.vars.iter().map(|(k, _)| k).collect::<BTreeSet<_>>(),
+            "env_keys": self.effective_keys(),
+        })
+    }
+
+
+    /// Where the OS finds `program`: taken as a path when it has more than one
+    /// component, otherwise searched on the child's PATH.
+    pub fn resolve_program(&self, program: &str) -> Option<PathBuf> {
+        let program = Path::new(program);
+        if program.components().count() > 1 {
+            return std::fs::canonicalize(program).ok();
+        }
+        let search = match self.vars.iter().rev().find(|(k, _)| k == "PATH") {
+            Some((_, v)) => OsString::from(v),
+            None => std::env::var_os("PATH")?,
+        };
+        std::env::split_paths(&search)
+            .map(|dir| dir.join(program))
+            .find(|p| is_executable(p))
+            .and_then(|p| std::fs::canonicalize(p).ok())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// `KEY=VALUE` from `--child-env`.
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or("expected KEY=VALUE")?;
+    if key.is_empty() || key.contains('\0') || value.contains('\0') {
+        return Err(format!("invalid variable name {:?}", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Read a dotenv-style file: `KEY=VALUE` lines, optionally prefixed with
+/// `export` and with the value in matching quotes; blank lines and `#` comments
+/// are skipped. Errors name the line, never its value.
+pub fn load_file(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("read child env file {}: {}", path.display(), e))?;
+    let mut vars = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = parse_var(line).map_err(|_| format!("{} line {}: expected KEY=VALUE", path.display(), i + 1))?;
+        let key = key.trim().to_string();
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+            .unwrap_or(value);
+        vars.push((key, value.to_string()));
+    }
+    Ok(vars)
+}
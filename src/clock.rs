@@ -0,0 +1,29 @@
+use crate::events::current_timestamp_ms;
+use uuid::Uuid;
+
+/// Wall-clock source for the pipeline. Injected so a run can be re-derived from
+/// its raw capture with the timestamps the original run saw.
+pub trait Clock: Send + Sync {
+    fn now_ms(&self) -> u64;
+}
+
+/// Source of span ids, injected for the same reason as [`Clock`].
+pub trait IdGen: Send + Sync {
+    fn next_id(&self) -> String;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        current_timestamp_ms()
+    }
+}
+
+pub struct RandomIds;
+
+impl IdGen for RandomIds {
+    fn next_id(&self) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
@@ -0,0 +1,122 @@
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// Holds client -> server traffic while paused (incident response). Messages
+/// keep being read and are held in order, up to `max_held_bytes`; the proxy
+/// stops reading from the client beyond that. Resuming releases them.
+#[derive(Clone)]
+pub struct ForwardGate {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    paused: watch::Sender<bool>,
+    paused_at: Mutex<Option<Instant>>,
+    max_held_bytes: usize,
+    held_messages: AtomicU64,
+    held_bytes: AtomicU64,
+    /// Serializes pause/resume so each change is recorded exactly once
+    actions: tokio::sync::Mutex<()>,
+}
+
+impl fmt::Debug for ForwardGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForwardGate")
+            .field("paused", &self.is_paused())
+            .field("max_held_bytes", &self.inner.max_held_bytes)
+            .finish()
+    }
+}
+
+impl Default for ForwardGate {
+    fn default() -> Self {
+        Self::new(16 * 1024 * 1024)
+    }
+}
+
+impl ForwardGate {
+    pub fn new(max_held_bytes: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                paused: watch::Sender::new(false),
+                paused_at: Mutex::new(None),
+                max_held_bytes,
+                held_messages: AtomicU64::new(0),
+                held_bytes: AtomicU64::new(0),
+                actions: tokio::sync::Mutex::new(()),
+            }),
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.inner.paused.subscribe()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.inner.paused.borrow()
+    }
+
+    pub fn max_held_bytes(&self) -> usize {
+        self.inner.max_held_bytes
+    }
+
+    /// Held while pausing or resuming and recording it.
+    pub async fn exclusive(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.inner.actions.lock().await
+    }
+
+    /// Returns false if forwarding was already paused.
+    pub fn pause(&self) -> bool {
+        let changed = self.inner.paused.send_if_modified(|p| !std::mem::replace(p, true));
+        if changed {
+            *self.inner.paused_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        }
+        changed
+    }
+
+    /// Returns false if forwarding wasn't paused.
+    pub fn resume(&self) -> bool {
+        *self.inner.paused_at.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        self.inner.paused.send_if_modified(|p| std::mem::replace(p, false))
+    }
+
+    fn paused_ms(&self) -> Option<u64> {
+        let paused_at = *self.inner.paused_at.lock().unwrap_or_else(|e| e.into_inner());
+        paused_at.map(|t| t.elapsed().as_millis() as u64)
+    }
+
+    /// Returns at once unless paused.
+    pub async fn wait_resumed(&self) {
+        let mut paused = self.subscribe();
+        let _ = paused.wait_for(|p| !*p).await;
+    }
+
+    pub fn held_bytes(&self) -> u64 {
+        self.inner.held_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn hold(&self, bytes: usize) {
+        self.inner.held_messages.fetch_add(1, Ordering::Relaxed);
+        self.inner.held_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn release(&self, bytes: usize) {
+        self.inner.held_messages.fetch_sub(1, Ordering::Relaxed);
+        self.inner.held_bytes.fetch_sub(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// `{"paused": .., "paused_ms": .., "held_messages": .., "held_bytes": .., "max_held_bytes": ..}`
+    pub fn status(&self) -> Value {
+        json!({
+            "paused": self.is_paused(),
+            "paused_ms": self.paused_ms(),
+            "held_messages": self.inner.held_messages.load(Ordering::Relaxed),
+            "held_bytes": self.held_bytes(),
+            "max_held_bytes": self.inner.max_held_bytes,
+        })
+    }
+}
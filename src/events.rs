@@ -1,12 +1,55 @@
-use crate::protocol::JsonRpcMessage;
+use crate::protocol::{JsonRpcMessage, RequestId};
 use bytes::Bytes;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::SystemTime;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum StreamDirection {
     Inbound,  // From child stdout (response)
     Outbound, // From parent stdin (request)
+    Sentinel, // Generated by Sentinel itself (lifecycle / diagnostics)
+    Stderr,   // From child stderr (diagnostic text, not JSON-RPC)
+}
+
+/// Semantic role of a JSON-RPC message, independent of the transport direction.
+///
+/// `direction` records which pipe the bytes travelled on; `role` records who
+/// initiated the exchange, so server-initiated requests (sampling, roots/list)
+/// are not mistaken for responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum MessageRole {
+    ClientRequest,
+    ServerResponse,
+    ServerRequest,
+    ClientResponse,
+    ClientNotification,
+    ServerNotification,
+}
+
+impl MessageRole {
+    pub fn classify(direction: StreamDirection, message: &JsonRpcMessage) -> Option<Self> {
+        let role = match (direction, message) {
+            (StreamDirection::Outbound, JsonRpcMessage::Request(req)) if !req.id.is_null() => {
+                MessageRole::ClientRequest
+            }
+            (StreamDirection::Outbound, JsonRpcMessage::Request(_) | JsonRpcMessage::Notification(_)) => {
+                MessageRole::ClientNotification
+            }
+            (StreamDirection::Outbound, JsonRpcMessage::Response(_)) => MessageRole::ClientResponse,
+            (StreamDirection::Inbound, JsonRpcMessage::Request(req)) if !req.id.is_null() => {
+                MessageRole::ServerRequest
+            }
+            (StreamDirection::Inbound, JsonRpcMessage::Request(_) | JsonRpcMessage::Notification(_)) => {
+                MessageRole::ServerNotification
+            }
+            (StreamDirection::Inbound, JsonRpcMessage::Response(_)) => MessageRole::ServerResponse,
+            (StreamDirection::Sentinel | StreamDirection::Stderr, _) => return None,
+        };
+        Some(role)
+    }
 }
 
 /// Raw bytes as observed by Sentinel (no ordering decided here).
@@ -15,15 +58,40 @@ pub struct RawTap {
     pub direction: StreamDirection,
     pub bytes: Bytes,
     pub observed_ts_ms: u64,
+    /// Which wrapped server the bytes belong to (set by the tap in multiplexed mode)
+    pub server: Option<Arc<str>>,
+    /// Taps dropped right before this one, per direction (set by the tap under
+    /// `--tap-overflow drop`, see [`crate::tap::TapDrops`])
+    pub dropped_before: [u64; 4],
 }
 
-/// Canonical, ordered tap event (ordering decided by the sequencer).
+impl RawTap {
+    /// A lifecycle/diagnostic event raised by the proxy, framed as a JSON-RPC
+    /// notification so it is ordered with the surrounding traffic.
+    pub fn sentinel(method: &str, params: serde_json::Value) -> Self {
+        let msg = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        Self {
+            direction: StreamDirection::Sentinel,
+            bytes: Bytes::from(serde_json::to_vec(&msg).unwrap_or_default()),
+            observed_ts_ms: current_timestamp_ms(),
+            server: None,
+            dropped_before: [0; 4],
+        }
+    }
+}
+
+/// Ordered tap event (ordering decided by the sequencer).
 #[derive(Debug, Clone)]
 pub struct TapEvent {
-    pub event_id: u64,
+    pub seq: u64,
     pub direction: StreamDirection,
     pub bytes: Bytes,
     pub observed_ts_ms: u64,
+    pub server: Option<Arc<str>>,
 }
 
 pub fn current_timestamp_ms() -> u64 {
@@ -33,13 +101,13 @@ pub fn current_timestamp_ms() -> u64 {
         .as_millis() as u64
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct McpLog {
 
     /// Identifier for this run of Sentinel
     pub run_id: String,
     
-    /// Canonical ordering assigned by Sentinel
+    /// Canonical ordering assigned by the audit writer
     pub event_id: u64,
 
     /// When Sentinel observed the bytes (source-of-truth for ordering)
@@ -48,9 +116,17 @@ pub struct McpLog {
     /// When the structured log was emitted (may be slightly later)
     pub timestamp: u64,
 
+    /// Transport direction the bytes were observed on
     pub direction: StreamDirection,
+
+    /// Who initiated the exchange (absent in schema v1 records)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<MessageRole>,
+
     pub method: Option<String>,
-    pub request_id: Option<u64>,
+    /// JSON-RPC id: a number or (since schema v7) a string; absent for
+    /// notifications and `null` ids
+    pub request_id: Option<RequestId>,
     pub latency_ms: Option<u64>,
     pub payload: serde_json::Value,
 
@@ -59,13 +135,137 @@ pub struct McpLog {
     pub trace_id: String,
     pub span_id: String,
     pub parent_span_id: Option<String>,
+
+    /// Name of the wrapped server (schema v6+), when one Sentinel multiplexes several (`run --servers`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+
+    /// Advisory detection rules the payload matched (schema v4+), e.g. `prompt_injection.ignore_previous`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detections: Option<Vec<String>>,
+
+    /// Where the message sat in a JSON-RPC batch (schema v8+)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchPosition>,
+
+    /// The message is a JSON-RPC error response (schema v9+)
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_error: bool,
+    /// `error.code` of an error response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i64>,
+    /// `error.message` of an error response (redacted like the payload)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+
+    /// `params.name` of a `tools/call` request (schema v10+)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    /// `params.uri` of a `resources/read` request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_uri: Option<String>,
+    /// `params.name` of a `prompts/get` request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_name: Option<String>,
+    /// blake3 (hex) of the canonicalized `params` of those requests, as
+    /// logged (after redaction)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params_digest: Option<String>,
+
+    /// `result.isError` of a `tools/call` response (schema v11+): a tool that
+    /// failed still answers with a JSON-RPC result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_is_error: Option<bool>,
+    /// Serialized size of `result.content` of a `tools/call` response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_bytes: Option<u64>,
+
+    /// Session epoch the event belongs to (schema v12+): bumped by each
+    /// `initialize` and each child restart or reconnect, so a run can be split
+    /// into connections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_epoch: Option<u64>,
+    /// Whether a response was paired with its request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pairing: Option<Pairing>,
+
+    /// A `notifications/cancelled` that closed its request's span (schema
+    /// v13+); `latency_ms` is how long the request was pending
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cancelled: bool,
+
+    /// How many times each redaction rule rewrote the event (schema v23+);
+    /// absent when nothing was redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<BTreeMap<String, u64>>,
+    /// Redaction scanned the payload only up to the config's `max_scan_bytes`
+    /// (schema v24+)
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub redaction_truncated_scan: bool,
+}
+
+/// How a response relates to the requests seen so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Pairing {
+    /// Answers a pending request of the same session epoch; shares its span
+    Matched,
+    /// No pending request has its id in this epoch; gets a span of its own
+    Unmatched,
+    /// Answers a request that was cancelled first; shares its span
+    LateResponse,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+/// Position of one element of a batch line. The elements of a batch are logged
+/// in array order, with consecutive event ids and the line's `observed_ts_ms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct BatchPosition {
+    /// 0-based
+    pub index: usize,
+    /// Elements in the batch
+    pub size: usize,
+}
+
+/// What a `tools/call`, `resources/read` or `prompts/get` request acts on.
+#[derive(Default)]
+struct RequestTarget {
+    tool_name: Option<String>,
+    resource_uri: Option<String>,
+    prompt_name: Option<String>,
+    params_digest: Option<String>,
+}
+
+impl RequestTarget {
+    fn of(method: &str, params: &serde_json::Value) -> Self {
+        let field = |key: &str| params.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let mut target = Self::default();
+        match method {
+            "tools/call" => target.tool_name = field("name"),
+            "resources/read" => target.resource_uri = field("uri"),
+            "prompts/get" => target.prompt_name = field("name"),
+            _ => return target,
+        }
+        target.params_digest = Some(params_digest(params));
+        target
+    }
+}
+
+/// blake3 (hex) of the canonicalized `params` of a request.
+pub fn params_digest(params: &serde_json::Value) -> String {
+    let canonical = serde_json::to_vec(&crate::audit::canonicalize_value(params)).unwrap_or_default();
+    blake3::hash(&canonical).to_hex().to_string()
 }
 
 impl McpLog {
+    #[allow(clippy::too_many_arguments)]
     pub fn from_message(
         run_id: String,
-        event_id: u64,
         observed_ts_ms: u64,
+        timestamp: u64,
         direction: StreamDirection,
         message: JsonRpcMessage,
         latency_ms: Option<u64>,
@@ -74,24 +274,41 @@ impl McpLog {
         span_id: String,
         parent_span_id: Option<String>,
     ) -> Self {
-        let timestamp = current_timestamp_ms();
+        let role = MessageRole::classify(direction, &message);
 
         let (method, request_id) = match &message {
-            JsonRpcMessage::Request(req) => (Some(req.method.clone()), req.id),
-            JsonRpcMessage::Response(resp) => (None, resp.id),
+            JsonRpcMessage::Request(req) => (Some(req.method.clone()), req.id.clone().non_null()),
+            JsonRpcMessage::Notification(note) => (Some(note.method.clone()), None),
+            JsonRpcMessage::Response(resp) => (None, resp.id.clone().non_null()),
+        };
+
+        let error = match &message {
+            JsonRpcMessage::Response(resp) => resp.error.as_ref(),
+            _ => None,
+        };
+        let (error_code, error_message) = match error {
+            Some(err) => (Some(err.code), Some(err.message.clone())),
+            None => (None, None),
+        };
+
+        let target = match &message {
+            JsonRpcMessage::Request(req) => RequestTarget::of(&req.method, &req.params),
+            _ => RequestTarget::default(),
         };
 
         let payload = match &message {
             JsonRpcMessage::Request(req) => serde_json::to_value(req).unwrap_or_default(),
+            JsonRpcMessage::Notification(note) => serde_json::to_value(note).unwrap_or_default(),
             JsonRpcMessage::Response(resp) => serde_json::to_value(resp).unwrap_or_default(),
         };
 
         Self {
-            run_id, 
-            event_id,
+            run_id,
+            event_id: 0,
             observed_ts_ms,
             timestamp,
             direction,
+            role,
             method,
             request_id,
             latency_ms,
@@ -100,6 +317,124 @@ impl McpLog {
             trace_id: trace_id.to_string(),
             span_id,
             parent_span_id,
+            server_name: None,
+            detections: None,
+            batch: None,
+            is_error: error.is_some(),
+            error_code,
+            error_message,
+            tool_name: target.tool_name,
+            resource_uri: target.resource_uri,
+            prompt_name: target.prompt_name,
+            params_digest: target.params_digest,
+            tool_is_error: None,
+            result_bytes: None,
+            session_epoch: None,
+            pairing: None,
+            cancelled: false,
+            redactions: None,
+            redaction_truncated_scan: false,
+        }
+    }
+
+    /// Fill in the tool outcome of a response to a `tools/call` request.
+    pub fn set_tool_result(&mut self) {
+        let Some(result) = self.payload.get("result") else {
+            return;
+        };
+        self.tool_is_error = Some(result.get("isError").and_then(|v| v.as_bool()).unwrap_or(false));
+        self.result_bytes = result
+            .get("content")
+            .map(|content| serde_json::to_vec(content).map_or(0, |bytes| bytes.len() as u64));
+    }
+
+    /// A line the child wrote to stderr, kept verbatim (lossily decoded) as the payload.
+    pub fn stderr_line(
+        run_id: String,
+        observed_ts_ms: u64,
+        timestamp: u64,
+        line: String,
+        session_id: &str,
+        trace_id: &str,
+        span_id: String,
+    ) -> Self {
+        Self {
+            run_id,
+            event_id: 0,
+            observed_ts_ms,
+            timestamp,
+            direction: StreamDirection::Stderr,
+            role: None,
+            method: None,
+            request_id: None,
+            latency_ms: None,
+            payload: serde_json::Value::String(line),
+            session_id: session_id.to_string(),
+            trace_id: trace_id.to_string(),
+            span_id,
+            parent_span_id: None,
+            server_name: None,
+            detections: None,
+            batch: None,
+            is_error: false,
+            error_code: None,
+            error_message: None,
+            tool_name: None,
+            resource_uri: None,
+            prompt_name: None,
+            params_digest: None,
+            tool_is_error: None,
+            result_bytes: None,
+            session_epoch: None,
+            pairing: None,
+            cancelled: false,
+            redactions: None,
+            redaction_truncated_scan: false,
+        }
+    }
+
+    /// Build an event generated by Sentinel itself rather than tapped from the wire.
+    pub fn synthetic(
+        run_id: String,
+        method: &str,
+        payload: serde_json::Value,
+        session_id: &str,
+        trace_id: &str,
+        timestamp: u64,
+        span_id: String,
+    ) -> Self {
+        Self {
+            run_id,
+            event_id: 0,
+            observed_ts_ms: timestamp,
+            timestamp,
+            direction: StreamDirection::Sentinel,
+            role: None,
+            method: Some(method.to_string()),
+            request_id: None,
+            latency_ms: None,
+            payload,
+            session_id: session_id.to_string(),
+            trace_id: trace_id.to_string(),
+            span_id,
+            parent_span_id: None,
+            server_name: None,
+            detections: None,
+            batch: None,
+            is_error: false,
+            error_code: None,
+            error_message: None,
+            tool_name: None,
+            resource_uri: None,
+            prompt_name: None,
+            params_digest: None,
+            tool_is_error: None,
+            result_bytes: None,
+            session_epoch: None,
+            pairing: None,
+            cancelled: false,
+            redactions: None,
+            redaction_truncated_scan: false,
         }
     }
 }
@@ -0,0 +1,320 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Live event feed shared by all WebSocket subscribers.
+///
+/// Each event is serialized once and shared as `Arc<str>`. The retained window is
+/// bounded both by event count and by total bytes: when either bound would be
+/// exceeded the oldest events are dropped, so slow subscribers lag sooner instead
+/// of the process holding an unbounded backlog for them.
+pub struct Feed {
+    inner: Mutex<Ring>,
+    /// Sequence number of the next event to be published
+    tail_tx: watch::Sender<u64>,
+    capacity: usize,
+    max_bytes: usize,
+    next_subscriber_id: AtomicU64,
+}
+
+struct Entry {
+    payload: Arc<str>,
+    mark: Watermark,
+    /// Cumulative bytes published up to and including this event
+    end_offset: u64,
+}
+
+#[derive(Default)]
+struct SubscriberStats {
+    /// Next sequence number this subscriber will read
+    cursor: u64,
+    lagged_events: u64,
+}
+
+#[derive(Default)]
+struct Ring {
+    entries: VecDeque<Entry>,
+    /// Sequence number of `entries[0]`
+    head_seq: u64,
+    retained_bytes: usize,
+    published_bytes: u64,
+    subscribers: HashMap<u64, SubscriberStats>,
+    /// Lag accumulated by subscribers that have since disconnected
+    departed_lagged_events: u64,
+    /// Highest mark among events trimmed from the window
+    trimmed_mark: Watermark,
+    /// Highest mark published so far
+    published_mark: Watermark,
+}
+
+impl Ring {
+    fn tail_seq(&self) -> u64 {
+        self.head_seq + self.entries.len() as u64
+    }
+
+    /// Cumulative byte offset at which the event `seq` starts.
+    fn start_offset(&self, seq: u64) -> u64 {
+        if seq < self.head_seq {
+            return self.start_offset(self.head_seq);
+        }
+        match self.entries.get((seq - self.head_seq) as usize) {
+            Some(e) => e.end_offset - e.payload.len() as u64,
+            None => self.published_bytes,
+        }
+    }
+}
+
+/// "Every event up to here has been delivered": the observed time and event id of
+/// the newest audit event a subscriber has received (or skipped while lagging).
+/// Stream metadata, not an audit record; never moves backwards for a subscriber.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Watermark {
+    pub ts_ms: u64,
+    pub event_id: u64,
+}
+
+impl Watermark {
+    fn max(self, other: Watermark) -> Watermark {
+        Watermark {
+            ts_ms: self.ts_ms.max(other.ts_ms),
+            event_id: self.event_id.max(other.event_id),
+        }
+    }
+
+    /// The control frame sent on event streams.
+    pub fn frame(&self) -> String {
+        serde_json::json!({ "op": "watermark", "ts_ms": self.ts_ms, "event_id": self.event_id }).to_string()
+    }
+}
+
+/// What a subscriber gets back from [`Subscriber::recv`].
+pub enum Recv {
+    /// An event and its mark
+    Event(Arc<str>, Watermark),
+    /// This many events were dropped before the subscriber could read them
+    Lagged(u64),
+    Closed,
+}
+
+impl Feed {
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        let (tail_tx, _) = watch::channel(0);
+        Self {
+            inner: Mutex::new(Ring::default()),
+            tail_tx,
+            capacity: capacity.max(1),
+            max_bytes,
+            next_subscriber_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Publish an event written to the audit log; `mark` is its observed time and id.
+    pub fn publish(&self, payload: Arc<str>, mark: Watermark) {
+        let tail = {
+            let mut ring = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            let len = payload.len();
+
+            // Trim from the front until the new event fits both bounds.
+            while !ring.entries.is_empty()
+                && (ring.entries.len() >= self.capacity || ring.retained_bytes + len > self.max_bytes)
+            {
+                if let Some(old) = ring.entries.pop_front() {
+                    ring.retained_bytes -= old.payload.len();
+                    ring.trimmed_mark = ring.trimmed_mark.max(old.mark);
+                    ring.head_seq += 1;
+                }
+            }
+
+            ring.published_bytes += len as u64;
+            let end_offset = ring.published_bytes;
+            ring.retained_bytes += len;
+            ring.published_mark = ring.published_mark.max(mark);
+            ring.entries.push_back(Entry { payload, mark, end_offset });
+            ring.tail_seq()
+        };
+
+        self.tail_tx.send_replace(tail);
+    }
+
+    /// Subscribe to events published from now on.
+    pub fn subscribe(self: &Arc<Self>) -> Subscriber {
+        let id = self.next_subscriber_id.fetch_add(1, Ordering::Relaxed);
+        let mut ring = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let cursor = ring.tail_seq();
+        ring.subscribers.insert(id, SubscriberStats { cursor, lagged_events: 0 });
+
+        // Earlier events reach a new subscriber through the history replay,
+        // which may overlap what it reads from here on.
+        Subscriber {
+            id,
+            cursor,
+            watermark: ring.published_mark,
+            feed: self.clone(),
+            tail_rx: self.tail_tx.subscribe(),
+        }
+    }
+
+    /// Feed gauges and per-subscriber backlog, in OpenMetrics text format (no `# EOF`).
+    pub fn render_openmetrics(&self) -> String {
+        let ring = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE sentinel_feed_retained_events gauge");
+        let _ = writeln!(out, "sentinel_feed_retained_events {}", ring.entries.len());
+        let _ = writeln!(out, "# TYPE sentinel_feed_retained_bytes gauge");
+        let _ = writeln!(out, "sentinel_feed_retained_bytes {}", ring.retained_bytes);
+        let _ = writeln!(out, "# TYPE sentinel_feed_max_bytes gauge");
+        let _ = writeln!(out, "sentinel_feed_max_bytes {}", self.max_bytes);
+        let _ = writeln!(out, "# TYPE sentinel_feed_subscribers gauge");
+        let _ = writeln!(out, "sentinel_feed_subscribers {}", ring.subscribers.len());
+
+        let mut ids: Vec<&u64> = ring.subscribers.keys().collect();
+        ids.sort();
+
+        let _ = writeln!(out, "# TYPE sentinel_feed_subscriber_backlog_bytes gauge");
+        let _ = writeln!(out, "# HELP sentinel_feed_subscriber_backlog_bytes Bytes published but not yet read, per connected subscriber");
+        for id in &ids {
+            let stats = &ring.subscribers[*id];
+            let backlog = ring.published_bytes - ring.start_offset(stats.cursor);
+            let _ = writeln!(out, "sentinel_feed_subscriber_backlog_bytes{{subscriber=\"{}\"}} {}", id, backlog);
+        }
+
+        let lagged: u64 = ring.subscribers.values().map(|s| s.lagged_events).sum::<u64>()
+            + ring.departed_lagged_events;
+        let _ = writeln!(out, "# TYPE sentinel_feed_lagged_events counter");
+        let _ = writeln!(out, "# HELP sentinel_feed_lagged_events Events dropped before a subscriber read them");
+        let _ = writeln!(out, "sentinel_feed_lagged_events_total {}", lagged);
+
+        out
+    }
+}
+
+/// A cursor into the [`Feed`]; unregisters itself on drop.
+pub struct Subscriber {
+    id: u64,
+    cursor: u64,
+    watermark: Watermark,
+    feed: Arc<Feed>,
+    tail_rx: watch::Receiver<u64>,
+}
+
+impl Subscriber {
+    /// Watermark covering everything this subscriber has received so far.
+    pub fn watermark(&self) -> Watermark {
+        self.watermark
+    }
+
+    pub async fn recv(&mut self) -> Recv {
+        loop {
+            self.tail_rx.borrow_and_update();
+            if let Some(next) = self.try_recv() {
+                return next;
+            }
+            if self.tail_rx.changed().await.is_err() {
+                return Recv::Closed;
+            }
+        }
+    }
+
+    /// The next event (or the lag) already published, without waiting for one;
+    /// `None` when the subscriber is current.
+    pub fn try_recv(&mut self) -> Option<Recv> {
+        let mut ring = self.feed.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if self.cursor < ring.head_seq {
+            let missed = ring.head_seq - self.cursor;
+            self.cursor = ring.head_seq;
+            self.watermark = self.watermark.max(ring.trimmed_mark);
+            if let Some(stats) = ring.subscribers.get_mut(&self.id) {
+                stats.cursor = self.cursor;
+                stats.lagged_events += missed;
+            }
+            return Some(Recv::Lagged(missed));
+        }
+        if self.cursor < ring.tail_seq() {
+            let entry = &ring.entries[(self.cursor - ring.head_seq) as usize];
+            let payload = entry.payload.clone();
+            let mark = entry.mark;
+            self.watermark = self.watermark.max(mark);
+            self.cursor += 1;
+            if let Some(stats) = ring.subscribers.get_mut(&self.id) {
+                stats.cursor = self.cursor;
+            }
+            return Some(Recv::Event(payload, mark));
+        }
+        None
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        let mut ring = self.feed.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(stats) = ring.subscribers.remove(&self.id) {
+            ring.departed_lagged_events += stats.lagged_events;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: u64, len: usize) -> (Arc<str>, Watermark) {
+        (Arc::from("x".repeat(len)), Watermark { ts_ms: id * 10, event_id: id })
+    }
+
+    fn retained(feed: &Feed) -> (Vec<u64>, usize) {
+        let ring = feed.inner.lock().unwrap();
+        (ring.entries.iter().map(|e| e.mark.event_id).collect(), ring.retained_bytes)
+    }
+
+    /// Events past the byte ceiling push out the oldest ones, however many
+    /// that takes, and the retained bytes are those of the events kept.
+    #[test]
+    fn the_byte_ceiling_evicts_the_oldest_events() {
+        let feed = Arc::new(Feed::new(100, 1_000));
+        let mut subscriber = feed.subscribe();
+        for (id, len) in [(1, 400), (2, 300), (3, 200)] {
+            let (payload, mark) = event(id, len);
+            feed.publish(payload, mark);
+        }
+        assert_eq!(retained(&feed), (vec![1, 2, 3], 900));
+
+        // 600 more bytes only fit once events 1 and 2 are gone.
+        let (payload, mark) = event(4, 600);
+        feed.publish(payload, mark);
+        assert_eq!(retained(&feed), (vec![3, 4], 800));
+        assert!(feed.render_openmetrics().contains("sentinel_feed_retained_bytes 800\n"));
+        // The subscriber's backlog is what it can still read; the rest is lag.
+        assert!(feed.render_openmetrics().contains("sentinel_feed_subscriber_backlog_bytes{subscriber=\"0\"} 800\n"));
+
+        assert!(matches!(subscriber.try_recv(), Some(Recv::Lagged(2))));
+        assert_eq!(subscriber.watermark().event_id, 2);
+        for id in [3, 4] {
+            match subscriber.try_recv() {
+                Some(Recv::Event(_, mark)) => assert_eq!(mark.event_id, id),
+                _ => panic!("expected event {}", id),
+            }
+        }
+        assert!(subscriber.try_recv().is_none());
+        assert!(feed.render_openmetrics().contains("sentinel_feed_subscriber_backlog_bytes{subscriber=\"0\"} 0\n"));
+        assert!(feed.render_openmetrics().contains("sentinel_feed_lagged_events_total 2\n"));
+    }
+
+    /// An event bigger than the ceiling is kept on its own, so the feed never
+    /// goes empty, and leaves once anything else is published.
+    #[test]
+    fn an_event_over_the_ceiling_is_kept_alone() {
+        let feed = Feed::new(100, 1_000);
+        for (id, len) in [(1, 100), (2, 1_500), (3, 50)] {
+            let (payload, mark) = event(id, len);
+            feed.publish(payload, mark);
+            if id == 2 {
+                assert_eq!(retained(&feed), (vec![2], 1_500));
+            }
+        }
+        assert_eq!(retained(&feed), (vec![3], 50));
+    }
+}
@@ -0,0 +1,154 @@
+//! C ABI over [`audit::verify_bytes`], enabled with the `ffi` feature.
+//!
+//! Ownership: input strings are borrowed for the duration of the call. The
+//! returned string is a `VerifyReport` as JSON, allocated here; release it with
+//! [`sentinel_string_free`] and never with the caller's `free`.
+
+use crate::audit::{self, VerifyOptions, VerifyReport};
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Mirrors [`VerifyOptions`]. A null pointer means the defaults.
+#[repr(C)]
+pub struct SentinelVerifyOptions {
+    pub check_offsets: bool,
+}
+
+/// Verify the audit log at `log_path` against the base64 Ed25519 public key
+/// `pubkey_b64`. Always returns a report (`"ok": false` with an `error` on
+/// bad arguments or a panic); returns null only if the report can't be allocated.
+///
+/// # Safety
+/// `log_path` and `pubkey_b64` must be valid NUL-terminated strings and
+/// `options` null or a valid pointer, all for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn sentinel_verify_path(
+    log_path: *const c_char,
+    pubkey_b64: *const c_char,
+    options: *const SentinelVerifyOptions,
+) -> *mut c_char {
+    let report = panic::catch_unwind(AssertUnwindSafe(|| {
+        let log_path = match arg_str(log_path, "log_path") {
+            Ok(s) => s,
+            Err(e) => return VerifyReport::failed(e),
+        };
+        let pubkey_b64 = match arg_str(pubkey_b64, "pubkey_b64") {
+            Ok(s) => s,
+            Err(e) => return VerifyReport::failed(e),
+        };
+        let options = match options.as_ref() {
+            Some(o) => VerifyOptions {
+                check_offsets: o.check_offsets,
+                ..VerifyOptions::default()
+            },
+            None => VerifyOptions::default(),
+        };
+        match std::fs::File::open(log_path) {
+            Ok(f) => audit::verify_bytes(f, pubkey_b64, &options),
+            Err(e) => VerifyReport::failed(format!("failed to open log file {:?}: {e}", log_path)),
+        }
+    }))
+    .unwrap_or_else(|_| VerifyReport::failed("internal error: verification panicked".to_string()));
+
+    serde_json::to_string(&report)
+        .ok()
+        .and_then(|json| CString::new(json).ok())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Release a string returned by this library. Null is ignored.
+///
+/// # Safety
+/// `s` must be null or a pointer returned by this library and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn sentinel_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn arg_str<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, String> {
+    if ptr.is_null() {
+        return Err(format!("{name} is null"));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SentinelVerifyOptions;
+    use serde_json::Value;
+    use std::ffi::{c_char, CStr, CString};
+
+    // Declared again as a C caller would, so the calls go through the C ABI
+    extern "C" {
+        fn sentinel_verify_path(
+            log_path: *const c_char,
+            pubkey_b64: *const c_char,
+            options: *const SentinelVerifyOptions,
+        ) -> *mut c_char;
+        fn sentinel_string_free(s: *mut c_char);
+    }
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rederive");
+
+    fn pubkey() -> CString {
+        CString::new(std::fs::read_to_string(format!("{FIXTURE}/pubkey.b64")).unwrap().trim()).unwrap()
+    }
+
+    /// The report for `log_path`, through the C ABI, with the string released.
+    fn verify(log_path: *const c_char, pubkey_b64: *const c_char, options: Option<&SentinelVerifyOptions>) -> Value {
+        unsafe {
+            let report = sentinel_verify_path(log_path, pubkey_b64, options.map_or(std::ptr::null(), |o| o));
+            assert!(!report.is_null());
+            let json = CStr::from_ptr(report).to_str().unwrap().to_string();
+            sentinel_string_free(report);
+            serde_json::from_str(&json).unwrap()
+        }
+    }
+
+    #[test]
+    fn verifies_a_log_through_the_c_abi() {
+        let log = CString::new(format!("{FIXTURE}/audit.jsonl")).unwrap();
+        let report = verify(log.as_ptr(), pubkey().as_ptr(), None);
+        assert_eq!(report["ok"], true, "{report}");
+        assert_eq!(report["events_verified"], 12);
+
+        let options = SentinelVerifyOptions { check_offsets: false };
+        let report = verify(log.as_ptr(), pubkey().as_ptr(), Some(&options));
+        assert_eq!(report["ok"], true, "{report}");
+    }
+
+    #[test]
+    fn reports_a_tampered_log() {
+        let text = std::fs::read_to_string(format!("{FIXTURE}/audit.jsonl")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        std::fs::write(&path, text.replacen("tools/list", "tools/lost", 1)).unwrap();
+
+        let log = CString::new(path.to_str().unwrap()).unwrap();
+        let report = verify(log.as_ptr(), pubkey().as_ptr(), None);
+        assert_eq!(report["ok"], false);
+        assert!(report["error"].as_str().unwrap().contains("hash"), "{report}");
+    }
+
+    #[test]
+    fn bad_arguments_are_reports_not_crashes() {
+        let report = verify(std::ptr::null(), pubkey().as_ptr(), None);
+        assert_eq!(report["ok"], false);
+        assert_eq!(report["error"], "log_path is null");
+
+        let missing = CString::new(format!("{FIXTURE}/missing.jsonl")).unwrap();
+        let report = verify(missing.as_ptr(), pubkey().as_ptr(), None);
+        assert!(report["error"].as_str().unwrap().starts_with("failed to open log file"), "{report}");
+
+        let log = CString::new(format!("{FIXTURE}/audit.jsonl")).unwrap();
+        let bad_key = CString::new("not a key").unwrap();
+        let report = verify(log.as_ptr(), bad_key.as_ptr(), None);
+        assert!(report["error"].as_str().unwrap().starts_with("verifying key"), "{report}");
+
+        unsafe { sentinel_string_free(std::ptr::null_mut()) };
+    }
+}
@@ -0,0 +1,461 @@
+use crate::events::StreamDirection;
+use crate::throughput::{LineKind, Throughput};
+use std::io;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+const CONTENT_LENGTH: &[u8] = b"content-length:";
+
+/// Piece size when streaming the rest of a truncated frame.
+pub const CHUNK_BYTES: usize = 64 * 1024;
+
+/// How messages are delimited on the child's stdio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Framing {
+    /// Decide per stream from its first line (a header means Content-Length)
+    #[default]
+    Auto,
+    /// One JSON message per line
+    Ndjson,
+    /// `Content-Length: N` headers, a blank line, then N bytes of JSON (LSP style)
+    ContentLength,
+}
+
+/// Framing in use on each direction, so messages Sentinel synthesizes are framed
+/// like the stream they are written to.
+#[derive(Debug, Default)]
+pub struct Framings {
+    inbound: OnceLock<Framing>,
+    outbound: OnceLock<Framing>,
+}
+
+impl Framings {
+    fn slot(&self, direction: StreamDirection) -> &OnceLock<Framing> {
+        match direction {
+            StreamDirection::Outbound => &self.outbound,
+            _ => &self.inbound,
+        }
+    }
+
+    /// Frame a JSON `body` for the stream in `direction`. A stream whose framing
+    /// is not known yet is assumed to match the other one.
+    pub fn encode(&self, direction: StreamDirection, body: &[u8]) -> Vec<u8> {
+        let framing = self
+            .slot(direction)
+            .get()
+            .or_else(|| self.inbound.get().or(self.outbound.get()))
+            .copied()
+            .unwrap_or(Framing::Ndjson);
+
+        match framing {
+            Framing::ContentLength => {
+                let mut out = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+                out.extend_from_slice(body);
+                out
+            }
+            _ => {
+                let mut out = body.to_vec();
+                out.push(b'\n');
+                out
+            }
+        }
+    }
+}
+
+/// One message as read from a stream: `raw` is forwarded untouched, `body()` is
+/// the JSON the proxy checks and taps.
+#[derive(Default)]
+pub struct Frame {
+    raw: Vec<u8>,
+    body: Range<usize>,
+    truncated: bool,
+}
+
+impl Frame {
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.raw[self.body.clone()]
+    }
+
+    /// The message is longer than the reader's limit: this is only its start,
+    /// the rest comes from [`FrameReader::next_chunk`].
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// What is left of a truncated frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rest {
+    None,
+    /// Up to and including the next newline
+    Line,
+    /// This many body bytes
+    Body(u64),
+}
+
+/// Reads whole messages from one direction, reassembling Content-Length frames
+/// however the bytes arrive.
+pub struct FrameReader<R> {
+    reader: R,
+    framing: Framing,
+    direction: StreamDirection,
+    framings: Arc<Framings>,
+    max_bytes: usize,
+    rest: Rest,
+    throughput: Option<Arc<Throughput>>,
+}
+
+impl<R: AsyncBufRead + Unpin> FrameReader<R> {
+    pub fn new(reader: R, framing: Framing, direction: StreamDirection, framings: Arc<Framings>) -> Self {
+        if framing != Framing::Auto {
+            let _ = framings.slot(direction).set(framing);
+        }
+        Self {
+            reader,
+            framing,
+            direction,
+            framings,
+            max_bytes: usize::MAX,
+            rest: Rest::None,
+            throughput: None,
+        }
+    }
+
+    /// Buffer at most `max_bytes` of a message; longer ones come back truncated.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes.max(1);
+        self
+    }
+
+    /// Count every frame and byte read in `throughput`.
+    pub fn with_throughput(mut self, throughput: Arc<Throughput>) -> Self {
+        self.throughput = Some(throughput);
+        self
+    }
+
+    /// Read the next message into `frame`; false at end of stream. A frame cut
+    /// short by the end of the stream is returned as read.
+    pub async fn next(&mut self, frame: &mut Frame) -> io::Result<bool> {
+        // Whatever the caller did not take of a truncated frame is dropped.
+        let mut skipped = Vec::new();
+        while self.next_chunk(&mut skipped).await? {}
+
+        let read = self.read_frame(frame).await?;
+        if let Some(throughput) = self.throughput.as_ref().filter(|_| read) {
+            let kind = if frame.truncated {
+                LineKind::Oversized
+            } else if serde_json::from_slice::<serde::de::IgnoredAny>(frame.body()).is_ok() {
+                LineKind::Json
+            } else {
+                LineKind::NonJson
+            };
+            throughput.record_line(self.direction, frame.raw.len(), kind);
+        }
+        Ok(read)
+    }
+
+    async fn read_frame(&mut self, frame: &mut Frame) -> io::Result<bool> {
+        frame.raw.clear();
+        frame.body = 0..0;
+        frame.truncated = false;
+        let (read, cut) = read_line_bounded(&mut self.reader, &mut frame.raw, self.max_bytes).await?;
+        if read == 0 {
+            return Ok(false);
+        }
+
+        if self.framing == Framing::Auto {
+            self.framing = if is_header(&frame.raw) {
+                Framing::ContentLength
+            } else {
+                Framing::Ndjson
+            };
+            let _ = self.framings.slot(self.direction).set(self.framing);
+            eprintln!("🧩 {:?} framing: {:?}", self.direction, self.framing);
+        }
+
+        if self.framing != Framing::ContentLength || cut {
+            frame.body = 0..frame.raw.len();
+            if cut {
+                frame.truncated = true;
+                self.rest = Rest::Line;
+            }
+            return Ok(true);
+        }
+
+        // Header lines up to the blank line that follows Content-Length. Blank
+        // lines before it (stray separators) are forwarded as part of the frame.
+        let mut length = None;
+        let mut line_start = 0;
+        loop {
+            let line = &frame.raw[line_start..];
+            if length.is_some() && line.iter().all(|b| matches!(b, b'\r' | b'\n')) {
+                break;
+            }
+            if let Some(n) = content_length(line) {
+                length = Some(n);
+            }
+            line_start = frame.raw.len();
+            let (read, cut) = read_line_bounded(&mut self.reader, &mut frame.raw, self.max_bytes).await?;
+            if read == 0 {
+                return Ok(true);
+            }
+            if cut {
+                // Not a header block we can make sense of; pass the line on as is.
+                frame.body = line_start..frame.raw.len();
+                frame.truncated = true;
+                self.rest = Rest::Line;
+                return Ok(true);
+            }
+        }
+
+        let start = frame.raw.len();
+        let length = length.unwrap_or(0) as u64;
+        let room = self.max_bytes.saturating_sub(start) as u64;
+        (&mut self.reader).take(length.min(room)).read_to_end(&mut frame.raw).await?;
+        frame.body = start..frame.raw.len();
+        if length > room {
+            frame.truncated = true;
+            self.rest = Rest::Body(length - (frame.raw.len() - start) as u64);
+        }
+        Ok(true)
+    }
+
+    /// Read the next piece of a truncated frame's remainder into `buf`; false
+    /// once the frame is complete (or the stream ends).
+    pub async fn next_chunk(&mut self, buf: &mut Vec<u8>) -> io::Result<bool> {
+        buf.clear();
+        let more = self.read_chunk(buf).await?;
+        if let Some(throughput) = &self.throughput {
+            throughput.record_bytes(self.direction, buf.len());
+        }
+        Ok(more)
+    }
+
+    async fn read_chunk(&mut self, buf: &mut Vec<u8>) -> io::Result<bool> {
+        match self.rest {
+            Rest::None => Ok(false),
+            Rest::Line => {
+                let (read, cut) = read_line_bounded(&mut self.reader, buf, CHUNK_BYTES).await?;
+                if !cut {
+                    self.rest = Rest::None;
+                }
+                Ok(read > 0)
+            }
+            Rest::Body(left) => {
+                let read = (&mut self.reader)
+                    .take(left.min(CHUNK_BYTES as u64))
+                    .read_to_end(buf)
+                    .await? as u64;
+                self.rest = match left - read {
+                    0 => Rest::None,
+                    // End of stream inside the body
+                    _ if read == 0 => Rest::None,
+                    left => Rest::Body(left),
+                };
+                Ok(read > 0)
+            }
+        }
+    }
+}
+
+/// Like `read_until(b'\n')`, but stops once `buf` holds `max` bytes. Returns the
+/// bytes read and whether the line was cut short of its newline by the limit.
+pub async fn read_line_bounded<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> io::Result<(usize, bool)> {
+    let mut read = 0;
+    loop {
+        let room = max.saturating_sub(buf.len());
+        if room == 0 {
+            return Ok((read, true));
+        }
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok((read, false));
+        }
+        let window = &available[..available.len().min(room)];
+        let (n, done) = match window.iter().position(|b| *b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (window.len(), false),
+        };
+        buf.extend_from_slice(&window[..n]);
+        reader.consume(n);
+        read += n;
+        if done {
+            return Ok((read, false));
+        }
+    }
+}
+
+/// Whether a stream's first line is a `Name: value` header rather than JSON.
+fn is_header(line: &[u8]) -> bool {
+    match line.iter().position(|b| *b == b':') {
+        Some(colon) => colon > 0 && line[..colon].iter().all(|b| b.is_ascii_alphanumeric() || *b == b'-'),
+        None => false,
+    }
+}
+
+/// The value of a `Content-Length` header line (name matched case-insensitively).
+fn content_length(line: &[u8]) -> Option<usize> {
+    let name = line.get(..CONTENT_LENGTH.len())?;
+    if !name.eq_ignore_ascii_case(CONTENT_LENGTH) {
+        return None;
+    }
+    std::str::from_utf8(&line[CONTENT_LENGTH.len()..])
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, BufReader, ReadBuf};
+
+    /// `len` bytes of `a` and a newline, made up as they are read.
+    struct LongLine {
+        left: usize,
+    }
+
+    impl AsyncRead for LongLine {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            let n = buf.remaining().min(self.left);
+            let mut piece = vec![b'a'; n];
+            if self.left == n {
+                if let Some(last) = piece.last_mut() {
+                    *last = b'\n';
+                }
+            }
+            buf.put_slice(&piece);
+            self.left -= n;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_64_mb_line_is_read_in_bounded_pieces() {
+        const LEN: usize = 64 * 1024 * 1024;
+        const MAX: usize = 4 * 1024 * 1024;
+        let reader = BufReader::new(LongLine { left: LEN });
+        let mut frames = FrameReader::new(reader, Framing::Ndjson, StreamDirection::Inbound, Arc::default()).with_max_bytes(MAX);
+
+        let mut frame = Frame::default();
+        assert!(frames.next(&mut frame).await.unwrap());
+        assert!(frame.is_truncated());
+        assert_eq!(frame.raw().len(), MAX);
+
+        let mut chunk = Vec::new();
+        let mut total = frame.raw().len();
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(frame.raw());
+        while frames.next_chunk(&mut chunk).await.unwrap() {
+            assert!(chunk.len() <= CHUNK_BYTES);
+            assert!(chunk.capacity() <= 2 * CHUNK_BYTES);
+            hasher.update(&chunk);
+            total += chunk.len();
+        }
+        assert_eq!(total, LEN);
+
+        let mut whole = vec![b'a'; LEN];
+        whole[LEN - 1] = b'\n';
+        assert_eq!(hasher.finalize(), blake3::hash(&whole));
+        assert!(!frames.next(&mut frame).await.unwrap());
+    }
+
+    /// Yields `pieces` one read at a time, as a pipe might.
+    struct Pieces {
+        pieces: std::collections::VecDeque<Vec<u8>>,
+    }
+
+    impl AsyncRead for Pieces {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            if let Some(mut piece) = self.pieces.pop_front() {
+                let n = buf.remaining().min(piece.len());
+                buf.put_slice(&piece[..n]);
+                if n < piece.len() {
+                    self.pieces.push_front(piece.split_off(n));
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// The raw bytes and body of every frame in `pieces`, and the framing found.
+    async fn frames(pieces: Vec<Vec<u8>>, framing: Framing) -> (Vec<(Vec<u8>, Vec<u8>)>, Option<Framing>) {
+        let framings = Arc::new(Framings::default());
+        let reader = BufReader::new(Pieces { pieces: pieces.into() });
+        let mut frames = FrameReader::new(reader, framing, StreamDirection::Inbound, framings.clone());
+        let mut frame = Frame::default();
+        let mut read = Vec::new();
+        while frames.next(&mut frame).await.unwrap() {
+            assert!(!frame.is_truncated());
+            read.push((frame.raw().to_vec(), frame.body().to_vec()));
+        }
+        (read, framings.inbound.get().copied())
+    }
+
+    fn framed(body: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+
+    const BODIES: [&str; 3] = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        "{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":{\"text\":\"two\\nlines\"}}",
+    ];
+
+    #[tokio::test]
+    async fn a_content_length_frame_split_across_reads_is_reassembled() {
+        let stream = framed(BODIES[0]);
+        // Every split point, and one byte per read.
+        let mut splits: Vec<Vec<Vec<u8>>> = (1..stream.len())
+            .map(|at| vec![stream[..at].to_vec(), stream[at..].to_vec()])
+            .collect();
+        splits.push(stream.iter().map(|b| vec![*b]).collect());
+
+        for pieces in splits {
+            let (read, framing) = frames(pieces, Framing::Auto).await;
+            assert_eq!(framing, Some(Framing::ContentLength));
+            assert_eq!(read.len(), 1);
+            assert_eq!(read[0].0, stream, "forwarded untouched");
+            assert_eq!(read[0].1, BODIES[0].as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn several_content_length_frames_in_one_read_come_out_one_by_one() {
+        let stream: Vec<u8> = BODIES.iter().flat_map(|body| framed(body)).collect();
+        let (read, framing) = frames(vec![stream.clone()], Framing::Auto).await;
+        assert_eq!(framing, Some(Framing::ContentLength));
+        let bodies: Vec<&[u8]> = read.iter().map(|(_, body)| body.as_slice()).collect();
+        assert_eq!(bodies, BODIES.map(str::as_bytes));
+        assert_eq!(read.iter().flat_map(|(raw, _)| raw.clone()).collect::<Vec<_>>(), stream);
+
+        // The last frame's body ends in the middle of a read: the rest is kept.
+        let mut pieces = vec![stream.clone()];
+        pieces[0].truncate(stream.len() - 5);
+        pieces.push(stream[stream.len() - 5..].to_vec());
+        let (split, _) = frames(pieces, Framing::Auto).await;
+        assert_eq!(split, read);
+    }
+
+    #[tokio::test]
+    async fn ndjson_lines_split_or_batched_in_reads_come_out_whole() {
+        let stream: Vec<u8> = BODIES[..2].iter().flat_map(|body| format!("{}\n", body).into_bytes()).collect();
+        let pieces = vec![stream[..10].to_vec(), stream[10..80].to_vec(), stream[80..].to_vec()];
+        let (read, framing) = frames(pieces, Framing::Auto).await;
+        assert_eq!(framing, Some(Framing::Ndjson));
+        let bodies: Vec<&[u8]> = read.iter().map(|(_, body)| body.as_slice()).collect();
+        assert_eq!(bodies, [format!("{}\n", BODIES[0]).as_bytes(), format!("{}\n", BODIES[1]).as_bytes()]);
+    }
+}
@@ -0,0 +1,200 @@
+use serde::Serialize;
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// What the proxy and the audit writer last reported about themselves.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HealthStatus {
+    /// Pid of the wrapped child while it runs
+    pub child_pid: Option<u32>,
+    /// How the child exited, until it is restarted
+    pub child_exit: Option<String>,
+    /// The audit writer is taking events
+    pub audit_running: bool,
+    /// Error of the last audit write, cleared by the next one that succeeds
+    pub audit_error: Option<String>,
+    /// Observed time of the last event written to the audit log
+    pub last_event_ts_ms: Option<u64>,
+}
+
+/// Readiness of a run, served on `/readyz`. The proxy and the audit writer
+/// publish their status into a watch channel that the server reads.
+#[derive(Debug, Clone)]
+pub struct Health {
+    tx: Arc<watch::Sender<HealthStatus>>,
+    started: Instant,
+    /// Readiness depends on a wrapped child process (not for socket or HTTP
+    /// upstreams, nor the hub, whose servers come and go with their clients)
+    watches_child: bool,
+    /// Readiness depends on the audit writer (not for `replay`)
+    watches_audit: bool,
+}
+
+impl Default for Health {
+    /// Watches nothing: always ready.
+    fn default() -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(HealthStatus::default())),
+            started: Instant::now(),
+            watches_child: false,
+            watches_audit: false,
+        }
+    }
+}
+
+impl Health {
+    /// For `run`: ready while the audit writer works and, if `watches_child`,
+    /// the wrapped child runs.
+    pub fn for_run(watches_child: bool) -> Self {
+        Self { watches_child, watches_audit: true, ..Self::default() }
+    }
+
+    pub fn child_started(&self, pid: Option<u32>) {
+        self.tx.send_modify(|s| {
+            s.child_pid = pid;
+            s.child_exit = None;
+        });
+    }
+
+    pub fn child_exited(&self, status: &ExitStatus) {
+        self.tx.send_modify(|s| {
+            s.child_pid = None;
+            s.child_exit = Some(status.to_string());
+        });
+    }
+
+    pub fn audit_running(&self, running: bool) {
+        self.tx.send_modify(|s| s.audit_running = running);
+    }
+
+    /// Record the outcome of an audit write; `event_ts_ms` is the observed time
+    /// of the event it wrote, if it wrote one.
+    pub fn audit_written(&self, result: &Result<(), String>, event_ts_ms: Option<u64>) {
+        self.tx.send_modify(|s| match result {
+            Ok(()) => {
+                s.audit_error = None;
+                s.last_event_ts_ms = event_ts_ms.or(s.last_event_ts_ms);
+            }
+            Err(e) => s.audit_error = Some(e.clone()),
+        });
+    }
+
+    /// Whether the run is ready, and the `/readyz` body saying why (not).
+    pub fn readiness(&self) -> (bool, serde_json::Value) {
+        let status = self.tx.borrow().clone();
+        let mut reasons = Vec::new();
+        if self.watches_child && status.child_pid.is_none() {
+            match &status.child_exit {
+                Some(exit) => reasons.push(format!("child exited ({})", exit)),
+                None => reasons.push("child not running".to_string()),
+            }
+        }
+        if self.watches_audit && !status.audit_running {
+            reasons.push("audit writer stopped".to_string());
+        }
+        if let Some(e) = status.audit_error.as_ref().filter(|_| self.watches_audit) {
+            reasons.push(format!("last audit write failed: {}", e));
+        }
+
+        let ready = reasons.is_empty();
+        let mut body = serde_json::json!(status);
+        body["ready"] = ready.into();
+        body["reasons"] = reasons.into();
+        body["uptime_ms"] = (self.started.elapsed().as_millis() as u64).into();
+        (ready, body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reasons(body: &serde_json::Value) -> Vec<&str> {
+        body["reasons"].as_array().unwrap().iter().map(|r| r.as_str().unwrap()).collect()
+    }
+
+    /// A run of a socket upstream: only the audit writer counts.
+    fn running() -> Health {
+        let health = Health::for_run(false);
+        health.audit_running(true);
+        health
+    }
+
+    #[test]
+    fn a_run_is_unready_until_its_audit_writer_starts() {
+        let health = Health::for_run(false);
+        let (ready, body) = health.readiness();
+        assert!(!ready);
+        assert_eq!(reasons(&body), ["audit writer stopped"]);
+
+        health.audit_running(true);
+        let (ready, body) = health.readiness();
+        assert!(ready);
+        assert!(reasons(&body).is_empty());
+        assert_eq!(body["ready"], true);
+    }
+
+    #[test]
+    fn a_failed_audit_write_makes_the_run_unready_until_one_succeeds() {
+        let health = running();
+        health.audit_written(&Ok(()), Some(1_000));
+        assert!(health.readiness().0);
+
+        health.audit_written(&Err("write event: No space left on device".to_string()), Some(2_000));
+        let (ready, body) = health.readiness();
+        assert!(!ready);
+        assert_eq!(reasons(&body), ["last audit write failed: write event: No space left on device"]);
+        // The failed write's event is not the last one written.
+        assert_eq!(body["last_event_ts_ms"], 1_000);
+
+        // A checkpoint written without an event clears the error too.
+        health.audit_written(&Ok(()), None);
+        let (ready, body) = health.readiness();
+        assert!(ready);
+        assert_eq!(body["last_event_ts_ms"], 1_000);
+    }
+
+    #[test]
+    fn a_stalled_audit_writer_makes_the_run_unready() {
+        let health = running();
+        health.audit_written(&Err("write event: Broken pipe".to_string()), None);
+        health.audit_running(false);
+        let (ready, body) = health.readiness();
+        assert!(!ready);
+        assert_eq!(
+            reasons(&body),
+            ["audit writer stopped", "last audit write failed: write event: Broken pipe"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_run_watching_its_child_is_unready_once_it_exits() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let health = Health::for_run(true);
+        health.audit_running(true);
+        assert_eq!(reasons(&health.readiness().1), ["child not running"]);
+
+        health.child_started(Some(42));
+        let (ready, body) = health.readiness();
+        assert!(ready);
+        assert_eq!(body["child_pid"], 42);
+
+        health.child_exited(&ExitStatus::from_raw(3 << 8));
+        let (ready, body) = health.readiness();
+        assert!(!ready);
+        assert_eq!(reasons(&body), ["child exited (exit status: 3)"]);
+    }
+
+    #[test]
+    fn audit_failures_do_not_count_when_the_audit_writer_is_not_watched() {
+        let health = Health::default();
+        health.audit_written(&Err("write event: Broken pipe".to_string()), None);
+        let (ready, body) = health.readiness();
+        assert!(ready);
+        assert_eq!(body["audit_error"], "write event: Broken pipe");
+    }
+}
@@ -0,0 +1,445 @@
+use crate::audit_crypto::DataKey;
+use crate::events::StreamDirection;
+use crate::log_index::LogIndex;
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Associated data for spilled records, so they can't be confused with audit records.
+const SPILL_AAD: &[u8] = b"sentinel/history-spill/v1";
+
+/// Recent events, serialized, for the dashboard's replay and paging.
+///
+/// The newest events stay in memory within `max_events` and `max_bytes`. With a
+/// spill directory, events evicted from memory are appended to an unlinked
+/// temporary file (gone when the process exits) and stay readable until the
+/// spill itself exceeds its byte cap. Events gone from both are read back from
+/// the audit log when it is indexed.
+pub struct EventStore {
+    inner: Mutex<Inner>,
+    max_events: usize,
+    max_bytes: usize,
+    log: LogFallback,
+}
+
+struct Inner {
+    memory: VecDeque<(u64, Arc<str>)>,
+    memory_bytes: usize,
+    spill: Option<Spill>,
+    /// Id of the first event pushed
+    first_id: Option<u64>,
+}
+
+/// Why [`EventStore::page`] or [`EventStore::query`] failed.
+pub enum HistoryError {
+    /// Some of the events asked for left the store, and the audit log can't be
+    /// read back (for this reason)
+    Unreadable(String),
+    /// Reading the spill or the audit log failed
+    Read(String),
+}
+
+impl From<String> for HistoryError {
+    fn from(e: String) -> Self {
+        HistoryError::Read(e)
+    }
+}
+
+/// Where events that left the store are read back from.
+enum LogFallback {
+    /// Nowhere (`replay`)
+    None,
+    Index(Arc<LogIndex>),
+    /// The audit log can't be read back, for this reason
+    Unavailable(String),
+}
+
+impl Inner {
+    /// Id of the oldest event held, in the spill or in memory.
+    fn oldest_id(&self) -> Option<u64> {
+        let spilled = self.spill.as_ref().and_then(|s| s.index.front()).map(|(id, _, _)| *id);
+        spilled.or_else(|| self.memory.front().map(|(id, _)| *id))
+    }
+
+    /// Whether events before `held_from` have left the store.
+    fn evicted_before(&self, held_from: u64) -> bool {
+        self.first_id.is_some_and(|first| first < held_from)
+    }
+}
+
+struct Spill {
+    file: File,
+    /// Spilled events, oldest first: (event_id, offset, len)
+    index: VecDeque<(u64, u64, u32)>,
+    /// Length of the file, i.e. where the next record goes
+    end: u64,
+    max_bytes: u64,
+    key: Option<DataKey>,
+}
+
+/// Which events [`EventStore::query`] returns; every field given must match.
+#[derive(Debug, Default, Clone)]
+pub struct EventFilter {
+    pub method: Option<String>,
+    pub direction: Option<StreamDirection>,
+    /// Only events with a higher id
+    pub since_event_id: Option<u64>,
+    /// Only events observed at or after this time
+    pub since_ts_ms: Option<u64>,
+}
+
+/// The fields of a stored event that [`EventFilter`] looks at.
+#[derive(Deserialize)]
+struct FilterFields {
+    observed_ts_ms: u64,
+    direction: StreamDirection,
+    method: Option<String>,
+}
+
+impl EventFilter {
+    /// Whether `json` passes the filter. A stored event that can't be read
+    /// passes, so whoever reads the page finds it rather than it going missing.
+    fn matches(&self, json: &str) -> bool {
+        let Ok(event) = serde_json::from_str::<FilterFields>(json) else {
+            return true;
+        };
+        self.method.as_ref().is_none_or(|m| event.method.as_ref() == Some(m))
+            && self.direction.is_none_or(|d| event.direction == d)
+            && self.since_ts_ms.is_none_or(|ts| event.observed_ts_ms >= ts)
+    }
+}
+
+impl EventStore {
+    pub fn new(max_events: usize, max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                memory: VecDeque::new(),
+                memory_bytes: 0,
+                spill: None,
+                first_id: None,
+            }),
+            max_events: max_events.max(1),
+            max_bytes,
+            log: LogFallback::None,
+        }
+    }
+
+    /// Read events that left the store back from the audit log through `index`.
+    pub fn with_log_index(mut self, index: Arc<LogIndex>) -> Self {
+        self.log = LogFallback::Index(index);
+        self
+    }
+
+    /// The audit log can't be read back; requests for events that left the
+    /// store are refused with `reason`.
+    pub fn with_unreadable_log(mut self, reason: String) -> Self {
+        self.log = LogFallback::Unavailable(reason);
+        self
+    }
+
+    /// [`HistoryError::Unreadable`] if the audit log can't be read back.
+    fn unreadable(&self) -> Result<(), HistoryError> {
+        match &self.log {
+            LogFallback::Unavailable(reason) => Err(HistoryError::Unreadable(reason.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Keep evicted events in a temporary file under `dir`, up to `max_bytes`.
+    pub fn with_spill(self, dir: &Path, max_bytes: u64) -> Result<Self, String> {
+        let file = tempfile::tempfile_in(dir)
+            .map_err(|e| format!("create history spill in {}: {}", dir.display(), e))?;
+        self.lock().spill = Some(Spill {
+            file,
+            index: VecDeque::new(),
+            end: 0,
+            max_bytes,
+            key: None,
+        });
+        Ok(self)
+    }
+
+    /// Encrypt spilled events with `key` (the run's data key, when the audit log
+    /// is encrypted). Call before the first event is pushed.
+    pub fn encrypt_spill(&self, key: DataKey) {
+        if let Some(spill) = self.lock().spill.as_mut() {
+            spill.key = Some(key);
+        }
+    }
+
+    pub fn push(&self, event_id: u64, json: Arc<str>) {
+        let mut inner = self.lock();
+        inner.first_id.get_or_insert(event_id);
+        inner.memory_bytes += json.len();
+        inner.memory.push_back((event_id, json));
+
+        while inner.memory.len() > 1
+            && (inner.memory.len() > self.max_events || inner.memory_bytes > self.max_bytes)
+        {
+            let Some((id, old)) = inner.memory.pop_front() else { break };
+            inner.memory_bytes -= old.len();
+            if let Some(spill) = inner.spill.as_mut() {
+                if let Err(e) = spill.append(id, &old) {
+                    eprintln!("❌ History spill failed, older events will be dropped: {}", e);
+                    inner.spill = None;
+                }
+            }
+        }
+    }
+
+    /// Events still held in memory with ids above `after`, oldest first (what new
+    /// WebSocket clients replay).
+    pub fn recent(&self, after: u64) -> Vec<(u64, Arc<str>)> {
+        let inner = self.lock();
+        let start = inner.memory.partition_point(|(id, _)| *id <= after);
+        inner.memory.range(start..).cloned().collect()
+    }
+
+    /// Up to `limit` events with ids below `before` (all, if `None`), oldest first.
+    pub fn page(&self, before: Option<u64>, limit: usize) -> Result<Vec<Arc<str>>, HistoryError> {
+        let mut inner = self.lock();
+        let before = before.unwrap_or(u64::MAX);
+        let mut page = Self::page_held(&mut inner, before, limit)?;
+
+        // Older still: read back from the audit log, without holding up new events
+        let evicted_until = inner
+            .oldest_id()
+            .filter(|id| inner.evicted_before(*id) && inner.first_id.is_some_and(|first| first < before))
+            .map(|held_from| held_from.min(before));
+        drop(inner);
+        let Some(until) = evicted_until.filter(|_| page.len() < limit) else {
+            return Ok(page);
+        };
+        self.unreadable()?;
+        if let LogFallback::Index(index) = &self.log {
+            let mut older = index.last_before(until, limit - page.len())?;
+            older.append(&mut page);
+            page = older;
+        }
+        Ok(page)
+    }
+
+    /// [`EventStore::page`] from what is held in memory and the spill.
+    fn page_held(inner: &mut Inner, before: u64, limit: usize) -> Result<Vec<Arc<str>>, String> {
+        let end = inner.memory.partition_point(|(id, _)| *id < before);
+        let from_memory = end.min(limit);
+        let mut page: Vec<Arc<str>> = inner
+            .memory
+            .range(end - from_memory..end)
+            .map(|(_, json)| json.clone())
+            .collect();
+
+        let wanted = limit - from_memory;
+        if wanted > 0 {
+            if let Some(spill) = inner.spill.as_mut() {
+                let end = spill.index.partition_point(|(id, _, _)| *id < before);
+                let start = end.saturating_sub(wanted);
+                let mut older = Vec::with_capacity(end - start);
+                for i in start..end {
+                    let (_, offset, len) = spill.index[i];
+                    older.push(Arc::from(spill.read(offset, len)?));
+                }
+                older.append(&mut page);
+                page = older;
+            }
+        }
+
+        Ok(page)
+    }
+
+    /// The matching events, oldest first, skipping the first `offset` matches
+    /// and returning at most `limit`. The offset to ask for next is returned
+    /// when there are more matches.
+    pub fn query(
+        &self,
+        filter: &EventFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<Arc<str>>, Option<usize>), HistoryError> {
+        let mut skipped = 0;
+        let mut events = Vec::new();
+        // One match past the page says whether there is a next one.
+        let mut keep = |json: Arc<str>| {
+            if !filter.matches(&json) {
+                return true;
+            }
+            if skipped < offset {
+                skipped += 1;
+                return true;
+            }
+            events.push(json);
+            events.len() <= limit
+        };
+
+        let mut more = true;
+        // Id of the next event to look at
+        let mut next = filter.since_event_id.map_or(0, |id| id.saturating_add(1));
+        // Events older than those held are read back from the audit log. More may
+        // leave the store while it is read, so it is read until it catches up.
+        // Without `since_event_id`, an unreadable log just leaves them out.
+        let mut inner = loop {
+            let inner = self.lock();
+            match inner.oldest_id() {
+                Some(held_from) if more && next < held_from && inner.evicted_before(held_from) => {
+                    let LogFallback::Index(index) = &self.log else {
+                        if filter.since_event_id.is_some() {
+                            self.unreadable()?;
+                        }
+                        break inner;
+                    };
+                    drop(inner);
+                    index.scan(next, held_from, |_, json| {
+                        more = keep(json);
+                        more
+                    })?;
+                    next = held_from;
+                }
+                _ => break inner,
+            }
+        };
+
+        let after = next.saturating_sub(1);
+        if let Some(spill) = inner.spill.as_mut().filter(|_| more) {
+            let start = spill.index.partition_point(|(id, _, _)| *id <= after);
+            for i in start..spill.index.len() {
+                let (_, offset, len) = spill.index[i];
+                if !keep(Arc::from(spill.read(offset, len)?)) {
+                    more = false;
+                    break;
+                }
+            }
+        }
+        if more {
+            let start = inner.memory.partition_point(|(id, _)| *id <= after);
+            for (_, json) in inner.memory.range(start..) {
+                if !keep(json.clone()) {
+                    break;
+                }
+            }
+        }
+
+        let next_offset = (events.len() > limit).then(|| {
+            events.truncate(limit);
+            offset + limit
+        });
+        Ok((events, next_offset))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Spill {
+    fn append(&mut self, event_id: u64, json: &str) -> Result<(), String> {
+        let record = match &self.key {
+            Some(key) => key.seal(SPILL_AAD, json.as_bytes())?,
+            None => json.as_bytes().to_vec(),
+        };
+        let len = record.len() as u64;
+        if len > self.max_bytes {
+            return Ok(());
+        }
+        if self.end + len > self.max_bytes {
+            self.compact((self.max_bytes / 2).min(self.max_bytes - len))?;
+        }
+
+        self.file
+            .seek(SeekFrom::Start(self.end))
+            .and_then(|_| self.file.write_all(&record))
+            .map_err(|e| format!("write: {}", e))?;
+        self.index.push_back((event_id, self.end, len as u32));
+        self.end += len;
+        Ok(())
+    }
+
+    /// Drop the oldest records until at most `keep` bytes remain and move the
+    /// rest to the start of the file, so the file never exceeds the cap.
+    fn compact(&mut self, keep: u64) -> Result<(), String> {
+        while self.end - self.index.front().map_or(self.end, |(_, offset, _)| *offset) > keep {
+            self.index.pop_front();
+        }
+        let start = self.index.front().map_or(self.end, |(_, offset, _)| *offset);
+
+        let mut live = vec![0u8; (self.end - start) as usize];
+        self.file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| self.file.read_exact(&mut live))
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| self.file.write_all(&live))
+            .and_then(|_| self.file.set_len(live.len() as u64))
+            .map_err(|e| format!("compact: {}", e))?;
+
+        for entry in self.index.iter_mut() {
+            entry.1 -= start;
+        }
+        self.end -= start;
+        Ok(())
+    }
+
+    fn read(&mut self, offset: u64, len: u32) -> Result<String, String> {
+        let mut record = vec![0u8; len as usize];
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(&mut record))
+            .map_err(|e| format!("read history spill: {}", e))?;
+        let json = match &self.key {
+            Some(key) => key.open(SPILL_AAD, &record)?,
+            None => record,
+        };
+        String::from_utf8(json).map_err(|_| "history spill record is not UTF-8".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const MEMORY_BUDGET: usize = 1024 * 1024;
+
+    /// A tool result carrying a screenshot: about 150 KB of base64.
+    fn image_event(event_id: u64) -> Arc<str> {
+        let image = serde_json::json!({ "type": "image", "mimeType": "image/png", "data": "iVBORw0K".repeat(19_200) });
+        let event = serde_json::json!({
+            "event_id": event_id,
+            "observed_ts_ms": event_id,
+            "direction": "Inbound",
+            "method": null,
+            "payload": { "result": { "content": [image] } },
+        });
+        Arc::from(event.to_string())
+    }
+
+    fn event_id(json: &str) -> u64 {
+        serde_json::from_str::<Value>(json).unwrap()["event_id"].as_u64().unwrap()
+    }
+
+    /// Memory holds a few screenshots at most; the rest of the session pages
+    /// back from the spill, down to event 1, without coming back into memory.
+    #[test]
+    fn an_image_heavy_session_pages_back_to_event_1_within_the_memory_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = EventStore::new(10_000, MEMORY_BUDGET).with_spill(dir.path(), 256 * 1024 * 1024).unwrap();
+        for id in 1..=200 {
+            store.push(id, image_event(id));
+            assert!(store.lock().memory_bytes <= MEMORY_BUDGET, "{} bytes in memory after event {}", store.lock().memory_bytes, id);
+        }
+        assert!(store.lock().memory.len() < 10);
+
+        let mut ids = Vec::new();
+        let mut before = None;
+        loop {
+            let Ok(page) = store.page(before, 50) else { panic!("page before {:?} failed", before) };
+            let Some(oldest) = page.first() else { break };
+            before = Some(event_id(oldest));
+            ids.extend(page.iter().rev().map(|json| event_id(json)));
+        }
+        assert_eq!(ids, (1..=200).rev().collect::<Vec<u64>>());
+        assert!(store.lock().memory_bytes <= MEMORY_BUDGET);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::control::ForwardGate;
+use crate::events::{current_timestamp_ms, RawTap, StreamDirection};
+use crate::tap::Tap;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, HeaderName, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Session header of MCP's streamable HTTP transport.
+const MCP_SESSION_ID: &str = "mcp-session-id";
+
+/// Headers that describe one hop rather than the message, so they are not relayed.
+const HOP_BY_HOP: &[HeaderName] = &[
+    header::CONNECTION,
+    header::CONTENT_LENGTH,
+    header::HOST,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    // Bodies are tapped as they pass, so ask the upstream for them uncompressed.
+    header::ACCEPT_ENCODING,
+];
+
+struct HttpProxy {
+    upstream: String,
+    client: reqwest::Client,
+    tx: Tap,
+    /// Requests wait here while forwarding is paused
+    gate: ForwardGate,
+    /// Ends open SSE relays so the listener can shut down
+    stop: CancellationToken,
+}
+
+/// Reverse-proxy MCP's streamable HTTP transport: every request to `listen` is
+/// forwarded to the `upstream` endpoint, whatever its local path. POST bodies
+/// are tapped as Outbound; JSON replies and SSE events as Inbound. Each
+/// exchange is logged as `sentinel/http_exchange`, with its `Mcp-Session-Id`.
+/// Runs until `stop` is cancelled.
+pub async fn run(
+    upstream: &str,
+    listen: &str,
+    tx: Tap,
+    gate: ForwardGate,
+    stop: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = listen.parse()?;
+    let state = Arc::new(HttpProxy {
+        upstream: upstream.to_string(),
+        client: reqwest::Client::builder().build()?,
+        tx,
+        gate,
+        stop: stop.clone(),
+    });
+    let app = Router::new().fallback(forward).with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    eprintln!("🌐 Proxying MCP over HTTP: http://{} -> {}", listener.local_addr()?, upstream);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(stop.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+async fn forward(State(proxy): State<Arc<HttpProxy>>, method: Method, headers: HeaderMap, body: Bytes) -> Response {
+    let observed_ts_ms = current_timestamp_ms();
+    if !body.is_empty() {
+        tap_messages(&proxy.tx, StreamDirection::Outbound, &body, observed_ts_ms).await;
+    }
+    if proxy.gate.is_paused() {
+        if proxy.gate.held_bytes() + body.len() as u64 > proxy.gate.max_held_bytes() as u64 {
+            return (StatusCode::SERVICE_UNAVAILABLE, "Sentinel: forwarding is paused").into_response();
+        }
+        proxy.gate.hold(body.len());
+        tokio::select! {
+            _ = proxy.gate.wait_resumed() => {}
+            _ = proxy.stop.cancelled() => {}
+        }
+        proxy.gate.release(body.len());
+        if proxy.stop.is_cancelled() {
+            return (StatusCode::SERVICE_UNAVAILABLE, "Sentinel: shutting down").into_response();
+        }
+    }
+
+    let request = proxy
+        .client
+        .request(method.clone(), &proxy.upstream)
+        .headers(relayed(&headers))
+        .body(body);
+
+    let response = match request.send().await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("❌ HTTP upstream {} failed: {}", proxy.upstream, e);
+            let event = RawTap::sentinel(
+                "sentinel/http_exchange",
+                json!({
+                    "http_method": method.as_str(),
+                    "mcp_session_id": session_id(&headers),
+                    "error": e.to_string(),
+                }),
+            );
+            let _ = proxy.tx.send(event).await;
+            return (StatusCode::BAD_GATEWAY, format!("Sentinel: upstream request failed: {}", e)).into_response();
+        }
+    };
+
+    let status = response.status();
+    let response_headers = relayed(response.headers());
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let event = RawTap::sentinel(
+        "sentinel/http_exchange",
+        json!({
+            "http_method": method.as_str(),
+            "status": status.as_u16(),
+            // A new session's id arrives on the response; later requests carry it.
+            "mcp_session_id": session_id(response.headers()).or(session_id(&headers)),
+            "content_type": content_type,
+        }),
+    );
+    let _ = proxy.tx.send(event).await;
+
+    let body = if content_type.starts_with("text/event-stream") {
+        relay_events(proxy, response)
+    } else {
+        match response.bytes().await {
+            Ok(bytes) => {
+                if !bytes.is_empty() {
+                    tap_messages(&proxy.tx, StreamDirection::Inbound, &bytes, current_timestamp_ms()).await;
+                }
+                Body::from(bytes)
+            }
+            Err(e) => {
+                eprintln!("❌ Reading HTTP upstream reply failed: {}", e);
+                return (StatusCode::BAD_GATEWAY, format!("Sentinel: upstream reply failed: {}", e)).into_response();
+            }
+        }
+    };
+
+    let mut reply = Response::new(body);
+    *reply.status_mut() = status;
+    *reply.headers_mut() = response_headers;
+    reply
+}
+
+/// Stream an SSE reply to the client as it arrives, tapping each event's data.
+/// The relay ends with the upstream stream, when the client goes away, or on shutdown.
+fn relay_events(proxy: Arc<HttpProxy>, response: reqwest::Response) -> Body {
+    let (chunk_tx, chunk_rx) = mpsc::channel::<Result<Bytes, reqwest::Error>>(16);
+
+    tokio::spawn(async move {
+        let mut upstream = response.bytes_stream();
+        let mut events = SseEvents::default();
+        loop {
+            let chunk = tokio::select! {
+                c = upstream.next() => c,
+                _ = proxy.stop.cancelled() => None,
+                _ = chunk_tx.closed() => None,
+            };
+            let Some(chunk) = chunk else { break };
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    let _ = chunk_tx.send(Err(e)).await;
+                    break;
+                }
+            };
+
+            // Forward first, then tap the events the chunk completed.
+            let observed_ts_ms = current_timestamp_ms();
+            let forwarded = chunk_tx.send(Ok(chunk.clone())).await.is_ok();
+            for data in events.push(&chunk) {
+                tap_messages(&proxy.tx, StreamDirection::Inbound, data.as_bytes(), observed_ts_ms).await;
+            }
+            if !forwarded {
+                break;
+            }
+        }
+    });
+
+    Body::from_stream(futures_util::stream::unfold(chunk_rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
+}
+
+/// Tap a body as JSON-RPC messages, one tap per element of a batch.
+async fn tap_messages(tx: &Tap, direction: StreamDirection, body: &[u8], observed_ts_ms: u64) {
+    let messages = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(batch)) => batch
+            .iter()
+            .map(|m| Bytes::from(serde_json::to_vec(m).unwrap_or_default()))
+            .collect(),
+        _ => vec![Bytes::copy_from_slice(body)],
+    };
+    for bytes in messages {
+        let raw = RawTap {
+            direction,
+            bytes,
+            observed_ts_ms,
+            server: None,
+            dropped_before: [0; 4],
+        };
+        if !tx.send(raw).await {
+            return;
+        }
+    }
+}
+
+fn relayed(headers: &HeaderMap) -> HeaderMap {
+    let mut out = headers.clone();
+    for name in HOP_BY_HOP {
+        out.remove(name);
+    }
+    out
+}
+
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers.get(MCP_SESSION_ID).and_then(|v| v.to_str().ok())
+}
+
+/// Incremental Server-Sent Events parser: feed it chunks, get back the `data`
+/// of every event they complete.
+#[derive(Default)]
+struct SseEvents {
+    line: Vec<u8>,
+    data: Option<String>,
+}
+
+impl SseEvents {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut done = Vec::new();
+        for &b in chunk {
+            if b != b'\n' {
+                self.line.push(b);
+                continue;
+            }
+            let line = String::from_utf8_lossy(self.line.strip_suffix(b"\r").unwrap_or(&self.line)).into_owned();
+            self.line.clear();
+
+            if line.is_empty() {
+                done.extend(self.data.take());
+            } else if let Some(value) = line.strip_prefix("data") {
+                let value = match value.strip_prefix(':') {
+                    Some(v) => v.strip_prefix(' ').unwrap_or(v),
+                    None if value.is_empty() => "",
+                    None => continue, // a field like `database:`, not `data`
+                };
+                match self.data.as_mut() {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => self.data = Some(value.to_string()),
+                }
+            }
+        }
+        done
+    }
+}
@@ -0,0 +1,339 @@
+use crate::child_env::{self, ChildEnv};
+use crate::events::RawTap;
+use crate::framing::read_line_bounded;
+use crate::proxy::{exit_signal, run_proxy, Client, ProxyConfig, Upstream};
+use crate::tap::Tap;
+use crate::throughput::Throughput;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Longest handshake line accepted from an attaching client.
+const HANDSHAKE_MAX_BYTES: usize = 4096;
+
+/// How long an attaching client has to say which server it wants.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `--servers` file: named MCP servers, in the same shape as Claude's
+/// `mcpServers` (which is accepted as the key too).
+#[derive(Deserialize)]
+struct ServersFile {
+    #[serde(alias = "mcpServers")]
+    servers: BTreeMap<String, ServerSpec>,
+}
+
+#[derive(Deserialize)]
+struct ServerSpec {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Dotenv-style file, loaded before `env`
+    #[serde(default)]
+    env_file: Option<PathBuf>,
+    #[serde(default)]
+    clear_env: bool,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+}
+
+/// One server the hub can start for an attaching client.
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub name: String,
+    pub command: Vec<String>,
+    pub env: ChildEnv,
+    /// Across every client it has served
+    pub throughput: Arc<Throughput>,
+}
+
+/// Read a `--servers` file. Relative `env_file` and `cwd` paths are taken
+/// from the file's own directory.
+pub fn load_servers(path: &Path) -> Result<Vec<Server>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("read servers file {}: {}", path.display(), e))?;
+    let file: ServersFile = serde_json::from_str(&text).map_err(|e| format!("servers file {}: {}", path.display(), e))?;
+    if file.servers.is_empty() {
+        return Err(format!("servers file {}: no servers listed", path.display()));
+    }
+    let base = path.parent().unwrap_or(Path::new("."));
+
+    let mut servers = Vec::new();
+    for (name, spec) in file.servers {
+        if name.is_empty() || name.chars().any(char::is_control) {
+            return Err(format!("servers file {}: invalid server name {:?}", path.display(), name));
+        }
+        let mut vars = match &spec.env_file {
+            Some(env_file) => child_env::load_file(&base.join(env_file))?,
+            None => Vec::new(),
+        };
+        vars.extend(spec.env);
+        let cwd = spec.cwd.map(|cwd| base.join(cwd));
+        if let Some(cwd) = cwd.as_ref().filter(|d| !d.is_dir()) {
+            return Err(format!("server {}: cwd {} is not a directory", name, cwd.display()));
+        }
+        servers.push(Server {
+            name,
+            command: std::iter::once(spec.command).chain(spec.args).collect(),
+            env: ChildEnv { vars, clear: spec.clear_env, cwd },
+            throughput: Arc::default(),
+        });
+    }
+    Ok(servers)
+}
+
+/// Where clients attach and which servers they can ask for.
+pub struct Hub {
+    pub servers: Vec<Server>,
+    pub listen: String,
+    /// Required in every handshake when set
+    pub token: Option<String>,
+}
+
+/// First line an attaching client sends.
+#[derive(Deserialize)]
+struct Handshake {
+    server: String,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// Serve several MCP servers from one Sentinel. Clients connect on
+/// `hub.listen` (normally through `sentinel attach <name>`), name a server, and
+/// from then on speak MCP to a fresh instance of it, proxied like a single
+/// `run` would be. Every tap is tagged with the server's name, so all servers
+/// share one audit log with per-server sessions. A server takes one client at
+/// a time. Runs until `stop` is cancelled, then ends every attachment.
+pub async fn run(hub: Hub, tx: Tap, config: ProxyConfig, stop: CancellationToken) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(&hub.listen).await?;
+    let local = listener.local_addr()?;
+    eprintln!("🧩 Hub listening on {} for {} server(s)", local, hub.servers.len());
+    for server in &hub.servers {
+        eprintln!("   {}: sentinel attach {} --hub {}", server.name, server.name, local);
+    }
+    let mut record = json!({
+        "listen": local.to_string(),
+        "servers": hub.servers.iter().map(|s| &s.name).collect::<Vec<_>>(),
+        "redaction": crate::redaction::describe(config.redaction),
+    });
+    if !config.redaction_rules.is_empty() {
+        record["redaction_rules"] = json!(config.redaction_rules);
+    }
+    let started = RawTap::sentinel("sentinel/hub_started", record);
+    tx.send(started).await;
+
+    let hub = Arc::new(hub);
+    let attached = Arc::new(Mutex::new(HashSet::<String>::new()));
+    let mut attachments = JoinSet::new();
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(a) => a,
+                Err(e) => {
+                    eprintln!("⚠️  Hub accept failed: {}", e);
+                    continue;
+                }
+            },
+            _ = stop.cancelled() => break,
+        };
+        // Reap finished attachments as we go.
+        while attachments.try_join_next().is_some() {}
+
+        attachments.spawn(attach_client(
+            stream,
+            peer.to_string(),
+            hub.clone(),
+            attached.clone(),
+            tx.clone(),
+            config.clone(),
+            stop.child_token(),
+        ));
+    }
+
+    drop(listener);
+    while attachments.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Serve one attaching client: handshake, then proxy it to its own instance
+/// of the server it asked for.
+async fn attach_client(
+    stream: TcpStream,
+    peer: String,
+    hub: Arc<Hub>,
+    attached: Arc<Mutex<HashSet<String>>>,
+    tx: Tap,
+    mut config: ProxyConfig,
+    stop: CancellationToken,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let server = match handshake(&mut reader, &hub, &attached).await {
+        Ok(server) => server,
+        Err(reason) => {
+            eprintln!("⚠️  Hub refused {}: {}", peer, reason);
+            let refused = RawTap::sentinel("sentinel/attach_refused", json!({ "peer": peer, "reason": reason }));
+            tx.send(refused).await;
+            let _ = writer.write_all(&reply(json!({ "error": reason }))).await;
+            return;
+        }
+    };
+    let tx = tx.for_server(&server.name);
+
+    if writer.write_all(&reply(json!({ "ok": true }))).await.is_err() {
+        release(&attached, &server.name);
+        return;
+    }
+    eprintln!("🔗 {} attached to {}", peer, server.name);
+    tx.send(RawTap::sentinel("sentinel/client_attached", json!({ "peer": peer }))).await;
+    let attached_at = Instant::now();
+
+    // A client that hangs up gets no more replies: give the server the usual
+    // grace to exit on its closed stdin, then stop it.
+    let client_gone = CancellationToken::new();
+    let grace = config.child_shutdown_timeout;
+    let watchdog = {
+        let client_gone = client_gone.clone();
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            client_gone.cancelled().await;
+            tokio::time::sleep(grace).await;
+            stop.cancel();
+        })
+    };
+
+    let client = Client {
+        input: Box::new(CancelOnEof { inner: reader, token: client_gone }),
+        output: Box::new(writer),
+    };
+    config.child_env = server.env.clone();
+    config.throughput = server.throughput.clone();
+    let result = run_proxy(Upstream::Command(server.command.clone()), client, tx.clone(), config, stop)
+        .await
+        .map_err(|e| e.to_string());
+    watchdog.abort();
+
+    let detached = match &result {
+        Ok(status) => json!({
+            "peer": peer,
+            "duration_ms": attached_at.elapsed().as_millis() as u64,
+            "exit_code": status.and_then(|s| s.code()),
+            "signal": status.as_ref().and_then(exit_signal),
+        }),
+        Err(e) => {
+            eprintln!("❌ {} ({}): {}", server.name, peer, e);
+            json!({
+                "peer": peer,
+                "duration_ms": attached_at.elapsed().as_millis() as u64,
+                "error": e,
+            })
+        }
+    };
+    eprintln!("🔗 {} detached from {}", peer, server.name);
+    tx.send(RawTap::sentinel("sentinel/client_detached", detached)).await;
+    release(&attached, &server.name);
+}
+
+/// Read the client's handshake and claim the server it names.
+async fn handshake<'a>(
+    reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>,
+    hub: &'a Hub,
+    attached: &Mutex<HashSet<String>>,
+) -> Result<&'a Server, String> {
+    let mut line = Vec::new();
+    let read = tokio::time::timeout(HANDSHAKE_TIMEOUT, read_line_bounded(reader, &mut line, HANDSHAKE_MAX_BYTES)).await;
+    match read {
+        Ok(Ok((n, false))) if n > 0 => {}
+        Ok(Ok(_)) => return Err("malformed handshake".to_string()),
+        Ok(Err(e)) => return Err(format!("handshake: {}", e)),
+        Err(_) => return Err("no handshake".to_string()),
+    }
+    let handshake: Handshake = serde_json::from_slice(&line).map_err(|_| "malformed handshake".to_string())?;
+
+    if let Some(expected) = &hub.token {
+        if handshake.token.as_ref() != Some(expected) {
+            return Err("invalid hub token".to_string());
+        }
+    }
+    let server = hub
+        .servers
+        .iter()
+        .find(|s| s.name == handshake.server)
+        .ok_or_else(|| format!("no server named {:?}", handshake.server))?;
+    if !attached.lock().unwrap_or_else(|e| e.into_inner()).insert(server.name.clone()) {
+        return Err(format!("server {:?} already has a client", server.name));
+    }
+    Ok(server)
+}
+
+fn release(attached: &Mutex<HashSet<String>>, name: &str) {
+    attached.lock().unwrap_or_else(|e| e.into_inner()).remove(name);
+}
+
+fn reply(value: Value) -> Vec<u8> {
+    let mut line = serde_json::to_vec(&value).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+/// Client input that cancels `token` once the client has hung up.
+struct CancelOnEof<R> {
+    inner: R,
+    token: CancellationToken,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CancelOnEof<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        match &poll {
+            Poll::Ready(Ok(())) if buf.filled().len() == before && buf.remaining() > 0 => self.token.cancel(),
+            Poll::Ready(Err(_)) => self.token.cancel(),
+            _ => {}
+        }
+        poll
+    }
+}
+
+/// Connect our stdio to server `name` on a running hub (`sentinel attach`).
+/// Meant to be what the MCP client launches: it relays bytes both ways and
+/// exits when the hub closes the connection.
+pub async fn attach(name: &str, hub: &str, token: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let stream = TcpStream::connect(hub)
+        .await
+        .map_err(|e| format!("connect to hub {}: {}", hub, e))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    writer.write_all(&reply(json!({ "server": name, "token": token }))).await?;
+    let mut line = Vec::new();
+    read_line_bounded(&mut reader, &mut line, HANDSHAKE_MAX_BYTES).await?;
+    let answer: Value = serde_json::from_slice(&line).map_err(|_| format!("hub {} did not answer the handshake", hub))?;
+    if let Some(error) = answer.get("error").and_then(Value::as_str) {
+        return Err(format!("hub refused: {}", error).into());
+    }
+
+    let upload = tokio::spawn(async move {
+        let _ = tokio::io::copy(&mut tokio::io::stdin(), &mut writer).await;
+        let _ = writer.shutdown().await;
+    });
+    let mut stdout = tokio::io::stdout();
+    let result = tokio::io::copy(&mut reader, &mut stdout).await;
+    let _ = stdout.flush().await;
+    upload.abort();
+    result?;
+    Ok(())
+}
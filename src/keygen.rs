@@ -4,8 +4,9 @@ use rand::rngs::OsRng;
 use std::fs;
 use std::path::Path;
 
-/// Generate an Ed25519 keypair and write base64-encoded files.
-pub fn generate_keypair(out_dir: impl AsRef<Path>) -> Result<(), String> {
+/// Generate an Ed25519 keypair and write base64-encoded files. With a
+/// passphrase the seed file is a keystore (see [`crate::keys::seal_keystore`]).
+pub fn generate_keypair(out_dir: impl AsRef<Path>, passphrase: Option<&str>) -> Result<(), String> {
     let out_dir = out_dir.as_ref();
     fs::create_dir_all(out_dir)
         .map_err(|e| format!("failed to create output dir {:?}: {}", out_dir, e))?;
@@ -14,13 +15,16 @@ pub fn generate_keypair(out_dir: impl AsRef<Path>) -> Result<(), String> {
     let signing_key = SigningKey::generate(&mut OsRng);
     let verifying_key = signing_key.verifying_key();
 
-    let seed_b64 = B64.encode(signing_key.to_bytes());
+    let seed_file = match passphrase {
+        Some(passphrase) => crate::keys::seal_keystore(&signing_key.to_bytes(), passphrase)?,
+        None => format!("{}\n", B64.encode(signing_key.to_bytes())),
+    };
     let pub_b64 = B64.encode(verifying_key.to_bytes());
 
     let seed_path = out_dir.join("sentinel_seed.b64");
     let pub_path = out_dir.join("sentinel_pub.b64");
 
-    fs::write(&seed_path, format!("{}\n", seed_b64))
+    fs::write(&seed_path, seed_file)
         .map_err(|e| format!("failed to write {:?}: {}", seed_path, e))?;
 
     fs::write(&pub_path, format!("{}\n", pub_b64))
@@ -28,6 +32,9 @@ pub fn generate_keypair(out_dir: impl AsRef<Path>) -> Result<(), String> {
 
     println!("Generated Sentinel signing keypair:");
     println!("  Private key (KEEP SECRET): {:?}", seed_path);
+    if passphrase.is_some() {
+        println!("  (passphrase-protected keystore)");
+    }
     println!("  Public key  (SHARE):       {:?}", pub_path);
 
     Ok(())
@@ -0,0 +1,386 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Environment variable read for the passphrase of a keystore being loaded.
+/// Without it the passphrase is prompted for on the terminal.
+pub const PASSPHRASE_ENV: &str = "SENTINEL_KEY_PASSPHRASE";
+
+/// Supplies base64 key material. Implemented for the CLI key specs below; a
+/// native KMS integration can implement it too.
+pub trait KeyProvider {
+    /// Identifies the provider in error messages. Never includes key material.
+    fn describe(&self) -> String;
+
+    /// The key as base64 text (surrounding whitespace is ignored).
+    fn fetch_b64(&self) -> Result<String, String>;
+}
+
+/// A key given on the command line:
+/// - `cmd:<program> [args...]` runs the program (no shell) and reads its stdout
+/// - `env:<VAR>` reads an environment variable
+/// - anything else is a file path
+///
+/// [`load_key32`] also takes `-`, the first line of stdin.
+#[derive(Debug, Clone)]
+pub enum KeySpec {
+    File(PathBuf),
+    Command(Vec<String>),
+    Env(String),
+}
+
+impl KeySpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if let Some(cmd) = spec.strip_prefix("cmd:") {
+            let argv: Vec<String> = cmd.split_whitespace().map(str::to_string).collect();
+            if argv.is_empty() {
+                return Err("empty cmd: key provider".to_string());
+            }
+            Ok(KeySpec::Command(argv))
+        } else if let Some(var) = spec.strip_prefix("env:") {
+            if var.is_empty() {
+                return Err("empty env: key provider".to_string());
+            }
+            Ok(KeySpec::Env(var.to_string()))
+        } else {
+            Ok(KeySpec::File(PathBuf::from(spec)))
+        }
+    }
+}
+
+impl KeyProvider for KeySpec {
+    fn describe(&self) -> String {
+        match self {
+            KeySpec::File(path) => format!("file {}", path.display()),
+            KeySpec::Command(argv) => format!("cmd:{}", argv[0]),
+            KeySpec::Env(var) => format!("env:{}", var),
+        }
+    }
+
+    fn fetch_b64(&self) -> Result<String, String> {
+        match self {
+            KeySpec::File(path) => std::fs::read_to_string(path).map_err(|e| e.to_string()),
+            KeySpec::Env(var) => std::env::var(var).map_err(|e| e.to_string()),
+            KeySpec::Command(argv) => {
+                // stderr passes through so the provider can report its own problems.
+                let output = Command::new(&argv[0])
+                    .args(&argv[1..])
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .map_err(|e| format!("could not start: {}", e))?;
+                if !output.status.success() {
+                    return Err(format!("exited with {}", output.status));
+                }
+                String::from_utf8(output.stdout).map_err(|_| "output is not UTF-8".to_string())
+            }
+        }
+    }
+}
+
+/// Fetch a 32-byte key from `spec`. `what` names the key in errors
+/// ("signing key", "recipient private key", ...).
+pub fn load_key32(spec: &str, what: &str) -> Result<[u8; 32], String> {
+    if spec == "-" {
+        return load_key32_from(&StdinKey(what), what);
+    }
+    let provider = KeySpec::parse(spec).map_err(|e| format!("{}: {}", what, e))?;
+    load_key32_from(&provider, what)
+}
+
+/// The line read for a `-` key spec, and the key it was read for. `run`
+/// passes the rest of stdin on to the child, so it can be read only once.
+static STDIN_KEY: Mutex<Option<(String, Zeroizing<String>)>> = Mutex::new(None);
+
+/// `-`: the first line of stdin, read on the first load of `what` and reused by
+/// later loads of it (a rotated log reloads its recipient key). Any other key
+/// asking for stdin is refused, as the line is already taken.
+struct StdinKey<'a>(&'a str);
+
+impl KeyProvider for StdinKey<'_> {
+    fn describe(&self) -> String {
+        "stdin".to_string()
+    }
+
+    fn fetch_b64(&self) -> Result<String, String> {
+        let mut cached = STDIN_KEY.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((owner, line)) = cached.as_ref() {
+            if owner != self.0 {
+                return Err(format!("stdin was already read for the {}", owner));
+            }
+            return Ok(line.to_string());
+        }
+
+        // Only the line is consumed: the rest stays buffered in std's stdin
+        // for whoever reads it next.
+        let mut line = Zeroizing::new(String::new());
+        std::io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            return Err("no key on stdin".to_string());
+        }
+        *cached = Some((self.0.to_string(), line.clone()));
+        Ok(line.to_string())
+    }
+}
+
+/// Fetch secret text other than a base64 key, such as an age identity file,
+/// from a spec of the same forms as for [`load_key32`].
+pub fn load_text(spec: &str, what: &str) -> Result<Zeroizing<String>, String> {
+    let fetched = if spec == "-" {
+        StdinKey(what).fetch_b64()
+    } else {
+        let provider = KeySpec::parse(spec).map_err(|e| format!("{}: {}", what, e))?;
+        provider.fetch_b64().map_err(|e| format!("{} from {}: fetch failed: {}", what, provider.describe(), e))
+    };
+    fetched.map(Zeroizing::new)
+}
+
+pub fn load_key32_from(provider: &dyn KeyProvider, what: &str) -> Result<[u8; 32], String> {
+    let fail = |stage: &str, detail: String| format!("{} from {}: {} failed: {}", what, provider.describe(), stage, detail);
+
+    let text = Zeroizing::new(provider.fetch_b64().map_err(|e| fail("fetch", e))?);
+    if is_keystore(&text) {
+        let passphrase = load_passphrase(what).map_err(|e| fail("unlock", e))?;
+        return open_keystore(&text, &passphrase).map_err(|e| match e {
+            KeystoreError::WrongPassphrase => fail("unlock", "wrong passphrase".to_string()),
+            KeystoreError::Corrupted(detail) => fail("decode", format!("corrupted keystore: {}", detail)),
+        });
+    }
+
+    let mut bytes = B64
+        .decode(text.trim())
+        .map_err(|_| fail("decode", "not valid base64".to_string()))?;
+    if bytes.len() != 32 {
+        return Err(fail("decode", format!("expected 32 bytes, got {}", bytes.len())));
+    }
+
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes);
+    bytes.zeroize();
+    Ok(out)
+}
+
+// ===== Passphrase-protected keystores =====
+//
+// A private key file may hold a JSON keystore instead of bare base64: the
+// 32-byte secret sealed with ChaCha20Poly1305 under a key derived from a
+// passphrase with Argon2id. `check` is derived from the same Argon2id output,
+// so a wrong passphrase is told apart from a damaged file before decrypting.
+
+const KEYSTORE_FORMAT: &str = "sentinel-keystore/v1";
+
+// OWASP's minimum for Argon2id; unlocking takes well under a second.
+const DEFAULT_KDF: KdfParams = KdfParams { m_cost_kib: 19 * 1024, t_cost: 2, p_cost: 1 };
+
+// Bounds on the parameters a keystore may ask for, so a hostile file cannot
+// make loading it allocate gigabytes or run for minutes.
+const MAX_M_COST_KIB: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 16;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct KdfParams {
+    m_cost_kib: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Keystore {
+    format: String,
+    kdf: String,
+    params: KdfParams,
+    salt: String,
+    nonce: String,
+    check: String,
+    ciphertext: String,
+}
+
+pub enum KeystoreError {
+    WrongPassphrase,
+    Corrupted(String),
+}
+
+/// Where `keygen` gets the passphrase for a new keystore.
+#[derive(Debug, Clone)]
+pub enum PassphraseSource {
+    Env(String),
+    Prompt,
+}
+
+impl PassphraseSource {
+    /// Read the passphrase, asking twice when prompting.
+    pub fn read_new(&self) -> Result<Zeroizing<String>, String> {
+        let passphrase = match self {
+            PassphraseSource::Env(var) => {
+                Zeroizing::new(std::env::var(var).map_err(|e| format!("passphrase from env:{}: {}", var, e))?)
+            }
+            PassphraseSource::Prompt => {
+                let first = prompt("New passphrase: ")?;
+                let second = prompt("Repeat passphrase: ")?;
+                if *first != *second {
+                    return Err("passphrases do not match".to_string());
+                }
+                first
+            }
+        };
+        if passphrase.is_empty() {
+            return Err("passphrase is empty".to_string());
+        }
+        Ok(passphrase)
+    }
+}
+
+fn prompt(text: &str) -> Result<Zeroizing<String>, String> {
+    rpassword::prompt_password(text)
+        .map(Zeroizing::new)
+        .map_err(|e| format!("cannot read passphrase from the terminal ({}); set {}", e, PASSPHRASE_ENV))
+}
+
+fn load_passphrase(what: &str) -> Result<Zeroizing<String>, String> {
+    match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => Ok(Zeroizing::new(passphrase)),
+        Err(std::env::VarError::NotUnicode(_)) => Err(format!("{} is not UTF-8", PASSPHRASE_ENV)),
+        Err(std::env::VarError::NotPresent) => prompt(&format!("Passphrase for {}: ", what)),
+    }
+}
+
+/// Whether key file contents are a keystore rather than bare base64.
+pub fn is_keystore(text: &str) -> bool {
+    text.trim_start().starts_with('{')
+}
+
+/// Seal `secret` under `passphrase`, returning the keystore file contents.
+pub fn seal_keystore(secret: &[u8; 32], passphrase: &str) -> Result<String, String> {
+    let params = DEFAULT_KDF;
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let (key, check) = derive_keystore_keys(passphrase, &salt, params)?;
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: secret, aad: &keystore_aad(params) })
+        .map_err(|_| "keystore encryption failed".to_string())?;
+
+    let keystore = Keystore {
+        format: KEYSTORE_FORMAT.to_string(),
+        kdf: "argon2id".to_string(),
+        params,
+        salt: B64.encode(salt),
+        nonce: B64.encode(nonce),
+        check: B64.encode(check),
+        ciphertext: B64.encode(ciphertext),
+    };
+    let json = serde_json::to_string_pretty(&keystore).map_err(|e| e.to_string())?;
+    Ok(format!("{}\n", json))
+}
+
+/// Open a keystore produced by [`seal_keystore`].
+pub fn open_keystore(text: &str, passphrase: &str) -> Result<[u8; 32], KeystoreError> {
+    let corrupted = |detail: &str| KeystoreError::Corrupted(detail.to_string());
+
+    let keystore: Keystore = serde_json::from_str(text).map_err(|e| KeystoreError::Corrupted(e.to_string()))?;
+    if keystore.format != KEYSTORE_FORMAT {
+        return Err(KeystoreError::Corrupted(format!("unsupported format {:?}", keystore.format)));
+    }
+    if keystore.kdf != "argon2id" {
+        return Err(KeystoreError::Corrupted(format!("unsupported kdf {:?}", keystore.kdf)));
+    }
+    let params = keystore.params;
+    if params.m_cost_kib > MAX_M_COST_KIB || params.t_cost > MAX_T_COST || params.p_cost > MAX_P_COST {
+        return Err(corrupted("kdf params out of range"));
+    }
+    let salt = B64.decode(&keystore.salt).map_err(|_| corrupted("salt is not valid base64"))?;
+    let nonce = B64.decode(&keystore.nonce).map_err(|_| corrupted("nonce is not valid base64"))?;
+    let check = B64.decode(&keystore.check).map_err(|_| corrupted("check is not valid base64"))?;
+    let ciphertext = B64.decode(&keystore.ciphertext).map_err(|_| corrupted("ciphertext is not valid base64"))?;
+    if nonce.len() != 12 {
+        return Err(corrupted("nonce must be 12 bytes"));
+    }
+
+    let (key, expected_check) = derive_keystore_keys(passphrase, &salt, params).map_err(KeystoreError::Corrupted)?;
+    if check[..] != expected_check[..] {
+        return Err(KeystoreError::WrongPassphrase);
+    }
+
+    // The passphrase is right, so a failure from here on means the file changed.
+    let mut plaintext = ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &keystore_aad(params) })
+        .map_err(|_| corrupted("ciphertext failed authentication"))?;
+    if plaintext.len() != 32 {
+        plaintext.zeroize();
+        return Err(corrupted("sealed key is not 32 bytes"));
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&plaintext);
+    plaintext.zeroize();
+    Ok(out)
+}
+
+fn keystore_aad(params: KdfParams) -> Vec<u8> {
+    format!("{}|argon2id|{}|{}|{}", KEYSTORE_FORMAT, params.m_cost_kib, params.t_cost, params.p_cost).into_bytes()
+}
+
+/// (encryption key, passphrase check), both derived from one Argon2id output.
+fn derive_keystore_keys(
+    passphrase: &str,
+    salt: &[u8],
+    params: KdfParams,
+) -> Result<(Zeroizing<[u8; 32]>, [u8; 32]), String> {
+    let argon_params = Params::new(params.m_cost_kib, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| format!("invalid kdf params: {}", e))?;
+    let mut master = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut master[..])
+        .map_err(|e| format!("kdf failed: {}", e))?;
+
+    let key = Zeroizing::new(blake3::derive_key("sentinel keystore v1 encryption key", &master[..]));
+    let check = blake3::derive_key("sentinel keystore v1 passphrase check", &master[..]);
+    Ok((key, check))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROVIDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/keys/provider.sh");
+
+    fn from_cmd(mode: &str) -> Result<[u8; 32], String> {
+        load_key32(&format!("cmd:{} {}", PROVIDER, mode), "signing key")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_cmd_provider_supplies_the_key_from_its_stdout() {
+        assert_eq!(from_cmd("key").unwrap(), [7; 32]);
+    }
+
+    /// Errors name the key, the provider and the stage, and never echo what
+    /// the provider printed.
+    #[cfg(unix)]
+    #[test]
+    fn a_failing_cmd_provider_says_where_without_the_output() {
+        let err = from_cmd("fail").unwrap_err();
+        assert_eq!(err, format!("signing key from cmd:{}: fetch failed: exited with exit status: 3", PROVIDER));
+
+        let err = from_cmd("short").unwrap_err();
+        assert_eq!(err, format!("signing key from cmd:{}: decode failed: expected 32 bytes, got 6", PROVIDER));
+
+        let err = from_cmd("garbage").unwrap_err();
+        assert_eq!(err, format!("signing key from cmd:{}: decode failed: not valid base64", PROVIDER));
+
+        let err = load_key32("cmd:/nonexistent/provider", "recipient private key").unwrap_err();
+        assert!(err.starts_with("recipient private key from cmd:/nonexistent/provider: fetch failed: could not start"), "{}", err);
+    }
+}
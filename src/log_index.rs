@@ -0,0 +1,258 @@
+use crate::audit::{self, AuditRecord};
+use crate::audit_crypto::RecordDecryptor;
+use crate::redaction::{self, RedactionPolicy};
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+/// Most entries an index keeps, however long the run.
+const MAX_INDEX_ENTRIES: usize = 4096;
+
+/// Where this run's events are in its audit log, so history that has left
+/// memory (and the spill) can be read back from the log.
+///
+/// Only every `stride`th event's offset is kept. When the index is full, every
+/// other entry goes and the stride doubles, so it stays within
+/// [`MAX_INDEX_ENTRIES`] and finding an event reads at most `stride` records.
+pub struct LogIndex {
+    inner: Mutex<Inner>,
+    /// Recipient private key of an encrypted log
+    recipient_sk: Option<Zeroizing<[u8; 32]>>,
+    /// Redact events read back, as the dashboard copy is (`--redact-scope stream-only`)
+    redaction: Option<Arc<RedactionPolicy>>,
+}
+
+struct Inner {
+    /// (event_id, segment, offset of its record), oldest first
+    entries: Vec<(u64, usize, u64)>,
+    stride: u64,
+    /// Events written since the last entry
+    since_entry: u64,
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone)]
+struct Segment {
+    path: PathBuf,
+    /// Offsets of the KeyEnvelopes of rotated data keys (after the first one)
+    envelopes: Vec<u64>,
+}
+
+impl LogIndex {
+    /// Index the log written at `path`; `recipient_sk` decrypts it when it is
+    /// envelope-encrypted.
+    pub fn new(
+        path: PathBuf,
+        recipient_sk: Option<Zeroizing<[u8; 32]>>,
+        redaction: Option<Arc<RedactionPolicy>>,
+    ) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: Vec::new(),
+                stride: 1,
+                since_entry: 0,
+                segments: vec![Segment { path, envelopes: Vec::new() }],
+            }),
+            recipient_sk,
+            redaction,
+        }
+    }
+
+    /// The record of `event_id` was written at `offset` of the current segment.
+    pub fn event_written(&self, event_id: u64, offset: u64) {
+        let mut inner = self.lock();
+        if !inner.entries.is_empty() && inner.since_entry + 1 < inner.stride {
+            inner.since_entry += 1;
+            return;
+        }
+        if inner.entries.len() == MAX_INDEX_ENTRIES {
+            let mut i = 0;
+            inner.entries.retain(|_| {
+                i += 1;
+                i % 2 == 1
+            });
+            inner.stride *= 2;
+        }
+        let segment = inner.segments.len() - 1;
+        inner.entries.push((event_id, segment, offset));
+        inner.since_entry = 0;
+    }
+
+    /// A KeyEnvelope for a rotated data key was written at `offset` of the current segment.
+    pub fn key_rotated(&self, offset: u64) {
+        if let Some(segment) = self.lock().segments.last_mut() {
+            segment.envelopes.push(offset);
+        }
+    }
+
+    /// Records go to the rotated segment at `path` from now on.
+    pub fn segment_started(&self, path: PathBuf) {
+        self.lock().segments.push(Segment { path, envelopes: Vec::new() });
+    }
+
+    /// Events with ids in `from..until`, oldest first, handed to `each` until it
+    /// returns false.
+    pub fn scan(&self, from: u64, until: u64, mut each: impl FnMut(u64, Arc<str>) -> bool) -> Result<(), String> {
+        let (start, segments) = {
+            let inner = self.lock();
+            // The last entry at or before `from`; the first one if `from` is older
+            let i = inner.entries.partition_point(|(id, _, _)| *id <= from).saturating_sub(1);
+            let Some(&(_, segment, offset)) = inner.entries.get(i) else {
+                return Ok(());
+            };
+            ((segment, offset), inner.segments[segment..].to_vec())
+        };
+
+        let mut offset = start.1;
+        for segment in &segments {
+            let go_on = self.scan_segment(segment, offset, |id, json| {
+                if id < from {
+                    return true;
+                }
+                id < until && each(id, json)
+            })?;
+            if !go_on {
+                break;
+            }
+            offset = 0;
+        }
+        Ok(())
+    }
+
+    /// Up to `n` events with ids below `until`, oldest first.
+    pub fn last_before(&self, until: u64, n: usize) -> Result<Vec<Arc<str>>, String> {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        // Ids are mostly consecutive; start further back until enough are found.
+        let mut back = n as u64;
+        loop {
+            let from = until.saturating_sub(back);
+            let mut found = VecDeque::with_capacity(n);
+            self.scan(from, until, |_, json| {
+                if found.len() == n {
+                    found.pop_front();
+                }
+                found.push_back(json);
+                true
+            })?;
+            if found.len() == n || from == 0 || self.first_id().is_none_or(|first| first >= from) {
+                return Ok(found.into());
+            }
+            back = back.saturating_mul(2);
+        }
+    }
+
+    /// Id of the oldest event indexed.
+    fn first_id(&self) -> Option<u64> {
+        self.lock().entries.first().map(|(id, _, _)| *id)
+    }
+
+    /// Hand the events of `segment` from `offset` on to `each`; false once it
+    /// asked to stop.
+    fn scan_segment(
+        &self,
+        segment: &Segment,
+        offset: u64,
+        mut each: impl FnMut(u64, Arc<str>) -> bool,
+    ) -> Result<bool, String> {
+        let path = segment.path.display();
+        let file = File::open(&segment.path).map_err(|e| format!("open audit log {}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+        let mut decryptor = match &self.recipient_sk {
+            Some(sk) => Some(self.decryptor(&mut reader, segment, sk, offset)?),
+            None => None,
+        };
+        reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| format!("seek audit log {}: {}", path, e))?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = reader
+                .read_line(&mut line)
+                .map_err(|e| format!("read audit log {}: {}", path, e))?;
+            if n == 0 {
+                return Ok(true);
+            }
+            let s = audit::normalize_line(&line);
+            if s.is_empty() || audit::parse_file_header(s).ok().flatten().is_some() {
+                continue;
+            }
+            let plaintext = match decryptor.as_mut() {
+                Some((decryptor, generations)) => {
+                    if let Some(env) = RecordDecryptor::parse_envelope(s) {
+                        if env.key_generation >= *generations {
+                            decryptor.add_envelope(&env)?;
+                            *generations = env.key_generation + 1;
+                        }
+                        continue;
+                    }
+                    match decryptor.decrypt_record(s)? {
+                        (inner, plaintext) if inner == "Event" => plaintext,
+                        _ => continue,
+                    }
+                }
+                None => s.to_string(),
+            };
+
+            let Ok(AuditRecord::Event { mut log, .. }) = serde_json::from_str::<AuditRecord>(&plaintext) else {
+                continue;
+            };
+            if let Some(policy) = &self.redaction {
+                redaction::redact_log(&mut log, policy).mark(&mut log);
+            }
+            let json = serde_json::to_string(&log).map_err(|e| format!("serialize event: {}", e))?;
+            if !each(log.event_id, Arc::from(json)) {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// A decryptor for the records of `segment` from `offset` on, with the key
+    /// generations it holds: the first KeyEnvelope (the first one at the top of
+    /// the file) and those of the data keys rotated before `offset`.
+    fn decryptor(
+        &self,
+        reader: &mut BufReader<File>,
+        segment: &Segment,
+        sk: &[u8; 32],
+        offset: u64,
+    ) -> Result<(RecordDecryptor, u32), String> {
+        let path = segment.path.display();
+        let mut read_envelope = |at: u64, lines: usize| -> Result<_, String> {
+            reader
+                .seek(SeekFrom::Start(at))
+                .map_err(|e| format!("seek audit log {}: {}", path, e))?;
+            let mut line = String::new();
+            for _ in 0..lines {
+                line.clear();
+                reader
+                    .read_line(&mut line)
+                    .map_err(|e| format!("read audit log {}: {}", path, e))?;
+                if let Some(env) = RecordDecryptor::parse_envelope(&line) {
+                    return Ok(env);
+                }
+            }
+            Err(format!("audit log {}: no KeyEnvelope at offset {}", path, at))
+        };
+
+        // The header, then the first envelope
+        let mut decryptor = RecordDecryptor::with_secret(&read_envelope(0, 2)?, *sk)?;
+        let mut generations = 1;
+        for &at in segment.envelopes.iter().filter(|at| **at < offset) {
+            decryptor.add_envelope(&read_envelope(at, 1)?)?;
+            generations += 1;
+        }
+        Ok((decryptor, generations))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -2,10 +2,9 @@ use clap::{Args, Parser, Subcommand};
 use std::process;
 use std::sync::Arc;
 use tokio::signal;
-use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::sync::mpsc;
 use uuid::Uuid;
-use std::path::Path;
-use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 
 mod proxy;
 mod protocol;
@@ -20,11 +19,52 @@ mod keygen;
 mod audit_crypto;
 mod config;
 mod frontend;
+mod schema;
+mod metrics;
+mod feed;
+mod watch;
+mod webhook;
+mod shutdown;
+mod report;
+mod clock;
+mod capture;
+mod rederive;
+mod throttle;
+mod tap;
+mod keys;
+mod detection;
+mod history;
+mod framing;
+mod child_env;
+mod control;
+mod http_proxy;
+mod hub;
+mod throughput;
+mod reassembly;
+mod merkle;
+mod blobs;
+mod migrate;
+mod timestamp;
+mod witness;
+mod replay;
+mod attestation;
+mod health;
+mod log_index;
+mod audit_control;
+mod auth;
+mod redact_test;
+mod unredact;
+#[cfg(feature = "ffi")]
+mod ffi;
 
 use parser::Parser as LogParser;
-use proxy::run_proxy;
+use proxy::{run_proxy, Client, Endpoint, ProxyConfig, RestartPolicy, Upstream};
 use server::{start_server, ServerState};
 use session::Session;
+use shutdown::{Phase, ShutdownController, ShutdownReason};
+
+/// Events kept in memory for dashboard replay (also bounded by --history-max-bytes)
+const HISTORY_MAX_EVENTS: usize = 10_000;
 
 #[derive(Parser)]
 #[command(name = "sentinel")]
@@ -34,36 +74,386 @@ struct Cli {
     command: Commands,
 }
 
+// Parsed once at startup; boxing RunArgs would buy nothing.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     Run(RunArgs),
     Verify(VerifyArgs),
+    /// Continuously verify an audit log as it grows
+    WatchVerify(WatchVerifyArgs),
     Keygen(KeygenArgs),
     RecipientKeygen(RecipientKeygenArgs),
+    /// Print the key id of a signing or recipient public key
+    KeyId(KeyIdArgs),
+    Schema(SchemaArgs),
+    /// Aggregate statistics across all audit logs in a directory
+    Report(ReportArgs),
+    /// Re-derive an audit log from its raw capture and diff it against the original
+    Rederive(RederiveArgs),
+    /// Connect stdio to a server of a running `sentinel run --servers` hub
+    Attach(AttachArgs),
+    /// Write a proof that one event is in an audit log, checkable without the log
+    Prove(ProveArgs),
+    /// Check a proof written by `sentinel prove` against the public key
+    VerifyProof(VerifyProofArgs),
+    /// Print a payload stored by `run --blob-dir`, given the hash in its stub
+    FetchBlob(FetchBlobArgs),
+    /// Rewrite a verified audit log with every event at the current record version
+    MigrateLog(MigrateLogArgs),
+    /// Replay an audit log's events to the dashboard (nothing is written)
+    Replay(ReplayArgs),
+    /// Decrypt an encrypted audit log to plaintext records, optionally verifying them
+    Decrypt(DecryptArgs),
+    /// Write a copy of an audit log with the values vault redaction rules encrypted restored
+    Unredact(UnredactArgs),
+    /// Apply a redaction config to sample events and count what each rule replaces
+    RedactTest(RedactTestArgs),
+    /// Print the data keys of an encrypted log, unwrapped with the escrow private key
+    RevealDek(RevealDekArgs),
+    /// Verify an audit log and write its key, runs, counts, final chain hash and
+    /// signed checkpoints as one JSON bundle for a third-party auditor
+    ExportAttestation(ExportAttestationArgs),
+    /// Check the checkpoint signatures in a bundle written by `sentinel export-attestation`
+    VerifyAttestation(VerifyAttestationArgs),
 }
 
 #[derive(Args)]
 struct RunArgs {
-    #[arg(num_args = 1.., last = true)]
+    #[arg(num_args = 1.., last = true, required_unless_present_any = ["connect", "http_upstream", "servers"])]
     command: Vec<String>,
 
+    /// Wrap every server listed in this JSON file in one Sentinel; MCP clients
+    /// reach them with `sentinel attach <name>`
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["command", "connect", "http_upstream", "child_env", "child_env_file", "child_clear_env", "child_cwd"]
+    )]
+    servers: Option<PathBuf>,
+
+    /// Where `sentinel attach` reaches the --servers hub
+    #[arg(long, default_value = "127.0.0.1:7400", requires = "servers")]
+    hub_listen: String,
+
+    /// Token `sentinel attach` must present (or set SENTINEL_HUB_TOKEN)
+    #[arg(long, requires = "servers")]
+    hub_token: Option<String>,
+
+    /// Tap an MCP server listening on a socket instead of spawning a command:
+    /// `tcp://host:port`, `unix:///path/to.sock` (Unix) or `pipe://name` (Windows named pipe)
+    #[arg(long, value_name = "URL", conflicts_with_all = ["command", "http_upstream"])]
+    connect: Option<String>,
+
+    /// Tap a streamable HTTP MCP endpoint, reverse-proxied on --http-listen
+    #[arg(long, value_name = "URL", conflicts_with = "command")]
+    http_upstream: Option<String>,
+
+    /// Where clients reach the --http-upstream endpoint
+    #[arg(long, default_value = "127.0.0.1:8080", requires = "http_upstream")]
+    http_listen: String,
+
     #[arg(long, default_value = "sentinel_audit.jsonl")]
     audit_log: String,
 
-    #[arg(long)]
+    /// Also write the audit log, byte for byte, to this file (repeatable); rotated
+    /// segments are mirrored too
+    #[arg(long = "audit-log-mirror", value_name = "PATH", conflicts_with = "append")]
+    audit_log_mirrors: Vec<String>,
+
+    /// How many of --audit-log and its mirrors must take each record
+    #[arg(long, value_enum, default_value_t = audit_crypto::Quorum::Any, requires = "audit_log_mirrors")]
+    audit_log_quorum: audit_crypto::Quorum,
+
+    /// Continue the hash chain of an existing --audit-log instead of truncating
+    /// it; refused unless the existing log verifies with the signing key
+    #[arg(long, requires = "signing_key", conflicts_with_all = ["encrypt_recipient_pubkey_b64_path", "encrypt_recipient_pubkey_b64_env", "encrypt_recipient"])]
+    append: bool,
+
+    /// Delete the segments an earlier run rotated --audit-log and its mirrors
+    /// into; without it a run that would replace the log refuses to start
+    #[arg(long, conflicts_with = "append")]
+    overwrite: bool,
+
+    /// Close the audit log with a checkpoint and continue in `<name>.<n>.jsonl`
+    /// once it reaches this many bytes
+    #[arg(long, requires = "signing_key", conflicts_with = "append", value_parser = clap::value_parser!(u64).range(1..))]
+    rotate_max_bytes: Option<u64>,
+
+    /// Rotate the audit log once it has been open this many hours (checked as events are written)
+    #[arg(long, requires = "signing_key", conflicts_with = "append", value_parser = clap::value_parser!(u64).range(1..))]
+    rotate_max_hours: Option<u64>,
+
+    /// Checkpoint signing key: a file path, `cmd:<program> [args]` (base64 on stdout),
+    /// `env:<VAR>` or `-` (the first line of stdin)
+    #[arg(long, group = "signing_key")]
     signing_key_b64_path: Option<String>,
 
-    #[arg(long)]
+    /// Read the checkpoint signing key from this environment variable
+    #[arg(long, value_name = "VAR", group = "signing_key")]
+    signing_key_b64_env: Option<String>,
+
+    /// Recipient public key for encryption (same forms as the signing key)
+    #[arg(long, group = "encrypt_key")]
     encrypt_recipient_pubkey_b64_path: Option<String>,
 
+    /// Read the recipient public key from this environment variable
+    #[arg(long, value_name = "VAR", group = "encrypt_key")]
+    encrypt_recipient_pubkey_b64_env: Option<String>,
+
+    /// Refuse to start unless the recipient public key has this key id
+    /// (`sentinel key-id` prints it)
+    #[arg(long, value_name = "HEX", requires = "encrypt_key")]
+    expect_recipient_key_id: Option<String>,
+
+    /// Also wrap every data key for this escrow public key (X25519, same forms
+    /// as the signing key) so the log stays recoverable with `sentinel reveal-dek`
+    #[arg(long, requires = "encrypt_key")]
+    escrow_pubkey_path: Option<String>,
+
+    /// `envelope` seals each record for the recipient public key; `age` writes
+    /// the whole log as an age stream to the --encrypt-recipient recipients
+    #[arg(long, value_enum, default_value_t = audit_crypto::EncryptFormat::Envelope)]
+    encrypt_format: audit_crypto::EncryptFormat,
+
+    /// age recipient (`age1...`) for --encrypt-format age (repeatable)
+    #[arg(long, value_name = "AGE_RECIPIENT", conflicts_with = "encrypt_key")]
+    encrypt_recipient: Vec<String>,
+
+    /// Switch an encrypted log to a fresh data key after this many records
+    #[arg(long, requires = "encrypt_key", value_parser = clap::value_parser!(u64).range(1..))]
+    rotate_dek_max_records: Option<u64>,
+
+    /// Switch an encrypted log to a fresh data key once the current one is this
+    /// many minutes old (checked as events are written)
+    #[arg(long, requires = "encrypt_key", value_parser = clap::value_parser!(u64).range(1..))]
+    rotate_dek_max_minutes: Option<u64>,
+
     #[arg(long, default_value_t = 1000)]
     checkpoint_every: u64,
 
+    /// Replace payloads larger than this many bytes in the audit log with a stub
+    /// holding their blake3 hash and size
+    #[arg(long)]
+    max_payload_bytes: Option<usize>,
+
+    /// Keep the full payloads replaced under --max-payload-bytes in this directory, by hash
+    #[arg(long, requires = "max_payload_bytes")]
+    blob_dir: Option<String>,
+
+    /// Also sign every event record, so events after the last checkpoint are signed too (slower)
+    #[arg(long, requires = "signing_key")]
+    sign_every_event: bool,
+
+    /// When to force audit records to disk: never, interval:<ms>, every-checkpoint or every-event
+    #[arg(long, default_value = "never", value_parser = audit_crypto::FsyncPolicy::parse)]
+    fsync: audit_crypto::FsyncPolicy,
+
+    /// Compress audit records: a plaintext log becomes a zstd stream, an encrypted
+    /// log compresses each record before encrypting it
+    #[arg(long, value_enum, default_value_t = audit_crypto::Compression::None, conflicts_with = "append")]
+    compress: audit_crypto::Compression,
+
+    /// Have each checkpoint timestamped by this RFC 3161 time-stamping authority;
+    /// proofs are appended as the tokens arrive
+    #[arg(long, value_name = "URL", requires = "signing_key")]
+    timestamp_url: Option<String>,
+
+    /// Push every checkpoint to this append-only witness service (http:// or https://)
+    #[arg(long, value_name = "URL", requires = "signing_key")]
+    witness_url: Option<String>,
+
+    /// Bearer token for --witness-url (or set SENTINEL_WITNESS_TOKEN)
+    #[arg(long, requires = "witness_url")]
+    witness_token: Option<String>,
+
+    /// Address of the dashboard and API (repeatable; port 0 picks a free port)
     #[arg(long, default_value = "127.0.0.1:3000")]
-    ws_bind: String,
+    ws_bind: Vec<String>,
+
+    /// Token for the dashboard and API: `viewer:<token>`, `admin:<token>`, or a
+    /// bare token without a ':', which is an admin's (repeatable; default $SENTINEL_WS_TOKEN)
+    #[arg(long)]
+    ws_token: Vec<String>,
+
+    /// File of tokens, one `viewer:<token>` or `admin:<token>` per line; also
+    /// `env:VAR` or `cmd:program`
+    #[arg(long)]
+    ws_tokens_file: Option<String>,
+
+    /// Serve on a --ws-bind beyond loopback without --ws-token
+    #[arg(long)]
+    allow_unauthenticated_remote: bool,
+
+    /// Let browsers on this origin (e.g. `https://ui.example.com`, or `*` for
+    /// any) call `/api/*` and `/ws` (repeatable); no CORS headers by default
+    #[arg(long = "cors-allow-origin", value_name = "ORIGIN")]
+    cors_allow_origins: Vec<String>,
+
+    /// Number of tool/method names given their own metrics series
+    #[arg(long, default_value_t = 50)]
+    metrics_top_k: usize,
+
+    /// Respawn the child when it exits with a failure status (same run, same audit log)
+    #[arg(long)]
+    restart_on_exit: bool,
+
+    /// Reconnect when a --connect server drops the connection (same run, same audit log)
+    #[arg(long, requires = "connect")]
+    reconnect: bool,
+
+    /// Give up after this many restarts or reconnects
+    #[arg(long, default_value_t = 5)]
+    max_restarts: u32,
+
+    /// Delay before the first restart or reconnect, doubled for each further one (capped at 30s)
+    #[arg(long, default_value_t = 500)]
+    restart_backoff_ms: u64,
+
+    /// On shutdown, how long the child may take to exit after SIGTERM before it is killed
+    #[arg(long, default_value_t = 3000)]
+    child_shutdown_timeout_ms: u64,
+
+    /// Set a variable in the child's environment (repeatable; only the name is audited)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = child_env::parse_var, conflicts_with_all = ["connect", "http_upstream"])]
+    child_env: Vec<(String, String)>,
+
+    /// Load child environment variables from a KEY=VALUE file (--child-env wins on conflicts)
+    #[arg(long, conflicts_with_all = ["connect", "http_upstream"])]
+    child_env_file: Option<PathBuf>,
+
+    /// Start the child with only the --child-env/--child-env-file variables
+    #[arg(long, conflicts_with_all = ["connect", "http_upstream"])]
+    child_clear_env: bool,
+
+    /// Working directory of the child (Sentinel's own by default)
+    #[arg(long, conflicts_with_all = ["connect", "http_upstream"])]
+    child_cwd: Option<PathBuf>,
+
+    /// How MCP messages are delimited on the child's stdin/stdout
+    #[arg(long, value_enum, default_value_t = framing::Framing::Auto)]
+    framing: framing::Framing,
+
+    /// Refuse to forward any single message larger than this many bytes
+    #[arg(long)]
+    max_message_bytes: Option<usize>,
+
+    /// Hold at most this many bytes of one message or stderr line; longer ones are
+    /// streamed through and audited as a truncated copy with their size and digest
+    #[arg(long, default_value_t = 4 * 1024 * 1024, value_parser = clap::value_parser!(u64).range(1024..))]
+    max_line_bytes: u64,
+
+    /// Refuse to forward messages that break a JSON-RPC rule; the sender gets an error instead
+    #[arg(long)]
+    strict_protocol: bool,
+
+    /// Skip a JSON-RPC rule when validating traffic (repeatable)
+    #[arg(long = "disable-protocol-rule", value_enum)]
+    disabled_protocol_rules: Vec<protocol::Rule>,
+
+    /// Pace child -> client traffic to this many bytes per second (messages are delayed, never dropped)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_inbound_bytes_per_sec: Option<u64>,
+
+    /// Pace client -> child traffic to this many bytes per second
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_outbound_bytes_per_sec: Option<u64>,
+
+    /// Tag inbound payloads that look like prompt injection (advisory; content is never changed)
+    #[arg(long)]
+    detect_prompt_injection: bool,
 
+    /// Extra detection rules: JSON `{"rules": [{"id": "...", "pattern": "<regex>"}]}` (enables detection)
     #[arg(long)]
-    ws_token: Option<String>,
+    detection_rules: Option<String>,
+
+    /// How much of a line that isn't JSON-RPC to keep in its `sentinel/unparsed` event
+    #[arg(long, value_enum, default_value_t = parser::UnparsedCapture::Truncated)]
+    log_unparsed: parser::UnparsedCapture,
+
+    /// Longest unparsed line kept whole under `--log-unparsed truncated`
+    #[arg(long, default_value_t = 4096)]
+    unparsed_max_bytes: usize,
+
+    /// Join JSON messages a peer writes over several lines (pretty-printed) into
+    /// one event, up to this many bytes each (0 disables)
+    #[arg(long, default_value_t = 0)]
+    max_reassembly_bytes: usize,
+
+    /// Which copies of each event PII redaction applies to: the audit log and the
+    /// dashboard, or only the dashboard stream and history (the log keeps the raw traffic)
+    #[arg(long, value_enum, default_value_t = redaction::RedactScope::AuditAndStream)]
+    redact_scope: redaction::RedactScope,
+
+    /// TOML file of redaction rules: detectors to turn on or off, custom
+    /// patterns, and payload paths always masked or left alone
+    #[arg(long)]
+    redaction_config: Option<String>,
+
+    /// Record every tapped message, unredacted, to this file for `sentinel rederive`
+    #[arg(long)]
+    raw_capture: Option<String>,
+
+    /// Maximum number of events buffered for live WebSocket subscribers
+    #[arg(long, default_value_t = 1000)]
+    broadcast_capacity: usize,
+
+    /// Maximum bytes buffered for live WebSocket subscribers; slow clients skip ahead beyond this
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    broadcast_max_bytes: usize,
+
+    /// Maximum bytes of event history kept in memory for dashboard replay
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    history_max_bytes: usize,
+
+    /// Directory for a temporary file holding history evicted from memory, so
+    /// the dashboard can still page back to the start of the run
+    #[arg(long)]
+    history_spill_dir: Option<String>,
+
+    /// Maximum size of the history spill file; the oldest spilled events go first
+    #[arg(long, default_value_t = 1024 * 1024 * 1024)]
+    history_spill_max_bytes: u64,
+
+    /// Recipient private key (same forms as the signing key) for reading history
+    /// that left memory back from the encrypted audit log, and for `/api/audit/verify`
+    #[arg(long, requires = "encrypt_key")]
+    history_recipient_privkey_path: Option<String>,
+
+    /// Milliseconds between watermark frames on WebSocket streams (0 disables)
+    #[arg(long, default_value_t = 1000)]
+    watermark_interval_ms: u64,
+
+    /// What to do when the audit pipeline can't keep up with traffic
+    #[arg(long, value_enum, default_value_t = tap::TapOverflow::Block)]
+    tap_overflow: tap::TapOverflow,
+
+    /// Most bytes of client messages held while forwarding is paused from the
+    /// dashboard; the client is not read from beyond this until forwarding resumes
+    #[arg(long, default_value_t = 16 * 1024 * 1024)]
+    max_held_bytes: usize,
+
+    /// Seconds between `sentinel/stats` throughput events in the audit log (0 disables)
+    #[arg(long, default_value_t = 60)]
+    throughput_interval_secs: u64,
+
+    /// Log requests unanswered for this many seconds as orphaned and stop tracking them (0 disables)
+    #[arg(long, default_value_t = 300)]
+    span_timeout_secs: u64,
+
+    /// Milliseconds between stats frames (tap drop counters) on WebSocket streams (0 disables)
+    #[arg(long, default_value_t = 5000)]
+    stats_interval_ms: u64,
+
+    /// Most messages queued for one WebSocket client; a client that falls this
+    /// far behind live traffic is disconnected
+    #[arg(long, default_value_t = 1024, value_parser = clap::value_parser!(u64).range(1..))]
+    ws_client_queue: u64,
+
+    /// Milliseconds between pings to WebSocket clients; a client that has not
+    /// answered one by the next is disconnected (0 disables)
+    #[arg(long, default_value_t = 30000)]
+    ws_ping_interval_ms: u64,
 }
 
 #[derive(Args)]
@@ -71,23 +461,453 @@ struct VerifyArgs {
     #[arg(long)]
     log: String,
 
+    #[arg(long, required_unless_present = "pubkey_b64_env", conflicts_with = "pubkey_b64_env")]
+    pubkey_b64_path: Option<String>,
+
+    /// Read the public key from this environment variable instead
+    #[arg(long, value_name = "VAR")]
+    pubkey_b64_env: Option<String>,
+
+    /// Recipient private key of an encrypted log (an age identity file for an age log)
+    #[arg(long)]
+    decrypt_recipient_privkey_b64_path: Option<String>,
+
+    /// Also verify the rotated segments that continue --log (`<name>.1.jsonl`, ...)
+    /// and that none is missing
+    #[arg(long)]
+    segments: bool,
+
+    /// Check that timestamp proofs are signed by a TSA chaining to one of these CA certificates (PEM)
+    #[arg(long, value_name = "PEM")]
+    tsa_ca_bundle: Option<String>,
+
+    /// Fail if a checkpoint has no timestamp proof (missing proofs are otherwise a warning)
+    #[arg(long)]
+    require_timestamps: bool,
+
+    /// Check every checkpoint against what this witness acknowledged (see `run --witness-url`)
+    #[arg(long, value_name = "URL")]
+    witness_url: Option<String>,
+
+    /// Bearer token for --witness-url (or set SENTINEL_WITNESS_TOKEN)
+    #[arg(long, requires = "witness_url")]
+    witness_token: Option<String>,
+}
+
+#[derive(Args)]
+struct WatchVerifyArgs {
+    #[arg(long)]
+    log: String,
+
     #[arg(long)]
     pubkey_b64_path: String,
 
+    /// Where to persist the verification cursor between passes and restarts
+    #[arg(long, default_value = "sentinel_watch_state.json")]
+    state: String,
+
+    /// How often to check for new records (e.g. 500ms, 30s, 5m)
+    #[arg(long, default_value = "30s", value_parser = watch::parse_interval)]
+    interval: std::time::Duration,
+
+    #[arg(long)]
+    decrypt_recipient_privkey_b64_path: Option<String>,
+
+    /// Serve GET /healthz on this address
+    #[arg(long)]
+    health_bind: Option<String>,
+
+    /// POST a JSON alert to this http:// URL when a violation is found
+    #[arg(long)]
+    webhook_url: Option<String>,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    #[arg(long, default_value = ".")]
+    dir: String,
+
+    /// Only include events on or after this UTC date (YYYY-MM-DD)
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Only include events before this UTC date (YYYY-MM-DD)
+    #[arg(long)]
+    until: Option<String>,
+
+    /// Comma-separated grouping keys: day, server, run, file
+    #[arg(long, default_value = "day,server")]
+    group_by: String,
+
+    #[arg(long, value_enum, default_value_t = report::ReportFormat::Table)]
+    format: report::ReportFormat,
+
+    /// Recipient key for encrypted logs; without it they are reported as skipped
     #[arg(long)]
     decrypt_recipient_privkey_b64_path: Option<String>,
 }
 
+#[derive(Args)]
+struct RederiveArgs {
+    /// Raw capture written by `run --raw-capture`
+    #[arg(long)]
+    capture: String,
+
+    /// Audit log written by the same run
+    #[arg(long)]
+    reference: String,
+
+    /// Re-sign checkpoints; without it, signatures are taken from the reference
+    #[arg(long)]
+    signing_key_b64_path: Option<String>,
+
+    /// Must match the value the run used
+    #[arg(long, default_value_t = 1000)]
+    checkpoint_every: u64,
+
+    /// Must match the rules the run disabled
+    #[arg(long = "disable-protocol-rule", value_enum)]
+    disabled_protocol_rules: Vec<protocol::Rule>,
+
+    /// Must match the run's detection settings
+    #[arg(long)]
+    detect_prompt_injection: bool,
+
+    #[arg(long)]
+    detection_rules: Option<String>,
+
+    /// Must match the run's unparsed-line settings
+    #[arg(long, value_enum, default_value_t = parser::UnparsedCapture::Truncated)]
+    log_unparsed: parser::UnparsedCapture,
+
+    #[arg(long, default_value_t = 4096)]
+    unparsed_max_bytes: usize,
+
+    /// Must match the run's value
+    #[arg(long, default_value_t = 0)]
+    max_reassembly_bytes: usize,
+
+    /// Must match the run's value
+    #[arg(long)]
+    max_payload_bytes: Option<usize>,
+
+    /// Must match the run's value (it shapes the latency summary)
+    #[arg(long, default_value_t = 50)]
+    metrics_top_k: usize,
+
+    /// Must match the run's redaction rules
+    #[arg(long)]
+    redaction_config: Option<String>,
+}
+
+#[derive(Args)]
+struct AttachArgs {
+    /// Name of the server in the hub's --servers file
+    server: String,
+
+    #[arg(long, default_value = "127.0.0.1:7400")]
+    hub: String,
+
+    /// Must match the hub's --hub-token (or set SENTINEL_HUB_TOKEN)
+    #[arg(long)]
+    hub_token: Option<String>,
+}
+
+#[derive(Args)]
+struct UnredactArgs {
+    /// Audit log written with vault redaction rules (plaintext or envelope-encrypted)
+    #[arg(long)]
+    log: String,
+
+    /// Recipient private key the run vaulted to
+    #[arg(long)]
+    recipient_privkey_b64_path: String,
+
+    /// Where to write the restored copy (must not exist)
+    #[arg(long)]
+    out: String,
+}
+
+#[derive(Args)]
+struct RedactTestArgs {
+    /// Redaction config to test (as `run --redaction-config`)
+    #[arg(long)]
+    config: String,
+
+    /// Plaintext audit log, or JSONL of events
+    #[arg(long)]
+    input: String,
+
+    /// Print a unified diff of each event redaction changes
+    #[arg(long)]
+    show_diff: bool,
+
+    /// Fail if a rule the config asks for never matched
+    #[arg(long)]
+    fail_on_zero_hits: bool,
+}
+
+#[derive(Args)]
+struct MigrateLogArgs {
+    #[arg(long)]
+    log: String,
+
+    /// Where to write the migrated log (must not exist)
+    #[arg(long)]
+    out: String,
+
+    /// Key the original log verifies with
+    #[arg(long)]
+    pubkey_b64_path: String,
+
+    /// Key that signs the migrated log's checkpoints and Migration record
+    #[arg(long)]
+    signing_key_b64_path: String,
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    #[arg(long)]
+    log: String,
+
+    #[arg(long)]
+    decrypt_recipient_privkey_b64_path: Option<String>,
+
+    /// Playback rate: 2.0 replays twice as fast as recorded
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Redaction rules for the replayed copies (as `run --redaction-config`)
+    #[arg(long)]
+    redaction_config: Option<String>,
+
+    /// Address of the dashboard and API (repeatable; port 0 picks a free port)
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    ws_bind: Vec<String>,
+
+    /// Token for the dashboard and API: `viewer:<token>`, `admin:<token>`, or a
+    /// bare token without a ':', which is an admin's (repeatable; default $SENTINEL_WS_TOKEN)
+    #[arg(long)]
+    ws_token: Vec<String>,
+
+    /// File of tokens, one `viewer:<token>` or `admin:<token>` per line; also
+    /// `env:VAR` or `cmd:program`
+    #[arg(long)]
+    ws_tokens_file: Option<String>,
+
+    /// Serve on a --ws-bind beyond loopback without --ws-token
+    #[arg(long)]
+    allow_unauthenticated_remote: bool,
+}
+
+#[derive(Args)]
+struct DecryptArgs {
+    #[arg(long)]
+    log: String,
+
+    /// Recipient private key (an age identity file for an age log)
+    #[arg(long)]
+    recipient_privkey_b64_path: String,
+
+    /// Write the plaintext records here instead of to stdout
+    #[arg(long)]
+    out: Option<String>,
+
+    /// Verify the hash chain and checkpoints of the decrypted records as they are written
+    #[arg(long, requires = "pubkey_b64_path")]
+    verify: bool,
+
+    #[arg(long, requires = "verify")]
+    pubkey_b64_path: Option<String>,
+}
+
+#[derive(Args)]
+struct RevealDekArgs {
+    #[arg(long)]
+    log: String,
+
+    /// Escrow private key (same forms as the signing key)
+    #[arg(long)]
+    escrow_privkey: String,
+
+    /// Confirm that the data keys, which decrypt the whole log, are to be printed
+    #[arg(long = "i-understand-this-exposes-the-key")]
+    i_understand_this_exposes_the_key: bool,
+}
+
+#[derive(Args)]
+struct ProveArgs {
+    #[arg(long)]
+    log: String,
+
+    #[arg(long)]
+    event_id: u64,
+
+    #[arg(long)]
+    decrypt_recipient_privkey_b64_path: Option<String>,
+
+    /// Where to write the proof (stdout by default)
+    #[arg(long)]
+    out: Option<String>,
+}
+
+#[derive(Args)]
+struct VerifyProofArgs {
+    #[arg(long)]
+    proof: String,
+
+    #[arg(long)]
+    pubkey_b64_path: String,
+}
+
+#[derive(Args)]
+struct ExportAttestationArgs {
+    #[arg(long)]
+    log: String,
+
+    #[arg(long)]
+    pubkey_b64_path: String,
+
+    #[arg(long)]
+    decrypt_recipient_privkey_b64_path: Option<String>,
+
+    /// Where to write the bundle (stdout by default)
+    #[arg(long)]
+    out: Option<String>,
+}
+
+#[derive(Args)]
+struct VerifyAttestationArgs {
+    #[arg(long)]
+    attestation: String,
+
+    /// Require the bundle to be for this public key (otherwise compare the
+    /// printed key_id with the one you expect)
+    #[arg(long)]
+    pubkey_b64_path: Option<String>,
+}
+
+#[derive(Args)]
+struct FetchBlobArgs {
+    #[arg(long)]
+    blob_dir: String,
+
+    /// `blake3` of the truncated payload stub
+    #[arg(long)]
+    hash: String,
+
+    /// Where to write the payload (stdout by default)
+    #[arg(long)]
+    out: Option<String>,
+}
+
 #[derive(Args)]
 struct KeygenArgs {
     #[arg(long, default_value = "keys")]
     out_dir: String,
+
+    #[command(flatten)]
+    passphrase: PassphraseArgs,
 }
 
 #[derive(Args)]
 struct RecipientKeygenArgs {
     #[arg(long, default_value = "keys")]
     out_dir: String,
+
+    #[command(flatten)]
+    passphrase: PassphraseArgs,
+}
+
+#[derive(Args)]
+struct KeyIdArgs {
+    /// Public key (same forms as the signing key)
+    #[arg(long)]
+    pubkey: String,
+}
+
+#[derive(Args)]
+struct PassphraseArgs {
+    /// Protect the private key with a passphrase, prompted for on the terminal
+    #[arg(long, conflicts_with = "passphrase_env")]
+    passphrase: bool,
+
+    /// Protect the private key with the passphrase in this environment variable
+    #[arg(long, value_name = "VAR")]
+    passphrase_env: Option<String>,
+}
+
+impl PassphraseArgs {
+    fn source(&self) -> Option<keys::PassphraseSource> {
+        match (&self.passphrase_env, self.passphrase) {
+            (Some(var), _) => Some(keys::PassphraseSource::Env(var.clone())),
+            (None, true) => Some(keys::PassphraseSource::Prompt),
+            (None, false) => None,
+        }
+    }
+}
+
+/// The dashboard and API tokens of `--ws-token` and `--ws-tokens-file`, or else
+/// `SENTINEL_WS_TOKEN` (an admin's).
+fn ws_auth(tokens: &[String], tokens_file: Option<&str>) -> Result<auth::AuthConfig, String> {
+    match std::env::var("SENTINEL_WS_TOKEN") {
+        Ok(token) if tokens.is_empty() && tokens_file.is_none() => auth::AuthConfig::from_args(&[token], None),
+        _ => auth::AuthConfig::from_args(tokens, tokens_file),
+    }
+}
+
+/// The key spec for a `--*-b64-path` / `--*-b64-env` pair (clap keeps them exclusive).
+fn key_spec(path: &Option<String>, env: &Option<String>) -> Option<String> {
+    match (path, env) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(var)) => Some(format!("env:{}", var)),
+        (None, None) => None,
+    }
+}
+
+impl RunArgs {
+    fn signing_key_spec(&self) -> Option<String> {
+        key_spec(&self.signing_key_b64_path, &self.signing_key_b64_env)
+    }
+
+    fn encrypt_key_spec(&self) -> Option<String> {
+        key_spec(&self.encrypt_recipient_pubkey_b64_path, &self.encrypt_recipient_pubkey_b64_env)
+    }
+
+    fn encryption(&self) -> Result<audit_crypto::Encryption, String> {
+        match (self.encrypt_format, self.encrypt_key_spec()) {
+            (audit_crypto::EncryptFormat::Age, _) => audit_crypto::Encryption::age(&self.encrypt_recipient),
+            (audit_crypto::EncryptFormat::Envelope, _) if !self.encrypt_recipient.is_empty() => {
+                Err("--encrypt-recipient takes age recipients; add --encrypt-format age".to_string())
+            }
+            (audit_crypto::EncryptFormat::Envelope, Some(spec)) => Ok(audit_crypto::Encryption::Envelope {
+                recipient: spec,
+                escrow: self.escrow_pubkey_path.clone(),
+            }),
+            (audit_crypto::EncryptFormat::Envelope, None) => Ok(audit_crypto::Encryption::None),
+        }
+    }
+}
+
+impl VerifyArgs {
+    fn pubkey_spec(&self) -> String {
+        key_spec(&self.pubkey_b64_path, &self.pubkey_b64_env).expect("clap requires a public key")
+    }
+}
+
+/// Read the passphrase for a new keystore, if one was asked for.
+fn new_passphrase(args: &PassphraseArgs) -> Result<Option<zeroize::Zeroizing<String>>, String> {
+    args.source().map(|source| source.read_new()).transpose()
+}
+
+#[derive(Args)]
+struct SchemaArgs {
+    #[arg(long, default_value = "schemas")]
+    out: String,
+
+    /// Fail if the committed schema files differ from the current types
+    #[arg(long)]
+    check: bool,
 }
 
 #[tokio::main]
@@ -96,30 +916,53 @@ async fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Run(a) => {
-            if let Err(e) = run(a).await {
+        Commands::Run(a) => match run(a).await {
+            Ok(code) => process::exit(code),
+            Err(e) => {
                 eprintln!("❌ Fatal error: {}", e);
                 process::exit(1);
             }
-        }
+        },
+        Commands::Verify(args) if args.segments => match verify_segments(&args).await {
+            Ok((n, summary)) => {
+                print_verify_warnings(&summary.warnings);
+                println!("✅ OK: {} audit log segment(s) verified successfully", n);
+                print_provenance(&summary.provenance);
+                print_gaps(&summary);
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("❌ VERIFY FAILED: {}", e);
+                process::exit(2);
+            }
+        },
         Commands::Verify(args) => {
-            let log_path = match audit_crypto::maybe_decrypt_to_temp_plaintext(
-                &args.log,
-                args.decrypt_recipient_privkey_b64_path.as_deref(),
-            ) {
-                Ok(p) => p,
+            let log = match audit_crypto::open_plaintext(&args.log, args.decrypt_recipient_privkey_b64_path.as_deref()) {
+                Ok(log) => log,
                 Err(e) => {
                     eprintln!("❌ VERIFY FAILED (decryption): {}", e);
                     process::exit(2);
                 }
             };
 
-            match audit::verify_audit_log_file(
-                log_path.to_string_lossy().as_ref(),
-                &args.pubkey_b64_path,
-            ) {
-                Ok(()) => {
+            // Offsets of decrypted records refer to the encrypted file; they are checked while decrypting.
+            let result = verify_options(&args).and_then(|options| {
+                let options = audit::VerifyOptions {
+                    check_offsets: !log.offsets_checked,
+                    ..options
+                };
+                audit::verify_log(log.reader, &args.pubkey_spec(), &options)
+            });
+            let result = match result {
+                Ok(summary) => check_witness(&args, &[PathBuf::from(&args.log)]).await.map(|()| summary),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(summary) => {
+                    print_verify_warnings(&summary.warnings);
                     println!("✅ OK: audit log verified successfully");
+                    print_provenance(&summary.provenance);
+                    print_gaps(&summary);
                     process::exit(0);
                 }
                 Err(e) => {
@@ -128,8 +971,323 @@ async fn main() {
                 }
             }
         }
+        Commands::WatchVerify(args) => {
+            let opts = watch::WatchOptions {
+                log: args.log.into(),
+                pubkey_path: args.pubkey_b64_path,
+                state_path: args.state.into(),
+                interval: args.interval,
+                decrypt_privkey_path: args.decrypt_recipient_privkey_b64_path,
+                health_bind: args.health_bind,
+                webhook_url: args.webhook_url,
+            };
+            if let Err(e) = watch::watch_verify(opts).await {
+                eprintln!("❌ VERIFY FAILED: {}", e);
+                process::exit(2);
+            }
+        }
+        Commands::Report(args) => {
+            let group_by = match report::parse_group_by(&args.group_by) {
+                Ok(g) => g,
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    process::exit(2);
+                }
+            };
+            let opts = report::ReportOptions {
+                dir: args.dir.into(),
+                since: args.since,
+                until: args.until,
+                group_by,
+                format: args.format,
+                decrypt_privkey_path: args.decrypt_recipient_privkey_b64_path,
+            };
+            match report::run_report(&opts) {
+                Ok(out) => print!("{}", out),
+                Err(e) => {
+                    eprintln!("❌ Report failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Commands::Rederive(args) => {
+            let detector = match detector(args.detect_prompt_injection, args.detection_rules.as_deref()) {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    process::exit(2);
+                }
+            };
+            let redaction_policy = match redaction_policy(args.redaction_config.as_deref()) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    process::exit(2);
+                }
+            };
+            let opts = rederive::RederiveOptions {
+                capture: args.capture.into(),
+                reference: args.reference.into(),
+                signing_key_path: args.signing_key_b64_path,
+                checkpoint_every: args.checkpoint_every,
+                protocol_rules: protocol_rules(&args.disabled_protocol_rules),
+                detector,
+                unparsed: args.log_unparsed,
+                unparsed_max_bytes: args.unparsed_max_bytes,
+                max_reassembly_bytes: args.max_reassembly_bytes,
+                metrics_top_k: args.metrics_top_k,
+                max_payload_bytes: args.max_payload_bytes,
+                redact: redaction::enabled_from_env(),
+                redaction: Arc::new(redaction_policy),
+            };
+            match rederive::rederive(&opts).await {
+                Ok(outcome) => match outcome.divergence {
+                    None => {
+                        println!("✅ OK: {} records re-derived identically", outcome.records);
+                        process::exit(0);
+                    }
+                    Some(diff) => {
+                        eprintln!("❌ DIVERGED: {}", diff);
+                        process::exit(1);
+                    }
+                },
+                Err(e) => {
+                    eprintln!("❌ Rederive failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        Commands::Replay(args) => {
+            if let Err(e) = replay_log(args).await {
+                eprintln!("❌ Replay failed: {}", e);
+                process::exit(2);
+            }
+            process::exit(0);
+        }
+        Commands::Decrypt(args) => match decrypt_log(&args) {
+            Ok(records) if args.verify => {
+                eprintln!("✅ OK: {} records decrypted and verified", records);
+                process::exit(0);
+            }
+            Ok(records) => {
+                eprintln!("✅ {} records decrypted", records);
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("❌ DECRYPT FAILED: {}", e);
+                process::exit(2);
+            }
+        },
+        Commands::RevealDek(args) => {
+            if !args.i_understand_this_exposes_the_key {
+                eprintln!("❌ reveal-dek prints keys that decrypt the whole log; pass --i-understand-this-exposes-the-key");
+                process::exit(1);
+            }
+            eprintln!("⚠️  Revealing the data keys of {}: anyone who sees them can read the log", args.log);
+            match audit_crypto::reveal_deks(&args.log, &args.escrow_privkey) {
+                Ok(deks) => {
+                    for (generation, dek) in &deks {
+                        println!("key_generation {}: {}", generation, dek.as_str());
+                    }
+                    eprintln!("✅ {} data key(s) revealed", deks.len());
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("❌ Reveal failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        Commands::Attach(args) => {
+            let token = args.hub_token.or_else(|| std::env::var("SENTINEL_HUB_TOKEN").ok());
+            if let Err(e) = hub::attach(&args.server, &args.hub, token).await {
+                eprintln!("❌ Attach failed: {}", e);
+                process::exit(1);
+            }
+            process::exit(0);
+        }
+        Commands::Prove(args) => {
+            let proof = audit_crypto::open_plaintext(&args.log, args.decrypt_recipient_privkey_b64_path.as_deref())
+            .and_then(|log| audit::make_inclusion_proof(log.reader, args.event_id))
+            .and_then(|proof| serde_json::to_string_pretty(&proof).map_err(|e| format!("serialize proof: {}", e)));
+            let written = match (proof, &args.out) {
+                (Ok(json), Some(out)) => std::fs::write(out, format!("{}\n", json)).map_err(|e| format!("write {}: {}", out, e)),
+                (Ok(json), None) => {
+                    println!("{}", json);
+                    Ok(())
+                }
+                (Err(e), _) => Err(e),
+            };
+            if let Err(e) = written {
+                eprintln!("❌ Prove failed: {}", e);
+                process::exit(2);
+            }
+        }
+        Commands::VerifyProof(args) => {
+            let result = std::fs::read_to_string(&args.proof)
+                .map_err(|e| format!("read {}: {}", args.proof, e))
+                .and_then(|text| serde_json::from_str(&text).map_err(|e| format!("parse proof: {}", e)))
+                .and_then(|proof| audit::verify_inclusion_proof(&proof, &args.pubkey_b64_path));
+            match result {
+                Ok(event_id) => {
+                    println!("✅ OK: event {} is included under a valid checkpoint signature", event_id);
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("❌ PROOF INVALID: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        Commands::ExportAttestation(args) => {
+            let exported = attestation::export(
+                &args.log,
+                &args.pubkey_b64_path,
+                args.decrypt_recipient_privkey_b64_path.as_deref(),
+            )
+            .and_then(|att| {
+                let json = serde_json::to_string_pretty(&att).map_err(|e| format!("serialize attestation: {}", e))?;
+                match &args.out {
+                    Some(out) => std::fs::write(out, format!("{}\n", json)).map_err(|e| format!("write {}: {}", out, e))?,
+                    None => println!("{}", json),
+                }
+                Ok(att)
+            });
+            match exported {
+                Ok(att) => {
+                    eprintln!(
+                        "✅ Attestation of {}: {} events, {} checkpoints, key_id {}",
+                        att.log_file,
+                        att.events,
+                        att.checkpoints.len(),
+                        att.key_id
+                    );
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("❌ Export failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        Commands::VerifyAttestation(args) => {
+            let result = std::fs::read_to_string(&args.attestation)
+                .map_err(|e| format!("read {}: {}", args.attestation, e))
+                .and_then(|text| serde_json::from_str(&text).map_err(|e| format!("parse attestation: {}", e)))
+                .and_then(|att: attestation::Attestation| {
+                    attestation::verify(&att, args.pubkey_b64_path.as_deref()).map(|check| (att, check))
+                });
+            match result {
+                Ok((att, check)) => {
+                    print_verify_warnings(&check.warnings);
+                    println!(
+                        "✅ OK: {} checkpoint signature(s) valid for key_id {}, covering events through {}",
+                        check.checkpoints, att.key_id, check.signed_through
+                    );
+                    println!("   Log {} (blake3 {}), runs {}", att.log_file, att.log_blake3, att.run_ids.join(", "));
+                    print_provenance(&att.provenance);
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("❌ ATTESTATION INVALID: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        Commands::FetchBlob(args) => {
+            let written = blobs::fetch(&args.blob_dir, &args.hash).and_then(|bytes| match &args.out {
+                Some(out) => std::fs::write(out, bytes).map_err(|e| format!("write {}: {}", out, e)),
+                None => {
+                    use std::io::Write;
+                    std::io::stdout().write_all(&bytes).map_err(|e| format!("write stdout: {}", e))
+                }
+            });
+            if let Err(e) = written {
+                eprintln!("❌ Fetch failed: {}", e);
+                process::exit(2);
+            }
+        }
+        Commands::MigrateLog(args) => {
+            let opts = migrate::MigrateOptions {
+                log: args.log.into(),
+                out: args.out.clone().into(),
+                pubkey_path: args.pubkey_b64_path,
+                signing_key_path: args.signing_key_b64_path,
+            };
+            match migrate::migrate_log(&opts) {
+                Ok(outcome) => {
+                    println!(
+                        "✅ OK: {} events migrated from record version(s) {:?} to v{} in {}",
+                        outcome.events,
+                        outcome.source_versions,
+                        audit::SCHEMA_VERSION,
+                        args.out
+                    );
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("❌ Migration failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        Commands::RedactTest(args) => {
+            let opts = redact_test::RedactTestOptions {
+                config: args.config.into(),
+                input: args.input,
+                show_diff: args.show_diff,
+            };
+            let outcome = match redact_test::redact_test(&opts, &mut std::io::stdout().lock()) {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    eprintln!("❌ Redaction test failed: {}", e);
+                    process::exit(2);
+                }
+            };
+            let width = outcome.hits.iter().map(|(rule, _)| rule.len()).max().unwrap_or(0).max(4);
+            println!("{:<width$}  {:>6}", "Rule", "Hits");
+            for (rule, hits) in &outcome.hits {
+                println!("{:<width$}  {:>6}", rule, hits);
+            }
+            println!(
+                "{} of {} events redacted, {} replacements",
+                outcome.redacted,
+                outcome.events,
+                outcome.hits.iter().map(|(_, n)| n).sum::<u64>()
+            );
+            if outcome.truncated > 0 {
+                println!("{} events scanned only up to max_scan_bytes", outcome.truncated);
+            }
+            if args.fail_on_zero_hits && !outcome.never_matched.is_empty() {
+                eprintln!("❌ Never matched: {}", outcome.never_matched.join(", "));
+                process::exit(1);
+            }
+            process::exit(0);
+        }
+        Commands::Unredact(args) => {
+            let opts = unredact::UnredactOptions {
+                log: args.log,
+                out: args.out.clone().into(),
+                recipient_privkey_path: args.recipient_privkey_b64_path,
+            };
+            match unredact::unredact(&opts) {
+                Ok(outcome) => {
+                    println!(
+                        "✅ {} vaulted values restored in {} ({} records); the copy does not verify",
+                        outcome.restored, args.out, outcome.records
+                    );
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("❌ Unredact failed: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
         Commands::Keygen(args) => {
-            if let Err(e) = keygen::generate_keypair(&args.out_dir) {
+            let result = new_passphrase(&args.passphrase)
+                .and_then(|passphrase| keygen::generate_keypair(&args.out_dir, passphrase.as_deref().map(String::as_str)));
+            if let Err(e) = result {
                 eprintln!("❌ Key generation failed: {}", e);
                 std::process::exit(1);
             }
@@ -137,31 +1295,282 @@ async fn main() {
             std::process::exit(0);
         }
         Commands::RecipientKeygen(args) => {
-            if let Err(e) = audit_crypto::keygen_recipient(&args.out_dir) {
+            let result = new_passphrase(&args.passphrase).and_then(|passphrase| {
+                audit_crypto::keygen_recipient(&args.out_dir, passphrase.as_deref().map(String::as_str))
+            });
+            if let Err(e) = result {
                 eprintln!("❌ Recipient key generation failed: {}", e);
                 std::process::exit(1);
             }
             println!("✅ Recipient keypair generated successfully");
             std::process::exit(0);
         }
+        Commands::KeyId(args) => {
+            let pk = match keys::load_key32(&args.pubkey, "public key") {
+                Ok(pk) => pk,
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    std::process::exit(1);
+                }
+            };
+            // Both kinds are 32 bytes, so print the id each would have; only
+            // the signing one can be ruled out from the bytes alone.
+            match ed25519_dalek::VerifyingKey::from_bytes(&pk) {
+                Ok(vk) => println!("Signing key id (Ed25519):   {}", audit::key_id_from_pubkey(&vk)),
+                Err(_) => println!("Signing key id (Ed25519):   - (not an Ed25519 public key)"),
+            }
+            println!("Recipient key id (X25519):  {}", audit_crypto::recipient_key_id(&pk));
+            if let Err(e) = audit_crypto::check_recipient_pubkey(&pk) {
+                println!("⚠️  As a recipient key: {}", e);
+            }
+            std::process::exit(0);
+        }
+        Commands::Schema(args) => {
+            if args.check {
+                match schema::check_schemas(&args.out) {
+                    Ok(mismatched) if mismatched.is_empty() => {
+                        println!("✅ Schemas in {} are up to date", args.out);
+                        process::exit(0);
+                    }
+                    Ok(mismatched) => {
+                        for name in mismatched {
+                            eprintln!("❌ Schema out of date: {}", name);
+                        }
+                        eprintln!(
+                            "   Bump SCHEMA_VERSION, then run 'sentinel schema --out {}' and commit the new files",
+                            args.out
+                        );
+                        process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("❌ Schema check failed: {}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+
+            match schema::write_schemas(&args.out) {
+                Ok(written) => {
+                    for name in written {
+                        println!("  {}/{}", args.out, name);
+                    }
+                    println!("✅ Schemas written successfully");
+                }
+                Err(e) => {
+                    eprintln!("❌ Schema generation failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/// Timestamp options of `verify`.
+fn verify_options(args: &VerifyArgs) -> Result<audit::VerifyOptions, String> {
+    let tsa_roots = match &args.tsa_ca_bundle {
+        Some(path) => Some(Arc::new(timestamp::TrustRoots::load(path)?)),
+        None => None,
+    };
+    Ok(audit::VerifyOptions {
+        require_timestamps: args.require_timestamps,
+        tsa_roots,
+        ..audit::VerifyOptions::default()
+    })
+}
+
+/// With `--witness-url`, cross-check the checkpoints of the verified logs (read
+/// again, and decrypted again if encrypted).
+async fn check_witness(args: &VerifyArgs, logs: &[PathBuf]) -> Result<(), String> {
+    let Some(url) = &args.witness_url else {
+        return Ok(());
+    };
+    let token = args.witness_token.clone().or_else(|| std::env::var("SENTINEL_WITNESS_TOKEN").ok());
+    let client = witness::WitnessClient::new(url, token)?;
+    let mut checkpoints = Vec::new();
+    for path in logs {
+        let log = audit_crypto::open_plaintext(&path.to_string_lossy(), args.decrypt_recipient_privkey_b64_path.as_deref())?;
+        checkpoints.extend(witness::log_checkpoints(log.reader)?);
+    }
+    let n = witness::cross_check(&client, &checkpoints)
+        .await
+        .map_err(|e| format!("witness: {}", e))?;
+    eprintln!("🧾 {} checkpoint(s) match the witness at {}", n, url);
+    Ok(())
+}
+
+fn print_verify_warnings(warnings: &[String]) {
+    for w in warnings {
+        eprintln!("⚠️  {}", w);
+    }
+}
+
+/// Gaps in the event ids that GapMarker records account for.
+fn print_gaps(summary: &audit::VerifySummary) {
+    if summary.gaps > 0 {
+        println!(
+            "   {} gap(s) in event ids: {} message(s) the audit pipeline dropped (--tap-overflow drop)",
+            summary.gaps, summary.missed_events
+        );
+    }
+}
+
+/// What the signed checkpoints say wrote the log.
+fn print_provenance(provenance: &[audit::Provenance]) {
+    for p in provenance {
+        println!(
+            "   Written by sentinel {} on host {:?} (command digest {})",
+            p.sentinel_version, p.hostname, p.command_digest
+        );
+    }
+}
+
+/// Verify `--log` and every rotated segment after it; returns the number of files
+/// and what verification found.
+/// Stream the plaintext records of `args.log` to `--out` or stdout, verifying
+/// each one first with `--verify`. Returns the number of records.
+fn decrypt_log(args: &DecryptArgs) -> Result<u64, String> {
+    use std::io::Write;
+
+    let mut verifier = match (&args.pubkey_b64_path, args.verify) {
+        (Some(pubkey), true) => Some(audit::ChainVerifier::new(audit::load_verify_key_b64(pubkey)?)),
+        _ => None,
+    };
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path).map_err(|e| format!("create {}: {}", path, e))?,
+        )),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout().lock())),
+    };
+
+    let records = if audit_crypto::is_age_log(&args.log)? {
+        decrypt_age_log(args, verifier.as_mut(), &mut out)?
+    } else {
+        audit_crypto::decrypt_records(&args.log, &args.recipient_privkey_b64_path, |line_no, record| {
+            if let Some(verifier) = verifier.as_mut() {
+                verifier.verify_line(line_no, None, record)?;
+            }
+            writeln!(out, "{}", record).map_err(|e| format!("write: {}", e))
+        })?
+    };
+    out.flush().map_err(|e| format!("write: {}", e))?;
+    if let Some(verifier) = &verifier {
+        verifier.finish()?;
+    }
+    Ok(records)
+}
+
+/// An age log decrypts to a whole plaintext log (header included) that
+/// `verify` accepts as it is; its offsets are checked like a plaintext log's.
+fn decrypt_age_log(
+    args: &DecryptArgs,
+    mut verifier: Option<&mut audit::ChainVerifier>,
+    out: &mut dyn std::io::Write,
+) -> Result<u64, String> {
+    use std::io::BufRead;
+
+    let log = audit_crypto::open_plaintext(&args.log, Some(&args.recipient_privkey_b64_path))?;
+    let mut reader = std::io::BufReader::new(log.reader);
+    let mut buf = Vec::new();
+    let (mut offset, mut records) = (0u64, 0u64);
+    for line_no in 1.. {
+        buf.clear();
+        let n = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| format!("line {}: read error: {}", line_no, e))?;
+        if n == 0 {
+            break;
+        }
+        let line = std::str::from_utf8(&buf).map_err(|_| format!("line {}: not valid UTF-8", line_no))?;
+        if let Some(verifier) = verifier.as_deref_mut() {
+            verifier.verify_line(line_no, Some(offset), line)?;
+        }
+        out.write_all(&buf).map_err(|e| format!("write: {}", e))?;
+        offset += n as u64;
+        let line = audit::normalize_line(line);
+        let is_header = line_no == 1 && audit::parse_file_header(line)?.is_some();
+        if !line.is_empty() && !is_header {
+            records += 1;
+        }
+    }
+    Ok(records)
+}
+
+async fn replay_log(args: ReplayArgs) -> Result<(), String> {
+    let opts = replay::ReplayOptions {
+        log: args.log,
+        decrypt_privkey_path: args.decrypt_recipient_privkey_b64_path,
+        speed: args.speed,
+        redaction: match redaction::enabled_from_env() {
+            true => Some(redaction_policy(args.redaction_config.as_deref())?),
+            false => None,
+        },
+    };
+    let events = replay::read_events(&opts)?;
+    let auth = ws_auth(&args.ws_token, args.ws_tokens_file.as_deref())?;
+    let ws_bind = server::bind_addrs(&args.ws_bind, auth.is_enabled(), args.allow_unauthenticated_remote)?;
+    let state = replay::server_state(auth, HISTORY_MAX_EVENTS);
+
+    let server_state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = start_server(server_state, &ws_bind).await {
+            eprintln!("❌ WebSocket server error: {}", e);
+        }
+    });
+
+    eprintln!("⏯️  Replaying {} events from {} at {}x (nothing is written)", events.len(), opts.log, opts.speed);
+    tokio::select! {
+        replayed = replay::replay(events, &state, opts.speed, opts.redaction.as_ref()) => {
+            eprintln!("✅ Replayed {} events; still serving them, press Ctrl+C to stop", replayed?);
+            let _ = signal::ctrl_c().await;
+        }
+        _ = signal::ctrl_c() => eprintln!("⏹️  Replay interrupted"),
+    }
+    state.shutdown.cancel();
+    Ok(())
+}
+
+async fn verify_segments(args: &VerifyArgs) -> Result<(usize, audit::VerifySummary), String> {
+    let paths = audit::segment_paths(Path::new(&args.log))?;
+    let mut segments = Vec::new();
+    for path in &paths {
+        let plaintext = audit_crypto::open_plaintext(
+            &path.to_string_lossy(),
+            args.decrypt_recipient_privkey_b64_path.as_deref(),
+        )
+        .map_err(|e| format!("{} (decryption): {}", path.display(), e))?;
+        segments.push((path.clone(), plaintext));
+    }
+    let vk = audit::load_verify_key_b64(&args.pubkey_spec())?;
+    let summary = audit::verify_segments(segments, vk, &verify_options(args)?)?;
+    check_witness(args, &paths).await?;
+    Ok((paths.len(), summary))
+}
+
+/// What this run wraps, as signed into its checkpoints: the command line, or the
+/// upstream it connects to instead.
+fn wrapped_command(args: &RunArgs) -> Vec<String> {
+    match (&args.connect, &args.http_upstream, &args.servers) {
+        (Some(url), _, _) => vec!["--connect".to_string(), url.clone()],
+        (None, Some(url), _) => vec!["--http-upstream".to_string(), url.clone()],
+        (None, None, Some(path)) => vec!["--servers".to_string(), path.display().to_string()],
+        (None, None, None) => args.command.clone(),
     }
 }
 
 /// Read the first checkpoint from an existing audit log to extract key_id
 fn read_first_checkpoint(log_path: &Path) -> Result<audit::AuditRecord, Box<dyn std::error::Error>> {
-    use std::fs::File;
     use std::io::{BufRead, BufReader};
     
-    let file = File::open(log_path)?;
-    let reader = BufReader::new(file);
+    let reader = BufReader::new(audit::open_log(log_path)?);
     
     for line in reader.lines() {
         let line = line?;
-        if line.trim().is_empty() {
+        let line = audit::normalize_line(&line);
+        if line.is_empty() {
             continue;
         }
         
-        let record: audit::AuditRecord = serde_json::from_str(&line)?;
+        let record: audit::AuditRecord = serde_json::from_str(line)?;
         
         if matches!(record, audit::AuditRecord::Checkpoint { .. }) {
             return Ok(record);
@@ -171,18 +1580,97 @@ fn read_first_checkpoint(log_path: &Path) -> Result<audit::AuditRecord, Box<dyn
     Err("No checkpoint found in existing audit log".into())
 }
 
-async fn run(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
-    let ws_token = args.ws_token
-        .or_else(|| std::env::var("SENTINEL_WS_TOKEN").ok());
+/// Print the recipient key id for the banner, warn about keys nobody can decrypt
+/// for, and refuse to start if it is not the expected one. Returns the key id.
+fn check_recipient_key(
+    spec: &str,
+    expected: Option<&str>,
+    signing_key: Option<&dyn audit::CheckpointSigner>,
+) -> Result<String, String> {
+    let pk = keys::load_key32(spec, "recipient public key")?;
+    let key_id = audit_crypto::recipient_key_id(&pk);
+    eprintln!("🔐 Audit log encrypted to recipient key id {}", key_id);
+    if let Err(e) = audit_crypto::check_recipient_pubkey(&pk) {
+        eprintln!("⚠️  Recipient public key looks wrong: {}", e);
+    }
+    if signing_key.is_some_and(|sk| sk.verifying_key().to_bytes() == pk) {
+        eprintln!("⚠️  The recipient public key is the signing public key; nobody holds a decryption key for it");
+        eprintln!("   Run 'sentinel recipient-keygen' to generate a recipient keypair");
+    }
+    match expected {
+        Some(expected) if !expected.trim().eq_ignore_ascii_case(&key_id) => Err(format!(
+            "Refusing to start: recipient public key has key id {}, expected {}",
+            key_id,
+            expected.trim()
+        )),
+        _ => Ok(key_id),
+    }
+}
+
+/// Why the audit log can't be read back while it is written (for history and
+/// `/api/audit/verify`), if it can't.
+fn unreadable_log(
+    encryption: &audit_crypto::Encryption,
+    compression: audit_crypto::Compression,
+    recipient_privkey: Option<&str>,
+) -> Option<String> {
+    match encryption {
+        audit_crypto::Encryption::Age(_) => Some("the audit log is one age stream".to_string()),
+        audit_crypto::Encryption::None if compression == audit_crypto::Compression::Zstd => {
+            Some("the audit log is one zstd stream".to_string())
+        }
+        audit_crypto::Encryption::Envelope { .. } if recipient_privkey.is_none() => Some(
+            "the audit log is encrypted; start with --history-recipient-privkey-path to read it back".to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// Load the recipient private key for reading history back from the encrypted
+/// audit log, refusing one that does not match the recipient public key.
+fn history_recipient_key(spec: &str, recipient_pub_spec: &str) -> Result<zeroize::Zeroizing<[u8; 32]>, String> {
+    let sk = zeroize::Zeroizing::new(keys::load_key32(spec, "recipient private key")?);
+    let pk = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(*sk));
+    let expected = keys::load_key32(recipient_pub_spec, "recipient public key")?;
+    if pk.as_bytes() != &expected {
+        return Err(format!(
+            "Refusing to start: --history-recipient-privkey-path has key id {}, the audit log is encrypted to {}",
+            audit_crypto::recipient_key_id(pk.as_bytes()),
+            audit_crypto::recipient_key_id(&expected)
+        ));
+    }
+    Ok(sk)
+}
+
+/// Print the escrow key id for the banner, warning about a key nobody can decrypt for.
+fn check_escrow_key(spec: &str) -> Result<(), String> {
+    let pk = keys::load_key32(spec, "escrow public key")?;
+    eprintln!("🔐 Data keys also wrapped for escrow key id {}", audit_crypto::recipient_key_id(&pk));
+    if let Err(e) = audit_crypto::check_recipient_pubkey(&pk) {
+        eprintln!("⚠️  Escrow public key looks wrong: {}", e);
+    }
+    Ok(())
+}
+
+/// Run the proxy until shutdown; returns the exit code for the process.
+async fn run(args: RunArgs) -> Result<i32, Box<dyn std::error::Error>> {
+    // Signed into the checkpoints
+    let provenance = audit::Provenance::current(&wrapped_command(&args));
+    let auth = ws_auth(&args.ws_token, args.ws_tokens_file.as_deref())?;
+    let cors = server::cors_layer(&args.cors_allow_origins)?;
+    let ws_bind = server::bind_addrs(&args.ws_bind, auth.is_enabled(), args.allow_unauthenticated_remote)?;
 
     let run_id = Uuid::new_v4().to_string();
 
     eprintln!("🚀 Starting Sentinel");
     eprintln!("   Run ID: {}", run_id);
     eprintln!("   Audit log: {}", args.audit_log);
+    for mirror in &args.audit_log_mirrors {
+        eprintln!("   Audit log mirror: {}", mirror);
+    }
 
-    let signing_key = if let Some(ref key_path) = args.signing_key_b64_path {
-        Some(audit::load_signing_key_b64(key_path)?)
+    let signing_key: Option<Arc<dyn audit::CheckpointSigner>> = if let Some(key_spec) = args.signing_key_spec() {
+        Some(Arc::new(audit::load_signing_key_b64(&key_spec)?))
     } else {
         eprintln!("⚠️  No signing key provided - audit log will NOT be tamper-evident");
         eprintln!("   Use --signing-key-b64-path to enable signed checkpoints");
@@ -190,9 +1678,42 @@ async fn run(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
+    let encryption = args.encryption()?;
+    // What `/api/config` reports the log is encrypted to
+    let encrypted_to = match &encryption {
+        audit_crypto::Encryption::None => "plaintext".to_string(),
+        audit_crypto::Encryption::Envelope { recipient, escrow } => {
+            let key_id = check_recipient_key(recipient, args.expect_recipient_key_id.as_deref(), signing_key.as_deref())?;
+            if let Some(escrow) = escrow {
+                check_escrow_key(escrow)?;
+            }
+            key_id
+        }
+        audit_crypto::Encryption::Age(recipients) => {
+            format!("age: {}", recipients.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", "))
+        }
+    };
+
     let audit_path = Path::new(&args.audit_log);
-    if let Some(ref sk) = signing_key {
-        if audit_path.exists() && audit_path.metadata()?.len() > 0 {
+    let existing_log = audit_path.exists() && audit_path.metadata()?.len() > 0;
+    // Where this run picks up the chain with --append
+    let mut resume_from = None;
+    if let Some(sk) = signing_key.as_ref().filter(|_| args.append && existing_log) {
+        if audit::segment_path(audit_path, 1).exists() {
+            return Err(format!(
+                "Refusing to append to {}: it was rotated (continued in {})",
+                args.audit_log,
+                audit::segment_path(audit_path, 1).display()
+            )
+            .into());
+        }
+        eprintln!("📋 Verifying existing audit log before appending...");
+        let tip = audit::chain_tip(audit_path, sk.verifying_key())
+            .map_err(|e| format!("Refusing to append to {}: {}", args.audit_log, e))?;
+        eprintln!("   ✓ Continuing after event_id {} of run {}", tip.last_event_id, tip.run_id);
+        resume_from = Some(tip);
+    } else if let Some(ref sk) = signing_key {
+        if existing_log {
             eprintln!("📋 Existing audit log found, validating signing key...");
             
             match read_first_checkpoint(audit_path) {
@@ -218,71 +1739,282 @@ async fn run(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
                 }
                 Err(e) => {
                     eprintln!("   ⚠️  Warning: Could not read existing log: {}", e);
-                    eprintln!("   Proceeding anyway (will truncate log)");
+                    eprintln!("   Proceeding anyway (the log will be truncated; use --append to keep it)");
                 }
             }
         }
     }
 
-    let enable_redaction = std::env::var("SENTINEL_REDACT_PII")
-        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-        .unwrap_or(true);
-    
-    if enable_redaction {
+    // --audit-log first, then the mirrors, in the order of the sink stats
+    let audit_outputs: Vec<String> =
+        std::iter::once(args.audit_log.clone()).chain(args.audit_log_mirrors.iter().cloned()).collect();
+    let sink_stats = Arc::new(audit_crypto::SinkStats::new(&audit_outputs));
+    if !args.append {
+        // This run replaces the log, so segments rotated from it by an earlier run
+        // would no longer continue it. They are signed evidence: only deleted when asked.
+        for output in &audit_outputs {
+            let stale = audit::segment_paths(Path::new(output))
+                .map_err(|e| format!("{}: {}; move its rotated segments away first", output, e))?
+                .split_off(1);
+            if stale.is_empty() {
+                continue;
+            }
+            if !args.overwrite {
+                return Err(format!(
+                    "{} has {} rotated segment(s) from an earlier run ({}, ...): move them away \
+                     or pass --overwrite to delete them",
+                    output,
+                    stale.len(),
+                    stale[0].display()
+                )
+                .into());
+            }
+            for stale in &stale {
+                eprintln!("🗑️  Removing audit log segment {} (--overwrite)", stale.display());
+                std::fs::remove_file(stale)?;
+            }
+        }
+    }
+    let rotation = audit_rotation(args.rotate_max_bytes, args.rotate_max_hours);
+    if rotation.is_some() {
+        // Segment headers name the previous segment by its file name, which is the same in every copy.
+        for mirror in args.audit_log_mirrors.iter().filter(|m| Path::new(m).file_name() != audit_path.file_name()) {
+            eprintln!(
+                "⚠️  Mirror {} is not named like {}; rename its copy before verifying it with --segments",
+                mirror, args.audit_log
+            );
+        }
+    }
+
+    let hub = match &args.servers {
+        Some(path) => {
+            let token = args.hub_token.clone().or_else(|| std::env::var("SENTINEL_HUB_TOKEN").ok());
+            if token.is_none() {
+                eprintln!("⚠️  Hub has no token: any local process can attach to the wrapped servers");
+                eprintln!("   Use --hub-token (or SENTINEL_HUB_TOKEN) to require one");
+            }
+            Some(hub::Hub {
+                servers: hub::load_servers(path)?,
+                listen: args.hub_listen.clone(),
+                token,
+            })
+        }
+        None => None,
+    };
+
+    let enable_redaction = redaction::enabled_from_env();
+    let redact_scope = enable_redaction.then_some(args.redact_scope);
+    let mut redaction_policy = redaction_policy(args.redaction_config.as_deref())?;
+    if redaction_policy.vaults() && redact_scope.is_some() {
+        // Vaulted values are encrypted to the log's own recipient
+        let audit_crypto::Encryption::Envelope { recipient, .. } = &encryption else {
+            return Err("vault redaction rules encrypt to the run's recipient key: add --encrypt-recipient-pubkey-b64-path".into());
+        };
+        redaction_policy = redaction_policy.with_vault_key(keys::load_key32(recipient, "recipient public key")?);
+    }
+    let redaction_rules = match redact_scope {
+        Some(_) => [redaction_policy.rule_names(), redaction_policy.scan_limits()].concat(),
+        None => Vec::new(),
+    };
+    let redaction_policy = Arc::new(redaction_policy);
+    // Redacted before hashing, or only on the copy shown on the dashboard
+    let redact_audit =
+        (redact_scope == Some(redaction::RedactScope::AuditAndStream)).then(|| redaction_policy.clone());
+    let redact_stream = (redact_scope == Some(redaction::RedactScope::StreamOnly)).then(|| redaction_policy.clone());
+
+    if redact_audit.is_some() {
         eprintln!("🔒 PII redaction enabled");
         eprintln!("   Set SENTINEL_REDACT_PII=false to disable");
+    } else if redact_stream.is_some() {
+        eprintln!("🔒 PII redaction enabled for the dashboard only; the audit log keeps the raw traffic");
+        eprintln!("   Set SENTINEL_REDACT_PII=false to disable");
     } else {
         eprintln!("⚠️  PII redaction DISABLED");
     }
+    match &args.redaction_config {
+        Some(path) if redact_scope.is_some() => eprintln!("   Rules from {}: {}", path, redaction_rules.join(", ")),
+        Some(path) => eprintln!("⚠️  --redaction-config {} is not applied: SENTINEL_REDACT_PII disables redaction", path),
+        None => {}
+    }
 
     let (raw_tx, raw_rx) = mpsc::channel::<events::RawTap>(1000);
+    let tap_drops = Arc::new(tap::TapDrops::default());
+    let raw_tx = tap::Tap::new(raw_tx, args.tap_overflow, tap_drops.clone());
+    if args.tap_overflow == tap::TapOverflow::Drop {
+        eprintln!("⚠️  Tap overflow policy: drop (traffic the audit pipeline can't keep up with is not audited)");
+    }
     let (tap_tx, tap_rx) = mpsc::channel::<events::TapEvent>(1000);
     let (log_tx, mut log_rx) = mpsc::channel::<events::McpLog>(1000);
 
-    let log_tx_clone = log_tx.clone();
+    let shutdown = Arc::new(ShutdownController::new());
+    let forward_gate = control::ForwardGate::new(args.max_held_bytes);
+    let throughput = Arc::new(throughput::Throughput::default());
+    // What `/api/stats` reports: one entry per wrapped server
+    let throughputs = match &hub {
+        Some(hub) => hub.servers.iter().map(|s| (Some(s.name.clone()), s.throughput.clone())).collect(),
+        None => vec![(None, throughput.clone())],
+    };
+
+    let feed = Arc::new(feed::Feed::new(args.broadcast_capacity, args.broadcast_max_bytes));
+    let feed_for_audit = feed.clone();
+
+    let metrics = Arc::new(metrics::Metrics::new(args.metrics_top_k));
+
+    let mut history = history::EventStore::new(HISTORY_MAX_EVENTS, args.history_max_bytes);
+    if let Some(dir) = &args.history_spill_dir {
+        history = history.with_spill(Path::new(dir), args.history_spill_max_bytes)?;
+        eprintln!("🗄️  History spill: {} (up to {} bytes)", dir, args.history_spill_max_bytes);
+    }
+    let recipient_sk = match (&encryption, &args.history_recipient_privkey_path) {
+        (audit_crypto::Encryption::Envelope { recipient, .. }, Some(spec)) => Some(history_recipient_key(spec, recipient)?),
+        _ => None,
+    };
+    let (audit_control, mut audit_requests) = audit_control::AuditControl::new(PathBuf::from(&args.audit_log));
+    let mut audit_control = audit_control
+        .with_verifying_key(signing_key.as_ref().map(|sk| sk.verifying_key()))
+        .with_recipient_key(recipient_sk.clone());
+    // What leaves the history is read back from the audit log, when it can be.
+    let log_index = match unreadable_log(&encryption, args.compress, args.history_recipient_privkey_path.as_deref()) {
+        Some(reason) => {
+            eprintln!("ℹ️  History that leaves memory can't be read back: {}", reason);
+            audit_control = audit_control.with_unreadable_log(reason.clone());
+            history = history.with_unreadable_log(reason);
+            None
+        }
+        None => {
+            let index = Arc::new(log_index::LogIndex::new(PathBuf::from(&args.audit_log), recipient_sk, redact_stream.clone()));
+            history = history.with_log_index(index.clone());
+            Some(index)
+        }
+    };
+
+    let session = Arc::new(Session::new(
+        Uuid::new_v4().to_string(),
+        Uuid::new_v4().to_string(),
+    ));
 
-    let (ws_tx, _) = broadcast::channel::<events::McpLog>(1000);
-    let ws_tx_for_audit = ws_tx.clone();
+    // One session per wrapped server, all in the run's trace.
+    let server_sessions: Vec<Arc<Session>> = hub
+        .iter()
+        .flat_map(|h| &h.servers)
+        .map(|s| {
+            Arc::new(Session::new(Uuid::new_v4().to_string(), session.trace_id.clone()).with_server_name(&s.name))
+        })
+        .collect();
 
+    // The hub's servers come and go with their clients; a lone child must keep running.
+    let health = health::Health::for_run(hub.is_none() && args.connect.is_none() && args.http_upstream.is_none());
+    let config = server::RuntimeConfig {
+        run_id: run_id.clone(),
+        session_id: session.session_id.clone(),
+        audit_log: args.audit_log.clone(),
+        redaction_enabled: redact_scope.is_some(),
+        redaction_scope: redaction::describe(redact_scope),
+        redaction_rules: redaction_rules.clone(),
+        signing_key_id: signing_key
+            .as_ref()
+            .map_or_else(|| "unsigned".to_string(), |sk| audit::key_id_from_pubkey(&sk.verifying_key())),
+        encryption: encrypted_to,
+        checkpoint_every: args.checkpoint_every,
+    };
     let state = Arc::new(ServerState {
-        tx: ws_tx.clone(),
-        auth_token: ws_token.clone(),
-        history: RwLock::new(VecDeque::new()),
+        feed,
+        auth,
+        history,
+        metrics: metrics.clone(),
+        watermark_interval: (args.watermark_interval_ms > 0)
+            .then(|| std::time::Duration::from_millis(args.watermark_interval_ms)),
+        tap_overflow: args.tap_overflow,
+        tap_drops: tap_drops.clone(),
+        audit_sinks: sink_stats.clone(),
+        stats_interval: (args.stats_interval_ms > 0).then(|| std::time::Duration::from_millis(args.stats_interval_ms)),
+        forward_gate: forward_gate.clone(),
+        tap: raw_tx.downgrade(),
+        throughput: throughputs,
+        sessions: if server_sessions.is_empty() { vec![session.clone()] } else { server_sessions.clone() },
+        shutdown: shutdown.token(Phase::StopServer),
+        health: health.clone(),
+        ws_client_queue: args.ws_client_queue as usize,
+        ws_ping_interval: (args.ws_ping_interval_ms > 0)
+            .then(|| std::time::Duration::from_millis(args.ws_ping_interval_ms)),
+        ws_clients: Default::default(),
+        cors,
+        audit: audit_control,
+        listening: Default::default(),
+        config: Some(config),
+        redactions: redact_scope.map(|_| redaction::RedactionTotals::default()),
     });
 
-    // Assign event IDs
-    tokio::spawn(async move {
-        let mut id = 1u64;
+    let mut capture = match &args.raw_capture {
+        Some(path) => {
+            eprintln!("🎞️  Raw capture: {} (unredacted traffic)", path);
+            Some(capture::CaptureWriter::create(path).await?)
+        }
+        None => None,
+    };
+
+    // Pending spans are checked for expiry a few times per timeout.
+    let span_timeout = std::time::Duration::from_secs(args.span_timeout_secs);
+    let mut span_sweep = (args.span_timeout_secs > 0).then(|| {
+        let period = (span_timeout / 4).max(std::time::Duration::from_secs(1));
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+    });
+
+    // Assign tap sequence numbers (canonical event IDs are assigned by the audit writer)
+    let tap_drops_for_sequencer = tap_drops.clone();
+    shutdown.spawn(Phase::Drain, "tap sequencer", async move {
+        let mut seq = 1u64;
         let mut rx = raw_rx;
 
-        while let Some(r) = rx.recv().await {
-            if tap_tx
-                .send(events::TapEvent {
-                    event_id: id,
-                    direction: r.direction,
-                    bytes: r.bytes,
-                    observed_ts_ms: r.observed_ts_ms,
-                })
-                .await
-                .is_err()
-            {
+        loop {
+            let r = tokio::select! {
+                r = rx.recv() => match r {
+                    Some(r) => r,
+                    None => break,
+                },
+                // Sequenced like a tap so the sweep lands at the same point on replay
+                _ = server::tick(&mut span_sweep) => events::RawTap::sentinel(
+                    parser::SPAN_SWEEP_METHOD,
+                    serde_json::json!({ "timeout_ms": span_timeout.as_millis() as u64 }),
+                ),
+            };
+            if let Some(gap) = tap_gap(&mut seq, r.dropped_before) {
+                if !sequence(&mut seq, gap, &mut capture, &tap_tx).await {
+                    break;
+                }
+            }
+            if !sequence(&mut seq, r, &mut capture, &tap_tx).await {
                 break;
             }
-            id += 1;
         }
-    });
+        // Taps dropped after the last one that got through
+        if let Some(gap) = tap_gap(&mut seq, tap_drops_for_sequencer.take_pending()) {
+            sequence(&mut seq, gap, &mut capture, &tap_tx).await;
+        }
 
-    let session = Arc::new(Session {
-        session_id: Uuid::new_v4().to_string(),
-        trace_id: Uuid::new_v4().to_string(),
+        if let Some(writer) = capture.as_mut() {
+            if let Err(e) = writer.flush().await {
+                eprintln!("❌ {}", e);
+            }
+        }
     });
 
     let run_id_clone = run_id.clone();
+    let session_for_audit = session.clone();
+
+    let protocol_rules = protocol_rules(&args.disabled_protocol_rules);
+    let detector = detector(args.detect_prompt_injection, args.detection_rules.as_deref())?;
+    let (log_unparsed, unparsed_max_bytes) = (args.log_unparsed, args.unparsed_max_bytes);
+    let max_reassembly_bytes = args.max_reassembly_bytes;
 
     // Parser
-    tokio::spawn(async move {
+    shutdown.spawn(Phase::Drain, "parser", async move {
         if let Err(e) =
-            LogParser::new(run_id_clone, log_tx_clone, session)
+            LogParser::new(run_id_clone, log_tx, session, metrics, protocol_rules)
+                .with_detector(detector)
+                .with_unparsed(log_unparsed, unparsed_max_bytes)
+                .with_reassembly(max_reassembly_bytes)
+                .with_server_sessions(server_sessions)
                 .process_stream(tap_rx)
                 .await
         {
@@ -291,195 +2023,796 @@ async fn run(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let audit_log_path = args.audit_log.clone();
-    let encrypt_path = args.encrypt_recipient_pubkey_b64_path.clone();
+    let audit_log_quorum = args.audit_log_quorum;
     let checkpoint_every = args.checkpoint_every;
+    let max_payload_bytes = args.max_payload_bytes;
+    let blob_store = match (&args.blob_dir, max_payload_bytes) {
+        (Some(dir), Some(max)) => {
+            eprintln!("🗃️  Payloads over {} bytes are moved out of the audit log into {}", max, dir);
+            Some(blobs::BlobStore::create(dir)?)
+        }
+        (None, Some(max)) => {
+            eprintln!("✂️  Payloads over {} bytes are replaced by a stub (use --blob-dir to keep them)", max);
+            None
+        }
+        _ => None,
+    };
+    let event_signer = signing_key.clone().filter(|_| args.sign_every_event);
+    if event_signer.is_some() {
+        eprintln!("✍️  Signing every event record");
+    }
+    let fsync = args.fsync;
+    let compression = args.compress;
+    let dek_rotation = audit_crypto::DekRotation {
+        max_records: args.rotate_dek_max_records,
+        max_age: args.rotate_dek_max_minutes.map(|m| std::time::Duration::from_secs(m * 60)),
+    };
+    // Timestamp proofs and witness receipts come back here, to be written as they arrive.
+    let (receipt_tx, mut receipt_rx) = mpsc::unbounded_channel();
+    let mut anchors = CheckpointAnchors::default();
+    if let Some(url) = &args.timestamp_url {
+        anchors.stamper = Some(timestamp::Stamper::new(url, receipt_tx.clone())?);
+        eprintln!("⏱️  Timestamping checkpoints with {}", url);
+    }
+    if let Some(url) = &args.witness_url {
+        let token = args.witness_token.clone().or_else(|| std::env::var("SENTINEL_WITNESS_TOKEN").ok());
+        let client = witness::WitnessClient::new(url, token)?;
+        anchors.witness = Some(witness::Witness::spawn(client, receipt_tx.clone()));
+        eprintln!("🧾 Sending checkpoints to the witness at {}", url);
+    }
+    drop(receipt_tx);
     let state_for_audit = state.clone();
-
-    let (audit_shutdown_tx, mut audit_shutdown_rx) = mpsc::channel::<()>(1);
+    let health_for_audit = health.clone();
+    let log_index_for_audit = log_index.clone();
+    let shutdown_for_audit = shutdown.clone();
+    let finalize = shutdown.token(Phase::FinalizeAudit);
 
     // Audit + history + broadcast
-    let audit_handle = tokio::spawn(async move {
-        let mut file = match tokio::fs::OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(&audit_log_path)
-            .await
-        {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("❌ Failed to open audit log: {}", e);
-                return;
+    shutdown.spawn(Phase::FinalizeAudit, "audit writer", async move {
+        let mut files = Vec::new();
+        for path in &audit_outputs {
+            match tokio::fs::OpenOptions::new()
+                .create(true)
+                .truncate(resume_from.is_none())
+                .append(resume_from.is_some())
+                .write(true)
+                .open(path)
+                .await
+            {
+                Ok(f) => files.push((PathBuf::from(path), f)),
+                Err(e) => {
+                    eprintln!("❌ Failed to open audit log {}: {}", path, e);
+                    return;
+                }
             }
+        }
+        // Only --audit-log itself can be appended to.
+        let appended_len = match (&resume_from, files.first()) {
+            (Some(_), Some((_, file))) => Some(file.metadata().await.map(|m| m.len())),
+            _ => None,
         };
+        let outputs = audit_crypto::Outputs::new(files, audit_log_quorum, sink_stats.clone());
 
-        let mut sink = match audit_crypto::AuditSink::new(
-            &mut file,
-            &run_id,
-            encrypt_path.as_deref(),
-        )
-        .await
-        {
+        let sink = match (&resume_from, appended_len) {
+            // Records carry their offset, so the file must end where it was verified.
+            (Some(tip), Some(Ok(len))) if len == tip.end_offset => Ok(audit_crypto::AuditSink::append(outputs, tip.end_offset)),
+            (Some(_), Some(Ok(_))) => Err("audit log changed after it was verified".to_string()),
+            (Some(_), Some(Err(e))) => Err(e.to_string()),
+            _ => audit_crypto::AuditSink::new(outputs, &run_id, &encryption, compression)
+                .await
+                .map(|sink| sink.with_dek_rotation(dek_rotation)),
+        };
+        let mut sink = match sink {
             Ok(s) => s,
             Err(e) => {
                 eprintln!("❌ Failed to initialize audit sink: {}", e);
                 return;
             }
         };
+        health_for_audit.audit_running(true);
+
+        // An age log has no data key of its own, but its history spill is kept encrypted too.
+        match (sink.data_key(), &encryption) {
+            (Some(dek), _) => state_for_audit.history.encrypt_spill(dek.clone()),
+            (None, audit_crypto::Encryption::Age(_)) => {
+                state_for_audit.history.encrypt_spill(audit_crypto::DataKey::random())
+            }
+            _ => {}
+        }
+
+        let mut chain = match &resume_from {
+            Some(tip) => audit::AuditChain::resume(redact_audit, tip),
+            None => audit::AuditChain::new(redact_audit),
+        }
+        .with_max_payload_bytes(max_payload_bytes)
+        .with_provenance(Some(provenance));
+
+        // Bind this run to the chain it continues before its first event.
+        if let (Some(tip), Some(sk)) = (&resume_from, signing_key.as_ref()) {
+            let boundary = chain
+                .run_boundary(sk.as_ref(), &tip.run_id, &run_id, events::current_timestamp_ms())
+                .and_then(|rb| serde_json::to_string(&rb).map_err(|e| format!("serialize run boundary: {}", e)));
+            let written = match boundary {
+                Ok(json) => sink.write_record("RunBoundary", &json).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                eprintln!("❌ Failed to write run boundary: {}", e);
+                return;
+            }
+        }
+
+        // Sign the tip the run starts from (zero for a new log), so a run without
+        // events still leaves a verifiable log behind.
+        if let Some(sk) = signing_key.as_ref() {
+            let start = chain.checkpoint(sk.as_ref(), &run_id, events::current_timestamp_ms()).and_then(|cp| {
+                let json = serde_json::to_string(&cp).map_err(|e| format!("serialize checkpoint: {}", e))?;
+                Ok((cp, json))
+            });
+            let written = match start {
+                Ok((cp, json)) => sink.write_record("Checkpoint", &json).await.map(|()| cp),
+                Err(e) => Err(e),
+            };
+            match written {
+                Ok(cp) => anchors.submit(&cp),
+                Err(e) => {
+                    eprintln!("❌ Failed to write start checkpoint: {}", e);
+                    return;
+                }
+            }
+        }
+
+        // The header, KeyEnvelope, run boundary and start checkpoint are durable before any event
+        // that depends on them.
+        let mut durability = audit_crypto::Durability::new(fsync);
+        if let Err(e) = durability.barrier(&mut sink).await {
+            eprintln!("❌ Failed to sync audit log: {}", e);
+        }
+        let mut fsync_tick = durability.ticker();
 
-        let mut prev_hash = [0u8; 32];
-        let mut since_last_checkpoint = 0;
-        let mut last_event_id = 0u64;
+        let mut closing = false;
+        let mut shutdown_recorded = false;
+        // Rotated segment currently written (0 is --audit-log itself), its file and when it was opened
+        let mut segment = 0u32;
+        let mut segment_file = PathBuf::from(&audit_log_path);
+        let mut segment_opened = std::time::Instant::now();
 
         loop {
+            // A write that reached only some outputs and could not be taken back out of them
+            if let Some(why) = sink.outputs().broken() {
+                shutdown_for_audit.trigger(ShutdownReason::AuditFailed(why.to_string()));
+                break;
+            }
+            // Reply to a checkpoint asked for through the API, once it is written
+            let mut forced = None;
             let maybe_log = tokio::select! {
                 log = log_rx.recv() => log,
-                _ = audit_shutdown_rx.recv() => {
-                    eprintln!("🔒 Audit loop received shutdown signal");
-                    None
+                _ = finalize.cancelled(), if !closing => {
+                    // Stop waiting for producers but keep what is already queued.
+                    eprintln!("🔒 Audit loop finalizing");
+                    log_rx.close();
+                    closing = true;
+                    continue;
+                }
+                _ = server::tick(&mut fsync_tick), if durability.unsynced() => {
+                    if let Err(e) = durability.sync(&mut sink).await {
+                        eprintln!("❌ Failed to sync audit log: {}", e);
+                    }
+                    continue;
+                }
+                Some(receipt) = receipt_rx.recv() => {
+                    write_receipt(&mut sink, &receipt).await;
+                    durability.wrote();
+                    continue;
                 }
+                Some(request) = audit_requests.recv() => match request {
+                    audit_control::AuditRequest::Flush(reply) => {
+                        let flushed = match sink.flush().await {
+                            Ok(()) => tokio::fs::metadata(&segment_file)
+                                .await
+                                .map(|m| audit_control::FlushedLog { segment, path: segment_file.clone(), len: m.len() })
+                                .map_err(|e| format!("audit log {}: {}", segment_file.display(), e)),
+                            Err(e) => Err(e),
+                        };
+                        let _ = reply.send(flushed);
+                        continue;
+                    }
+                    // Recorded in the chain, right before the checkpoint that covers it.
+                    audit_control::AuditRequest::Checkpoint(reply) => {
+                        eprintln!("📍 Checkpoint requested through the API");
+                        forced = Some(reply);
+                        Some(checkpoint_requested_event(&run_id, &session_for_audit))
+                    }
+                },
             };
 
             let mut log = match maybe_log {
                 Some(l) => l,
+                // The shutdown event is the last one in the chain, before the final checkpoint.
+                None if !shutdown_recorded => {
+                    shutdown_recorded = true;
+                    shutdown_event(&shutdown_for_audit, &run_id, &session_for_audit, state_for_audit.redactions.as_ref())
+                }
                 None => break,
             };
 
-            if enable_redaction {
-                redaction::redact_log(&mut log);
+            let envelope_offset = sink.position();
+            match sink.rotate_dek_if_due().await {
+                Ok(Some(generation)) => {
+                    eprintln!("🔑 Audit log data key rotated (key generation {})", generation);
+                    if let Some(index) = &log_index_for_audit {
+                        index.key_rotated(envelope_offset);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("❌ Failed to rotate the audit log data key: {}; still using the current one", e),
             }
 
-            let (rec, hash) = match audit::make_event_record(&prev_hash, log.clone()) {
+            let offset = sink.position();
+            // Taps dropped before they were sequenced leave a GapMarker, not an event.
+            let gap = audit::is_gap_notice(&log);
+            let (rec_json, hash, blob, redactions) = match prepare_event(&chain, &mut log, offset, event_signer.as_ref()).await {
                 Ok(r) => r,
                 Err(e) => {
-                    eprintln!("❌ Failed to create event record: {}", e);
+                    eprintln!("❌ {}", e);
                     continue;
                 }
             };
 
-            let rec_json = match serde_json::to_string(&rec) {
-                Ok(j) => j,
-                Err(e) => {
-                    eprintln!("❌ Failed to serialize event record: {}", e);
-                    continue;
+            // Stored before the record that refers to it
+            if let (Some(store), Some(bytes)) = (blob_store.as_ref(), blob) {
+                if let Err(e) = store.put(&bytes).await {
+                    eprintln!("❌ Failed to store payload of event {}: {}", log.event_id, e);
                 }
-            };
+            }
 
-            if let Err(e) = sink.write_record("Event", &rec_json).await {
+            let written = sink.write_record(if gap { "GapMarker" } else { "Event" }, &rec_json).await;
+            health_for_audit.audit_written(&written, Some(log.observed_ts_ms));
+            if let Err(e) = written {
                 eprintln!("❌ Failed to write event record: {}", e);
                 continue;
             }
 
-            prev_hash = hash;
-            last_event_id = log.event_id;
-            since_last_checkpoint += 1;
-
-            if signing_key.is_some() && since_last_checkpoint >= checkpoint_every {
-                let cp = audit::make_checkpoint_record(
-                    signing_key.as_ref().unwrap(),
-                    &run_id,
-                    events::current_timestamp_ms(),
-                    last_event_id,
-                    &prev_hash,
-                );
+            chain.commit(log.event_id, hash);
+            if let Some(totals) = &state_for_audit.redactions {
+                totals.record(&redactions);
+            }
+            if let Some(index) = log_index_for_audit.as_ref().filter(|_| !gap) {
+                index.event_written(log.event_id, offset);
+            }
 
-                let cp_json = match serde_json::to_string(&cp) {
-                    Ok(j) => j,
-                    Err(e) => {
-                        eprintln!("❌ Failed to serialize checkpoint: {}", e);
-                        since_last_checkpoint = 0;
-                        continue;
+            if let Some(sk) = signing_key.as_ref().filter(|_| forced.is_some() || chain.pending() >= checkpoint_every) {
+                let created = chain
+                    .checkpoint(sk.as_ref(), &run_id, events::current_timestamp_ms())
+                    .and_then(|cp| {
+                        let json = serde_json::to_string(&cp).map_err(|e| format!("serialize checkpoint: {}", e))?;
+                        Ok((cp, json))
+                    });
+                // The event is already in the chain: a failed checkpoint must not
+                // keep it from being synced, rotated past and published.
+                let written = match created {
+                    Ok((cp, cp_json)) => {
+                        let written = sink.write_record("Checkpoint", &cp_json).await;
+                        health_for_audit.audit_written(&written, None);
+                        written
+                            .map(|()| cp)
+                            .map_err(|e| format!("write checkpoint: {}", e))
                     }
+                    Err(e) => Err(format!("create checkpoint: {}", e)),
                 };
-
-                if let Err(e) = sink.write_record("Checkpoint", &cp_json).await {
-                    eprintln!("❌ Failed to write checkpoint: {}", e);
+                match written {
+                    Ok(cp) => {
+                        anchors.submit(&cp);
+                        if let Err(e) = durability.checkpointed(&mut sink).await {
+                            eprintln!("❌ Failed to sync audit log: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("❌ Failed to {}", e);
+                        if let Some(reply) = forced.take() {
+                            let _ = reply.send(Err(e));
+                        }
+                    }
                 }
+            }
 
-                since_last_checkpoint = 0;
+            // Whatever the fsync policy, so a copy taken now ends on this checkpoint.
+            if let Some(reply) = forced.take() {
+                let synced = durability.sync(&mut sink).await.map(|()| audit_control::ForcedCheckpoint {
+                    last_event_id: chain.last_event_id(),
+                    last_entry_hash_b64: chain.last_entry_hash_b64(),
+                    signed: signing_key.is_some(),
+                });
+                let _ = reply.send(synced);
             }
 
-            {
-                let mut hist = state_for_audit.history.write().await;
-                hist.push_back(log.clone());
-                if hist.len() > 10_000 {
-                    hist.pop_front();
+            if let (Some(rotation), Some(sk)) = (rotation.as_ref(), signing_key.as_ref()) {
+                if rotation.due(sink.position(), segment_opened.elapsed()) {
+                    let path = audit::segment_path(Path::new(&audit_log_path), segment + 1);
+                    let rotated = rotate_audit_log(
+                        &mut sink,
+                        &mut chain,
+                        (sk.as_ref(), &anchors),
+                        &run_id,
+                        (&segment_file, segment + 1),
+                        (&encryption, compression, dek_rotation),
+                        &mut durability,
+                    )
+                    .await;
+                    match rotated {
+                        Ok(next) => {
+                            eprintln!("🔁 Audit log rotated to {}", path.display());
+                            if let Some(index) = &log_index_for_audit {
+                                index.segment_started(path.clone());
+                            }
+                            sink = next;
+                            segment += 1;
+                            segment_file = path;
+                            segment_opened = std::time::Instant::now();
+                        }
+                        Err(e) => eprintln!("❌ Failed to rotate audit log: {}; still writing the current segment", e),
+                    }
+                }
+            }
+
+            // The record is written; what the dashboard gets is a separate copy.
+            let shown = match &redact_stream {
+                Some(policy) => {
+                    let mut shown = log.clone();
+                    let redactions = redaction::redact_log(&mut shown, policy);
+                    redactions.mark(&mut shown);
+                    if let Some(totals) = &state_for_audit.redactions {
+                        totals.record(&redactions);
+                    }
+                    std::borrow::Cow::Owned(shown)
                 }
+                None => std::borrow::Cow::Borrowed(&log),
+            };
+            // A gap marker has no event to show; the ids it skips stay missing.
+            if let (false, Ok(text)) = (gap, serde_json::to_string(&shown)) {
+                let text: Arc<str> = Arc::from(text);
+                let mark = feed::Watermark { ts_ms: log.observed_ts_ms, event_id: log.event_id };
+                feed_for_audit.publish(text.clone(), mark);
+                state_for_audit.history.push(log.event_id, text);
             }
 
-            let _ = ws_tx_for_audit.send(log);
+            // Group commit: one sync covers every event that was already queued.
+            if let Err(e) = durability.event_written(&mut sink, !log_rx.is_empty()).await {
+                eprintln!("❌ Failed to sync audit log: {}", e);
+            }
         }
 
+        // Always closed by a checkpoint, even when no event reached the chain.
         if let Some(ref sk) = signing_key {
-            if last_event_id > 0 {
-                eprintln!("🔒 Writing final checkpoint for event_id {}", last_event_id);
-                
-                let final_cp = audit::make_checkpoint_record(
-                    sk,
-                    &run_id,
-                    events::current_timestamp_ms(),
-                    last_event_id,
-                    &prev_hash,
-                );
-
-                if let Ok(cp_json) = serde_json::to_string(&final_cp) {
-                    if let Err(e) = sink.write_record("Checkpoint", &cp_json).await {
-                        eprintln!("❌ Failed to write final checkpoint: {}", e);
-                    } else {
-                        eprintln!("✓ Final checkpoint written");
+            eprintln!("🔒 Writing final checkpoint for event_id {}", chain.last_event_id());
+
+            match chain.checkpoint(sk.as_ref(), &run_id, events::current_timestamp_ms()) {
+                Ok(final_cp) => {
+                    if let Ok(cp_json) = serde_json::to_string(&final_cp) {
+                        if let Err(e) = sink.write_record("Checkpoint", &cp_json).await {
+                            eprintln!("❌ Failed to write final checkpoint: {}", e);
+                        } else {
+                            eprintln!("✓ Final checkpoint written");
+                            anchors.submit(&final_cp);
+                        }
                     }
                 }
+                Err(e) => eprintln!("❌ Failed to create final checkpoint: {}", e),
+            }
+        }
+
+        // Give outstanding timestamp and witness requests a moment; the log is valid without them.
+        drop(anchors);
+        let deadline = tokio::time::Instant::now() + RECEIPT_DRAIN;
+        loop {
+            match tokio::time::timeout_at(deadline, receipt_rx.recv()).await {
+                Ok(Some(receipt)) => write_receipt(&mut sink, &receipt).await,
+                Ok(None) => break,
+                Err(_) => {
+                    eprintln!("⚠️  Closing the audit log without the timestamp proofs or witness receipts still pending");
+                    break;
+                }
             }
         }
 
-        if let Err(e) = sink.flush().await {
+        let closed = match sink.close().await {
+            Ok(()) => durability.barrier(&mut sink).await,
+            Err(e) => Err(e),
+        };
+        health_for_audit.audit_running(false);
+        if let Err(e) = closed {
             eprintln!("❌ Failed to flush audit log: {}", e);
         } else {
             eprintln!("✓ Audit log closed cleanly");
         }
+        if audit_outputs.len() > 1 {
+            for failure in sink_stats.failures() {
+                eprintln!("⚠️  Audit log output {}", failure);
+            }
+        }
     });
 
-    let ws_bind = args.ws_bind.clone();
     let state_for_server = state.clone();
 
-    tokio::spawn(async move {
+    shutdown.spawn(Phase::StopServer, "server", async move {
         if let Err(e) = start_server(state_for_server, &ws_bind).await {
             eprintln!("❌ WebSocket server error: {}", e);
         }
     });
 
-    let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
+    if let Some(limit) = args.max_message_bytes {
+        eprintln!("🚧 Enforcing max message size: {} bytes (larger messages are not forwarded)", limit);
+    }
+    for (direction, limit) in [("inbound", args.max_inbound_bytes_per_sec), ("outbound", args.max_outbound_bytes_per_sec)] {
+        if let Some(limit) = limit {
+            eprintln!("🐢 Pacing {} traffic to {} bytes/s", direction, limit);
+        }
+    }
+    if args.strict_protocol {
+        eprintln!("🚧 Enforcing strict JSON-RPC 2.0 (violating messages are not forwarded)");
+    }
+    let mut child_vars = match &args.child_env_file {
+        Some(path) => child_env::load_file(path)?,
+        None => Vec::new(),
+    };
+    child_vars.extend(args.child_env);
+    if let Some(cwd) = args.child_cwd.as_ref().filter(|d| !d.is_dir()) {
+        return Err(format!("--child-cwd {}: not a directory", cwd.display()).into());
+    }
+    let proxy_config = ProxyConfig {
+        max_message_bytes: args.max_message_bytes,
+        strict_protocol: args.strict_protocol,
+        protocol_rules,
+        max_inbound_bytes_per_sec: args.max_inbound_bytes_per_sec,
+        max_outbound_bytes_per_sec: args.max_outbound_bytes_per_sec,
+        framing: args.framing,
+        child_shutdown_timeout: std::time::Duration::from_millis(args.child_shutdown_timeout_ms),
+        restart: (args.restart_on_exit || args.reconnect).then_some(RestartPolicy {
+            max_restarts: args.max_restarts,
+            initial_backoff: std::time::Duration::from_millis(args.restart_backoff_ms),
+        }),
+        child_env: child_env::ChildEnv {
+            vars: child_vars,
+            clear: args.child_clear_env,
+            cwd: args.child_cwd,
+        },
+        max_line_bytes: args.max_line_bytes as usize,
+        forward_gate,
+        throughput,
+        throughput_interval: (args.throughput_interval_secs > 0)
+            .then(|| std::time::Duration::from_secs(args.throughput_interval_secs)),
+        redaction: redact_scope,
+        redaction_rules,
+        health: Some(health).filter(|_| hub.is_none()),
+    };
+
+    let upstream = match (args.connect.as_deref(), args.http_upstream) {
+        (Some(url), _) => Upstream::Socket(endpoint(url)?),
+        (None, Some(url)) => {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!("--http-upstream: expected an http:// or https:// URL, got {:?}", url).into());
+            }
+            if args.max_message_bytes.is_some()
+                || args.strict_protocol
+                || args.max_inbound_bytes_per_sec.is_some()
+                || args.max_outbound_bytes_per_sec.is_some()
+            {
+                eprintln!("⚠️  Message limits, pacing and --strict-protocol are not applied in HTTP mode");
+            }
+            Upstream::Http { url, listen: args.http_listen }
+        }
+        (None, None) => Upstream::Command(args.command),
+    };
+    let proxy_shutdown = shutdown.clone();
+    let stop_input = shutdown.token(Phase::StopInput);
+    shutdown.extend(Phase::StopInput, proxy_config.child_shutdown_timeout);
+    shutdown.spawn(Phase::StopInput, "proxy", async move {
+        let result = match hub {
+            Some(hub) => hub::run(hub, raw_tx, proxy_config, stop_input).await.map(|()| None),
+            None => run_proxy(upstream, Client::stdio(), raw_tx, proxy_config, stop_input).await,
+        };
+        let reason = match result {
+            Ok(Some(status)) => {
+                proxy_shutdown.set_child_exit_code(status.code());
+                ShutdownReason::child_exited(status)
+            }
+            Ok(None) => ShutdownReason::ConnectionClosed,
+            Err(e) => ShutdownReason::ProxyError(e.to_string()),
+        };
+        proxy_shutdown.trigger(reason);
+    });
+
+    let signal_shutdown = shutdown.clone();
     tokio::spawn(async move {
-        if let Err(e) = signal::ctrl_c().await {
-            eprintln!("❌ Error setting up Ctrl+C handler: {}", e);
-            return;
+        tokio::select! {
+            result = signal::ctrl_c() => match result {
+                Ok(()) => signal_shutdown.trigger(ShutdownReason::Signal("SIGINT")),
+                Err(e) => eprintln!("❌ Error setting up Ctrl+C handler: {}", e),
+            },
+            _ = sigterm() => signal_shutdown.trigger(ShutdownReason::Signal("SIGTERM")),
+            _ = signal_shutdown.triggered() => {}
         }
-        eprintln!("\n🛑 Received Ctrl+C, shutting down gracefully...");
-        let _ = shutdown_tx.send(()).await;
     });
 
-    tokio::select! {
-        result = run_proxy(args.command, raw_tx) => {
-            match result {
-                Ok(_) => eprintln!("📋 Proxy completed successfully"),
-                Err(e) => eprintln!("❌ Proxy error: {}", e),
-            }
+    shutdown.triggered().await;
+    eprintln!("⏳ Waiting for audit log to finalize...");
+    let reports = shutdown.run_phases().await;
+
+    let reason = shutdown.reason();
+    eprintln!(
+        "✅ Sentinel shutdown complete ({}, {} ms)",
+        reason.as_ref().map(|r| r.to_string()).unwrap_or_default(),
+        shutdown.elapsed().as_millis()
+    );
+    for r in reports.iter().filter(|r| r.timed_out) {
+        eprintln!("   ⚠️  phase {} timed out after {} ms", r.phase.as_str(), r.elapsed.as_millis());
+    }
+
+    Ok(reason
+        .and_then(|r| r.exit_code())
+        .unwrap_or_else(|| shutdown.child_exit_code().unwrap_or(0)))
+}
+
+/// Resolves on SIGTERM (what service managers and `docker stop` send), so it
+/// shuts the run down like Ctrl+C. Never resolves where there is no SIGTERM.
+async fn sigterm() {
+    #[cfg(unix)]
+    match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        Ok(mut term) => {
+            term.recv().await;
+            return;
         }
-        _ = shutdown_rx.recv() => {
-            eprintln!("📋 Shutdown signal received");
+        Err(e) => eprintln!("❌ Error setting up SIGTERM handler: {}", e),
+    }
+    std::future::pending::<()>().await
+}
+
+/// Give `raw` sequence number `*seq` and hand it to the parser, writing it to the
+/// raw capture first. Returns false once the parser is gone.
+async fn sequence(
+    seq: &mut u64,
+    raw: events::RawTap,
+    capture: &mut Option<capture::CaptureWriter>,
+    tap_tx: &mpsc::Sender<events::TapEvent>,
+) -> bool {
+    let evt = events::TapEvent {
+        seq: *seq,
+        direction: raw.direction,
+        bytes: raw.bytes,
+        observed_ts_ms: raw.observed_ts_ms,
+        server: raw.server,
+    };
+    *seq += 1;
+
+    if let Some(writer) = capture.as_mut() {
+        if let Err(e) = writer.write(&evt).await {
+            eprintln!("❌ {}; raw capture stopped", e);
+            *capture = None;
         }
     }
+    tap_tx.send(evt).await.is_ok()
+}
 
-    drop(log_tx);
-    if let Err(e) = audit_shutdown_tx.send(()).await {
-        eprintln!("⚠️  Failed to signal audit shutdown: {}", e);
+/// Taps dropped under `--tap-overflow drop` (`dropped` per direction) keep the
+/// sequence numbers they would have had: `*seq` moves past them, and the notice
+/// naming them, to be sequenced in their place, is returned. `None` when nothing
+/// was dropped.
+fn tap_gap(seq: &mut u64, dropped: [u64; 4]) -> Option<events::RawTap> {
+    let missed: u64 = dropped.iter().sum();
+    if missed == 0 {
+        return None;
     }
+    eprintln!("⚠️  Audit pipeline was full, {} tap(s) dropped", missed);
+    let first = *seq;
+    *seq += missed;
+    Some(tap::gap_notice(first, *seq - 1, dropped))
+}
 
-    eprintln!("⏳ Waiting for audit log to finalize...");
-    if let Err(e) = audit_handle.await {
-        eprintln!("⚠️  Audit task join error: {}", e);
+/// Build the next event record. Under `--sign-every-event` its entry hash is
+/// signed on a blocking thread, keeping signing off the runtime that forwards
+/// traffic; the record's layout (and so the next event's offset) depends on the
+/// signature, so events are still signed and written strictly in chain order.
+/// Returns the serialized record (without newline), its entry hash and any
+/// payload offloaded by `--max-payload-bytes`.
+async fn prepare_event(
+    chain: &audit::AuditChain,
+    log: &mut events::McpLog,
+    position: u64,
+    signer: Option<&Arc<dyn audit::CheckpointSigner>>,
+) -> Result<(String, [u8; 32], Option<Vec<u8>>, redaction::RedactionReport), String> {
+    let audit::PreparedEvent { mut record, entry_hash, blob, redactions } = chain.prepare(log, position)?;
+    if let (Some(signer), audit::AuditRecord::Event { .. }) = (signer, &record) {
+        let signer = signer.clone();
+        let signature = tokio::task::spawn_blocking(move || audit::sign_entry_hash(signer.as_ref(), &entry_hash))
+            .await
+            .map_err(|e| format!("event signing task failed: {}", e))??;
+        audit::set_event_signature(&mut record, signature);
     }
+    let json = serde_json::to_string(&record).map_err(|e| format!("failed to serialize event record: {}", e))?;
+    Ok((json, entry_hash, blob, redactions))
+}
 
-    eprintln!("✅ Sentinel shutdown complete");
-    Ok(())
-}
\ No newline at end of file
+/// When the audit writer closes the current segment and continues in the next.
+struct AuditRotation {
+    max_bytes: Option<u64>,
+    max_age: Option<std::time::Duration>,
+}
+
+impl AuditRotation {
+    fn due(&self, bytes: u64, age: std::time::Duration) -> bool {
+        self.max_bytes.is_some_and(|max| bytes >= max) || self.max_age.is_some_and(|max| age >= max)
+    }
+}
+
+fn audit_rotation(max_bytes: Option<u64>, max_hours: Option<u64>) -> Option<AuditRotation> {
+    if max_bytes.is_none() && max_hours.is_none() {
+        return None;
+    }
+    eprintln!(
+        "🔁 Audit log rotation: every {} / {}",
+        max_bytes.map_or("-".to_string(), |b| format!("{} bytes", b)),
+        max_hours.map_or("-".to_string(), |h| format!("{} h", h)),
+    );
+    Some(AuditRotation {
+        max_bytes,
+        max_age: max_hours.map(|h| std::time::Duration::from_secs(h * 3600)),
+    })
+}
+
+/// Close the current audit segment with a checkpoint of its chain tip and open
+/// segment `n` (of --audit-log and each mirror), whose first record signs that tip
+/// over; `previous` is the file being closed, and the new one is encrypted and compressed like it. Unless the fsync policy is `never`, both files are synced (the new
+/// one before any event goes into it). On failure the current segment stays open.
+/// The closing checkpoint's timestamp proof and witness receipt, if any, land in
+/// the new segment.
+async fn rotate_audit_log(
+    sink: &mut audit_crypto::AuditSink<tokio::fs::File>,
+    chain: &mut audit::AuditChain,
+    (signer, anchors): (&dyn audit::CheckpointSigner, &CheckpointAnchors),
+    run_id: &str,
+    (previous, n): (&Path, u32),
+    (encryption, compression, dek_rotation): (&audit_crypto::Encryption, audit_crypto::Compression, audit_crypto::DekRotation),
+    durability: &mut audit_crypto::Durability,
+) -> Result<audit_crypto::AuditSink<tokio::fs::File>, String> {
+    if chain.pending() > 0 {
+        let cp = chain.checkpoint(signer, run_id, events::current_timestamp_ms())?;
+        let cp_json = serde_json::to_string(&cp).map_err(|e| format!("serialize checkpoint: {}", e))?;
+        sink.write_record("Checkpoint", &cp_json).await?;
+        anchors.submit(&cp);
+    }
+    sink.close().await?;
+    durability.barrier(sink).await?;
+
+    let outputs = sink.outputs().create_segment(n).await?;
+    // Encrypted segments each get their own KeyEnvelope, starting again at key generation 0.
+    let mut next = audit_crypto::AuditSink::new(outputs, run_id, encryption, compression)
+        .await?
+        .with_dek_rotation(dek_rotation);
+    let previous = previous.file_name().unwrap_or_default().to_string_lossy();
+    let header = chain.segment_header(signer, run_id, n, &previous, events::current_timestamp_ms())?;
+    let header_json = serde_json::to_string(&header).map_err(|e| format!("serialize segment header: {}", e))?;
+    next.write_record("SegmentHeader", &header_json).await?;
+    durability.barrier(&mut next).await?;
+    Ok(next)
+}
+
+/// How long closing the audit log waits for outstanding timestamp proofs and witness receipts
+const RECEIPT_DRAIN: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Where each written checkpoint is sent besides the log.
+#[derive(Default)]
+struct CheckpointAnchors {
+    stamper: Option<timestamp::Stamper>,
+    witness: Option<witness::Witness>,
+}
+
+impl CheckpointAnchors {
+    fn submit(&self, checkpoint: &audit::AuditRecord) {
+        if let Some(stamper) = &self.stamper {
+            stamper.request(checkpoint);
+        }
+        if let Some(witness) = &self.witness {
+            witness.submit(checkpoint);
+        }
+    }
+}
+
+/// Write a TimestampProof or WitnessAck that came back for a checkpoint.
+async fn write_receipt(sink: &mut audit_crypto::AuditSink<tokio::fs::File>, receipt: &audit::AuditRecord) {
+    let kind = match receipt {
+        audit::AuditRecord::TimestampProof { .. } => "TimestampProof",
+        _ => "WitnessAck",
+    };
+    let written = match serde_json::to_string(receipt) {
+        Ok(json) => sink.write_record(kind, &json).await,
+        Err(e) => Err(format!("serialize {}: {}", kind, e)),
+    };
+    if let Err(e) = written {
+        eprintln!("❌ Failed to write {}: {}", kind, e);
+    }
+}
+
+/// The socket a `--connect` URL names (a bare `host:port` is TCP).
+fn endpoint(url: &str) -> Result<Endpoint, Box<dyn std::error::Error>> {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("tcp", url));
+    match scheme {
+        "tcp" => match rest.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(Endpoint::Tcp(rest.to_string())),
+            _ => Err(format!("--connect: expected tcp://host:port, got {:?}", url).into()),
+        },
+        #[cfg(unix)]
+        "unix" if !rest.is_empty() => Ok(Endpoint::Unix(PathBuf::from(rest))),
+        #[cfg(windows)]
+        "pipe" if !rest.is_empty() => Ok(Endpoint::Pipe(format!(r"\\.\pipe\{}", rest))),
+        "unix" | "pipe" if rest.is_empty() => Err(format!("--connect: {}:// needs a path, got {:?}", scheme, url).into()),
+        "unix" => Err("--connect: unix:// sockets are only supported on Unix".into()),
+        "pipe" => Err("--connect: pipe:// named pipes are only supported on Windows".into()),
+        _ => Err(format!("--connect: unsupported scheme {:?} (expected tcp://, unix:// or pipe://)", scheme).into()),
+    }
+}
+
+fn detector(builtin: bool, rules_path: Option<&str>) -> Result<Option<Arc<detection::Detector>>, String> {
+    if !builtin && rules_path.is_none() {
+        return Ok(None);
+    }
+    let detector = detection::Detector::new(builtin, rules_path.map(Path::new))?;
+    eprintln!("🔎 Advisory detection enabled (inbound payloads are tagged, never modified)");
+    Ok(Some(Arc::new(detector)))
+}
+
+/// The rules of `--redaction-config`, or the built-in ones without it.
+fn redaction_policy(config: Option<&str>) -> Result<redaction::RedactionPolicy, String> {
+    match config {
+        Some(path) => redaction::RedactionPolicy::load(Path::new(path)),
+        None => Ok(redaction::RedactionPolicy::default()),
+    }
+}
+
+fn protocol_rules(disabled: &[protocol::Rule]) -> protocol::RuleSet {
+    disabled
+        .iter()
+        .fold(protocol::RuleSet::all(), |rules, rule| rules.without(*rule))
+}
+
+/// Records in the chain that a checkpoint was forced through `/api/audit/checkpoint`.
+fn checkpoint_requested_event(run_id: &str, session: &Session) -> events::McpLog {
+    events::McpLog::synthetic(
+        run_id.to_string(),
+        "sentinel/checkpoint_requested",
+        serde_json::json!({ "trigger": "api" }),
+        &session.session_id,
+        &session.trace_id,
+        events::current_timestamp_ms(),
+        Uuid::new_v4().to_string(),
+    )
+}
+
+/// The last event of a run: why it stopped and, when redaction is on, what it
+/// redacted: every rule's count and the secrets by type.
+fn shutdown_event(
+    shutdown: &ShutdownController,
+    run_id: &str,
+    session: &Session,
+    redactions: Option<&redaction::RedactionTotals>,
+) -> events::McpLog {
+    let reason = shutdown.reason();
+    let mut payload = serde_json::json!({
+        "reason": reason.as_ref().map(|r| r.kind()),
+        "detail": reason.as_ref().map(|r| r.to_string()),
+        "child_exit_code": shutdown.child_exit_code(),
+        "elapsed_ms": shutdown.elapsed().as_millis() as u64,
+    });
+    if let Some(totals) = redactions {
+        payload["redactions"] = totals.snapshot();
+        payload["secrets_redacted"] = totals.secrets();
+    }
+    events::McpLog::synthetic(
+        run_id.to_string(),
+        "sentinel/shutdown",
+        payload,
+        &session.session_id,
+        &session.trace_id,
+        events::current_timestamp_ms(),
+        Uuid::new_v4().to_string(),
+    )
+}
@@ -0,0 +1,157 @@
+//! Merkle trees over event entry hashes, shaped as in RFC 9162 (Certificate
+//! Transparency v2): leaves and interior nodes are domain-separated, and a tree
+//! of `n` leaves splits at the largest power of two below `n`. Built with blake3.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use serde::{Deserialize, Serialize};
+
+pub fn leaf_hash(entry_hash: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0x00]);
+    hasher.update(entry_hash);
+    *hasher.finalize().as_bytes()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+/// Root of a tree without leaves (a checkpoint with no events since the last one).
+pub fn empty_root() -> [u8; 32] {
+    *blake3::hash(b"").as_bytes()
+}
+
+/// Builds the root over leaves appended one at a time, keeping only the roots of
+/// the perfect subtrees so far (one per set bit of the leaf count).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MerkleAccumulator {
+    leaves: u64,
+    /// (height, root) of each perfect subtree, largest first
+    #[serde(with = "stack_b64")]
+    stack: Vec<(u32, [u8; 32])>,
+}
+
+impl MerkleAccumulator {
+    pub fn push(&mut self, entry_hash: &[u8; 32]) {
+        let mut node = (0u32, leaf_hash(entry_hash));
+        while let Some(&(height, left)) = self.stack.last().filter(|(h, _)| *h == node.0) {
+            self.stack.pop();
+            node = (height + 1, node_hash(&left, &node.1));
+        }
+        self.stack.push(node);
+        self.leaves += 1;
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        let mut subtrees = self.stack.iter().rev();
+        let Some(&(_, mut acc)) = subtrees.next() else {
+            return empty_root();
+        };
+        for (_, left) in subtrees {
+            acc = node_hash(left, &acc);
+        }
+        acc
+    }
+}
+
+/// Root over a complete list of entry hashes.
+pub fn root(entry_hashes: &[[u8; 32]]) -> [u8; 32] {
+    match entry_hashes.len() {
+        0 => empty_root(),
+        1 => leaf_hash(&entry_hashes[0]),
+        n => {
+            let k = split(n);
+            node_hash(&root(&entry_hashes[..k]), &root(&entry_hashes[k..]))
+        }
+    }
+}
+
+/// Audit path of leaf `index`: the sibling hashes from the leaf up to the root.
+pub fn inclusion_path(entry_hashes: &[[u8; 32]], index: usize) -> Vec<[u8; 32]> {
+    let n = entry_hashes.len();
+    if n <= 1 {
+        return Vec::new();
+    }
+    let k = split(n);
+    if index < k {
+        let mut path = inclusion_path(&entry_hashes[..k], index);
+        path.push(root(&entry_hashes[k..]));
+        path
+    } else {
+        let mut path = inclusion_path(&entry_hashes[k..], index - k);
+        path.push(root(&entry_hashes[..k]));
+        path
+    }
+}
+
+/// Root implied by `path` for the leaf at `index` of a `tree_size`-leaf tree
+/// (RFC 9162 section 2.1.3.2).
+pub fn root_from_path(entry_hash: &[u8; 32], index: u64, tree_size: u64, path: &[[u8; 32]]) -> Result<[u8; 32], String> {
+    if index >= tree_size {
+        return Err(format!("leaf index {index} outside a tree of {tree_size} leaves"));
+    }
+    let (mut fn_, mut sn) = (index, tree_size - 1);
+    let mut r = leaf_hash(entry_hash);
+    for p in path {
+        if sn == 0 {
+            return Err("inclusion path is too long".to_string());
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    if sn != 0 {
+        return Err("inclusion path is too short".to_string());
+    }
+    Ok(r)
+}
+
+/// Largest power of two strictly below `n` (n >= 2).
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+mod stack_b64 {
+    use super::B64;
+    use base64::Engine as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(stack: &[(u32, [u8; 32])], s: S) -> Result<S::Ok, S::Error> {
+        let encoded: Vec<(u32, String)> = stack.iter().map(|(h, r)| (*h, B64.encode(r))).collect();
+        encoded.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<(u32, [u8; 32])>, D::Error> {
+        let encoded = Vec::<(u32, String)>::deserialize(d)?;
+        encoded
+            .into_iter()
+            .map(|(h, r)| {
+                let bytes = B64.decode(r).map_err(serde::de::Error::custom)?;
+                let root: [u8; 32] = bytes
+                    .try_into()
+                    .map_err(|_| serde::de::Error::custom("merkle subtree root is not 32 bytes"))?;
+                Ok((h, root))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,574 @@
+use crate::protocol::Rule;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+
+/// Label set of the series holding everything outside the tracked top-K:
+/// `{overflow="true"}` instead of the family's label, so no real name collides
+/// with it.
+pub const OVERFLOW_LABEL: &str = "overflow=\"true\"";
+
+/// Re-evaluate top-K membership after this many observations.
+const REBALANCE_EVERY: u64 = 1_000;
+
+/// Untracked names competing for a slot, and demoted names whose values are
+/// kept, each as a multiple of the cap.
+const CANDIDATE_FACTOR: usize = 4;
+
+/// Latency histogram bucket upper bounds (ms), log-scale.
+const LATENCY_BUCKETS_MS: [u64; 12] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 5_000, 30_000];
+
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS_MS.len()],
+    count: u64,
+    sum: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, value: u64) {
+        for (i, bound) in LATENCY_BUCKETS_MS.iter().enumerate() {
+            if value <= *bound {
+                self.buckets[i] += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Upper bound (ms) of the bucket holding quantile `q`, or `None` if it lies
+    /// beyond the last bucket (or nothing was observed).
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+        LATENCY_BUCKETS_MS
+            .iter()
+            .zip(self.buckets.iter())
+            .find(|(_, cumulative)| **cumulative >= rank)
+            .map(|(bound, _)| *bound)
+    }
+}
+
+/// Fixed-memory estimate of the number of distinct label values (HyperLogLog, 1024 registers).
+struct DistinctEstimator {
+    registers: Vec<u8>,
+}
+
+impl DistinctEstimator {
+    const BITS: u32 = 10;
+
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << Self::BITS],
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        let hash = blake3::hash(value.as_bytes());
+        let mut first = [0u8; 8];
+        first.copy_from_slice(&hash.as_bytes()[..8]);
+        let h = u64::from_le_bytes(first);
+
+        let idx = (h >> (64 - Self::BITS)) as usize;
+        let rank = ((h << Self::BITS).leading_zeros() + 1).min(64 - Self::BITS + 1) as u8;
+        if rank > self.registers[idx] {
+            self.registers[idx] = rank;
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+}
+
+/// Fixed-memory set of label values whose kept series values were dropped (a
+/// Bloom filter of 2^16 bits). A false positive keeps a value in overflow,
+/// where it is still counted.
+struct Forgotten {
+    bits: Vec<u64>,
+}
+
+impl Forgotten {
+    const BITS: u32 = 16;
+    const HASHES: usize = 4;
+
+    fn new() -> Self {
+        Self {
+            bits: vec![0; (1 << Self::BITS) / 64],
+        }
+    }
+
+    fn positions(value: &str) -> impl Iterator<Item = usize> {
+        let hash = *blake3::hash(value.as_bytes()).as_bytes();
+        (0..Self::HASHES).map(move |i| {
+            let mut word = [0u8; 4];
+            word.copy_from_slice(&hash[i * 4..i * 4 + 4]);
+            (u32::from_le_bytes(word) >> (32 - Self::BITS)) as usize
+        })
+    }
+
+    fn insert(&mut self, value: &str) {
+        for p in Self::positions(value) {
+            self.bits[p / 64] |= 1 << (p % 64);
+        }
+    }
+
+    fn contains(&self, value: &str) -> bool {
+        Self::positions(value).all(|p| self.bits[p / 64] & (1 << (p % 64)) != 0)
+    }
+}
+
+/// A metric family keyed by a user-controlled label, capped at `cap` tracked values.
+///
+/// The `cap` busiest values get their own series; observations of any other
+/// value go to the overflow series. Each observation lands in exactly one series
+/// and values never move between them, so every series only grows and the last
+/// values a scraper saw of each add up to every observation, while cardinality
+/// stays bounded. Membership is re-evaluated every `REBALANCE_EVERY` observations.
+///
+/// A demoted value's series picks up where it left off if it is promoted again.
+/// What it had counted is kept for the `cap * CANDIDATE_FACTOR` most recent
+/// demotions; a value demoted longer ago than that stays in overflow for good
+/// rather than come back as a series starting at 0.
+pub struct BoundedFamily<T: Default> {
+    cap: usize,
+    tracked: HashMap<String, (T, u64)>,
+    overflow: T,
+    /// Recent volume of untracked values competing for a slot
+    candidates: HashMap<String, u64>,
+    /// Series values of demoted label values, with the rebalance that demoted them
+    retired: HashMap<String, (T, u64)>,
+    forgotten: Forgotten,
+    rebalances: u64,
+    distinct: DistinctEstimator,
+    since_rebalance: u64,
+}
+
+impl<T: Default> BoundedFamily<T> {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            tracked: HashMap::new(),
+            overflow: T::default(),
+            candidates: HashMap::new(),
+            retired: HashMap::new(),
+            forgotten: Forgotten::new(),
+            rebalances: 0,
+            distinct: DistinctEstimator::new(),
+            since_rebalance: 0,
+        }
+    }
+
+    /// Return the series to update for `label`, recording one observation.
+    pub fn series(&mut self, label: &str) -> &mut T {
+        self.distinct.insert(label);
+        self.since_rebalance += 1;
+        if self.since_rebalance >= REBALANCE_EVERY {
+            self.rebalance();
+        }
+
+        if !self.tracked.contains_key(label) {
+            if self.tracked.len() >= self.cap || !self.may_track(label) {
+                self.note_candidate(label);
+                return &mut self.overflow;
+            }
+            let value = self.retired.remove(label).map(|(value, _)| value).unwrap_or_default();
+            self.tracked.insert(label.to_string(), (value, 0));
+        }
+
+        let (value, volume) = self.tracked.get_mut(label).expect("checked above");
+        *volume += 1;
+        value
+    }
+
+    /// Whether `label` can get a series without one restarting at 0.
+    fn may_track(&self, label: &str) -> bool {
+        self.retired.contains_key(label) || !self.forgotten.contains(label)
+    }
+
+    fn note_candidate(&mut self, label: &str) {
+        if let Some(v) = self.candidates.get_mut(label) {
+            *v += 1;
+            return;
+        }
+        if !self.may_track(label) {
+            return;
+        }
+        if self.candidates.len() >= self.cap * CANDIDATE_FACTOR {
+            // Space-saving: replace the weakest candidate, inheriting its count.
+            let weakest = self
+                .candidates
+                .iter()
+                .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+                .map(|(k, v)| (k.clone(), *v));
+            if let Some((k, v)) = weakest {
+                self.candidates.remove(&k);
+                self.candidates.insert(label.to_string(), v + 1);
+            }
+            return;
+        }
+        self.candidates.insert(label.to_string(), 1);
+    }
+
+    /// Keep the series value of a demoted label, forgetting the one demoted
+    /// longest ago if there is no room.
+    fn retire(&mut self, label: String, value: T) {
+        if self.retired.len() >= self.cap * CANDIDATE_FACTOR {
+            let oldest = self
+                .retired
+                .iter()
+                .min_by(|a, b| (a.1).1.cmp(&(b.1).1).then_with(|| a.0.cmp(b.0)))
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                self.retired.remove(&k);
+                self.forgotten.insert(&k);
+            }
+        }
+        self.retired.insert(label, (value, self.rebalances));
+    }
+
+    /// Promote candidates busier than the quietest tracked values, demoting
+    /// those, then decay volumes so membership follows recent traffic.
+    fn rebalance(&mut self) {
+        self.since_rebalance = 0;
+        self.rebalances += 1;
+
+        // Ties are broken by label, so the same traffic always gives the same
+        // series (`rederive` reproduces the latency summary).
+        let mut candidates: Vec<(String, u64)> = self.candidates.drain().collect();
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        for (label, volume) in candidates {
+            if !self.may_track(&label) {
+                continue;
+            }
+            let quietest = self
+                .tracked
+                .iter()
+                .min_by(|a, b| (a.1).1.cmp(&(b.1).1).then_with(|| a.0.cmp(b.0)))
+                .map(|(k, (_, v))| (k.clone(), *v));
+
+            match quietest {
+                Some((demote, demote_volume)) if volume > demote_volume => {
+                    if let Some((value, v)) = self.tracked.remove(&demote) {
+                        self.retire(demote.clone(), value);
+                        self.candidates.insert(demote, v / 2);
+                    }
+                    let value = self.retired.remove(&label).map(|(value, _)| value).unwrap_or_default();
+                    self.tracked.insert(label, (value, volume));
+                }
+                _ => {
+                    self.candidates.insert(label, volume / 2);
+                }
+            }
+        }
+
+        for (_, volume) in self.tracked.values_mut() {
+            *volume /= 2;
+        }
+        let (retired, forgotten) = (&self.retired, &self.forgotten);
+        self.candidates
+            .retain(|k, v| *v > 0 && (retired.contains_key(k) || !forgotten.contains(k)));
+    }
+
+    pub fn tracked_count(&self) -> usize {
+        self.tracked.len()
+    }
+
+    pub fn distinct_estimate(&self) -> u64 {
+        self.distinct.estimate()
+    }
+
+    /// Tracked series sorted by label, followed by overflow (`None`).
+    pub fn iter_series(&self) -> impl Iterator<Item = (Option<&str>, &T)> {
+        let mut labels: Vec<&String> = self.tracked.keys().collect();
+        labels.sort();
+        labels
+            .into_iter()
+            .map(move |k| (Some(k.as_str()), &self.tracked[k].0))
+            .chain(std::iter::once((None, &self.overflow)))
+    }
+}
+
+struct MetricsInner {
+    tool_calls: BoundedFamily<u64>,
+    method_latency: BoundedFamily<Histogram>,
+    /// Indexed by `Rule::index`
+    protocol_violations: [u64; Rule::ALL.len()],
+    detections: BoundedFamily<u64>,
+}
+
+/// Process-wide counters exported in OpenMetrics text format on `/metrics`.
+pub struct Metrics {
+    inner: Mutex<MetricsInner>,
+}
+
+impl Metrics {
+    pub fn new(top_k: usize) -> Self {
+        Self {
+            inner: Mutex::new(MetricsInner {
+                tool_calls: BoundedFamily::new(top_k),
+                method_latency: BoundedFamily::new(top_k),
+                protocol_violations: [0; Rule::ALL.len()],
+                detections: BoundedFamily::new(top_k),
+            }),
+        }
+    }
+
+    pub fn record_tool_call(&self, tool: &str) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        *inner.tool_calls.series(tool) += 1;
+    }
+
+    pub fn record_latency(&self, method: &str, latency_ms: u64) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.method_latency.series(method).observe(latency_ms);
+    }
+
+    /// Latency per method, for `/api/latency` and the `sentinel/latency_summary`
+    /// event. Quantiles are bucket upper bounds; `null` means beyond the last
+    /// bucket.
+    pub fn latency_summary(&self) -> Value {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let methods: Vec<Value> = inner
+            .method_latency
+            .iter_series()
+            .filter(|(_, hist)| hist.count > 0)
+            .map(|(method, hist)| {
+                let mut entry = json!({
+                    "count": hist.count,
+                    "sum_ms": hist.sum,
+                    "p50_ms": hist.quantile(0.5),
+                    "p90_ms": hist.quantile(0.9),
+                    "p99_ms": hist.quantile(0.99),
+                });
+                match method {
+                    Some(method) => entry["method"] = json!(method),
+                    None => entry["overflow"] = json!(true),
+                }
+                entry
+            })
+            .collect();
+        json!({
+            "methods": methods,
+            "bucket_bounds_ms": LATENCY_BUCKETS_MS,
+        })
+    }
+
+    /// Count one violation by code; unknown codes are ignored so the label set stays fixed.
+    pub fn record_protocol_violation(&self, code: &str) {
+        if let Some(rule) = Rule::ALL.iter().find(|r| r.code() == code) {
+            let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            inner.protocol_violations[rule.index()] += 1;
+        }
+    }
+
+    pub fn record_detection(&self, rule: &str) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        *inner.detections.series(rule) += 1;
+    }
+
+    /// Metric families in OpenMetrics text format; the caller appends `# EOF`.
+    pub fn render_openmetrics(&self) -> String {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE sentinel_tool_calls counter");
+        let _ = writeln!(out, "# HELP sentinel_tool_calls tools/call requests by tool (top-K, the rest under overflow=\"true\")");
+        for (tool, count) in inner.tool_calls.iter_series() {
+            let _ = writeln!(out, "sentinel_tool_calls_total{{{}}} {}", labels("tool", tool), count);
+        }
+        write_cardinality(&mut out, "tool", &inner.tool_calls);
+
+        let _ = writeln!(out, "# TYPE sentinel_method_latency_ms histogram");
+        let _ = writeln!(out, "# HELP sentinel_method_latency_ms Request latency by method (top-K, the rest under overflow=\"true\")");
+        for (method, hist) in inner.method_latency.iter_series() {
+            let method = labels("method", method);
+            for (bound, count) in LATENCY_BUCKETS_MS.iter().zip(hist.buckets.iter()) {
+                let _ = writeln!(out, "sentinel_method_latency_ms_bucket{{{},le=\"{}\"}} {}", method, bound, count);
+            }
+            let _ = writeln!(out, "sentinel_method_latency_ms_bucket{{{},le=\"+Inf\"}} {}", method, hist.count);
+            let _ = writeln!(out, "sentinel_method_latency_ms_sum{{{}}} {}", method, hist.sum);
+            let _ = writeln!(out, "sentinel_method_latency_ms_count{{{}}} {}", method, hist.count);
+        }
+        write_cardinality(&mut out, "method", &inner.method_latency);
+
+        let _ = writeln!(out, "# TYPE sentinel_protocol_violations counter");
+        let _ = writeln!(out, "# HELP sentinel_protocol_violations JSON-RPC rule violations seen, by rule");
+        for rule in Rule::ALL {
+            let _ = writeln!(
+                out,
+                "sentinel_protocol_violations_total{{rule=\"{}\"}} {}",
+                rule.code(),
+                inner.protocol_violations[rule.index()]
+            );
+        }
+
+        let _ = writeln!(out, "# TYPE sentinel_detections counter");
+        let _ = writeln!(out, "# HELP sentinel_detections Advisory detection rule hits on inbound payloads");
+        for (rule, count) in inner.detections.iter_series() {
+            let _ = writeln!(out, "sentinel_detections_total{{{}}} {}", labels("rule", rule), count);
+        }
+
+        out
+    }
+}
+
+fn write_cardinality<T: Default>(out: &mut String, label: &str, family: &BoundedFamily<T>) {
+    let _ = writeln!(out, "# TYPE sentinel_{}_names_seen gauge", label);
+    let _ = writeln!(out, "sentinel_{}_names_seen {}", label, family.distinct_estimate());
+    let _ = writeln!(out, "# TYPE sentinel_{}_names_tracked gauge", label);
+    let _ = writeln!(out, "sentinel_{}_names_tracked {}", label, family.tracked_count());
+}
+
+/// `name="value"`, or [`OVERFLOW_LABEL`] for the overflow series.
+fn labels(name: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("{}=\"{}\"", name, escape_label(value)),
+        None => OVERFLOW_LABEL.to_string(),
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(family: &BoundedFamily<u64>, label: &str) -> Option<u64> {
+        family.iter_series().find(|(l, _)| *l == Some(label)).map(|(_, v)| *v)
+    }
+
+    /// The last value of every series a scraper has seen, checking none went back.
+    #[derive(Default)]
+    struct Scraper {
+        last: HashMap<Option<String>, u64>,
+    }
+
+    impl Scraper {
+        /// Scrape `family`, returning the total of every series ever seen.
+        fn scrape(&mut self, family: &BoundedFamily<u64>) -> u64 {
+            for (label, value) in family.iter_series() {
+                let last = self.last.entry(label.map(str::to_string)).or_default();
+                assert!(*value >= *last, "{:?} went from {} to {}", label, last, value);
+                *last = *value;
+            }
+            self.last.values().sum()
+        }
+    }
+
+    #[test]
+    fn ten_thousand_tool_names_stay_within_the_cap_and_are_all_counted() {
+        const CAP: usize = 50;
+        let mut family = BoundedFamily::<u64>::new(CAP);
+        let mut scraper = Scraper::default();
+        let mut observed = 0;
+        for round in 0..3u64 {
+            for i in 0..10_000u64 {
+                // A few names much busier than the rest, and churn among those.
+                let calls = if i % 1_000 == round { 40 } else { 1 };
+                for _ in 0..calls {
+                    // Series only come and go at a rebalance, so a scrape right
+                    // before each one sees every value a series ends with.
+                    if family.since_rebalance + 1 == REBALANCE_EVERY {
+                        assert_eq!(scraper.scrape(&family), observed);
+                    }
+                    *family.series(&format!("tool-{}", i)) += 1;
+                    observed += 1;
+                }
+                assert!(family.tracked_count() <= CAP);
+                assert!(family.candidates.len() <= CAP * CANDIDATE_FACTOR);
+                assert!(family.retired.len() <= CAP * CANDIDATE_FACTOR);
+            }
+        }
+        assert_eq!(scraper.scrape(&family), observed);
+        assert_eq!(family.iter_series().count(), CAP + 1);
+        assert!(scraper.last.len() > CAP + 1, "membership changed");
+        let seen = family.distinct_estimate() as f64;
+        assert!((seen - 10_000.0).abs() < 1_000.0, "estimated {} names", seen);
+
+        let metrics = Metrics::new(CAP);
+        for i in 0..10_000 {
+            metrics.record_tool_call(&format!("tool-{}", i));
+        }
+        let text = metrics.render_openmetrics();
+        let series = text.lines().filter(|l| l.starts_with("sentinel_tool_calls_total{")).count();
+        assert_eq!(series, CAP + 1);
+        assert!(text.contains("sentinel_tool_names_tracked 50\n"));
+    }
+
+    #[test]
+    fn a_demoted_label_picks_up_where_it_left_off_when_promoted_again() {
+        let mut family = BoundedFamily::<u64>::new(1);
+        for _ in 0..10 {
+            *family.series("a") += 1;
+        }
+        // "b" takes the only slot at the next rebalance.
+        while value(&family, "b").is_none() {
+            *family.series("b") += 1;
+        }
+        assert_eq!(value(&family, "a"), None);
+        assert_eq!(family.retired["a"].0, 10);
+
+        let mut calls = 0;
+        while value(&family, "a").is_none() {
+            *family.series("a") += 1;
+            calls += 1;
+        }
+        assert!(calls > 1, "a is promoted at a rebalance, not at once");
+        assert_eq!(value(&family, "a"), Some(11));
+        assert!(family.retired.contains_key("b"));
+    }
+
+    #[test]
+    fn a_label_demoted_too_long_ago_stays_in_overflow() {
+        let mut family = BoundedFamily::<u64>::new(1);
+        *family.series("a") += 1;
+        family.retire("a".into(), 0);
+        for i in 0..CANDIDATE_FACTOR {
+            family.retire(format!("b{}", i), 0);
+        }
+        assert!(!family.retired.contains_key("a"));
+        assert!(!family.may_track("a"));
+
+        family.tracked.clear();
+        *family.series("a") += 1;
+        assert_eq!(value(&family, "a"), None);
+        assert_eq!(family.iter_series().last(), Some((None, &1)));
+    }
+
+    #[test]
+    fn a_tool_named_other_does_not_collide_with_overflow() {
+        let metrics = Metrics::new(1);
+        metrics.record_tool_call("other");
+        metrics.record_tool_call("search");
+        let text = metrics.render_openmetrics();
+        assert!(text.contains("sentinel_tool_calls_total{tool=\"other\"} 1\n"), "{}", text);
+        assert!(text.contains("sentinel_tool_calls_total{overflow=\"true\"} 1\n"), "{}", text);
+
+        metrics.record_latency("tools/list", 3);
+        metrics.record_latency("tools/call", 3);
+        let summary = metrics.latency_summary();
+        assert_eq!(summary["methods"][0]["method"], "tools/list");
+        assert_eq!(summary["methods"][1]["overflow"], true);
+        assert!(summary["methods"][1].get("method").is_none());
+    }
+}
@@ -0,0 +1,258 @@
+//! `sentinel migrate-log`: rewrite a verified audit log with every event at the
+//! current record version, re-chained and re-signed, and close it with a signed
+//! `Migration` record naming the original.
+
+use crate::audit::{self, AuditChain, AuditRecord, MigrationSource};
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::PathBuf;
+
+pub struct MigrateOptions {
+    pub log: PathBuf,
+    pub out: PathBuf,
+    /// Verifies the original log
+    pub pubkey_path: String,
+    /// Signs the rewritten log
+    pub signing_key_path: String,
+}
+
+pub struct MigrateOutcome {
+    pub events: u64,
+    /// Event record versions found in the original
+    pub source_versions: Vec<u32>,
+}
+
+/// A chain for a log whose first event (or gap) is `event_id`.
+fn resume_before(run_id: &str, event_id: u64) -> AuditChain {
+    AuditChain::resume(
+        None,
+        &audit::ChainTip {
+            run_id: run_id.to_string(),
+            last_event_id: event_id - 1,
+            last_entry_hash: [0u8; 32],
+            end_offset: 0,
+        },
+    )
+}
+
+pub fn migrate_log(opts: &MigrateOptions) -> Result<MigrateOutcome, String> {
+    if opts.out.exists() {
+        return Err(format!("{} already exists", opts.out.display()));
+    }
+
+    // Only a log that verifies as it is can be vouched for under a new chain.
+    audit::verify_audit_log_file(&opts.log, &opts.pubkey_path)
+        .map_err(|e| format!("{} does not verify: {}", opts.log.display(), e))?;
+    let signer = audit::load_signing_key_b64(&opts.signing_key_path)?;
+
+    let file_blake3 = *blake3::hash(
+        &std::fs::read(&opts.log).map_err(|e| format!("read {}: {}", opts.log.display(), e))?,
+    )
+    .as_bytes();
+    let mut text = String::new();
+    audit::open_log(&opts.log)?
+        .read_to_string(&mut text)
+        .map_err(|e| format!("read {}: {}", opts.log.display(), e))?;
+
+    let header = serde_json::to_string(&audit::make_file_header(audit::FORMAT_AUDIT))
+        .map_err(|e| format!("serialize header: {}", e))?;
+    let mut out = format!("{}\n", header);
+
+    let mut chain = AuditChain::new(None);
+    let mut run_id = String::new();
+    let mut source_tip = [0u8; 32];
+    let mut versions = BTreeSet::new();
+    let mut events = 0u64;
+    let mut gaps = 0u64;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = audit::normalize_line(line);
+        if line.is_empty() || (i == 0 && audit::parse_file_header(line)?.is_some()) {
+            continue;
+        }
+        let record: AuditRecord =
+            serde_json::from_str(line).map_err(|e| format!("line {}: JSON parse error: {}", i + 1, e))?;
+
+        let rewritten = match record {
+            AuditRecord::Event { mut log, integrity } => {
+                // The chain numbers events itself; a verified log is already contiguous.
+                if events == 0 && gaps == 0 && log.event_id > 1 {
+                    chain = resume_before(&log.run_id, log.event_id);
+                }
+                let event_id = log.event_id;
+                run_id = log.run_id.clone();
+                versions.insert(integrity.version);
+                source_tip = audit::decode_b64_32(&integrity.entry_hash_b64)
+                    .map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+                let mut prepared = chain
+                    .prepare(&mut log, out.len() as u64)
+                    .map_err(|e| format!("line {}: {}", i + 1, e))?;
+                if log.event_id != event_id {
+                    return Err(format!("line {}: event_id {} is out of sequence", i + 1, event_id));
+                }
+                if integrity.signature_b64.is_some() {
+                    let sig = audit::sign_entry_hash(&signer, &prepared.entry_hash)?;
+                    audit::set_event_signature(&mut prepared.record, sig);
+                }
+                chain.commit(event_id, prepared.entry_hash);
+                events += 1;
+                prepared.record
+            }
+            AuditRecord::Checkpoint { run_id: ref cp_run_id, created_ts_ms, .. } => {
+                run_id.clone_from(cp_run_id);
+                // The rewritten checkpoint still names what wrote the original.
+                chain = chain.with_provenance(audit::checkpoint_provenance(&record));
+                chain.checkpoint(&signer, &run_id, created_ts_ms)?
+            }
+            AuditRecord::RunBoundary { previous_run_id, run_id: next_run_id, created_ts_ms, .. } => {
+                let rb = chain.run_boundary(&signer, &previous_run_id, &next_run_id, created_ts_ms)?;
+                run_id = next_run_id;
+                rb
+            }
+            // Carried over, re-signed at the same point of the new chain.
+            AuditRecord::Migration {
+                created_ts_ms,
+                source_file_blake3_b64,
+                source_last_entry_hash_b64,
+                source_versions,
+                ..
+            } => {
+                let source = MigrationSource {
+                    file_blake3: audit::decode_b64_32(&source_file_blake3_b64)?,
+                    last_entry_hash: audit::decode_b64_32(&source_last_entry_hash_b64)?,
+                    versions: source_versions,
+                };
+                chain.migration(&signer, &run_id, created_ts_ms, &source)?
+            }
+            // Re-chained like an event, skipping the same ids.
+            AuditRecord::GapMarker {
+                run_id: gap_run_id,
+                created_ts_ms,
+                first_event_id,
+                last_event_id,
+                first_seq,
+                last_seq,
+                dropped,
+                integrity,
+            } => {
+                if events == 0 && gaps == 0 && first_event_id > 1 {
+                    chain = resume_before(&gap_run_id, first_event_id);
+                }
+                if first_event_id != chain.last_event_id() + 1 {
+                    return Err(format!("line {}: gap at event_id {} is out of sequence", i + 1, first_event_id));
+                }
+                run_id = gap_run_id;
+                source_tip = audit::decode_b64_32(&integrity.entry_hash_b64)
+                    .map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+                let gap = audit::TapGap { first_seq, last_seq, dropped };
+                let prepared = chain
+                    .prepare_gap(&run_id, created_ts_ms, gap, out.len() as u64)
+                    .map_err(|e| format!("line {}: {}", i + 1, e))?;
+                chain.commit(last_event_id, prepared.entry_hash);
+                gaps += 1;
+                prepared.record
+            }
+            // The tokens stamp the original checkpoints, which the Migration record vouches for.
+            AuditRecord::TimestampProof { .. } => continue,
+            // Acknowledge the original's checkpoints, not the rewritten ones.
+            AuditRecord::WitnessAck { .. } => continue,
+            AuditRecord::SegmentHeader { .. } => {
+                return Err("the log is a rotated segment; migrate-log needs an unrotated log".to_string())
+            }
+            AuditRecord::FileHeader { .. } => return Err(format!("line {}: unexpected FileHeader", i + 1)),
+        };
+
+        let json = serde_json::to_string(&rewritten).map_err(|e| format!("serialize record: {}", e))?;
+        out.push_str(&json);
+        out.push('\n');
+    }
+
+    let source = MigrationSource {
+        file_blake3,
+        last_entry_hash: source_tip,
+        versions: versions.into_iter().collect(),
+    };
+    let migration = chain.migration(&signer, &run_id, crate::events::current_timestamp_ms(), &source)?;
+    let json = serde_json::to_string(&migration).map_err(|e| format!("serialize migration: {}", e))?;
+    out.push_str(&json);
+    out.push('\n');
+
+    std::fs::write(&opts.out, out).map_err(|e| format!("write {}: {}", opts.out.display(), e))?;
+    Ok(MigrateOutcome {
+        events,
+        source_versions: source.versions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use std::path::Path;
+
+    const V1_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/v1");
+
+    #[test]
+    fn a_v1_log_migrates_to_the_current_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let (seed, pubkey) = (dir.path().join("seed.b64"), dir.path().join("pub.b64"));
+        std::fs::write(&seed, STANDARD.encode(key.to_bytes())).unwrap();
+        std::fs::write(&pubkey, STANDARD.encode(key.verifying_key().to_bytes())).unwrap();
+
+        let original = Path::new(V1_FIXTURE).join("audit.jsonl");
+        let opts = MigrateOptions {
+            log: original.clone(),
+            out: dir.path().join("migrated.jsonl"),
+            pubkey_path: Path::new(V1_FIXTURE).join("pubkey.b64").to_str().unwrap().to_string(),
+            signing_key_path: seed.to_str().unwrap().to_string(),
+        };
+        let outcome = migrate_log(&opts).unwrap();
+        assert_eq!(outcome.events, 9);
+        assert_eq!(outcome.source_versions, [1]);
+
+        let summary = audit::verify_audit_log_file(&opts.out, pubkey.to_str().unwrap()).unwrap();
+        assert_eq!(summary.events_verified, 9);
+        assert_eq!(summary.checkpoints_verified, 3);
+
+        let text = std::fs::read_to_string(&opts.out).unwrap();
+        let records: Vec<AuditRecord> = text.lines().skip(1).map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert!(records.iter().all(|r| match r {
+            AuditRecord::Event { integrity, .. } => integrity.version == audit::SCHEMA_VERSION,
+            _ => true,
+        }));
+        let Some(AuditRecord::Migration { source_file_blake3_b64, source_versions, .. }) = records.last() else {
+            panic!("the migrated log ends on a Migration record");
+        };
+        let digest = blake3::hash(&std::fs::read(&original).unwrap());
+        assert_eq!(source_file_blake3_b64, &STANDARD.encode(digest.as_bytes()));
+        assert_eq!(source_versions, &[1]);
+    }
+
+    #[test]
+    fn a_gap_is_carried_over_into_the_new_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("audit.jsonl");
+        let mut writer = audit::test_log::LogWriter::create(&original);
+        writer.event("tools/list");
+        writer.gap(2);
+        writer.event("tools/call");
+        writer.checkpoint();
+        let pubkey = dir.path().join("pub.b64");
+        writer.write_pubkey(&pubkey);
+        let seed = dir.path().join("seed.b64");
+        std::fs::write(&seed, STANDARD.encode([7; 32])).unwrap();
+
+        let opts = MigrateOptions {
+            log: original,
+            out: dir.path().join("migrated.jsonl"),
+            pubkey_path: pubkey.to_str().unwrap().to_string(),
+            signing_key_path: seed.to_str().unwrap().to_string(),
+        };
+        assert_eq!(migrate_log(&opts).unwrap().events, 2);
+        let summary = audit::verify_audit_log_file(&opts.out, pubkey.to_str().unwrap()).unwrap();
+        assert_eq!((summary.events_verified, summary.gaps, summary.missed_events), (2, 1, 2));
+    }
+}
@@ -1,23 +1,183 @@
-use crate::events::{McpLog, StreamDirection, TapEvent};
-use crate::protocol::JsonRpcMessage;
-use crate::session::Session;
+use crate::clock::{Clock, IdGen, RandomIds, SystemClock};
+use crate::detection::Detector;
+use crate::events::{BatchPosition, McpLog, Pairing, StreamDirection, TapEvent};
+use crate::metrics::Metrics;
+use crate::protocol::{JsonRpcMessage, RequestId, RuleSet, Validator};
+use crate::reassembly::{Reassembler, Step};
+use crate::session::{Session, SessionInfo, SessionPhase};
+use crate::tap::TAP_GAP_METHOD;
 
-use serde_json;
-use std::collections::HashMap;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use std::time::Instant;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
+/// A request still waiting for its response.
+struct PendingSpan {
+    span_id: String,
+    /// Clock reading when the request was logged
+    start_ms: u64,
+    method: String,
+    /// Session epoch the request was issued in
+    epoch: u64,
+    /// `_meta.progressToken` the requester asked for progress under (a string
+    /// or an integer, like a request id)
+    progress_token: Option<RequestId>,
+}
+
+/// Parsing state kept per wrapped server, so one server's sessions, request
+/// ids and protocol checks never mix with another's.
+struct ServerSession {
+    session: Arc<Session>,
+    validator: Validator,
+    /// Client requests (outbound) by request_id. The two sides number their
+    /// requests independently, so the same id can be pending in both maps.
+    pending_spans: HashMap<RequestId, PendingSpan>,
+    /// Server-initiated requests (inbound: sampling, roots/list, elicitation)
+    server_spans: HashMap<RequestId, PendingSpan>,
+    /// Progress token -> the pending request that asked for progress under it,
+    /// keyed with the request's direction. A reused token goes to the latest request.
+    progress_tokens: HashMap<(StreamDirection, RequestId), RequestId>,
+    /// Messages being joined from several lines, by direction (`--max-reassembly-bytes`)
+    reassemblers: HashMap<StreamDirection, Reassembler>,
+    /// Spans of requests cancelled in this epoch, by direction and id, oldest
+    /// first (at most `MAX_CANCELLED`), so a response that races the
+    /// cancellation is logged as late rather than unmatched
+    cancelled: VecDeque<(StreamDirection, RequestId, String)>,
+}
+
+/// Cancelled requests remembered per server for late responses.
+const MAX_CANCELLED: usize = 1024;
+
+impl ServerSession {
+    fn new(session: Arc<Session>, protocol_rules: RuleSet) -> Self {
+        Self {
+            session,
+            validator: Validator::new(protocol_rules),
+            pending_spans: HashMap::new(),
+            server_spans: HashMap::new(),
+            progress_tokens: HashMap::new(),
+            reassemblers: HashMap::new(),
+            cancelled: VecDeque::new(),
+        }
+    }
+
+    /// Pending requests sent in `direction`.
+    fn spans(&mut self, direction: StreamDirection) -> &mut HashMap<RequestId, PendingSpan> {
+        match direction {
+            StreamDirection::Inbound => &mut self.server_spans,
+            _ => &mut self.pending_spans,
+        }
+    }
+
+    /// Track a request until its response. Returns the request already pending
+    /// under the same id, which the response can no longer be told apart from.
+    fn start(&mut self, direction: StreamDirection, request_id: RequestId, span: PendingSpan) -> Option<PendingSpan> {
+        let replaced = self.finish(direction, &request_id);
+        if let Some(token) = &span.progress_token {
+            self.progress_tokens.insert((direction, token.clone()), request_id.clone());
+        }
+        self.spans(direction).insert(request_id, span);
+        replaced
+    }
+
+    /// Remove a pending request sent in `direction`, with its progress token.
+    fn finish(&mut self, direction: StreamDirection, request_id: &RequestId) -> Option<PendingSpan> {
+        let span = self.spans(direction).remove(request_id)?;
+        if let Some(token) = &span.progress_token {
+            let key = (direction, token.clone());
+            if self.progress_tokens.get(&key) == Some(request_id) {
+                self.progress_tokens.remove(&key);
+            }
+        }
+        Some(span)
+    }
+
+    /// The pending request sent in `direction` that asked for progress under `token`.
+    fn by_progress_token(&self, direction: StreamDirection, token: &RequestId) -> Option<&PendingSpan> {
+        let request_id = self.progress_tokens.get(&(direction, token.clone()))?;
+        let spans = match direction {
+            StreamDirection::Inbound => &self.server_spans,
+            _ => &self.pending_spans,
+        };
+        spans.get(request_id).filter(|p| p.progress_token.as_ref() == Some(token))
+    }
+
+    fn remember_cancelled(&mut self, direction: StreamDirection, request_id: RequestId, span_id: String) {
+        if self.cancelled.len() >= MAX_CANCELLED {
+            self.cancelled.pop_front();
+        }
+        self.cancelled.push_back((direction, request_id, span_id));
+    }
+
+    /// Span of a request cancelled in `direction`, forgotten once taken.
+    fn take_cancelled(&mut self, direction: StreamDirection, request_id: &RequestId) -> Option<String> {
+        let i = self.cancelled.iter().rposition(|(d, id, _)| *d == direction && id == request_id)?;
+        self.cancelled.remove(i).map(|(_, _, span_id)| span_id)
+    }
+
+    fn is_holding(&self, direction: StreamDirection) -> bool {
+        self.reassemblers.get(&direction).is_some_and(Reassembler::is_holding)
+    }
+
+    /// Drop tokens whose request is no longer pending.
+    fn prune_progress_tokens(&mut self) {
+        let (client, server) = (&self.pending_spans, &self.server_spans);
+        self.progress_tokens.retain(|(direction, _), request_id| match direction {
+            StreamDirection::Inbound => server.contains_key(request_id),
+            _ => client.contains_key(request_id),
+        });
+    }
+}
+
+/// The direction a response's request travelled in.
+fn requester(response_direction: StreamDirection) -> StreamDirection {
+    match response_direction {
+        StreamDirection::Inbound => StreamDirection::Outbound,
+        _ => StreamDirection::Inbound,
+    }
+}
+
+/// Raised by the tap sequencer every so often (`--span-timeout-secs`): requests
+/// pending for longer than `params.timeout_ms` are logged as orphaned. Kept in
+/// the raw capture so `rederive` expires the same spans, but never logged itself.
+pub const SPAN_SWEEP_METHOD: &str = "sentinel/span_sweep";
+
+/// How much of a line that isn't a JSON-RPC message its `sentinel/unparsed`
+/// event keeps. The event itself is always logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum UnparsedCapture {
+    /// Only the size and the parse error, not the line
+    Off,
+    /// The line, cut at `--unparsed-max-bytes`
+    #[default]
+    Truncated,
+    Full,
+}
+
 /// Parser converts raw tapped bytes into structured MCP logs
 pub struct Parser {
     run_id: String,
-    session: Arc<Session>,
     log_tx: mpsc::Sender<McpLog>,
-    
+    metrics: Arc<Metrics>,
+    protocol_rules: RuleSet,
+    clock: Arc<dyn Clock>,
+    ids: Arc<dyn IdGen>,
+    detector: Option<Arc<Detector>>,
+    unparsed: UnparsedCapture,
+    unparsed_max_bytes: usize,
+    /// Longest message joined from several lines; 0 disables joining
+    max_reassembly_bytes: usize,
 
-    /// request_id -> (span_id, start_time)
-    pending_spans: HashMap<u64, (String, Instant)>,
+    /// State of the server the current event belongs to
+    current: ServerSession,
+    current_server: Option<Arc<str>>,
+    /// Every other server seen so far (`None`: events not tagged with a server)
+    others: HashMap<Option<Arc<str>>, ServerSession>,
+    /// Sessions of the named servers, by name
+    server_sessions: HashMap<String, Arc<Session>>,
 }
 
 impl Parser {
@@ -25,107 +185,1121 @@ impl Parser {
         run_id: String,
         log_tx: mpsc::Sender<McpLog>,
         session: Arc<Session>,
+        metrics: Arc<Metrics>,
+        protocol_rules: RuleSet,
     ) -> Self {
         Self {
             run_id,
-            session,
             log_tx,
-            pending_spans: HashMap::new(),
+            metrics,
+            protocol_rules,
+            clock: Arc::new(SystemClock),
+            ids: Arc::new(RandomIds),
+            detector: None,
+            unparsed: UnparsedCapture::default(),
+            unparsed_max_bytes: 4096,
+            max_reassembly_bytes: 0,
+            current: ServerSession::new(session, protocol_rules),
+            current_server: None,
+            others: HashMap::new(),
+            server_sessions: HashMap::new(),
         }
     }
-    
+
+    /// Sessions for the servers of a multiplexed run (see `run --servers`).
+    /// Taps tagged with a server name are parsed in that server's session;
+    /// untagged ones in the session passed to [`Parser::new`].
+    pub fn with_server_sessions(mut self, sessions: impl IntoIterator<Item = Arc<Session>>) -> Self {
+        self.server_sessions = sessions
+            .into_iter()
+            .filter_map(|s| Some((s.server_name.clone()?, s)))
+            .collect();
+        self
+    }
+
+    /// Replace the wall clock and span id source (used by `rederive`). The clock
+    /// is read exactly once per emitted log, in emission order.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>, ids: Arc<dyn IdGen>) -> Self {
+        self.clock = clock;
+        self.ids = ids;
+        self
+    }
+
+    /// Tag inbound payloads matching advisory detection rules.
+    pub fn with_detector(mut self, detector: Option<Arc<Detector>>) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// How much of a non-JSON-RPC line to keep; `max_bytes` applies to
+    /// [`UnparsedCapture::Truncated`].
+    pub fn with_unparsed(mut self, capture: UnparsedCapture, max_bytes: usize) -> Self {
+        self.unparsed = capture;
+        self.unparsed_max_bytes = max_bytes;
+        self
+    }
+
+    /// Join JSON messages a peer writes over several lines, up to `max_bytes`
+    /// each (0 leaves every line to be parsed on its own).
+    pub fn with_reassembly(mut self, max_bytes: usize) -> Self {
+        self.max_reassembly_bytes = max_bytes;
+        self
+    }
+
     pub async fn process_stream(
         mut self,
         mut tap_rx: mpsc::Receiver<TapEvent>,
     ) -> anyhow::Result<()> {
-        let mut expected_id = 1u64;
-            while let Some(evt) = tap_rx.recv().await {
-                if evt.event_id != expected_id {
-                    eprintln!(
-                        "⚠️  Warning: Missing event IDs. Expected {}, got {}",
-                        expected_id, evt.event_id
-                    );
+        while let Some(evt) = tap_rx.recv().await {
+            self.select_server(&evt.server);
+            let direction = evt.direction;
+            let bytes = evt.bytes.clone();
+
+            if direction == StreamDirection::Stderr {
+                let line = String::from_utf8_lossy(frame_payload(&bytes)).into_owned();
+                let log = McpLog::stderr_line(
+                    self.run_id.clone(),
+                    evt.observed_ts_ms,
+                    self.clock.now_ms(),
+                    line,
+                    &self.current.session.session_id,
+                    &self.current.session.trace_id,
+                    self.ids.next_id(),
+                );
+                self.emit(log).await;
+                continue;
+            }
+
+            // The rest of a message split over lines
+            let mut observed_ts_ms = evt.observed_ts_ms;
+            let mut bytes = bytes;
+            let mut reassembled = false;
+            if self.current.is_holding(direction) {
+                match self.reassemble(direction, &bytes, observed_ts_ms).await {
+                    Some((joined, first_ts_ms)) => {
+                        bytes = joined.into();
+                        observed_ts_ms = first_ts_ms;
+                        reassembled = true;
+                    }
+                    None => continue,
                 }
-                expected_id = evt.event_id + 1;
-                let direction = evt.direction;
-                let bytes = evt.bytes.clone();
-
-                let message: JsonRpcMessage = match serde_json::from_slice(&bytes) {
-                        Ok(m) => m,
-                        Err(_) => continue, // Ignore non-JSON
-                    };
-
-                match (&direction, &message) {
-                // ----------------------------
-                // Outbound REQUEST
-                // ----------------------------
-                (StreamDirection::Outbound, JsonRpcMessage::Request(req)) => {
-                    let span_id = Uuid::new_v4().to_string();
-                    let start = Instant::now();
-
-                    if let Some(request_id) = req.id {
-                        self.pending_spans.insert(request_id, (span_id.clone(), start));
+            }
+
+            let line = frame_payload(&bytes);
+            let value: Value = match serde_json::from_slice(line) {
+                Ok(v) => v,
+                Err(e) => {
+                    if direction != StreamDirection::Sentinel && !reassembled && self.max_reassembly_bytes > 0 {
+                        // The start of a message split over lines is held, not logged
+                        if self.reassemble(direction, &bytes, observed_ts_ms).await.is_none() {
+                            continue;
+                        }
                     }
+                    // Blank lines carry nothing worth recording
+                    if direction != StreamDirection::Sentinel && !line.trim_ascii().is_empty() {
+                        self.log_unparsed(direction, line, e.to_string(), observed_ts_ms, None).await;
+                    }
+                    continue;
+                }
+            };
+
+            if direction != StreamDirection::Sentinel {
+                self.check_protocol(direction, &value, observed_ts_ms).await;
+            }
 
-                let log = McpLog::from_message(
+            match value {
+                // A batch: one log per element, in order, sharing the line's timestamp
+                Value::Array(batch) if direction != StreamDirection::Sentinel => {
+                    let size = batch.len();
+                    for (index, value) in batch.into_iter().enumerate() {
+                        let position = BatchPosition { index, size };
+                        self.handle_message(direction, value, observed_ts_ms, Some(position)).await;
+                    }
+                }
+                value => self.handle_message(direction, value, observed_ts_ms, None).await,
+            }
+        }
+
+        // Nothing can complete a held message or answer a pending request now.
+        let current = self.current_server.clone();
+        for server in self.servers() {
+            self.select_server(&server);
+            self.flush_held().await;
+            self.orphan_pending("stream_ended", None).await;
+        }
+        self.select_server(&current);
+
+        let log = self.synthetic("sentinel/latency_summary", self.metrics.latency_summary());
+        self.emit(log).await;
+
+        Ok(())
+    }
+
+    /// Log one JSON-RPC message (or one element of a batch, at `batch`).
+    async fn handle_message(
+        &mut self,
+        direction: StreamDirection,
+        value: Value,
+        observed_ts_ms: u64,
+        batch: Option<BatchPosition>,
+    ) {
+        let message = match JsonRpcMessage::deserialize(&value) {
+            Ok(m) => m,
+            // JSON, but not a JSON-RPC message
+            Err(e) => {
+                if direction != StreamDirection::Sentinel {
+                    let raw = serde_json::to_vec(&value).unwrap_or_default();
+                    self.log_unparsed(direction, &raw, e.to_string(), observed_ts_ms, batch).await;
+                }
+                return;
+            }
+        };
+
+        match (&direction, &message) {
+            // ----------------------------
+            // Sentinel lifecycle event raised by the proxy
+            // ----------------------------
+            (StreamDirection::Sentinel, JsonRpcMessage::Notification(note)) if note.method == SPAN_SWEEP_METHOD => {
+                if let Some(timeout_ms) = note.params.get("timeout_ms").and_then(Value::as_u64) {
+                    self.orphan_all_servers("timeout", Some(observed_ts_ms.saturating_sub(timeout_ms))).await;
+                }
+            }
+
+            // Taps the audit pipeline had no room for: the audit writer records a
+            // GapMarker in place of an event. Neither the clock nor the span ids
+            // are drawn on, so rederive replays both in the same order.
+            (StreamDirection::Sentinel, JsonRpcMessage::Notification(note)) if note.method == TAP_GAP_METHOD => {
+                let session = &self.current.session;
+                let log = McpLog::synthetic(
                     self.run_id.clone(),
-                    evt.event_id,
-                    evt.observed_ts_ms,
+                    &note.method,
+                    note.params.clone(),
+                    &session.session_id,
+                    &session.trace_id,
+                    observed_ts_ms,
+                    String::new(),
+                );
+                let _ = self.log_tx.send(log).await;
+            }
+
+            (StreamDirection::Sentinel, JsonRpcMessage::Notification(note)) => {
+                // Violations the proxy refused to forward under --strict-protocol
+                if note.method == "sentinel/protocol_violation" {
+                    self.count_violations(&note.params);
+                }
+
+                let mut log = self.synthetic(&note.method, note.params.clone());
+                log.observed_ts_ms = observed_ts_ms;
+
+                self.emit(log).await;
+
+                // Requests the crashed child (or the closed connection, or the
+                // client that went away) never answered won't be answered now.
+                if let Some(reason @ ("child_restarted" | "connection_closed" | "client_detached")) =
+                    note.method.strip_prefix("sentinel/")
+                {
+                    self.flush_held().await;
+                    self.orphan_pending(reason, None).await;
+                    self.reset_session(reason).await;
+                }
+            }
+
+            // ----------------------------
+            // REQUEST: from the client (outbound) or the server (inbound)
+            // ----------------------------
+            (StreamDirection::Outbound | StreamDirection::Inbound, JsonRpcMessage::Request(req)) => {
+                if direction == StreamDirection::Outbound && req.method == "initialize" {
+                    self.reset_session("initialize").await;
+                    self.current.session.set_info(Some(SessionInfo::from_request(&req.params)));
+                }
+
+                if direction == StreamDirection::Outbound && req.method == "tools/call" {
+                    if let Some(tool) = req.params.get("name").and_then(|v| v.as_str()) {
+                        self.metrics.record_tool_call(tool);
+                    }
+                }
+
+                let span_id = self.ids.next_id();
+                let now = self.clock.now_ms();
+
+                if let Some(request_id) = req.id.clone().non_null() {
+                    let epoch = self.current.session.epoch();
+                    let progress_token = req
+                        .params
+                        .pointer("/_meta/progressToken")
+                        .and_then(|token| RequestId::deserialize(token).ok())
+                        .and_then(RequestId::non_null);
+                    let replaced = self.current.start(
+                        direction,
+                        request_id.clone(),
+                        PendingSpan {
+                            span_id: span_id.clone(),
+                            start_ms: now,
+                            method: req.method.clone(),
+                            epoch,
+                            progress_token,
+                        },
+                    );
+                    // A reused id: whichever request the response answers, the
+                    // earlier one can't be paired any more.
+                    if let Some(earlier) = replaced {
+                        self.orphan(direction, request_id, earlier, "id_reused").await;
+                    }
+                }
+
+                let mut log = McpLog::from_message(
+                    self.run_id.clone(),
+                    observed_ts_ms,
+                    now,
                     direction,
                     message,
                     None,
-                    &self.session.session_id,
-                    &self.session.trace_id,
+                    &self.current.session.session_id,
+                    &self.current.session.trace_id,
                     span_id,
-                    None, // parent_span_id (leave None unless you later model nesting)
+                    None, // a request starts its own span; notifications about it point here
                 );
-
-                    let _ = self.log_tx.send(log).await;
+                log.batch = batch;
+                if direction == StreamDirection::Inbound {
+                    self.detect(&mut log);
                 }
 
-                // ----------------------------
-                // Inbound RESPONSE
-                // ----------------------------
-                (StreamDirection::Inbound, JsonRpcMessage::Response(resp)) => {
-                let (span_id, latency_ms) = if let Some(id) = resp.id {
-                    if let Some((span, start)) = self.pending_spans.remove(&id) {
-                        (span, Some(start.elapsed().as_millis() as u64))
-                    } else {
-                        (Uuid::new_v4().to_string(), None)
+                self.emit(log).await;
+            }
+
+            // ----------------------------
+            // RESPONSE: from the server (inbound) or the client (outbound)
+            // ----------------------------
+            (StreamDirection::Outbound | StreamDirection::Inbound, JsonRpcMessage::Response(resp)) => {
+                // Spans from an earlier session epoch must never pair with a new response.
+                let epoch = self.current.session.epoch();
+                let now = self.clock.now_ms();
+                let request_id = Some(&resp.id).filter(|id| !id.is_null());
+                let pending = request_id
+                    .and_then(|id| self.current.finish(requester(direction), id))
+                    .filter(|p| p.epoch == epoch);
+
+                let (span_id, latency_ms, method, pairing) = match pending {
+                    Some(p) => {
+                        let latency_ms = now.saturating_sub(p.start_ms);
+                        self.metrics.record_latency(&p.method, latency_ms);
+                        (p.span_id, Some(latency_ms), Some(p.method), Pairing::Matched)
                     }
-                } else {
-                    (Uuid::new_v4().to_string(), None)
+                    None => match request_id.and_then(|id| self.current.take_cancelled(requester(direction), id)) {
+                        // The request was cancelled; the answer came anyway
+                        Some(span_id) => (span_id, None, None, Pairing::LateResponse),
+                        // Unknown id, or a request from an earlier session epoch
+                        None => (self.ids.next_id(), None, None, Pairing::Unmatched),
+                    },
+                };
+                // The server's half of the handshake
+                let initialize_result = match method.as_deref() {
+                    Some("initialize") if direction == StreamDirection::Inbound => resp.result.clone(),
+                    _ => None,
                 };
 
-                let log = McpLog::from_message(
+                let mut log = McpLog::from_message(
                     self.run_id.clone(),
-                    evt.event_id,
-                    evt.observed_ts_ms,
+                    observed_ts_ms,
+                    now,
                     direction,
                     message,
                     latency_ms,
-                    &self.session.session_id,
-                    &self.session.trace_id,
+                    &self.current.session.session_id,
+                    &self.current.session.trace_id,
                     span_id,
                     None, // IMPORTANT: response is not its own parent
                 );
+                log.batch = batch;
+                log.pairing = Some(pairing);
+                if method.as_deref() == Some("tools/call") {
+                    log.set_tool_result();
+                }
+                if direction == StreamDirection::Inbound {
+                    self.detect(&mut log);
+                }
 
-                let _ = self.log_tx.send(log).await;
+                self.emit(log).await;
+
+                if let Some(result) = initialize_result {
+                    self.record_session_info(&result, observed_ts_ms).await;
+                }
+            }
+
+            // ----------------------------
+            // NOTIFICATION (either direction): no id, never answered
+            // ----------------------------
+            (StreamDirection::Outbound | StreamDirection::Inbound, JsonRpcMessage::Notification(note)) => {
+                if direction == StreamDirection::Outbound && note.method == "notifications/initialized" {
+                    if let Some(mut info) = self.current.session.info() {
+                        info.phase = SessionPhase::Operating;
+                        self.current.session.set_info(Some(info));
+                    }
+                }
+                let now = self.clock.now_ms();
+                // A cancellation closes the sender's pending request
+                let cancelled = match note.method.as_str() {
+                    "notifications/cancelled" => self.cancel(direction, &note.params),
+                    _ => None,
+                };
+                let (parent_span_id, latency_ms) = match &cancelled {
+                    Some(p) => (Some(p.span_id.clone()), Some(now.saturating_sub(p.start_ms))),
+                    None => (self.originating_span(direction, &note.method, &note.params), None),
+                };
+                let span_id = self.ids.next_id();
+
+                let mut log = McpLog::from_message(
+                    self.run_id.clone(),
+                    observed_ts_ms,
+                    now,
+                    direction,
+                    message,
+                    latency_ms,
+                    &self.current.session.session_id,
+                    &self.current.session.trace_id,
+                    span_id,
+                    parent_span_id,
+                );
+                log.batch = batch;
+                log.cancelled = cancelled.is_some();
+                if direction == StreamDirection::Inbound {
+                    self.detect(&mut log);
+                }
+
+                self.emit(log).await;
             }
 
             _ => {}
         }
+    }
+
+    /// Make `server`'s state current, creating it on its first event.
+    fn select_server(&mut self, server: &Option<Arc<str>>) {
+        if *server == self.current_server {
+            return;
+        }
+        let next = match self.others.remove(server) {
+            Some(state) => state,
+            None => {
+                let name = server.as_deref().unwrap_or_default();
+                let session = self.server_sessions.get(name).cloned().unwrap_or_else(|| {
+                    // Not announced up front: give it a session of its own in the run's trace.
+                    let trace_id = self.current.session.trace_id.clone();
+                    Arc::new(Session::new(Uuid::new_v4().to_string(), trace_id).with_server_name(name))
+                });
+                ServerSession::new(session, self.protocol_rules)
+            }
+        };
+        let previous = std::mem::replace(&mut self.current, next);
+        let previous_server = std::mem::replace(&mut self.current_server, server.clone());
+        self.others.insert(previous_server, previous);
+    }
+
+    /// Span of the request a notification is about, while it is still pending:
+    /// the other side's request that asked for progress under this `progressToken`.
+    fn originating_span(&mut self, direction: StreamDirection, method: &str, params: &Value) -> Option<String> {
+        let epoch = self.current.session.epoch();
+        let pending = match method {
+            "notifications/progress" => {
+                let token = RequestId::deserialize(params.get("progressToken")?).ok()?;
+                self.current.by_progress_token(requester(direction), &token)
+            }
+            _ => None,
+        };
+        pending.filter(|p| p.epoch == epoch).map(|p| p.span_id.clone())
+    }
+
+    /// Close the pending request a `notifications/cancelled` sent in `direction`
+    /// names, remembering its span for a response that still arrives.
+    fn cancel(&mut self, direction: StreamDirection, params: &Value) -> Option<PendingSpan> {
+        let epoch = self.current.session.epoch();
+        let request_id = RequestId::deserialize(params.get("requestId")?).ok()?;
+        let pending = self.current.finish(direction, &request_id).filter(|p| p.epoch == epoch)?;
+        self.current.remember_cancelled(direction, request_id, pending.span_id.clone());
+        Some(pending)
+    }
+
+    async fn emit(&self, mut log: McpLog) {
+        log.server_name = self.current.session.server_name.clone();
+        log.session_epoch = Some(self.current.session.epoch());
+        let _ = self.log_tx.send(log).await;
+    }
+
+    /// Record forwarded messages that break an enabled JSON-RPC rule. They still
+    /// reach the peer (enforcement is the proxy's job under `--strict-protocol`).
+    async fn check_protocol(&mut self, direction: StreamDirection, value: &Value, observed_ts_ms: u64) {
+        let violations = self.current.validator.validate(direction, value);
+        if violations.is_empty() {
+            return;
         }
 
-        Ok(())
+        let codes: Vec<&str> = violations.iter().map(|r| r.code()).collect();
+        let params = json!({
+            "direction": direction,
+            "request_id": value.get("id"),
+            "method": value.get("method"),
+            "violations": codes,
+            "action": "forwarded",
+        });
+        self.count_violations(&params);
+
+        let mut log = self.synthetic("sentinel/protocol_violation", params);
+        log.observed_ts_ms = observed_ts_ms;
+
+        self.emit(log).await;
+    }
+
+    fn count_violations(&self, params: &Value) {
+        let codes = params.get("violations").and_then(Value::as_array);
+        for code in codes.into_iter().flatten().filter_map(Value::as_str) {
+            self.metrics.record_protocol_violation(code);
+        }
+    }
+
+    /// Advisory only: the payload is tagged and counted, never changed.
+    fn detect(&self, log: &mut McpLog) {
+        let Some(detector) = &self.detector else {
+            return;
+        };
+        let hits = detector.scan(&log.payload);
+        if hits.is_empty() {
+            return;
+        }
+        for rule in &hits {
+            self.metrics.record_detection(rule);
+        }
+        eprintln!(
+            "⚠️  Detection on inbound {} (request_id {}): {}",
+            log.method.as_deref().unwrap_or("response"),
+            log.request_id.as_ref().map_or_else(|| "-".to_string(), |id| id.to_string()),
+            hits.join(", ")
+        );
+        log.detections = Some(hits);
+    }
+
+    /// Complete the session's handshake from the `initialize` result and log
+    /// it as `sentinel/session_info`.
+    async fn record_session_info(&mut self, result: &Value, observed_ts_ms: u64) {
+        let mut info = self
+            .current
+            .session
+            .info()
+            .unwrap_or_else(|| SessionInfo::from_request(&Value::Null));
+        info.answered(result);
+        self.current.session.set_info(Some(info.clone()));
+
+        let mut log = self.synthetic("sentinel/session_info", serde_json::to_value(&info).unwrap_or_default());
+        log.observed_ts_ms = observed_ts_ms;
+        self.emit(log).await;
+    }
+
+    /// Record a line (or batch element) that isn't a JSON-RPC message as
+    /// `sentinel/unparsed`, so garbage on the wire still reaches the audit log.
+    async fn log_unparsed(
+        &mut self,
+        direction: StreamDirection,
+        raw: &[u8],
+        error: String,
+        observed_ts_ms: u64,
+        batch: Option<BatchPosition>,
+    ) {
+        let mut payload = json!({
+            "direction": direction,
+            "bytes": raw.len(),
+            "error": error,
+        });
+        let kept = match self.unparsed {
+            UnparsedCapture::Off => None,
+            UnparsedCapture::Truncated => Some(&raw[..raw.len().min(self.unparsed_max_bytes)]),
+            UnparsedCapture::Full => Some(raw),
+        };
+        if let Some(kept) = kept {
+            payload["raw"] = json!(String::from_utf8_lossy(kept));
+            payload["truncated"] = json!(kept.len() < raw.len());
+        }
+
+        let mut log = self.synthetic("sentinel/unparsed", payload);
+        log.observed_ts_ms = observed_ts_ms;
+        log.batch = batch;
+        self.emit(log).await;
+    }
+
+    fn synthetic(&self, method: &str, payload: Value) -> McpLog {
+        McpLog::synthetic(
+            self.run_id.clone(),
+            method,
+            payload,
+            &self.current.session.session_id,
+            &self.current.session.trace_id,
+            self.clock.now_ms(),
+            self.ids.next_id(),
+        )
+    }
+
+    /// Every server seen so far, in name order.
+    fn servers(&self) -> Vec<Option<Arc<str>>> {
+        let mut servers: Vec<Option<Arc<str>>> = self.others.keys().cloned().collect();
+        servers.push(self.current_server.clone());
+        servers.sort();
+        servers
+    }
+
+    /// Feed a line to the direction's reassembler. Returns the bytes to parse:
+    /// the line itself, or a whole message that was split over lines, with the
+    /// time its first line was observed. `None` while the message is incomplete,
+    /// and when it outgrew `--max-reassembly-bytes` (then it is logged unparsed).
+    async fn reassemble(&mut self, direction: StreamDirection, line: &[u8], observed_ts_ms: u64) -> Option<(Vec<u8>, u64)> {
+        let max_bytes = self.max_reassembly_bytes;
+        let reassembler = self
+            .current
+            .reassemblers
+            .entry(direction)
+            .or_insert_with(|| Reassembler::new(max_bytes));
+        match reassembler.push(line, observed_ts_ms) {
+            Step::Pass => Some((line.to_vec(), observed_ts_ms)),
+            Step::Hold => None,
+            Step::Complete { bytes, observed_ts_ms } => Some((bytes, observed_ts_ms)),
+            Step::Overflow { bytes, observed_ts_ms } => {
+                let error = format!("message split over lines exceeds {} bytes", max_bytes);
+                self.log_unparsed(direction, frame_payload(&bytes), error, observed_ts_ms, None).await;
+                None
+            }
+        }
+    }
+
+    /// Log the incomplete messages held for the current server as unparsed.
+    async fn flush_held(&mut self) {
+        for direction in [StreamDirection::Outbound, StreamDirection::Inbound] {
+            let held = self.current.reassemblers.get_mut(&direction).and_then(Reassembler::take);
+            if let Some((bytes, observed_ts_ms)) = held {
+                let error = "incomplete message split over lines".to_string();
+                self.log_unparsed(direction, frame_payload(&bytes), error, observed_ts_ms, None).await;
+            }
+        }
+    }
+
+    /// [`Parser::orphan_pending`] for every server, in name order.
+    async fn orphan_all_servers(&mut self, reason: &str, started_before: Option<u64>) {
+        let current = self.current_server.clone();
+        for server in self.servers() {
+            self.select_server(&server);
+            self.orphan_pending(reason, started_before).await;
+        }
+        self.select_server(&current);
+    }
+
+    /// Close pending spans without a response (client requests first, then the
+    /// server's), logging each as orphaned on its own span: all of them, or those
+    /// started before `started_before` (ms).
+    async fn orphan_pending(&mut self, reason: &str, started_before: Option<u64>) {
+        for direction in [StreamDirection::Outbound, StreamDirection::Inbound] {
+            let mut pending: Vec<(RequestId, PendingSpan)> = self
+                .current
+                .spans(direction)
+                .extract_if(|_, p| started_before.is_none_or(|t| p.start_ms < t))
+                .collect();
+            self.current.prune_progress_tokens();
+            pending.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (request_id, p) in pending {
+                self.orphan(direction, request_id, p, reason).await;
+            }
+        }
+    }
+
+    /// Log a request that will never be answered, on its own span.
+    async fn orphan(&mut self, direction: StreamDirection, request_id: RequestId, p: PendingSpan, reason: &str) {
+        let now = self.clock.now_ms();
+        let mut log = McpLog::synthetic(
+            self.run_id.clone(),
+            "sentinel/orphaned_request",
+            json!({
+                "request_id": request_id,
+                "direction": direction,
+                "method": p.method,
+                "pending_ms": now.saturating_sub(p.start_ms),
+                "reason": reason,
+            }),
+            &self.current.session.session_id,
+            &self.current.session.trace_id,
+            now,
+            p.span_id,
+        );
+        log.request_id = Some(request_id);
+        self.emit(log).await;
     }
+
+    /// Start a new session epoch and discard per-session state left over from the
+    /// previous one, recording what was dropped (counts only).
+    async fn reset_session(&mut self, reason: &str) {
+        let previous_epoch = self.current.session.epoch();
+        let epoch = self.current.session.advance_epoch();
+        // A new session starts with a new handshake
+        self.current.session.set_info(None);
+
+        let before = self.current.pending_spans.len() + self.current.server_spans.len();
+        self.current.pending_spans.retain(|_, p| p.epoch == epoch);
+        self.current.server_spans.retain(|_, p| p.epoch == epoch);
+        self.current.prune_progress_tokens();
+        self.current.cancelled.clear();
+        let discarded_spans = before - self.current.pending_spans.len() - self.current.server_spans.len();
+
+        if discarded_spans == 0 {
+            return;
+        }
+
+        let log = self.synthetic(
+            "sentinel/session_state_cleared",
+            json!({
+                "reason": reason,
+                "previous_epoch": previous_epoch,
+                "epoch": epoch,
+                "discarded": {
+                    "pending_spans": discarded_spans,
+                },
+            }),
+        );
+
+        self.emit(log).await;
+    }
+}
+
+/// Strip a UTF-8 BOM and trailing CR/LF from a tapped line before parsing.
+/// Only the parser's view is normalized; the forwarded bytes are never touched.
+fn frame_payload(bytes: &[u8]) -> &[u8] {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let end = bytes
+        .iter()
+        .rposition(|b| !matches!(b, b'\r' | b'\n'))
+        .map_or(0, |i| i + 1);
+    &bytes[..end]
 }
 
-fn current_timestamp() -> u64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::MessageRole;
+    use proptest::prelude::*;
 
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64
+    /// Parse `messages` as one stream and return the responses it logged.
+    async fn responses(messages: &[(StreamDirection, Value)]) -> Vec<McpLog> {
+        parse(messages)
+            .await
+            .into_iter()
+            .filter(|log| log.payload.get("result").is_some() || log.payload.get("error").is_some())
+            .collect()
+    }
+
+    /// Parse `messages` as one stream and return everything it logged.
+    async fn parse(messages: &[(StreamDirection, Value)]) -> Vec<McpLog> {
+        let lines = messages.iter().map(|(direction, message)| (*direction, serde_json::to_vec(message).unwrap()));
+        parse_lines(lines.collect(), 0).await
+    }
+
+    /// Parse tapped `lines` as one stream, joining messages split over lines up
+    /// to `max_reassembly_bytes`, and return everything it logged.
+    async fn parse_lines(lines: Vec<(StreamDirection, Vec<u8>)>, max_reassembly_bytes: usize) -> Vec<McpLog> {
+        let (tap_tx, tap_rx) = mpsc::channel(16);
+        let (log_tx, mut log_rx) = mpsc::channel(16);
+        let session = Arc::new(Session::new("session".to_string(), "trace".to_string()));
+        let parser = Parser::new("run".to_string(), log_tx, session, Arc::new(Metrics::new(10)), RuleSet::default())
+            .with_reassembly(max_reassembly_bytes);
+        let parsing = tokio::spawn(parser.process_stream(tap_rx));
+        let collecting = tokio::spawn(async move {
+            let mut logs = Vec::new();
+            while let Some(log) = log_rx.recv().await {
+                logs.push(log);
+            }
+            logs
+        });
+        for (seq, (direction, bytes)) in lines.into_iter().enumerate() {
+            let tap = TapEvent {
+                seq: seq as u64 + 1,
+                direction,
+                bytes: bytes.into(),
+                observed_ts_ms: 1_000 + seq as u64,
+                server: None,
+            };
+            tap_tx.send(tap).await.unwrap();
+        }
+        drop(tap_tx);
+
+        parsing.await.unwrap().unwrap();
+        collecting.await.unwrap()
+    }
+
+    fn call(id: u64, method: &str) -> (StreamDirection, Value) {
+        let params = json!({ "name": "read_file", "arguments": { "path": "/tmp/x" } });
+        (StreamDirection::Outbound, json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+    }
+
+    fn reply(id: u64, result: Value) -> (StreamDirection, Value) {
+        (StreamDirection::Inbound, json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn text(text: &str) -> Value {
+        json!([{ "type": "text", "text": text }])
+    }
+
+    #[tokio::test]
+    async fn a_tool_result_records_whether_the_tool_failed() {
+        let logs = responses(&[
+            call(1, "tools/call"),
+            reply(1, json!({ "content": text("ENOENT: no such file"), "isError": true })),
+            call(2, "tools/call"),
+            reply(2, json!({ "content": text("hello"), "isError": false })),
+            call(3, "tools/call"),
+            reply(3, json!({ "content": text("hello") })),
+        ])
+        .await;
+        let outcomes: Vec<_> = logs.iter().map(|l| l.tool_is_error).collect();
+        assert_eq!(outcomes, [Some(true), Some(false), Some(false)]);
+        assert_eq!(logs[0].result_bytes, Some(text("ENOENT: no such file").to_string().len() as u64));
+    }
+
+    #[tokio::test]
+    async fn a_2_mb_tool_result_is_measured_whole() {
+        let content = text(&"x".repeat(2 * 1024 * 1024));
+        let size = serde_json::to_vec(&content).unwrap().len() as u64;
+        let logs = responses(&[call(1, "tools/call"), reply(1, json!({ "content": content }))]).await;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].result_bytes, Some(size));
+        assert!(size > 2 * 1024 * 1024);
+        assert_eq!(logs[0].tool_is_error, Some(false));
+    }
+
+    #[tokio::test]
+    async fn only_tool_call_results_carry_the_tool_fields() {
+        let logs = responses(&[
+            call(1, "resources/read"),
+            reply(1, json!({ "contents": [], "content": text("not a tool"), "isError": true })),
+            call(2, "tools/call"),
+            (
+                StreamDirection::Inbound,
+                json!({ "jsonrpc": "2.0", "id": 2, "error": { "code": -32602, "message": "Unknown tool" } }),
+            ),
+            // No request to pair with: the method is unknown.
+            reply(3, json!({ "content": text("hello"), "isError": true })),
+        ])
+        .await;
+        assert_eq!(logs.len(), 3);
+        for log in &logs {
+            assert_eq!((log.tool_is_error, log.result_bytes), (None, None), "{}", log.payload);
+        }
+    }
+
+    fn cancel(id: u64) -> (StreamDirection, Value) {
+        let params = json!({ "requestId": id, "reason": "user aborted" });
+        (StreamDirection::Outbound, json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": params }))
+    }
+
+    fn by_method<'a>(logs: &'a [McpLog], method: &str) -> Vec<&'a McpLog> {
+        logs.iter().filter(|log| log.method.as_deref() == Some(method)).collect()
+    }
+
+    #[tokio::test]
+    async fn a_response_after_its_cancellation_is_late_and_shares_the_span() {
+        let logs = parse(&[call(1, "tools/call"), cancel(1), reply(1, json!({ "content": text("done") }))]).await;
+        let request = by_method(&logs, "tools/call")[0];
+        let cancellation = by_method(&logs, "notifications/cancelled")[0];
+        assert!(cancellation.cancelled);
+        assert_eq!(cancellation.parent_span_id.as_ref(), Some(&request.span_id));
+        assert!(cancellation.latency_ms.is_some(), "timed until the cancellation");
+
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!(response.pairing, Some(Pairing::LateResponse));
+        assert_eq!(response.latency_ms, None);
+        assert_eq!(response.span_id, request.span_id);
+        assert!(by_method(&logs, "sentinel/orphaned_request").is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_cancellation_after_the_response_closes_nothing() {
+        let logs = parse(&[call(1, "tools/call"), reply(1, json!({ "content": text("done") })), cancel(1)]).await;
+        let request = by_method(&logs, "tools/call")[0];
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!(response.pairing, Some(Pairing::Matched));
+        assert_eq!(response.span_id, request.span_id);
+
+        let cancellation = by_method(&logs, "notifications/cancelled")[0];
+        assert!(!cancellation.cancelled);
+        assert_eq!(cancellation.parent_span_id, None);
+        assert!(by_method(&logs, "sentinel/orphaned_request").is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_request_never_answered_is_orphaned_when_the_stream_ends() {
+        let logs = parse(&[call(1, "tools/call"), call(2, "tools/call"), reply(2, json!({}))]).await;
+        let request = by_method(&logs, "tools/call")[0];
+        let orphaned = by_method(&logs, "sentinel/orphaned_request");
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(orphaned[0].span_id, request.span_id);
+        assert_eq!(orphaned[0].payload["method"], "tools/call");
+        assert_eq!(orphaned[0].payload["reason"], "stream_ended");
+    }
+
+    /// A 100 KB `tools/call` response whose text is full of what a JSON scanner
+    /// could trip on: braces, brackets, escaped quotes and backslashes.
+    fn large_response() -> Value {
+        let text = r#"a{b}"c\d[e] "#.repeat(100 * 1024 / 13);
+        json!({ "jsonrpc": "2.0", "id": 1, "result": { "content": [{ "type": "text", "text": text }] } })
+    }
+
+    /// The tapped lines of `response` cut at `offsets`, after the request.
+    fn split_at(response: &Value, offsets: &[usize]) -> Vec<(StreamDirection, Vec<u8>)> {
+        let mut bytes = serde_json::to_vec(response).unwrap();
+        bytes.push(b'\n');
+        let mut offsets: Vec<usize> = offsets.iter().map(|o| o % (bytes.len() - 1) + 1).collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        let request = serde_json::to_vec(&call(1, "tools/call").1).unwrap();
+        let mut lines = vec![(StreamDirection::Outbound, request)];
+        let mut start = 0;
+        for end in offsets.into_iter().chain([bytes.len()]) {
+            lines.push((StreamDirection::Inbound, bytes[start..end].to_vec()));
+            start = end;
+        }
+        lines
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn a_100_kb_response_split_anywhere_is_logged_once_and_whole(
+            offsets in proptest::collection::vec(any::<usize>(), 1..12),
+        ) {
+            let response = large_response();
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            let logs = runtime.block_on(parse_lines(split_at(&response, &offsets), 1024 * 1024));
+
+            let unparsed = by_method(&logs, "sentinel/unparsed");
+            prop_assert!(unparsed.is_empty(), "{:?}", unparsed.first().map(|l| &l.payload));
+            let responses: Vec<&McpLog> = logs.iter().filter(|log| log.pairing.is_some()).collect();
+            prop_assert_eq!(responses.len(), 1);
+            prop_assert_eq!(responses[0].pairing, Some(Pairing::Matched));
+            prop_assert_eq!(&responses[0].payload, &response);
+            // Timed from the first piece
+            prop_assert_eq!(responses[0].observed_ts_ms, 1_001);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_split_response_over_the_reassembly_limit_is_logged_unparsed() {
+        let response = large_response();
+        let logs = parse_lines(split_at(&response, &[1_000, 50_000]), 64 * 1024).await;
+        assert!(logs.iter().all(|log| log.pairing.is_none()));
+        let unparsed = by_method(&logs, "sentinel/unparsed");
+        assert!(!unparsed.is_empty());
+        assert!(unparsed[0].payload["error"].as_str().unwrap().contains("exceeds 65536 bytes"), "{}", unparsed[0].payload);
+    }
+
+    fn request(direction: StreamDirection, id: Value, method: &str) -> (StreamDirection, Value) {
+        (direction, json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": {} }))
+    }
+
+    fn answer(direction: StreamDirection, id: Value) -> (StreamDirection, Value) {
+        (direction, json!({ "jsonrpc": "2.0", "id": id, "result": {} }))
+    }
+
+    /// The span of the request logged with `method`.
+    fn span_of(logs: &[McpLog], method: &str) -> String {
+        by_method(logs, method)[0].span_id.clone()
+    }
+
+    #[tokio::test]
+    async fn numeric_and_string_ids_that_look_alike_pair_separately() {
+        use StreamDirection::{Inbound, Outbound};
+        let logs = parse(&[
+            request(Outbound, json!(1), "tools/call"),
+            request(Outbound, json!("1"), "resources/read"),
+            request(Outbound, json!("req-42"), "tools/list"),
+            answer(Inbound, json!("req-42")),
+            answer(Inbound, json!(1)),
+            answer(Inbound, json!("1")),
+        ])
+        .await;
+
+        let responses: Vec<&McpLog> = logs.iter().filter(|log| log.pairing.is_some()).collect();
+        let ids: Vec<_> = responses.iter().map(|log| log.request_id.clone()).collect();
+        assert_eq!(
+            ids,
+            [
+                Some(RequestId::String("req-42".into())),
+                Some(RequestId::Number(1)),
+                Some(RequestId::String("1".into())),
+            ]
+        );
+        let spans: Vec<&str> = responses.iter().map(|log| log.span_id.as_str()).collect();
+        assert_eq!(spans, [span_of(&logs, "tools/list"), span_of(&logs, "tools/call"), span_of(&logs, "resources/read")]);
+        assert!(responses.iter().all(|log| log.pairing == Some(Pairing::Matched) && log.latency_ms.is_some()));
+        assert!(by_method(&logs, "sentinel/orphaned_request").is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_and_server_requests_with_the_same_ids_pair_within_their_direction() {
+        use StreamDirection::{Inbound, Outbound};
+        let logs = parse(&[
+            request(Outbound, json!(1), "tools/call"),
+            request(Inbound, json!(1), "sampling/createMessage"),
+            request(Outbound, json!(2), "resources/read"),
+            request(Inbound, json!(2), "roots/list"),
+            // The client answers the server's 1 before the server answers its 1.
+            answer(Outbound, json!(1)),
+            answer(Inbound, json!(2)),
+            answer(Inbound, json!(1)),
+            answer(Outbound, json!(2)),
+        ])
+        .await;
+
+        let responses: Vec<(StreamDirection, &str)> = logs
+            .iter()
+            .filter(|log| log.pairing == Some(Pairing::Matched))
+            .map(|log| (log.direction, log.span_id.as_str()))
+            .collect();
+        assert_eq!(
+            responses,
+            [
+                (Outbound, span_of(&logs, "sampling/createMessage").as_str()),
+                (Inbound, span_of(&logs, "resources/read").as_str()),
+                (Inbound, span_of(&logs, "tools/call").as_str()),
+                (Outbound, span_of(&logs, "roots/list").as_str()),
+            ]
+        );
+        assert_eq!(logs.iter().filter(|log| log.pairing.is_some()).count(), 4);
+        assert!(by_method(&logs, "sentinel/orphaned_request").is_empty());
+    }
+
+    /// The server cancelling its own request works like the client cancelling
+    /// one, in either order against the client's answer.
+    #[tokio::test]
+    async fn a_server_cancelling_its_request_closes_it_in_either_order() {
+        use StreamDirection::{Inbound, Outbound};
+        let cancel_inbound = |id: u64| {
+            (Inbound, json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": { "requestId": id } }))
+        };
+
+        let logs = parse(&[request(Inbound, json!(1), "sampling/createMessage"), cancel_inbound(1), answer(Outbound, json!(1))]).await;
+        let request_span = span_of(&logs, "sampling/createMessage");
+        let cancellation = by_method(&logs, "notifications/cancelled")[0];
+        assert!(cancellation.cancelled);
+        assert_eq!(cancellation.parent_span_id.as_ref(), Some(&request_span));
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!((response.pairing, &response.span_id), (Some(Pairing::LateResponse), &request_span));
+
+        let logs = parse(&[request(Inbound, json!(1), "sampling/createMessage"), answer(Outbound, json!(1)), cancel_inbound(1)]).await;
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!(response.pairing, Some(Pairing::Matched));
+        assert!(!by_method(&logs, "notifications/cancelled")[0].cancelled);
+
+        // A cancellation for the other side's request closes nothing.
+        let logs = parse(&[request(Outbound, json!(1), "tools/call"), cancel_inbound(1), answer(Inbound, json!(1))]).await;
+        assert!(!by_method(&logs, "notifications/cancelled")[0].cancelled);
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!(response.pairing, Some(Pairing::Matched));
+    }
+
+    /// Each kind of message on each pipe gets its own role, and keeps the pipe
+    /// it came on as its direction.
+    #[tokio::test]
+    async fn every_role_is_classified_from_kind_and_pipe() {
+        use StreamDirection::{Inbound, Outbound};
+        let notification = |direction, method: &str| (direction, json!({ "jsonrpc": "2.0", "method": method }));
+        let logs = parse(&[
+            request(Outbound, json!(1), "tools/call"),
+            answer(Inbound, json!(1)),
+            request(Inbound, json!(1), "sampling/createMessage"),
+            answer(Outbound, json!(1)),
+            notification(Outbound, "notifications/initialized"),
+            notification(Inbound, "notifications/tools/list_changed"),
+        ])
+        .await;
+
+        let roles: Vec<(StreamDirection, Option<MessageRole>)> = logs
+            .iter()
+            .filter(|log| log.direction != StreamDirection::Sentinel)
+            .map(|log| (log.direction, log.role))
+            .collect();
+        assert_eq!(
+            roles,
+            [
+                (Outbound, Some(MessageRole::ClientRequest)),
+                (Inbound, Some(MessageRole::ServerResponse)),
+                (Inbound, Some(MessageRole::ServerRequest)),
+                (Outbound, Some(MessageRole::ClientResponse)),
+                (Outbound, Some(MessageRole::ClientNotification)),
+                (Inbound, Some(MessageRole::ServerNotification)),
+            ]
+        );
+    }
+
+    /// A request left pending across a restart can't be answered by the
+    /// response to a new request that reuses its id.
+    #[tokio::test]
+    async fn a_request_pending_across_a_restart_is_not_answered_by_a_reused_id() {
+        use StreamDirection::{Inbound, Outbound, Sentinel};
+        let restarted = (Sentinel, json!({ "jsonrpc": "2.0", "method": "sentinel/child_restarted", "params": {} }));
+        let logs = parse(&[
+            request(Inbound, json!(7), "sampling/createMessage"),
+            restarted,
+            request(Inbound, json!(7), "roots/list"),
+            answer(Outbound, json!(7)),
+        ])
+        .await;
+
+        let orphaned = by_method(&logs, "sentinel/orphaned_request");
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(orphaned[0].payload["method"], "sampling/createMessage");
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!(response.pairing, Some(Pairing::Matched));
+        assert_eq!(response.span_id, span_of(&logs, "roots/list"));
+        assert_ne!(response.session_epoch, by_method(&logs, "sampling/createMessage")[0].session_epoch);
+    }
+
+    /// A new `initialize` discards requests of the old session, says how many,
+    /// and a reused id then pairs only with the new request.
+    #[tokio::test]
+    async fn a_new_session_discards_pending_requests_of_the_old_one() {
+        use StreamDirection::{Inbound, Outbound};
+        let logs = parse(&[
+            request(Outbound, json!(1), "tools/call"),
+            request(Outbound, json!(0), "initialize"),
+            request(Outbound, json!(1), "resources/read"),
+            answer(Inbound, json!(1)),
+        ])
+        .await;
+
+        let cleared = by_method(&logs, "sentinel/session_state_cleared");
+        assert_eq!(cleared.len(), 1);
+        assert_eq!(cleared[0].payload["reason"], "initialize");
+        assert_eq!(cleared[0].payload["discarded"]["pending_spans"], 1);
+        let response = logs.iter().find(|log| log.pairing.is_some()).unwrap();
+        assert_eq!(response.pairing, Some(Pairing::Matched));
+        assert_eq!(response.span_id, span_of(&logs, "resources/read"));
+    }
+
+    /// Streams from a Windows-hosted server (a BOM on the first line, CRLF
+    /// line endings) log every message, the first `initialize` included.
+    #[tokio::test]
+    async fn bom_and_crlf_streams_are_logged_whole() {
+        let lines = |direction, name: &str| -> Vec<(StreamDirection, Vec<u8>)> {
+            let path = format!("{}/tests/fixtures/crlf/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let bytes = std::fs::read(path).unwrap();
+            bytes.split_inclusive(|b| *b == b'\n').map(|line| (direction, line.to_vec())).collect()
+        };
+        let client = lines(StreamDirection::Outbound, "client.jsonl");
+        let server = lines(StreamDirection::Inbound, "server.jsonl");
+        assert!(client[0].1.starts_with(b"\xEF\xBB\xBF") && server[0].1.starts_with(b"\xEF\xBB\xBF"));
+        assert!(client.iter().chain(&server).all(|(_, line)| line.ends_with(b"\r\n")));
+        // initialize and its result, then the rest of each side
+        let stream = vec![client[0].clone(), server[0].clone(), client[1].clone(), client[2].clone(), server[1].clone()];
+        let logs = parse_lines(stream, 0).await;
+
+        assert!(by_method(&logs, "sentinel/unparsed").is_empty());
+        assert_eq!(by_method(&logs, "initialize").len(), 1);
+        assert_eq!(by_method(&logs, "notifications/initialized").len(), 1);
+        assert_eq!(by_method(&logs, "tools/list").len(), 1);
+        assert_eq!(logs.iter().filter(|log| log.pairing == Some(Pairing::Matched)).count(), 2);
+    }
 }
@@ -1,9 +1,55 @@
+use crate::events::StreamDirection;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A JSON-RPC id as sent: a number, a string or `null`. Serialized as the bare
+/// JSON value, so `7` and `"7"` stay distinct ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+    #[default]
+    Null,
+}
+
+impl RequestId {
+    pub fn is_null(&self) -> bool {
+        matches!(self, RequestId::Null)
+    }
+
+    /// `None` for `null`, which answers nothing in particular.
+    pub fn non_null(self) -> Option<Self> {
+        (!self.is_null()).then_some(self)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestId::Number(n) => write!(f, "{}", n),
+            RequestId::String(s) => write!(f, "{:?}", s),
+            RequestId::Null => f.write_str("null"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcRequest {
     pub jsonrpc: String,
-    pub id: Option<u64>,
+    pub id: RequestId,
+    pub method: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+/// A request without an `id`: nothing answers it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
     pub method: String,
     #[serde(default)]
     pub params: serde_json::Value,
@@ -20,16 +66,348 @@ pub struct JsonRpcError {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcResponse {
     pub jsonrpc: String,
-    pub id: Option<u64>,
+    #[serde(default)]
+    pub id: RequestId,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<JsonRpcError>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum JsonRpcMessage {
     Request(JsonRpcRequest),
+    Notification(JsonRpcNotification),
     Response(JsonRpcResponse),
 }
+
+/// Told apart by which members are present: `method` and `id` make a request,
+/// `method` alone a notification, anything else is read as a response.
+impl<'de> Deserialize<'de> for JsonRpcMessage {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let message = match (value.get("method").is_some(), value.get("id").is_some()) {
+            (true, true) => serde_json::from_value(value).map(JsonRpcMessage::Request),
+            (true, false) => serde_json::from_value(value).map(JsonRpcMessage::Notification),
+            (false, _) => serde_json::from_value(value).map(JsonRpcMessage::Response),
+        };
+        message.map_err(serde::de::Error::custom)
+    }
+}
+
+/// A structural JSON-RPC 2.0 rule checked on every forwarded message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rule {
+    /// `jsonrpc` must be present and equal to "2.0"
+    JsonrpcVersion,
+    /// The message must be an object (or a batch of objects)
+    NotAnObject,
+    /// A response must not carry both `result` and `error`
+    ResultAndError,
+    /// A response must carry one of `result` or `error`
+    MissingResultOrError,
+    /// `id` must be a string, an integer or null
+    InvalidId,
+    /// A response id must have the same JSON type as its request's id
+    IdTypeChanged,
+    /// `error` must be an object with an integer `code` and a string `message`
+    MalformedError,
+    /// `params`, when present, must be an object or an array
+    InvalidParams,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::JsonrpcVersion,
+        Rule::NotAnObject,
+        Rule::ResultAndError,
+        Rule::MissingResultOrError,
+        Rule::InvalidId,
+        Rule::IdTypeChanged,
+        Rule::MalformedError,
+        Rule::InvalidParams,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Violation code recorded in audit events and metrics.
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::JsonrpcVersion => "jsonrpc_version",
+            Rule::NotAnObject => "not_an_object",
+            Rule::ResultAndError => "result_and_error",
+            Rule::MissingResultOrError => "missing_result_or_error",
+            Rule::InvalidId => "invalid_id",
+            Rule::IdTypeChanged => "id_type_changed",
+            Rule::MalformedError => "malformed_error",
+            Rule::InvalidParams => "invalid_params",
+        }
+    }
+}
+
+/// The set of rules being enforced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleSet(u16);
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl RuleSet {
+    pub fn all() -> Self {
+        Self(Rule::ALL.iter().fold(0, |bits, r| bits | 1 << r.index()))
+    }
+
+    pub fn without(self, rule: Rule) -> Self {
+        Self(self.0 & !(1 << rule.index()))
+    }
+
+    pub fn contains(self, rule: Rule) -> bool {
+        self.0 & (1 << rule.index()) != 0
+    }
+}
+
+/// JSON type of a request id, for matching it against the response's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdKind {
+    Number,
+    String,
+}
+
+/// Cap on request ids remembered for `id_type_changed`; a peer that never answers
+/// must not grow this without bound.
+const MAX_TRACKED_IDS: usize = 10_000;
+
+/// Checks messages against a [`RuleSet`]. Stateful only for `id_type_changed`,
+/// which pairs each response with the request it answers.
+#[derive(Debug, Default)]
+pub struct Validator {
+    rules: RuleSet,
+    /// (direction the request travelled, id rendered as text) -> id type
+    requests: HashMap<(StreamDirection, String), IdKind>,
+}
+
+impl Validator {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Every enabled rule `message` breaks, in [`Rule::ALL`] order, without duplicates.
+    pub fn validate(&mut self, direction: StreamDirection, message: &Value) -> Vec<Rule> {
+        let mut violations = Vec::new();
+        match message {
+            Value::Array(batch) if !batch.is_empty() => {
+                for item in batch {
+                    self.check_one(direction, item, &mut violations);
+                }
+            }
+            other => self.check_one(direction, other, &mut violations),
+        }
+
+        violations.retain(|r| self.rules.contains(*r));
+        violations.sort_by_key(|r| r.index());
+        violations.dedup();
+        violations
+    }
+
+    fn check_one(&mut self, direction: StreamDirection, message: &Value, violations: &mut Vec<Rule>) {
+        let Some(obj) = message.as_object() else {
+            violations.push(Rule::NotAnObject);
+            return;
+        };
+
+        if obj.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            violations.push(Rule::JsonrpcVersion);
+        }
+
+        let id = obj.get("id");
+        let id_kind = match id {
+            None | Some(Value::Null) => None,
+            Some(Value::String(_)) => Some(IdKind::String),
+            Some(Value::Number(n)) if n.is_i64() || n.is_u64() => Some(IdKind::Number),
+            Some(_) => {
+                violations.push(Rule::InvalidId);
+                None
+            }
+        };
+        let id_key = id.filter(|_| id_kind.is_some()).map(|v| match v {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        });
+
+        if obj.contains_key("method") {
+            if let Some(params) = obj.get("params") {
+                if !params.is_object() && !params.is_array() {
+                    violations.push(Rule::InvalidParams);
+                }
+            }
+            if let (Some(key), Some(kind)) = (id_key, id_kind) {
+                if self.requests.len() >= MAX_TRACKED_IDS {
+                    self.requests.clear();
+                }
+                self.requests.insert((direction, key), kind);
+            }
+            return;
+        }
+
+        match (obj.get("result"), obj.get("error")) {
+            (Some(_), Some(_)) => violations.push(Rule::ResultAndError),
+            (None, None) => violations.push(Rule::MissingResultOrError),
+            _ => {}
+        }
+
+        if let Some(error) = obj.get("error") {
+            let well_formed = error.get("code").is_some_and(|c| c.is_i64())
+                && error.get("message").is_some_and(Value::is_string);
+            if !well_formed {
+                violations.push(Rule::MalformedError);
+            }
+        }
+
+        if let (Some(key), Some(kind)) = (id_key, id_kind) {
+            let request_direction = match direction {
+                StreamDirection::Inbound => StreamDirection::Outbound,
+                _ => StreamDirection::Inbound,
+            };
+            if let Some(request_kind) = self.requests.remove(&(request_direction, key)) {
+                if request_kind != kind {
+                    violations.push(Rule::IdTypeChanged);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{test_proxy, ProxyConfig};
+    use serde_json::json;
+
+    /// One malformed message per rule, breaking that rule and no other. Some
+    /// rules need a message seen before it: `id_type_changed` needs the request.
+    fn fixtures() -> Vec<(Rule, Vec<(StreamDirection, Value)>)> {
+        use StreamDirection::{Inbound, Outbound};
+        vec![
+            (Rule::JsonrpcVersion, vec![(Outbound, json!({"jsonrpc": "1.0", "id": 1, "method": "ping"}))]),
+            (Rule::NotAnObject, vec![(Outbound, json!(42))]),
+            (
+                Rule::ResultAndError,
+                vec![(Inbound, json!({"jsonrpc": "2.0", "id": 1, "result": {}, "error": {"code": -1, "message": "no"}}))],
+            ),
+            (Rule::MissingResultOrError, vec![(Inbound, json!({"jsonrpc": "2.0", "id": 1}))]),
+            (Rule::InvalidId, vec![(Outbound, json!({"jsonrpc": "2.0", "id": 1.5, "method": "ping"}))]),
+            (
+                Rule::IdTypeChanged,
+                vec![
+                    (Outbound, json!({"jsonrpc": "2.0", "id": 7, "method": "ping"})),
+                    (Inbound, json!({"jsonrpc": "2.0", "id": "7", "result": {}})),
+                ],
+            ),
+            (
+                Rule::MalformedError,
+                vec![(Inbound, json!({"jsonrpc": "2.0", "id": 1, "error": {"code": "bad", "message": "no"}}))],
+            ),
+            (Rule::InvalidParams, vec![(Outbound, json!({"jsonrpc": "2.0", "id": 1, "method": "ping", "params": 3}))]),
+        ]
+    }
+
+    /// Violations of the last message, after validating the ones before it.
+    fn violations(rules: RuleSet, messages: &[(StreamDirection, Value)]) -> Vec<Rule> {
+        let mut validator = Validator::new(rules);
+        let (last, before) = messages.split_last().unwrap();
+        for (direction, message) in before {
+            assert_eq!(validator.validate(*direction, message), vec![]);
+        }
+        validator.validate(last.0, &last.1)
+    }
+
+    #[test]
+    fn every_rule_has_a_fixture() {
+        let covered: Vec<Rule> = fixtures().into_iter().map(|(rule, _)| rule).collect();
+        assert_eq!(covered, Rule::ALL);
+    }
+
+    #[test]
+    fn each_fixture_breaks_only_its_rule() {
+        for (rule, messages) in fixtures() {
+            assert_eq!(violations(RuleSet::all(), &messages), vec![rule], "{}", rule.code());
+        }
+    }
+
+    #[test]
+    fn a_disabled_rule_lets_its_fixture_through() {
+        for (rule, messages) in fixtures() {
+            assert_eq!(violations(RuleSet::all().without(rule), &messages), vec![], "{}", rule.code());
+        }
+    }
+
+    #[test]
+    fn well_formed_traffic_breaks_nothing() {
+        use StreamDirection::{Inbound, Outbound};
+        let exchange = [
+            (Outbound, json!({"jsonrpc": "2.0", "id": "a", "method": "tools/list", "params": {}})),
+            (Inbound, json!({"jsonrpc": "2.0", "id": "a", "result": {"tools": []}})),
+            (Outbound, json!({"jsonrpc": "2.0", "method": "notifications/initialized"})),
+            (Outbound, json!([{"jsonrpc": "2.0", "id": 2, "method": "ping", "params": []}])),
+            (Inbound, json!({"jsonrpc": "2.0", "id": 2, "error": {"code": -32601, "message": "nope"}})),
+            (Inbound, json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": "parse"}})),
+        ];
+        let mut validator = Validator::new(RuleSet::all());
+        for (direction, message) in &exchange {
+            assert_eq!(validator.validate(*direction, message), vec![], "{}", message);
+        }
+    }
+
+    /// Under `--strict-protocol` a request breaking a rule never reaches the
+    /// server: the client gets an Invalid Request error in its place, and the
+    /// refusal is tapped for the audit log.
+    #[tokio::test]
+    async fn strict_protocol_refuses_a_violating_request() {
+        let input = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\",\"params\":3}\n\
+                      {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"ping\"}\n";
+        let config = ProxyConfig {
+            strict_protocol: true,
+            ..test_proxy::config()
+        };
+        let (received, taps) = test_proxy::through_cat(config, input.to_vec()).await;
+
+        let replies: Vec<Value> = received
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 2, "{:?}", replies);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[0]["error"]["code"], -32600);
+        assert_eq!(replies[0]["error"]["data"]["violations"], json!(["invalid_params"]));
+        // Only the valid request reached `cat` to be echoed.
+        assert_eq!(replies[1], json!({"jsonrpc": "2.0", "id": 2, "method": "ping"}));
+
+        let refused: Vec<_> = test_proxy::sentinel_events(&taps)
+            .into_iter()
+            .filter(|(method, _)| method == "sentinel/protocol_violation")
+            .collect();
+        assert_eq!(refused.len(), 1);
+        assert_eq!(refused[0].1["action"], "suppressed");
+        assert_eq!(refused[0].1["request_id"], 1);
+    }
+
+    #[tokio::test]
+    async fn a_violating_request_is_forwarded_without_strict_protocol() {
+        let input = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\",\"params\":3}\n";
+        let (received, taps) = test_proxy::through_cat(test_proxy::config(), input.to_vec()).await;
+        assert_eq!(received, input);
+        assert!(test_proxy::sentinel_events(&taps)
+            .iter()
+            .all(|(method, _)| method != "sentinel/protocol_violation"));
+    }
+}
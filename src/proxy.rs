@@ -1,60 +1,311 @@
+use crate::child_env::ChildEnv;
+use crate::control::ForwardGate;
 use crate::events::{current_timestamp_ms, RawTap, StreamDirection};
+use crate::health::Health;
+use crate::framing::{read_line_bounded, Frame, FrameReader, Framing, Framings, CHUNK_BYTES};
+use crate::protocol::{Rule, RuleSet, Validator};
+use crate::redaction::{self, RedactScope};
+use crate::tap::Tap;
+use crate::throttle::Throttle;
+use crate::throughput::Throughput;
 use bytes::Bytes;
-use std::process::{self, Stdio};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::mpsc;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use std::time::{Duration, Instant};
+use std::fmt;
+use tokio::net::TcpStream;
+use tokio::process::{Child, ChildStderr, Command};
+use tokio::task::JoinHandle;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
-pub async fn run_proxy(
-    command: Vec<String>,
-    raw_sender: mpsc::Sender<RawTap>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if command.is_empty() {
-        return Err("Empty command".into());
+/// JSON-RPC error code returned in place of a message over `--max-message-bytes`.
+const OVERSIZED_MESSAGE_CODE: i64 = -32001;
+
+/// JSON-RPC "Invalid Request", returned in place of a message rejected by `--strict-protocol`.
+const PROTOCOL_VIOLATION_CODE: i64 = -32600;
+
+/// Proxy behaviour configured from the CLI.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    /// Refuse to forward single messages larger than this (opt-in enforcement)
+    pub max_message_bytes: Option<usize>,
+    /// Refuse to forward messages that break an enabled protocol rule
+    pub strict_protocol: bool,
+    /// Protocol rules checked when `strict_protocol` is set
+    pub protocol_rules: RuleSet,
+    /// Pace child -> client forwarding to this many bytes per second
+    pub max_inbound_bytes_per_sec: Option<u64>,
+    /// Pace client -> child forwarding to this many bytes per second
+    pub max_outbound_bytes_per_sec: Option<u64>,
+    /// How messages are delimited on stdin/stdout
+    pub framing: Framing,
+    /// How long the child may take to exit after SIGTERM before it is killed
+    pub child_shutdown_timeout: Duration,
+    /// Respawn the child when it exits unsuccessfully
+    pub restart: Option<RestartPolicy>,
+    /// Environment and working directory of the child
+    pub child_env: ChildEnv,
+    /// Most bytes of one message (or stderr line) held in memory; longer ones
+    /// are streamed through and tapped truncated
+    pub max_line_bytes: usize,
+    /// Holds client -> server traffic while paused from the dashboard or control API
+    pub forward_gate: ForwardGate,
+    /// Bytes and lines read in each direction
+    pub throughput: Arc<Throughput>,
+    /// How often a `sentinel/stats` event reports `throughput`
+    pub throughput_interval: Option<Duration>,
+    /// Redaction in effect (`None` when disabled), recorded in the start event
+    pub redaction: Option<RedactScope>,
+    /// Names of the redaction rules applied (empty when disabled), recorded in
+    /// the start event
+    pub redaction_rules: Vec<String>,
+    /// Where the child's pid and exit are reported for `/readyz`
+    pub health: Option<Health>,
+}
+
+/// When and how often a crashed child is respawned.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+    /// Delay before the first restart; doubled for each further attempt
+    pub initial_backoff: Duration,
+}
+
+impl RestartPolicy {
+    /// Longest delay between restarts.
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1u32 << (attempt - 1).min(16))
+            .min(Self::MAX_BACKOFF)
     }
+}
 
-    let mut child = Command::new(&command[0])
-        .args(&command[1..])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()?;
+/// Why a message was withheld instead of forwarded.
+enum Suppression {
+    Oversized { limit: usize },
+    ProtocolViolation { violations: Vec<Rule> },
+}
 
-    let mut child_stdin = child.stdin.take().ok_or("Failed to open child stdin")?;
-    let child_stdout = child.stdout.take().ok_or("Failed to open child stdout")?;
+impl ProxyConfig {
+    /// How much of a message to buffer. Anything over `max_message_bytes` is
+    /// refused anyway, so nothing more than that needs to be held.
+    fn read_limit(&self) -> usize {
+        match self.max_message_bytes {
+            Some(limit) => self.max_line_bytes.min(limit.saturating_add(1)),
+            None => self.max_line_bytes,
+        }
+    }
 
-    let parent_stdin = tokio::io::stdin();
-    let mut parent_stdout = tokio::io::stdout();
+    /// Decide whether the message `line` must be withheld. The validator is shared by both
+    /// directions so responses can be paired with their requests.
+    fn check(&self, line: &[u8], direction: StreamDirection, validator: &std::sync::Mutex<Validator>) -> Option<Suppression> {
+        if let Some(limit) = self.max_message_bytes.filter(|l| line.len() > *l) {
+            return Some(Suppression::Oversized { limit });
+        }
+        if !self.strict_protocol {
+            return None;
+        }
+        // Non-JSON lines are not JSON-RPC traffic; they are forwarded untouched.
+        let value = serde_json::from_slice::<serde_json::Value>(line).ok()?;
+        let violations = validator
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .validate(direction, &value);
+        (!violations.is_empty()).then_some(Suppression::ProtocolViolation { violations })
+    }
+}
 
-    // ----- OUTBOUND: parent stdin -> child stdin -----
-    let tx_out = raw_sender.clone();
-    let stdin_handle = tokio::spawn(async move {
-        let mut reader = BufReader::new(parent_stdin);
-        let mut line = Vec::<u8>::new();
+/// The MCP server Sentinel sits in front of.
+#[derive(Debug, Clone)]
+pub enum Upstream {
+    /// A child process speaking MCP on its stdio
+    Command(Vec<String>),
+    /// A server listening on a socket
+    Socket(Endpoint),
+    /// A streamable HTTP endpoint, served to the client on `listen`
+    Http { url: String, listen: String },
+}
 
-        loop {
-            line.clear();
-            match reader.read_until(b'\n', &mut line).await {
-                Ok(0) => break,
-                Ok(_) => {
+/// Where a socket upstream listens.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    /// `host:port`
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    /// Full pipe path (`\\.\pipe\name`)
+    #[cfg(windows)]
+    Pipe(String),
+}
 
-                    let observed_ts_ms = current_timestamp_ms(); // capture timestamp before forwarding
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "tcp://{}", address),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+            #[cfg(windows)]
+            Endpoint::Pipe(path) => write!(f, "{}", path),
+        }
+    }
+}
 
-                    if child_stdin.write_all(&line).await.is_err() {
-                        break;
+/// Whatever carries server -> client traffic from a socket upstream.
+type UpstreamReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Whatever carries client -> server traffic: the child's stdin or the socket's write half.
+type UpstreamWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Whatever carries server -> client traffic: our stdout or an attached client's socket.
+type ClientWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// The MCP client's end: Sentinel's own stdio, or a client attached through the
+/// hub (`run --servers`).
+pub struct Client {
+    pub input: Box<dyn AsyncRead + Send + Unpin>,
+    pub output: ClientWriter,
+}
+
+impl Client {
+    pub fn stdio() -> Self {
+        Self {
+            input: Box::new(tokio::io::stdin()),
+            output: Box::new(tokio::io::stdout()),
+        }
+    }
+}
+
+/// The established upstream, handed to its supervisor.
+enum Connection {
+    Child(Vec<String>, Child),
+    Socket(Endpoint, Option<String>, UpstreamReader),
+}
+
+/// Run the upstream and pump both directions between it and `client` until it
+/// goes away. Cancelling `stop_input` stops reading from the client and closes
+/// the upstream's input; a child is also asked to terminate (see [`terminate`]).
+/// Its remaining output is still forwarded and tapped.
+/// Returns how the child exited (`None` for a socket upstream); the caller finishes
+/// shutdown before exiting with it.
+pub async fn run_proxy(
+    upstream: Upstream,
+    client: Client,
+    raw_sender: Tap,
+    config: ProxyConfig,
+    stop_input: CancellationToken,
+) -> Result<Option<ExitStatus>, Box<dyn std::error::Error>> {
+    // Connect before reading from the client, so its first message has somewhere to go.
+    let (writer, connection): (UpstreamWriter, Connection) = match upstream {
+        // The client speaks HTTP to us; `client` is not involved.
+        Upstream::Http { url, listen } => {
+            let result = crate::http_proxy::run(&url, &listen, raw_sender.clone(), config.forward_gate, stop_input)
+                .await
+                .map_err(|e| e.to_string());
+            return result.map(|()| None).map_err(Into::into);
+        }
+        Upstream::Command(command) => {
+            if command.is_empty() {
+                return Err("Empty command".into());
+            }
+            let mut child = spawn_child(&command, &config.child_env)?;
+            if let Some(health) = &config.health {
+                health.child_started(child.id());
+            }
+            // Recorded before any traffic is read, so it leads the audit log.
+            let mut record = spawn_record(&command, &config.child_env, &child).await;
+            record["redaction"] = json!(redaction::describe(config.redaction));
+            if !config.redaction_rules.is_empty() {
+                record["redaction_rules"] = json!(config.redaction_rules);
+            }
+            tap(&raw_sender, RawTap::sentinel("sentinel/run_started", record)).await;
+            let stdin = child.stdin.take().ok_or("Failed to open child stdin")?;
+            (Box::new(stdin), Connection::Child(command, child))
+        }
+        Upstream::Socket(endpoint) => {
+            let (reader, writer, peer) = connect(&endpoint).await?;
+            (writer, Connection::Socket(endpoint, peer, reader))
+        }
+    };
+
+    let parent_stdin = client.input;
+    // Also cancelled below once the upstream is gone, so a quiet client can't keep us waiting.
+    let stop_reading = stop_input.child_token();
+
+    // Both directions may need to write to either side (synthesized error replies),
+    // so the writers are shared. The upstream writer is taken and dropped on EOF so
+    // the server sees its input close, and replaced on restart or reconnect.
+    let upstream_in = Arc::new(Mutex::new(Some(writer)));
+    let parent_stdout = Arc::new(Mutex::new(Some(client.output)));
+    let validator = Arc::new(std::sync::Mutex::new(Validator::new(config.protocol_rules)));
+    let framings = Arc::new(Framings::default());
+
+    // ----- OUTBOUND: parent stdin -> upstream -----
+    let mut outbound = Outbound {
+        tx: raw_sender.clone(),
+        dest: upstream_in.clone(),
+        source: parent_stdout.clone(),
+        config: config.clone(),
+        validator: validator.clone(),
+        framings: framings.clone(),
+        throttle: config.max_outbound_bytes_per_sec.map(Throttle::new),
+    };
+    let stop_outbound = stop_reading.clone();
+    let stdin_handle = tokio::spawn(async move {
+        let mut reader = FrameReader::new(
+            BufReader::new(parent_stdin),
+            outbound.config.framing,
+            StreamDirection::Outbound,
+            outbound.framings.clone(),
+        )
+        .with_max_bytes(outbound.config.read_limit())
+        .with_throughput(outbound.config.throughput.clone());
+        let mut frame = Frame::default();
+        let gate = outbound.config.forward_gate.clone();
+        let mut paused = gate.subscribe();
+        // Read while forwarding is paused, oldest first
+        let mut held: VecDeque<Frame> = VecDeque::new();
+        let mut held_bytes = 0usize;
+        let mut client_done = false;
+
+        'read: loop {
+            if !*paused.borrow_and_update() {
+                while let Some(frame) = held.pop_front() {
+                    held_bytes -= frame.raw().len();
+                    gate.release(frame.raw().len());
+                    if !outbound.forward(&mut reader, &frame).await {
+                        break 'read;
                     }
-                    let _ = child_stdin.flush().await;
+                }
+                if client_done {
+                    break;
+                }
+            }
 
-                    let data = Bytes::copy_from_slice(&line);
-                    if tx_out
-                        .send(RawTap {
-                            direction: StreamDirection::Outbound,
-                            bytes: data,
-                            observed_ts_ms,
-                        })
-                        .await
-                        .is_err()
-                    {
+            // Past a held truncated frame the reader is mid-message: its rest is
+            // only read once the frame is released.
+            let full = held_bytes >= gate.max_held_bytes() || held.back().is_some_and(Frame::is_truncated);
+            let read = tokio::select! {
+                r = reader.next(&mut frame), if !full && !client_done => r,
+                Ok(()) = paused.changed() => continue,
+                _ = stop_outbound.cancelled() => break,
+            };
+            match read {
+                // Held messages are still released on resume.
+                Ok(false) => client_done = true,
+                Ok(true) if *paused.borrow() => {
+                    held_bytes += frame.raw().len();
+                    gate.hold(frame.raw().len());
+                    held.push_back(std::mem::take(&mut frame));
+                }
+                Ok(true) => {
+                    if !outbound.forward(&mut reader, &frame).await {
                         break;
                     }
                 }
@@ -62,36 +313,474 @@ pub async fn run_proxy(
             }
         }
 
-        let _ = child_stdin.shutdown().await;
+        if !held.is_empty() {
+            eprintln!("⚠️  {} held message(s) were never forwarded", held.len());
+            for frame in &held {
+                gate.release(frame.raw().len());
+            }
+            let discarded = RawTap::sentinel(
+                "sentinel/held_discarded",
+                json!({ "messages": held.len(), "bytes": held_bytes }),
+            );
+            tap(&outbound.tx, discarded).await;
+        }
+
+        end_throttling(&mut outbound.throttle, StreamDirection::Outbound, &outbound.tx).await;
+
+        if let Some(mut upstream_in) = outbound.dest.lock().await.take() {
+            let _ = upstream_in.shutdown().await;
+        }
     });
 
-    // ----- INBOUND: child stdout -> parent stdout -----
-    let tx_in = raw_sender.clone();
-    let stdout_handle = tokio::spawn(async move {
-        let mut reader = BufReader::new(child_stdout);
-        let mut line = Vec::<u8>::new();
+    let run_started_at = Instant::now();
+    let throughput = config.throughput.clone();
+    let reporter = config
+        .throughput_interval
+        .map(|every| tokio::spawn(report_throughput(raw_sender.clone(), throughput.clone(), every)));
+    let pumps = OutputPumps {
+        tx: raw_sender.clone(),
+        parent_stdout,
+        upstream_in,
+        config,
+        validator,
+        framings,
+    };
+
+    let is_child = matches!(connection, Connection::Child(..));
+    let result = match connection {
+        Connection::Child(command, child) => supervise_child(&command, child, &pumps, &stdin_handle, &stop_input)
+            .await
+            .map(Some),
+        Connection::Socket(endpoint, peer, reader) => supervise_connection(&endpoint, peer, reader, &pumps, &stdin_handle, &stop_input)
+            .await
+            .map(|()| None),
+    }
+    // Held across the await below, so it must be Send.
+    .map_err(|e| e.to_string());
+
+    stop_reading.cancel();
+    let _ = stdin_handle.await;
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+
+    if is_child {
+        let mut ended = match &result {
+            Ok(status) => json!({
+                "exit_code": status.and_then(|s| s.code()),
+                "signal": status.as_ref().and_then(exit_signal),
+                "success": status.is_some_and(|s| s.success()),
+            }),
+            Err(e) => json!({ "error": e }),
+        };
+        ended["duration_ms"] = json!(run_started_at.elapsed().as_millis() as u64);
+        ended["throughput"] = throughput.snapshot();
+        tap(&raw_sender, RawTap::sentinel("sentinel/run_ended", ended)).await;
+    }
+    Ok(result?)
+}
+
+/// What was executed, for the `sentinel/run_started` event: the child's
+/// environment (key names only) plus argv, the resolved executable and its
+/// blake3 digest, the PID, and the Sentinel version.
+async fn spawn_record(command: &[String], env: &ChildEnv, child: &Child) -> serde_json::Value {
+    let spawned_ts_ms = current_timestamp_ms();
+    let program_path = env.resolve_program(&command[0]);
+    let program_blake3 = match program_path.clone() {
+        Some(path) => tokio::task::spawn_blocking(move || {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update_reader(std::fs::File::open(path).ok()?).ok()?;
+            Some(hasher.finalize().to_hex().to_string())
+        })
+        .await
+        .ok()
+        .flatten(),
+        None => None,
+    };
+
+    let mut record = env.describe(&command[0]);
+    record["argv"] = json!(command);
+    record["program_path"] = json!(program_path.map(|p| p.display().to_string()));
+    record["program_blake3"] = json!(program_blake3);
+    record["pid"] = json!(child.id());
+    record["spawned_ts_ms"] = json!(spawned_ts_ms);
+    record["sentinel_version"] = json!(env!("CARGO_PKG_VERSION"));
+    record
+}
+
+/// Log the throughput totals every `every` until aborted.
+async fn report_throughput(tx: Tap, throughput: Arc<Throughput>, every: Duration) {
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    loop {
+        ticks.tick().await;
+        if !tap(&tx, RawTap::sentinel("sentinel/stats", throughput.snapshot())).await {
+            break;
+        }
+    }
+}
+
+/// Wait for the child, respawning it under the restart policy.
+async fn supervise_child(
+    command: &[String],
+    mut child: Child,
+    pumps: &OutputPumps,
+    stdin_handle: &JoinHandle<()>,
+    stop_input: &CancellationToken,
+) -> Result<ExitStatus, Box<dyn std::error::Error>> {
+    let config = &pumps.config;
+    let mut restarts = 0u32;
+    loop {
+        let [stdout_handle, stderr_handle] = pumps.attach(&mut child)?;
+
+        let (status, stopping) = tokio::select! {
+            status = child.wait() => (status?, false),
+            _ = stop_input.cancelled() => (terminate(&mut child, config.child_shutdown_timeout).await?, true),
+        };
+        if let Some(health) = &config.health {
+            health.child_exited(&status);
+        }
+        // Forward and tap whatever the child wrote before it went away.
+        let _ = tokio::join!(stdout_handle, stderr_handle);
+
+        let Some(policy) = config.restart.filter(|_| !stopping && !status.success()) else {
+            return Ok(status);
+        };
+        if stdin_handle.is_finished() || restarts >= policy.max_restarts {
+            if restarts >= policy.max_restarts {
+                eprintln!("❌ Child exited ({}) and {} restart(s) are used up", status, restarts);
+            }
+            return Ok(status);
+        }
+
+        restarts += 1;
+        let backoff = policy.backoff(restarts);
+        eprintln!(
+            "🔁 Child exited ({}); restarting in {:?} (attempt {}/{})",
+            status, backoff, restarts, policy.max_restarts
+        );
+        let event = RawTap::sentinel(
+            "sentinel/child_restarted",
+            json!({
+                "exit_code": status.code(),
+                "signal": exit_signal(&status),
+                "attempt": restarts,
+                "max_restarts": policy.max_restarts,
+                "backoff_ms": backoff.as_millis() as u64,
+            }),
+        );
+        if !tap(&pumps.tx, event).await {
+            return Ok(status);
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = stop_input.cancelled() => return Ok(status),
+        }
+
+        child = spawn_child(command, &config.child_env)?;
+        if let Some(health) = &config.health {
+            health.child_started(child.id());
+        }
+        *pumps.upstream_in.lock().await = child.stdin.take().map(|w| Box::new(w) as UpstreamWriter);
+    }
+}
+
+/// Pump a socket upstream's replies until it closes, reconnecting under the
+/// restart policy. Each connection is bracketed by `sentinel/connection_opened` and
+/// `sentinel/connection_closed` events.
+///
+/// The connection closing after the client or Sentinel is done is a normal end;
+/// the server closing it first (with reconnects used up or disabled) is an error.
+async fn supervise_connection(
+    endpoint: &Endpoint,
+    peer: Option<String>,
+    reader: UpstreamReader,
+    pumps: &OutputPumps,
+    stdin_handle: &JoinHandle<()>,
+    stop_input: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = &pumps.config;
+    let address = endpoint.to_string();
+    let mut reconnects = 0u32;
+    let mut connection = Some((reader, peer));
+
+    loop {
+        if let Some((reader, peer)) = connection.take() {
+            eprintln!("🔌 Connected to {}", peer.as_deref().unwrap_or(&address));
+            let opened = RawTap::sentinel(
+                "sentinel/connection_opened",
+                json!({ "address": address, "peer": peer, "reconnects": reconnects }),
+            );
+            if !tap(&pumps.tx, opened).await {
+                return Ok(());
+            }
+            let opened_at = Instant::now();
+            let mut reply_handle = tokio::spawn(pumps.clone().pump_stdout(reader));
+
+            let stopping = tokio::select! {
+                _ = &mut reply_handle => false,
+                _ = stop_input.cancelled() => true,
+            };
+            // Our side of the connection is closed once input stops; give the
+            // server the same grace as a child to answer and hang up.
+            if stopping && tokio::time::timeout(config.child_shutdown_timeout, &mut reply_handle).await.is_err() {
+                eprintln!(
+                    "⚠️  {} did not close within {:?}, dropping the connection",
+                    address, config.child_shutdown_timeout
+                );
+                reply_handle.abort();
+            }
+            *pumps.upstream_in.lock().await = None;
+
+            let closed_by = if stopping {
+                "sentinel"
+            } else if stdin_handle.is_finished() {
+                "client"
+            } else {
+                "server"
+            };
+            eprintln!("🔌 Connection to {} closed by the {}", address, closed_by);
+            let closed = RawTap::sentinel(
+                "sentinel/connection_closed",
+                json!({
+                    "address": address,
+                    "closed_by": closed_by,
+                    "duration_ms": opened_at.elapsed().as_millis() as u64,
+                }),
+            );
+            if !tap(&pumps.tx, closed).await || closed_by != "server" {
+                return Ok(());
+            }
+        }
+
+        let Some(policy) = config.restart else {
+            return Err(format!("connection to {} closed by the server", address).into());
+        };
+        if reconnects >= policy.max_restarts {
+            return Err(format!("connection to {} lost and {} reconnect(s) are used up", address, reconnects).into());
+        }
+
+        reconnects += 1;
+        let backoff = policy.backoff(reconnects);
+        eprintln!(
+            "🔁 Reconnecting to {} in {:?} (attempt {}/{})",
+            address, backoff, reconnects, policy.max_restarts
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = stop_input.cancelled() => return Ok(()),
+        }
+        if stdin_handle.is_finished() {
+            return Ok(());
+        }
+
+        match connect(endpoint).await {
+            Ok((reader, writer, peer)) => {
+                *pumps.upstream_in.lock().await = Some(writer);
+                connection = Some((reader, peer));
+            }
+            Err(e) => eprintln!("⚠️  {}", e),
+        }
+    }
+}
+
+/// Open a connection to `endpoint`, split into its two directions, with the
+/// peer's address where the transport has one.
+async fn connect(endpoint: &Endpoint) -> Result<(UpstreamReader, UpstreamWriter, Option<String>), String> {
+    let failed = |e: std::io::Error| format!("connect to {}: {}", endpoint, e);
+    match endpoint {
+        Endpoint::Tcp(address) => {
+            let stream = TcpStream::connect(address).await.map_err(failed)?;
+            let peer = stream.peer_addr().map(|a| a.to_string()).ok();
+            let (r, w) = stream.into_split();
+            Ok((Box::new(r), Box::new(w), peer))
+        }
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            let (r, w) = tokio::net::UnixStream::connect(path).await.map_err(failed)?.into_split();
+            Ok((Box::new(r), Box::new(w), None))
+        }
+        #[cfg(windows)]
+        Endpoint::Pipe(path) => {
+            use tokio::net::windows::named_pipe::ClientOptions;
+            // Every instance of the pipe is serving another client.
+            const ERROR_PIPE_BUSY: i32 = 231;
+            let mut attempts = 0;
+            let pipe = loop {
+                match ClientOptions::new().open(path) {
+                    Ok(pipe) => break pipe,
+                    Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && attempts < 100 => {
+                        attempts += 1;
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                    Err(e) => return Err(failed(e)),
+                }
+            };
+            let (r, w) = tokio::io::split(pipe);
+            Ok((Box::new(r), Box::new(w), None))
+        }
+    }
+}
+
+fn spawn_child(command: &[String], env: &ChildEnv) -> std::io::Result<Child> {
+    let mut cmd = Command::new(&command[0]);
+    env.apply(&mut cmd);
+    cmd.args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+}
+
+/// The signal that terminated the child, if any (Unix only).
+pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    return std::os::unix::process::ExitStatusExt::signal(status);
+    #[cfg(not(unix))]
+    None
+}
+
+/// The client -> upstream direction of [`run_proxy`].
+struct Outbound {
+    tx: Tap,
+    dest: Arc<Mutex<Option<UpstreamWriter>>>,
+    /// Where replies Sentinel synthesizes for the client go
+    source: Arc<Mutex<Option<ClientWriter>>>,
+    config: ProxyConfig,
+    validator: Arc<std::sync::Mutex<Validator>>,
+    framings: Arc<Framings>,
+    throttle: Option<Throttle>,
+}
+
+impl Outbound {
+    /// Check, pace, forward and tap one message from the client. Returns false
+    /// once the direction should stop.
+    async fn forward<R>(&mut self, reader: &mut FrameReader<R>, frame: &Frame) -> bool
+    where
+        R: tokio::io::AsyncBufRead + Unpin,
+    {
+        let peers = Peers {
+            source: &*self.source,
+            source_direction: StreamDirection::Inbound,
+            dest: &*self.dest,
+            dest_direction: StreamDirection::Outbound,
+            framings: &self.framings,
+        };
+        if frame.is_truncated() {
+            let passed = pass_oversized(reader, frame, peers, &self.config, &self.tx).await;
+            return passed || self.config.restart.is_some();
+        }
+
+        if let Some(reason) = self.config.check(frame.body(), StreamDirection::Outbound, &self.validator) {
+            return suppress(frame.body(), StreamDirection::Outbound, reason, peers, &self.tx).await;
+        }
+
+        if !pace(&mut self.throttle, frame.raw().len(), StreamDirection::Outbound, &self.tx).await {
+            return false;
+        }
+
+        let observed_ts_ms = current_timestamp_ms(); // capture timestamp before forwarding
+
+        if !write_line(&self.dest, frame.raw()).await {
+            // A crashed child is about to be replaced (or a lost connection
+            // re-established); this message is lost.
+            return self.config.restart.is_some();
+        }
+
+        self.tx
+            .send(RawTap {
+                direction: StreamDirection::Outbound,
+                bytes: Bytes::copy_from_slice(frame.body()),
+                observed_ts_ms,
+                server: None,
+                dropped_before: [0; 4],
+            })
+            .await
+    }
+}
+
+/// Everything the upstream's output pumps need, shared across restarts and reconnects.
+#[derive(Clone)]
+struct OutputPumps {
+    tx: Tap,
+    parent_stdout: Arc<Mutex<Option<ClientWriter>>>,
+    upstream_in: Arc<Mutex<Option<UpstreamWriter>>>,
+    config: ProxyConfig,
+    validator: Arc<std::sync::Mutex<Validator>>,
+    framings: Arc<Framings>,
+}
+
+impl OutputPumps {
+    /// Start pumping a (new) child's stdout and stderr.
+    fn attach(&self, child: &mut Child) -> Result<[JoinHandle<()>; 2], Box<dyn std::error::Error>> {
+        let child_stdout = child.stdout.take().ok_or("Failed to open child stdout")?;
+        let child_stderr = child.stderr.take().ok_or("Failed to open child stderr")?;
+        Ok([
+            tokio::spawn(self.clone().pump_stdout(child_stdout)),
+            tokio::spawn(pump_stderr(child_stderr, self.tx.clone(), self.config.max_line_bytes)),
+        ])
+    }
+
+    /// INBOUND: child stdout (or the socket) -> parent stdout
+    async fn pump_stdout(self, upstream_out: impl AsyncRead + Unpin) {
+        let mut reader = FrameReader::new(
+            BufReader::new(upstream_out),
+            self.config.framing,
+            StreamDirection::Inbound,
+            self.framings.clone(),
+        )
+        .with_max_bytes(self.config.read_limit())
+        .with_throughput(self.config.throughput.clone());
+        let mut frame = Frame::default();
+        let mut throttle = self.config.max_inbound_bytes_per_sec.map(Throttle::new);
 
         loop {
-            line.clear();
-            match reader.read_until(b'\n', &mut line).await {
-                Ok(0) => break,
-                Ok(_) => {
+            match reader.next(&mut frame).await {
+                Ok(false) => break,
+                Ok(true) => {
+                    let peers = Peers {
+                        source: &*self.upstream_in,
+                        source_direction: StreamDirection::Outbound,
+                        dest: &*self.parent_stdout,
+                        dest_direction: StreamDirection::Inbound,
+                        framings: &self.framings,
+                    };
+                    if frame.is_truncated() {
+                        if !pass_oversized(&mut reader, &frame, peers, &self.config, &self.tx).await {
+                            break;
+                        }
+                        continue;
+                    }
+
+                    if let Some(reason) = self.config.check(frame.body(), StreamDirection::Inbound, &self.validator) {
+                        if !suppress(frame.body(), StreamDirection::Inbound, reason, peers, &self.tx).await {
+                            break;
+                        }
+                        continue;
+                    }
+
+                    if !pace(&mut throttle, frame.raw().len(), StreamDirection::Inbound, &self.tx).await {
+                        break;
+                    }
+
                     // Forward FIRST
-                    if parent_stdout.write_all(&line).await.is_err() {
+                    if !write_line(&self.parent_stdout, frame.raw()).await {
                         break;
                     }
-                    let _ = parent_stdout.flush().await;
 
                     let observed_ts_ms = current_timestamp_ms();
-                    let data = Bytes::copy_from_slice(&line);
-                    if tx_in
+                    let data = Bytes::copy_from_slice(frame.body());
+                    if !self
+                        .tx
                         .send(RawTap {
                             direction: StreamDirection::Inbound,
                             bytes: data,
                             observed_ts_ms,
+                            server: None,
+                            dropped_before: [0; 4],
                         })
                         .await
-                        .is_err()
                     {
                         break;
                     }
@@ -99,9 +788,647 @@ pub async fn run_proxy(
                 Err(_) => break,
             }
         }
+
+        end_throttling(&mut throttle, StreamDirection::Inbound, &self.tx).await;
+    }
+}
+
+/// STDERR: child stderr -> parent stderr (tapped, never checked or paced)
+async fn pump_stderr(child_stderr: ChildStderr, tx: Tap, max_line_bytes: usize) {
+    let mut reader = BufReader::new(child_stderr);
+    let mut parent_stderr = tokio::io::stderr();
+    let mut line = Vec::<u8>::new();
+    let mut forwarding = true;
+
+    loop {
+        line.clear();
+        match read_line_bounded(&mut reader, &mut line, max_line_bytes).await {
+            Ok((0, _)) | Err(_) => break,
+            Ok((_, cut)) => {
+                let observed_ts_ms = current_timestamp_ms();
+                // Keep draining even if our stderr is gone, or the child would block.
+                if forwarding {
+                    forwarding = parent_stderr.write_all(&line).await.is_ok();
+                }
+
+                let tapped = if cut {
+                    let mut oversized = Oversized::new(&line);
+                    let mut chunk = Vec::new();
+                    while let Ok((n, cut)) = read_line_bounded(&mut reader, &mut chunk, CHUNK_BYTES).await {
+                        if forwarding && n > 0 {
+                            forwarding = parent_stderr.write_all(&chunk).await.is_ok();
+                        }
+                        oversized.update(&chunk);
+                        chunk.clear();
+                        if n == 0 || !cut {
+                            break;
+                        }
+                    }
+                    tap(&tx, oversized.event(StreamDirection::Stderr, max_line_bytes, "forwarded")).await
+                } else {
+                    tap(
+                        &tx,
+                        RawTap {
+                            direction: StreamDirection::Stderr,
+                            bytes: Bytes::copy_from_slice(&line),
+                            observed_ts_ms,
+                            server: None,
+                            dropped_before: [0; 4],
+                        },
+                    )
+                    .await
+                };
+                if forwarding {
+                    let _ = parent_stderr.flush().await;
+                }
+                if !tapped && !forwarding {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Length, digest and start of a message too long to hold.
+struct Oversized {
+    head: Vec<u8>,
+    hasher: blake3::Hasher,
+    len: u64,
+}
+
+impl Oversized {
+    fn new(head: &[u8]) -> Self {
+        let mut oversized = Self {
+            head: head.to_vec(),
+            hasher: blake3::Hasher::new(),
+            len: 0,
+        };
+        oversized.hasher.update(head);
+        oversized.len = head.len() as u64;
+        oversized
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.len += chunk.len() as u64;
+    }
+
+    /// The truncated copy travels as a string in the event, so the parser never
+    /// tries to read it as JSON.
+    fn event(&self, direction: StreamDirection, limit: usize, action: &str) -> RawTap {
+        eprintln!(
+            "⚠️  Oversized {:?} message: {} bytes (limit {}), {}",
+            direction, self.len, limit, action
+        );
+        RawTap::sentinel(
+            "sentinel/oversized_line",
+            json!({
+                "direction": direction,
+                "size_bytes": self.len,
+                "limit_bytes": limit,
+                "blake3": self.hasher.finalize().to_hex().to_string(),
+                "action": action,
+                "truncated": String::from_utf8_lossy(&self.head),
+            }),
+        )
+    }
+}
+
+/// Pass on a message longer than the read limit without holding it: its start
+/// is in `frame` and the rest is streamed from `reader` piece by piece. Over
+/// `--max-message-bytes` it is drained instead of forwarded, and answered like
+/// a held one (see [`suppress`]) when its start gives away its id. Either way a
+/// `sentinel/oversized_line` event records its size, digest and a truncated
+/// copy. Returns false if forwarding failed.
+async fn pass_oversized<R, S, D>(
+    reader: &mut FrameReader<R>,
+    frame: &Frame,
+    peers: Peers<'_, S, D>,
+    config: &ProxyConfig,
+    tx: &Tap,
+) -> bool
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+    S: AsyncWrite + Unpin,
+    D: AsyncWrite + Unpin,
+{
+    let (dest, direction) = (peers.dest, peers.dest_direction);
+    let suppressing = config.max_message_bytes.is_some();
+    let mut forwarded = suppressing || write_raw(dest, frame.raw()).await;
+
+    let mut oversized = Oversized::new(frame.body());
+    let mut chunk = Vec::new();
+    while let Ok(true) = reader.next_chunk(&mut chunk).await {
+        if forwarded && !suppressing {
+            forwarded = write_raw(dest, &chunk).await;
+        }
+        oversized.update(&chunk);
+    }
+    if forwarded && !suppressing {
+        flush(dest).await;
+    }
+
+    let Some(limit) = config.max_message_bytes else {
+        tap(tx, oversized.event(direction, config.max_line_bytes, "forwarded")).await;
+        return forwarded;
+    };
+    if !tap(tx, oversized.event(direction, limit, "suppressed")).await {
+        return false;
+    }
+    let (kind, id) = kind_of(Some(head_shape(frame.body())));
+    let digest = oversized.hasher.finalize().to_hex().to_string();
+    let error = json!({
+        "code": OVERSIZED_MESSAGE_CODE,
+        "message": format!("{} of {} bytes exceeds Sentinel max-message-bytes ({})", kind, oversized.len, limit),
+        "data": { "size_bytes": oversized.len, "limit_bytes": limit, "blake3": digest },
+    });
+    answer(kind, id, error, peers, tx).await
+}
+
+/// Ask the child to exit (SIGTERM on Unix; elsewhere it only sees its stdin
+/// close), then kill it if it is still running after `grace`.
+async fn terminate(child: &mut Child, grace: Duration) -> std::io::Result<ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: kill(2) has no memory-safety preconditions; `pid` is our own
+        // child, which has not been reaped (we still hold its handle).
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+
+    match tokio::time::timeout(grace, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            eprintln!("⚠️  Child did not exit within {:?}, killing it", grace);
+            child.kill().await?;
+            child.wait().await
+        }
+    }
+}
+
+/// Just enough of a message to answer it without keeping the body.
+#[derive(Deserialize, Default)]
+struct MessageShape {
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    #[serde(default)]
+    method: Option<serde::de::IgnoredAny>,
+}
+
+/// What a message is, by its shape, and the id an answer to it would carry.
+fn kind_of(shape: Option<MessageShape>) -> (&'static str, Option<serde_json::Value>) {
+    match shape {
+        Some(MessageShape { id: Some(id), method: Some(_) }) if !id.is_null() => ("request", Some(id)),
+        Some(MessageShape { method: Some(_), .. }) => ("notification", None),
+        Some(MessageShape { id: Some(id), method: None }) => ("response", Some(id)),
+        _ => ("unparsed", None),
+    }
+}
+
+/// The shape of a message from its start alone: its members are read in order
+/// up to the first one cut off. `id` and `method` usually come before the bulk
+/// of `params` or `result`. An id is only kept once a `method`, `result` or
+/// `error` member says what it belongs to.
+fn head_shape(head: &[u8]) -> MessageShape {
+    let mut shape = MessageShape::default();
+    let mut answers = false;
+    let mut rest = head.trim_ascii_start().strip_prefix(b"{").unwrap_or_default();
+    loop {
+        let mut keys = serde_json::Deserializer::from_slice(rest).into_iter::<String>();
+        let Some(Ok(name)) = keys.next() else {
+            break;
+        };
+        let Some(value) = rest[keys.byte_offset()..].trim_ascii_start().strip_prefix(b":") else {
+            break;
+        };
+        let value = value.trim_ascii_start();
+        answers |= name == "result" || name == "error";
+        let mut values = serde_json::Deserializer::from_slice(value).into_iter::<serde_json::Value>();
+        let Some(Ok(parsed)) = values.next() else {
+            break;
+        };
+        // A value at the very end may have been cut short (`12` of `123`).
+        let Some(next) = value[values.byte_offset()..].trim_ascii_start().split_first() else {
+            break;
+        };
+        match name.as_str() {
+            "id" => shape.id = Some(parsed),
+            "method" => shape.method = Some(serde::de::IgnoredAny),
+            _ => {}
+        }
+        match next {
+            (b',', after) => rest = after.trim_ascii_start(),
+            _ => break,
+        }
+    }
+    if shape.method.is_none() && !answers {
+        shape.id = None;
+    }
+    shape
+}
+
+/// The two ends of a stream: who sent the message and where it was headed,
+/// with the tap direction of bytes written to each.
+struct Peers<'a, S, D> {
+    source: &'a Mutex<Option<S>>,
+    source_direction: StreamDirection,
+    dest: &'a Mutex<Option<D>>,
+    dest_direction: StreamDirection,
+    framings: &'a Framings,
+}
+
+/// Handle a message that must not be forwarded (over `--max-message-bytes`, or
+/// breaking a rule under `--strict-protocol`). A request is answered with a
+/// JSON-RPC error back to its sender; a response is replaced by an error result
+/// for the original requester. The suppression is tapped so it lands in the audit
+/// log. Returns false once the tap channel is closed.
+async fn suppress<S, D>(
+    line: &[u8],
+    direction: StreamDirection,
+    reason: Suppression,
+    peers: Peers<'_, S, D>,
+    tx: &Tap,
+) -> bool
+where
+    S: AsyncWrite + Unpin,
+    D: AsyncWrite + Unpin,
+{
+    let digest = blake3::hash(line).to_hex().to_string();
+    let (kind, id) = kind_of(serde_json::from_slice::<MessageShape>(line).ok());
+
+    let (event, error) = match &reason {
+        Suppression::Oversized { limit } => {
+            eprintln!(
+                "⚠️  Suppressed oversized {} {:?} message ({} bytes > limit {})",
+                kind,
+                direction,
+                line.len(),
+                limit
+            );
+            (
+                RawTap::sentinel(
+                    "sentinel/message_suppressed",
+                    json!({
+                        "direction": direction,
+                        "kind": kind,
+                        "request_id": id,
+                        "size_bytes": line.len(),
+                        "limit_bytes": limit,
+                        "blake3": digest,
+                    }),
+                ),
+                json!({
+                    "code": OVERSIZED_MESSAGE_CODE,
+                    "message": format!("{} of {} bytes exceeds Sentinel max-message-bytes ({})", kind, line.len(), limit),
+                    "data": { "size_bytes": line.len(), "limit_bytes": limit, "blake3": digest },
+                }),
+            )
+        }
+        Suppression::ProtocolViolation { violations } => {
+            let codes: Vec<&str> = violations.iter().map(|r| r.code()).collect();
+            eprintln!(
+                "⚠️  Suppressed {} {:?} message violating JSON-RPC rules: {}",
+                kind,
+                direction,
+                codes.join(", ")
+            );
+            (
+                RawTap::sentinel(
+                    "sentinel/protocol_violation",
+                    json!({
+                        "direction": direction,
+                        "kind": kind,
+                        "request_id": id,
+                        "violations": codes,
+                        "action": "suppressed",
+                        "size_bytes": line.len(),
+                        "blake3": digest,
+                    }),
+                ),
+                json!({
+                    "code": PROTOCOL_VIOLATION_CODE,
+                    "message": format!("{} rejected by Sentinel strict protocol checks: {}", kind, codes.join(", ")),
+                    "data": { "violations": codes, "blake3": digest },
+                }),
+            )
+        }
+    };
+
+    if !tap(tx, event).await {
+        return false;
+    }
+    answer(kind, id, error, peers, tx).await
+}
+
+/// Send a JSON-RPC `error` in place of a withheld message: a request's to its
+/// sender, a response's on to the original requester. Nothing answers a
+/// notification or a message without an id. The reply is tapped like any
+/// other traffic. Returns false once the tap channel is closed.
+async fn answer<S, D>(
+    kind: &str,
+    id: Option<serde_json::Value>,
+    error: serde_json::Value,
+    peers: Peers<'_, S, D>,
+    tx: &Tap,
+) -> bool
+where
+    S: AsyncWrite + Unpin,
+    D: AsyncWrite + Unpin,
+{
+    let Some(id) = id else {
+        return true;
+    };
+
+    // An id that is not a string or number can't be echoed back; JSON-RPC says null.
+    let id = if id.is_string() || id.is_number() { id } else { serde_json::Value::Null };
+    let reply = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": error,
     });
+    let body = serde_json::to_vec(&reply).unwrap_or_default();
+
+    let delivered_direction = if kind == "request" {
+        let bytes = peers.framings.encode(peers.source_direction, &body);
+        write_line(peers.source, &bytes).await.then_some(peers.source_direction)
+    } else {
+        let bytes = peers.framings.encode(peers.dest_direction, &body);
+        write_line(peers.dest, &bytes).await.then_some(peers.dest_direction)
+    };
+
+    match delivered_direction {
+        Some(direction) => {
+            tap(
+                tx,
+                RawTap {
+                    direction,
+                    bytes: Bytes::from(body),
+                    observed_ts_ms: current_timestamp_ms(),
+                    server: None,
+                    dropped_before: [0; 4],
+                },
+            )
+            .await
+        }
+        None => true,
+    }
+}
+
+/// Hold a message until its direction's byte budget allows it. An episode that the
+/// message ends is tapped first. Returns false once the tap channel is closed.
+async fn pace(
+    throttle: &mut Option<Throttle>,
+    len: usize,
+    direction: StreamDirection,
+    tx: &Tap,
+) -> bool {
+    let Some(t) = throttle.as_mut() else {
+        return true;
+    };
+    match t.pace(len).await {
+        Some(episode) => tap(tx, throttled_event(direction, episode)).await,
+        None => true,
+    }
+}
 
-    let _ = tokio::join!(stdin_handle, stdout_handle);
-    let status = child.wait().await?;
-    process::exit(status.code().unwrap_or(1));
+/// Record an episode still open when the stream ends.
+async fn end_throttling(throttle: &mut Option<Throttle>, direction: StreamDirection, tx: &Tap) {
+    if let Some(episode) = throttle.as_mut().and_then(Throttle::finish) {
+        tap(tx, throttled_event(direction, episode)).await;
+    }
+}
+
+fn throttled_event(direction: StreamDirection, mut episode: serde_json::Value) -> RawTap {
+    eprintln!("🐢 Throttled {:?} traffic: {}", direction, episode);
+    episode["direction"] = json!(direction);
+    RawTap::sentinel("sentinel/throttled", episode)
+}
+
+/// Write to a shared writer; false if it has been closed or the write failed.
+async fn write_line<W: AsyncWrite + Unpin>(writer: &Mutex<Option<W>>, bytes: &[u8]) -> bool {
+    write_raw(writer, bytes).await && {
+        flush(writer).await;
+        true
+    }
+}
+
+/// [`write_line`] without the flush, for a message written in pieces.
+async fn write_raw<W: AsyncWrite + Unpin>(writer: &Mutex<Option<W>>, bytes: &[u8]) -> bool {
+    match writer.lock().await.as_mut() {
+        Some(w) => w.write_all(bytes).await.is_ok(),
+        None => false,
+    }
+}
+
+async fn flush<W: AsyncWrite + Unpin>(writer: &Mutex<Option<W>>) {
+    if let Some(w) = writer.lock().await.as_mut() {
+        let _ = w.flush().await;
+    }
+}
+
+async fn tap(tx: &Tap, raw: RawTap) -> bool {
+    tx.send(raw).await
+}
+
+#[cfg(test)]
+pub mod test_proxy {
+    use super::*;
+    use crate::tap::{TapDrops, TapOverflow};
+    use tokio::io::AsyncReadExt;
+
+    /// A config with the CLI's default line limit.
+    pub fn config() -> ProxyConfig {
+        ProxyConfig {
+            max_line_bytes: 4 * 1024 * 1024,
+            ..Default::default()
+        }
+    }
+
+    /// Proxy `input` from the client to a `cat` child, which echoes whatever
+    /// reaches it. Returns what the client got back and every tap, in order.
+    pub async fn through_cat(config: ProxyConfig, input: Vec<u8>) -> (Vec<u8>, Vec<RawTap>) {
+        through(&["cat"], config, input).await
+    }
+
+    /// [`through_cat`] with any child.
+    pub async fn through(command: &[&str], config: ProxyConfig, input: Vec<u8>) -> (Vec<u8>, Vec<RawTap>) {
+        let (mut client_in, proxy_in) = tokio::io::duplex(64 * 1024);
+        let (proxy_out, mut client_out) = tokio::io::duplex(64 * 1024);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+        let tap = Tap::new(tx, TapOverflow::Block, Arc::new(TapDrops::default()));
+
+        let writer = tokio::spawn(async move {
+            let _ = client_in.write_all(&input).await;
+            let _ = client_in.shutdown().await;
+        });
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let _ = client_out.read_to_end(&mut received).await;
+            received
+        });
+        let taps = tokio::spawn(async move {
+            let mut taps = Vec::new();
+            while let Some(raw) = rx.recv().await {
+                taps.push(raw);
+            }
+            taps
+        });
+
+        let client = Client {
+            input: Box::new(proxy_in),
+            output: Box::new(proxy_out),
+        };
+        let command = command.iter().map(|arg| arg.to_string()).collect();
+        let status = run_proxy(Upstream::Command(command), client, tap, config, CancellationToken::new())
+            .await
+            .expect("proxy runs");
+        assert!(status.is_some_and(|s| s.success()));
+        writer.await.unwrap();
+        (reader.await.unwrap(), taps.await.unwrap())
+    }
+
+    /// The `sentinel/...` notifications among `taps`, as (method, params).
+    pub fn sentinel_events(taps: &[RawTap]) -> Vec<(String, serde_json::Value)> {
+        taps.iter()
+            .filter(|t| t.direction == StreamDirection::Sentinel)
+            .filter_map(|t| serde_json::from_slice::<serde_json::Value>(&t.bytes).ok())
+            .map(|v| (v["method"].as_str().unwrap_or_default().to_string(), v["params"].clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_proxy::{self, sentinel_events};
+    use super::*;
+    use serde_json::Value;
+
+    const LIMIT: usize = 1000;
+
+    fn limited() -> ProxyConfig {
+        ProxyConfig {
+            max_message_bytes: Some(LIMIT),
+            ..test_proxy::config()
+        }
+    }
+
+    fn messages(received: &[u8]) -> Vec<Value> {
+        received
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap())
+            .collect()
+    }
+
+    fn oversized_lines(taps: &[RawTap]) -> Vec<Value> {
+        sentinel_events(taps)
+            .into_iter()
+            .filter(|(method, _)| method == "sentinel/oversized_line")
+            .map(|(_, params)| params)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn an_oversized_request_is_answered_with_an_error_and_never_reaches_the_server() {
+        let big = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"blob": "a".repeat(5 * LIMIT)}});
+        let mut input = serde_json::to_vec(&big).unwrap();
+        input.push(b'\n');
+        let size = input.len();
+        input.extend_from_slice(b"{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"ping\"}\n");
+
+        let (received, taps) = test_proxy::through_cat(limited(), input).await;
+
+        let replies = messages(&received);
+        assert_eq!(replies.len(), 2, "{:?}", replies);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[0]["error"]["code"], OVERSIZED_MESSAGE_CODE);
+        assert_eq!(replies[0]["error"]["data"]["size_bytes"], size);
+        assert_eq!(replies[0]["error"]["data"]["limit_bytes"], LIMIT);
+        // The stream goes on: the next request reached `cat` and came back.
+        assert_eq!(replies[1]["id"], 2);
+        assert_eq!(replies[1]["method"], "ping");
+
+        let suppressed = oversized_lines(&taps);
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0]["direction"], "Outbound");
+        assert_eq!(suppressed[0]["action"], "suppressed");
+        assert_eq!(suppressed[0]["size_bytes"], size);
+        assert_eq!(suppressed[0]["blake3"], replies[0]["error"]["data"]["blake3"]);
+        // Nothing of it was tapped as traffic but its error reply.
+        assert!(taps
+            .iter()
+            .filter(|t| t.direction == StreamDirection::Outbound)
+            .all(|t| t.bytes.len() < LIMIT));
+    }
+
+    #[tokio::test]
+    async fn an_oversized_response_is_dropped_and_the_client_gets_an_error() {
+        // Answers the first request with a result far over the limit, then echoes.
+        let script = format!(
+            "read -r line; printf '{{\"jsonrpc\":\"2.0\",\"id\":\"big\",\"result\":{{\"text\":\"%s\"}}}}\\n' {}; exec cat",
+            "a".repeat(5 * LIMIT)
+        );
+        let input = b"{\"jsonrpc\":\"2.0\",\"id\":\"big\",\"method\":\"tools/call\"}\n\
+                      {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"ping\"}\n";
+
+        let (received, taps) = test_proxy::through(&["sh", "-c", &script], limited(), input.to_vec()).await;
+
+        let replies = messages(&received);
+        assert_eq!(replies.len(), 2, "{:?}", replies);
+        assert_eq!(replies[0]["id"], "big");
+        assert_eq!(replies[0]["error"]["code"], OVERSIZED_MESSAGE_CODE);
+        assert!(replies[0].get("result").is_none());
+        assert!(received.len() < LIMIT * 2);
+        assert_eq!(replies[1]["id"], 2);
+
+        let suppressed = oversized_lines(&taps);
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0]["direction"], "Inbound");
+        assert_eq!(suppressed[0]["action"], "suppressed");
+        assert!(suppressed[0]["size_bytes"].as_u64().unwrap() > 5 * LIMIT as u64);
+    }
+
+    /// Passed through both ways (to `cat` and back) whole, while only the
+    /// first `max_line_bytes` of it are held and tapped.
+    #[tokio::test]
+    async fn a_64_mb_line_is_forwarded_whole_and_tapped_truncated() {
+        const LEN: usize = 64 * 1024 * 1024;
+        let config = test_proxy::config();
+        let max = config.max_line_bytes;
+        let mut line = vec![b'a'; LEN];
+        line[LEN - 1] = b'\n';
+        let digest = blake3::hash(&line).to_hex().to_string();
+
+        let (received, taps) = test_proxy::through_cat(config, line).await;
+
+        assert_eq!(received.len(), LEN);
+        assert_eq!(blake3::hash(&received).to_hex().to_string(), digest);
+        let oversized = oversized_lines(&taps);
+        assert_eq!(oversized.len(), 2);
+        for direction in ["Outbound", "Inbound"] {
+            let event = oversized.iter().find(|e| e["direction"] == direction).unwrap();
+            assert_eq!(event["action"], "forwarded");
+            assert_eq!(event["size_bytes"], LEN);
+            assert_eq!(event["limit_bytes"], max);
+            assert_eq!(event["blake3"], digest.as_str());
+            assert_eq!(event["truncated"].as_str().unwrap().len(), max);
+        }
+        assert!(taps.iter().all(|t| t.bytes.len() < max + 1024));
+    }
+
+    #[test]
+    fn the_start_of_a_message_gives_away_its_id_and_method() {
+        let shape = |head: &str| kind_of(Some(head_shape(head.as_bytes())));
+        assert_eq!(shape(r#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"a":"bb"#), ("request", Some(json!(7))));
+        assert_eq!(shape(r#" { "id" : "x" , "result": {"te"#), ("response", Some(json!("x"))));
+        assert_eq!(shape(r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"#), ("notification", None));
+        // The id comes after what was cut off: nothing to answer.
+        assert_eq!(shape(r#"{"jsonrpc":"2.0","params":{"blob":"aaaa"#), ("unparsed", None));
+        assert_eq!(shape(r#"{"id":12"#), ("unparsed", None));
+        assert_eq!(shape(r#"{"jsonrpc":"2.0","id":12,"method":"tools/call""#), ("unparsed", None));
+        // Could still be a request whose method comes after its params.
+        assert_eq!(shape(r#"{"jsonrpc":"2.0","id":3,"params":{"blob":"aaaa"#), ("unparsed", None));
+        assert_eq!(shape("aaaa"), ("unparsed", None));
+    }
 }
@@ -0,0 +1,118 @@
+/// Brace/bracket depth of a JSON text read so far, strings skipped.
+#[derive(Debug, Default)]
+struct Scan {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Closed more than it opened: not the start of a JSON value
+    broken: bool,
+}
+
+impl Scan {
+    fn feed(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.in_string {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => match self.depth.checked_sub(1) {
+                    Some(depth) => self.depth = depth,
+                    None => self.broken = true,
+                },
+                b'"' => self.in_string = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        !self.broken && (self.depth > 0 || self.in_string)
+    }
+}
+
+/// What to do with a line fed to a [`Reassembler`].
+pub enum Step {
+    /// Not part of a split message: parse the line as it is
+    Pass,
+    /// Held until the message is complete
+    Hold,
+    /// The lines held so far close a message: parse these bytes, observed at
+    /// the first line's time
+    Complete { bytes: Vec<u8>, observed_ts_ms: u64 },
+    /// The held message outgrew the limit; `bytes` is everything held
+    Overflow { bytes: Vec<u8>, observed_ts_ms: u64 },
+}
+
+/// Joins a JSON message a peer wrote over several lines (pretty-printed, or
+/// flushed mid-object at a newline) back into one, for one direction. Lines
+/// are held with their line endings, so the joined bytes are exactly what was
+/// on the wire.
+pub struct Reassembler {
+    max_bytes: usize,
+    held: Vec<u8>,
+    observed_ts_ms: u64,
+    scan: Scan,
+}
+
+impl Reassembler {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            held: Vec::new(),
+            observed_ts_ms: 0,
+            scan: Scan::default(),
+        }
+    }
+
+    pub fn is_holding(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Feed a tapped line. While nothing is held, only call this for lines that
+    /// failed to parse: one that starts a JSON object or array without closing
+    /// it is held; anything else passes.
+    pub fn push(&mut self, line: &[u8], observed_ts_ms: u64) -> Step {
+        if !self.is_holding() {
+            let start = line.iter().find(|b| !b.is_ascii_whitespace());
+            if !matches!(start, Some(b'{' | b'[')) {
+                return Step::Pass;
+            }
+            let mut scan = Scan::default();
+            scan.feed(line);
+            if !scan.is_open() {
+                return Step::Pass;
+            }
+            self.scan = scan;
+            self.observed_ts_ms = observed_ts_ms;
+        } else {
+            self.scan.feed(line);
+        }
+        self.held.extend_from_slice(line);
+
+        if self.held.len() > self.max_bytes {
+            let (bytes, observed_ts_ms) = self.take().unwrap_or_default();
+            return Step::Overflow { bytes, observed_ts_ms };
+        }
+        if self.scan.is_open() {
+            return Step::Hold;
+        }
+        let (bytes, observed_ts_ms) = self.take().unwrap_or_default();
+        Step::Complete { bytes, observed_ts_ms }
+    }
+
+    /// Everything held, with the time its first line was observed.
+    pub fn take(&mut self) -> Option<(Vec<u8>, u64)> {
+        if !self.is_holding() {
+            return None;
+        }
+        self.scan = Scan::default();
+        Some((std::mem::take(&mut self.held), self.observed_ts_ms))
+    }
+}
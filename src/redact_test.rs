@@ -0,0 +1,98 @@
+//! `sentinel redact-test`: apply a redaction config to sample events and count
+//! what each rule replaces, without running anything. The input is a plaintext
+//! audit log or JSONL of bare events; nothing is written back.
+
+use crate::audit::{self, AuditRecord};
+use crate::audit_crypto;
+use crate::events::McpLog;
+use crate::redaction::{self, RedactionPolicy, RedactionReport};
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+pub struct RedactTestOptions {
+    pub config: PathBuf,
+    pub input: String,
+    /// Write a unified diff of each event redaction changed
+    pub show_diff: bool,
+}
+
+pub struct RedactTestOutcome {
+    pub events: u64,
+    /// Events at least one rule fired on
+    pub redacted: u64,
+    /// Events scanned only up to `max_scan_bytes`
+    pub truncated: u64,
+    /// Every rule the policy applies, in order, with its hits
+    pub hits: Vec<(String, u64)>,
+    /// Rules the config asks for that never fired
+    pub never_matched: Vec<String>,
+}
+
+/// Redact the events of `opts.input` with the config at `opts.config`, writing
+/// the diffs to `out`. An invalid config is an error, as it is for `run`.
+pub fn redact_test(opts: &RedactTestOptions, out: &mut impl Write) -> Result<RedactTestOutcome, String> {
+    let policy = RedactionPolicy::load(&opts.config)?;
+    let log = audit_crypto::open_plaintext(&opts.input, None)?;
+
+    let mut events = 0u64;
+    let mut redacted = 0u64;
+    let mut truncated = 0u64;
+    let mut total = RedactionReport::default();
+    for (i, line) in BufReader::new(log.reader).lines().enumerate() {
+        let line = line.map_err(|e| format!("read {}: {}", opts.input, e))?;
+        let line = audit::normalize_line(&line);
+        if line.is_empty() || (i == 0 && audit::parse_file_header(line)?.is_some()) {
+            continue;
+        }
+        let Some(mut event) = parse_event(line).map_err(|e| format!("line {}: {}", i + 1, e))? else {
+            continue;
+        };
+        events += 1;
+
+        let before = opts.show_diff.then(|| event.clone());
+        let report = redaction::redact_log(&mut event, &policy);
+        truncated += u64::from(report.truncated_scan());
+        if report.is_empty() {
+            continue;
+        }
+        redacted += 1;
+        total.add(&report);
+        if let Some(before) = before {
+            write_diff(out, i + 1, &before, &event).map_err(|e| format!("write diff: {}", e))?;
+        }
+    }
+
+    let hits = policy.rule_names().into_iter().map(|rule| {
+        let n = total.get(&rule);
+        (rule, n)
+    });
+    let never_matched = policy.configured_rule_names().into_iter().filter(|rule| total.get(rule) == 0).collect();
+    Ok(RedactTestOutcome { events, redacted, truncated, hits: hits.collect(), never_matched })
+}
+
+/// The event on `line`: an audit log `Event` record or a bare event. Other
+/// records of a log are skipped.
+fn parse_event(line: &str) -> Result<Option<McpLog>, String> {
+    let value: serde_json::Value = serde_json::from_str(line).map_err(|e| format!("JSON parse error: {}", e))?;
+    if value.get("record_type").is_none() {
+        return serde_json::from_value(value).map(Some).map_err(|e| format!("not an event: {}", e));
+    }
+    match serde_json::from_value::<AuditRecord>(value) {
+        Ok(AuditRecord::Event { log, .. }) => Ok(Some(log)),
+        Ok(_) => Ok(None),
+        Err(e) => Err(format!("bad record: {}", e)),
+    }
+}
+
+fn write_diff(out: &mut impl Write, line: usize, before: &McpLog, after: &McpLog) -> std::io::Result<()> {
+    let name = format!("event {} (line {}, {})", after.event_id, line, after.method.as_deref().unwrap_or("-"));
+    let pretty = |log: &McpLog| serde_json::to_string_pretty(log).unwrap_or_default() + "\n";
+    let (before, after) = (pretty(before), pretty(after));
+    let diff = similar::TextDiff::from_lines(&before, &after);
+    write!(
+        out,
+        "{}",
+        diff.unified_diff().context_radius(2).header(&format!("{} before", name), &format!("{} after", name))
+    )
+}
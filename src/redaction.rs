@@ -1,65 +1,1489 @@
-use regex::Regex;
+use crate::audit_crypto;
+
+use base64::engine::general_purpose::{
+    GeneralPurpose, STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD,
+};
+use base64::Engine as _;
+use regex::{Captures, Regex};
+use serde::Deserialize;
 use serde_json::Value;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::UnicodeNormalization;
 
 lazy_static::lazy_static! {
+    /// A PEM private key block; to the end of the text if it was cut before its END line
+    static ref PRIVATE_KEY_PATTERN: Regex = Regex::new(r#"(?s)-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY-----.*?(?:-----END (?:[A-Z0-9]+ )*PRIVATE KEY-----|\z)"#).unwrap();
+    /// Three dot-separated base64url parts, the first a JSON header
+    static ref JWT_PATTERN: Regex = Regex::new(r#"\beyJ[A-Za-z0-9_-]{5,}\.[A-Za-z0-9_-]{5,}\.[A-Za-z0-9_-]{10,}"#).unwrap();
+    static ref AWS_ACCESS_KEY_PATTERN: Regex = Regex::new(r#"\b(?:AKIA|ASIA|AGPA|AIDA|AROA|ANPA|ANVA|AIPA)[0-9A-Z]{16}\b"#).unwrap();
+    /// Classic (`ghp_`, `gho_`, `ghu_`, `ghs_`, `ghr_`) and fine-grained tokens
+    static ref GITHUB_TOKEN_PATTERN: Regex = Regex::new(r#"\b(?:gh[pousr]_[A-Za-z0-9]{36,255}|github_pat_[A-Za-z0-9_]{22,255})\b"#).unwrap();
+    static ref SLACK_TOKEN_PATTERN: Regex = Regex::new(r#"\bxox[abposr]-[A-Za-z0-9-]{10,}"#).unwrap();
     static ref API_KEY_PATTERN: Regex = Regex::new(r#"(?i)(?:api[_-]?key|apikey|access[_-]?token|secret[_-]?key)\s*[:=]\s*["']?([a-zA-Z0-9_\-]{32,})["']?"#).unwrap();
     static ref SK_KEY_PATTERN: Regex = Regex::new(r#"sk-[a-zA-Z0-9]{32,}"#).unwrap();
     static ref EMAIL_PATTERN: Regex = Regex::new(r#"\b[\w\.-]+@[\w\.-]+\.\w+\b"#).unwrap();
     static ref TOKEN_PATTERN: Regex = Regex::new(r#"(?i)(?:token|bearer)\s*[:=]\s*["']?([a-zA-Z0-9_\-\.]{20,})["']?"#).unwrap();
+    /// 13 to 19 digits, maybe grouped by spaces or dashes; checked with Luhn
+    static ref CREDIT_CARD_PATTERN: Regex = Regex::new(r#"\b\d(?:[ -]?\d){12,18}\b"#).unwrap();
+    /// AAA-GG-SSSS; area, group and serial are checked for ranges never issued
+    static ref SSN_PATTERN: Regex = Regex::new(r#"\b(\d{3})-(\d{2})-(\d{4})\b"#).unwrap();
+    /// NANP-style numbers with separators, or international ones with a `+`
+    static ref PHONE_PATTERN: Regex = Regex::new(r#"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)\s?|\b\d{3}[\s.-])\d{3}[\s.-]\d{4}\b|\+\d{8,15}\b"#).unwrap();
+    static ref IPV4_PATTERN: Regex = Regex::new(r#"\b\d{1,3}(?:\.\d{1,3}){3}\b"#).unwrap();
+    /// Candidates only: a match is an address if it parses as one
+    static ref IPV6_PATTERN: Regex = Regex::new(r#"(?i)[0-9a-f]{0,4}(?::[0-9a-f]{0,4}){2,7}"#).unwrap();
+    /// An absolute Unix or Windows path with at least one directory; not the path of a URL
+    static ref PATH_PATTERN: Regex = Regex::new(r#"(^|[\s"'(\[=,])(?:[A-Za-z]:)?[/\\](?:[^\s/\\"']+[/\\])+([^\s/\\"']+)"#).unwrap();
+    /// A value a vault rule encrypted, inside a string
+    static ref VAULT_TOKEN_PATTERN: Regex = Regex::new(r#"\{"sentinel_vault":"([A-Za-z0-9+/]*={0,2})"\}"#).unwrap();
+    /// First frame of a JavaScript, Python, Java or Rust stack trace
+    static ref STACK_FRAME_PATTERN: Regex = Regex::new(r#"(?m)^(?:Traceback \(most recent call last\)|\s+at |\s+File "|stack backtrace:)"#).unwrap();
+}
+
+/// Which copies of an event are redacted (`run --redact-scope`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RedactScope {
+    /// The audit log and the dashboard; the log cannot show the raw traffic
+    #[default]
+    #[value(name = "audit+stream")]
+    AuditAndStream,
+    /// Only the dashboard stream and history; the log hashes the raw traffic
+    #[value(name = "stream-only")]
+    StreamOnly,
+}
+
+impl RedactScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RedactScope::AuditAndStream => "audit+stream",
+            RedactScope::StreamOnly => "stream-only",
+        }
+    }
+}
+
+/// How a run's start event records its redaction: the scope, or `off`.
+pub fn describe(scope: Option<RedactScope>) -> &'static str {
+    scope.map_or("off", |s| s.as_str())
+}
+
+/// `SENTINEL_REDACT_PII` (on unless set to something other than `1`/`true`).
+pub fn enabled_from_env() -> bool {
+    std::env::var("SENTINEL_REDACT_PII")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(true)
+}
+
+/// A built-in detector, named as a redaction config turns it on or off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Detector {
+    PrivateKey,
+    Jwt,
+    AwsAccessKey,
+    GithubToken,
+    SlackToken,
+    ApiKey,
+    SkKey,
+    Email,
+    Token,
+    CreditCard,
+    Ssn,
+    Phone,
+    Ip,
+    /// Long tokens of mixed letters and digits whose characters look random
+    HighEntropy,
+    /// Error text only: paths keep their last component
+    ErrorPaths,
+    /// Error text only: a stack trace is cut at its first frame
+    ErrorStackTraces,
+}
+
+impl Detector {
+    /// In the order they are applied
+    const ALL: [Detector; 16] = [
+        Detector::PrivateKey,
+        Detector::Jwt,
+        Detector::AwsAccessKey,
+        Detector::GithubToken,
+        Detector::SlackToken,
+        Detector::ApiKey,
+        Detector::SkKey,
+        Detector::Email,
+        Detector::Token,
+        Detector::CreditCard,
+        Detector::Ssn,
+        Detector::Phone,
+        Detector::Ip,
+        Detector::HighEntropy,
+        Detector::ErrorPaths,
+        Detector::ErrorStackTraces,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Detector::PrivateKey => "private_key",
+            Detector::Jwt => "jwt",
+            Detector::AwsAccessKey => "aws_access_key",
+            Detector::GithubToken => "github_token",
+            Detector::SlackToken => "slack_token",
+            Detector::ApiKey => "api_key",
+            Detector::SkKey => "sk_key",
+            Detector::Email => "email",
+            Detector::Token => "token",
+            Detector::CreditCard => "credit_card",
+            Detector::Ssn => "ssn",
+            Detector::Phone => "phone",
+            Detector::Ip => "ip",
+            Detector::HighEntropy => "high_entropy",
+            Detector::ErrorPaths => "error_paths",
+            Detector::ErrorStackTraces => "error_stack_traces",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.name() == name)
+    }
+
+    /// The detectors there were before redaction configs; the others are opt-in.
+    fn on_by_default(self) -> bool {
+        !matches!(
+            self,
+            Detector::CreditCard | Detector::Ssn | Detector::Phone | Detector::Ip | Detector::HighEntropy
+        )
+    }
+
+    /// Secrets are replaced with a typed marker, which `secrets_redacted` counts.
+    fn is_secret(self) -> bool {
+        matches!(
+            self,
+            Detector::PrivateKey
+                | Detector::Jwt
+                | Detector::AwsAccessKey
+                | Detector::GithubToken
+                | Detector::SlackToken
+                | Detector::HighEntropy
+        )
+    }
+
+    /// `text` with what this detector finds replaced, each replacement counted in `found`.
+    fn apply(self, text: String, secrets: &SecretSettings, found: &mut RedactionReport) -> String {
+        let hits = Cell::new(0u64);
+        let hit = |replacement: String| {
+            hits.set(hits.get() + 1);
+            replacement
+        };
+        let marked_if = |redact: bool, matched: &str| match redact {
+            true => hit(format!("[REDACTED:{}]", self.name())),
+            false => matched.to_string(),
+        };
+        let secret = |matched: &str| marked_if(!secrets.allowed(matched), matched);
+        let replaced = match self {
+            Detector::PrivateKey => replace_all(&PRIVATE_KEY_PATTERN, &text, |caps: &Captures<'_>| secret(&caps[0])),
+            Detector::Jwt => replace_all(&JWT_PATTERN, &text, |caps: &Captures<'_>| secret(&caps[0])),
+            Detector::AwsAccessKey => replace_all(&AWS_ACCESS_KEY_PATTERN, &text, |caps: &Captures<'_>| secret(&caps[0])),
+            Detector::GithubToken => replace_all(&GITHUB_TOKEN_PATTERN, &text, |caps: &Captures<'_>| secret(&caps[0])),
+            Detector::SlackToken => replace_all(&SLACK_TOKEN_PATTERN, &text, |caps: &Captures<'_>| secret(&caps[0])),
+            Detector::HighEntropy => replace_all(&secrets.candidate, &text, |caps: &Captures<'_>| {
+                let token = &caps[0];
+                let mixed = token.bytes().any(|b| b.is_ascii_digit()) && token.bytes().any(|b| b.is_ascii_alphabetic());
+                match mixed && shannon_entropy(token) > secrets.min_entropy {
+                    true => secret(token),
+                    false => token.to_string(),
+                }
+            }),
+            Detector::ApiKey => replace_all(&API_KEY_PATTERN, &text, |caps: &Captures<'_>| hit(partial(&caps[0]))),
+            Detector::SkKey => replace_all(&SK_KEY_PATTERN, &text, |caps: &Captures<'_>| hit(partial(&caps[0]))),
+            Detector::Email => replace_all(&EMAIL_PATTERN, &text, |caps: &Captures<'_>| {
+                let email = &caps[0];
+                hit(match email.find('@') {
+                    Some(at_pos) => {
+                        let local = &email[..at_pos];
+                        let cut = local.char_indices().nth(3).map_or(local.len(), |(i, _)| i);
+                        format!("{}***@***", &local[..cut])
+                    }
+                    None => "***".to_string(),
+                })
+            }),
+            Detector::Token => replace_all(&TOKEN_PATTERN, &text, |caps: &Captures<'_>| hit(partial(&caps[0]))),
+            Detector::CreditCard => {
+                replace_all(&CREDIT_CARD_PATTERN, &text, |caps: &Captures<'_>| marked_if(luhn_valid(&caps[0]), &caps[0]))
+            }
+            Detector::Ssn => replace_all(&SSN_PATTERN, &text, |caps: &Captures<'_>| {
+                let issued = !matches!(&caps[1], "000" | "666")
+                    && !caps[1].starts_with('9')
+                    && &caps[2] != "00"
+                    && &caps[3] != "0000";
+                marked_if(issued, &caps[0])
+            }),
+            Detector::Phone => replace_all(&PHONE_PATTERN, &text, |caps: &Captures<'_>| marked_if(true, &caps[0])),
+            Detector::Ip => {
+                let v4 = replace_all(&IPV4_PATTERN, &text, |caps: &Captures<'_>| {
+                    marked_if(caps[0].parse::<Ipv4Addr>().is_ok(), &caps[0])
+                });
+                let v4 = v4.into_owned();
+                let v6 = replace_all_in(&IPV6_PATTERN, &v4, |haystack, caps: &Captures<'_>| {
+                    let m = caps.get(0).unwrap();
+                    marked_if(is_ipv6_at(haystack, m.start(), m.end()), m.as_str())
+                });
+                Cow::Owned(v6.into_owned())
+            }
+            Detector::ErrorPaths | Detector::ErrorStackTraces => Cow::Borrowed(text.as_str()),
+        };
+        let replaced = match replaced {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(s) => s,
+        };
+        found.hit_n(self.name(), hits.get());
+        replaced
+    }
+}
+
+/// Bits per character of `s`, from the frequency of its bytes.
+fn shannon_entropy(s: &str) -> f64 {
+    let mut counts = [0usize; 256];
+    for b in s.bytes() {
+        counts[b as usize] += 1;
+    }
+    let len = s.len() as f64;
+    counts
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// What redaction replaced: how many times each rule fired, by the name
+/// [`RedactionPolicy::rule_names`] gives it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RedactionReport {
+    rules: BTreeMap<String, u64>,
+    /// Strings past `max_scan_bytes` were left unscanned
+    truncated_scan: bool,
+}
+
+impl RedactionReport {
+    fn hit_n(&mut self, rule: &str, n: u64) {
+        if n > 0 {
+            match self.rules.get_mut(rule) {
+                Some(count) => *count += n,
+                None => {
+                    self.rules.insert(rule.to_string(), n);
+                }
+            }
+        }
+    }
+
+    pub fn add(&mut self, other: &RedactionReport) {
+        for (rule, n) in &other.rules {
+            self.hit_n(rule, *n);
+        }
+        self.truncated_scan |= other.truncated_scan;
+    }
+
+    /// No rule fired.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Part of the event went unscanned, over the config's `max_scan_bytes`.
+    pub fn truncated_scan(&self) -> bool {
+        self.truncated_scan
+    }
+
+    /// How many times `rule` fired.
+    pub fn get(&self, rule: &str) -> u64 {
+        self.rules.get(rule).copied().unwrap_or(0)
+    }
+
+    /// Replacements of every rule together.
+    pub fn total(&self) -> u64 {
+        self.rules.values().sum()
+    }
+
+    /// The counts of the secret detectors: secrets redacted by type.
+    pub fn secrets(&self) -> BTreeMap<&str, u64> {
+        self.rules
+            .iter()
+            .filter(|(rule, _)| Detector::from_name(rule).is_some_and(Detector::is_secret))
+            .map(|(rule, n)| (rule.as_str(), *n))
+            .collect()
+    }
+
+    /// Record the report on the event it is of, as `redactions` and
+    /// `redaction_truncated_scan`; adds to what an earlier redaction of the
+    /// event recorded, and leaves an event nothing was redacted from as it is.
+    pub fn mark(&self, log: &mut crate::events::McpLog) {
+        if self.truncated_scan {
+            log.redaction_truncated_scan = true;
+        }
+        if self.is_empty() {
+            return;
+        }
+        let counts = log.redactions.get_or_insert_with(BTreeMap::new);
+        for (rule, n) in &self.rules {
+            *counts.entry(rule.clone()).or_default() += n;
+        }
+    }
+}
+
+/// Redactions of a run so far, for the `sentinel/shutdown` event and `/api/stats`.
+#[derive(Debug, Default)]
+pub struct RedactionTotals {
+    report: Mutex<RedactionReport>,
+    /// Events anything was redacted from
+    events: AtomicU64,
+    /// Events scanned only up to `max_scan_bytes`
+    truncated_scans: AtomicU64,
+}
+
+impl RedactionTotals {
+    pub fn record(&self, report: &RedactionReport) {
+        if report.truncated_scan() {
+            self.truncated_scans.fetch_add(1, Ordering::Relaxed);
+        }
+        if report.is_empty() {
+            return;
+        }
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.report.lock().unwrap_or_else(|e| e.into_inner()).add(report);
+    }
+
+    /// `{"events": .., "total": .., "rules": {<rule>: <count>}, "truncated_scans": ..}`
+    pub fn snapshot(&self) -> Value {
+        let report = self.report.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::json!({
+            "events": self.events.load(Ordering::Relaxed),
+            "total": report.total(),
+            "rules": &report.rules,
+            "truncated_scans": self.truncated_scans.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Secrets redacted by type, as `secrets_redacted` reports them.
+    pub fn secrets(&self) -> Value {
+        let report = self.report.lock().unwrap_or_else(|e| e.into_inner());
+        serde_json::json!(report.secrets())
+    }
+}
+
+/// False-positive controls of the secret detectors.
+struct SecretSettings {
+    /// Tokens `high_entropy` considers, at least `min_length` long
+    candidate: Regex,
+    /// Bits per character above which a candidate is a secret
+    min_entropy: f64,
+    /// A secret matching one of these is left as it is
+    allow: Vec<Regex>,
+}
+
+impl SecretSettings {
+    fn new(spec: &SecretsSpec) -> Result<Self, String> {
+        if spec.min_length == 0 {
+            return Err("redaction secrets: min_length must be at least 1".to_string());
+        }
+        let candidate = Regex::new(&format!(r"[A-Za-z0-9+/_-]{{{},}}={{0,2}}", spec.min_length))
+            .map_err(|e| format!("redaction secrets: min_length {}: {}", spec.min_length, e))?;
+        let allow = spec
+            .allow
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| format!("redaction secrets: allow pattern {:?}: {}", p, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { candidate, min_entropy: spec.min_entropy, allow })
+    }
+
+    fn allowed(&self, secret: &str) -> bool {
+        self.allow.iter().any(|re| re.is_match(secret))
+    }
+}
+
+/// What the detectors and custom rules match against, when it differs from the
+/// text itself: each cluster (a character and the combining marks after it)
+/// NFKC-folded, so full-width digits and letters read as ASCII; zero-width
+/// characters left out; and `%XX` escapes of printable ASCII decoded.
+struct Shadow {
+    text: String,
+    /// For each byte of `text`, the range of the original it came from
+    spans: Vec<(usize, usize)>,
+}
+
+impl Shadow {
+    /// `None` when matching `text` as it is finds the same.
+    fn of(text: &str) -> Option<Self> {
+        if text.is_ascii() && !text.contains('%') {
+            return None;
+        }
+        let mut shadow = Shadow { text: String::with_capacity(text.len()), spans: Vec::with_capacity(text.len()) };
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if is_zero_width(c) {
+                continue;
+            }
+            if let Some(decoded) = (c == '%').then(|| percent_decoded(&text[start..])).flatten() {
+                shadow.push(decoded, (start, start + 3));
+                chars.nth(1);
+                continue;
+            }
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, mark)) = chars.peek().filter(|(_, m)| canonical_combining_class(*m) != 0) {
+                end = i + mark.len_utf8();
+                chars.next();
+            }
+            for folded in text[start..end].nfkc().filter(|c| !is_zero_width(*c)) {
+                shadow.push(folded, (start, end));
+            }
+        }
+        (shadow.text != text).then_some(shadow)
+    }
+
+    fn push(&mut self, c: char, span: (usize, usize)) {
+        self.text.push(c);
+        self.spans.extend(std::iter::repeat_n(span, c.len_utf8()));
+    }
+
+    /// The range of the original that `self.text[start..end]` came from.
+    fn original(&self, start: usize, end: usize, len: usize) -> (usize, usize) {
+        let from = self.spans.get(start).map_or(len, |s| s.0);
+        match end > start {
+            true => (from, self.spans[end - 1].1),
+            false => (from, from),
+        }
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' | '\u{00AD}' | '\u{180E}')
+}
+
+/// The printable ASCII character a `%XX` escape at the start of `s` stands for.
+fn percent_decoded(s: &str) -> Option<char> {
+    let byte = u8::from_str_radix(s.get(1..3)?, 16).ok()?;
+    (byte.is_ascii_graphic() || byte == b' ').then_some(byte as char)
+}
+
+/// `re.replace_all` over the [`Shadow`] of `text`, each replacement put in
+/// place of the original characters its match came from. A replacement that
+/// is the match itself leaves them as written.
+fn replace_all<'t>(re: &Regex, text: &'t str, mut f: impl FnMut(&Captures<'_>) -> String) -> Cow<'t, str> {
+    replace_all_in(re, text, |_, caps| f(caps))
+}
+
+/// [`replace_all`], also giving `f` the text the captures are of.
+fn replace_all_in<'t>(re: &Regex, text: &'t str, mut f: impl FnMut(&str, &Captures<'_>) -> String) -> Cow<'t, str> {
+    let Some(shadow) = Shadow::of(text) else {
+        return re.replace_all(text, |caps: &Captures<'_>| f(text, caps));
+    };
+    let mut out = String::new();
+    let mut last = 0;
+    let mut replaced = false;
+    for caps in re.captures_iter(&shadow.text) {
+        let m = caps.get(0).unwrap();
+        let (start, end) = shadow.original(m.start(), m.end(), text.len());
+        // Within a cluster an earlier match already replaced
+        if start < last {
+            continue;
+        }
+        let replacement = f(&shadow.text, &caps);
+        if replacement != m.as_str() {
+            out.push_str(&text[last..start]);
+            out.push_str(&replacement);
+            last = end;
+            replaced = true;
+        }
+    }
+    if !replaced {
+        return Cow::Borrowed(text);
+    }
+    out.push_str(&text[last..]);
+    Cow::Owned(out)
+}
+
+/// Longest string decoded to look for JSON in it
+const BASE64_JSON_MAX_LEN: usize = 1 << 20;
+
+/// The JSON object or array `s` is the base64 of (standard or URL-safe, padded
+/// or not), and the engine that decodes it.
+fn base64_json(s: &str) -> Option<(Value, &'static GeneralPurpose)> {
+    // `{` and `[` encode to `e` and `W`
+    if !(16..=BASE64_JSON_MAX_LEN).contains(&s.len()) || !(s.starts_with("ey") || s.starts_with('W')) {
+        return None;
+    }
+    if !s.bytes().all(|b| b.is_ascii_alphanumeric() || b"+/-_=".contains(&b)) {
+        return None;
+    }
+    let engine = match (s.contains(['-', '_']), s.len().is_multiple_of(4)) {
+        (false, true) => &STANDARD,
+        (false, false) => &STANDARD_NO_PAD,
+        (true, true) => &URL_SAFE,
+        (true, false) => &URL_SAFE_NO_PAD,
+    };
+    let value: Value = serde_json::from_slice(&engine.decode(s).ok()?).ok()?;
+    (value.is_object() || value.is_array()).then_some((value, engine))
+}
+
+/// The first 8 characters of a match, then `***`.
+fn partial(matched: &str) -> String {
+    let cut = matched.char_indices().nth(8).map_or(matched.len(), |(i, _)| i);
+    format!("{}***", &matched[..cut])
+}
+
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match i % 2 {
+            1 if d * 2 > 9 => d * 2 - 9,
+            1 => d * 2,
+            _ => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// `text[start..end]` is an IPv6 address on its own: it parses, holds at least
+/// two groups, and is not part of a longer word (`std::fs` holds `::f`).
+fn is_ipv6_at(text: &str, start: usize, end: usize) -> bool {
+    let candidate = &text[start..end];
+    let joined = |c: char| c.is_alphanumeric() || c == '_' || c == ':' || c == '.';
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    candidate.split(':').filter(|g| !g.is_empty()).count() >= 2
+        && !before.is_some_and(joined)
+        && !after.is_some_and(joined)
+        && candidate.parse::<Ipv6Addr>().is_ok()
+}
+
+/// What replaces the matches of a custom rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// `[REDACTED:<rule name>]`
+    #[default]
+    Mask,
+    /// The first 8 characters of the match, then `***`
+    Partial,
+    /// `blake3:<hex>` of the match, so equal values stay correlatable
+    Hash,
+    /// The rule's `replacement`, where `$1` or `${name}` is a capture group
+    Replace,
+    /// `{"sentinel_vault":"<b64>"}`: the match encrypted to the run's recipient
+    /// key, for `sentinel unredact`
+    Vault,
+}
+
+/// What a path rule does to the value at its path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathAction {
+    /// Replace the whole value with `"[REDACTED]"`
+    Mask,
+    /// Replace the value with `blake3:<hex>` of it (of its JSON text unless it
+    /// is a string), so equal values stay correlatable
+    Hash,
+    /// Remove the key, or the array element
+    Drop,
+    /// Replace the value with `{"sentinel_vault": "<b64>"}`, its JSON text
+    /// encrypted to the run's recipient key
+    Vault,
+    /// Leave the value's strings to path rules below it: no detector or custom
+    /// rule rewrites them
+    Allow,
+}
+
+impl PathAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            PathAction::Mask => "mask",
+            PathAction::Hash => "hash",
+            PathAction::Drop => "drop",
+            PathAction::Vault => "vault",
+            PathAction::Allow => "allow",
+        }
+    }
+}
+
+/// `--redaction-config`, a TOML file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    /// Detector name to on or off; those not named keep their default
+    #[serde(default)]
+    detectors: BTreeMap<String, bool>,
+    #[serde(default)]
+    rules: Vec<RuleSpec>,
+    #[serde(default)]
+    paths: Vec<PathSpec>,
+    #[serde(default)]
+    secrets: SecretsSpec,
+    /// Methods whose payload strings are not scanned; path rules still apply
+    #[serde(default)]
+    skip_methods: Vec<String>,
+    /// Methods always scanned in full, whatever `skip_methods` and `max_scan_bytes` say
+    #[serde(default)]
+    force_methods: Vec<String>,
+    /// Bytes of payload strings scanned per event; the rest is left as it is
+    max_scan_bytes: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct SecretsSpec {
+    /// Shortest token `high_entropy` looks at
+    min_length: usize,
+    /// Bits per character a token needs to count as a secret
+    min_entropy: f64,
+    /// Patterns of known non-secrets (test keys, placeholders)
+    allow: Vec<String>,
+}
+
+impl Default for SecretsSpec {
+    fn default() -> Self {
+        Self { min_length: 24, min_entropy: 4.0, allow: Vec::new() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    pattern: String,
+    #[serde(default)]
+    strategy: Strategy,
+    replacement: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathSpec {
+    /// Into the payload: a JSON pointer (`/params/arguments/password`) or a
+    /// dotted glob (`params.arguments.*token*`, `params.env.**`)
+    path: String,
+    action: PathAction,
+    /// Only events of these methods; every event when empty
+    #[serde(default)]
+    methods: Vec<String>,
+}
+
+struct PatternRule {
+    name: String,
+    pattern: Regex,
+    strategy: Strategy,
+    /// For [`Strategy::Replace`]
+    replacement: String,
+}
+
+impl PatternRule {
+    fn apply(&self, text: String, vault_key: Option<&[u8; 32]>, found: &mut RedactionReport) -> String {
+        let mut hits = 0;
+        let replaced = replace_all(&self.pattern, &text, |caps: &Captures<'_>| {
+            hits += 1;
+            self.replace(caps, vault_key)
+        });
+        let replaced = match replaced {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(s) => s,
+        };
+        found.hit_n(&self.name, hits);
+        replaced
+    }
+
+    fn replace(&self, caps: &Captures<'_>, vault_key: Option<&[u8; 32]>) -> String {
+        match self.strategy {
+            Strategy::Vault => match vault_key.and_then(|pk| vault(pk, caps[0].as_bytes())) {
+                Some(sealed) => serde_json::json!({ VAULT_KEY: sealed }).to_string(),
+                None => format!("[REDACTED:{}]", self.name),
+            },
+            Strategy::Mask => format!("[REDACTED:{}]", self.name),
+            Strategy::Partial => partial(&caps[0]),
+            Strategy::Hash => format!("blake3:{}", blake3::hash(caps[0].as_bytes()).to_hex()),
+            Strategy::Replace => {
+                let mut out = String::new();
+                caps.expand(&self.replacement, &mut out);
+                out
+            }
+        }
+    }
+}
+
+/// The key of the object a vaulted value is replaced with.
+const VAULT_KEY: &str = "sentinel_vault";
+
+/// `plaintext` encrypted to `recipient_pub`, base64; `None` if that failed.
+fn vault(recipient_pub: &[u8; 32], plaintext: &[u8]) -> Option<String> {
+    match audit_crypto::vault_seal(recipient_pub, plaintext) {
+        Ok(sealed) => Some(STANDARD.encode(sealed)),
+        Err(e) => {
+            eprintln!("❌ Failed to vault a redacted value: {}; masking it instead", e);
+            None
+        }
+    }
+}
+
+/// The base64 of `value` if a path rule vaulted it.
+fn vaulted(value: &Value) -> Option<&str> {
+    match value {
+        Value::Object(obj) if obj.len() == 1 => obj.get(VAULT_KEY)?.as_str(),
+        _ => None,
+    }
+}
+
+/// `text` with `f` applied to what is outside its vaulted values, which stay as
+/// they are.
+fn outside_vaults(text: String, mut f: impl FnMut(String) -> String) -> String {
+    if !text.contains(VAULT_KEY) {
+        return f(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for m in VAULT_TOKEN_PATTERN.find_iter(&text) {
+        out.push_str(&f(text[last..m.start()].to_string()));
+        out.push_str(m.as_str());
+        last = m.end();
+    }
+    out.push_str(&f(text[last..].to_string()));
+    out
 }
 
-pub fn redact_pii(value: &mut Value) {
+/// `value` with the values vault rules encrypted to `recipient_sk` decrypted
+/// again; how many there were.
+pub fn unvault(value: &mut Value, recipient_sk: &[u8; 32]) -> Result<u64, String> {
+    let open = |b64: &str| -> Result<String, String> {
+        let sealed = STANDARD.decode(b64).map_err(|e| format!("vaulted value: bad base64: {}", e))?;
+        let plaintext = audit_crypto::vault_open(recipient_sk, &sealed).map_err(|e| format!("vaulted value: {}", e))?;
+        String::from_utf8(plaintext).map_err(|_| "vaulted value: not UTF-8".to_string())
+    };
+    if let Some(b64) = vaulted(value) {
+        let json = open(b64)?;
+        *value = serde_json::from_str(&json).map_err(|e| format!("vaulted value: not JSON: {}", e))?;
+        return Ok(1);
+    }
     match value {
-        Value::String(s) => {
-            let mut redacted = s.clone();
-            
-            // Redact API keys
-            redacted = API_KEY_PATTERN.replace_all(&redacted, |caps: &regex::Captures<'_>| {
-                let matched = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-                format!("{}***", &matched[..8.min(matched.len())])
-            }).to_string();
-            
-            // Redact sk- keys
-            redacted = SK_KEY_PATTERN.replace_all(&redacted, |caps: &regex::Captures<'_>| {
-                let matched = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-                format!("{}***", &matched[..8.min(matched.len())])
-            }).to_string();
-            
-            // Redact emails
-            redacted = EMAIL_PATTERN.replace_all(&redacted, |caps: &regex::Captures<'_>| {
-                let email = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-                if let Some(at_pos) = email.find('@') {
-                    format!("{}***@***", &email[..at_pos.min(3)])
-                } else {
-                    "***".to_string()
+        Value::Object(obj) => obj.values_mut().map(|v| unvault(v, recipient_sk)).sum(),
+        Value::Array(arr) => arr.iter_mut().map(|v| unvault(v, recipient_sk)).sum(),
+        Value::String(s) if s.contains(VAULT_KEY) => {
+            let mut restored = 0;
+            let mut failed = None;
+            let text = VAULT_TOKEN_PATTERN.replace_all(s, |caps: &Captures<'_>| match open(&caps[1]) {
+                Ok(plaintext) => {
+                    restored += 1;
+                    plaintext
+                }
+                Err(e) => {
+                    failed.get_or_insert(e);
+                    caps[0].to_string()
                 }
-            }).to_string();
-            
-            // Redact tokens
-            redacted = TOKEN_PATTERN.replace_all(&redacted, |caps: &regex::Captures<'_>| {
-                let matched = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-                format!("{}***", &matched[..8.min(matched.len())])
-            }).to_string();
-            
-            if redacted != *s {
-                *value = Value::String(redacted);
+            });
+            let text = text.into_owned();
+            if let Some(e) = failed {
+                return Err(e);
             }
+            *s = text;
+            Ok(restored)
         }
-        Value::Array(arr) => {
-            for item in arr.iter_mut() {
-                redact_pii(item);
+        _ => Ok(0),
+    }
+}
+
+/// One step of a path rule.
+enum Segment {
+    Key(String),
+    /// A key or index with `*` in it, matching any run of characters
+    Glob(Regex),
+    /// `**`: any number of keys and indices, none included
+    AnyDepth,
+}
+
+impl Segment {
+    fn parse_glob(s: &str) -> Self {
+        match s {
+            "**" => Segment::AnyDepth,
+            s if s.contains('*') => {
+                let parts: Vec<String> = s.split('*').map(regex::escape).collect();
+                Segment::Glob(Regex::new(&format!("^{}$", parts.join(".*"))).unwrap())
             }
+            s => Segment::Key(s.to_string()),
+        }
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        match self {
+            Segment::Key(k) => k == key,
+            Segment::Glob(re) => re.is_match(key),
+            Segment::AnyDepth => true,
         }
-        Value::Object(obj) => {
-            for (_, val) in obj.iter_mut() {
-                redact_pii(val);
+    }
+}
+
+/// Whether `segments` match all of `path`.
+fn matches_path(segments: &[Segment], path: &[String]) -> bool {
+    match segments.split_first() {
+        None => path.is_empty(),
+        Some((Segment::AnyDepth, rest)) => (0..=path.len()).any(|i| matches_path(rest, &path[i..])),
+        Some((segment, rest)) => path
+            .split_first()
+            .is_some_and(|(key, tail)| segment.matches(key) && matches_path(rest, tail)),
+    }
+}
+
+struct PathRule {
+    /// As written in the config
+    path: String,
+    segments: Vec<Segment>,
+    action: PathAction,
+    methods: Vec<String>,
+}
+
+impl PathRule {
+    fn parse(spec: PathSpec) -> Result<Self, String> {
+        let segments: Vec<Segment> = match spec.path.strip_prefix('/') {
+            // JSON pointer tokens are literal keys
+            Some(rest) => rest
+                .split('/')
+                .map(|s| Segment::Key(s.replace("~1", "/").replace("~0", "~")))
+                .collect(),
+            None if spec.path.is_empty() || spec.path.split('.').any(str::is_empty) => {
+                return Err(format!(
+                    "redaction path {:?}: expected a JSON pointer (/a/b) or dotted path (a.b)",
+                    spec.path
+                ))
             }
+            None => spec.path.split('.').map(Segment::parse_glob).collect(),
+        };
+        if segments.iter().all(|s| matches!(s, Segment::AnyDepth)) {
+            return Err(format!("redaction path {:?}: matches the whole payload", spec.path));
+        }
+        Ok(Self { path: spec.path, segments, action: spec.action, methods: spec.methods })
+    }
+
+    fn applies_to(&self, method: Option<&str>) -> bool {
+        self.methods.is_empty() || method.is_some_and(|m| self.methods.iter().any(|x| x == m))
+    }
+
+    /// As the start event lists it: `<action>:<path>`, then `@<methods>` when scoped.
+    fn name(&self) -> String {
+        match self.methods.is_empty() {
+            true => format!("{}:{}", self.action.as_str(), self.path),
+            false => format!("{}:{}@{}", self.action.as_str(), self.path, self.methods.join(",")),
         }
-        _ => {}
     }
 }
 
-pub fn redact_log(log: &mut crate::events::McpLog) {
-    redact_pii(&mut log.payload);
+/// What redaction rewrites in an event: the built-in detectors that are on, the
+/// custom rules, and the payload paths always masked or left alone. The default
+/// is what redaction did before it could be configured.
+pub struct RedactionPolicy {
+    detectors: Vec<Detector>,
+    /// The detectors the config file turns on itself
+    configured: Vec<Detector>,
+    secrets: SecretSettings,
+    rules: Vec<PatternRule>,
+    paths: Vec<PathRule>,
+    /// Recipient public key vault rules encrypt to; without it they mask
+    vault_key: Option<[u8; 32]>,
+    skip_methods: Vec<String>,
+    force_methods: Vec<String>,
+    max_scan_bytes: Option<usize>,
 }
 
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self {
+            detectors: Detector::ALL.into_iter().filter(|d| d.on_by_default()).collect(),
+            configured: Vec::new(),
+            secrets: SecretSettings::new(&SecretsSpec::default()).unwrap(),
+            rules: Vec::new(),
+            paths: Vec::new(),
+            vault_key: None,
+            skip_methods: Vec::new(),
+            force_methods: Vec::new(),
+            max_scan_bytes: None,
+        }
+    }
+}
+
+impl RedactionPolicy {
+    /// The policy of the redaction config at `path`, validated: an unknown
+    /// detector, a rule that doesn't compile or a path that isn't a JSON
+    /// pointer is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("read redaction config {}: {}", path.display(), e))?;
+        let file: PolicyFile =
+            toml::from_str(&text).map_err(|e| format!("parse redaction config {}: {}", path.display(), e))?;
+
+        let mut policy = Self { secrets: SecretSettings::new(&file.secrets)?, ..Self::default() };
+        for (name, on) in &file.detectors {
+            let detector = Detector::from_name(name).ok_or_else(|| {
+                let known: Vec<&str> = Detector::ALL.iter().map(|d| d.name()).collect();
+                format!("redaction config {}: unknown detector {:?} (known: {})", path.display(), name, known.join(", "))
+            })?;
+            policy.detectors.retain(|d| *d != detector);
+            if *on {
+                policy.detectors.push(detector);
+                policy.configured.push(detector);
+            }
+        }
+        policy.detectors.sort_by_key(|d| Detector::ALL.iter().position(|a| a == d));
+
+        for spec in file.rules {
+            let name = spec.name;
+            if name.is_empty() {
+                return Err(format!("redaction config {}: a rule has no name", path.display()));
+            }
+            if Detector::from_name(&name).is_some() || policy.rules.iter().any(|r| r.name == name) {
+                return Err(format!("redaction rule {:?}: the name is already taken", name));
+            }
+            let pattern =
+                Regex::new(&spec.pattern).map_err(|e| format!("redaction rule {:?}: invalid pattern: {}", name, e))?;
+            let replacement = match (spec.strategy, spec.replacement) {
+                (Strategy::Replace, Some(r)) => r,
+                (Strategy::Replace, None) => {
+                    return Err(format!("redaction rule {:?}: strategy \"replace\" needs a replacement", name))
+                }
+                (_, Some(_)) => {
+                    return Err(format!("redaction rule {:?}: a replacement is only used with strategy \"replace\"", name))
+                }
+                (_, None) => String::new(),
+            };
+            policy.rules.push(PatternRule { name, pattern, strategy: spec.strategy, replacement });
+        }
+
+        for spec in file.paths {
+            policy.paths.push(PathRule::parse(spec)?);
+        }
+
+        if let Some(method) = file.skip_methods.iter().find(|m| file.force_methods.contains(m)) {
+            return Err(format!(
+                "redaction config {}: {:?} is in both skip_methods and force_methods",
+                path.display(),
+                method
+            ));
+        }
+        if file.max_scan_bytes == Some(0) {
+            return Err(format!("redaction config {}: max_scan_bytes must be at least 1", path.display()));
+        }
+        policy.skip_methods = file.skip_methods;
+        policy.force_methods = file.force_methods;
+        policy.max_scan_bytes = file.max_scan_bytes;
+        Ok(policy)
+    }
+
+    /// Names of the rules applied, as the run's start event and `/api/config`
+    /// list them: the detectors that are on, the custom rules, then the path
+    /// rules as `<action>:<path>[@<methods>]`.
+    pub fn rule_names(&self) -> Vec<String> {
+        let detectors = self.detectors.iter().map(|d| d.name().to_string());
+        let rules = self.rules.iter().map(|r| r.name.clone());
+        let paths = self.paths.iter().map(PathRule::name);
+        detectors.chain(rules).chain(paths).collect()
+    }
+
+    /// The rules the config file asks for, which sample traffic should make
+    /// fire: the detectors it turns on, the custom rules and the path rules
+    /// other than `allow`, named as in [`RedactionPolicy::rule_names`].
+    pub fn configured_rule_names(&self) -> Vec<String> {
+        let detectors = self.detectors.iter().filter(|d| self.configured.contains(d)).map(|d| d.name().to_string());
+        let rules = self.rules.iter().map(|r| r.name.clone());
+        let paths = self.paths.iter().filter(|p| p.action != PathAction::Allow).map(PathRule::name);
+        detectors.chain(rules).chain(paths).collect()
+    }
+
+    /// How much of each event is scanned, for the start event: `skip:<method>`,
+    /// `force:<method>` and `max_scan_bytes:<n>`; empty when every event is
+    /// scanned in full.
+    pub fn scan_limits(&self) -> Vec<String> {
+        let skip = self.skip_methods.iter().map(|m| format!("skip:{}", m));
+        let force = self.force_methods.iter().map(|m| format!("force:{}", m));
+        let max = self.max_scan_bytes.map(|n| format!("max_scan_bytes:{}", n));
+        skip.chain(force).chain(max).collect()
+    }
+
+    /// Some rule or path vaults what it matches.
+    pub fn vaults(&self) -> bool {
+        self.rules.iter().any(|r| r.strategy == Strategy::Vault) || self.paths.iter().any(|p| p.action == PathAction::Vault)
+    }
+
+    /// Vault to `recipient_pub`, the run's recipient key.
+    pub fn with_vault_key(mut self, recipient_pub: [u8; 32]) -> Self {
+        self.vault_key = Some(recipient_pub);
+        self
+    }
+
+    fn has(&self, detector: Detector) -> bool {
+        self.detectors.contains(&detector)
+    }
+
+    /// `s` with what the detectors and custom rules find replaced. Values a
+    /// rule vaulted are not looked at again.
+    fn redact_str(&self, s: &str, found: &mut RedactionReport) -> String {
+        let mut redacted = s.to_string();
+        for detector in &self.detectors {
+            redacted = outside_vaults(redacted, |t| detector.apply(t, &self.secrets, found));
+        }
+        for rule in &self.rules {
+            redacted = outside_vaults(redacted, |t| rule.apply(t, self.vault_key.as_ref(), found));
+        }
+        redacted
+    }
+
+    /// Error text gets more than the string rules: servers tend to put file
+    /// paths and stack traces in it. Paths keep only their last component, and
+    /// a stack trace is cut at its first frame.
+    fn redact_error_text(&self, s: &str, found: &mut RedactionReport) -> String {
+        let mut text = s;
+        let mut trace_cut = false;
+        if let Some(m) = STACK_FRAME_PATTERN.find(s).filter(|_| self.has(Detector::ErrorStackTraces)) {
+            text = s[..m.start()].trim_end();
+            trace_cut = true;
+            found.hit_n(Detector::ErrorStackTraces.name(), 1);
+        }
+        let mut redacted = match self.has(Detector::ErrorPaths) {
+            true => {
+                let mut hits = 0;
+                let redacted = PATH_PATTERN.replace_all(text, |caps: &Captures<'_>| {
+                    hits += 1;
+                    format!("{}.../{}", &caps[1], &caps[2])
+                });
+                let redacted = redacted.into_owned();
+                found.hit_n(Detector::ErrorPaths.name(), hits);
+                redacted
+            }
+            false => text.to_string(),
+        };
+        redacted = self.redact_str(&redacted, found);
+        if trace_cut {
+            if !redacted.is_empty() {
+                redacted.push(' ');
+            }
+            redacted.push_str("[stack trace redacted]");
+        }
+        redacted
+    }
+
+    fn redact_error_strings(&self, value: &mut Value, found: &mut RedactionReport) {
+        match value {
+            Value::String(s) => *s = self.redact_error_text(s, found),
+            Value::Array(arr) => arr.iter_mut().for_each(|v| self.redact_error_strings(v, found)),
+            Value::Object(obj) => obj.values_mut().for_each(|v| self.redact_error_strings(v, found)),
+            _ => {}
+        }
+    }
+
+    /// `s` encoded again from its JSON redacted, if it is base64 of a JSON
+    /// object or array with something to redact.
+    fn redact_base64_json(&self, s: &str, found: &mut RedactionReport) -> Option<String> {
+        let (mut value, engine) = base64_json(s)?;
+        let mut inner = RedactionReport::default();
+        self.redact_value(&mut value, &[], &mut Vec::new(), true, &mut None, &mut inner);
+        if inner.is_empty() {
+            return None;
+        }
+        found.add(&inner);
+        Some(engine.encode(value.to_string()))
+    }
+
+    /// The first of `paths` matching `path`.
+    fn path_rule<'a>(paths: &[&'a PathRule], path: &[String]) -> Option<&'a PathRule> {
+        paths.iter().find(|p| matches_path(&p.segments, path)).copied()
+    }
+
+    /// Redact the strings of `value`, which is at `path` of the payload, and
+    /// apply `paths`; `scan` is false below an allowed path. `budget` is what
+    /// is left of `max_scan_bytes`: strings past it are kept unscanned. False
+    /// if `value` is to be dropped.
+    fn redact_value(
+        &self,
+        value: &mut Value,
+        paths: &[&PathRule],
+        path: &mut Vec<String>,
+        mut scan: bool,
+        budget: &mut Option<usize>,
+        found: &mut RedactionReport,
+    ) -> bool {
+        if vaulted(value).is_some() {
+            // Already redacted, and its base64 would look like a secret
+            return true;
+        }
+        if let Some(rule) = Self::path_rule(paths, path).filter(|_| !path.is_empty()) {
+            // A value masked before (a redacted log read back) is not counted again
+            let unchanged = rule.action == PathAction::Mask && value.as_str() == Some("[REDACTED]");
+            if rule.action != PathAction::Allow && !unchanged {
+                found.hit_n(&rule.name(), 1);
+            }
+            match rule.action {
+                PathAction::Mask => {
+                    *value = Value::String("[REDACTED]".to_string());
+                    return true;
+                }
+                PathAction::Hash => {
+                    let hash = match &*value {
+                        Value::String(s) => blake3::hash(s.as_bytes()),
+                        other => blake3::hash(other.to_string().as_bytes()),
+                    };
+                    *value = Value::String(format!("blake3:{}", hash.to_hex()));
+                    return true;
+                }
+                PathAction::Drop => return false,
+                PathAction::Vault => {
+                    *value = match self.vault_key.as_ref().and_then(|pk| vault(pk, value.to_string().as_bytes())) {
+                        Some(sealed) => serde_json::json!({ VAULT_KEY: sealed }),
+                        None => Value::String("[REDACTED]".to_string()),
+                    };
+                    return true;
+                }
+                PathAction::Allow => scan = false,
+            }
+        }
+        let track = !paths.is_empty();
+        if !scan && !track {
+            return true;
+        }
+        match value {
+            Value::String(s) if scan => {
+                let cut = match budget {
+                    Some(left) => {
+                        let cut = s.floor_char_boundary((*left).min(s.len()));
+                        *left -= cut;
+                        cut
+                    }
+                    None => s.len(),
+                };
+                if cut < s.len() {
+                    found.truncated_scan = true;
+                    if cut > 0 {
+                        // A match across the cut is missed, as one past it is
+                        let head = self.redact_str(&s[..cut], found);
+                        *s = head + &s[cut..];
+                    }
+                    return true;
+                }
+                if let Some(redacted) = self.redact_base64_json(s, found) {
+                    *s = redacted;
+                    return true;
+                }
+                let redacted = self.redact_str(s, found);
+                if redacted != *s {
+                    *s = redacted;
+                }
+            }
+            Value::Array(arr) => {
+                let mut i = 0;
+                arr.retain_mut(|item| {
+                    if track {
+                        path.push(i.to_string());
+                    }
+                    let keep = self.redact_value(item, paths, path, scan, budget, found);
+                    if track {
+                        path.pop();
+                    }
+                    i += 1;
+                    keep
+                });
+            }
+            Value::Object(obj) => {
+                obj.retain(|key, val| {
+                    if track {
+                        path.push(key.clone());
+                    }
+                    let keep = self.redact_value(val, paths, path, scan, budget, found);
+                    if track {
+                        path.pop();
+                    }
+                    keep
+                });
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Redact `log` as `policy` says; the secrets it replaced, by type. The
+/// payload strings of a `skip_methods` event are not scanned, and those of
+/// any other past `max_scan_bytes`, unless it is one of `force_methods`.
+pub fn redact_log(log: &mut crate::events::McpLog, policy: &RedactionPolicy) -> RedactionReport {
+    let mut found = RedactionReport::default();
+    let method = log.method.as_deref();
+    let paths: Vec<&PathRule> = policy.paths.iter().filter(|p| p.applies_to(method)).collect();
+    let listed = |methods: &[String]| method.is_some_and(|m| methods.iter().any(|x| x == m));
+    let forced = listed(&policy.force_methods);
+    let scan = forced || !listed(&policy.skip_methods);
+    let mut budget = if forced { None } else { policy.max_scan_bytes };
+    policy.redact_value(&mut log.payload, &paths, &mut Vec::new(), scan, &mut budget, &mut found);
+    if let Some(uri) = &mut log.resource_uri {
+        *uri = policy.redact_str(uri, &mut found);
+    }
+    if log.is_error {
+        if let Some(message) = &mut log.error_message {
+            *message = policy.redact_error_text(message, &mut found);
+        }
+        if let Some(error) = log.payload.get_mut("error") {
+            for key in ["message", "data"] {
+                // Left to the path rules that reach it
+                let at = ["error".to_string(), key.to_string()];
+                if (1..=at.len()).any(|n| RedactionPolicy::path_rule(&paths, &at[..n]).is_some()) {
+                    continue;
+                }
+                if let Some(v) = error.get_mut(key) {
+                    policy.redact_error_strings(v, &mut found);
+                }
+            }
+        }
+    }
+    // Taken again over what is logged, so no digest of a redacted value is kept.
+    if log.params_digest.is_some() && !found.is_empty() {
+        log.params_digest = log.payload.get("params").map(crate::events::params_digest);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::strategy::Strategy;
+
+    fn redact(s: &str) -> (String, RedactionReport) {
+        let mut found = RedactionReport::default();
+        let redacted = RedactionPolicy::default().redact_str(s, &mut found);
+        (redacted, found)
+    }
+
+    /// `s` in full-width forms, which NFKC folds back to ASCII.
+    fn full_width(s: &str) -> String {
+        s.chars().map(|c| char::from_u32(c as u32 + 0xFEE0).filter(|_| c.is_ascii_graphic()).unwrap_or(c)).collect()
+    }
+
+    /// The log of `message` as the proxy would build it.
+    fn log_of(message: serde_json::Value) -> crate::events::McpLog {
+        let message = serde_json::from_value(message).unwrap();
+        crate::events::McpLog::from_message(
+            "run".into(),
+            0,
+            0,
+            crate::events::StreamDirection::Outbound,
+            message,
+            None,
+            "session",
+            "trace",
+            "span".into(),
+            None,
+        )
+    }
+
+    #[test]
+    fn params_digest_is_taken_over_the_redacted_params() {
+        let mut log = log_of(serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "send", "arguments": {"to": "alice@example.com"}},
+        }));
+        let received = log.params_digest.clone().unwrap();
+        let found = redact_log(&mut log, &RedactionPolicy::default());
+        assert_eq!(found.get("email"), 1);
+        let digest = log.params_digest.clone().unwrap();
+        assert_ne!(digest, received);
+        assert_eq!(digest, crate::events::params_digest(&log.payload["params"]));
+
+        // Nothing redacted, nothing to take again
+        let mut log = log_of(serde_json::json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "send", "arguments": {"to": "nobody"}},
+        }));
+        let received = log.params_digest.clone();
+        redact_log(&mut log, &RedactionPolicy::default());
+        assert_eq!(log.params_digest, received);
+    }
+
+    #[test]
+    fn email_with_multibyte_local_part() {
+        let (redacted, found) = redact("from éé@example.com and 日本語テキスト@example.com");
+        assert_eq!(redacted, "from éé***@*** and 日本語***@***");
+        assert_eq!(found.get("email"), 2);
+    }
+
+    #[test]
+    fn normalized_text_is_matched_and_the_rest_kept() {
+        let (redacted, found) = redact(&format!("mail {} ok", full_width("bob@example.com")));
+        assert_eq!(redacted, "mail bob***@*** ok");
+        assert_eq!(found.get("email"), 1);
+
+        let (redacted, _) = redact("é b\u{200B}ob@exam\u{200D}ple.com é");
+        assert_eq!(redacted, "é bob***@*** é");
+
+        let (redacted, _) = redact("q=me%40example.com&x=%E2%9C%93");
+        assert_eq!(redacted, "q=me***@***&x=%E2%9C%93");
+    }
+
+    /// Only the path rule `path`, no detector.
+    fn path_policy(path: &str, action: PathAction, methods: &[&str]) -> RedactionPolicy {
+        let spec = PathSpec {
+            path: path.to_string(),
+            action,
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+        };
+        RedactionPolicy { detectors: Vec::new(), paths: vec![PathRule::parse(spec).unwrap()], ..RedactionPolicy::default() }
+    }
+
+    /// JSON made of a few short keys, so that generated paths often exist.
+    fn json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i32>().prop_map(Value::from),
+            "[a-z ]{0,6}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 32, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::Array),
+                prop::collection::btree_map("[a-c]", inner, 0..4)
+                    .prop_map(|m| Value::Object(m.into_iter().collect())),
+            ]
+        })
+    }
+
+    fn request(method: &str, params: Value) -> crate::events::McpLog {
+        log_of(serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+    }
+
+    fn action() -> impl Strategy<Value = PathAction> {
+        prop::sample::select(vec![PathAction::Mask, PathAction::Hash, PathAction::Drop])
+    }
+
+    /// `value` with every `key` member removed, at any depth.
+    fn without_key(value: &Value, key: &str) -> Value {
+        match value {
+            Value::Object(obj) => {
+                Value::Object(obj.iter().filter(|(k, _)| *k != key).map(|(k, v)| (k.clone(), without_key(v, key))).collect())
+            }
+            Value::Array(arr) => Value::Array(arr.iter().map(|v| without_key(v, key)).collect()),
+            other => other.clone(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn a_pointer_rule_changes_nothing_but_its_path(
+            params in json(),
+            outer in "[a-c]",
+            inner in "[a-c]",
+            action in action(),
+        ) {
+            let policy = path_policy(&format!("/params/{}/{}", outer, inner), action, &[]);
+            let mut log = request("tools/call", params.clone());
+            let found = redact_log(&mut log, &policy);
+
+            // What the rule should have done, applied by hand
+            let mut expected = log_of(serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": params})).payload;
+            let parent = expected["params"].get_mut(&outer).and_then(Value::as_object_mut);
+            let hit = match parent {
+                Some(parent) if parent.contains_key(&inner) => {
+                    match action {
+                        PathAction::Drop => {
+                            parent.remove(&inner);
+                        }
+                        PathAction::Mask => parent[&inner] = Value::from("[REDACTED]"),
+                        _ => {
+                            let hash = match &parent[&inner] {
+                                Value::String(s) => blake3::hash(s.as_bytes()),
+                                other => blake3::hash(other.to_string().as_bytes()),
+                            };
+                            parent[&inner] = Value::from(format!("blake3:{}", hash.to_hex()));
+                        }
+                    }
+                    true
+                }
+                _ => false,
+            };
+            prop_assert_eq!(&log.payload, &expected);
+            prop_assert_eq!(found.total(), hit as u64);
+        }
+
+        #[test]
+        fn a_glob_drop_removes_the_key_everywhere_and_nothing_else(params in json(), key in "[a-c]") {
+            let policy = path_policy(&format!("params.**.{}", key), PathAction::Drop, &[]);
+            let mut log = request("tools/call", params.clone());
+            redact_log(&mut log, &policy);
+            let mut expected = params.clone();
+            if let Value::Object(obj) = &mut expected {
+                // `**` matches no key at all too, so `params.<key>` goes as well
+                obj.remove(&key);
+            }
+            prop_assert_eq!(&log.payload["params"], &without_key(&expected, &key));
+        }
+
+        #[test]
+        fn a_scoped_rule_acts_only_on_its_methods(
+            params in json(),
+            outer in "[a-c]",
+            action in action(),
+        ) {
+            let path = format!("params.{}.**", outer);
+            let scoped = path_policy(&path, action, &["tools/call"]);
+            let mut log = request("resources/read", params.clone());
+            let before = log.payload.clone();
+            let found = redact_log(&mut log, &scoped);
+            prop_assert_eq!(&log.payload, &before);
+            prop_assert!(found.is_empty());
+
+            // On its own method it does what the unscoped rule does
+            let mut log = request("tools/call", params.clone());
+            let mut unscoped = log.clone();
+            redact_log(&mut log, &scoped);
+            redact_log(&mut unscoped, &path_policy(&path, action, &[]));
+            prop_assert_eq!(&log.payload, &unscoped.payload);
+        }
+
+        #[test]
+        fn any_text_redacts_without_panicking(s in "\\PC{0,64}") {
+            redact(&s);
+        }
+
+        #[test]
+        fn email_is_found_among_multibyte_text(
+            before in "[a-zé日ß ]{0,12}",
+            local in "[a-zé日ß]{1,8}",
+            after in "[a-zé日ß ]{0,12}",
+        ) {
+            let (redacted, found) = redact(&format!("{} {}@example.com {}", before, local, after));
+            prop_assert_eq!(found.get("email"), 1);
+            let kept: String = local.chars().take(3).collect();
+            prop_assert_eq!(redacted, format!("{} {}***@*** {}", before, kept, after));
+        }
+
+        #[test]
+        fn full_width_email_is_found(local in "[a-z]{1,8}", domain in "[a-z]{1,8}", around in "[é日 ]{0,6}") {
+            let email = full_width(&format!("{}@{}.com", local, domain));
+            let (redacted, found) = redact(&format!("{} {} {}", around, email, around));
+            prop_assert_eq!(found.get("email"), 1);
+            prop_assert!(!redacted.contains(&email));
+            prop_assert!(redacted.starts_with(&around) && redacted.ends_with(&around));
+        }
+
+        #[test]
+        fn zero_width_characters_do_not_hide_an_email(
+            local in "[a-z]{2,8}",
+            at in 0usize..8,
+            zw in prop::sample::select(vec!['\u{200B}', '\u{200C}', '\u{200D}', '\u{FEFF}']),
+        ) {
+            let email = format!("{}@example.com", local);
+            let at = at.min(email.len());
+            let hidden = format!("{}{}{}", &email[..at], zw, &email[at..]);
+            let (redacted, found) = redact(&format!("é {} é", hidden));
+            prop_assert_eq!(found.get("email"), 1);
+            prop_assert!(!redacted.contains("example"));
+        }
+    }
+}
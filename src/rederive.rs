@@ -0,0 +1,550 @@
+use crate::audit::{self, AuditChain, AuditRecord};
+use crate::capture;
+use crate::clock::{Clock, IdGen};
+use crate::detection::Detector;
+use crate::events::McpLog;
+use crate::metrics::Metrics;
+use crate::parser::{Parser, UnparsedCapture};
+use crate::protocol::RuleSet;
+use crate::redaction::RedactionPolicy;
+use crate::session::Session;
+
+use ed25519_dalek::SigningKey;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Method of the event the audit writer adds at shutdown. It is not derived from
+/// the capture, so it is carried over from the reference.
+const SHUTDOWN_METHOD: &str = "sentinel/shutdown";
+
+/// Most field differences listed for the first diverging record.
+const MAX_FIELD_DIFFS: usize = 20;
+
+pub struct RederiveOptions {
+    pub capture: PathBuf,
+    pub reference: PathBuf,
+    /// Without the key, checkpoint signatures are carried over from the reference
+    /// and only the signed fields are compared.
+    pub signing_key_path: Option<String>,
+    pub checkpoint_every: u64,
+    pub protocol_rules: RuleSet,
+    pub detector: Option<Arc<Detector>>,
+    pub unparsed: UnparsedCapture,
+    pub unparsed_max_bytes: usize,
+    pub max_reassembly_bytes: usize,
+    pub metrics_top_k: usize,
+    pub max_payload_bytes: Option<usize>,
+    pub redact: bool,
+    /// The run's `--redaction-config`, applied if it redacted its audit log
+    pub redaction: Arc<RedactionPolicy>,
+}
+
+pub struct RederiveOutcome {
+    /// Records compared, including the header
+    pub records: usize,
+    /// Description of the first divergence, if any
+    pub divergence: Option<String>,
+}
+
+/// Readings taken from the reference log, handed out in the order the original
+/// run consumed them.
+struct Replay<T>(Mutex<VecDeque<T>>);
+
+impl<T> Replay<T> {
+    fn new(items: impl IntoIterator<Item = T>) -> Self {
+        Self(Mutex::new(items.into_iter().collect()))
+    }
+
+    fn next(&self) -> Option<T> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).pop_front()
+    }
+}
+
+impl Clock for Replay<u64> {
+    fn now_ms(&self) -> u64 {
+        // Running past the reference is itself a divergence; the diff reports it.
+        self.next().unwrap_or(0)
+    }
+}
+
+impl IdGen for Replay<String> {
+    fn next_id(&self) -> String {
+        self.next().unwrap_or_default()
+    }
+}
+
+/// Feed a raw capture through the parser, redaction and hash chain in-process and
+/// compare the regenerated records with the reference log, record by record.
+pub async fn rederive(opts: &RederiveOptions) -> Result<RederiveOutcome, String> {
+    let reference = load_reference(&opts.reference)?;
+    let taps = capture::read_capture(&opts.capture)?;
+
+    let first = reference
+        .events
+        .first()
+        .ok_or_else(|| "reference log has no events".to_string())?;
+    let run_id = first.run_id.clone();
+    let session = Arc::new(Session::new(first.session_id.clone(), first.trace_id.clone()));
+    // A multiplexed run has a session per server; each server's first event names it.
+    let mut server_sessions: HashMap<&str, Arc<Session>> = HashMap::new();
+    for log in &reference.events {
+        if let Some(name) = log.server_name.as_deref() {
+            server_sessions.entry(name).or_insert_with(|| {
+                Arc::new(Session::new(log.session_id.clone(), log.trace_id.clone()).with_server_name(name))
+            });
+        }
+    }
+    let server_sessions: Vec<Arc<Session>> = server_sessions.into_values().collect();
+
+    // The parser reads the clock once per log and draws a span id for every new
+    // span, so both sequences follow event order (the shutdown event aside).
+    let parsed: Vec<&McpLog> = reference
+        .events
+        .iter()
+        .filter(|l| l.method.as_deref() != Some(SHUTDOWN_METHOD))
+        .collect();
+    let clock = Replay::new(parsed.iter().map(|l| l.timestamp));
+    let mut seen = HashSet::new();
+    let ids = Replay::new(
+        parsed
+            .iter()
+            .filter(|l| seen.insert(l.span_id.as_str()))
+            .map(|l| l.span_id.clone()),
+    );
+
+    let (tap_tx, tap_rx) = mpsc::channel(1000);
+    let (log_tx, mut log_rx) = mpsc::channel(1000);
+    let parser = Parser::new(run_id.clone(), log_tx, session, Arc::new(Metrics::new(opts.metrics_top_k)), opts.protocol_rules)
+        .with_clock(Arc::new(clock), Arc::new(ids))
+        .with_detector(opts.detector.clone())
+        .with_unparsed(opts.unparsed, opts.unparsed_max_bytes)
+        .with_reassembly(opts.max_reassembly_bytes)
+        .with_server_sessions(server_sessions);
+    let parser_task = tokio::spawn(parser.process_stream(tap_rx));
+
+    let feeder = tokio::spawn(async move {
+        for evt in taps {
+            if tap_tx.send(evt).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut logs = Vec::new();
+    while let Some(log) = log_rx.recv().await {
+        logs.push(log);
+    }
+    let _ = feeder.await;
+    parser_task
+        .await
+        .map_err(|e| format!("parser task failed: {}", e))?
+        .map_err(|e| format!("parser failed: {}", e))?;
+
+    if let Some(shutdown) = reference.events.iter().rev().find(|l| l.method.as_deref() == Some(SHUTDOWN_METHOD)) {
+        logs.push(shutdown.clone());
+    }
+
+    let regenerated = regenerate(opts, &reference, &run_id, logs)?;
+    let divergence = first_divergence(&reference.lines, &regenerated);
+
+    Ok(RederiveOutcome {
+        records: regenerated.len().max(reference.lines.len()),
+        divergence,
+    })
+}
+
+struct Reference {
+    /// Every record line, header first, without the trailing newline
+    lines: Vec<String>,
+    events: Vec<McpLog>,
+    /// Each event's signature (`run --sign-every-event`), in event order
+    event_signatures: Vec<Option<String>>,
+    checkpoints: Vec<AuditRecord>,
+    /// TimestampProof and WitnessAck lines with their index in `lines`. Answers
+    /// from the TSA and the witness are not derived from the capture, so they are
+    /// carried over where they were written.
+    receipts: Vec<(usize, String)>,
+}
+
+fn load_reference(path: &Path) -> Result<Reference, String> {
+    let mut text = String::new();
+    audit::open_log(path)?
+        .read_to_string(&mut text)
+        .map_err(|e| format!("read {}: {}", path.display(), e))?;
+    let lines: Vec<String> = text.split_terminator('\n').map(str::to_string).collect();
+
+    match lines.first().map(|l| audit::parse_file_header(l)).transpose()?.flatten() {
+        Some(format) if format == audit::FORMAT_AUDIT => {}
+        Some(format) => {
+            return Err(format!(
+                "reference log format {:?} is not supported; rederive needs a plaintext {} log",
+                format,
+                audit::FORMAT_AUDIT
+            ))
+        }
+        None => return Err("reference log has no FileHeader; rederive needs a log written by this build".to_string()),
+    }
+
+    let mut events = Vec::new();
+    let mut event_signatures = Vec::new();
+    let mut checkpoints = Vec::new();
+    let mut receipts = Vec::new();
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        let record: AuditRecord =
+            serde_json::from_str(line).map_err(|e| format!("reference line {}: {}", i + 1, e))?;
+        match record {
+            AuditRecord::Event { log, integrity } => {
+                events.push(log);
+                event_signatures.push(integrity.signature_b64);
+            }
+            cp @ AuditRecord::Checkpoint { .. } => checkpoints.push(cp),
+            AuditRecord::TimestampProof { .. } | AuditRecord::WitnessAck { .. } => receipts.push((i, line.clone())),
+            // Derived from the gap notice in the capture, like an event
+            AuditRecord::GapMarker { .. } => {}
+            AuditRecord::FileHeader { .. } => {
+                return Err(format!("reference line {}: unexpected FileHeader", i + 1))
+            }
+            AuditRecord::RunBoundary { .. } => {
+                return Err(format!(
+                    "reference line {}: the log continues an earlier run (--append); rederive needs a single run's log",
+                    i + 1
+                ))
+            }
+            AuditRecord::SegmentHeader { .. } => {
+                return Err(format!(
+                    "reference line {}: the log is a rotated segment; rederive needs an unrotated log",
+                    i + 1
+                ))
+            }
+            AuditRecord::Migration { .. } => {
+                return Err(format!(
+                    "reference line {}: the log was rewritten by migrate-log; rederive needs the log the run wrote",
+                    i + 1
+                ))
+            }
+        }
+    }
+
+    Ok(Reference {
+        lines,
+        events,
+        event_signatures,
+        checkpoints,
+        receipts,
+    })
+}
+
+impl Reference {
+    /// Whether the original run redacted its audit log, from the scope its start
+    /// event records; `None` for runs that did not record one.
+    fn redacted(&self) -> Option<bool> {
+        let started = self
+            .events
+            .iter()
+            .find(|log| matches!(log.method.as_deref(), Some("sentinel/run_started" | "sentinel/hub_started")))?;
+        Some(started.payload.get("redaction")?.as_str()? == "audit+stream")
+    }
+}
+
+/// Re-run the audit writer over `logs`, producing the lines it would have written.
+fn regenerate(
+    opts: &RederiveOptions,
+    reference: &Reference,
+    run_id: &str,
+    logs: Vec<McpLog>,
+) -> Result<Vec<String>, String> {
+    let signing_key = opts
+        .signing_key_path
+        .as_deref()
+        .map(audit::load_signing_key_b64)
+        .transpose()?;
+    // Checkpoints exist only if the original run had a signing key.
+    let checkpointing = !reference.checkpoints.is_empty();
+    // Runs since the start checkpoint was added sign the empty chain first.
+    let start_checkpoint = matches!(
+        reference.checkpoints.first(),
+        Some(AuditRecord::Checkpoint { last_event_id: 0, .. })
+    );
+    let mut checkpoints = reference.checkpoints.iter();
+
+    // The header carries the creation time; reuse it so offsets line up.
+    let header = reference.lines[0].clone();
+    let mut position = header.len() as u64 + 1;
+    let mut lines = vec![header];
+    let mut chain = AuditChain::new(reference.redacted().unwrap_or(opts.redact).then(|| opts.redaction.clone()))
+        .with_max_payload_bytes(opts.max_payload_bytes)
+        .with_provenance(reference.checkpoints.first().and_then(audit::checkpoint_provenance));
+    if matches!(reference.checkpoints.first(), Some(AuditRecord::Checkpoint { version: 1, .. })) {
+        chain = chain.without_merkle();
+    }
+
+    let mut checkpoint = |chain: &mut AuditChain, lines: &mut Vec<String>, position: &mut u64| -> Result<(), String> {
+        let original = checkpoints.next();
+        let ts = match original {
+            Some(AuditRecord::Checkpoint { created_ts_ms, .. }) => *created_ts_ms,
+            _ => 0,
+        };
+        let record = match &signing_key {
+            Some(sk) => chain.checkpoint(sk, run_id, ts)?,
+            None => {
+                let mut record = chain.checkpoint(&SigningKey::from_bytes(&[0u8; 32]), run_id, ts)?;
+                if let (
+                    AuditRecord::Checkpoint { signature_b64, key_id, .. },
+                    Some(AuditRecord::Checkpoint { signature_b64: sig, key_id: kid, .. }),
+                ) = (&mut record, original)
+                {
+                    signature_b64.clone_from(sig);
+                    key_id.clone_from(kid);
+                }
+                record
+            }
+        };
+        let json = serde_json::to_string(&record).map_err(|e| format!("serialize checkpoint: {}", e))?;
+        *position += json.len() as u64 + 1;
+        lines.push(json);
+        Ok(())
+    };
+
+    if start_checkpoint {
+        checkpoint(&mut chain, &mut lines, &mut position)?;
+    }
+
+    let mut event_signatures = reference.event_signatures.iter();
+    let mut receipts = reference.receipts.iter().peekable();
+    for mut log in logs {
+        carry_receipts(&mut receipts, &mut lines, &mut position);
+        let audit::PreparedEvent { mut record, entry_hash: hash, .. } = chain.prepare(&mut log, position)?;
+        // Signatures are deterministic, so a signed event re-signs identically.
+        let original = match record {
+            AuditRecord::Event { .. } => event_signatures.next().and_then(Option::as_ref),
+            _ => None,
+        };
+        if let Some(original) = original {
+            let signature = match &signing_key {
+                Some(sk) => audit::sign_entry_hash(sk, &hash)?,
+                None => original.clone(),
+            };
+            audit::set_event_signature(&mut record, signature);
+        }
+        let json = serde_json::to_string(&record).map_err(|e| format!("serialize event: {}", e))?;
+        position += json.len() as u64 + 1;
+        lines.push(json);
+        chain.commit(log.event_id, hash);
+
+        if checkpointing && chain.pending() >= opts.checkpoint_every {
+            checkpoint(&mut chain, &mut lines, &mut position)?;
+        }
+    }
+
+    // The final checkpoint is written even with nothing pending since the start checkpoint.
+    carry_receipts(&mut receipts, &mut lines, &mut position);
+    if checkpointing && (chain.pending() > 0 || start_checkpoint) {
+        checkpoint(&mut chain, &mut lines, &mut position)?;
+    }
+    carry_receipts(&mut receipts, &mut lines, &mut position);
+
+    Ok(lines)
+}
+
+/// Append the reference's receipts that were written at this point.
+fn carry_receipts<'a>(
+    receipts: &mut std::iter::Peekable<impl Iterator<Item = &'a (usize, String)>>,
+    lines: &mut Vec<String>,
+    position: &mut u64,
+) {
+    while let Some((_, line)) = receipts.next_if(|(at, _)| *at == lines.len()) {
+        *position += line.len() as u64 + 1;
+        lines.push(line.clone());
+    }
+}
+
+/// Describe the first record that differs, field by field.
+fn first_divergence(reference: &[String], regenerated: &[String]) -> Option<String> {
+    let index = (0..reference.len().max(regenerated.len()))
+        .find(|&i| reference.get(i) != regenerated.get(i))?;
+    let line_no = index + 1;
+
+    let (expected, actual) = match (reference.get(index), regenerated.get(index)) {
+        (Some(_), None) => {
+            return Some(format!(
+                "line {}: reference has {} more record(s) than the capture produced",
+                line_no,
+                reference.len() - index
+            ))
+        }
+        (None, Some(_)) => {
+            return Some(format!(
+                "line {}: capture produced {} more record(s) than the reference",
+                line_no,
+                regenerated.len() - index
+            ))
+        }
+        (Some(e), Some(a)) => (e, a),
+        (None, None) => return None,
+    };
+
+    let expected: Value = serde_json::from_str(expected).unwrap_or(Value::Null);
+    let actual: Value = serde_json::from_str(actual).unwrap_or(Value::Null);
+    let record_type = expected
+        .get("record_type")
+        .and_then(Value::as_str)
+        .unwrap_or("record");
+    let actual_type = actual.get("record_type").and_then(Value::as_str).unwrap_or("record");
+    if record_type != actual_type {
+        return Some(format!(
+            "line {}: reference has a {} record here, rederived {}",
+            line_no, record_type, actual_type
+        ));
+    }
+
+    let mut diffs = Vec::new();
+    diff_values("", &expected, &actual, &mut diffs);
+
+    let mut out = format!("line {}: {} differs from the reference", line_no, record_type);
+    if diffs.is_empty() {
+        // Same JSON value, different bytes: key order or number formatting.
+        out.push_str("\n  (values are equal; the serialized bytes differ)");
+    }
+    for (path, e, a) in diffs.iter().take(MAX_FIELD_DIFFS) {
+        out.push_str(&format!("\n  {}\n    reference: {}\n    rederived: {}", path, e, a));
+    }
+    if diffs.len() > MAX_FIELD_DIFFS {
+        out.push_str(&format!("\n  ... and {} more field(s)", diffs.len() - MAX_FIELD_DIFFS));
+    }
+    Some(out)
+}
+
+fn diff_values(path: &str, expected: &Value, actual: &Value, out: &mut Vec<(String, String, String)>) {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => {
+            let mut keys: Vec<&String> = e.keys().chain(a.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{}/{}", path, key);
+                match (e.get(key), a.get(key)) {
+                    (Some(ev), Some(av)) => diff_values(&child, ev, av, out),
+                    (ev, av) => out.push((child, render(ev), render(av))),
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(a)) if e.len() == a.len() => {
+            for (i, (ev, av)) in e.iter().zip(a).enumerate() {
+                diff_values(&format!("{}/{}", path, i), ev, av, out);
+            }
+        }
+        (e, a) if e != a => out.push((path.to_string(), render(Some(e)), render(Some(a)))),
+        _ => {}
+    }
+}
+
+fn render(value: Option<&Value>) -> String {
+    const MAX: usize = 120;
+    match value {
+        None => "(missing)".to_string(),
+        Some(v) => {
+            let s = v.to_string();
+            if s.len() > MAX {
+                let cut = (0..=MAX).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0);
+                format!("{}…", &s[..cut])
+            } else {
+                s
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rederive");
+
+    /// Options `sentinel rederive` uses by default, for the fixture run (which
+    /// redacted its audit log with the built-in rules).
+    fn options(capture: PathBuf, reference: PathBuf) -> RederiveOptions {
+        RederiveOptions {
+            capture,
+            reference,
+            signing_key_path: None,
+            checkpoint_every: 1000,
+            protocol_rules: RuleSet::all(),
+            detector: None,
+            unparsed: UnparsedCapture::Truncated,
+            unparsed_max_bytes: 4096,
+            max_reassembly_bytes: 0,
+            metrics_top_k: 50,
+            max_payload_bytes: None,
+            redact: true,
+            redaction: Arc::new(RedactionPolicy::default()),
+        }
+    }
+
+    fn fixture_options() -> RederiveOptions {
+        let dir = Path::new(FIXTURE);
+        options(dir.join("capture.jsonl"), dir.join("audit.jsonl"))
+    }
+
+    #[tokio::test]
+    async fn fixture_rederives_identically() {
+        let outcome = rederive(&fixture_options()).await.unwrap();
+        assert_eq!(outcome.divergence, None);
+        assert_eq!(outcome.records, 15);
+    }
+
+    #[tokio::test]
+    async fn a_changed_capture_diverges_at_the_field() {
+        let capture = std::fs::read_to_string(Path::new(FIXTURE).join("capture.jsonl")).unwrap();
+        let mut records: Vec<Value> = capture.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        // The server answers the tools/call with different text than it did
+        let bytes = |r: &Value| String::from_utf8(STANDARD.decode(r["bytes_b64"].as_str().unwrap()).unwrap()).unwrap();
+        let answer = records.iter_mut().find(|r| bytes(r).contains("mailed")).unwrap();
+        answer["bytes_b64"] = STANDARD.encode(bytes(answer).replace("mailed", "posted")).into();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.jsonl");
+        let lines: Vec<String> = records.iter().map(Value::to_string).collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let outcome = rederive(&options(path, Path::new(FIXTURE).join("audit.jsonl"))).await.unwrap();
+        let divergence = outcome.divergence.expect("a changed capture must diverge");
+        assert!(divergence.contains("Event differs"), "{}", divergence);
+        assert!(divergence.contains("/log/payload/result/content/0/text"), "{}", divergence);
+        assert!(divergence.contains("posted"), "{}", divergence);
+    }
+
+    #[tokio::test]
+    async fn unstable_redaction_diverges() {
+        // Redaction that does not do what it did for the reference is the
+        // instability the harness exists to catch
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("rules.toml");
+        std::fs::write(&config, "[detectors]\nemail = false\n").unwrap();
+        let redaction = Arc::new(RedactionPolicy::load(&config).unwrap());
+        let outcome = rederive(&RederiveOptions { redaction, ..fixture_options() }).await.unwrap();
+        let divergence = outcome.divergence.expect("differently redacted events must diverge");
+        assert!(divergence.contains("bob@example.com"), "{}", divergence);
+    }
+
+    #[test]
+    fn reordered_keys_are_reported_as_byte_differences() {
+        let reference = vec![r#"{"record_type":"Event","a":1,"b":2}"#.to_string()];
+        let regenerated = vec![r#"{"record_type":"Event","b":2,"a":1}"#.to_string()];
+        let divergence = first_divergence(&reference, &regenerated).unwrap();
+        assert!(divergence.starts_with("line 1: Event differs"), "{}", divergence);
+        assert!(divergence.contains("values are equal; the serialized bytes differ"), "{}", divergence);
+        assert_eq!(first_divergence(&reference, &reference), None);
+    }
+
+    #[test]
+    fn missing_records_are_reported() {
+        let reference = vec!["{}".to_string(), "{}".to_string()];
+        let divergence = first_divergence(&reference, &reference[..1]).unwrap();
+        assert_eq!(divergence, "line 2: reference has 1 more record(s) than the capture produced");
+    }
+}
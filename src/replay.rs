@@ -0,0 +1,129 @@
+//! `sentinel replay`: re-emit the events of an audit log over the dashboard
+//! server, paced by their recorded timestamps. Nothing is written to the log or
+//! anywhere else; replayed events carry `"replay": true` so clients can tell
+//! them from live traffic.
+
+use crate::audit::{self, AuditRecord};
+use crate::audit_crypto::{self, SinkStats};
+use crate::auth::AuthConfig;
+use crate::control::ForwardGate;
+use crate::events::{McpLog, RawTap};
+use crate::feed::{Feed, Watermark};
+use crate::health::Health;
+use crate::history::EventStore;
+use crate::metrics::Metrics;
+use crate::redaction::{self, RedactionPolicy};
+use crate::server::ServerState;
+use crate::session::Session;
+use crate::tap::{Tap, TapDrops, TapOverflow};
+use crate::throughput::Throughput;
+
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+pub struct ReplayOptions {
+    pub log: String,
+    /// Needed when the log is encrypted
+    pub decrypt_privkey_path: Option<String>,
+    /// 2.0 replays twice as fast as recorded
+    pub speed: f64,
+    /// Redact the replayed copies, as `run` does for the dashboard
+    pub redaction: Option<RedactionPolicy>,
+}
+
+/// The events of a (possibly encrypted) log, in file order.
+pub fn read_events(opts: &ReplayOptions) -> Result<Vec<McpLog>, String> {
+    if !(opts.speed.is_finite() && opts.speed > 0.0) {
+        return Err(format!("--speed must be a positive number, got {}", opts.speed));
+    }
+    let log = audit_crypto::open_plaintext(&opts.log, opts.decrypt_privkey_path.as_deref())?;
+
+    let mut events = Vec::new();
+    for (i, line) in BufReader::new(log.reader).lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: read error: {}", i + 1, e))?;
+        let line = audit::normalize_line(&line);
+        if line.is_empty() || (i == 0 && audit::parse_file_header(line)?.is_some()) {
+            continue;
+        }
+        let rec: AuditRecord =
+            serde_json::from_str(line).map_err(|e| format!("line {}: JSON parse error: {}", i + 1, e))?;
+        if let AuditRecord::Event { log, .. } = rec {
+            events.push(log);
+        }
+    }
+    Ok(events)
+}
+
+/// A dashboard server state fed only by the replay. Its tap is already closed,
+/// so control actions taken from the dashboard are not recorded anywhere.
+pub fn server_state(auth: AuthConfig, history_max_events: usize) -> Arc<ServerState> {
+    let (tx, _) = mpsc::channel::<RawTap>(1);
+    let tap_drops = Arc::new(TapDrops::default());
+    let tap = Tap::new(tx, TapOverflow::Block, tap_drops.clone());
+
+    Arc::new(ServerState {
+        feed: Arc::new(Feed::new(1000, 64 * 1024 * 1024)),
+        auth,
+        history: EventStore::new(history_max_events, 64 * 1024 * 1024),
+        metrics: Arc::new(Metrics::new(50)),
+        watermark_interval: Some(Duration::from_millis(1000)),
+        tap_overflow: TapOverflow::Block,
+        tap_drops,
+        audit_sinks: Arc::new(SinkStats::new(&[])),
+        stats_interval: Some(Duration::from_millis(5000)),
+        forward_gate: ForwardGate::new(16 * 1024 * 1024),
+        tap: tap.downgrade(),
+        throughput: vec![(None, Arc::new(Throughput::default()))],
+        sessions: vec![Arc::new(Session::new(Uuid::new_v4().to_string(), Uuid::new_v4().to_string()))],
+        shutdown: CancellationToken::new(),
+        health: Health::default(),
+        ws_client_queue: 1024,
+        ws_ping_interval: Some(Duration::from_millis(30000)),
+        ws_clients: Default::default(),
+        cors: None,
+        audit: Default::default(),
+        listening: Default::default(),
+        config: None,
+        redactions: None,
+    })
+}
+
+/// Publish `events` to the feed and history, sleeping between them for the
+/// recorded gap divided by `speed`. Returns the number replayed.
+pub async fn replay(
+    events: Vec<McpLog>,
+    state: &ServerState,
+    speed: f64,
+    redaction: Option<&RedactionPolicy>,
+) -> Result<u64, String> {
+    let mut previous_ts_ms: Option<u64> = None;
+    let mut replayed = 0u64;
+    for mut log in events {
+        if let Some(previous) = previous_ts_ms {
+            // Out-of-order timestamps are replayed back to back
+            let gap_ms = log.observed_ts_ms.saturating_sub(previous);
+            if gap_ms > 0 {
+                tokio::time::sleep(Duration::from_secs_f64(gap_ms as f64 / 1000.0 / speed)).await;
+            }
+        }
+        previous_ts_ms = Some(log.observed_ts_ms);
+
+        if let Some(policy) = redaction {
+            redaction::redact_log(&mut log, policy).mark(&mut log);
+        }
+        let mut value = serde_json::to_value(&log).map_err(|e| format!("serialize event {}: {}", log.event_id, e))?;
+        if let Value::Object(map) = &mut value {
+            map.insert("replay".to_string(), Value::Bool(true));
+        }
+        let text: Arc<str> = Arc::from(value.to_string());
+        state.feed.publish(text.clone(), Watermark { ts_ms: log.observed_ts_ms, event_id: log.event_id });
+        state.history.push(log.event_id, text);
+        replayed += 1;
+    }
+    Ok(replayed)
+}
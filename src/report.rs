@@ -0,0 +1,655 @@
+use crate::audit::{normalize_line, open_log, parse_file_header, AuditRecord};
+use crate::audit_crypto::RecordDecryptor;
+use crate::events::{McpLog, StreamDirection};
+use crate::metrics::Histogram;
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+const MS_PER_DAY: u64 = 86_400_000;
+
+/// A dimension rows can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// UTC calendar day of the event
+    Day,
+    /// `serverInfo.name` from the run's initialize response
+    Server,
+    Run,
+    /// Log file name
+    File,
+}
+
+impl GroupBy {
+    fn name(self) -> &'static str {
+        match self {
+            GroupBy::Day => "day",
+            GroupBy::Server => "server",
+            GroupBy::Run => "run",
+            GroupBy::File => "file",
+        }
+    }
+}
+
+/// Parse a comma-separated `--group-by` list such as `day,server`.
+pub fn parse_group_by(s: &str) -> Result<Vec<GroupBy>, String> {
+    s.split(',')
+        .map(|part| match part.trim() {
+            "day" => Ok(GroupBy::Day),
+            "server" => Ok(GroupBy::Server),
+            "run" => Ok(GroupBy::Run),
+            "file" => Ok(GroupBy::File),
+            other => Err(format!("unknown group-by key {:?} (use day, server, run, file)", other)),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+pub struct ReportOptions {
+    pub dir: PathBuf,
+    /// Only events on or after this UTC date (YYYY-MM-DD)
+    pub since: Option<String>,
+    /// Only events before this UTC date (YYYY-MM-DD)
+    pub until: Option<String>,
+    pub group_by: Vec<GroupBy>,
+    pub format: ReportFormat,
+    pub decrypt_privkey_path: Option<String>,
+}
+
+/// Running totals for one group; memory is independent of event count.
+#[derive(Default)]
+struct Aggregate {
+    events: u64,
+    requests: u64,
+    tool_calls: u64,
+    responses: u64,
+    errors: u64,
+    latency: Histogram,
+}
+
+impl Aggregate {
+    fn observe(&mut self, log: &McpLog) {
+        self.events += 1;
+        match (log.direction, &log.method) {
+            (StreamDirection::Outbound, Some(method)) => {
+                self.requests += 1;
+                if method == "tools/call" {
+                    self.tool_calls += 1;
+                }
+            }
+            (StreamDirection::Inbound, None) => {
+                self.responses += 1;
+                if log.payload.get("error").is_some_and(|e| !e.is_null()) {
+                    self.errors += 1;
+                }
+                if let Some(ms) = log.latency_ms {
+                    self.latency.observe(ms);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    #[serde(flatten)]
+    group: BTreeMap<&'static str, String>,
+    events: u64,
+    requests: u64,
+    tool_calls: u64,
+    responses: u64,
+    errors: u64,
+    error_rate: f64,
+    /// Latency percentiles are histogram bucket upper bounds; None = above the top bucket
+    latency_p50_ms: Option<u64>,
+    latency_p95_ms: Option<u64>,
+    latency_p99_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Skipped {
+    file: String,
+    reason: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    group_by: Vec<&'static str>,
+    files_scanned: usize,
+    files_skipped: usize,
+    rows: Vec<Row>,
+    skipped: Vec<Skipped>,
+}
+
+/// Scan every `*.jsonl` audit log in `opts.dir` and render the aggregate report.
+pub fn run_report(opts: &ReportOptions) -> Result<String, String> {
+    let since_ms = opts.since.as_deref().map(parse_date_ms).transpose()?;
+    let until_ms = opts.until.as_deref().map(parse_date_ms).transpose()?;
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&opts.dir)
+        .map_err(|e| format!("read {}: {}", opts.dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    files.sort();
+
+    let mut groups: BTreeMap<Vec<String>, Aggregate> = BTreeMap::new();
+    let mut skipped = Vec::new();
+    let mut scanned = 0;
+
+    for path in &files {
+        let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+        // Two streaming passes: the server name is only known once the initialize
+        // response has been seen, but it labels every event of the run.
+        let server = match find_server_name(path, opts.decrypt_privkey_path.as_deref()) {
+            Ok(server) => server,
+            Err(reason) => {
+                skipped.push(Skipped { file: file_name, reason });
+                continue;
+            }
+        };
+
+        let result = for_each_event(path, opts.decrypt_privkey_path.as_deref(), |log| {
+            if since_ms.is_some_and(|since| log.observed_ts_ms < since)
+                || until_ms.is_some_and(|until| log.observed_ts_ms >= until)
+            {
+                return true;
+            }
+
+            let key = opts
+                .group_by
+                .iter()
+                .map(|g| match g {
+                    GroupBy::Day => format_date(log.observed_ts_ms),
+                    // A multiplexed run names each event's server itself.
+                    GroupBy::Server => log
+                        .server_name
+                        .clone()
+                        .or_else(|| server.clone())
+                        .unwrap_or_else(|| "unknown".to_string()),
+                    GroupBy::Run => log.run_id.clone(),
+                    GroupBy::File => file_name.clone(),
+                })
+                .collect();
+            groups.entry(key).or_default().observe(log);
+            true
+        });
+
+        match result {
+            Ok(()) => scanned += 1,
+            Err(reason) => skipped.push(Skipped { file: file_name, reason }),
+        }
+    }
+
+    let rows = groups
+        .into_iter()
+        .map(|(key, agg)| Row {
+            group: opts.group_by.iter().map(|g| g.name()).zip(key).collect(),
+            events: agg.events,
+            requests: agg.requests,
+            tool_calls: agg.tool_calls,
+            responses: agg.responses,
+            errors: agg.errors,
+            error_rate: if agg.responses > 0 { agg.errors as f64 / agg.responses as f64 } else { 0.0 },
+            latency_p50_ms: agg.latency.quantile(0.50),
+            latency_p95_ms: agg.latency.quantile(0.95),
+            latency_p99_ms: agg.latency.quantile(0.99),
+        })
+        .collect();
+
+    let report = Report {
+        group_by: opts.group_by.iter().map(|g| g.name()).collect(),
+        files_scanned: scanned,
+        files_skipped: skipped.len(),
+        rows,
+        skipped,
+    };
+
+    Ok(match opts.format {
+        ReportFormat::Json => {
+            serde_json::to_string_pretty(&report).map_err(|e| format!("serialize report: {}", e))? + "\n"
+        }
+        ReportFormat::Csv => render_csv(&report),
+        ReportFormat::Table => render_table(&report),
+    })
+}
+
+/// Decrypting reader over a log's Event records. The callback returns false to stop early.
+fn for_each_event(
+    path: &Path,
+    decrypt_privkey_path: Option<&str>,
+    mut f: impl FnMut(&McpLog) -> bool,
+) -> Result<(), String> {
+    let file = open_log(path)?;
+    let mut decryptor: Option<RecordDecryptor> = None;
+
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.map_err(|e| format!("line {}: read error: {}", line_no, e))?;
+        let line = normalize_line(&line);
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(env) = RecordDecryptor::parse_envelope(line) {
+            let priv_path = decrypt_privkey_path.ok_or("encrypted (no recipient key given)")?;
+            match decryptor.as_mut() {
+                // A rotated data key
+                Some(d) => d.add_envelope(&env).map_err(|e| format!("line {}: {}", line_no, e))?,
+                None => decryptor = Some(RecordDecryptor::new(&env, priv_path).map_err(|e| format!("encrypted: {}", e))?),
+            }
+            continue;
+        }
+
+        let plaintext;
+        let record_json = match &mut decryptor {
+            Some(d) => {
+                plaintext = d.decrypt_line(line).map_err(|e| format!("line {}: {}", line_no, e))?;
+                plaintext.as_str()
+            }
+            None => line,
+        };
+
+        if line_no == 1 && parse_file_header(record_json).ok().flatten().is_some() {
+            continue;
+        }
+
+        match serde_json::from_str::<AuditRecord>(record_json) {
+            Ok(AuditRecord::Event { log, .. }) => {
+                if !f(&log) {
+                    return Ok(());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => return Err(format!("line {}: not an audit record: {}", line_no, e)),
+        }
+    }
+
+    Ok(())
+}
+
+fn find_server_name(path: &Path, decrypt_privkey_path: Option<&str>) -> Result<Option<String>, String> {
+    let mut server = None;
+    for_each_event(path, decrypt_privkey_path, |log| {
+        if log.direction != StreamDirection::Inbound {
+            return true;
+        }
+        server = log
+            .payload
+            .pointer("/result/serverInfo/name")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        server.is_none()
+    })?;
+    Ok(server)
+}
+
+fn render_table(report: &Report) -> String {
+    let (header, rows) = tabulate(report);
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| rows.iter().map(|r| r[i].len()).chain([header[i].len()]).max().unwrap_or(0))
+        .collect();
+
+    let mut out = String::new();
+    for line in std::iter::once(&header).chain(rows.iter()) {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, w)| format!("{:<width$}", cell, width = w))
+            .collect();
+        let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+    }
+
+    let _ = writeln!(out, "\n{} file(s) scanned, {} skipped", report.files_scanned, report.files_skipped);
+    for s in &report.skipped {
+        let _ = writeln!(out, "  skipped {}: {}", s.file, s.reason);
+    }
+    out
+}
+
+fn render_csv(report: &Report) -> String {
+    let (header, rows) = tabulate(report);
+    let mut out = String::new();
+    for line in std::iter::once(&header).chain(rows.iter()) {
+        let cells: Vec<String> = line.iter().map(|c| csv_escape(c)).collect();
+        let _ = writeln!(out, "{}", cells.join(","));
+    }
+    out
+}
+
+fn tabulate(report: &Report) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut header: Vec<String> = report.group_by.iter().map(|g| g.to_string()).collect();
+    header.extend(
+        [
+            "events",
+            "requests",
+            "tool_calls",
+            "responses",
+            "errors",
+            "error_rate",
+            "latency_p50_ms",
+            "latency_p95_ms",
+            "latency_p99_ms",
+        ]
+        .map(String::from),
+    );
+
+    let fmt_latency = |v: Option<u64>, count: u64| match v {
+        Some(ms) => ms.to_string(),
+        None if count > 0 => ">30000".to_string(),
+        None => String::new(),
+    };
+
+    let rows = report
+        .rows
+        .iter()
+        .map(|r| {
+            let mut cells: Vec<String> = report.group_by.iter().map(|g| r.group[g].clone()).collect();
+            cells.extend([
+                r.events.to_string(),
+                r.requests.to_string(),
+                r.tool_calls.to_string(),
+                r.responses.to_string(),
+                r.errors.to_string(),
+                format!("{:.4}", r.error_rate),
+                fmt_latency(r.latency_p50_ms, r.responses),
+                fmt_latency(r.latency_p95_ms, r.responses),
+                fmt_latency(r.latency_p99_ms, r.responses),
+            ]);
+            cells
+        })
+        .collect();
+
+    (header, rows)
+}
+
+/// A CSV cell: quoted, with its quotes doubled, when it holds a comma, quote or
+/// line break.
+pub fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Parse `YYYY-MM-DD` as midnight UTC, in ms since the epoch.
+fn parse_date_ms(s: &str) -> Result<u64, String> {
+    let bad = || format!("invalid date {:?} (expected YYYY-MM-DD)", s);
+    let mut parts = s.trim().splitn(3, '-');
+    let y: i64 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+    let m: u32 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+    let d: u32 = parts.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return Err(bad());
+    }
+    let days = days_from_civil(y, m, d);
+    if days < 0 {
+        return Err(bad());
+    }
+    Ok(days as u64 * MS_PER_DAY)
+}
+
+/// UTC date (`YYYY-MM-DD`) of a millisecond timestamp.
+fn format_date(ts_ms: u64) -> String {
+    let (y, m, d) = civil_from_days((ts_ms / MS_PER_DAY) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// UTC time of a millisecond timestamp, compact enough for a file name
+/// (`YYYYMMDDTHHMMSSZ`).
+pub fn format_timestamp(ts_ms: u64) -> String {
+    let (y, m, d) = civil_from_days((ts_ms / MS_PER_DAY) as i64);
+    let secs = ts_ms % MS_PER_DAY / 1000;
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", y, m, d, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// Gregorian calendar conversions (Howard Hinnant's days_from_civil / civil_from_days).
+
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let y = yoe + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::test_log::LogWriter;
+    use crate::audit_crypto::{keygen_recipient, AuditSink, Compression, Encryption, Outputs, Quorum, SinkStats};
+    use crate::protocol::JsonRpcMessage;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    /// 2024-06-01T00:00:00Z
+    const JUNE_1: u64 = 1_717_200_000_000;
+    const HOUR: u64 = 3_600_000;
+
+    fn message(run: &str, ts: u64, direction: StreamDirection, message: Value, latency_ms: Option<u64>) -> McpLog {
+        let message: JsonRpcMessage = serde_json::from_value(message).unwrap();
+        McpLog::from_message(run.into(), ts, ts, direction, message, latency_ms, "session", "trace", "span".into(), None)
+    }
+
+    /// A request at `ts` and its response `latency_ms` later.
+    fn exchange(run: &str, ts: u64, id: u64, method: &str, latency_ms: u64, response: Value) -> [McpLog; 2] {
+        let mut response = response;
+        response["jsonrpc"] = json!("2.0");
+        response["id"] = json!(id);
+        [
+            message(run, ts, StreamDirection::Outbound, json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {}}), None),
+            message(run, ts + latency_ms, StreamDirection::Inbound, response, Some(latency_ms)),
+        ]
+    }
+
+    fn initialize(run: &str, ts: u64, server: &str, latency_ms: u64) -> [McpLog; 2] {
+        exchange(run, ts, 0, "initialize", latency_ms, json!({"result": {"serverInfo": {"name": server, "version": "1"}}}))
+    }
+
+    fn failed() -> Value {
+        json!({"error": {"code": -32000, "message": "failed"}})
+    }
+
+    fn ok() -> Value {
+        json!({"result": {}})
+    }
+
+    /// Run a: server alpha on June 1. Run b: server beta across midnight into
+    /// June 2. Run c: alpha again on June 2, encrypted. Plus a log that is not
+    /// one and a file that is not a log.
+    async fn fixture_dir() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = |name: &str, logs: Vec<McpLog>| {
+            let mut writer = LogWriter::create(&dir.path().join(name));
+            logs.into_iter().for_each(|log| writer.log(log));
+            writer.checkpoint();
+        };
+
+        let a = JUNE_1 + 9 * HOUR;
+        plain(
+            "a.jsonl",
+            [
+                initialize("run-a", a, "alpha", 4),
+                exchange("run-a", a + 1_000, 1, "tools/call", 20, ok()),
+                exchange("run-a", a + 2_000, 2, "tools/call", 40, failed()),
+                exchange("run-a", a + 3_000, 3, "tools/list", 8, ok()),
+            ]
+            .concat(),
+        );
+
+        let b = JUNE_1 + 24 * HOUR - 1_000;
+        plain(
+            "b.jsonl",
+            [
+                initialize("run-b", b, "beta", 3),
+                exchange("run-b", b + 2_000, 1, "tools/call", 300, ok()),
+                exchange("run-b", b + 3_000, 2, "resources/read", 700, failed()),
+            ]
+            .concat(),
+        );
+
+        keygen_recipient(dir.path(), None).unwrap();
+        let c = JUNE_1 + 30 * HOUR;
+        write_encrypted(
+            &dir.path().join("c.jsonl"),
+            &dir.path().join("recipient_pub.b64"),
+            [initialize("run-c", c, "alpha", 2), exchange("run-c", c + 1_000, 1, "tools/call", 9, ok())].concat(),
+        )
+        .await;
+
+        fs::write(dir.path().join("d.jsonl"), "not an audit record\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a log\n").unwrap();
+        let key = dir.path().join("recipient_priv.b64").display().to_string();
+        (dir, key)
+    }
+
+    async fn write_encrypted(path: &Path, recipient: &Path, logs: Vec<McpLog>) {
+        let file = tokio::fs::File::create(path).await.unwrap();
+        let outputs = Outputs::new(vec![(path.to_path_buf(), file)], Quorum::Any, Arc::new(SinkStats::new(&[])));
+        let encryption = Encryption::Envelope { recipient: recipient.display().to_string(), escrow: None };
+        let mut sink = AuditSink::new(outputs, "run-c", &encryption, Compression::None).await.unwrap();
+        let mut chain = crate::audit::AuditChain::new(None);
+        for mut log in logs {
+            let prepared = chain.prepare(&mut log, sink.position()).unwrap();
+            sink.write_record("Event", &serde_json::to_string(&prepared.record).unwrap()).await.unwrap();
+            chain.commit(log.event_id, prepared.entry_hash);
+        }
+        sink.close().await.unwrap();
+    }
+
+    fn report(dir: &Path, group_by: &str, format: ReportFormat, since: Option<&str>, key: Option<&str>) -> String {
+        run_report(&ReportOptions {
+            dir: dir.to_path_buf(),
+            since: since.map(String::from),
+            until: None,
+            group_by: parse_group_by(group_by).unwrap(),
+            format,
+            decrypt_privkey_path: key.map(String::from),
+        })
+        .unwrap()
+    }
+
+    /// Grouped by day and server, without the key: the encrypted run and the
+    /// unreadable log are skipped, and a run crossing midnight is split.
+    #[tokio::test]
+    async fn json_by_day_and_server() {
+        let (dir, _) = fixture_dir().await;
+        let expected = r#"{
+  "group_by": [
+    "day",
+    "server"
+  ],
+  "files_scanned": 2,
+  "files_skipped": 2,
+  "rows": [
+    {
+      "day": "2024-06-01",
+      "server": "alpha",
+      "events": 8,
+      "requests": 4,
+      "tool_calls": 2,
+      "responses": 4,
+      "errors": 1,
+      "error_rate": 0.25,
+      "latency_p50_ms": 10,
+      "latency_p95_ms": 50,
+      "latency_p99_ms": 50
+    },
+    {
+      "day": "2024-06-01",
+      "server": "beta",
+      "events": 2,
+      "requests": 1,
+      "tool_calls": 0,
+      "responses": 1,
+      "errors": 0,
+      "error_rate": 0.0,
+      "latency_p50_ms": 5,
+      "latency_p95_ms": 5,
+      "latency_p99_ms": 5
+    },
+    {
+      "day": "2024-06-02",
+      "server": "beta",
+      "events": 4,
+      "requests": 2,
+      "tool_calls": 1,
+      "responses": 2,
+      "errors": 1,
+      "error_rate": 0.5,
+      "latency_p50_ms": 500,
+      "latency_p95_ms": 1000,
+      "latency_p99_ms": 1000
+    }
+  ],
+  "skipped": [
+    {
+      "file": "c.jsonl",
+      "reason": "encrypted (no recipient key given)"
+    },
+    {
+      "file": "d.jsonl",
+      "reason": "line 1: not an audit record: expected ident at line 1 column 2"
+    }
+  ]
+}
+"#;
+        assert_eq!(report(dir.path(), "day,server", ReportFormat::Json, None, None), expected);
+    }
+
+    /// With the key the encrypted run counts, under the server its own
+    /// initialize names; `--since` leaves out June 1.
+    #[tokio::test]
+    async fn csv_by_server_since_a_day() {
+        let (dir, key) = fixture_dir().await;
+        let expected = "\
+server,events,requests,tool_calls,responses,errors,error_rate,latency_p50_ms,latency_p95_ms,latency_p99_ms
+alpha,4,2,1,2,0,0.0000,2,10,10
+beta,4,2,1,2,1,0.5000,500,1000,1000
+";
+        assert_eq!(report(dir.path(), "server", ReportFormat::Csv, Some("2024-06-02"), Some(&key)), expected);
+    }
+
+    #[tokio::test]
+    async fn table_by_run() {
+        let (dir, key) = fixture_dir().await;
+        let expected = "\
+run    events  requests  tool_calls  responses  errors  error_rate  latency_p50_ms  latency_p95_ms  latency_p99_ms
+run-a  8       4         2           4          1       0.2500      10              50              50
+run-b  6       3         1           3          1       0.3333      500             1000            1000
+run-c  4       2         1           2          0       0.0000      2               10              10
+
+3 file(s) scanned, 1 skipped
+  skipped d.jsonl: line 1: not an audit record: expected ident at line 1 column 2
+";
+        assert_eq!(report(dir.path(), "run", ReportFormat::Table, None, Some(&key)), expected);
+    }
+}
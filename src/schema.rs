@@ -0,0 +1,76 @@
+use crate::audit::{AuditRecord, SCHEMA_VERSION};
+use crate::events::McpLog;
+use schemars::{schema::RootSchema, schema_for};
+use std::fs;
+use std::path::Path;
+
+/// Public JSON Schemas, named `<type>.v<SCHEMA_VERSION>.schema.json`.
+fn public_schemas() -> Vec<(String, RootSchema)> {
+    vec![
+        (format!("mcp_log.v{}.schema.json", SCHEMA_VERSION), schema_for!(McpLog)),
+        (format!("audit_record.v{}.schema.json", SCHEMA_VERSION), schema_for!(AuditRecord)),
+    ]
+}
+
+fn render(schema: &RootSchema) -> Result<String, String> {
+    serde_json::to_string_pretty(schema)
+        .map(|s| format!("{}\n", s))
+        .map_err(|e| format!("serialize schema: {e}"))
+}
+
+/// Write every public schema into `out_dir`. A schema already there is
+/// published: verifiers of its version parse records against it, so one that
+/// differs from the current types is an error, not rewritten. Changing a hashed
+/// type takes a new `SCHEMA_VERSION`.
+pub fn write_schemas(out_dir: impl AsRef<Path>) -> Result<Vec<String>, String> {
+    let out_dir = out_dir.as_ref();
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("failed to create {:?}: {e}", out_dir))?;
+
+    let mut written = Vec::new();
+    for (name, schema) in public_schemas() {
+        let path = out_dir.join(&name);
+        let rendered = render(&schema)?;
+        match fs::read_to_string(&path) {
+            Ok(published) if published == rendered => {}
+            Ok(_) => {
+                return Err(format!(
+                    "{:?} is published and differs from the current types: bump SCHEMA_VERSION instead",
+                    path
+                ))
+            }
+            Err(_) => fs::write(&path, rendered).map_err(|e| format!("failed to write {:?}: {e}", path))?,
+        }
+        written.push(name);
+    }
+    Ok(written)
+}
+
+/// Compare the generated schemas against the committed golden files in `dir`.
+/// Returns the names of files that are missing or differ.
+pub fn check_schemas(dir: impl AsRef<Path>) -> Result<Vec<String>, String> {
+    let dir = dir.as_ref();
+    let mut mismatched = Vec::new();
+    for (name, schema) in public_schemas() {
+        let expected = render(&schema)?;
+        match fs::read_to_string(dir.join(&name)) {
+            Ok(actual) if actual == expected => {}
+            _ => mismatched.push(name),
+        }
+    }
+    Ok(mismatched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMAS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/schemas");
+
+    /// The checked-in schemas of the current version are what the types generate.
+    #[test]
+    fn the_checked_in_schemas_match_the_types() {
+        let mismatched = check_schemas(SCHEMAS).unwrap();
+        assert!(mismatched.is_empty(), "out of date: {:?}; run `sentinel schema` after bumping SCHEMA_VERSION", mismatched);
+    }
+}
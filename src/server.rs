@@ -1,65 +1,601 @@
-use crate::events::McpLog;
+use crate::feed::{Feed, Recv};
 use crate::frontend::FrontendAssets;
+use crate::health::Health;
+use crate::history::{EventFilter, EventStore, HistoryError};
+use crate::metrics::Metrics;
+use crate::control::ForwardGate;
+use crate::session::Session;
+use crate::events::{RawTap, StreamDirection};
+use crate::tap::{TapDrops, TapOverflow, WeakTap};
+use crate::throughput::Throughput;
+use crate::audit_crypto::SinkStats;
+use crate::audit_control::{AuditControl, AuditControlError};
+use crate::auth::{AuthConfig, Role};
+use crate::redaction::RedactionTotals;
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State, Path,
+        ws::{close_code, rejection::WebSocketUpgradeRejection, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        FromRequestParts, Query, Request, State, Path,
     },
-    http::{StatusCode, HeaderMap},
-    response::{IntoResponse, Response},
-    routing::get,
+    http::{request::Parts, HeaderName, HeaderValue, StatusCode, HeaderMap},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
     Router,
 };
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use mime_guess::from_path;
-use serde::Deserialize;
-use serde_json;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::sync::{broadcast, RwLock};
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
-use std::collections::VecDeque;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::future::IntoFuture;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::{collections::VecDeque, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Most events returned by one `/api/history` request.
+const MAX_HISTORY_PAGE: usize = 500;
+
+/// How often an idle `/api/stream` gets a comment, so proxies keep it open.
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// How long a closing WebSocket connection gets to take what is still queued
+/// for it (or its close frame, when evicted).
+const WS_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Deserialize)]
 struct AuthQuery {
     token: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct WsQuery {
+    token: Option<String>,
+    /// Resume after the last event the client has (all of history if absent)
+    since_event_id: Option<u64>,
+    /// Send no events until the client's first subscribe message, so the
+    /// history replay is filtered too
+    #[serde(default)]
+    wait_for_subscribe: bool,
+    /// The subscription to start with, as for `/api/stream`
+    methods: Option<String>,
+    directions: Option<String>,
+    #[serde(default)]
+    errors_only: bool,
+}
+
+#[derive(Deserialize)]
+struct AuditDownloadQuery {
+    token: Option<String>,
+    /// Rotated segment to download (the one being written if absent)
+    segment: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    token: Option<String>,
+    /// Resume after this event (the `Last-Event-ID` header wins)
+    since_event_id: Option<u64>,
+    /// Comma-separated, like the lists of a subscribe message
+    methods: Option<String>,
+    directions: Option<String>,
+    #[serde(default)]
+    errors_only: bool,
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    token: Option<String>,
+    /// Return events with ids below this one (the newest events if absent)
+    before: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    token: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    method: Option<String>,
+    direction: Option<StreamDirection>,
+    since_event_id: Option<u64>,
+    since_ts_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    token: Option<String>,
+    #[serde(default)]
+    format: ExportFormat,
+    method: Option<String>,
+    direction: Option<StreamDirection>,
+    since_event_id: Option<u64>,
+    since_ts_ms: Option<u64>,
+}
+
+/// How `/api/events/export` writes the events.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    /// JSON Lines, each event as stored
+    #[default]
+    Json,
+    /// One row per event with [`EXPORT_CSV_COLUMNS`]
+    Csv,
+}
+
+/// Columns of a CSV export.
+const EXPORT_CSV_COLUMNS: &str = "event_id,observed_ts_ms,direction,method,request_id,latency_ms,is_error,tool_name";
+
+/// The fields of an event that a CSV export writes.
+#[derive(Deserialize)]
+struct ExportFields {
+    event_id: u64,
+    observed_ts_ms: u64,
+    direction: StreamDirection,
+    method: Option<String>,
+    request_id: Option<serde_json::Value>,
+    latency_ms: Option<u64>,
+    #[serde(default)]
+    is_error: bool,
+    tool_is_error: Option<bool>,
+    tool_name: Option<String>,
+}
+
+/// What a WebSocket client asked to receive with a `{"type": "subscribe", ..}`
+/// message; the default (no fields) is every event. Fields this version does
+/// not know are ignored.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Subscription {
+    /// Only events with one of these methods
+    #[serde(default)]
+    methods: Option<Vec<String>>,
+    /// Only events on one of these directions
+    #[serde(default)]
+    directions: Option<Vec<StreamDirection>>,
+    /// Only JSON-RPC error responses and failed tool calls
+    #[serde(default)]
+    errors_only: bool,
+}
+
+/// Messages a WebSocket client may send.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(Subscription),
+}
+
+/// The fields of an event that a [`Subscription`] looks at.
+#[derive(Deserialize)]
+struct SubscriptionFields {
+    direction: StreamDirection,
+    method: Option<String>,
+    #[serde(default)]
+    is_error: bool,
+    tool_is_error: Option<bool>,
+}
+
+impl Subscription {
+    /// A subscription given as query parameters, its lists comma-separated.
+    fn from_query(methods: Option<&str>, directions: Option<&str>, errors_only: bool) -> Result<Self, String> {
+        let list = |s: &str| s.split(',').map(str::trim).filter(|v| !v.is_empty()).map(String::from).collect::<Vec<_>>();
+        let directions = directions
+            .map(|d| {
+                list(d)
+                    .into_iter()
+                    .map(|d| {
+                        serde_json::from_value(serde_json::Value::String(d.clone()))
+                            .map_err(|_| format!("unknown direction {:?}", d))
+                    })
+                    .collect::<Result<Vec<StreamDirection>, _>>()
+            })
+            .transpose()?;
+        Ok(Self { methods: methods.map(list), directions, errors_only })
+    }
+
+    fn is_everything(&self) -> bool {
+        self.methods.is_none() && self.directions.is_none() && !self.errors_only
+    }
+
+    fn matches(&self, json: &str) -> bool {
+        if self.is_everything() {
+            return true;
+        }
+        let Ok(event) = serde_json::from_str::<SubscriptionFields>(json) else {
+            return false;
+        };
+        self.methods.as_ref().is_none_or(|methods| event.method.as_ref().is_some_and(|m| methods.contains(m)))
+            && self.directions.as_ref().is_none_or(|directions| directions.contains(&event.direction))
+            && (!self.errors_only || event.is_error || event.tool_is_error == Some(true))
+    }
+}
+
+/// Connected WebSocket clients, and how many were disconnected for falling
+/// behind or not answering pings.
+#[derive(Default)]
+pub struct WsClients {
+    connected: AtomicU64,
+    evicted: AtomicU64,
+}
+
+/// Counts a client as connected until dropped.
+struct WsClientGuard<'a>(&'a WsClients);
+
+impl Drop for WsClientGuard<'_> {
+    fn drop(&mut self) {
+        self.0.connected.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl WsClients {
+    fn connect(&self) -> WsClientGuard<'_> {
+        self.connected.fetch_add(1, Ordering::Relaxed);
+        WsClientGuard(self)
+    }
+
+    fn evicted(&self) {
+        self.evicted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "connected": self.connected.load(Ordering::Relaxed),
+            "evicted": self.evicted.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Client gauges, in OpenMetrics text format (no `# EOF`).
+    pub fn render_openmetrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE sentinel_ws_clients gauge");
+        let _ = writeln!(out, "sentinel_ws_clients {}", self.connected.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# TYPE sentinel_ws_evicted_clients counter");
+        let _ = writeln!(out, "# HELP sentinel_ws_evicted_clients WebSocket clients disconnected for a full queue or a missed pong");
+        let _ = writeln!(out, "sentinel_ws_evicted_clients_total {}", self.evicted.load(Ordering::Relaxed));
+        out
+    }
+}
+
+/// The effective settings of a run, for `/api/config` and the `{"type": "config"}`
+/// frame WebSocket clients get first.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeConfig {
+    pub run_id: String,
+    pub session_id: String,
+    pub audit_log: String,
+    pub redaction_enabled: bool,
+    /// `audit+stream`, `stream-only` or `off`
+    pub redaction_scope: &'static str,
+    /// The rules applied, empty when redaction is off
+    pub redaction_rules: Vec<String>,
+    /// Key id of the signing key, or `unsigned`
+    pub signing_key_id: String,
+    /// Recipient key id of an encrypted log, `age: ` and the recipients of an
+    /// age stream, or `plaintext`
+    pub encryption: String,
+    /// Events between checkpoints
+    pub checkpoint_every: u64,
+}
+
 pub struct ServerState {
-    pub tx: broadcast::Sender<McpLog>,
-    pub auth_token: Option<String>,
-    pub history: RwLock<VecDeque<McpLog>>,
+    pub feed: Arc<Feed>,
+    /// Tokens and their roles; none lets every request in
+    pub auth: AuthConfig,
+    pub history: EventStore,
+    pub metrics: Arc<Metrics>,
+    /// How often WebSocket streams repeat the watermark; `None` disables it
+    pub watermark_interval: Option<Duration>,
+    pub tap_overflow: TapOverflow,
+    pub tap_drops: Arc<TapDrops>,
+    /// Error counters of --audit-log and its mirrors
+    pub audit_sinks: Arc<SinkStats>,
+    /// How often WebSocket streams get a stats frame; `None` disables it
+    pub stats_interval: Option<Duration>,
+    /// Paused and resumed through `/api/control`
+    pub forward_gate: ForwardGate,
+    /// Records control actions in the audit log
+    pub tap: WeakTap,
+    /// Per wrapped server (`None` unless multiplexing)
+    pub throughput: Vec<(Option<String>, Arc<Throughput>)>,
+    /// The audited session, or one per wrapped server when multiplexing
+    pub sessions: Vec<Arc<Session>>,
+    /// Cancelled when the server should stop; open WebSocket streams end too
+    pub shutdown: CancellationToken,
+    /// Child and audit writer status for `/readyz`
+    pub health: Health,
+    /// Most messages queued for one WebSocket client before it is disconnected
+    pub ws_client_queue: usize,
+    /// How often WebSocket clients are pinged; one that has not answered a ping
+    /// by the next is disconnected. `None` disables pings
+    pub ws_ping_interval: Option<Duration>,
+    pub ws_clients: WsClients,
+    /// CORS for `/api/*` and `/ws`; `None` sends no CORS headers
+    pub cors: Option<CorsLayer>,
+    /// The audit log being written, for `/api/audit/*`
+    pub audit: AuditControl,
+    /// Addresses the server listens on, once bound
+    pub listening: OnceLock<Vec<SocketAddr>>,
+    /// `None` for `replay`, which writes no audit log
+    pub config: Option<RuntimeConfig>,
+    /// What redaction replaced so far; `None` when it is off, and for `replay`
+    pub redactions: Option<RedactionTotals>,
+}
+
+/// CORS for browsers on these origins (`*` for any), or `None` for no origins.
+/// Preflight requests are answered without a token; the requests themselves
+/// still need one.
+pub fn cors_layer(origins: &[String]) -> Result<Option<CorsLayer>, String> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    let allow_origin = if origins.iter().any(|o| o.trim() == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|o| {
+                o.trim()
+                    .trim_end_matches('/')
+                    .parse::<axum::http::HeaderValue>()
+                    .map_err(|_| format!("invalid --cors-allow-origin {:?}", o))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([axum::http::Method::GET, axum::http::Method::POST])
+            .allow_headers([axum::http::header::AUTHORIZATION, axum::http::header::CONTENT_TYPE])
+            .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)]),
+    ))
+}
+
+/// The addresses of `--ws-bind`. One beyond loopback is refused without a token,
+/// unless `allow_unauthenticated_remote`: the server shows every MCP payload.
+pub fn bind_addrs(binds: &[String], authenticated: bool, allow_unauthenticated_remote: bool) -> Result<Vec<SocketAddr>, String> {
+    let mut addrs = Vec::new();
+    for bind in binds {
+        // A host name binds every address it resolves to (`localhost`: 127.0.0.1 and ::1)
+        let resolved = std::net::ToSocketAddrs::to_socket_addrs(bind.trim())
+            .map_err(|e| format!("invalid --ws-bind {:?}: {}", bind, e))?;
+        for addr in resolved {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    let remote: Vec<String> = addrs.iter().filter(|a| !a.ip().is_loopback()).map(|a| a.to_string()).collect();
+    if !remote.is_empty() && !authenticated && !allow_unauthenticated_remote {
+        return Err(format!(
+            "Refusing to serve the dashboard on {} without a token: every MCP payload would be readable from the network. \
+             Set --ws-token (or SENTINEL_WS_TOKEN), bind to 127.0.0.1, or pass --allow-unauthenticated-remote",
+            remote.join(", ")
+        ));
+    }
+    Ok(addrs)
 }
 
+/// Serve on each of `addrs` (from [`bind_addrs`]) until shutdown. Port 0 gets a
+/// free port; the addresses bound are printed and served on `/api/info`.
 pub async fn start_server(
     state: Arc<ServerState>,
-    bind_addr: &str,
+    addrs: &[SocketAddr],
 ) -> Result<(), Box<dyn std::error::Error>> {
 
-    let app = Router::new()
+    let api = Router::new()
         // WebSocket
         .route("/ws", get(websocket_handler))
+        // Paged event history, older events read back from the spill or the audit log
+        .route("/api/history", get(history_handler))
+        // Events by method, direction, id or time, paged by offset
+        .route("/api/events", get(events_handler))
+        // All the events matching those filters, as JSON Lines or CSV
+        .route("/api/events/export", get(export_handler))
+        // The WebSocket stream as Server-Sent Events
+        .route("/api/stream", get(stream_handler))
+        // Hold / release client -> server traffic
+        .route("/api/control", get(control_status_handler))
+        .route("/api/control/pause", post(pause_handler))
+        .route("/api/control/resume", post(resume_handler))
+        // Throughput totals
+        .route("/api/stats", get(stats_handler))
+        // p50/p90/p99 per method
+        .route("/api/latency", get(latency_handler))
+        // Prometheus / OpenMetrics scrape endpoint
+        .route("/metrics", get(metrics_handler))
+        // Who is talking: the initialize handshake of each session
+        .route("/api/session", get(session_handler))
+        // Where this server listens (the ports picked for port 0)
+        .route("/api/info", get(info_handler))
+        // Redaction, signing, encryption and checkpoint settings of this run
+        .route("/api/config", get(config_handler))
+        // The audit log as far as it is flushed, and its verification
+        .route("/api/audit/download", get(audit_download_handler))
+        .route("/api/audit/verify", get(audit_verify_handler))
+        // Checkpoint and sync now, before copying the log
+        .route("/api/audit/checkpoint", post(audit_checkpoint_handler));
+    // Only the API is called from other origins; the frontend is served with it.
+    let api = match state.cors.clone() {
+        Some(cors) => api.layer(cors),
+        None => api,
+    };
+
+    let app = api
+        // Liveness and readiness for supervisors (no token)
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
         // Frontend (index.html + assets)
         .route("/", get(serve_index))
         .route("/*path", get(serve_static))
+        .layer(axum::middleware::from_fn(request_id))
         .with_state(state.clone());
 
-    let addr: SocketAddr = bind_addr.parse()?;
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("bind {}: {}", addr, e))?;
+        listeners.push(listener);
+    }
+    let bound = listeners.iter().map(|l| l.local_addr()).collect::<Result<Vec<_>, _>>()?;
+    let loopback_only = bound.iter().all(|a| a.ip().is_loopback());
 
-    if let Some(ref token) = state.auth_token {
-        eprintln!("🔒 WebSocket server started with authentication on {}", addr);
-        eprintln!("   Connect with: ws://{}?token={}", addr, token);
-    } else {
-        eprintln!("⚠️  WebSocket server started WITHOUT authentication on {}", addr);
+    for addr in &bound {
+        if state.auth.is_enabled() {
+            eprintln!("🔒 WebSocket server started with authentication on {}", addr);
+            eprintln!("   Connect with: ws://{}/ws?token=<token>", addr);
+        } else {
+            eprintln!("⚠️  WebSocket server started WITHOUT authentication on {}", addr);
+        }
+        eprintln!("📊 Dashboard available at: http://{}", addr);
+    }
+    if state.auth.is_enabled() {
+        eprintln!(
+            "🔑 {} viewer token(s), {} admin token(s)",
+            state.auth.count(Role::Viewer),
+            state.auth.count(Role::Admin)
+        );
+    }
+    match (loopback_only, state.auth.is_enabled()) {
+        (true, _) => eprintln!("🏠 Loopback only: not reachable from other hosts"),
+        (false, true) => eprintln!("🌐 Reachable from the network; requests need the token"),
+        (false, false) => eprintln!("🚨 Reachable from the network WITHOUT authentication (--allow-unauthenticated-remote)"),
+    }
+    if !state.auth.is_enabled() {
         eprintln!("   For production, use --ws-token flag");
     }
+    let _ = state.listening.set(bound);
 
-    eprintln!("📊 Dashboard available at: http://{}", addr);
-
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let servers = listeners.into_iter().map(|listener| {
+        axum::serve(listener, app.clone())
+            .with_graceful_shutdown(state.shutdown.clone().cancelled_owned())
+            .into_future()
+    });
+    futures_util::future::try_join_all(servers).await?;
     Ok(())
 }
 
+//
+// ---------- Errors ----------
+//
+
+/// Header carrying the id of a request, on its response too.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    /// Id of the request being handled, set by [`request_id`]
+    static REQUEST_ID: Arc<str>;
+}
+
+/// A failed request: `{"error": {"code": .., "message": .., "request_id": ..}}`
+/// with its status, and a line on stderr with the same request id.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    /// Logged instead of `message`, which the client gets
+    detail: Option<String>,
+    /// Seconds for `Retry-After`
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into(), detail: None, retry_after: None }
+    }
+
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", message)
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    /// `detail` only goes to stderr; the client is pointed there by request id.
+    fn internal(detail: impl Into<String>) -> Self {
+        Self {
+            detail: Some(detail.into()),
+            ..Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", "internal error; see the server log")
+        }
+    }
+
+    fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = REQUEST_ID.try_with(|id| id.clone()).ok();
+        eprintln!(
+            "❌ {} {} (request {})",
+            self.status.as_u16(),
+            self.detail.as_deref().unwrap_or(&self.message),
+            request_id.as_deref().unwrap_or("-"),
+        );
+        let body = serde_json::json!({
+            "error": { "code": self.code, "message": self.message, "request_id": request_id.as_deref() },
+        });
+        let mut response = (self.status, axum::Json(body)).into_response();
+        if let Some(secs) = self.retry_after {
+            response.headers_mut().insert(axum::http::header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
+/// [`Query`], refusing parameters it can't read with a `bad_request` error.
+struct ApiQuery<T>(T);
+
+#[axum::async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for ApiQuery<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(params)) => Ok(Self(params)),
+            Err(e) => Err(ApiError::bad_request(e.body_text())),
+        }
+    }
+}
+
+/// Give every request an id: the client's `X-Request-Id` if it sent a short
+/// plain one, a new UUID otherwise. It is returned in the same header.
+async fn request_id(request: Request, next: Next) -> Response {
+    let id: Arc<str> = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .filter(|v| v.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .map(Arc::from)
+        .unwrap_or_else(|| Arc::from(uuid::Uuid::new_v4().to_string()));
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
 //
 // ---------- Frontend handlers ----------
 //
@@ -90,6 +626,10 @@ fn serve_asset(path: &str) -> Response {
 
             (StatusCode::OK, headers, body).into_response()
         }
+        // Unknown API routes and missing files are not the dashboard's routes
+        None if path == "api" || path.starts_with("api/") || std::path::Path::new(path).extension().is_some() => {
+            ApiError::not_found(format!("no such path: /{}", path)).into_response()
+        }
         None => {
             // SPA fallback → index.html
             if let Some(index) = FrontendAssets::get("index.html") {
@@ -101,63 +641,1358 @@ fn serve_asset(path: &str) -> Response {
                 )
                     .into_response()
             } else {
-                StatusCode::NOT_FOUND.into_response()
+                ApiError::not_found("the dashboard is not built into this binary").into_response()
             }
         }
     }
 }
 
+//
+// ---------- Health ----------
+//
+
+/// 200 whenever the server answers.
+async fn healthz_handler() -> impl IntoResponse {
+    axum::Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// 200 while the child runs and the audit writer works, else 503; the body
+/// gives the reasons, the child's pid, the uptime and the last event's time.
+async fn readyz_handler(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let (ready, body) = state.health.readiness();
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, axum::Json(body))
+}
+
+//
+// ---------- Metrics ----------
+//
+
+async fn metrics_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Metrics")?;
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        format!(
+            "{}{}{}{}{}# EOF\n",
+            state.metrics.render_openmetrics(),
+            state.feed.render_openmetrics(),
+            state.ws_clients.render_openmetrics(),
+            state.tap_drops.render_openmetrics(),
+            state.audit_sinks.render_openmetrics()
+        ),
+    )
+        .into_response())
+}
+
+/// Throughput totals so far: `{"servers": [{"server": .., "inbound": {..}, "outbound": {..}}]}`,
+/// with one entry (`"server": null`) unless multiplexing, WebSocket client
+/// counts: `"websocket": {"connected": .., "evicted": ..}`, and what redaction
+/// replaced: `"redactions": {"events": .., "total": .., "rules": {..},
+/// "truncated_scans": ..}` (null when it is off).
+async fn stats_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Stats")?;
+    let servers: Vec<_> = state
+        .throughput
+        .iter()
+        .map(|(server, throughput)| {
+            let mut entry = throughput.snapshot();
+            entry["server"] = serde_json::json!(server);
+            entry
+        })
+        .collect();
+    Ok(axum::Json(serde_json::json!({
+        "servers": servers,
+        "websocket": state.ws_clients.snapshot(),
+        "redactions": state.redactions.as_ref().map(RedactionTotals::snapshot),
+    }))
+    .into_response())
+}
+
+/// Latency per method so far: `{"methods": [{"method": .., "count": .., "p50_ms": ..}], ..}`
+async fn latency_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Latency")?;
+    Ok(axum::Json(state.metrics.latency_summary()).into_response())
+}
+
+/// `{"sessions": [{"session_id": .., "server": .., "info": ..}]}`; `info` is
+/// null until the session's `initialize` is seen.
+async fn session_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Session")?;
+    let sessions: Vec<_> = state.sessions.iter().map(|s| s.describe()).collect();
+    Ok(axum::Json(serde_json::json!({ "sessions": sessions })).into_response())
+}
+
+/// Where this server listens, so a client of a `--ws-bind` with port 0 can find
+/// the port picked.
+async fn info_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    let role = authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Info")?;
+    let listening = state.listening.get().cloned().unwrap_or_default();
+    Ok(axum::Json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "listen": listening.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        "loopback_only": listening.iter().all(|a| a.ip().is_loopback()),
+        "authenticated": state.auth.is_enabled(),
+        "role": role,
+    }))
+    .into_response())
+}
+
+async fn config_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Config")?;
+    match &state.config {
+        Some(config) => Ok(axum::Json(config).into_response()),
+        None => Err(ApiError::not_found("replay has no run configuration")),
+    }
+}
+
+/// The token of a request: `Authorization: Bearer <token>`, or else the `token`
+/// query parameter.
+fn request_token<'a>(headers: &'a HeaderMap, query: Option<&'a str>) -> Option<&'a str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .or(query)
+}
+
+/// The role of the request's token, if it is at least `required`. Without
+/// tokens configured, everyone is an admin.
+fn authorize(state: &ServerState, provided: Option<&str>, required: Role, what: &str) -> Result<Role, ApiError> {
+    if !state.auth.is_enabled() {
+        return Ok(Role::Admin);
+    }
+    let Some(provided) = provided else {
+        return Err(ApiError::unauthorized(format!("{} authentication failed: no token provided", what)));
+    };
+    match state.auth.role_of(provided) {
+        Some(role) if role >= required => Ok(role),
+        Some(role) => Err(ApiError::forbidden(format!("{} needs the {} role; the token has {}", what, required, role))),
+        None => Err(ApiError::unauthorized(format!("{} authentication failed: invalid token", what))),
+    }
+}
+
+//
+// ---------- Control ----------
+//
+
+async fn control_status_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Control")?;
+    Ok(axum::Json(state.forward_gate.status()).into_response())
+}
+
+/// Stop forwarding client messages; they are held until resumed. The
+/// response is the gate's status, as from `GET /api/control`.
+async fn pause_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Admin, "Control")?;
+    // Control actions are only taken while they can be audited.
+    let tap = state.tap.upgrade().ok_or_else(stopped_proxy)?;
+    let _exclusive = state.forward_gate.exclusive().await;
+    if state.forward_gate.pause() {
+        eprintln!("⏸️  Forwarding paused from the control API");
+        let event = RawTap::sentinel(
+            "sentinel/forwarding_paused",
+            serde_json::json!({ "max_held_bytes": state.forward_gate.max_held_bytes() }),
+        );
+        tap.send(event).await;
+    }
+    Ok(axum::Json(state.forward_gate.status()).into_response())
+}
+
+async fn resume_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Admin, "Control")?;
+    let tap = state.tap.upgrade().ok_or_else(stopped_proxy)?;
+    let _exclusive = state.forward_gate.exclusive().await;
+    // Recorded before the gate opens, so released messages follow it in the log.
+    if state.forward_gate.is_paused() {
+        let held = state.forward_gate.status();
+        let event = RawTap::sentinel(
+            "sentinel/forwarding_resumed",
+            serde_json::json!({
+                "paused_ms": held["paused_ms"],
+                "held_messages": held["held_messages"],
+                "held_bytes": held["held_bytes"],
+            }),
+        );
+        tap.send(event).await;
+        state.forward_gate.resume();
+        eprintln!("▶️  Forwarding resumed from the control API ({} held message(s) released)", held["held_messages"]);
+    }
+    Ok(axum::Json(state.forward_gate.status()).into_response())
+}
+
+/// 503 for a control action once the proxy has stopped: it could not be audited.
+fn stopped_proxy() -> ApiError {
+    ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "unavailable", "the proxy has stopped; control actions can't be audited")
+}
+
+//
+// ---------- History ----------
+//
+
+/// A JSON array of events, oldest first. Page back by passing the first
+/// returned `event_id` as `before`; an empty array means there is nothing older,
+/// a 410 that the older events can't be read back.
+async fn history_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<HistoryQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "History")?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_HISTORY_PAGE);
+    let page = match state.history.page(params.before, limit) {
+        Ok(page) => page,
+        Err(e) => return Err(history_error(e)),
+    };
+
+    let mut body = String::from("[");
+    for (i, json) in page.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        body.push_str(json);
+    }
+    body.push(']');
+
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+/// `{"events": [..], "next_offset": n}`: the events of the run (from memory, the
+/// spill or the audit log) that match every filter given, oldest first.
+/// `next_offset` is null once there are no more matches.
+async fn events_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<EventsQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Events")?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_HISTORY_PAGE);
+    let filter = EventFilter {
+        method: params.method,
+        direction: params.direction,
+        since_event_id: params.since_event_id,
+        since_ts_ms: params.since_ts_ms,
+    };
+    let (events, next_offset) = match state.history.query(&filter, params.offset.unwrap_or(0), limit) {
+        Ok(found) => found,
+        Err(e) => return Err(history_error(e)),
+    };
+
+    let mut body = String::from("{\"events\":[");
+    for (i, json) in events.iter().enumerate() {
+        if i > 0 {
+            body.push(',');
+        }
+        body.push_str(json);
+    }
+    body.push_str("],\"next_offset\":");
+    body.push_str(&serde_json::json!(next_offset).to_string());
+    body.push('}');
+
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+/// Every event matching the filters of `/api/events`, unpaged, as a download:
+/// JSON Lines or CSV, read from the history a page at a time while it is sent.
+async fn export_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<ExportQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Export")?;
+
+    let filter = EventFilter {
+        method: params.method,
+        direction: params.direction,
+        since_event_id: params.since_event_id,
+        since_ts_ms: params.since_ts_ms,
+    };
+    // The first page before answering, so that a failure still gets its status
+    let (events, more) = export_page(state.clone(), filter.clone()).await.map_err(history_error)?;
+
+    let run_id = state.config.as_ref().map_or("replay", |c| c.run_id.as_str());
+    let stamp = crate::report::format_timestamp(crate::events::current_timestamp_ms());
+    let (extension, content_type) = match params.format {
+        ExportFormat::Json => ("jsonl", "application/x-ndjson"),
+        ExportFormat::Csv => ("csv", "text/csv; charset=utf-8"),
+    };
+    let name = format!("sentinel-events-{}-{}.{}", run_id, stamp, extension).replace('"', "");
+    eprintln!("📤 Exporting events as {}", name);
+
+    let export = EventExport {
+        state: state.clone(),
+        filter,
+        format: params.format,
+        page: Some(Ok((events, more))),
+        header: matches!(params.format, ExportFormat::Csv),
+    };
+    let chunks = futures_util::stream::unfold(export, |mut export| async move {
+        let chunk = export.next_chunk().await?;
+        Some((chunk, export))
+    });
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, content_type.to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        axum::body::Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+/// Events of an export, and whether more follow them.
+type ExportPage = (Vec<Arc<str>>, bool);
+
+/// The first page of events matching `filter`, read off the async runtime as
+/// it may read the spill or the audit log.
+async fn export_page(state: Arc<ServerState>, filter: EventFilter) -> Result<ExportPage, HistoryError> {
+    tokio::task::spawn_blocking(move || state.history.query(&filter, 0, MAX_HISTORY_PAGE))
+        .await
+        .map_err(|e| HistoryError::Read(e.to_string()))?
+        .map(|(events, next)| (events, next.is_some()))
+}
+
+/// An export being sent: the page read last, and the filter that reads the
+/// next one after it.
+struct EventExport {
+    state: Arc<ServerState>,
+    filter: EventFilter,
+    format: ExportFormat,
+    /// The next page to send and whether more follow; `None` once all are sent
+    page: Option<Result<ExportPage, String>>,
+    /// The CSV header is still to be sent
+    header: bool,
+}
+
+impl EventExport {
+    /// The next page, written out. A page that can't be read, or a stored event
+    /// that can't be, ends the body with an error, so the client sees the
+    /// download failed.
+    async fn next_chunk(&mut self) -> Option<Result<String, String>> {
+        let (events, more) = match self.page.take()? {
+            Ok(page) => page,
+            Err(e) => {
+                eprintln!("❌ Event export failed: {}", e);
+                return Some(Err(e));
+            }
+        };
+
+        let mut chunk = String::new();
+        if std::mem::take(&mut self.header) {
+            chunk.push_str(EXPORT_CSV_COLUMNS);
+            chunk.push('\n');
+        }
+        let mut last_id = None;
+        for json in &events {
+            let fields = match serde_json::from_str::<ExportFields>(json) {
+                Ok(fields) => fields,
+                Err(e) => {
+                    // What came before it is sent, then the error.
+                    let after = last_id.or(self.filter.since_event_id).unwrap_or(0);
+                    self.page = Some(Err(format!("the event after {} can't be read: {}", after, e)));
+                    return Some(Ok(chunk));
+                }
+            };
+            last_id = Some(fields.event_id);
+            match self.format {
+                ExportFormat::Json => {
+                    chunk.push_str(json);
+                    chunk.push('\n');
+                }
+                ExportFormat::Csv => write_csv_row(&mut chunk, &fields),
+            }
+        }
+
+        // Continued after the last event sent rather than by offset, so events
+        // that leave the history meanwhile don't shift what comes next.
+        if let (true, Some(id)) = (more, last_id) {
+            self.filter.since_event_id = Some(id);
+            self.page = Some(
+                export_page(self.state.clone(), self.filter.clone())
+                    .await
+                    .map_err(|(HistoryError::Unreadable(reason) | HistoryError::Read(reason))| reason),
+            );
+        }
+        Some(Ok(chunk))
+    }
+}
+
+fn write_csv_row(out: &mut String, f: &ExportFields) {
+    use crate::report::csv_escape;
+    let request_id = match &f.request_id {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    };
+    let direction = serde_json::to_value(f.direction).unwrap_or_default();
+    let _ = writeln!(
+        out,
+        "{},{},{},{},{},{},{},{}",
+        f.event_id,
+        f.observed_ts_ms,
+        direction.as_str().unwrap_or(""),
+        csv_escape(f.method.as_deref().unwrap_or("")),
+        csv_escape(&request_id),
+        f.latency_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+        f.is_error || f.tool_is_error == Some(true),
+        csv_escape(f.tool_name.as_deref().unwrap_or("")),
+    );
+}
+
+/// 410 with the reason when events that left the history can't be read back
+/// from the audit log; 500 when reading failed.
+fn history_error(e: HistoryError) -> ApiError {
+    match e {
+        HistoryError::Unreadable(reason) => ApiError::new(StatusCode::GONE, "gone", reason),
+        HistoryError::Read(e) => ApiError::internal(e),
+    }
+}
+
+//
+// ---------- Audit log ----------
+//
+
+/// The audit log file as it is (ciphertext if encrypted), up to the last record
+/// flushed when the request came in.
+async fn audit_download_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuditDownloadQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Admin, "Audit download")?;
+
+    let log = match state.audit.segment(params.segment).await {
+        Ok(log) => log,
+        Err(e) => return Err(audit_error(e)),
+    };
+    let file = match tokio::fs::File::open(&log.path).await {
+        Ok(f) => f,
+        Err(e) => return Err(ApiError::internal(format!("open audit log {}: {}", log.path.display(), e))),
+    };
+    let name = log.path.file_name().unwrap_or_default().to_string_lossy().replace('"', "");
+    eprintln!("📤 Audit log {} downloaded ({} bytes)", log.path.display(), log.len);
+
+    let body = axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(tokio::io::AsyncReadExt::take(file, log.len)));
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (axum::http::header::CONTENT_LENGTH, log.len.to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Verify the audit log as far as it is flushed, with this run's verifying key.
+async fn audit_verify_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Audit verify")?;
+
+    let body = match state.audit.verify().await {
+        Ok((log, summary)) => serde_json::json!({
+            "ok": true,
+            "segments": log.segment + 1,
+            "bytes": log.len,
+            "records_seen": summary.records_seen,
+            "events_verified": summary.events_verified,
+            "checkpoints_verified": summary.checkpoints_verified,
+            "gaps": summary.gaps,
+            "missed_events": summary.missed_events,
+            "last_event_id": summary.last_event.map(|e| e.event_id),
+            "last_checkpoint_ts_ms": summary.last_checkpoint_ts_ms,
+            "warnings": summary.warnings,
+        }),
+        Err(AuditControlError::Failed(e)) => {
+            eprintln!("❌ Audit log verification failed: {}", e);
+            serde_json::json!({ "ok": false, "error": e })
+        }
+        Err(e) => return Err(audit_error(e)),
+    };
+    Ok(axum::Json(body).into_response())
+}
+
+/// Write a checkpoint (if the log is signed) and sync the log, recording in it
+/// that this was asked for. The reply names the chain tip it ended on.
+async fn audit_checkpoint_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<AuthQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Admin, "Audit checkpoint")?;
+
+    match state.audit.checkpoint().await {
+        Ok(cp) => Ok(axum::Json(cp).into_response()),
+        Err(e) => Err(audit_error(e)),
+    }
+}
+
+/// 404 without an audit log (or such a segment), 409 with the reason when it
+/// can't be verified while it is written, 429 for a checkpoint forced too soon
+/// after the last one, 500 when reading it failed.
+fn audit_error(e: AuditControlError) -> ApiError {
+    match e {
+        AuditControlError::NotFound(reason) => ApiError::not_found(reason),
+        AuditControlError::Unverifiable(reason) => ApiError::new(StatusCode::CONFLICT, "conflict", reason),
+        AuditControlError::RateLimited(wait) => {
+            let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            let message = format!("a checkpoint was forced less than 5s ago; retry in {}s", secs);
+            ApiError::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", message).with_retry_after(secs)
+        }
+        AuditControlError::Failed(e) => ApiError::internal(e),
+    }
+}
+
 //
 // ---------- WebSocket ----------
 //
 
 async fn websocket_handler(
-    ws: WebSocketUpgrade,
-    Query(params): Query<AuthQuery>,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<WsQuery>,
     State(state): State<Arc<ServerState>>,
-) -> Result<Response, StatusCode> {
-    if let Some(ref expected_token) = state.auth_token {
-        match params.token {
-            Some(provided) if provided == *expected_token => {}
-            Some(_) => {
-                eprintln!("❌ WebSocket authentication failed: invalid token");
-                return Err(StatusCode::UNAUTHORIZED);
+) -> Result<Response, ApiError> {
+    let role = authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "WebSocket")?;
+    let ws = ws.map_err(|e| ApiError::bad_request(e.body_text()))?;
+
+    let subscription =
+        match Subscription::from_query(params.methods.as_deref(), params.directions.as_deref(), params.errors_only) {
+            Ok(s) => s,
+            Err(e) => return Err(ApiError::bad_request(e)),
+        };
+    let since_event_id = params.since_event_id.unwrap_or(0);
+    let waiting = params.wait_for_subscribe;
+    Ok(ws.on_upgrade(move |socket| websocket_loop(socket, state, role, since_event_id, subscription, waiting)))
+}
+
+/// Send the history after `since_event_id`, then live events. Each event is sent
+/// once, in increasing `event_id` order, if it matches the client's subscription.
+/// While `waiting`, events are held until the client subscribes (again).
+///
+/// Messages go through a queue of `ws_client_queue` messages, which the history
+/// replay (and the live events that came in during it) waits on but live
+/// traffic does not: a client that lets it fill up, or misses a pong, is
+/// disconnected with a close frame saying why.
+async fn websocket_loop(
+    socket: WebSocket,
+    state: Arc<ServerState>,
+    role: Role,
+    since_event_id: u64,
+    mut subscription: Subscription,
+    mut waiting: bool,
+) {
+    let (sink, mut stream) = socket.split();
+    let (out, queue) = mpsc::channel(state.ws_client_queue.max(1));
+    let (close_tx, close_rx) = oneshot::channel();
+    let mut writer = tokio::spawn(websocket_writer(sink, queue, close_rx));
+    let _client = state.ws_clients.connect();
+
+    // Subscribed before the replay, so events published meanwhile are not
+    // missed; those the replay already sent are skipped below.
+    let mut subscriber = state.feed.subscribe();
+    let mut last_sent = since_event_id;
+    // Replayed one event per turn of the loop, after any client message, so a
+    // subscription also filters the rest of the replay.
+    let mut replay = VecDeque::from(state.history.recent(since_event_id));
+    // Until the subscriber has nothing left over from the replay
+    let mut catching_up = true;
+    let mut watermark_tick = state.watermark_interval.map(tokio::time::interval);
+    let mut stats_tick = state.stats_interval.map(tokio::time::interval);
+    let mut ping_tick = state.ws_ping_interval.map(tokio::time::interval);
+    let mut awaiting_pong = false;
+
+    eprintln!("✅ WebSocket client connected ({})", role);
+    if let Some(config) = &state.config {
+        let mut frame = serde_json::json!(config);
+        frame["type"] = "config".into();
+        // The queue is still empty
+        let _ = enqueue(&out, Message::Text(frame.to_string()));
+    }
+
+    // Why the client is disconnected, if it is evicted
+    let evicted: Option<String> = loop {
+        let next = tokio::select! {
+            biased;
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Subscribe(new)) => {
+                            subscription = new;
+                            waiting = false;
+                            let mut frame = serde_json::json!(subscription);
+                            frame["op"] = "subscribed".into();
+                            frame.to_string()
+                        }
+                        Err(e) => serde_json::json!({ "op": "error", "error": e.to_string() }).to_string(),
+                    };
+                    if let Err(reason) = enqueue(&out, Message::Text(reply)) {
+                        break reason;
+                    }
+                    continue;
+                }
+                Some(Ok(Message::Pong(_))) => {
+                    awaiting_pong = false;
+                    continue;
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break None,
+                Some(Ok(_)) => continue,
+            },
+            permit = out.reserve(), if !waiting && !replay.is_empty() => {
+                let Ok(permit) = permit else { break None };
+                let Some((event_id, text)) = replay.pop_front() else { continue };
+                last_sent = event_id;
+                if subscription.matches(&text) {
+                    permit.send(Message::Text(text.to_string()));
+                }
+                continue;
+            }
+            // Live events wait in the subscriber until the replay is done. Those
+            // that came in meanwhile are part of catching up, and wait for room
+            // in the queue like the replay.
+            permit = out.reserve(), if !waiting && replay.is_empty() && catching_up => {
+                let Ok(_permit) = permit else { break None };
+                match subscriber.try_recv() {
+                    // The slot reserved is free again for the enqueue below.
+                    Some(next) => next,
+                    None => {
+                        catching_up = false;
+                        continue;
+                    }
+                }
+            }
+            next = subscriber.recv(), if !waiting && replay.is_empty() && !catching_up => next,
+            _ = tick(&mut ping_tick) => {
+                if awaiting_pong {
+                    break Some("no pong to the last ping".to_string());
+                }
+                awaiting_pong = true;
+                if let Err(reason) = enqueue(&out, Message::Ping(Vec::new())) {
+                    break reason;
+                }
+                continue;
+            }
+            _ = tick(&mut watermark_tick) => {
+                if let Err(reason) = enqueue(&out, Message::Text(subscriber.watermark().frame())) {
+                    break reason;
+                }
+                continue;
+            }
+            _ = tick(&mut stats_tick) => {
+                if let Err(reason) = enqueue(&out, Message::Text(stats_frame(&state))) {
+                    break reason;
+                }
+                continue;
+            }
+            _ = state.shutdown.cancelled() => Recv::Closed,
+        };
+        let message = match next {
+            Recv::Event(_, mark) if mark.event_id <= last_sent => continue,
+            Recv::Event(text, mark) => {
+                last_sent = mark.event_id;
+                if !subscription.matches(&text) {
+                    continue;
+                }
+                Message::Text(text.to_string())
             }
-            None => {
-                eprintln!("❌ WebSocket authentication failed: no token provided");
-                return Err(StatusCode::UNAUTHORIZED);
+            Recv::Lagged(missed) => {
+                eprintln!("⚠️  WebSocket client fell behind, skipped {} events", missed);
+                Message::Text(serde_json::json!({ "type": "gap", "missed": missed }).to_string())
             }
+            Recv::Closed => break None,
+        };
+        if let Err(reason) = enqueue(&out, message) {
+            break reason;
         }
+    };
+
+    if let Some(reason) = evicted {
+        eprintln!("⚠️  WebSocket client evicted: {}", reason);
+        state.ws_clients.evicted();
+        let _ = close_tx.send(CloseFrame { code: close_code::POLICY, reason: reason.into() });
+    }
+    // The writer sends what is still queued (or the close frame), then ends.
+    drop(out);
+    if tokio::time::timeout(WS_CLOSE_TIMEOUT, &mut writer).await.is_err() {
+        writer.abort();
     }
 
-    Ok(ws.on_upgrade(move |socket| websocket_loop(socket, state)))
+    eprintln!("❌ WebSocket client disconnected");
 }
 
-async fn websocket_loop(mut socket: WebSocket, state: Arc<ServerState>) {
-    // Replay history
-    {
-        let hist = state.history.read().await;
-        for log in hist.iter() {
-            if let Ok(text) = serde_json::to_string(log) {
-                if socket.send(Message::Text(text)).await.is_err() {
+/// Queue a message for a WebSocket client. Fails with the reason to evict the
+/// client when its queue is full, or with `None` when the connection is gone.
+fn enqueue(out: &mpsc::Sender<Message>, message: Message) -> Result<(), Option<String>> {
+    out.try_send(message).map_err(|e| match e {
+        mpsc::error::TrySendError::Full(_) => {
+            Some(format!("too slow: {} messages queued", out.max_capacity()))
+        }
+        mpsc::error::TrySendError::Closed(_) => None,
+    })
+}
+
+/// Send queued messages until the queue closes. Once `close` fires, whatever is
+/// still queued is dropped and the close frame goes out instead.
+async fn websocket_writer(
+    mut sink: SplitSink<WebSocket, Message>,
+    mut queue: mpsc::Receiver<Message>,
+    mut close: oneshot::Receiver<CloseFrame<'static>>,
+) {
+    loop {
+        let sent = tokio::select! {
+            biased;
+            Ok(frame) = &mut close => {
+                let _ = sink.send(Message::Close(Some(frame))).await;
+                return;
+            }
+            message = queue.recv() => match message {
+                // A send stuck on a slow client gives way to the close frame too.
+                Some(message) => tokio::select! {
+                    biased;
+                    Ok(frame) = &mut close => {
+                        let _ = sink.send(Message::Close(Some(frame))).await;
+                        return;
+                    }
+                    sent = sink.send(message) => sent,
+                },
+                None => {
+                    let _ = sink.close().await;
                     return;
                 }
+            },
+        };
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+//
+// ---------- Server-Sent Events ----------
+//
+
+/// The WebSocket stream as `text/event-stream`: the history after
+/// `since_event_id` (or `Last-Event-ID`), then live events, each as `data:` with
+/// its `event_id` as `id:`. Skipped events show as an `event: gap`.
+async fn stream_handler(
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<StreamQuery>,
+    State(state): State<Arc<ServerState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, request_token(&headers, params.token.as_deref()), Role::Viewer, "Stream")?;
+
+    let subscription =
+        match Subscription::from_query(params.methods.as_deref(), params.directions.as_deref(), params.errors_only) {
+            Ok(s) => s,
+            Err(e) => return Err(ApiError::bad_request(e)),
+        };
+    // Sent by browsers when they reconnect, and newer than the URL's since_event_id
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let since_event_id = last_event_id.or(params.since_event_id).unwrap_or(0);
+
+    let stream = SseStream {
+        // Subscribed before the replay, as for WebSocket clients
+        subscriber: state.feed.subscribe(),
+        replay: VecDeque::from(state.history.recent(since_event_id)),
+        last_sent: since_event_id,
+        subscription,
+        shutdown: state.shutdown.clone(),
+    };
+    eprintln!("✅ Event stream client connected");
+    let events = futures_util::stream::unfold(stream, |mut stream| async move {
+        let event = stream.next_event().await?;
+        Some((Ok::<_, Infallible>(event), stream))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(SSE_KEEP_ALIVE)).into_response())
+}
+
+/// Where an `/api/stream` client is: what is left of its replay, then the feed.
+struct SseStream {
+    subscriber: crate::feed::Subscriber,
+    replay: VecDeque<(u64, Arc<str>)>,
+    last_sent: u64,
+    subscription: Subscription,
+    shutdown: CancellationToken,
+}
+
+impl SseStream {
+    /// The next event to send, or `None` once the server stops.
+    async fn next_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some((event_id, text)) = self.replay.pop_front() {
+                self.last_sent = event_id;
+                if self.subscription.matches(&text) {
+                    return Some(Event::default().id(event_id.to_string()).data(&*text));
+                }
+                continue;
             }
+            let next = tokio::select! {
+                next = self.subscriber.recv() => next,
+                _ = self.shutdown.cancelled() => Recv::Closed,
+            };
+            match next {
+                Recv::Event(_, mark) if mark.event_id <= self.last_sent => continue,
+                Recv::Event(text, mark) => {
+                    self.last_sent = mark.event_id;
+                    if self.subscription.matches(&text) {
+                        return Some(Event::default().id(mark.event_id.to_string()).data(&*text));
+                    }
+                }
+                Recv::Lagged(missed) => {
+                    let gap = serde_json::json!({ "type": "gap", "missed": missed }).to_string();
+                    return Some(Event::default().event("gap").data(gap));
+                }
+                Recv::Closed => return None,
+            }
+        }
+    }
+}
+
+impl Drop for SseStream {
+    fn drop(&mut self) {
+        eprintln!("❌ Event stream client disconnected");
+    }
+}
+
+/// Cumulative pipeline counters, sent as `{"op": "stats", ...}`.
+fn stats_frame(state: &ServerState) -> String {
+    serde_json::json!({
+        "op": "stats",
+        "tap_overflow": state.tap_overflow.as_str(),
+        "tap_dropped": state.tap_drops.totals(),
+        "tap_dropped_total": state.tap_drops.total(),
+        "audit_sinks": state.audit_sinks.snapshot(),
+        "forwarding": state.forward_gate.status(),
+    })
+    .to_string()
+}
+
+/// Next tick of an optional interval; pending forever when there is none.
+pub async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(i) => {
+            i.tick().await;
         }
+        None => std::future::pending().await,
     }
+}
 
-    let rx = state.tx.subscribe();
-    let mut stream = BroadcastStream::new(rx);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::Watermark;
+    use tokio_tungstenite::tungstenite::{self, protocol::frame::coding::CloseCode};
 
-    eprintln!("✅ WebSocket client connected");
+    const QUEUE: usize = 8;
 
-    while let Some(Ok(log)) = stream.next().await {
-        if let Ok(text) = serde_json::to_string(&log) {
-            if socket.send(Message::Text(text)).await.is_err() {
-                break;
+    /// Serve `state` on a free loopback port.
+    async fn serve(state: Arc<ServerState>) -> SocketAddr {
+        let addrs = [SocketAddr::from(([127, 0, 0, 1], 0))];
+        tokio::spawn({
+            let state = state.clone();
+            async move { start_server(state, &addrs).await.unwrap() }
+        });
+        loop {
+            if let Some(addr) = state.listening.get().and_then(|a| a.first()) {
+                return *addr;
             }
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
     }
 
-    eprintln!("❌ WebSocket client disconnected");
+    fn publish(state: &ServerState, event_id: u64) {
+        let event = serde_json::json!({
+            "event_id": event_id,
+            "direction": "Inbound",
+            "method": "tools/call",
+            "payload": "x".repeat(64 * 1024),
+        });
+        let text: Arc<str> = Arc::from(event.to_string());
+        state.history.push(event_id, text.clone());
+        state.feed.publish(text, Watermark { ts_ms: event_id, event_id });
+    }
+
+    /// The `event_id` of a frame sent by `publish`, read off its head: parsing
+    /// all of them would slow the test down enough to miss the close frame.
+    fn event_id(text: &str) -> u64 {
+        let rest = &text[text.find(r#""event_id":"#).unwrap() + 11..];
+        rest[..rest.find(',').unwrap()].parse().unwrap()
+    }
+
+    /// A client that stops reading fills its queue and is disconnected with a
+    /// close frame saying why; a client reading along is not held up by it.
+    #[tokio::test]
+    async fn a_stalled_websocket_client_is_evicted_without_holding_up_the_others() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 16);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.ws_client_queue = QUEUE;
+        settings.watermark_interval = None;
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        let addr = serve(state.clone()).await;
+        let url = format!("ws://{}/ws", addr);
+
+        let (mut stalled, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (healthy, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        while state.ws_clients.snapshot()["connected"] != 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // More than the socket buffers of the stalled client can take.
+        const EVENTS: u64 = 600;
+        let (mut healthy_tx, mut healthy_rx) = healthy.split();
+        let reader = tokio::spawn(async move {
+            let mut ids = Vec::new();
+            while let Some(Ok(message)) = healthy_rx.next().await {
+                if let tungstenite::Message::Text(text) = message {
+                    ids.push(event_id(&text));
+                    if ids.len() as u64 == EVENTS + 1 {
+                        break;
+                    }
+                }
+            }
+            ids
+        });
+        // Once evicted, the stalled client reads again and finds what its socket
+        // held, then the close frame. It reads at once, while events are still
+        // published, as the server gives it only so long.
+        let stalled = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while state.ws_clients.snapshot()["evicted"] != 1 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                while let Some(message) = stalled.next().await {
+                    if let Ok(tungstenite::Message::Close(frame)) = message {
+                        return frame;
+                    }
+                }
+                None
+            }
+        });
+        for event_id in 1..=EVENTS {
+            publish(&state, event_id);
+            tokio::task::yield_now().await;
+        }
+
+        // The healthy client still gets everything, including what comes after.
+        publish(&state, EVENTS + 1);
+        let ids = tokio::time::timeout(Duration::from_secs(10), reader).await.unwrap().unwrap();
+        assert_eq!(ids, (1..=EVENTS + 1).collect::<Vec<_>>());
+
+        let close = tokio::time::timeout(Duration::from_secs(10), stalled)
+            .await
+            .unwrap()
+            .unwrap()
+            .expect("a close frame");
+        assert_eq!(close.code, CloseCode::Policy);
+        assert_eq!(close.reason.as_str(), format!("too slow: {} messages queued", QUEUE));
+        assert_eq!(state.ws_clients.snapshot()["evicted"], 1);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while state.ws_clients.snapshot()["connected"] != 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("only the healthy client is left");
+        let _ = healthy_tx.close().await;
+    }
+
+    /// A client reconnecting mid-stream gets the events after the last one it
+    /// saw, replayed and then live, each once and in order. Live events that
+    /// come in while the replay fills its queue wait for it, and do not get
+    /// the client evicted.
+    #[tokio::test]
+    async fn a_reconnecting_client_gets_its_replay_then_live_events_in_order() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 1000);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.ws_client_queue = QUEUE;
+        settings.watermark_interval = None;
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        for event_id in 1..=100 {
+            publish(&state, event_id);
+        }
+        let addr = serve(state.clone()).await;
+
+        let url = format!("ws://{}/ws?since_event_id=10", addr);
+        let (mut client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        for event_id in 101..=150 {
+            publish(&state, event_id);
+            tokio::task::yield_now().await;
+        }
+
+        let ids = tokio::time::timeout(Duration::from_secs(10), async {
+            let mut ids = Vec::new();
+            while ids.len() < 140 {
+                match client.next().await {
+                    Some(Ok(tungstenite::Message::Text(text))) => ids.push(event_id(&text)),
+                    Some(Ok(tungstenite::Message::Close(frame))) => panic!("closed: {:?}", frame),
+                    Some(Ok(_)) => {}
+                    other => panic!("stream ended: {:?}", other),
+                }
+            }
+            ids
+        })
+        .await
+        .unwrap();
+        assert_eq!(ids, (11..=150).collect::<Vec<_>>());
+        assert_eq!(state.ws_clients.snapshot()["evicted"], 0);
+        let _ = client.close(None).await;
+    }
+
+    #[tokio::test]
+    async fn a_websocket_client_gets_the_run_configuration_first() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 16);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.watermark_interval = None;
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        settings.config = Some(RuntimeConfig {
+            run_id: "run".into(),
+            session_id: "session".into(),
+            audit_log: "audit.jsonl".into(),
+            redaction_enabled: false,
+            redaction_scope: "off",
+            redaction_rules: Vec::new(),
+            signing_key_id: "unsigned".into(),
+            encryption: "plaintext".into(),
+            checkpoint_every: 1000,
+        });
+        let addr = serve(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        let Some(Ok(tungstenite::Message::Text(text))) = client.next().await else {
+            panic!("no config frame");
+        };
+        let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(frame["type"], "config");
+        assert_eq!(frame["run_id"], "run");
+        assert!(frame.get("op").is_none());
+        let _ = client.close(None).await;
+    }
+
+    #[tokio::test]
+    async fn an_export_names_directions_as_stored_and_fails_on_an_unreadable_event() {
+        let state = crate::replay::server_state(AuthConfig::default(), 16);
+        for (event_id, direction) in [(1, "Outbound"), (2, "Inbound")] {
+            let event = serde_json::json!({
+                "event_id": event_id,
+                "observed_ts_ms": 1000 + event_id,
+                "direction": direction,
+                "method": "tools/call",
+            });
+            state.history.push(event_id, Arc::from(event.to_string()));
+        }
+        let addr = serve(state.clone()).await;
+        let url = format!("http://{}/api/events/export?format=csv", addr);
+
+        let csv = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], EXPORT_CSV_COLUMNS);
+        assert_eq!(rows[1], "1,1001,Outbound,tools/call,,,false,");
+        assert_eq!(rows[2], "2,1002,Inbound,tools/call,,,false,");
+
+        state.history.push(3, Arc::from("{\"event_id\": 3}"));
+        let body = match reqwest::get(&url).await {
+            Ok(response) => response.text().await.ok(),
+            Err(_) => None,
+        };
+        assert_eq!(body, None, "the download is cut off");
+    }
+
+    /// Push and publish a small event.
+    fn publish_event(state: &ServerState, event_id: u64, method: &str, direction: &str, is_error: bool) {
+        let event = serde_json::json!({
+            "event_id": event_id,
+            "direction": direction,
+            "method": method,
+            "is_error": is_error,
+        });
+        let text: Arc<str> = Arc::from(event.to_string());
+        state.history.push(event_id, text.clone());
+        state.feed.publish(text, Watermark { ts_ms: event_id, event_id });
+    }
+
+    /// The next text frame a client gets.
+    async fn next_frame<S>(client: &mut S) -> serde_json::Value
+    where
+        S: futures_util::Stream<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match client.next().await {
+                    Some(Ok(tungstenite::Message::Text(text))) => return serde_json::from_str(&text).unwrap(),
+                    Some(Ok(_)) => {}
+                    other => panic!("stream ended: {:?}", other),
+                }
+            }
+        })
+        .await
+        .expect("a frame")
+    }
+
+    /// A subscription filters the replay and live events, and a new one applies
+    /// to what follows it; the event ids a client gets show nothing else slipped in.
+    #[tokio::test]
+    async fn a_subscription_filters_replay_and_live_events_until_it_is_changed() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 100);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.watermark_interval = None;
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        publish_event(&state, 1, "tools/call", "Outbound", false);
+        publish_event(&state, 2, "tools/call", "Inbound", true);
+        publish_event(&state, 3, "tools/list", "Outbound", false);
+        publish_event(&state, 4, "tools/list", "Inbound", false);
+        let addr = serve(state.clone()).await;
+
+        let url = format!("ws://{}/ws?methods=tools/call", addr);
+        let (mut client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        assert_eq!(next_frame(&mut client).await["event_id"], 1);
+        assert_eq!(next_frame(&mut client).await["event_id"], 2);
+        publish_event(&state, 5, "tools/list", "Outbound", false);
+        publish_event(&state, 6, "tools/call", "Outbound", false);
+        assert_eq!(next_frame(&mut client).await["event_id"], 6);
+
+        // Unknown fields are ignored.
+        let subscribe = r#"{"type": "subscribe", "directions": ["Inbound"], "errors_only": true, "sample": 0.5}"#;
+        client.send(tungstenite::Message::Text(subscribe.into())).await.unwrap();
+        let ack = next_frame(&mut client).await;
+        assert_eq!(ack["op"], "subscribed");
+        assert_eq!(ack["methods"], serde_json::Value::Null);
+        assert_eq!(ack["directions"], serde_json::json!(["Inbound"]));
+        publish_event(&state, 7, "tools/call", "Inbound", false);
+        publish_event(&state, 8, "tools/call", "Outbound", true);
+        publish_event(&state, 9, "tools/list", "Inbound", true);
+        assert_eq!(next_frame(&mut client).await["event_id"], 9);
+
+        // No fields is everything again.
+        client.send(tungstenite::Message::Text(r#"{"type": "subscribe"}"#.into())).await.unwrap();
+        assert_eq!(next_frame(&mut client).await["op"], "subscribed");
+        publish_event(&state, 10, "ping", "Outbound", false);
+        assert_eq!(next_frame(&mut client).await["event_id"], 10);
+
+        // A message it can't read changes nothing.
+        client.send(tungstenite::Message::Text(r#"{"type": "unsubscribe"}"#.into())).await.unwrap();
+        assert_eq!(next_frame(&mut client).await["op"], "error");
+        publish_event(&state, 11, "ping", "Inbound", false);
+        assert_eq!(next_frame(&mut client).await["event_id"], 11);
+        let _ = client.close(None).await;
+    }
+
+    /// Two clients on one stream each get what their own subscription asks for.
+    #[tokio::test]
+    async fn subscriptions_are_per_connection() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 100);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.watermark_interval = None;
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        let addr = serve(state.clone()).await;
+
+        let (mut calls, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws?methods=tools/call", addr))
+            .await
+            .unwrap();
+        let (mut everything, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        while state.ws_clients.snapshot()["connected"] != 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        publish_event(&state, 1, "tools/list", "Outbound", false);
+        publish_event(&state, 2, "tools/call", "Outbound", false);
+        assert_eq!(next_frame(&mut calls).await["event_id"], 2);
+        assert_eq!(next_frame(&mut everything).await["event_id"], 1);
+        assert_eq!(next_frame(&mut everything).await["event_id"], 2);
+        let _ = calls.close(None).await;
+        let _ = everything.close(None).await;
+    }
+
+    /// A client that drops off after event 5 and reconnects with
+    /// `since_event_id=5` gets the events it missed, then live ones, each once.
+    #[tokio::test]
+    async fn a_client_reconnecting_after_a_drop_gets_what_it_missed_once() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 100);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.watermark_interval = None;
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        let addr = serve(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        while state.ws_clients.snapshot()["connected"] != 1 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        for event_id in 1..=5 {
+            publish_event(&state, event_id, "tools/call", "Outbound", false);
+            assert_eq!(next_frame(&mut client).await["event_id"], event_id);
+        }
+        client.close(None).await.unwrap();
+        drop(client);
+        while state.ws_clients.snapshot()["connected"] != 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        for event_id in 6..=10 {
+            publish_event(&state, event_id, "tools/call", "Outbound", false);
+        }
+        let url = format!("ws://{}/ws?since_event_id=5", addr);
+        let (mut client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let mut ids = Vec::new();
+        for _ in 6..=10 {
+            ids.push(next_frame(&mut client).await["event_id"].as_u64().unwrap());
+        }
+        publish_event(&state, 11, "tools/call", "Outbound", false);
+        ids.push(next_frame(&mut client).await["event_id"].as_u64().unwrap());
+        assert_eq!(ids, (6..=11).collect::<Vec<_>>());
+
+        // Nothing else is on its way.
+        publish_event(&state, 12, "tools/call", "Outbound", false);
+        assert_eq!(next_frame(&mut client).await["event_id"], 12);
+        let _ = client.close(None).await;
+    }
+
+    /// What a consumer has been told so far, across its connections.
+    #[derive(Default)]
+    struct Consumer {
+        watermark: Watermark,
+        last_event: u64,
+    }
+
+    impl Consumer {
+        /// Take the next frame: a watermark must not go back, and an event
+        /// must not be one a watermark already covered.
+        async fn read<S>(&mut self, client: &mut S) -> serde_json::Value
+        where
+            S: futures_util::Stream<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+        {
+            let frame = next_frame(client).await;
+            if frame["op"] == "watermark" {
+                let mark = Watermark {
+                    ts_ms: frame["ts_ms"].as_u64().unwrap(),
+                    event_id: frame["event_id"].as_u64().unwrap(),
+                };
+                assert!(mark.ts_ms >= self.watermark.ts_ms && mark.event_id >= self.watermark.event_id, "{:?} after {:?}", mark, self.watermark);
+                self.watermark = mark;
+            } else if let Some(event_id) = frame["event_id"].as_u64() {
+                assert!(event_id > self.watermark.event_id, "event {} after watermark {:?}", event_id, self.watermark);
+                self.last_event = event_id;
+            }
+            frame
+        }
+    }
+
+    /// Watermarks hold still while the pipeline stalls, pick up where they
+    /// were on a reconnect, and never run ahead of the events sent.
+    #[tokio::test]
+    async fn watermarks_never_regress_across_stalls_and_reconnects() {
+        let mut state = crate::replay::server_state(AuthConfig::default(), 1000);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.watermark_interval = Some(Duration::from_millis(5));
+        settings.stats_interval = None;
+        settings.ws_ping_interval = None;
+        let addr = serve(state.clone()).await;
+        let url = |since: u64| format!("ws://{}/ws?since_event_id={}", addr, since);
+        let mut consumer = Consumer::default();
+
+        for id in 1..=100 {
+            publish(&state, id);
+        }
+        let (mut client, _) = tokio_tungstenite::connect_async(url(0)).await.unwrap();
+        while consumer.last_event < 100 {
+            consumer.read(&mut client).await;
+        }
+
+        // Nothing published: the watermark catches up to event 100 and stays.
+        let mut stalled = 0;
+        while stalled < 10 {
+            if consumer.read(&mut client).await["op"] == "watermark" && consumer.watermark.event_id == 100 {
+                stalled += 1;
+            }
+        }
+        assert_eq!(consumer.watermark, Watermark { ts_ms: 100, event_id: 100 });
+        let _ = client.close(None).await;
+
+        for id in 101..=300 {
+            publish(&state, id);
+        }
+        let (mut client, _) = tokio_tungstenite::connect_async(url(100)).await.unwrap();
+        while consumer.last_event < 300 {
+            consumer.read(&mut client).await;
+        }
+        while consumer.watermark.event_id < 300 {
+            consumer.read(&mut client).await;
+        }
+        let _ = client.close(None).await;
+    }
+
+    /// With a memory budget of a few events, `/api/history` still pages back
+    /// to event 1, through the spill.
+    #[tokio::test]
+    async fn history_pages_back_to_event_1_through_the_spill() {
+        let spill = tempfile::tempdir().unwrap();
+        let mut state = crate::replay::server_state(AuthConfig::default(), 1000);
+        let settings = Arc::get_mut(&mut state).unwrap();
+        settings.history = EventStore::new(1000, 256 * 1024).with_spill(spill.path(), 64 * 1024 * 1024).unwrap();
+        let addr = serve(state.clone()).await;
+        for id in 1..=100 {
+            publish(&state, id);
+        }
+
+        let mut ids = Vec::new();
+        let mut url = format!("http://{}/api/history?limit=30", addr);
+        loop {
+            let page: Vec<serde_json::Value> = reqwest::get(&url).await.unwrap().json().await.unwrap();
+            let Some(oldest) = page.first() else { break };
+            url = format!("http://{}/api/history?limit=30&before={}", addr, oldest["event_id"]);
+            ids.extend(page.iter().rev().map(|event| event["event_id"].as_u64().unwrap()));
+        }
+        assert_eq!(ids, (1..=100).rev().collect::<Vec<u64>>());
+    }
 }
@@ -1,22 +1,156 @@
+use serde::Serialize;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
-
 pub struct Session {
     pub session_id: String,
     pub trace_id: String,
+    /// The wrapped server this session belongs to (multiplexed mode only)
+    pub server_name: Option<String>,
+
+    /// Bumped whenever the MCP session is reset (re-initialize, child restart).
+    /// Per-session state tagged with an older epoch must be ignored.
+    epoch: AtomicU64,
+
+    /// What the latest `initialize` handshake declared
+    info: RwLock<Option<SessionInfo>>,
+}
+
+/// Where the MCP lifecycle is, as seen on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPhase {
+    /// `initialize` sent, not answered yet
+    Initializing,
+    /// The server answered `initialize`
+    Initialized,
+    /// The client sent `notifications/initialized`
+    Operating,
+}
+
+/// The distilled `initialize` handshake, logged as `sentinel/session_info` and
+/// served by `/api/session`. Request fields are filled as the request passes;
+/// `protocol_version` is the server's answer once there is one.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub phase: SessionPhase,
+    pub protocol_version: Option<String>,
+    pub client_name: Option<String>,
+    pub client_version: Option<String>,
+    pub server_name: Option<String>,
+    pub server_version: Option<String>,
+    /// Declared capabilities, dotted for nested flags that are set (`tools.listChanged`)
+    pub client_capabilities: Vec<String>,
+    pub server_capabilities: Vec<String>,
+}
+
+impl SessionInfo {
+    /// From the params of an `initialize` request.
+    pub fn from_request(params: &Value) -> Self {
+        let client = params.get("clientInfo");
+        Self {
+            phase: SessionPhase::Initializing,
+            protocol_version: string_at(params, "protocolVersion"),
+            client_name: client.and_then(|c| string_at(c, "name")),
+            client_version: client.and_then(|c| string_at(c, "version")),
+            server_name: None,
+            server_version: None,
+            client_capabilities: capability_flags(params.get("capabilities")),
+            server_capabilities: Vec::new(),
+        }
+    }
+
+    /// Add the server's side from the result of the `initialize` response.
+    pub fn answered(&mut self, result: &Value) {
+        let server = result.get("serverInfo");
+        self.phase = SessionPhase::Initialized;
+        if let Some(version) = string_at(result, "protocolVersion") {
+            self.protocol_version = Some(version);
+        }
+        self.server_name = server.and_then(|s| string_at(s, "name"));
+        self.server_version = server.and_then(|s| string_at(s, "version"));
+        self.server_capabilities = capability_flags(result.get("capabilities"));
+    }
+}
+
+fn string_at(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(|v| v.as_str()).map(str::to_string)
+}
+
+/// `{"tools": {"listChanged": true}, "logging": {}}` -> `["logging", "tools", "tools.listChanged"]`
+fn capability_flags(capabilities: Option<&Value>) -> Vec<String> {
+    let mut flags = Vec::new();
+    for (name, value) in capabilities.and_then(|c| c.as_object()).into_iter().flatten() {
+        flags.push(name.clone());
+        for (flag, set) in value.as_object().into_iter().flatten() {
+            if set.as_bool() == Some(true) {
+                flags.push(format!("{}.{}", name, flag));
+            }
+        }
+    }
+    flags.sort();
+    flags
+}
+
+impl Session {
+    pub fn new(session_id: String, trace_id: String) -> Self {
+        Self {
+            session_id,
+            trace_id,
+            server_name: None,
+            epoch: AtomicU64::new(0),
+            info: RwLock::new(None),
+        }
+    }
+
+    pub fn with_server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Start a new epoch and return its number.
+    pub fn advance_epoch(&self) -> u64 {
+        self.epoch.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    pub fn info(&self) -> Option<SessionInfo> {
+        self.info.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set_info(&self, info: Option<SessionInfo>) {
+        *self.info.write().unwrap_or_else(|e| e.into_inner()) = info;
+    }
+
+    /// `{"session_id": .., "server": .., "info": SessionInfo | null}`
+    pub fn describe(&self) -> Value {
+        serde_json::json!({
+            "session_id": self.session_id,
+            "server": self.server_name,
+            "info": self.info(),
+        })
+    }
 }
 
+#[allow(dead_code)]
 pub struct SessionState {
     pending_requests: HashMap<u64, Instant>,
 }
 
 
+#[allow(dead_code)]
 pub struct SessionTracker {
     pending: HashMap<u64, (u64, String)>, // request_id -> (timestamp, span_id)
 }
 
+#[allow(dead_code)]
 impl SessionTracker {
     pub fn new() -> Self {
         Self {
@@ -42,6 +176,7 @@ impl SessionTracker {
 
 }
 
+#[allow(dead_code)]
 impl SessionState {
     pub fn new() -> Self {
         Self {
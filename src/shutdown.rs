@@ -0,0 +1,340 @@
+use std::fmt;
+use std::future::Future;
+use std::process::ExitStatus;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Ordered shutdown phases. Each phase cancels its token, then waits (bounded by
+/// its timeout) for the tasks registered under it before the next phase starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Stop reading new input from the client; ask the child to exit (SIGTERM, then kill)
+    StopInput,
+    /// Let the tap sequencer and parser flush what is already in flight
+    Drain,
+    /// Write the shutdown event and final checkpoint, flush the audit sink
+    FinalizeAudit,
+    /// Stop the dashboard / WebSocket server
+    StopServer,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::StopInput, Phase::Drain, Phase::FinalizeAudit, Phase::StopServer];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn timeout(self) -> Duration {
+        match self {
+            Phase::StopInput => Duration::from_secs(5),
+            Phase::Drain => Duration::from_secs(5),
+            Phase::FinalizeAudit => Duration::from_secs(10),
+            Phase::StopServer => Duration::from_secs(2),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::StopInput => "stop_input",
+            Phase::Drain => "drain",
+            Phase::FinalizeAudit => "finalize_audit",
+            Phase::StopServer => "stop_server",
+        }
+    }
+}
+
+/// Why the runtime is shutting down. The first trigger wins.
+#[derive(Debug, Clone)]
+pub enum ShutdownReason {
+    /// The child's exit code, or the signal that terminated it (Unix)
+    ChildExited { code: Option<i32>, signal: Option<i32> },
+    /// The TCP upstream's connection ended after the client or Sentinel was done
+    ConnectionClosed,
+    Signal(&'static str),
+    ProxyError(String),
+    /// The audit log can no longer be written consistently
+    AuditFailed(String),
+}
+
+impl fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownReason::ChildExited { code: Some(code), .. } => write!(f, "child exited with status {}", code),
+            ShutdownReason::ChildExited { signal: Some(signal), .. } => {
+                write!(f, "child terminated by signal {}", signal)
+            }
+            ShutdownReason::ChildExited { .. } => write!(f, "child terminated"),
+            ShutdownReason::ConnectionClosed => write!(f, "upstream connection closed"),
+            ShutdownReason::Signal(name) => write!(f, "received {}", name),
+            ShutdownReason::ProxyError(e) => write!(f, "proxy error: {}", e),
+            ShutdownReason::AuditFailed(e) => write!(f, "audit log failed: {}", e),
+        }
+    }
+}
+
+impl ShutdownReason {
+    pub fn child_exited(status: ExitStatus) -> Self {
+        ShutdownReason::ChildExited {
+            code: status.code(),
+            signal: crate::proxy::exit_signal(&status),
+        }
+    }
+
+    /// Exit code for Sentinel itself: the child's code when it exited, 128 + the
+    /// signal number when it was killed (as shells report it), 1 otherwise.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            ShutdownReason::ChildExited { code, signal } => Some(code.or(signal.map(|s| 128 + s)).unwrap_or(1)),
+            ShutdownReason::ProxyError(_) | ShutdownReason::AuditFailed(_) => Some(1),
+            ShutdownReason::ConnectionClosed | ShutdownReason::Signal(_) => None,
+        }
+    }
+
+    /// Short machine-readable label for audit records.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ShutdownReason::ChildExited { .. } => "child_exited",
+            ShutdownReason::ConnectionClosed => "connection_closed",
+            ShutdownReason::Signal(_) => "signal",
+            ShutdownReason::ProxyError(_) => "proxy_error",
+            ShutdownReason::AuditFailed(_) => "audit_failed",
+        }
+    }
+}
+
+/// How one phase went, for the exit summary.
+#[derive(Debug, Clone)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    pub elapsed: Duration,
+    pub timed_out: bool,
+}
+
+struct State {
+    reason: Option<ShutdownReason>,
+    triggered_at: Option<Instant>,
+    child_exit_code: Option<i32>,
+    tasks: Vec<Vec<(&'static str, JoinHandle<()>)>>,
+    /// Added to each phase's default timeout
+    extra_time: Vec<Duration>,
+}
+
+/// Owns cancellation for the whole `run` runtime.
+pub struct ShutdownController {
+    /// Cancelled on the first trigger
+    triggered: CancellationToken,
+    /// One token per phase, cancelled when that phase starts
+    phases: Vec<CancellationToken>,
+    state: Mutex<State>,
+}
+
+impl Default for ShutdownController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownController {
+    pub fn new() -> Self {
+        let triggered = CancellationToken::new();
+        let phases = Phase::ALL.iter().map(|_| CancellationToken::new()).collect();
+        Self {
+            triggered,
+            phases,
+            state: Mutex::new(State {
+                reason: None,
+                triggered_at: None,
+                child_exit_code: None,
+                tasks: Phase::ALL.iter().map(|_| Vec::new()).collect(),
+                extra_time: vec![Duration::ZERO; Phase::ALL.len()],
+            }),
+        }
+    }
+
+    /// Token a task watches to learn that its phase has started.
+    pub fn token(&self, phase: Phase) -> CancellationToken {
+        self.phases[phase.index()].clone()
+    }
+
+    /// Spawn a task that is awaited (and aborted on timeout) during `phase`.
+    pub fn spawn<F>(&self, phase: Phase, name: &'static str, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(fut);
+        self.lock().tasks[phase.index()].push((name, handle));
+    }
+
+    /// Give `phase` more time than its default timeout (e.g. a configured grace period).
+    pub fn extend(&self, phase: Phase, by: Duration) {
+        self.lock().extra_time[phase.index()] += by;
+    }
+
+    /// Request shutdown. Only the first reason is kept.
+    pub fn trigger(&self, reason: ShutdownReason) {
+        {
+            let mut state = self.lock();
+            if state.reason.is_some() {
+                return;
+            }
+            eprintln!("🛑 Shutting down: {}", reason);
+            state.reason = Some(reason);
+            state.triggered_at = Some(Instant::now());
+        }
+        self.triggered.cancel();
+    }
+
+    pub async fn triggered(&self) {
+        self.triggered.cancelled().await
+    }
+
+    pub fn reason(&self) -> Option<ShutdownReason> {
+        self.lock().reason.clone()
+    }
+
+    /// Time since shutdown was triggered.
+    pub fn elapsed(&self) -> Duration {
+        self.lock().triggered_at.map(|t| t.elapsed()).unwrap_or_default()
+    }
+
+    pub fn set_child_exit_code(&self, code: Option<i32>) {
+        self.lock().child_exit_code = code;
+    }
+
+    pub fn child_exit_code(&self) -> Option<i32> {
+        self.lock().child_exit_code
+    }
+
+    /// Run every phase in order. Tasks that overrun their phase timeout are aborted.
+    pub async fn run_phases(&self) -> Vec<PhaseReport> {
+        let mut reports = Vec::with_capacity(Phase::ALL.len());
+
+        for phase in Phase::ALL {
+            let started = Instant::now();
+            self.phases[phase.index()].cancel();
+
+            let (tasks, timeout) = {
+                let mut state = self.lock();
+                (std::mem::take(&mut state.tasks[phase.index()]), phase.timeout() + state.extra_time[phase.index()])
+            };
+            let deadline = tokio::time::Instant::now() + timeout;
+            let mut timed_out = false;
+
+            for (name, mut handle) in tasks {
+                match tokio::time::timeout_at(deadline, &mut handle).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => eprintln!("⚠️  {} task failed during {}: {}", name, phase.as_str(), e),
+                    Err(_) => {
+                        eprintln!("⚠️  {} did not finish within {:?} ({}), aborting", name, timeout, phase.as_str());
+                        handle.abort();
+                        timed_out = true;
+                    }
+                }
+            }
+
+            reports.push(PhaseReport {
+                phase,
+                elapsed: started.elapsed(),
+                timed_out,
+            });
+        }
+
+        reports
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn each_reason_has_its_exit_code_and_kind() {
+        let cases = [
+            (ShutdownReason::ChildExited { code: Some(3), signal: None }, Some(3), "child_exited"),
+            (ShutdownReason::ChildExited { code: Some(0), signal: None }, Some(0), "child_exited"),
+            (ShutdownReason::ChildExited { code: None, signal: Some(9) }, Some(137), "child_exited"),
+            (ShutdownReason::ChildExited { code: None, signal: None }, Some(1), "child_exited"),
+            (ShutdownReason::ConnectionClosed, None, "connection_closed"),
+            (ShutdownReason::Signal("SIGINT"), None, "signal"),
+            (ShutdownReason::ProxyError("spawn failed".into()), Some(1), "proxy_error"),
+            (ShutdownReason::AuditFailed("mirror lost".into()), Some(1), "audit_failed"),
+        ];
+        for (reason, code, kind) in cases {
+            assert_eq!(reason.exit_code(), code, "{}", reason);
+            assert_eq!(reason.kind(), kind, "{}", reason);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_child_exit_status_becomes_its_reason() {
+        use std::os::unix::process::ExitStatusExt;
+        let exited = ShutdownReason::child_exited(ExitStatus::from_raw(3 << 8));
+        assert_eq!(exited.to_string(), "child exited with status 3");
+        assert_eq!(exited.exit_code(), Some(3));
+        let killed = ShutdownReason::child_exited(ExitStatus::from_raw(15));
+        assert_eq!(killed.to_string(), "child terminated by signal 15");
+        assert_eq!(killed.exit_code(), Some(128 + 15));
+    }
+
+    #[tokio::test]
+    async fn the_first_trigger_wins() {
+        let shutdown = ShutdownController::new();
+        assert!(shutdown.reason().is_none());
+        shutdown.trigger(ShutdownReason::ChildExited { code: Some(2), signal: None });
+        shutdown.trigger(ShutdownReason::Signal("SIGINT"));
+        shutdown.trigger(ShutdownReason::ProxyError("late".into()));
+        shutdown.triggered().await;
+        assert_eq!(shutdown.reason().map(|r| r.kind()), Some("child_exited"));
+        assert_eq!(shutdown.reason().and_then(|r| r.exit_code()), Some(2));
+    }
+
+    /// Each phase's tasks see its token only once the previous phase's tasks
+    /// have finished.
+    #[tokio::test]
+    async fn phases_run_in_order() {
+        let shutdown = ShutdownController::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        for phase in Phase::ALL.into_iter().rev() {
+            let token = shutdown.token(phase);
+            let seen = seen.clone();
+            shutdown.spawn(phase, phase.as_str(), async move {
+                token.cancelled().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                seen.lock().unwrap().push(phase);
+            });
+        }
+        shutdown.trigger(ShutdownReason::Signal("SIGTERM"));
+
+        let reports = shutdown.run_phases().await;
+        assert_eq!(*seen.lock().unwrap(), Phase::ALL);
+        assert_eq!(reports.iter().map(|r| r.phase).collect::<Vec<_>>(), Phase::ALL);
+        assert!(reports.iter().all(|r| !r.timed_out));
+    }
+
+    #[tokio::test]
+    async fn a_task_that_overruns_its_phase_is_aborted() {
+        let shutdown = ShutdownController::new();
+        let finished = Arc::new(Mutex::new(false));
+        let flag = finished.clone();
+        shutdown.spawn(Phase::StopServer, "stuck", async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            *flag.lock().unwrap() = true;
+        });
+        shutdown.trigger(ShutdownReason::ConnectionClosed);
+
+        let reports = shutdown.run_phases().await;
+        let stop_server = reports.iter().find(|r| r.phase == Phase::StopServer).unwrap();
+        assert!(stop_server.timed_out);
+        assert!(stop_server.elapsed < Duration::from_secs(10));
+        assert!(!*finished.lock().unwrap());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::events::{RawTap, StreamDirection};
+
+use serde_json::{json, Map, Value};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+const DIRECTIONS: [StreamDirection; 4] = [
+    StreamDirection::Inbound,
+    StreamDirection::Outbound,
+    StreamDirection::Sentinel,
+    StreamDirection::Stderr,
+];
+
+/// Method of the notice the tap sequencer puts where dropped taps are missing.
+/// The audit writer records a `GapMarker` for it instead of an event.
+pub const TAP_GAP_METHOD: &str = "sentinel/tap_gap";
+
+/// What a tap does when the audit pipeline behind it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TapOverflow {
+    /// Wait for room: nothing is lost, forwarding stalls until the pipeline catches up
+    #[default]
+    Block,
+    /// Never wait: the message is still forwarded, but its tap is dropped and counted
+    Drop,
+}
+
+impl TapOverflow {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TapOverflow::Block => "block",
+            TapOverflow::Drop => "drop",
+        }
+    }
+}
+
+/// Taps dropped under [`TapOverflow::Drop`], per direction.
+#[derive(Debug, Default)]
+pub struct TapDrops {
+    total: [AtomicU64; 4],
+    /// Dropped since the last tap that got through. Taps are stamped and queued
+    /// under this lock, so the count a tap carries is exactly what is missing
+    /// between it and the one before it in the queue.
+    pending: Mutex<[u64; 4]>,
+}
+
+impl TapDrops {
+    fn index(direction: StreamDirection) -> usize {
+        DIRECTIONS.iter().position(|d| *d == direction).unwrap_or(0)
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, [u64; 4]> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Drops no tap has carried yet: those after the last tap that got through,
+    /// once the pipeline has closed.
+    pub fn take_pending(&self) -> [u64; 4] {
+        std::mem::take(&mut *self.pending())
+    }
+
+    /// Cumulative drops as `{"Inbound": n, ...}`.
+    pub fn totals(&self) -> Value {
+        counts(self.total.each_ref().map(|c| c.load(Ordering::Relaxed)))
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+
+    pub fn render_openmetrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE sentinel_tap_dropped counter");
+        let _ = writeln!(out, "# HELP sentinel_tap_dropped Taps dropped because the audit pipeline was full (--tap-overflow drop)");
+        for (direction, count) in DIRECTIONS.iter().zip(&self.total) {
+            let _ = writeln!(
+                out,
+                "sentinel_tap_dropped_total{{direction=\"{:?}\"}} {}",
+                direction,
+                count.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+/// The notice for taps `first_seq..=last_seq`, dropped (`dropped` per direction)
+/// before they were sequenced.
+pub fn gap_notice(first_seq: u64, last_seq: u64, dropped: [u64; 4]) -> RawTap {
+    RawTap::sentinel(
+        TAP_GAP_METHOD,
+        json!({ "first_seq": first_seq, "last_seq": last_seq, "dropped": counts(dropped) }),
+    )
+}
+
+fn counts(values: [u64; 4]) -> Value {
+    let map: Map<String, Value> = DIRECTIONS
+        .iter()
+        .zip(values)
+        .map(|(d, n)| (format!("{:?}", d), json!(n)))
+        .collect();
+    Value::Object(map)
+}
+
+/// The proxy's end of the tap channel, applying the overflow policy.
+#[derive(Clone)]
+pub struct Tap {
+    tx: mpsc::Sender<RawTap>,
+    overflow: TapOverflow,
+    drops: Arc<TapDrops>,
+    /// Stamped on every tap sent through this handle
+    server: Option<Arc<str>>,
+}
+
+impl Tap {
+    pub fn new(tx: mpsc::Sender<RawTap>, overflow: TapOverflow, drops: Arc<TapDrops>) -> Self {
+        Self { tx, overflow, drops, server: None }
+    }
+
+    /// A handle on the same pipeline that tags its taps with a server name.
+    pub fn for_server(&self, name: &str) -> Self {
+        Self {
+            server: Some(Arc::from(name)),
+            ..self.clone()
+        }
+    }
+
+    /// A handle that does not keep the pipeline open, for long-lived holders
+    /// (the dashboard server) that must not delay shutdown.
+    pub fn downgrade(&self) -> WeakTap {
+        WeakTap {
+            tx: self.tx.downgrade(),
+            overflow: self.overflow,
+            drops: self.drops.clone(),
+        }
+    }
+
+    /// Hand `raw` to the pipeline. Returns false once the pipeline has shut down
+    /// (a dropped tap is not a failure).
+    pub async fn send(&self, mut raw: RawTap) -> bool {
+        if self.server.is_some() {
+            raw.server = self.server.clone();
+        }
+        match self.overflow {
+            TapOverflow::Block => self.tx.send(raw).await.is_ok(),
+            TapOverflow::Drop => {
+                let mut pending = self.drops.pending();
+                raw.dropped_before = *pending;
+                match self.tx.try_send(raw) {
+                    Ok(()) => {
+                        *pending = [0; 4];
+                        true
+                    }
+                    Err(TrySendError::Full(raw)) => {
+                        let i = TapDrops::index(raw.direction);
+                        pending[i] += 1;
+                        self.drops.total[i].fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                    Err(TrySendError::Closed(_)) => false,
+                }
+            }
+        }
+    }
+}
+
+/// See [`Tap::downgrade`].
+#[derive(Clone)]
+pub struct WeakTap {
+    tx: mpsc::WeakSender<RawTap>,
+    overflow: TapOverflow,
+    drops: Arc<TapDrops>,
+}
+
+impl WeakTap {
+    /// `None` once the pipeline has shut down.
+    pub fn upgrade(&self) -> Option<Tap> {
+        let tx = self.tx.upgrade()?;
+        Some(Tap::new(tx, self.overflow, self.drops.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn raw(direction: StreamDirection) -> RawTap {
+        RawTap {
+            direction,
+            bytes: Bytes::from_static(b"{}"),
+            observed_ts_ms: 0,
+            server: None,
+            dropped_before: [0; 4],
+        }
+    }
+
+    #[tokio::test]
+    async fn a_tap_carries_the_drops_since_the_one_before_it() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let drops = Arc::new(TapDrops::default());
+        let tap = Tap::new(tx, TapOverflow::Drop, drops.clone());
+
+        assert!(tap.send(raw(StreamDirection::Outbound)).await);
+        // The queue is full: both are dropped
+        assert!(tap.send(raw(StreamDirection::Outbound)).await);
+        assert!(tap.send(raw(StreamDirection::Inbound)).await);
+        assert_eq!(rx.recv().await.unwrap().dropped_before, [0; 4]);
+
+        assert!(tap.send(raw(StreamDirection::Inbound)).await);
+        assert_eq!(rx.recv().await.unwrap().dropped_before, [1, 1, 0, 0]);
+
+        // Drops after the last tap are left for the sequencer to take at the end.
+        assert!(tap.send(raw(StreamDirection::Stderr)).await);
+        assert!(tap.send(raw(StreamDirection::Stderr)).await);
+        assert_eq!(rx.recv().await.unwrap().dropped_before, [0; 4]);
+        assert_eq!(drops.take_pending(), [0, 0, 0, 1]);
+        assert_eq!(drops.total(), 3);
+    }
+}
@@ -0,0 +1,89 @@
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// An episode ends once messages have flowed without delay for this long, so a
+/// sustained flood is reported once rather than per refill.
+const EPISODE_QUIET: Duration = Duration::from_secs(1);
+
+/// Token bucket pacing one forwarding direction to `rate` bytes per second, with
+/// up to one second of burst. Messages are delayed, never dropped or reordered;
+/// while the proxy waits it stops reading, so the pipe pushes back on the writer.
+pub struct Throttle {
+    rate: u64,
+    tokens: f64,
+    refilled: Instant,
+    episode: Option<Episode>,
+}
+
+/// A run of consecutive delayed messages.
+struct Episode {
+    started: Instant,
+    last_delay_end: Instant,
+    bytes_delayed: u64,
+    messages_delayed: u64,
+    total_delay: Duration,
+}
+
+impl Throttle {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            refilled: Instant::now(),
+            episode: None,
+        }
+    }
+
+    /// Wait until `bytes` may be forwarded. The first undelayed message after
+    /// [`EPISODE_QUIET`] ends the current episode, whose summary is returned.
+    pub async fn pace(&mut self, bytes: usize) -> Option<serde_json::Value> {
+        self.refill();
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            let quiet = self
+                .episode
+                .as_ref()
+                .is_some_and(|e| e.last_delay_end.elapsed() >= EPISODE_QUIET);
+            return if quiet { self.finish() } else { None };
+        }
+
+        // A message larger than the bucket runs it into debt and waits it off.
+        let wait = Duration::from_secs_f64(-self.tokens / self.rate as f64);
+        let started = Instant::now();
+        tokio::time::sleep(wait).await;
+        self.refill();
+
+        let now = Instant::now();
+        let episode = self.episode.get_or_insert(Episode {
+            started,
+            last_delay_end: now,
+            bytes_delayed: 0,
+            messages_delayed: 0,
+            total_delay: Duration::ZERO,
+        });
+        episode.last_delay_end = now;
+        episode.bytes_delayed += bytes as u64;
+        episode.messages_delayed += 1;
+        episode.total_delay += now - started;
+        None
+    }
+
+    /// Close the open episode, if any (also called when the stream ends).
+    pub fn finish(&mut self) -> Option<serde_json::Value> {
+        let episode = self.episode.take()?;
+        Some(json!({
+            "limit_bytes_per_sec": self.rate,
+            "duration_ms": (episode.last_delay_end - episode.started).as_millis() as u64,
+            "delay_ms": episode.total_delay.as_millis() as u64,
+            "bytes_delayed": episode.bytes_delayed,
+            "messages_delayed": episode.messages_delayed,
+        }))
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.refilled = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+    }
+}
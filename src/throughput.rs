@@ -0,0 +1,79 @@
+use crate::events::StreamDirection;
+
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What the proxy read in one direction. A Content-Length frame counts as one
+/// line; `json_lines + non_json_lines + oversized_lines == lines`.
+#[derive(Debug, Default)]
+struct Counters {
+    bytes: AtomicU64,
+    lines: AtomicU64,
+    json_lines: AtomicU64,
+    non_json_lines: AtomicU64,
+    /// Too long to hold, so never checked for JSON
+    oversized_lines: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self) -> Value {
+        json!({
+            "bytes": self.bytes.load(Ordering::Relaxed),
+            "lines": self.lines.load(Ordering::Relaxed),
+            "json_lines": self.json_lines.load(Ordering::Relaxed),
+            "non_json_lines": self.non_json_lines.load(Ordering::Relaxed),
+            "oversized_lines": self.oversized_lines.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// How a line read by the proxy is counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Json,
+    NonJson,
+    Oversized,
+}
+
+/// Bytes and lines through one upstream (one hub server) over the whole run:
+/// client -> server is outbound, server -> client inbound.
+#[derive(Debug, Default)]
+pub struct Throughput {
+    inbound: Counters,
+    outbound: Counters,
+}
+
+impl Throughput {
+    fn counters(&self, direction: StreamDirection) -> &Counters {
+        match direction {
+            StreamDirection::Outbound => &self.outbound,
+            _ => &self.inbound,
+        }
+    }
+
+    /// A line (or the held start of an oversized one) of `bytes` bytes.
+    pub fn record_line(&self, direction: StreamDirection, bytes: usize, kind: LineKind) {
+        let counters = self.counters(direction);
+        counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        counters.lines.fetch_add(1, Ordering::Relaxed);
+        let kind = match kind {
+            LineKind::Json => &counters.json_lines,
+            LineKind::NonJson => &counters.non_json_lines,
+            LineKind::Oversized => &counters.oversized_lines,
+        };
+        kind.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// More of an oversized line.
+    pub fn record_bytes(&self, direction: StreamDirection, bytes: usize) {
+        self.counters(direction).bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// `{"inbound": {...}, "outbound": {...}}`
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "inbound": self.inbound.snapshot(),
+            "outbound": self.outbound.snapshot(),
+        })
+    }
+}
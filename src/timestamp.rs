@@ -0,0 +1,392 @@
+//! RFC 3161 timestamps for checkpoints (`run --timestamp-url`). A checkpoint
+//! signature only proves who wrote the log; a token from a time-stamping
+//! authority proves the checkpoint existed by the token's time, so a log cannot
+//! be back-dated later by whoever holds the signing key.
+
+use crate::audit::{self, AuditRecord};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use cmpv2::status::PkiStatus;
+use cms::cert::CertificateChoices;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use der::asn1::{Int, OctetString};
+use der::oid::db::{rfc5280, rfc5911, rfc5912};
+use der::oid::ObjectIdentifier;
+use der::{Decode, Encode};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use rand::RngCore;
+use rsa::pkcs8::DecodePublicKey;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use x509_cert::Certificate;
+use x509_tsp::{MessageImprint, TimeStampReq, TimeStampResp, TspVersion, TstInfo};
+
+/// id-ct-TSTInfo: content type of a timestamp token's signed content
+const ID_CT_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+
+/// Issuer chains longer than this are not followed
+const MAX_CHAIN: usize = 8;
+
+/// Message imprint submitted for a checkpoint: SHA-256 of its signing preimage.
+pub fn imprint(preimage: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(preimage).into()
+}
+
+/// Requests timestamps from one TSA over HTTP.
+pub struct TsaClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl TsaClient {
+    pub fn new(url: &str) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| format!("http client: {}", e))?;
+        Ok(Self { url: url.to_string(), http })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Timestamp `imprint` (a SHA-256 digest); returns the DER TimeStampToken.
+    pub async fn stamp(&self, imprint: &[u8; 32]) -> Result<Vec<u8>, String> {
+        let mut nonce = [0u8; 8];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        // A positive INTEGER in its shortest encoding
+        nonce[0] = (nonce[0] & 0x7f) | 0x40;
+        let nonce = Int::new(&nonce).map_err(|e| format!("nonce: {}", e))?;
+        let req = TimeStampReq {
+            version: TspVersion::V1,
+            message_imprint: MessageImprint {
+                hash_algorithm: AlgorithmIdentifierOwned {
+                    oid: rfc5912::ID_SHA_256,
+                    parameters: None,
+                },
+                hashed_message: OctetString::new(imprint.to_vec()).map_err(|e| format!("imprint: {}", e))?,
+            },
+            req_policy: None,
+            nonce: Some(nonce.clone()),
+            cert_req: true,
+            extensions: None,
+        };
+        let body = req.to_der().map_err(|e| format!("encode request: {}", e))?;
+
+        let resp = self
+            .http
+            .post(&self.url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("request to {}: {}", self.url, e))?;
+        if !resp.status().is_success() {
+            return Err(format!("{} answered {}", self.url, resp.status()));
+        }
+        let bytes = resp.bytes().await.map_err(|e| format!("read response: {}", e))?;
+
+        let resp = TimeStampResp::from_der(&bytes).map_err(|e| format!("parse response: {}", e))?;
+        if !matches!(resp.status.status, PkiStatus::Accepted | PkiStatus::GrantedWithMods) {
+            return Err(format!("TSA refused the request ({:?})", resp.status.status));
+        }
+        let token = resp
+            .time_stamp_token
+            .ok_or("TSA granted the request without a token")?
+            .to_der()
+            .map_err(|e| format!("encode token: {}", e))?;
+
+        let (_, tst) = parse_token(&token, imprint)?;
+        if tst.nonce != Some(nonce) {
+            return Err("token nonce does not match the request".to_string());
+        }
+        Ok(token)
+    }
+}
+
+/// Decode a token and check that it timestamps `imprint`. Says nothing about
+/// who signed it; see [`verify_token`].
+fn parse_token(token: &[u8], imprint: &[u8; 32]) -> Result<(SignedData, TstInfo), String> {
+    let info = cms::content_info::ContentInfo::from_der(token).map_err(|e| format!("parse token: {}", e))?;
+    if info.content_type != rfc5911::ID_SIGNED_DATA {
+        return Err("token is not CMS SignedData".to_string());
+    }
+    let sd = info
+        .content
+        .decode_as::<SignedData>()
+        .map_err(|e| format!("parse token SignedData: {}", e))?;
+    if sd.encap_content_info.econtent_type != ID_CT_TST_INFO {
+        return Err("token does not contain a TSTInfo".to_string());
+    }
+    let content = sd
+        .encap_content_info
+        .econtent
+        .as_ref()
+        .ok_or("token has no TSTInfo content")?;
+    let tst = TstInfo::from_der(content.value()).map_err(|e| format!("parse TSTInfo: {}", e))?;
+    if tst.message_imprint.hash_algorithm.oid != rfc5912::ID_SHA_256
+        || tst.message_imprint.hashed_message.as_bytes() != imprint
+    {
+        return Err("token does not timestamp this checkpoint".to_string());
+    }
+    Ok((sd, tst))
+}
+
+/// CA certificates trusted to issue TSA certificates (`verify --tsa-ca-bundle`).
+#[derive(Debug)]
+pub struct TrustRoots {
+    certs: Vec<Certificate>,
+}
+
+impl TrustRoots {
+    /// Load a PEM bundle of one or more certificates.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path).map_err(|e| format!("read {}: {}", path.display(), e))?;
+        let certs = Certificate::load_pem_chain(&pem).map_err(|e| format!("parse {}: {}", path.display(), e))?;
+        if certs.is_empty() {
+            return Err(format!("{} holds no certificates", path.display()));
+        }
+        Ok(Self { certs })
+    }
+}
+
+/// Check a token against `imprint` and, with `roots`, its signature and the
+/// signer's certificate chain up to one of them. Returns the token's genTime
+/// (ms since the epoch).
+pub fn verify_token(token: &[u8], imprint: &[u8; 32], roots: Option<&TrustRoots>) -> Result<u64, String> {
+    let (sd, tst) = parse_token(token, imprint)?;
+    let gen_time_ms = tst.gen_time.to_unix_duration().as_millis() as u64;
+    let Some(roots) = roots else {
+        return Ok(gen_time_ms);
+    };
+
+    let embedded: Vec<&Certificate> = sd
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|c| match c {
+            CertificateChoices::Certificate(cert) => Some(cert),
+            _ => None,
+        })
+        .collect();
+
+    let signer = sd.signer_infos.0.iter().next().ok_or("token has no signer")?;
+    let cert = embedded
+        .iter()
+        .copied()
+        .chain(roots.certs.iter())
+        .find(|cert| identifies(&signer.sid, cert))
+        .ok_or("token signer certificate is neither in the token nor in the CA bundle")?;
+
+    let content = sd.encap_content_info.econtent.as_ref().ok_or("token has no TSTInfo content")?;
+    check_signer_info(signer, cert, content.value())?;
+
+    if !has_time_stamping_eku(cert) {
+        return Err("token signer certificate is not for time stamping".to_string());
+    }
+    let validity = &cert.tbs_certificate.validity;
+    let (not_before, not_after) = (
+        validity.not_before.to_unix_duration().as_millis() as u64,
+        validity.not_after.to_unix_duration().as_millis() as u64,
+    );
+    if gen_time_ms < not_before || gen_time_ms > not_after {
+        return Err("token was issued outside its signer certificate's validity".to_string());
+    }
+
+    check_chain(cert, &embedded, roots)?;
+    Ok(gen_time_ms)
+}
+
+fn identifies(sid: &SignerIdentifier, cert: &Certificate) -> bool {
+    match sid {
+        SignerIdentifier::IssuerAndSerialNumber(ias) => {
+            ias.issuer == cert.tbs_certificate.issuer && ias.serial_number == cert.tbs_certificate.serial_number
+        }
+        SignerIdentifier::SubjectKeyIdentifier(ski) => cert
+            .tbs_certificate
+            .extensions
+            .iter()
+            .flatten()
+            .any(|ext| ext.extn_id == rfc5280::ID_CE_SUBJECT_KEY_IDENTIFIER && ski_matches(ext.extn_value.as_bytes(), ski)),
+    }
+}
+
+fn ski_matches(extn_value: &[u8], ski: &x509_cert::ext::pkix::SubjectKeyIdentifier) -> bool {
+    x509_cert::ext::pkix::SubjectKeyIdentifier::from_der(extn_value).is_ok_and(|s| s == *ski)
+}
+
+fn has_time_stamping_eku(cert: &Certificate) -> bool {
+    cert.tbs_certificate.extensions.iter().flatten().any(|ext| {
+        ext.extn_id == rfc5280::ID_CE_EXT_KEY_USAGE
+            && x509_cert::ext::pkix::ExtendedKeyUsage::from_der(ext.extn_value.as_bytes())
+                .is_ok_and(|eku| eku.0.contains(&rfc5280::ID_KP_TIME_STAMPING))
+    })
+}
+
+/// The signed attributes must carry the digest of the TSTInfo, and the
+/// signature must cover them.
+fn check_signer_info(signer: &SignerInfo, cert: &Certificate, tst_der: &[u8]) -> Result<(), String> {
+    let attrs = signer.signed_attrs.as_ref().ok_or("token signer has no signed attributes")?;
+    let digest = hash(&signer.digest_alg.oid, tst_der)?;
+    let message_digest = attrs
+        .iter()
+        .find(|a| a.oid == rfc5911::ID_MESSAGE_DIGEST)
+        .and_then(|a| a.values.iter().next())
+        .and_then(|v| v.decode_as::<OctetString>().ok())
+        .ok_or("token signer has no message digest")?;
+    if message_digest.as_bytes() != digest.as_slice() {
+        return Err("token signature does not cover its TSTInfo".to_string());
+    }
+
+    let signed = attrs.to_der().map_err(|e| format!("encode signed attributes: {}", e))?;
+    let prehash = hash(&signer.digest_alg.oid, &signed)?;
+    verify_signature(cert, &signer.signature_algorithm.oid, &prehash, signer.signature.as_bytes())
+        .map_err(|e| format!("token signature: {}", e))
+}
+
+/// Follow issuers from `leaf` until a certificate of the bundle signs one.
+fn check_chain(leaf: &Certificate, embedded: &[&Certificate], roots: &TrustRoots) -> Result<(), String> {
+    let mut cert = leaf;
+    for _ in 0..MAX_CHAIN {
+        if roots.certs.iter().any(|root| root == cert) {
+            return Ok(());
+        }
+        let issuer_name = &cert.tbs_certificate.issuer;
+        let tbs = cert.tbs_certificate.to_der().map_err(|e| format!("encode certificate: {}", e))?;
+        let sig_alg = &cert.signature_algorithm.oid;
+        let sig = cert.signature.raw_bytes();
+
+        let signed_by = |issuer: &&Certificate| {
+            issuer.tbs_certificate.subject == *issuer_name
+                && digest_for(sig_alg)
+                    .and_then(|d| hash(&d, &tbs))
+                    .and_then(|h| verify_signature(issuer, sig_alg, &h, sig))
+                    .is_ok()
+        };
+        if roots.certs.iter().find(signed_by).is_some() {
+            return Ok(());
+        }
+        cert = embedded
+            .iter()
+            .copied()
+            .filter(|c| *c != cert)
+            .find(signed_by)
+            .ok_or("token signer certificate does not chain to the CA bundle")?;
+    }
+    Err("token certificate chain is too long".to_string())
+}
+
+fn digest_for(sig_alg: &ObjectIdentifier) -> Result<ObjectIdentifier, String> {
+    match *sig_alg {
+        rfc5912::SHA_256_WITH_RSA_ENCRYPTION | rfc5912::ECDSA_WITH_SHA_256 => Ok(rfc5912::ID_SHA_256),
+        rfc5912::SHA_384_WITH_RSA_ENCRYPTION | rfc5912::ECDSA_WITH_SHA_384 => Ok(rfc5912::ID_SHA_384),
+        rfc5912::SHA_512_WITH_RSA_ENCRYPTION => Ok(rfc5912::ID_SHA_512),
+        other => Err(format!("unsupported signature algorithm {}", other)),
+    }
+}
+
+fn hash(alg: &ObjectIdentifier, data: &[u8]) -> Result<Vec<u8>, String> {
+    match *alg {
+        rfc5912::ID_SHA_256 => Ok(Sha256::digest(data).to_vec()),
+        rfc5912::ID_SHA_384 => Ok(Sha384::digest(data).to_vec()),
+        rfc5912::ID_SHA_512 => Ok(Sha512::digest(data).to_vec()),
+        other => Err(format!("unsupported digest algorithm {}", other)),
+    }
+}
+
+/// Verify `sig` over an already hashed message with the key of `cert`.
+fn verify_signature(cert: &Certificate, sig_alg: &ObjectIdentifier, prehash: &[u8], sig: &[u8]) -> Result<(), String> {
+    let spki = cert
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| format!("encode public key: {}", e))?;
+    match *sig_alg {
+        rfc5912::RSA_ENCRYPTION
+        | rfc5912::SHA_256_WITH_RSA_ENCRYPTION
+        | rfc5912::SHA_384_WITH_RSA_ENCRYPTION
+        | rfc5912::SHA_512_WITH_RSA_ENCRYPTION => {
+            let key = rsa::RsaPublicKey::from_public_key_der(&spki).map_err(|e| format!("RSA key: {}", e))?;
+            let scheme = match prehash.len() {
+                32 => rsa::Pkcs1v15Sign::new::<Sha256>(),
+                48 => rsa::Pkcs1v15Sign::new::<Sha384>(),
+                _ => rsa::Pkcs1v15Sign::new::<Sha512>(),
+            };
+            key.verify(scheme, prehash, sig).map_err(|_| "invalid RSA signature".to_string())
+        }
+        rfc5912::ECDSA_WITH_SHA_256 | rfc5912::ECDSA_WITH_SHA_384 => {
+            let curve = cert
+                .tbs_certificate
+                .subject_public_key_info
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|p| p.decode_as::<ObjectIdentifier>().ok())
+                .ok_or("EC key without a named curve")?;
+            let ok = match curve {
+                rfc5912::SECP_256_R_1 => {
+                    let key = p256::ecdsa::VerifyingKey::from_public_key_der(&spki).map_err(|e| format!("P-256 key: {}", e))?;
+                    let sig = p256::ecdsa::Signature::from_der(sig).map_err(|e| format!("P-256 signature: {}", e))?;
+                    key.verify_prehash(prehash, &sig).is_ok()
+                }
+                rfc5912::SECP_384_R_1 => {
+                    let key = p384::ecdsa::VerifyingKey::from_public_key_der(&spki).map_err(|e| format!("P-384 key: {}", e))?;
+                    let sig = p384::ecdsa::Signature::from_der(sig).map_err(|e| format!("P-384 signature: {}", e))?;
+                    key.verify_prehash(prehash, &sig).is_ok()
+                }
+                other => return Err(format!("unsupported curve {}", other)),
+            };
+            ok.then_some(()).ok_or_else(|| "invalid ECDSA signature".to_string())
+        }
+        other => Err(format!("unsupported signature algorithm {}", other)),
+    }
+}
+
+/// Timestamps checkpoints in the background for the audit writer. Each
+/// `TimestampProof` comes back on `proofs` once its token arrives; a failed
+/// request is reported and dropped.
+pub struct Stamper {
+    client: Arc<TsaClient>,
+    proofs: mpsc::UnboundedSender<AuditRecord>,
+}
+
+impl Stamper {
+    pub fn new(url: &str, proofs: mpsc::UnboundedSender<AuditRecord>) -> Result<Self, String> {
+        Ok(Self { client: Arc::new(TsaClient::new(url)?), proofs })
+    }
+
+    /// Ask for a timestamp on `checkpoint` without waiting for it.
+    pub fn request(&self, checkpoint: &AuditRecord) {
+        let Some((run_id, last_event_id, preimage)) = audit::checkpoint_signing_preimage(checkpoint) else {
+            return;
+        };
+        let imprint = imprint(&preimage);
+        let client = self.client.clone();
+        let proofs = self.proofs.clone();
+        tokio::spawn(async move {
+            match client.stamp(&imprint).await {
+                Ok(token) => {
+                    let gen_time_ms = verify_token(&token, &imprint, None).unwrap_or_default();
+                    let _ = proofs.send(AuditRecord::TimestampProof {
+                        run_id,
+                        checkpoint_last_event_id: last_event_id,
+                        imprint_b64: B64.encode(imprint),
+                        tsa_url: client.url().to_string(),
+                        token_b64: B64.encode(token),
+                        gen_time_ms,
+                        version: 1,
+                    });
+                }
+                Err(e) => eprintln!(
+                    "⚠️  Timestamp for the checkpoint at event_id {} failed: {} (the log stays valid without it)",
+                    last_event_id, e
+                ),
+            }
+        });
+    }
+}
@@ -0,0 +1,79 @@
+//! `sentinel unredact`: write a copy of an audit log with the values that vault
+//! redaction rules encrypted restored. The original is only read; the copy no
+//! longer verifies, since the chain hashed the vaulted form.
+
+use crate::audit;
+use crate::audit_crypto;
+use crate::keys;
+use crate::redaction;
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+pub struct UnredactOptions {
+    pub log: String,
+    pub out: PathBuf,
+    /// Recipient private key of the run; also decrypts an envelope-encrypted log
+    pub recipient_privkey_path: String,
+}
+
+pub struct UnredactOutcome {
+    pub records: u64,
+    /// Vaulted values restored
+    pub restored: u64,
+}
+
+pub fn unredact(opts: &UnredactOptions) -> Result<UnredactOutcome, String> {
+    if opts.out.exists() {
+        return Err(format!("{} already exists", opts.out.display()));
+    }
+    let sk = keys::load_key32(&opts.recipient_privkey_path, "recipient private key")?;
+    let log = audit_crypto::open_plaintext_with_secret(&opts.log, &sk)?;
+
+    let mut file = std::fs::OpenOptions::new();
+    file.write(true).create_new(true);
+    // The copy holds what redaction kept out of the log
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut file, 0o600);
+    let out = file.open(&opts.out).map_err(|e| format!("create {}: {}", opts.out.display(), e))?;
+    let outcome = restore(opts, log.reader, out, &sk);
+    if outcome.is_err() {
+        // A partial copy would pass for the whole log
+        let _ = std::fs::remove_file(&opts.out);
+    }
+    outcome
+}
+
+fn restore(
+    opts: &UnredactOptions,
+    log: impl std::io::Read,
+    out: std::fs::File,
+    sk: &[u8; 32],
+) -> Result<UnredactOutcome, String> {
+    let mut out = std::io::BufWriter::new(out);
+    let mut records = 0u64;
+    let mut restored = 0u64;
+    for (i, line) in BufReader::new(log).lines().enumerate() {
+        let line = line.map_err(|e| format!("read {}: {}", opts.log, e))?;
+        let record = audit::normalize_line(&line);
+        let rewritten = match record.contains("sentinel_vault") {
+            true => {
+                let mut value: serde_json::Value =
+                    serde_json::from_str(record).map_err(|e| format!("line {}: JSON parse error: {}", i + 1, e))?;
+                match redaction::unvault(&mut value, sk).map_err(|e| format!("line {}: {}", i + 1, e))? {
+                    0 => None,
+                    n => {
+                        restored += n;
+                        Some(value.to_string())
+                    }
+                }
+            }
+            false => None,
+        };
+        writeln!(out, "{}", rewritten.as_deref().unwrap_or(record))
+            .map_err(|e| format!("write {}: {}", opts.out.display(), e))?;
+        records += 1;
+    }
+    out.flush().map_err(|e| format!("write {}: {}", opts.out.display(), e))?;
+    Ok(UnredactOutcome { records, restored })
+}
@@ -0,0 +1,66 @@
+use serde_json::Value;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Check a webhook URL up front so a typo fails at startup, not at alert time.
+pub fn validate_url(url: &str) -> Result<(), String> {
+    parse_http_url(url).map(|_| ())
+}
+
+/// POST `body` as JSON to a plain `http://` webhook. Succeeds on any 2xx reply.
+pub async fn post_json(url: &str, body: &Value) -> Result<(), String> {
+    let (host, port, path) = parse_http_url(url)?;
+    let payload = serde_json::to_vec(body).map_err(|e| format!("serialize webhook body: {}", e))?;
+
+    let exchange = async {
+        let mut stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(|e| format!("connect {}:{}: {}", host, port, e))?;
+
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            path,
+            host,
+            payload.len()
+        );
+        stream.write_all(head.as_bytes()).await.map_err(|e| format!("write: {}", e))?;
+        stream.write_all(&payload).await.map_err(|e| format!("write: {}", e))?;
+
+        let mut status_line = [0u8; 12];
+        stream
+            .read_exact(&mut status_line)
+            .await
+            .map_err(|e| format!("read reply: {}", e))?;
+        match &status_line[..] {
+            [b'H', b'T', b'T', b'P', b'/', _, _, _, b' ', b'2', _, _] => Ok(()),
+            other => Err(format!("webhook replied {}", String::from_utf8_lossy(other).trim())),
+        }
+    };
+
+    tokio::time::timeout(WEBHOOK_TIMEOUT, exchange)
+        .await
+        .map_err(|_| "webhook timed out".to_string())?
+}
+
+fn parse_http_url(url: &str) -> Result<(String, u16, String), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("unsupported webhook URL {:?} (only http:// is supported)", url))?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse::<u16>().map_err(|_| format!("bad port in webhook URL {:?}", url))?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in webhook URL {:?}", url));
+    }
+
+    Ok((host.to_string(), port, path.to_string()))
+}
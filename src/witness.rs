@@ -0,0 +1,240 @@
+//! Remote witness for checkpoints (`run --witness-url`). Each checkpoint's chain
+//! tip is pushed to an append-only service we do not write ourselves, so a log
+//! replaced wholesale (re-chained and re-signed with the same key) no longer
+//! matches what the witness acknowledged.
+//!
+//! The witness speaks JSON over HTTP(S):
+//! - `POST <url>` with a [`WitnessedCheckpoint`] (no `receipt_id`); a 2xx reply
+//!   carries `{"receipt_id": "..."}`
+//! - `GET <url>?run_id=<run_id>` returns `{"checkpoints": [...]}`, every
+//!   checkpoint acknowledged for that run
+
+use crate::audit::{self, AuditRecord};
+use crate::events::current_timestamp_ms;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Checkpoints waiting to be sent; when full, new ones are dropped with a warning
+const WITNESS_QUEUE: usize = 64;
+/// Attempts per checkpoint before giving up on it
+const WITNESS_ATTEMPTS: u32 = 5;
+const WITNESS_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A checkpoint as the witness stores it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WitnessedCheckpoint {
+    pub run_id: String,
+    pub last_event_id: u64,
+    pub last_entry_hash_b64: String,
+    pub signature_b64: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt_id: Option<String>,
+}
+
+impl WitnessedCheckpoint {
+    fn from_record(rec: &AuditRecord) -> Option<Self> {
+        match rec {
+            AuditRecord::Checkpoint {
+                run_id,
+                last_event_id,
+                last_entry_hash_b64,
+                signature_b64,
+                ..
+            } => Some(Self {
+                run_id: run_id.clone(),
+                last_event_id: *last_event_id,
+                last_entry_hash_b64: last_entry_hash_b64.clone(),
+                signature_b64: signature_b64.clone(),
+                receipt_id: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Receipt {
+    receipt_id: String,
+}
+
+#[derive(Deserialize)]
+struct Acknowledged {
+    checkpoints: Vec<WitnessedCheckpoint>,
+}
+
+pub struct WitnessClient {
+    url: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl WitnessClient {
+    pub fn new(url: &str, token: Option<String>) -> Result<Self, String> {
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("witness URL {:?}: {}", url, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(format!("witness URL {:?} must be http:// or https://", url));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| format!("http client: {}", e))?;
+        Ok(Self { url: url.to_string(), token, http })
+    }
+
+    fn request(&self, method: reqwest::Method) -> reqwest::RequestBuilder {
+        let req = self.http.request(method, &self.url);
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    /// Send one checkpoint; returns the witness's receipt id.
+    async fn submit(&self, checkpoint: &WitnessedCheckpoint) -> Result<String, String> {
+        let resp = self
+            .request(reqwest::Method::POST)
+            .json(checkpoint)
+            .send()
+            .await
+            .map_err(|e| format!("request to {}: {}", self.url, e))?;
+        if !resp.status().is_success() {
+            return Err(format!("{} answered {}", self.url, resp.status()));
+        }
+        let receipt: Receipt = resp.json().await.map_err(|e| format!("parse receipt: {}", e))?;
+        Ok(receipt.receipt_id)
+    }
+
+    /// Every checkpoint the witness acknowledged for `run_id`.
+    pub async fn acknowledged(&self, run_id: &str) -> Result<Vec<WitnessedCheckpoint>, String> {
+        let resp = self
+            .request(reqwest::Method::GET)
+            .query(&[("run_id", run_id)])
+            .send()
+            .await
+            .map_err(|e| format!("request to {}: {}", self.url, e))?;
+        if !resp.status().is_success() {
+            return Err(format!("{} answered {}", self.url, resp.status()));
+        }
+        let ack: Acknowledged = resp.json().await.map_err(|e| format!("parse witness reply: {}", e))?;
+        Ok(ack.checkpoints)
+    }
+}
+
+/// Sends checkpoints to the witness from a background task, in order, retrying
+/// each a few times. A `WitnessAck` comes back on `acks` for every receipt.
+pub struct Witness {
+    queue: mpsc::Sender<WitnessedCheckpoint>,
+}
+
+impl Witness {
+    pub fn spawn(client: WitnessClient, acks: mpsc::UnboundedSender<AuditRecord>) -> Self {
+        let (queue, mut rx) = mpsc::channel::<WitnessedCheckpoint>(WITNESS_QUEUE);
+        tokio::spawn(async move {
+            while let Some(cp) = rx.recv().await {
+                let mut backoff = WITNESS_BACKOFF;
+                for attempt in 1..=WITNESS_ATTEMPTS {
+                    match client.submit(&cp).await {
+                        Ok(receipt_id) => {
+                            let _ = acks.send(AuditRecord::WitnessAck {
+                                run_id: cp.run_id.clone(),
+                                checkpoint_last_event_id: cp.last_event_id,
+                                last_entry_hash_b64: cp.last_entry_hash_b64.clone(),
+                                witness_url: client.url.clone(),
+                                receipt_id,
+                                acked_ts_ms: current_timestamp_ms(),
+                                version: 1,
+                            });
+                            break;
+                        }
+                        Err(e) if attempt == WITNESS_ATTEMPTS => eprintln!(
+                            "⚠️  Witness did not acknowledge the checkpoint at event_id {}: {}",
+                            cp.last_event_id, e
+                        ),
+                        Err(_) => {
+                            tokio::time::sleep(backoff).await;
+                            backoff *= 2;
+                        }
+                    }
+                }
+            }
+        });
+        Self { queue }
+    }
+
+    /// Queue `checkpoint` for the witness without waiting.
+    pub fn submit(&self, checkpoint: &AuditRecord) {
+        let Some(cp) = WitnessedCheckpoint::from_record(checkpoint) else {
+            return;
+        };
+        let event_id = cp.last_event_id;
+        if self.queue.try_send(cp).is_err() {
+            eprintln!(
+                "⚠️  Witness queue full; the checkpoint at event_id {} is not sent",
+                event_id
+            );
+        }
+    }
+}
+
+/// The checkpoints of a log's plaintext records, in order.
+pub fn log_checkpoints(log: impl Read) -> Result<Vec<WitnessedCheckpoint>, String> {
+    let mut out = Vec::new();
+    for (i, line) in BufReader::new(log).lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: read error: {}", i + 1, e))?;
+        let line = audit::normalize_line(&line);
+        if line.is_empty() || (i == 0 && audit::parse_file_header(line)?.is_some()) {
+            continue;
+        }
+        let rec: AuditRecord =
+            serde_json::from_str(line).map_err(|e| format!("line {}: JSON parse error: {}", i + 1, e))?;
+        out.extend(WitnessedCheckpoint::from_record(&rec));
+    }
+    Ok(out)
+}
+
+/// Check `checkpoints` (a whole log) against what the witness acknowledged for
+/// their runs: each must have been acknowledged with the same chain tip, and the
+/// witness must hold none the log lacks. Returns the number checked.
+pub async fn cross_check(client: &WitnessClient, checkpoints: &[WitnessedCheckpoint]) -> Result<usize, String> {
+    let mut runs: BTreeMap<&str, Vec<&WitnessedCheckpoint>> = BTreeMap::new();
+    for cp in checkpoints {
+        runs.entry(cp.run_id.as_str()).or_default().push(cp);
+    }
+
+    for (run_id, local) in runs {
+        let remote: BTreeMap<u64, WitnessedCheckpoint> = client
+            .acknowledged(run_id)
+            .await?
+            .into_iter()
+            .map(|cp| (cp.last_event_id, cp))
+            .collect();
+
+        for cp in &local {
+            match remote.get(&cp.last_event_id) {
+                None => {
+                    return Err(format!(
+                        "run {}: the checkpoint at event_id {} was never acknowledged by the witness",
+                        run_id, cp.last_event_id
+                    ))
+                }
+                Some(w) if w.last_entry_hash_b64 != cp.last_entry_hash_b64 => {
+                    return Err(format!(
+                        "run {}: the checkpoint at event_id {} differs from the one the witness acknowledged (log replaced?)",
+                        run_id, cp.last_event_id
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(extra) = remote.keys().find(|id| !local.iter().any(|cp| cp.last_event_id == **id)) {
+            return Err(format!(
+                "run {}: the witness acknowledged a checkpoint at event_id {} that the log does not contain (log truncated or replaced?)",
+                run_id, extra
+            ));
+        }
+    }
+    Ok(checkpoints.len())
+}
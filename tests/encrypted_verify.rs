@@ -0,0 +1,99 @@
+//! `sentinel verify` of an envelope-encrypted log decrypts it as it reads and
+//! leaves no plaintext behind, next to the log or in the temp directory.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Text only the plaintext of the log holds
+const MARKER: &str = "plaintext-marker-5b1e";
+
+/// The files under `dir` and their contents.
+fn files(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(self::files(&path));
+        } else {
+            let contents = std::fs::read(&path).unwrap();
+            files.push((path, contents));
+        }
+    }
+    files.sort();
+    files
+}
+
+fn sentinel(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sentinel"));
+    command.args(args).current_dir(dir).stdin(Stdio::null());
+    command
+}
+
+#[test]
+fn verifying_an_encrypted_log_leaves_no_plaintext_behind() {
+    let dir = tempfile::tempdir().unwrap();
+    for keygen in ["keygen", "recipient-keygen"] {
+        let status = sentinel(dir.path(), &[keygen, "--out-dir", "keys"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    let request = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{{"name":"{}"}}}}"#, MARKER);
+    let status = sentinel(
+        dir.path(),
+        &[
+            "run",
+            "--audit-log",
+            "audit.jsonl",
+            "--signing-key-b64-path",
+            "keys/sentinel_seed.b64",
+            "--encrypt-recipient-pubkey-b64-path",
+            "keys/recipient_pub.b64",
+            "--ws-bind",
+            "127.0.0.1:0",
+            "--",
+            "sh",
+            "-c",
+            &format!("printf '%s\\n' '{}'", request),
+        ],
+    )
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status()
+    .unwrap();
+    assert!(status.success());
+    let log = std::fs::read(dir.path().join("audit.jsonl")).unwrap();
+    assert!(!String::from_utf8_lossy(&log).contains(MARKER), "the log is not encrypted");
+    let decrypted = sentinel(
+        dir.path(),
+        &["decrypt", "--log", "audit.jsonl", "--recipient-privkey-b64-path", "keys/recipient_priv.b64"],
+    )
+    .output()
+    .unwrap();
+    assert!(String::from_utf8_lossy(&decrypted.stdout).contains(MARKER), "the request was not logged");
+    let before = files(dir.path());
+
+    let tmp = tempfile::tempdir().unwrap();
+    let output = sentinel(
+        dir.path(),
+        &[
+            "verify",
+            "--log",
+            "audit.jsonl",
+            "--pubkey-b64-path",
+            "keys/sentinel_pub.b64",
+            "--decrypt-recipient-privkey-b64-path",
+            "keys/recipient_priv.b64",
+        ],
+    )
+    .env("TMPDIR", tmp.path())
+    .output()
+    .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(files(tmp.path()), []);
+    let after = files(dir.path());
+    assert!(after == before, "verify changed the log's directory: {:?}", after.iter().map(|(path, _)| path).collect::<Vec<_>>());
+}
@@ -0,0 +1,9 @@
+#!/bin/sh
+# A `cmd:` key provider for the tests in src/keys.rs: prints the base64 seed
+# [7; 32], or misbehaves the way a broken provider would.
+case "$1" in
+    key) echo "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=" ;;
+    short) echo "c2VjcmV0" ;;
+    garbage) echo "s3cr3t!not-base64" ;;
+    *) echo "provider.sh: access denied" >&2; exit 3 ;;
+esac
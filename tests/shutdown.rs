@@ -0,0 +1,246 @@
+//! Runs of the `sentinel` binary, stopped each way a run can stop, and what
+//! they leave behind: the exit code and the end of the audit log.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+struct Run {
+    dir: tempfile::TempDir,
+}
+
+impl Run {
+    /// A directory holding a fresh signing keypair.
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let status = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+            .args(["keygen", "--out-dir"])
+            .arg(dir.path().join("keys"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn log(&self) -> PathBuf {
+        self.path("audit.jsonl")
+    }
+
+    /// `sentinel run` with `args` before the `--`, its client's stdin closed.
+    fn spawn(&self, args: &[&str], command: &[&str]) -> Child {
+        Command::new(env!("CARGO_BIN_EXE_sentinel"))
+            .arg("run")
+            .arg("--audit-log")
+            .arg(self.log())
+            .arg("--signing-key-b64-path")
+            .arg(self.path("keys/sentinel_seed.b64"))
+            .args(["--ws-bind", "127.0.0.1:0"])
+            .args(args)
+            .arg("--")
+            .args(command)
+            .current_dir(self.dir.path())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    fn run(&self, args: &[&str], command: &[&str]) -> ExitStatus {
+        wait(self.spawn(args, command))
+    }
+
+    fn records(&self) -> Vec<Value> {
+        std::fs::read_to_string(self.log())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// `sentinel verify` accepts the log.
+    fn verifies(&self) {
+        let output = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+            .arg("verify")
+            .arg("--log")
+            .arg(self.log())
+            .arg("--pubkey-b64-path")
+            .arg(self.path("keys/sentinel_pub.b64"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    /// The payload of the run's `sentinel/shutdown` event.
+    fn shutdown(&self) -> Value {
+        self.records()
+            .into_iter()
+            .find(|r| r["log"]["method"] == "sentinel/shutdown")
+            .map(|r| r["log"]["payload"].clone())
+            .expect("a shutdown event")
+    }
+}
+
+fn wait(mut child: Child) -> ExitStatus {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            panic!("sentinel did not exit");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Wait for the child to create `path`, i.e. for the run to be under way.
+fn wait_for(path: &Path) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "{} never appeared", path.display());
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    // Sentinel's signal handlers are set up right after the child is spawned.
+    std::thread::sleep(Duration::from_millis(200));
+}
+
+#[test]
+fn a_child_exit_is_the_exit_code() {
+    let run = Run::new();
+    let status = run.run(&[], &["sh", "-c", "exit 3"]);
+    assert_eq!(status.code(), Some(3));
+    let shutdown = run.shutdown();
+    assert_eq!(shutdown["reason"], "child_exited");
+    assert_eq!(shutdown["child_exit_code"], 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_child_killed_by_a_signal_exits_with_128_plus_its_number() {
+    let run = Run::new();
+    let status = run.run(&[], &["sh", "-c", "kill -9 $$"]);
+    assert_eq!(status.code(), Some(128 + 9));
+    let shutdown = run.shutdown();
+    assert_eq!(shutdown["reason"], "child_exited");
+    assert_eq!(shutdown["detail"], "child terminated by signal 9");
+}
+
+#[cfg(unix)]
+#[test]
+fn sigint_stops_the_run() {
+    let run = Run::new();
+    let ready = run.path("ready");
+    let script = format!("touch {}; exec sleep 30", ready.display());
+    let child = run.spawn(&[], &["sh", "-c", &script]);
+    wait_for(&ready);
+    // SAFETY: kill(2) has no memory-safety preconditions; the pid is our child's.
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    let status = wait(child);
+    // The child was asked to stop and died of it: no code of its own to pass on.
+    assert_eq!(status.code(), Some(0));
+    let shutdown = run.shutdown();
+    assert_eq!(shutdown["reason"], "signal");
+    assert_eq!(shutdown["detail"], "received SIGINT");
+}
+
+#[test]
+fn a_closed_connection_ends_the_run() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // Hangs up once Sentinel has closed its side, after the client's input ended.
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        std::io::copy(&mut stream, &mut std::io::sink()).unwrap();
+    });
+
+    let run = Run::new();
+    let connect = format!("tcp://{}", address);
+    let status = run.run(&["--connect", &connect], &[]);
+    server.join().unwrap();
+    assert_eq!(status.code(), Some(0));
+    assert_eq!(run.shutdown()["reason"], "connection_closed");
+}
+
+#[test]
+fn a_proxy_error_exits_1() {
+    let run = Run::new();
+    let status = run.run(&[], &["/nonexistent/mcp-server"]);
+    assert_eq!(status.code(), Some(1));
+    let shutdown = run.shutdown();
+    assert_eq!(shutdown["reason"], "proxy_error");
+    assert!(shutdown["child_exit_code"].is_null());
+}
+
+/// Stopped with SIGTERM, Sentinel passes it on to the child, which says
+/// goodbye before exiting. That goodbye is audited, and the chain still ends
+/// on Sentinel's own shutdown event and a final checkpoint covering it.
+#[cfg(unix)]
+#[test]
+fn sigterm_is_passed_on_and_the_log_still_ends_on_the_shutdown_event() {
+    let run = Run::new();
+    let ready = run.path("ready");
+    let script = run.path("server.sh");
+    std::fs::write(
+        &script,
+        format!(
+            r#"trap 'echo "{{\"jsonrpc\":\"2.0\",\"method\":\"notifications/message\",\"params\":{{\"data\":\"farewell\"}}}}"; exit 0' TERM
+touch {}
+while :; do sleep 0.05; done
+"#,
+            ready.display()
+        ),
+    )
+    .unwrap();
+    let child = run.spawn(&[], &["sh", script.to_str().unwrap()]);
+    wait_for(&ready);
+    // SAFETY: kill(2) has no memory-safety preconditions; the pid is our child's.
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    assert_eq!(wait(child).code(), Some(0));
+
+    let records = run.records();
+    let events: Vec<&Value> = records.iter().filter(|r| r["record_type"] == "Event").collect();
+    assert!(
+        events.iter().any(|e| e["log"]["payload"]["params"]["data"] == "farewell"),
+        "the child's farewell was not audited"
+    );
+    let last = events.last().unwrap();
+    assert_eq!(last["log"]["method"], "sentinel/shutdown");
+    assert_eq!(last["log"]["payload"]["detail"], "received SIGTERM");
+    let checkpoint = records.last().unwrap();
+    assert_eq!(checkpoint["record_type"], "Checkpoint");
+    assert_eq!(checkpoint["last_event_id"], last["log"]["event_id"]);
+    run.verifies();
+}
+
+/// A child that exits before any traffic: Sentinel exits with its code, and
+/// only after closing the log with a signed final checkpoint.
+#[test]
+fn a_run_without_traffic_exits_with_the_childs_code_after_the_final_checkpoint() {
+    let run = Run::new();
+    let status = run.run(&[], &["sh", "-c", "exit 5"]);
+    assert_eq!(status.code(), Some(5));
+
+    let records = run.records();
+    assert!(records
+        .iter()
+        .filter(|r| r["record_type"] == "Event")
+        .all(|r| r["log"]["direction"] == "Sentinel"));
+    let last_event = records.iter().rev().find(|r| r["record_type"] == "Event").unwrap();
+    let checkpoint = records.last().unwrap();
+    assert_eq!(checkpoint["record_type"], "Checkpoint");
+    assert_eq!(checkpoint["last_event_id"], last_event["log"]["event_id"]);
+    run.verifies();
+}
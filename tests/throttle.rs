@@ -0,0 +1,119 @@
+//! `sentinel run --max-inbound-bytes-per-sec` against a child flooding its
+//! stdout: what reaches the client, how fast, and the episodes the audit log
+//! records.
+
+use serde_json::Value;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const RATE: u64 = 20_000;
+const LINE: usize = 200;
+/// Lines per flood: twice the bucket, so about half of each flood waits
+const LINES: usize = 200;
+
+/// A notification exactly `LINE` bytes long, newline included.
+fn line(n: usize) -> String {
+    let head = format!(r#"{{"jsonrpc":"2.0","method":"notifications/message","params":{{"n":{},"data":""#, n);
+    let tail = "\"}}\n";
+    format!("{}{}{}", head, "x".repeat(LINE - head.len() - tail.len()), tail)
+}
+
+#[test]
+fn a_flood_is_paced_to_the_cap_and_each_episode_is_logged() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| -> PathBuf { dir.path().join(name) };
+    let status = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+        .args(["keygen", "--out-dir"])
+        .arg(path("keys"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let flood: String = (0..LINES).map(line).collect();
+    std::fs::write(path("flood.jsonl"), &flood).unwrap();
+
+    // Two floods, far enough apart for the first episode to end: the sleep
+    // starts as soon as the first flood is in the pipe, a second before it
+    // has all been forwarded.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+        .arg("run")
+        .arg("--audit-log")
+        .arg(path("audit.jsonl"))
+        .arg("--signing-key-b64-path")
+        .arg(path("keys/sentinel_seed.b64"))
+        .args(["--ws-bind", "127.0.0.1:0"])
+        .args(["--max-inbound-bytes-per-sec", &RATE.to_string()])
+        .args(["--", "sh", "-c", "cat flood.jsonl; sleep 3; cat flood.jsonl"])
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // When each byte reached the client, from the first one on.
+    let mut stdout = child.stdout.take().unwrap();
+    let mut received = Vec::new();
+    let mut arrivals = Vec::new();
+    let mut first = None;
+    let mut buf = [0; 4096];
+    loop {
+        let n = stdout.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        let start = *first.get_or_insert_with(Instant::now);
+        received.extend_from_slice(&buf[..n]);
+        arrivals.push((received.len(), start.elapsed()));
+    }
+    let status = child.wait().unwrap();
+    assert!(status.success());
+
+    // Delayed, never dropped or reordered.
+    assert!(received == [flood.as_bytes(), flood.as_bytes()].concat(), "the forwarded bytes differ from the child's");
+
+    // A full bucket's burst, then no faster than the cap (a line of slack for
+    // the message that runs the bucket into debt).
+    for (bytes, elapsed) in &arrivals {
+        let allowed = RATE as f64 * (1.0 + elapsed.as_secs_f64()) + LINE as f64;
+        assert!(*bytes as f64 <= allowed, "{} bytes forwarded after {:?}", bytes, elapsed);
+    }
+    let paced = Duration::from_secs_f64((flood.len() as u64 - RATE) as f64 / RATE as f64);
+    let (_, first_flood) = arrivals.iter().find(|(bytes, _)| *bytes >= flood.len()).unwrap();
+    assert!(*first_flood >= paced * 9 / 10, "a flood forwarded in {:?}", first_flood);
+
+    let episodes: Vec<Value> = std::fs::read_to_string(path("audit.jsonl"))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .filter(|r| r["log"]["method"] == "sentinel/throttled")
+        .map(|r| r["log"]["payload"].clone())
+        .collect();
+    assert_eq!(episodes.len(), 2, "{:?}", episodes);
+    for episode in &episodes {
+        assert_eq!(episode["direction"], "Inbound");
+        assert_eq!(episode["limit_bytes_per_sec"], RATE);
+        // The bucket refills a little while the burst drains, so somewhat
+        // less than the flood beyond the bucket waits.
+        let bytes_delayed = episode["bytes_delayed"].as_u64().unwrap();
+        assert!(bytes_delayed >= flood.len() as u64 / 4 && bytes_delayed <= flood.len() as u64, "{}", episode);
+        assert_eq!(episode["messages_delayed"].as_u64().unwrap(), bytes_delayed / LINE as u64, "{}", episode);
+        // Waited off at the cap, one message after another
+        let delay_ms = episode["delay_ms"].as_u64().unwrap();
+        assert!(delay_ms >= bytes_delayed * 1000 / RATE * 8 / 10, "{}", episode);
+        assert!(episode["duration_ms"].as_u64().unwrap() >= delay_ms, "{}", episode);
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+        .arg("verify")
+        .arg("--log")
+        .arg(path("audit.jsonl"))
+        .arg("--pubkey-b64-path")
+        .arg(path("keys/sentinel_pub.b64"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
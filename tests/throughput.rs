@@ -0,0 +1,146 @@
+//! Throughput of `sentinel run` with the options that trade it for something
+//! else. Timings mean little in a debug build, so these are ignored by default:
+//!
+//! ```text
+//! cargo test --release --test throughput -- --ignored --nocapture --test-threads 1
+//! ```
+//!
+//! Each run pipes notifications through `cat`, so every message is audited
+//! twice, once each way, and nothing waits on a response.
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 3;
+
+struct Bench {
+    dir: tempfile::TempDir,
+}
+
+impl Bench {
+    /// A directory holding a fresh signing keypair and `input`, one message per line.
+    fn new(input: impl Iterator<Item = String>) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let status = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+            .args(["keygen", "--out-dir"])
+            .arg(dir.path().join("keys"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let lines: Vec<String> = input.map(|line| line + "\n").collect();
+        std::fs::write(dir.path().join("input.jsonl"), lines.concat()).unwrap();
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Write `contents` to `name` in the directory and return its path.
+    fn write(&self, name: &str, contents: &str) -> String {
+        let path = self.path(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// The fastest of a few runs with `args`, and the events it audited.
+    fn time(&self, args: &[&str]) -> (Duration, usize) {
+        let mut best = Duration::MAX;
+        let mut events = 0;
+        for _ in 0..RUNS {
+            let log = self.path("audit.jsonl");
+            let _ = std::fs::remove_file(&log);
+            let start = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_sentinel"))
+                .arg("run")
+                .arg("--audit-log")
+                .arg(&log)
+                .arg("--signing-key-b64-path")
+                .arg(self.path("keys/sentinel_seed.b64"))
+                .args(["--ws-bind", "127.0.0.1:0"])
+                .args(args)
+                .args(["--", "cat"])
+                .current_dir(self.dir.path())
+                .stdin(std::fs::File::open(self.path("input.jsonl")).unwrap())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            best = best.min(start.elapsed());
+            assert!(status.success());
+            events = std::fs::read_to_string(&log)
+                .unwrap()
+                .lines()
+                .filter(|line| line.contains(r#""record_type":"Event""#))
+                .count();
+        }
+        (best, events)
+    }
+
+    /// Time each of `variants` and print a line per variant.
+    fn compare(&self, title: &str, variants: &[(&str, &[&str])]) {
+        println!("{}", title);
+        for (name, args) in variants {
+            let (elapsed, events) = self.time(args);
+            println!(
+                "  {:<28} {:>7} events in {:>6.2} s: {:>8.0} events/s",
+                name,
+                events,
+                elapsed.as_secs_f64(),
+                events as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+}
+
+fn notification(method: &str, data: &str) -> String {
+    serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": { "data": data } }).to_string()
+}
+
+#[test]
+#[ignore = "benchmark; run on a release build"]
+fn signing_every_event() {
+    let text = "x".repeat(200);
+    let bench = Bench::new((0..20_000).map(|_| notification("notifications/message", &text)));
+    bench.compare(
+        "20k small notifications, checkpoint every 1000 events",
+        &[("checkpoints only", &[]), ("--sign-every-event", &["--sign-every-event"])],
+    );
+}
+
+/// `len` characters of base64 that look random, like a file's contents.
+fn base64_blob(len: usize) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            ALPHABET[(state >> 58) as usize] as char
+        })
+        .collect()
+}
+
+#[test]
+#[ignore = "benchmark; run on a release build"]
+fn redacting_large_payloads() {
+    let blob = base64_blob(256 * 1024);
+    let bench = Bench::new((0..400).map(|_| notification("notifications/resources/updated", &blob)));
+    let skip = bench.write("skip.toml", "skip_methods = [\"notifications/resources/updated\"]\n");
+    let cap = bench.write("cap.toml", "max_scan_bytes = 65536\n");
+    let force = bench.write(
+        "force.toml",
+        "max_scan_bytes = 65536\nforce_methods = [\"notifications/resources/updated\"]\n",
+    );
+    bench.compare(
+        "400 notifications of 256 KB of base64, default detectors",
+        &[
+            ("scanned in full", &[]),
+            ("skip_methods", &["--redaction-config", &skip]),
+            ("max_scan_bytes = 65536", &["--redaction-config", &cap]),
+            ("... with force_methods", &["--redaction-config", &force]),
+        ],
+    );
+}